@@ -0,0 +1,9 @@
+pub mod backup;
+pub mod bench_read;
+pub mod config;
+pub mod create_user;
+pub mod gc;
+pub mod import;
+pub mod migrate;
+pub mod migrate_storage;
+pub mod preview_collection_names;
@@ -0,0 +1,85 @@
+use crate::{
+    config::AppConfig,
+    db::{self, ReadPool},
+    services::{
+        create_file_driver, create_search_service, BackupService, BackupSummary, PasswordService,
+    },
+    AppError,
+};
+use std::{path::Path, sync::Arc};
+
+/// Exports all the metadata and file contents of the instance into the given directory.
+pub async fn export(
+    config_path: Option<impl AsRef<Path> + Clone>,
+    directory: &Path,
+) -> Result<(), AppError> {
+    let app_config = AppConfig::load(config_path)?;
+    let backup_service = create_backup_service(&app_config).await?;
+
+    let summary = backup_service.export(directory).await?;
+    print_summary(&summary);
+    println!(
+        "The backup has been exported to `{}`.",
+        directory.canonicalize()?.display()
+    );
+
+    Ok(())
+}
+
+/// Restores a backup into an empty instance. Pending migrations are run first,
+/// so a fresh database can be restored into.
+pub async fn import_backup(
+    config_path: Option<impl AsRef<Path> + Clone>,
+    directory: &Path,
+) -> Result<(), AppError> {
+    let app_config = AppConfig::load(config_path)?;
+
+    db::run_migrations(&app_config.database_url_base, &app_config.database_name)?;
+
+    let backup_service = create_backup_service(&app_config).await?;
+
+    let summary = backup_service.restore(directory).await?;
+    print_summary(&summary);
+    println!("The backup has been restored.");
+
+    if 0 < summary.user_count {
+        println!("Passwords are not part of backups; the restored users have random passwords and must have them reset.");
+    }
+
+    Ok(())
+}
+
+async fn create_backup_service(app_config: &AppConfig) -> Result<Arc<BackupService>, AppError> {
+    let db_pool = db::create_database_connection_pool(
+        &app_config.database_url_base,
+        &app_config.database_name,
+        &app_config.database_pool,
+        None,
+    )?;
+    let search_service = create_search_service(
+        app_config,
+        db_pool.clone(),
+        ReadPool::primary_only(db_pool.clone()),
+    )
+    .await?;
+    let file_driver = create_file_driver(app_config).await?;
+
+    Ok(BackupService::new(
+        db_pool,
+        PasswordService::new(),
+        search_service,
+        file_driver,
+    ))
+}
+
+fn print_summary(summary: &BackupSummary) {
+    println!("[Backup]");
+    println!("- users: {}", summary.user_count);
+    println!("- collections: {}", summary.collection_count);
+    println!(
+        "- files: {} ({} bytes)",
+        summary.file_count, summary.byte_count
+    );
+    println!("- tags: {}", summary.tag_count);
+    println!("- file attributes: {}", summary.file_attribute_count);
+}
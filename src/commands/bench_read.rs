@@ -0,0 +1,65 @@
+use crate::{config::AppConfig, services::local_file_system::PositionalReader, AppError};
+use std::{
+    path::Path,
+    pin::Pin,
+    time::{Duration, Instant},
+};
+use tokio::io::{AsyncRead, BufReader};
+
+/// Measures the read throughput of a file with a buffered reader and with positional reads,
+/// using the buffer size in the config for the latter.
+/// The file is read `iterations` times per reader; the first pass warms up the page cache, so it is not counted.
+pub async fn bench_read(
+    config_path: Option<impl AsRef<Path> + Clone>,
+    file: impl AsRef<Path>,
+    iterations: u32,
+) -> Result<(), AppError> {
+    let app_config = AppConfig::load(config_path)?;
+    let file = file.as_ref();
+    let file_size = tokio::fs::metadata(file).await?.len();
+    let buffer_size = app_config.file_read.buffer_size.as_u64() as usize;
+
+    println!(
+        "Reading `{}` ({} bytes) {} time(s) per reader.",
+        file.display(),
+        file_size,
+        iterations
+    );
+
+    for positional in [false, true] {
+        let mut elapsed = Duration::ZERO;
+
+        for iteration in 0..=iterations {
+            let opened = tokio::fs::File::open(file).await?;
+            let mut reader: Pin<Box<dyn AsyncRead + Send>> = if positional {
+                Box::pin(PositionalReader::new(
+                    opened.into_std().await,
+                    0,
+                    file_size,
+                    buffer_size,
+                ))
+            } else {
+                Box::pin(BufReader::new(opened))
+            };
+
+            let started_at = Instant::now();
+            tokio::io::copy(&mut reader, &mut tokio::io::sink()).await?;
+
+            if iteration != 0 {
+                elapsed += started_at.elapsed();
+            }
+        }
+
+        let seconds = elapsed.as_secs_f64().max(f64::EPSILON);
+        let throughput = (file_size as f64 * iterations as f64) / seconds / (1024.0 * 1024.0);
+
+        println!(
+            "- {}: {:.2} MiB/s ({:.3}s in total)",
+            if positional { "positional" } else { "buffered" },
+            throughput,
+            elapsed.as_secs_f64()
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,249 @@
+use crate::{
+    config::{AppConfig, SearchBackend},
+    db, AppError,
+};
+use figment::{Figment, Profile};
+use serde_json::Value as JsonValue;
+use std::{path::Path, time::Duration};
+use uuid::Uuid;
+
+const MEILISEARCH_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Validates the config, and prints where each value comes from.
+/// The paths have to be writable, the database and the search server reachable, and the periods non-zero.
+/// Fails if any check fails, so that pipelines can stop before deploying a broken config.
+pub async fn check(config_path: Option<impl AsRef<Path>>) -> Result<(), AppError> {
+    let config_path = config_path.map(|config_path| config_path.as_ref().to_path_buf());
+    let figment = AppConfig::figment(config_path.as_deref())?;
+    let app_config = AppConfig::load(config_path.as_ref())?;
+
+    println!("Configuration has been loaded successfully.");
+
+    println!("[Sources]");
+
+    let mut keys = Vec::new();
+    collect_keys("", &serde_json::to_value(&app_config).unwrap(), &mut keys);
+
+    for key in &keys {
+        println!("- {}: {}", key, describe_source(&figment, key));
+    }
+
+    println!("[Checks]");
+
+    let mut problem_count = 0;
+    let mut report = |name: &str, result: Result<String, String>| match result {
+        Ok(detail) => println!("- [ok] {}: {}", name, detail),
+        Err(problem) => {
+            problem_count += 1;
+            println!("- [failed] {}: {}", name, problem);
+        }
+    };
+
+    report("file_base_path", check_writable(&app_config.file_base_path));
+    report("temp_base_path", check_writable(&app_config.temp_base_path));
+
+    if app_config.transcode.enabled {
+        report(
+            "transcode.rendition_base_path",
+            check_writable(&app_config.transcode.rendition_base_path),
+        );
+    }
+
+    if let Some(tls) = &app_config.tls {
+        report("tls.certs", check_readable(&tls.certs));
+        report("tls.key", check_readable(&tls.key));
+
+        if let Some(client_ca) = &tls.client_ca {
+            report("tls.client_ca", check_readable(client_ca));
+        }
+    }
+
+    report("database", check_database(&app_config));
+
+    if app_config.search.backend == SearchBackend::Meilisearch {
+        report("meilisearch_url", check_meilisearch(&app_config).await);
+    }
+
+    let periods = [
+        (
+            "expired_staging_file_removal_period",
+            app_config.expired_staging_file_removal_period,
+        ),
+        (
+            "expired_staging_file_expiration",
+            app_config.expired_staging_file_expiration,
+        ),
+        (
+            "direct_upload_expiration",
+            app_config.direct_upload_expiration,
+        ),
+        (
+            "pending_commit_retry_period",
+            app_config.pending_commit_retry_period,
+        ),
+        (
+            "stats_history_recording_period",
+            app_config.stats_history_recording_period,
+        ),
+        (
+            "retention_policy_period",
+            app_config.retention_policy_period,
+        ),
+        ("transcode.poll_period", app_config.transcode.poll_period),
+        ("scanner.timeout", app_config.scanner.timeout),
+        (
+            "upload_ticket.expiration",
+            app_config.upload_ticket.expiration,
+        ),
+        (
+            "password_reset.expiration",
+            app_config.password_reset.expiration,
+        ),
+        (
+            "email_change.expiration",
+            app_config.email_change.expiration,
+        ),
+        (
+            "login_throttle.failure_window",
+            app_config.login_throttle.failure_window,
+        ),
+    ];
+
+    for (name, period) in periods {
+        report(name, check_period(period));
+    }
+
+    report(
+        "login_throttle.base_delay",
+        if app_config.login_throttle.base_delay <= app_config.login_throttle.max_delay {
+            Ok(format!("{}s", app_config.login_throttle.base_delay))
+        } else {
+            Err(format!(
+                "{}s is longer than `login_throttle.max_delay` of {}s",
+                app_config.login_throttle.base_delay, app_config.login_throttle.max_delay
+            ))
+        },
+    );
+
+    if problem_count != 0 {
+        return Err(AppError::ConfigCheckFailed(problem_count));
+    }
+
+    println!("No problems have been found.");
+
+    Ok(())
+}
+
+/// Collects the dotted keys of the leaves of the config.
+fn collect_keys(prefix: &str, value: &JsonValue, keys: &mut Vec<String>) {
+    match value {
+        JsonValue::Object(map) if !map.is_empty() => {
+            for (key, value) in map {
+                let key = if prefix.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", prefix, key)
+                };
+                collect_keys(&key, value, keys);
+            }
+        }
+        _ => keys.push(prefix.to_owned()),
+    }
+}
+
+/// Describes where the value of a key comes from: an environment variable, the config file, or the default.
+fn describe_source(figment: &Figment, key: &str) -> String {
+    let metadata = match figment.find_metadata(key) {
+        Some(metadata) => metadata,
+        None => return "default".to_owned(),
+    };
+    let keys = key.split('.').collect::<Vec<_>>();
+    let name = metadata.interpolate(&Profile::Default, &keys);
+
+    match &metadata.source {
+        Some(source) => format!("{} `{}` in {}", metadata.name, name, source),
+        None => format!("{} `{}`", metadata.name, name),
+    }
+}
+
+/// Checks that files can be written in a directory.
+/// A missing directory is created by the server, so its nearest existing ancestor has to be writable instead.
+fn check_writable(path: &Path) -> Result<String, String> {
+    let mut dir = path;
+
+    while !dir.exists() {
+        match dir.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => dir = parent,
+            _ => {
+                dir = Path::new(".");
+                break;
+            }
+        }
+    }
+
+    if !dir.is_dir() {
+        return Err(format!("`{}` is not a directory", dir.display()));
+    }
+
+    let probe_path = dir.join(format!(".poly-tag-check-{}", Uuid::new_v4()));
+
+    if let Err(err) = std::fs::write(&probe_path, b"") {
+        return Err(format!("`{}` is not writable: {}", dir.display(), err));
+    }
+
+    std::fs::remove_file(&probe_path).ok();
+
+    if dir == path {
+        Ok("writable".to_owned())
+    } else {
+        Ok(format!(
+            "does not exist, but can be created in `{}`",
+            dir.display()
+        ))
+    }
+}
+
+fn check_readable(path: &Path) -> Result<String, String> {
+    match std::fs::File::open(path) {
+        Ok(_) => Ok("readable".to_owned()),
+        Err(err) => Err(format!("`{}` is not readable: {}", path.display(), err)),
+    }
+}
+
+fn check_database(app_config: &AppConfig) -> Result<String, String> {
+    let statuses =
+        db::get_migration_statuses(&app_config.database_url_base, &app_config.database_name)
+            .map_err(|err| err.to_string())?;
+    let pending_count = statuses.iter().filter(|status| !status.applied).count();
+
+    Ok(format!(
+        "reachable, {} pending migration(s) to be applied on startup",
+        pending_count
+    ))
+}
+
+async fn check_meilisearch(app_config: &AppConfig) -> Result<String, String> {
+    let url = format!(
+        "{}/health",
+        app_config.meilisearch_url.trim_end_matches('/')
+    );
+    let response = reqwest::Client::new()
+        .get(&url)
+        .timeout(MEILISEARCH_TIMEOUT)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status());
+
+    match response {
+        Ok(_) => Ok("reachable".to_owned()),
+        Err(err) => Err(format!("`{}` is not reachable: {}", url, err)),
+    }
+}
+
+fn check_period(period: u64) -> Result<String, String> {
+    if period == 0 {
+        return Err("should be longer than 0s".to_owned());
+    }
+
+    Ok(format!("{}s", period))
+}
@@ -0,0 +1,48 @@
+use crate::{
+    config::AppConfig,
+    db,
+    services::{PasswordService, UserService},
+    AppError,
+};
+use std::path::Path;
+
+/// Creates a user directly in the database, without a running server.
+/// The password is prompted twice from the terminal so that it never appears in the shell history.
+/// Pending migrations are run first, so this can bootstrap a fresh database.
+pub async fn create_user(
+    config_path: Option<impl AsRef<Path> + Clone>,
+    username: &str,
+    email: &str,
+) -> Result<(), AppError> {
+    let app_config = AppConfig::load(config_path)?;
+
+    let password = rpassword::prompt_password("Password: ")?;
+    let password_confirmation = rpassword::prompt_password("Confirm password: ")?;
+
+    if password != password_confirmation {
+        return Err(AppError::PasswordMismatch);
+    }
+
+    if password.is_empty() {
+        return Err(AppError::EmptyPassword);
+    }
+
+    db::run_migrations(&app_config.database_url_base, &app_config.database_name)?;
+
+    let db_pool = db::create_database_connection_pool(
+        &app_config.database_url_base,
+        &app_config.database_name,
+        &app_config.database_pool,
+        None,
+    )?;
+    let user_service = UserService::new(db_pool, PasswordService::new());
+
+    let user = user_service.create_user(username, email, &password).await?;
+
+    println!(
+        "User `{}` ({}) has been created with the id {}.",
+        user.username, user.email, user.id
+    );
+
+    Ok(())
+}
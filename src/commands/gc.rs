@@ -0,0 +1,68 @@
+use crate::{
+    config::AppConfig,
+    db,
+    services::{create_file_driver, GcService},
+    AppError,
+};
+use std::path::Path;
+
+/// Reconciles the stored blobs against the files, and removes the orphaned blobs if `delete` is set.
+pub async fn gc(
+    config_path: Option<impl AsRef<Path> + Clone>,
+    delete: bool,
+) -> Result<(), AppError> {
+    let app_config = AppConfig::load(config_path)?;
+
+    let db_pool = db::create_database_connection_pool(
+        &app_config.database_url_base,
+        &app_config.database_name,
+        &app_config.database_pool,
+        None,
+    )?;
+    let file_driver = create_file_driver(&app_config).await?;
+    let gc_service = GcService::new(db_pool, file_driver);
+
+    let report = if delete {
+        gc_service.sweep().await?
+    } else {
+        gc_service.check().await?
+    };
+
+    if report.is_clean() {
+        println!("The stored blobs and the files agree with each other.");
+        return Ok(());
+    }
+
+    println!("[Orphaned Blobs]");
+
+    for blob in &report.orphaned_blobs {
+        match blob.size_on_disk {
+            Some(size_on_disk) => println!("- {} ({} bytes)", blob.id, size_on_disk),
+            None => println!("- {} (vanished)", blob.id),
+        }
+    }
+
+    println!("[Files With Missing Blobs]");
+
+    for file_id in &report.missing_blob_file_ids {
+        println!("- {}", file_id);
+    }
+
+    if delete {
+        println!(
+            "{} orphaned blob(s) have been removed, freeing {} bytes. {} file(s) are missing their blobs.",
+            report.orphaned_blobs.len(),
+            report.orphaned_size_on_disk(),
+            report.missing_blob_file_ids.len()
+        );
+    } else {
+        println!(
+            "{} orphaned blob(s) occupy {} bytes. {} file(s) are missing their blobs. Nothing has been changed; pass `--delete` to remove the orphaned blobs.",
+            report.orphaned_blobs.len(),
+            report.orphaned_size_on_disk(),
+            report.missing_blob_file_ids.len()
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,497 @@
+use crate::{
+    config::AppConfig,
+    db::{self, ReadPool},
+    services::{
+        create_file_driver, create_search_service, AddFileToCollectionError,
+        CollectionFilePairService, CollectionNamingService, CollectionService,
+        ContentExtractionService, FileService, FileServiceError, IdService, ImportBatch,
+        MetadataService, PerceptualHashService, ScannerService, StagingFileService,
+        StagingFileServiceError, TagService, WriteError,
+    },
+    AppError,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use thiserror::Error;
+use tokio::{fs::OpenOptions, io::AsyncWriteExt};
+use uuid::Uuid;
+
+/// A line of the import journal.
+/// Each line is appended once its step has completed, so that an interrupted import can resume
+/// without importing files twice or creating their collections again.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum JournalEntry {
+    /// A folder has been mapped to a collection.
+    Collection { path: String, collection_id: Uuid },
+    /// A file has been imported, put in its collection and tagged.
+    File { path: String, file_id: Uuid },
+}
+
+/// The sidecar JSON of a file, found next to it as `<file name>.json`.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+struct Sidecar {
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+/// A folder with the files directly in it.
+#[derive(Debug)]
+struct ImportFolder {
+    /// The path relative to the import root, separated by `/`. Empty for the root itself.
+    path: String,
+    full_path: PathBuf,
+    files: Vec<ImportFile>,
+}
+
+#[derive(Debug)]
+struct ImportFile {
+    /// The path relative to the import root, separated by `/`.
+    path: String,
+    full_path: PathBuf,
+    name: String,
+    sidecar_path: Option<PathBuf>,
+}
+
+#[derive(Error, Debug)]
+enum ImportFileError {
+    #[error("io error: {0}")]
+    IO(#[from] std::io::Error),
+    #[error("invalid sidecar: {0}")]
+    Sidecar(#[from] serde_json::Error),
+    #[error("{0}")]
+    StagingFileService(#[from] StagingFileServiceError),
+    #[error("{0}")]
+    Write(#[from] WriteError),
+    #[error("{0}")]
+    FileService(#[from] FileServiceError),
+    #[error("{0}")]
+    AddFileToCollection(#[from] AddFileToCollectionError),
+    #[error("failed to tag the file: {0}")]
+    AddTagToFile(String),
+    #[error("the staging file has been removed during the import")]
+    StagingFileRemoved,
+}
+
+struct Importer {
+    staging_file_service: Arc<StagingFileService>,
+    file_service: Arc<FileService>,
+    collection_file_pair_service: Arc<CollectionFilePairService>,
+    tag_service: Arc<TagService>,
+}
+
+/// Imports a local directory tree through the staging and promotion pipeline.
+/// Every sub-directory with files becomes a collection named by the configured template,
+/// while the files directly in the root are imported without a collection.
+/// If `use_sidecars` is set, the tags in `<file name>.json` next to each file are applied,
+/// and the sidecars themselves are not imported.
+/// The progress is appended to the journal, so running the same import again resumes it.
+pub async fn import(
+    config_path: Option<impl AsRef<Path> + Clone>,
+    root: PathBuf,
+    journal_path: Option<PathBuf>,
+    use_sidecars: bool,
+) -> Result<(), AppError> {
+    let app_config = AppConfig::load(config_path)?;
+
+    if !tokio::fs::metadata(&root).await?.is_dir() {
+        return Err(AppError::NotADirectory(root));
+    }
+
+    let root = root.canonicalize()?;
+    let journal_path = journal_path.unwrap_or_else(|| PathBuf::from("import-journal.jsonl"));
+
+    let journal_content = match tokio::fs::read_to_string(&journal_path).await {
+        Ok(content) => content,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(err) => return Err(err.into()),
+    };
+    let journal = parse_journal(&journal_content)?;
+    let mut collection_ids = HashMap::new();
+    let mut imported_paths = HashMap::new();
+
+    for entry in journal {
+        match entry {
+            JournalEntry::Collection {
+                path,
+                collection_id,
+            } => {
+                collection_ids.insert(path, collection_id);
+            }
+            JournalEntry::File { path, file_id } => {
+                imported_paths.insert(path, file_id);
+            }
+        }
+    }
+
+    let mut journal = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&journal_path)
+        .await?;
+
+    // terminate the line left incomplete by an interrupted run, so that it stays apart from new lines
+    if !journal_content.is_empty() && !journal_content.ends_with('\n') {
+        journal.write_all(b"\n").await?;
+    }
+
+    let journal_path = journal_path.canonicalize()?;
+
+    let folders = walk_folders(&root, &journal_path, use_sidecars).await?;
+
+    let db_pool = db::create_database_connection_pool(
+        &app_config.database_url_base,
+        &app_config.database_name,
+        &app_config.database_pool,
+        None,
+    )?;
+    // the import reads back what it has just written, so replicas that may lag behind are not used
+    let read_pool = ReadPool::primary_only(db_pool.clone());
+    let search_service =
+        create_search_service(&app_config, db_pool.clone(), read_pool.clone()).await?;
+    let file_driver = create_file_driver(&app_config).await?;
+
+    let id_service = IdService::new(app_config.id_version);
+    let collection_service = CollectionService::new(
+        db_pool.clone(),
+        read_pool.clone(),
+        id_service.clone(),
+        search_service.clone(),
+        app_config.unique_collection_names,
+        &app_config.collection_limits,
+    );
+    let staging_file_service =
+        StagingFileService::new(db_pool.clone(), id_service.clone(), file_driver.clone());
+    let file_service = FileService::new(
+        db_pool.clone(),
+        read_pool,
+        staging_file_service.clone(),
+        search_service.clone(),
+        MetadataService::new(app_config.ffprobe_path.clone()),
+        ContentExtractionService::new(app_config.pdftotext_path.clone()),
+        ScannerService::new(&app_config.scanner),
+        PerceptualHashService::new(&app_config.perceptual_hash),
+        file_driver,
+        &app_config.file_cache,
+        app_config.file_versions.max_versions,
+        &app_config.mime_policy,
+    );
+    let collection_file_pair_service = CollectionFilePairService::new(
+        db_pool.clone(),
+        search_service.clone(),
+        app_config.collection_limits.max_files,
+    );
+    let tag_service = TagService::new(db_pool.clone(), search_service);
+    let collection_naming_service = CollectionNamingService::new(
+        db_pool,
+        app_config.import.collection_name_template.clone(),
+        app_config.import.collection_name_collision,
+    );
+
+    // map the folders that have not been mapped by a previous run to collections
+    let mut unmapped_folders = Vec::new();
+    let mut batches = Vec::new();
+
+    for folder in &folders {
+        if folder.path.is_empty()
+            || folder.files.is_empty()
+            || collection_ids.contains_key(&folder.path)
+        {
+            continue;
+        }
+
+        let modified = tokio::fs::metadata(&folder.full_path).await?.modified()?;
+        let date = DateTime::<Utc>::from(modified).date_naive();
+
+        unmapped_folders.push(folder);
+        batches.push(ImportBatch {
+            path: Some(folder.path.clone()),
+            date,
+        });
+    }
+
+    let plans = collection_naming_service.plan_collections(&batches).await?;
+    let mut created_collection_count = 0;
+
+    for plan in &plans {
+        let collection_id = match plan.existing_collection_id {
+            Some(collection_id) => collection_id,
+            None => {
+                let collection = collection_service
+                    .create_collection(&plan.name, None, false, None)
+                    .await?;
+                created_collection_count += 1;
+                println!("- collection created: \"{}\"", plan.name);
+                collection.id
+            }
+        };
+
+        for &batch in &plan.batches {
+            let path = unmapped_folders[batch].path.clone();
+            append_journal(
+                &mut journal,
+                &JournalEntry::Collection {
+                    path: path.clone(),
+                    collection_id,
+                },
+            )
+            .await?;
+            collection_ids.insert(path, collection_id);
+        }
+    }
+
+    let importer = Importer {
+        staging_file_service,
+        file_service,
+        collection_file_pair_service,
+        tag_service,
+    };
+    let mut imported_count = 0;
+    let mut skipped_count = 0;
+    let mut failed_count = 0;
+
+    for folder in &folders {
+        let collection_id = collection_ids.get(&folder.path).copied();
+
+        for file in &folder.files {
+            if imported_paths.contains_key(&file.path) {
+                skipped_count += 1;
+                continue;
+            }
+
+            match importer.import_file(file, collection_id).await {
+                Ok(file_id) => {
+                    append_journal(
+                        &mut journal,
+                        &JournalEntry::File {
+                            path: file.path.clone(),
+                            file_id,
+                        },
+                    )
+                    .await?;
+                    imported_paths.insert(file.path.clone(), file_id);
+                    imported_count += 1;
+                    println!("- imported: {} ({})", file.path, file_id);
+                }
+                Err(err) => {
+                    failed_count += 1;
+                    println!("- failed: {} ({})", file.path, err);
+                }
+            }
+        }
+    }
+
+    println!(
+        "{} file(s) imported, {} already imported, {} failed. {} collection(s) created.",
+        imported_count, skipped_count, failed_count, created_collection_count
+    );
+
+    if 0 < failed_count {
+        println!(
+            "Run the same import again to retry the failed files. The progress is kept in `{}`.",
+            journal_path.display()
+        );
+    }
+
+    Ok(())
+}
+
+impl Importer {
+    /// Imports a single file, puts it in the collection and applies the tags of its sidecar.
+    async fn import_file(
+        &self,
+        file: &ImportFile,
+        collection_id: Option<Uuid>,
+    ) -> Result<Uuid, ImportFileError> {
+        // read the sidecar first, so that a broken sidecar does not leave an untagged file behind
+        let sidecar = match &file.sidecar_path {
+            Some(sidecar_path) => {
+                let content = tokio::fs::read(sidecar_path).await?;
+                Some(serde_json::from_slice::<Sidecar>(&content)?)
+            }
+            None => None,
+        };
+
+        let staging_file = self
+            .staging_file_service
+            .create_staging_file(&file.name, None, None, None)
+            .await?;
+
+        let result = self.promote_file(staging_file.id, &file.full_path).await;
+        let file_id = match result {
+            Ok(file_id) => file_id,
+            Err(err) => {
+                // it is safe to ignore the result, as leftover staging files expire anyway
+                self.staging_file_service
+                    .remove_staging_file_by_id(staging_file.id, None, true)
+                    .await
+                    .ok();
+                return Err(err);
+            }
+        };
+
+        if let Some(collection_id) = collection_id {
+            match self
+                .collection_file_pair_service
+                .add_file_to_collection(collection_id, file_id, None)
+                .await
+            {
+                Ok(_) | Err(AddFileToCollectionError::AlreadyExists { .. }) => {}
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        if let Some(sidecar) = sidecar.filter(|sidecar| !sidecar.tags.is_empty()) {
+            self.tag_service
+                .add_tags_to_files(&[file_id], sidecar.tags.as_slice())
+                .await
+                .map_err(|err| ImportFileError::AddTagToFile(err.to_string()))?;
+        }
+
+        Ok(file_id)
+    }
+
+    async fn promote_file(
+        &self,
+        staging_file_id: Uuid,
+        path: &Path,
+    ) -> Result<Uuid, ImportFileError> {
+        let source = tokio::fs::File::open(path).await?;
+        let staging_file = self
+            .staging_file_service
+            .fill_staging_file_by_id(staging_file_id, None, Box::pin(source))
+            .await??;
+
+        if staging_file.is_none() {
+            return Err(ImportFileError::StagingFileRemoved);
+        }
+
+        let file = self
+            .file_service
+            .create_file_from_staging_file_id(staging_file_id)
+            .await?;
+
+        match file {
+            Some(file) => Ok(file.id),
+            None => Err(ImportFileError::StagingFileRemoved),
+        }
+    }
+}
+
+/// Collects the folders under the root, including the root itself, in a stable order.
+/// Hidden entries and the journal are skipped, as are the sidecars if they are in use.
+async fn walk_folders(
+    root: &Path,
+    journal_path: &Path,
+    use_sidecars: bool,
+) -> Result<Vec<ImportFolder>, std::io::Error> {
+    let mut folders = Vec::new();
+    let mut pending = vec![(String::new(), root.to_path_buf())];
+
+    while let Some((path, full_path)) = pending.pop() {
+        let mut entries = Vec::new();
+        let mut read_dir = tokio::fs::read_dir(&full_path).await?;
+
+        while let Some(entry) = read_dir.next_entry().await? {
+            let name = entry.file_name().to_string_lossy().into_owned();
+
+            if name.starts_with('.') || entry.path() == journal_path {
+                continue;
+            }
+
+            entries.push((name, entry.path(), tokio::fs::metadata(entry.path()).await?));
+        }
+
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let file_names = entries
+            .iter()
+            .filter(|(_, _, metadata)| metadata.is_file())
+            .map(|(name, _, _)| name.as_str())
+            .collect::<Vec<_>>();
+        let is_sidecar = |name: &str| {
+            use_sidecars
+                && name
+                    .strip_suffix(".json")
+                    .is_some_and(|target| file_names.contains(&target))
+        };
+
+        let mut files = Vec::new();
+
+        for (name, entry_path, metadata) in &entries {
+            let relative_path = if path.is_empty() {
+                name.clone()
+            } else {
+                format!("{}/{}", path, name)
+            };
+
+            if metadata.is_dir() {
+                pending.push((relative_path, entry_path.clone()));
+                continue;
+            }
+
+            if !metadata.is_file() || is_sidecar(name) {
+                continue;
+            }
+
+            let sidecar_name = format!("{}.json", name);
+            let sidecar_path = if use_sidecars && file_names.contains(&sidecar_name.as_str()) {
+                Some(full_path.join(sidecar_name))
+            } else {
+                None
+            };
+
+            files.push(ImportFile {
+                path: relative_path,
+                full_path: entry_path.clone(),
+                name: name.clone(),
+                sidecar_path,
+            });
+        }
+
+        folders.push(ImportFolder {
+            path,
+            full_path,
+            files,
+        });
+    }
+
+    folders.sort_by(|a, b| a.path.cmp(&b.path));
+
+    Ok(folders)
+}
+
+fn parse_journal(content: &str) -> Result<Vec<JournalEntry>, AppError> {
+    let mut entries = Vec::new();
+
+    for line in content.lines() {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        // a line may be incomplete if a previous run was killed while writing it
+        match serde_json::from_str::<JournalEntry>(line) {
+            Ok(entry) => entries.push(entry),
+            Err(err) if err.is_eof() => continue,
+            Err(err) => return Err(AppError::InvalidImportJournal(err)),
+        }
+    }
+
+    Ok(entries)
+}
+
+async fn append_journal(
+    journal: &mut tokio::fs::File,
+    entry: &JournalEntry,
+) -> Result<(), AppError> {
+    let mut line = serde_json::to_vec(entry).map_err(AppError::InvalidImportJournal)?;
+    line.push(b'\n');
+    journal.write_all(&line).await?;
+    journal.sync_data().await?;
+    Ok(())
+}
@@ -0,0 +1,69 @@
+use crate::{config::AppConfig, db, AppError};
+use std::path::Path;
+
+/// Runs all pending migrations without starting the server.
+pub fn run(config_path: Option<impl AsRef<Path> + Clone>) -> Result<(), AppError> {
+    let app_config = AppConfig::load(config_path)?;
+    let versions = db::run_migrations(&app_config.database_url_base, &app_config.database_name)?;
+
+    if versions.is_empty() {
+        println!("No pending migrations. The database is up to date.");
+        return Ok(());
+    }
+
+    for version in &versions {
+        println!("- applied: {}", version);
+    }
+
+    println!("{} migration(s) have been applied.", versions.len());
+
+    Ok(())
+}
+
+/// Prints every migration along with whether it has been applied.
+pub fn status(config_path: Option<impl AsRef<Path> + Clone>) -> Result<(), AppError> {
+    let app_config = AppConfig::load(config_path)?;
+    let statuses =
+        db::get_migration_statuses(&app_config.database_url_base, &app_config.database_name)?;
+
+    println!("[Migrations]");
+
+    for status in &statuses {
+        println!(
+            "- [{}] {}",
+            if status.applied { "x" } else { " " },
+            status.name
+        );
+    }
+
+    println!(
+        "{} applied, {} pending.",
+        statuses.iter().filter(|status| status.applied).count(),
+        statuses.iter().filter(|status| !status.applied).count()
+    );
+
+    Ok(())
+}
+
+/// Reverts the last `steps` applied migrations.
+pub fn revert(config_path: Option<impl AsRef<Path> + Clone>, steps: usize) -> Result<(), AppError> {
+    let app_config = AppConfig::load(config_path)?;
+    let versions = db::revert_migrations(
+        &app_config.database_url_base,
+        &app_config.database_name,
+        steps,
+    )?;
+
+    if versions.is_empty() {
+        println!("No applied migrations. Nothing has been reverted.");
+        return Ok(());
+    }
+
+    for version in &versions {
+        println!("- reverted: {}", version);
+    }
+
+    println!("{} migration(s) have been reverted.", versions.len());
+
+    Ok(())
+}
@@ -0,0 +1,125 @@
+use crate::{
+    config::AppConfig,
+    db,
+    services::{create_file_driver, BlobMigration, StorageMigrationService},
+    AppError,
+};
+use std::{path::Path, time::Instant};
+
+/// Moves the blobs of all files from the storage of the config to the storage of the target config.
+/// Each blob is verified against its recorded hash and size once it is in the target storage.
+/// Blobs the target storage already holds intact are skipped, so running the same migration again resumes it.
+/// The source storage is left untouched; the switch-over is done by hand once the migration has completed.
+pub async fn migrate_storage(
+    config_path: Option<impl AsRef<Path> + Clone>,
+    target_config_path: impl AsRef<Path>,
+) -> Result<(), AppError> {
+    let target_config_path = target_config_path.as_ref();
+    let app_config = AppConfig::load(config_path)?;
+    let target_config = AppConfig::load(Some(target_config_path))?;
+
+    if app_config.storage == target_config.storage
+        && app_config.file_base_path == target_config.file_base_path
+    {
+        return Err(AppError::SameStorage);
+    }
+
+    let db_pool = db::create_database_connection_pool(
+        &app_config.database_url_base,
+        &app_config.database_name,
+        &app_config.database_pool,
+        None,
+    )?;
+    let source = create_file_driver(&app_config).await?;
+    let target = create_file_driver(&target_config).await?;
+    let storage_migration_service = StorageMigrationService::new(db_pool, source, target);
+
+    let blobs = storage_migration_service.list_blobs().await?;
+    let total_size = blobs.iter().map(|blob| blob.size as u64).sum::<u64>();
+    let started_at = Instant::now();
+
+    println!(
+        "Moving {} blob(s) ({} bytes) to the target storage.",
+        blobs.len(),
+        total_size
+    );
+
+    let mut copied_count = 0;
+    let mut copied_size = 0;
+    let mut skipped_count = 0;
+    let mut missing_ids = Vec::new();
+    let mut failures = Vec::new();
+
+    for (index, blob) in blobs.iter().enumerate() {
+        let outcome = match storage_migration_service.migrate_blob(blob).await {
+            Ok(BlobMigration::Copied) => {
+                copied_count += 1;
+                copied_size += blob.size as u64;
+                "copied".to_owned()
+            }
+            Ok(BlobMigration::AlreadyMigrated) => {
+                skipped_count += 1;
+                "already migrated".to_owned()
+            }
+            Ok(BlobMigration::MissingInSource) => {
+                missing_ids.push(blob.id);
+                "missing in the source storage".to_owned()
+            }
+            Err(err) => {
+                let outcome = format!("failed: {}", err);
+                failures.push((blob.id, err));
+                outcome
+            }
+        };
+
+        println!(
+            "[{}/{}] {} ({} bytes): {}",
+            index + 1,
+            blobs.len(),
+            blob.id,
+            blob.size,
+            outcome
+        );
+    }
+
+    let seconds = started_at.elapsed().as_secs_f64().max(f64::EPSILON);
+
+    println!(
+        "{} blob(s) ({} bytes) have been copied in {:.1}s ({:.2} MiB/s), {} blob(s) were already migrated.",
+        copied_count,
+        copied_size,
+        seconds,
+        copied_size as f64 / seconds / (1024.0 * 1024.0),
+        skipped_count
+    );
+
+    if !missing_ids.is_empty() {
+        println!("[Blobs Missing In The Source Storage]");
+
+        for id in &missing_ids {
+            println!("- {}", id);
+        }
+    }
+
+    if !failures.is_empty() {
+        println!("[Failed Blobs]");
+
+        for (id, err) in &failures {
+            println!("- {}: {}", id, err);
+        }
+
+        println!(
+            "{} blob(s) could not be moved. Run this command again once the cause has been fixed; the blobs already moved are not copied again.",
+            failures.len()
+        );
+
+        return Ok(());
+    }
+
+    println!(
+        "The migration has completed. To switch over, stop the server and run this command once more to move the files uploaded in the meantime. Then copy `storage` and `file_base_path` from `{}` into the config and start the server again. The blobs in the source storage are kept; remove them once the switch-over has been confirmed.",
+        target_config_path.display()
+    );
+
+    Ok(())
+}
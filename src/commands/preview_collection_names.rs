@@ -0,0 +1,81 @@
+use crate::{
+    config::AppConfig,
+    db,
+    services::{CollectionNamingService, ImportBatch},
+    AppError,
+};
+use chrono::{DateTime, Utc};
+use std::path::{Path, PathBuf};
+
+/// Prints the collections an import of the given folders would create, without creating anything.
+/// Each folder is treated as a batch dated by its last modification time.
+pub async fn preview_collection_names(
+    config_path: Option<impl AsRef<Path> + Clone>,
+    folders: Vec<PathBuf>,
+) -> Result<(), AppError> {
+    let app_config = AppConfig::load(config_path)?;
+
+    let mut batches = Vec::with_capacity(folders.len());
+
+    for folder in &folders {
+        let modified = tokio::fs::metadata(folder).await?.modified()?;
+        let date = DateTime::<Utc>::from(modified).date_naive();
+        let path = folder
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+
+        batches.push(ImportBatch {
+            path: Some(path),
+            date,
+        });
+    }
+
+    let db_pool = db::create_database_connection_pool(
+        &app_config.database_url_base,
+        &app_config.database_name,
+        &app_config.database_pool,
+        None,
+    )?;
+    let collection_naming_service = CollectionNamingService::new(
+        db_pool,
+        app_config.import.collection_name_template.clone(),
+        app_config.import.collection_name_collision,
+    );
+
+    let plans = collection_naming_service.plan_collections(&batches).await?;
+
+    println!(
+        "[Collections] (template: `{}`, collision: {:?})",
+        collection_naming_service.template(),
+        app_config.import.collection_name_collision
+    );
+
+    for plan in &plans {
+        match plan.existing_collection_id {
+            Some(collection_id) => {
+                println!("- \"{}\" (existing: {})", plan.name, collection_id)
+            }
+            None => println!("- \"{}\" (new)", plan.name),
+        }
+
+        for &batch in &plan.batches {
+            println!("    - {}", folders[batch].display());
+        }
+    }
+
+    println!(
+        "{} collection(s) would be created, {} would be reused. Nothing has been changed.",
+        plans
+            .iter()
+            .filter(|plan| plan.existing_collection_id.is_none())
+            .count(),
+        plans
+            .iter()
+            .filter(|plan| plan.existing_collection_id.is_some())
+            .count()
+    );
+
+    Ok(())
+}
@@ -1,12 +1,27 @@
 pub mod models;
 pub mod schema;
 
-use diesel::{Connection, PgConnection};
+use crate::config::AppDatabasePool;
+use diesel::{
+    debug_query, migration::MigrationSource, pg::Pg, query_builder::QueryFragment, Connection,
+    PgConnection,
+};
 use diesel_async::{
-    pooled_connection::{deadpool::Pool, AsyncDieselConnectionManager},
-    AsyncPgConnection,
+    pooled_connection::{
+        deadpool::{Hook, Object, Pool, PoolError},
+        AsyncDieselConnectionManager, ManagerConfig,
+    },
+    AsyncConnection, AsyncPgConnection, SimpleAsyncConnection,
 };
 use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
+use std::{
+    future::Future,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
 use thiserror::Error;
 
 const MIGRATIONS: EmbeddedMigrations = embed_migrations!("src/db/migrations");
@@ -23,23 +38,240 @@ pub enum DBError {
     Diesel(#[from] diesel::result::Error),
 }
 
-pub fn run_migrations(database_url_base: &str, database_name: &str) -> Result<(), DBError> {
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MigrationStatus {
+    pub name: String,
+    pub applied: bool,
+}
+
+/// Runs all pending migrations. Returns the versions of the migrations that have been applied.
+pub fn run_migrations(
+    database_url_base: &str,
+    database_name: &str,
+) -> Result<Vec<String>, DBError> {
     let url = make_database_url(database_url_base, database_name);
     let mut connection = PgConnection::establish(&url)?;
-    connection.run_pending_migrations(MIGRATIONS)?;
-    Ok(())
+    let versions = connection.run_pending_migrations(MIGRATIONS)?;
+    Ok(versions.iter().map(|version| version.to_string()).collect())
 }
 
+/// Lists all embedded migrations in order, along with whether each one has been applied.
+pub fn get_migration_statuses(
+    database_url_base: &str,
+    database_name: &str,
+) -> Result<Vec<MigrationStatus>, DBError> {
+    let url = make_database_url(database_url_base, database_name);
+    let mut connection = PgConnection::establish(&url)?;
+    let applied_versions = connection.applied_migrations()?;
+    let migrations = MigrationSource::<Pg>::migrations(&MIGRATIONS)?;
+
+    let mut statuses = migrations
+        .iter()
+        .map(|migration| MigrationStatus {
+            name: migration.name().to_string(),
+            applied: applied_versions.contains(&migration.name().version()),
+        })
+        .collect::<Vec<_>>();
+    statuses.sort_by(|a, b| a.name.cmp(&b.name));
+
+    Ok(statuses)
+}
+
+/// Reverts the last `steps` applied migrations, or all of them if fewer have been applied.
+/// Returns the versions of the reverted migrations, the most recent first.
+pub fn revert_migrations(
+    database_url_base: &str,
+    database_name: &str,
+    steps: usize,
+) -> Result<Vec<String>, DBError> {
+    let url = make_database_url(database_url_base, database_name);
+    let mut connection = PgConnection::establish(&url)?;
+    let applied_count = connection.applied_migrations()?.len();
+
+    let mut versions = Vec::with_capacity(steps.min(applied_count));
+
+    for _ in 0..steps.min(applied_count) {
+        let version = connection.revert_last_migration(MIGRATIONS)?;
+        versions.push(version.to_string());
+    }
+
+    Ok(versions)
+}
+
+/// Counts the connections of a pool through its hooks, so that they can be monitored.
+#[derive(Debug, Default)]
+pub struct DatabasePoolMetrics {
+    created: AtomicU64,
+    recycled: AtomicU64,
+}
+
+impl DatabasePoolMetrics {
+    /// The number of connections that have been established.
+    pub fn created(&self) -> u64 {
+        self.created.load(Ordering::Relaxed)
+    }
+
+    /// The number of times an idle connection has been reused.
+    pub fn recycled(&self) -> u64 {
+        self.recycled.load(Ordering::Relaxed)
+    }
+}
+
+/// Creates a connection pool with the given settings.
+/// If `metrics` is given, it is updated whenever a connection is created or reused.
 pub fn create_database_connection_pool(
     database_url_base: &str,
     database_name: &str,
+    pool_config: &AppDatabasePool,
+    metrics: Option<Arc<DatabasePoolMetrics>>,
 ) -> Result<Pool<AsyncPgConnection>, DBError> {
     let url = make_database_url(database_url_base, database_name);
-    let manager = AsyncDieselConnectionManager::<AsyncPgConnection>::new(url);
-    let pool = Pool::builder(manager).build()?;
+    create_connection_pool(url, pool_config, metrics)
+}
+
+/// Creates a pool for reads, spread over a connection pool per replica URL.
+/// Reads go to `primary` if there are no replicas.
+pub fn create_read_pool(
+    primary: Pool<AsyncPgConnection>,
+    replica_urls: &[String],
+    pool_config: &AppDatabasePool,
+) -> Result<ReadPool, DBError> {
+    let replicas = replica_urls
+        .iter()
+        .map(|url| create_connection_pool(url.clone(), pool_config, None))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(ReadPool::new(
+        primary,
+        replicas,
+        pool_config.slow_query_threshold.map(Duration::from_millis),
+    ))
+}
+
+fn create_connection_pool(
+    url: String,
+    pool_config: &AppDatabasePool,
+    metrics: Option<Arc<DatabasePoolMetrics>>,
+) -> Result<Pool<AsyncPgConnection>, DBError> {
+    let mut manager_config = ManagerConfig::default();
+
+    if let Some(statement_timeout) = pool_config.statement_timeout {
+        // the setting lasts for the session, so it has to be set only once per connection
+        manager_config.custom_setup = Box::new(move |url| {
+            Box::pin(async move {
+                let mut connection = AsyncPgConnection::establish(url).await?;
+                connection
+                    .batch_execute(&format!("SET statement_timeout = {}", statement_timeout))
+                    .await
+                    .map_err(diesel::ConnectionError::CouldntSetupConfiguration)?;
+                Ok(connection)
+            })
+        });
+    }
+
+    let manager =
+        AsyncDieselConnectionManager::<AsyncPgConnection>::new_with_config(url, manager_config);
+    let mut builder = Pool::builder(manager)
+        .wait_timeout(pool_config.wait_timeout.map(Duration::from_secs))
+        .create_timeout(pool_config.create_timeout.map(Duration::from_secs))
+        .recycle_timeout(pool_config.recycle_timeout.map(Duration::from_secs))
+        // the timeouts require a runtime to be set
+        .runtime(deadpool::Runtime::Tokio1);
+
+    if let Some(max_size) = pool_config.max_size {
+        builder = builder.max_size(max_size);
+    }
+
+    if let Some(metrics) = metrics {
+        let created_metrics = metrics.clone();
+        builder = builder
+            .post_create(Hook::sync_fn(move |_, _| {
+                created_metrics.created.fetch_add(1, Ordering::Relaxed);
+                Ok(())
+            }))
+            .post_recycle(Hook::sync_fn(move |_, _| {
+                metrics.recycled.fetch_add(1, Ordering::Relaxed);
+                Ok(())
+            }));
+    }
+
+    let pool = builder.build()?;
     Ok(pool)
 }
 
+/// Hands out connections for read-only queries, from the read replicas in turn.
+/// Falls back to the primary if there are no replicas, or the chosen replica cannot give out a connection.
+/// Replicas may lag behind the primary, so reads that must see a preceding write should use the primary.
+/// Queries run through [`ReadPool::measure`] are logged if they take longer than `slow_query_threshold`.
+#[derive(Clone)]
+pub struct ReadPool {
+    primary: Pool<AsyncPgConnection>,
+    replicas: Arc<[Pool<AsyncPgConnection>]>,
+    next_replica: Arc<AtomicUsize>,
+    slow_query_threshold: Option<Duration>,
+}
+
+impl ReadPool {
+    pub fn new(
+        primary: Pool<AsyncPgConnection>,
+        replicas: Vec<Pool<AsyncPgConnection>>,
+        slow_query_threshold: Option<Duration>,
+    ) -> Self {
+        Self {
+            primary,
+            replicas: replicas.into(),
+            next_replica: Arc::new(AtomicUsize::new(0)),
+            slow_query_threshold,
+        }
+    }
+
+    /// Creates a pool that reads from the primary only, without logging slow queries.
+    pub fn primary_only(primary: Pool<AsyncPgConnection>) -> Self {
+        Self::new(primary, Vec::new(), None)
+    }
+
+    /// Runs `query` with `run`, and logs it along with its bound parameters if it is slow.
+    /// The query is rendered before it runs, and only if slow queries are logged.
+    pub async fn measure<Q, F, T>(&self, name: &str, query: Q, run: impl FnOnce(Q) -> F) -> T
+    where
+        Q: QueryFragment<Pg>,
+        F: Future<Output = T>,
+    {
+        let threshold = match self.slow_query_threshold {
+            Some(threshold) => threshold,
+            None => return run(query).await,
+        };
+
+        let sql = debug_query::<Pg, _>(&query).to_string();
+        let started_at = Instant::now();
+        let result = run(query).await;
+        let elapsed = started_at.elapsed();
+
+        if threshold <= elapsed {
+            let elapsed_ms = elapsed.as_millis() as u64;
+            log::warn!(target: "db", query = name, elapsed_ms, sql; "Slow query.");
+        }
+
+        result
+    }
+
+    pub async fn get(&self) -> Result<Object<AsyncPgConnection>, PoolError> {
+        if self.replicas.is_empty() {
+            return self.primary.get().await;
+        }
+
+        let index = self.next_replica.fetch_add(1, Ordering::Relaxed) % self.replicas.len();
+
+        match self.replicas[index].get().await {
+            Ok(connection) => Ok(connection),
+            Err(err) => {
+                log::warn!(target: "db", replica = index, err:err; "Failed to get a connection from the replica. Falling back to the primary.");
+                self.primary.get().await
+            }
+        }
+    }
+}
+
 fn make_database_url(database_url_base: &str, database_name: &str) -> String {
     if database_url_base.ends_with('/') {
         format!("{}{}", database_url_base, database_name)
@@ -77,7 +309,7 @@ pub mod test {
         fn drop(&mut self) {
             let url = make_database_url(&self.database_url_base, &self.maintenance_database_name);
             let mut connection = PgConnection::establish(&url).unwrap();
-            let query = format!("DROP DATABASE \"{}\"", &self.database_name);
+            let query = format!("DROP DATABASE \"{}\"", self.database_name);
             diesel::sql_query(query).execute(&mut connection).unwrap();
         }
     }
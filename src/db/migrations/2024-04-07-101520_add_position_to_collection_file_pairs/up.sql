@@ -0,0 +1,5 @@
+-- Your SQL goes here
+
+ALTER TABLE collection_file_pairs ADD COLUMN position INTEGER NULL;
+
+CREATE INDEX ON collection_file_pairs(collection_id, position ASC NULLS LAST);
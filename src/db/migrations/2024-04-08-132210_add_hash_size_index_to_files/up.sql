@@ -0,0 +1,3 @@
+-- Your SQL goes here
+
+CREATE INDEX files_size_hash_idx ON files(size DESC, hash ASC);
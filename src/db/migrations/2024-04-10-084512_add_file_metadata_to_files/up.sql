@@ -0,0 +1,3 @@
+-- Your SQL goes here
+
+ALTER TABLE files ADD COLUMN file_metadata JSONB NULL;
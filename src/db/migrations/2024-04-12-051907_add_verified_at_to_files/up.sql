@@ -0,0 +1,5 @@
+-- Your SQL goes here
+
+-- existing files have never been verified, so only new files get the default
+ALTER TABLE files ADD COLUMN verified_at TIMESTAMP NULL;
+ALTER TABLE files ALTER COLUMN verified_at SET DEFAULT NOW();
@@ -0,0 +1,19 @@
+-- Your SQL goes here
+
+CREATE TABLE transcode_jobs (
+  id UUID NOT NULL PRIMARY KEY DEFAULT uuid_generate_v4(),
+  file_id UUID NOT NULL,
+  profile TEXT NOT NULL,
+  status TEXT NOT NULL DEFAULT 'pending', -- pending, running, completed, failed
+  error TEXT NULL,
+  attempts INTEGER NOT NULL DEFAULT 0,
+  size BIGINT NULL,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  started_at TIMESTAMP NULL,
+  finished_at TIMESTAMP NULL,
+  CONSTRAINT transcode_jobs_file_profile_unique UNIQUE (file_id, profile),
+  CONSTRAINT transcode_jobs_status_check CHECK (status IN ('pending', 'running', 'completed', 'failed')),
+  CONSTRAINT transcode_jobs_file_fk FOREIGN KEY (file_id) REFERENCES files(id) ON UPDATE CASCADE ON DELETE CASCADE
+);
+
+CREATE INDEX ON transcode_jobs(status, created_at ASC);
@@ -0,0 +1,16 @@
+-- Your SQL goes here
+
+CREATE TABLE upload_tickets (
+  token TEXT NOT NULL PRIMARY KEY,
+  user_id INTEGER NOT NULL,
+  user_session_token TEXT NULL, -- NULL once the session is gone, which voids the ticket
+  max_size BIGINT NOT NULL,
+  file_id UUID NULL,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  expires_at TIMESTAMP NOT NULL,
+  used_at TIMESTAMP NULL,
+  CONSTRAINT upload_tickets_user_fk FOREIGN KEY (user_id) REFERENCES users(id) ON UPDATE CASCADE ON DELETE CASCADE,
+  CONSTRAINT upload_tickets_user_session_fk FOREIGN KEY (user_session_token) REFERENCES user_sessions(token) ON UPDATE CASCADE ON DELETE SET NULL
+);
+
+CREATE INDEX ON upload_tickets(user_session_token);
@@ -0,0 +1,10 @@
+-- Your SQL goes here
+
+CREATE TABLE stats_history (
+  date DATE NOT NULL PRIMARY KEY,
+  file_count BIGINT NOT NULL,
+  byte_count BIGINT NOT NULL,
+  user_count BIGINT NOT NULL,
+  tag_count BIGINT NOT NULL,
+  recorded_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,11 @@
+-- Your SQL goes here
+
+CREATE TABLE password_reset_tokens (
+  token TEXT NOT NULL PRIMARY KEY,
+  user_id INTEGER NOT NULL,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  expires_at TIMESTAMP NOT NULL,
+  CONSTRAINT password_reset_tokens_user_fk FOREIGN KEY (user_id) REFERENCES users(id) ON UPDATE CASCADE ON DELETE CASCADE
+);
+
+CREATE INDEX ON password_reset_tokens(user_id);
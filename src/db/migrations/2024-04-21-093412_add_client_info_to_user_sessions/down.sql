@@ -0,0 +1,6 @@
+-- This file should undo anything in `up.sql`
+
+DROP INDEX user_sessions_user_id_idx;
+
+ALTER TABLE user_sessions DROP COLUMN ip_address;
+ALTER TABLE user_sessions DROP COLUMN user_agent;
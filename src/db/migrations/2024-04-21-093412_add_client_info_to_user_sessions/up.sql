@@ -0,0 +1,7 @@
+-- Your SQL goes here
+
+-- the client of existing sessions is unknown, so they are left NULL
+ALTER TABLE user_sessions ADD COLUMN user_agent TEXT NULL;
+ALTER TABLE user_sessions ADD COLUMN ip_address TEXT NULL;
+
+CREATE INDEX ON user_sessions(user_id);
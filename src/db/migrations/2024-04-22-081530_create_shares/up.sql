@@ -0,0 +1,17 @@
+-- Your SQL goes here
+
+CREATE TABLE shares (
+  id UUID NOT NULL PRIMARY KEY,
+  token TEXT NOT NULL UNIQUE,
+  user_id INTEGER NOT NULL,
+  file_id UUID,
+  collection_id UUID,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  expires_at TIMESTAMP,
+  CONSTRAINT shares_user_fk FOREIGN KEY (user_id) REFERENCES users(id) ON UPDATE CASCADE ON DELETE CASCADE,
+  CONSTRAINT shares_file_fk FOREIGN KEY (file_id) REFERENCES files(id) ON UPDATE CASCADE ON DELETE CASCADE,
+  CONSTRAINT shares_collection_fk FOREIGN KEY (collection_id) REFERENCES collections(id) ON UPDATE CASCADE ON DELETE CASCADE,
+  CONSTRAINT shares_target_check CHECK ((file_id IS NULL) <> (collection_id IS NULL))
+);
+
+CREATE INDEX ON shares(user_id);
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE staging_files DROP COLUMN expected_hash;
+ALTER TABLE staging_files DROP COLUMN expected_size;
@@ -0,0 +1,4 @@
+-- Your SQL goes here
+
+ALTER TABLE staging_files ADD COLUMN expected_size BIGINT;
+ALTER TABLE staging_files ADD COLUMN expected_hash BIGINT;
@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+
+DROP TABLE staging_file_chunks;
+
+DROP EXTENSION IF EXISTS btree_gist;
@@ -0,0 +1,15 @@
+-- Your SQL goes here
+
+-- required to combine the UUID equality with the range overlap in a single exclusion constraint
+CREATE EXTENSION IF NOT EXISTS btree_gist;
+
+CREATE TABLE staging_file_chunks (
+  staging_file_id UUID NOT NULL,
+  chunk_offset BIGINT NOT NULL,
+  size BIGINT NOT NULL,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  completed_at TIMESTAMP,
+  PRIMARY KEY (staging_file_id, chunk_offset),
+  CONSTRAINT staging_file_chunks_staging_file_fk FOREIGN KEY (staging_file_id) REFERENCES staging_files(id) ON UPDATE CASCADE ON DELETE CASCADE,
+  CONSTRAINT staging_file_chunks_no_overlap EXCLUDE USING gist (staging_file_id WITH =, int8range(chunk_offset, chunk_offset + size) WITH &&)
+);
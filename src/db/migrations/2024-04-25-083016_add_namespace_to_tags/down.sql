@@ -0,0 +1,11 @@
+-- This file should undo anything in `up.sql`
+
+DROP INDEX tags_file_id_idx;
+DROP INDEX tags_name_idx;
+
+ALTER TABLE tags DROP CONSTRAINT tags_pkey;
+
+UPDATE tags SET name = namespace || ':' || name WHERE namespace <> '';
+
+ALTER TABLE tags DROP COLUMN namespace;
+ALTER TABLE tags ADD PRIMARY KEY (name, file_id);
@@ -0,0 +1,14 @@
+-- Your SQL goes here
+
+ALTER TABLE tags ADD COLUMN namespace TEXT NOT NULL DEFAULT '';
+
+-- split the existing structured tags, e.g. `year:1959`, into their namespace and value
+UPDATE tags
+SET namespace = split_part(name, ':', 1), name = substring(name FROM position(':' IN name) + 1)
+WHERE position(':' IN name) > 0;
+
+ALTER TABLE tags DROP CONSTRAINT tags_pkey;
+ALTER TABLE tags ADD PRIMARY KEY (namespace, name, file_id);
+
+CREATE INDEX ON tags(name ASC);
+CREATE INDEX ON tags(file_id ASC);
@@ -0,0 +1,14 @@
+-- This file should undo anything in `up.sql`
+
+DROP INDEX collections_name_trgm_idx;
+DROP INDEX collections_search_vector_idx;
+
+ALTER TABLE collections DROP COLUMN search_vector;
+
+DROP INDEX files_name_trgm_idx;
+DROP INDEX files_search_vector_idx;
+
+ALTER TABLE files DROP COLUMN search_vector;
+ALTER TABLE files DROP COLUMN search_content;
+
+DROP EXTENSION IF EXISTS pg_trgm;
@@ -0,0 +1,15 @@
+-- Your SQL goes here
+
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+
+-- the text extracted from files, which is only kept by the PostgreSQL search backend
+ALTER TABLE files ADD COLUMN search_content TEXT;
+ALTER TABLE files ADD COLUMN search_vector TSVECTOR GENERATED ALWAYS AS (to_tsvector('simple', name || ' ' || COALESCE(search_content, ''))) STORED;
+
+CREATE INDEX files_search_vector_idx ON files USING GIN (search_vector);
+CREATE INDEX files_name_trgm_idx ON files USING GIN (name gin_trgm_ops);
+
+ALTER TABLE collections ADD COLUMN search_vector TSVECTOR GENERATED ALWAYS AS (to_tsvector('simple', name || ' ' || COALESCE(description, ''))) STORED;
+
+CREATE INDEX collections_search_vector_idx ON collections USING GIN (search_vector);
+CREATE INDEX collections_name_trgm_idx ON collections USING GIN (name gin_trgm_ops);
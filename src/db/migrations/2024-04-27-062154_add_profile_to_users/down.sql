@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE users DROP CONSTRAINT users_avatar_file_fk;
+ALTER TABLE users DROP COLUMN avatar_file_id;
+ALTER TABLE users DROP COLUMN display_name;
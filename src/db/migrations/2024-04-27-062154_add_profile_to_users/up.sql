@@ -0,0 +1,6 @@
+-- Your SQL goes here
+
+ALTER TABLE users ADD COLUMN display_name TEXT;
+-- removing the avatar file only clears the avatar
+ALTER TABLE users ADD COLUMN avatar_file_id UUID;
+ALTER TABLE users ADD CONSTRAINT users_avatar_file_fk FOREIGN KEY (avatar_file_id) REFERENCES files(id) ON UPDATE CASCADE ON DELETE SET NULL;
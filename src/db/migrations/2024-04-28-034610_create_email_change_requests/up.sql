@@ -0,0 +1,12 @@
+-- Your SQL goes here
+
+CREATE TABLE email_change_requests (
+  token TEXT NOT NULL PRIMARY KEY,
+  user_id INTEGER NOT NULL,
+  new_email TEXT NOT NULL,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  expires_at TIMESTAMP NOT NULL,
+  CONSTRAINT email_change_requests_user_fk FOREIGN KEY (user_id) REFERENCES users(id) ON UPDATE CASCADE ON DELETE CASCADE
+);
+
+CREATE INDEX ON email_change_requests(user_id);
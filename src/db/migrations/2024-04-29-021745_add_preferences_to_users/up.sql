@@ -0,0 +1,3 @@
+-- Your SQL goes here
+
+ALTER TABLE users ADD COLUMN user_preferences JSONB NOT NULL DEFAULT '{}';
@@ -0,0 +1,15 @@
+-- Your SQL goes here
+
+-- entries outlive the users, files and collections they mention, so only the user is a foreign key
+CREATE TABLE audit_logs (
+  id BIGSERIAL NOT NULL PRIMARY KEY,
+  user_id INTEGER,
+  action TEXT NOT NULL,
+  file_id UUID,
+  collection_id UUID,
+  details JSONB NOT NULL DEFAULT '{}',
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  CONSTRAINT audit_logs_user_fk FOREIGN KEY (user_id) REFERENCES users(id) ON UPDATE CASCADE ON DELETE SET NULL
+);
+
+CREATE INDEX ON audit_logs(user_id, id);
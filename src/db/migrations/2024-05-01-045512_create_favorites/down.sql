@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+
+DROP TABLE favorite_collections;
+DROP TABLE favorite_files;
@@ -0,0 +1,23 @@
+-- Your SQL goes here
+
+CREATE TABLE favorite_files (
+  user_id INTEGER NOT NULL,
+  file_id UUID NOT NULL,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  PRIMARY KEY (user_id, file_id),
+  CONSTRAINT favorite_files_user_fk FOREIGN KEY (user_id) REFERENCES users(id) ON UPDATE CASCADE ON DELETE CASCADE,
+  CONSTRAINT favorite_files_file_fk FOREIGN KEY (file_id) REFERENCES files(id) ON UPDATE CASCADE ON DELETE CASCADE
+);
+
+CREATE INDEX ON favorite_files(file_id);
+
+CREATE TABLE favorite_collections (
+  user_id INTEGER NOT NULL,
+  collection_id UUID NOT NULL,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  PRIMARY KEY (user_id, collection_id),
+  CONSTRAINT favorite_collections_user_fk FOREIGN KEY (user_id) REFERENCES users(id) ON UPDATE CASCADE ON DELETE CASCADE,
+  CONSTRAINT favorite_collections_collection_fk FOREIGN KEY (collection_id) REFERENCES collections(id) ON UPDATE CASCADE ON DELETE CASCADE
+);
+
+CREATE INDEX ON favorite_collections(collection_id);
@@ -0,0 +1,13 @@
+-- Your SQL goes here
+
+CREATE TABLE file_views (
+  user_id INTEGER NOT NULL,
+  file_id UUID NOT NULL,
+  viewed_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  PRIMARY KEY (user_id, file_id),
+  CONSTRAINT file_views_user_fk FOREIGN KEY (user_id) REFERENCES users(id) ON UPDATE CASCADE ON DELETE CASCADE,
+  CONSTRAINT file_views_file_fk FOREIGN KEY (file_id) REFERENCES files(id) ON UPDATE CASCADE ON DELETE CASCADE
+);
+
+CREATE INDEX ON file_views(file_id);
+CREATE INDEX ON file_views(user_id, viewed_at DESC);
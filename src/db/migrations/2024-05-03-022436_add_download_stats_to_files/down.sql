@@ -0,0 +1,7 @@
+-- This file should undo anything in `up.sql`
+
+DROP INDEX files_download_count_id_idx;
+
+ALTER TABLE files DROP COLUMN last_downloaded_at;
+ALTER TABLE files DROP COLUMN served_bytes;
+ALTER TABLE files DROP COLUMN download_count;
@@ -0,0 +1,7 @@
+-- Your SQL goes here
+
+ALTER TABLE files ADD COLUMN download_count BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE files ADD COLUMN served_bytes BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE files ADD COLUMN last_downloaded_at TIMESTAMP NULL;
+
+CREATE INDEX files_download_count_id_idx ON files(download_count, id);
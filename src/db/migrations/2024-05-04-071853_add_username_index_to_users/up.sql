@@ -0,0 +1,7 @@
+-- Your SQL goes here
+
+-- usernames were not unique before, so duplicates are renamed apart, keeping the oldest user's name intact
+UPDATE users SET username = username || '_' || id
+WHERE id NOT IN (SELECT MIN(id) FROM users GROUP BY username);
+
+CREATE UNIQUE INDEX users_username_idx ON users(username);
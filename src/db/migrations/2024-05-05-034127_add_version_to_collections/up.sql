@@ -0,0 +1,3 @@
+-- Your SQL goes here
+
+ALTER TABLE collections ADD COLUMN version INTEGER NOT NULL DEFAULT 1;
@@ -0,0 +1,13 @@
+-- Your SQL goes here
+
+CREATE TABLE pending_commits (
+  file_id UUID NOT NULL PRIMARY KEY,
+  stage TEXT NOT NULL,
+  attempts INTEGER NOT NULL DEFAULT 0,
+  last_error TEXT NULL,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  next_attempt_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  CONSTRAINT pending_commits_file_fk FOREIGN KEY (file_id) REFERENCES files(id) ON UPDATE CASCADE ON DELETE CASCADE
+);
+
+CREATE INDEX ON pending_commits(next_attempt_at);
@@ -0,0 +1,17 @@
+-- Your SQL goes here
+
+CREATE TABLE file_versions (
+  id UUID NOT NULL PRIMARY KEY,
+  file_id UUID NOT NULL,
+  version INTEGER NOT NULL,
+  name TEXT NOT NULL,
+  mime TEXT NOT NULL,
+  size BIGINT NOT NULL,
+  hash BIGINT NOT NULL,
+  uploaded_at TIMESTAMP NOT NULL,
+  file_metadata JSONB NULL,
+  search_content TEXT NULL,
+  replaced_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  CONSTRAINT file_versions_file_fk FOREIGN KEY (file_id) REFERENCES files(id) ON UPDATE CASCADE ON DELETE CASCADE,
+  CONSTRAINT file_versions_version_unique UNIQUE (file_id, version)
+);
@@ -0,0 +1,15 @@
+-- Your SQL goes here
+
+CREATE TABLE file_comments (
+  id UUID NOT NULL PRIMARY KEY,
+  file_id UUID NOT NULL,
+  user_id INTEGER NOT NULL,
+  content TEXT NOT NULL,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  edited_at TIMESTAMP,
+  CONSTRAINT file_comments_file_fk FOREIGN KEY (file_id) REFERENCES files(id) ON UPDATE CASCADE ON DELETE CASCADE,
+  CONSTRAINT file_comments_user_fk FOREIGN KEY (user_id) REFERENCES users(id) ON UPDATE CASCADE ON DELETE CASCADE
+);
+
+CREATE INDEX ON file_comments(file_id, created_at, id);
+CREATE INDEX ON file_comments(user_id);
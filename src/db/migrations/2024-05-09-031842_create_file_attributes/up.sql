@@ -0,0 +1,11 @@
+-- Your SQL goes here
+
+CREATE TABLE file_attributes (
+  file_id UUID NOT NULL,
+  key TEXT NOT NULL,
+  value TEXT NOT NULL,
+  PRIMARY KEY (file_id, key),
+  CONSTRAINT file_attributes_file_fk FOREIGN KEY (file_id) REFERENCES files(id) ON UPDATE CASCADE ON DELETE CASCADE
+);
+
+CREATE INDEX ON file_attributes(key, value);
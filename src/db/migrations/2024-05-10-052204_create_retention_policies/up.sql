@@ -0,0 +1,11 @@
+-- Your SQL goes here
+
+CREATE TABLE retention_policies (
+  collection_id UUID NOT NULL PRIMARY KEY,
+  max_age_days INTEGER,
+  keep_latest INTEGER,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  updated_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  CONSTRAINT retention_policies_collection_fk FOREIGN KEY (collection_id) REFERENCES collections(id) ON UPDATE CASCADE ON DELETE CASCADE,
+  CONSTRAINT retention_policies_rule_check CHECK (max_age_days IS NOT NULL OR keep_latest IS NOT NULL)
+);
@@ -0,0 +1,9 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE file_versions DROP COLUMN scanned_at;
+ALTER TABLE file_versions DROP COLUMN scan_signature;
+ALTER TABLE file_versions DROP COLUMN scan_status;
+
+ALTER TABLE files DROP COLUMN scanned_at;
+ALTER TABLE files DROP COLUMN scan_signature;
+ALTER TABLE files DROP COLUMN scan_status;
@@ -0,0 +1,11 @@
+-- Your SQL goes here
+
+ALTER TABLE files ADD COLUMN scan_status TEXT NULL;
+ALTER TABLE files ADD COLUMN scan_signature TEXT NULL;
+ALTER TABLE files ADD COLUMN scanned_at TIMESTAMP NULL;
+ALTER TABLE files ADD CONSTRAINT files_scan_status_check CHECK (scan_status IN ('clean', 'infected'));
+
+ALTER TABLE file_versions ADD COLUMN scan_status TEXT NULL;
+ALTER TABLE file_versions ADD COLUMN scan_signature TEXT NULL;
+ALTER TABLE file_versions ADD COLUMN scanned_at TIMESTAMP NULL;
+ALTER TABLE file_versions ADD CONSTRAINT file_versions_scan_status_check CHECK (scan_status IN ('clean', 'infected'));
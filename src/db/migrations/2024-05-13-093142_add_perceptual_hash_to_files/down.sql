@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE file_versions DROP COLUMN perceptual_hash;
+
+ALTER TABLE files DROP COLUMN perceptual_hash;
@@ -0,0 +1,5 @@
+-- Your SQL goes here
+
+ALTER TABLE files ADD COLUMN perceptual_hash BIGINT NULL;
+
+ALTER TABLE file_versions ADD COLUMN perceptual_hash BIGINT NULL;
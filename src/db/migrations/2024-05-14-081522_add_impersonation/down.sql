@@ -0,0 +1,6 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE audit_logs DROP COLUMN impersonated_by;
+
+ALTER TABLE user_sessions DROP COLUMN expires_at;
+ALTER TABLE user_sessions DROP COLUMN impersonated_by;
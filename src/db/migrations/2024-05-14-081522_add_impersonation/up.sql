@@ -0,0 +1,9 @@
+-- Your SQL goes here
+
+-- sessions of admins acting as a user; they expire, unlike the sessions users log in with
+-- `impersonated_by` has no foreign key, as a second one to `users` would make the joins between the tables ambiguous
+ALTER TABLE user_sessions ADD COLUMN impersonated_by INTEGER NULL;
+ALTER TABLE user_sessions ADD COLUMN expires_at TIMESTAMP NULL;
+
+-- the admin who has taken the action on behalf of the user
+ALTER TABLE audit_logs ADD COLUMN impersonated_by INTEGER NULL;
@@ -0,0 +1,4 @@
+-- Your SQL goes here
+
+-- disabled users cannot log in, but keep their files and audit history
+ALTER TABLE users ADD COLUMN disabled_at TIMESTAMP NULL;
@@ -1,4 +1,4 @@
-use chrono::NaiveDateTime;
+use chrono::{NaiveDate, NaiveDateTime};
 use diesel::{
     associations::Identifiable, deserialize::Queryable, prelude::Insertable,
     query_builder::AsChangeset, Selectable,
@@ -15,12 +15,17 @@ pub struct Collection {
     pub name: String,
     pub description: Option<String>,
     pub created_at: NaiveDateTime,
+    /// Incremented on every update; clients send it back in `If-Match` to avoid overwriting newer changes.
+    /// Defaults to `0` for documents serialized before it existed, e.g. old backups and search indices.
+    #[serde(default)]
+    pub version: i32,
 }
 
 #[derive(Serialize, Deserialize, Insertable, Debug, Clone, PartialEq)]
 #[diesel(table_name = crate::db::schema::collections)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
 pub struct CreatingCollection<'a> {
+    pub id: Uuid,
     pub name: &'a str,
     pub description: Option<&'a str>,
 }
@@ -42,6 +47,20 @@ pub struct User {
     pub username: String,
     pub email: String,
     pub joined_at: NaiveDateTime,
+    pub display_name: Option<String>,
+    /// The file holding the avatar image of the user, if any.
+    pub avatar_file_id: Option<Uuid>,
+    /// The role of the user, either `user` or `admin`.
+    /// Defaults to `user` for documents serialized before it existed, e.g. old backups.
+    #[serde(default = "default_user_role")]
+    pub role: String,
+    /// When the user has been disabled, if they are. Disabled users cannot log in.
+    #[serde(default)]
+    pub disabled_at: Option<NaiveDateTime>,
+}
+
+fn default_user_role() -> String {
+    "user".to_owned()
 }
 
 #[derive(Serialize, Deserialize, Selectable, Queryable, Identifiable, Debug, Clone, PartialEq)]
@@ -51,6 +70,7 @@ pub struct User {
 pub struct UserIdWithPassword {
     pub id: i32,
     pub password: String,
+    pub disabled_at: Option<NaiveDateTime>,
 }
 
 #[derive(Serialize, Deserialize, Insertable, Debug, Clone, PartialEq)]
@@ -73,12 +93,40 @@ pub struct UserSession {
     pub created_at: NaiveDateTime,
 }
 
+/// A session of an admin acting as a user, which expires unlike the sessions users log in with.
+#[derive(Serialize, Deserialize, Queryable, Debug, Clone, PartialEq)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[serde(rename_all = "camelCase")]
+pub struct ImpersonationSession {
+    pub user_id: i32,
+    pub token: String,
+    pub created_at: NaiveDateTime,
+    pub impersonated_by: i32,
+    pub expires_at: NaiveDateTime,
+}
+
 #[derive(Serialize, Deserialize, Insertable, Debug, Clone, PartialEq)]
 #[diesel(table_name = crate::db::schema::user_sessions)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
 pub struct CreatingUserSession<'a> {
     pub user_id: i32,
     pub token: &'a str,
+    pub user_agent: Option<&'a str>,
+    pub ip_address: Option<String>,
+    pub impersonated_by: Option<i32>,
+    pub expires_at: Option<NaiveDateTime>,
+}
+
+#[derive(Serialize, Deserialize, Selectable, Queryable, Debug, Clone, PartialEq)]
+#[diesel(table_name = crate::db::schema::user_sessions)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[serde(rename_all = "camelCase")]
+pub struct UserSessionWithClientInfo {
+    pub user_id: i32,
+    pub token: String,
+    pub created_at: NaiveDateTime,
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Selectable, Queryable, Identifiable, Debug, Clone, PartialEq)]
@@ -103,6 +151,12 @@ pub struct CreatingFile<'a> {
     pub mime: &'a str,
     pub size: i64,
     pub hash: i64,
+    pub file_metadata: Option<serde_json::Value>,
+    pub search_content: Option<&'a str>,
+    pub scan_status: Option<&'a str>,
+    pub scan_signature: Option<&'a str>,
+    pub scanned_at: Option<NaiveDateTime>,
+    pub perceptual_hash: Option<i64>,
 }
 
 #[derive(Serialize, Deserialize, Selectable, Queryable, Identifiable, Debug, Clone, PartialEq)]
@@ -113,6 +167,7 @@ pub struct CreatingFile<'a> {
 pub struct CollectionFilePair {
     pub collection_id: Uuid,
     pub file_id: Uuid,
+    pub position: Option<i32>,
 }
 
 #[derive(Serialize, Deserialize, Insertable, Debug, Clone, PartialEq)]
@@ -123,6 +178,118 @@ pub struct CreatingCollectionFilePair {
     pub file_id: Uuid,
 }
 
+#[derive(Serialize, Deserialize, Insertable, Debug, Clone, PartialEq)]
+#[diesel(table_name = crate::db::schema::file_views)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct CreatingFileView {
+    pub user_id: i32,
+    pub file_id: Uuid,
+}
+
+/// A user-defined attribute of a file, e.g. `camera=X100V`.
+#[derive(Serialize, Deserialize, Selectable, Queryable, Debug, Clone, PartialEq)]
+#[diesel(table_name = crate::db::schema::file_attributes)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[serde(rename_all = "camelCase")]
+pub struct FileAttribute {
+    pub file_id: Uuid,
+    pub key: String,
+    pub value: String,
+}
+
+#[derive(Serialize, Deserialize, Insertable, Debug, Clone, PartialEq)]
+#[diesel(table_name = crate::db::schema::file_attributes)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct CreatingFileAttribute<'a> {
+    pub file_id: Uuid,
+    pub key: &'a str,
+    pub value: &'a str,
+}
+
+/// A comment left on a file by a user.
+#[derive(Serialize, Deserialize, Selectable, Queryable, Debug, Clone, PartialEq)]
+#[diesel(table_name = crate::db::schema::file_comments)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[serde(rename_all = "camelCase")]
+pub struct FileComment {
+    pub id: Uuid,
+    pub file_id: Uuid,
+    /// The user who wrote the comment.
+    pub user_id: i32,
+    pub content: String,
+    pub created_at: NaiveDateTime,
+    /// The last time the comment was edited, or `None` if it never was.
+    pub edited_at: Option<NaiveDateTime>,
+}
+
+#[derive(Serialize, Deserialize, Insertable, Debug, Clone, PartialEq)]
+#[diesel(table_name = crate::db::schema::file_comments)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct CreatingFileComment<'a> {
+    pub id: Uuid,
+    pub file_id: Uuid,
+    pub user_id: i32,
+    pub content: &'a str,
+}
+
+/// A former version of a file, kept when the file was replaced by a newer upload.
+/// Its data is stored under its own ID.
+#[derive(Serialize, Deserialize, Selectable, Queryable, Debug, Clone, PartialEq)]
+#[diesel(table_name = crate::db::schema::file_versions)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[serde(rename_all = "camelCase")]
+pub struct FileVersion {
+    pub id: Uuid,
+    pub file_id: Uuid,
+    /// Numbered from `1` in the order the versions were replaced.
+    pub version: i32,
+    pub name: String,
+    pub mime: String,
+    pub size: i64,
+    pub hash: i64,
+    pub uploaded_at: NaiveDateTime,
+    pub replaced_at: NaiveDateTime,
+}
+
+/// A file promotion whose storage commit or indexing has not completed yet.
+#[derive(Serialize, Deserialize, Selectable, Queryable, Debug, Clone, PartialEq)]
+#[diesel(table_name = crate::db::schema::pending_commits)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[serde(rename_all = "camelCase")]
+pub struct PendingCommit {
+    pub file_id: Uuid,
+    /// The step to run next; either `data` or `index`.
+    pub stage: String,
+    pub attempts: i32,
+    pub last_error: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub next_attempt_at: NaiveDateTime,
+}
+
+#[derive(Serialize, Deserialize, Insertable, Debug, Clone, PartialEq)]
+#[diesel(table_name = crate::db::schema::pending_commits)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct CreatingPendingCommit<'a> {
+    pub file_id: Uuid,
+    pub stage: &'a str,
+}
+
+#[derive(Serialize, Deserialize, Insertable, Debug, Clone, PartialEq)]
+#[diesel(table_name = crate::db::schema::favorite_files)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct CreatingFavoriteFile {
+    pub user_id: i32,
+    pub file_id: Uuid,
+}
+
+#[derive(Serialize, Deserialize, Insertable, Debug, Clone, PartialEq)]
+#[diesel(table_name = crate::db::schema::favorite_collections)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct CreatingFavoriteCollection {
+    pub user_id: i32,
+    pub collection_id: Uuid,
+}
+
 #[derive(Serialize, Deserialize, Selectable, Queryable, Identifiable, Debug, Clone, PartialEq)]
 #[diesel(table_name = crate::db::schema::staging_files)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
@@ -133,15 +300,22 @@ pub struct StagingFile {
     pub mime: Option<String>,
     pub size: i64,
     pub staged_at: NaiveDateTime,
+    /// The size the uploader declared the file will have once fully staged.
+    pub expected_size: Option<i64>,
+    /// The hash the uploader declared the file will have once fully staged.
+    pub expected_hash: Option<i64>,
 }
 
 #[derive(Serialize, Deserialize, Insertable, Debug, Clone, PartialEq)]
 #[diesel(table_name = crate::db::schema::staging_files)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
 pub struct CreatingStagingFile<'a> {
+    pub id: Uuid,
     pub name: &'a str,
     pub mime: Option<&'a str>,
     pub size: i64,
+    pub expected_size: Option<i64>,
+    pub expected_hash: Option<i64>,
 }
 
 #[derive(Serialize, Deserialize, AsChangeset, Debug, Clone, PartialEq)]
@@ -152,19 +326,256 @@ pub struct UpdatingStagingFile<'a> {
     pub mime: Option<&'a str>,
 }
 
+#[derive(Serialize, Deserialize, Selectable, Queryable, Identifiable, Debug, Clone, PartialEq)]
+#[diesel(primary_key(staging_file_id, chunk_offset))]
+#[diesel(table_name = crate::db::schema::staging_file_chunks)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[serde(rename_all = "camelCase")]
+pub struct StagingFileChunk {
+    pub staging_file_id: Uuid,
+    pub chunk_offset: i64,
+    pub size: i64,
+    pub created_at: NaiveDateTime,
+    /// The time the chunk was fully written, or `None` if it is still being written.
+    pub completed_at: Option<NaiveDateTime>,
+}
+
+#[derive(Serialize, Deserialize, Insertable, Debug, Clone, PartialEq)]
+#[diesel(table_name = crate::db::schema::staging_file_chunks)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct CreatingStagingFileChunk {
+    pub staging_file_id: Uuid,
+    pub chunk_offset: i64,
+    pub size: i64,
+}
+
+#[derive(Serialize, Deserialize, Selectable, Queryable, Identifiable, Debug, Clone, PartialEq)]
+#[diesel(table_name = crate::db::schema::tags)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(primary_key(namespace, name, file_id))]
+#[serde(rename_all = "camelCase")]
+pub struct Tag {
+    pub name: String,
+    pub file_id: Uuid,
+    /// The namespace of the tag, e.g. `year` for `year:1959`; empty if the tag has no namespace.
+    #[serde(default)]
+    pub namespace: String,
+}
+
 #[derive(Serialize, Deserialize, Insertable, Debug, Clone, PartialEq)]
 #[diesel(table_name = crate::db::schema::tags)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
 pub struct CreatingTag<'a> {
     pub name: &'a str,
     pub file_id: Uuid,
+    pub namespace: &'a str,
 }
 
 #[derive(Serialize, Deserialize, Identifiable, Debug, Clone, PartialEq)]
 #[diesel(table_name = crate::db::schema::tags)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
-#[diesel(primary_key(name, file_id))]
+#[diesel(primary_key(namespace, name, file_id))]
 pub struct RemovingTag<'a> {
     pub name: &'a str,
     pub file_id: Uuid,
+    pub namespace: &'a str,
+}
+
+#[derive(Serialize, Deserialize, Selectable, Queryable, Identifiable, Debug, Clone, PartialEq)]
+#[diesel(table_name = crate::db::schema::transcode_jobs)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[serde(rename_all = "camelCase")]
+pub struct TranscodeJob {
+    pub id: Uuid,
+    pub file_id: Uuid,
+    pub profile: String,
+    pub status: String,
+    pub error: Option<String>,
+    pub attempts: i32,
+    pub size: Option<i64>,
+    pub created_at: NaiveDateTime,
+    pub started_at: Option<NaiveDateTime>,
+    pub finished_at: Option<NaiveDateTime>,
+}
+
+#[derive(Serialize, Deserialize, Insertable, Debug, Clone, PartialEq)]
+#[diesel(table_name = crate::db::schema::transcode_jobs)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct CreatingTranscodeJob<'a> {
+    pub id: Uuid,
+    pub file_id: Uuid,
+    pub profile: &'a str,
+}
+
+#[derive(Serialize, Deserialize, Selectable, Queryable, Identifiable, Debug, Clone, PartialEq)]
+#[diesel(primary_key(token))]
+#[diesel(table_name = crate::db::schema::upload_tickets)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[serde(rename_all = "camelCase")]
+pub struct UploadTicket {
+    pub token: String,
+    pub user_id: i32,
+    pub max_size: i64,
+    pub file_id: Option<Uuid>,
+    pub created_at: NaiveDateTime,
+    pub expires_at: NaiveDateTime,
+    pub used_at: Option<NaiveDateTime>,
+}
+
+#[derive(Serialize, Deserialize, Insertable, Debug, Clone, PartialEq)]
+#[diesel(table_name = crate::db::schema::upload_tickets)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct CreatingUploadTicket<'a> {
+    pub token: &'a str,
+    pub user_id: i32,
+    pub user_session_token: &'a str,
+    pub max_size: i64,
+    pub expires_at: NaiveDateTime,
+}
+
+#[derive(Serialize, Deserialize, Selectable, Queryable, Identifiable, Debug, Clone, PartialEq)]
+#[diesel(primary_key(token))]
+#[diesel(table_name = crate::db::schema::password_reset_tokens)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[serde(rename_all = "camelCase")]
+pub struct PasswordResetToken {
+    pub token: String,
+    pub user_id: i32,
+    pub created_at: NaiveDateTime,
+    pub expires_at: NaiveDateTime,
+}
+
+#[derive(Serialize, Deserialize, Insertable, Debug, Clone, PartialEq)]
+#[diesel(table_name = crate::db::schema::password_reset_tokens)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct CreatingPasswordResetToken<'a> {
+    pub token: &'a str,
+    pub user_id: i32,
+    pub expires_at: NaiveDateTime,
+}
+
+#[derive(Serialize, Deserialize, Selectable, Queryable, Identifiable, Debug, Clone, PartialEq)]
+#[diesel(primary_key(token))]
+#[diesel(table_name = crate::db::schema::email_change_requests)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[serde(rename_all = "camelCase")]
+pub struct EmailChangeRequest {
+    pub token: String,
+    pub user_id: i32,
+    pub new_email: String,
+    pub created_at: NaiveDateTime,
+    pub expires_at: NaiveDateTime,
+}
+
+#[derive(Serialize, Deserialize, Insertable, Debug, Clone, PartialEq)]
+#[diesel(table_name = crate::db::schema::email_change_requests)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct CreatingEmailChangeRequest<'a> {
+    pub token: &'a str,
+    pub user_id: i32,
+    pub new_email: &'a str,
+    pub expires_at: NaiveDateTime,
+}
+
+#[derive(Serialize, Deserialize, Selectable, Queryable, Identifiable, Debug, Clone, PartialEq)]
+#[diesel(table_name = crate::db::schema::shares)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[serde(rename_all = "camelCase")]
+pub struct Share {
+    pub id: Uuid,
+    pub token: String,
+    pub user_id: i32,
+    pub file_id: Option<Uuid>,
+    pub collection_id: Option<Uuid>,
+    pub created_at: NaiveDateTime,
+    pub expires_at: Option<NaiveDateTime>,
+}
+
+#[derive(Serialize, Deserialize, Insertable, Debug, Clone, PartialEq)]
+#[diesel(table_name = crate::db::schema::shares)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct CreatingShare<'a> {
+    pub id: Uuid,
+    pub token: &'a str,
+    pub user_id: i32,
+    pub file_id: Option<Uuid>,
+    pub collection_id: Option<Uuid>,
+    pub expires_at: Option<NaiveDateTime>,
+}
+
+/// Which files of a collection to let go of periodically.
+/// A file is let go if it is older than `max_age_days`, or if it is not among the `keep_latest` most recent files.
+#[derive(Serialize, Deserialize, Selectable, Queryable, Identifiable, Debug, Clone, PartialEq)]
+#[diesel(primary_key(collection_id))]
+#[diesel(table_name = crate::db::schema::retention_policies)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[serde(rename_all = "camelCase")]
+pub struct RetentionPolicy {
+    pub collection_id: Uuid,
+    pub max_age_days: Option<i32>,
+    pub keep_latest: Option<i32>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Serialize, Deserialize, Insertable, Debug, Clone, PartialEq)]
+#[diesel(table_name = crate::db::schema::retention_policies)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct CreatingRetentionPolicy {
+    pub collection_id: Uuid,
+    pub max_age_days: Option<i32>,
+    pub keep_latest: Option<i32>,
+}
+
+#[derive(Serialize, Deserialize, Selectable, Queryable, Identifiable, Debug, Clone, PartialEq)]
+#[diesel(primary_key(date))]
+#[diesel(table_name = crate::db::schema::stats_history)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[serde(rename_all = "camelCase")]
+pub struct StatsSnapshot {
+    pub date: NaiveDate,
+    pub file_count: i64,
+    pub byte_count: i64,
+    pub user_count: i64,
+    pub tag_count: i64,
+    pub recorded_at: NaiveDateTime,
+}
+
+#[derive(Serialize, Deserialize, Insertable, Debug, Clone, PartialEq)]
+#[diesel(table_name = crate::db::schema::stats_history)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct CreatingStatsSnapshot {
+    pub date: NaiveDate,
+    pub file_count: i64,
+    pub byte_count: i64,
+    pub user_count: i64,
+    pub tag_count: i64,
+}
+
+#[derive(Serialize, Deserialize, Selectable, Queryable, Identifiable, Debug, Clone, PartialEq)]
+#[diesel(table_name = crate::db::schema::audit_logs)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[serde(rename_all = "camelCase")]
+pub struct AuditLog {
+    pub id: i64,
+    pub user_id: Option<i32>,
+    pub action: String,
+    pub file_id: Option<Uuid>,
+    pub collection_id: Option<Uuid>,
+    pub details: serde_json::Value,
+    pub created_at: NaiveDateTime,
+    /// The admin who has taken the action while impersonating the user, if any.
+    pub impersonated_by: Option<i32>,
+}
+
+#[derive(Serialize, Deserialize, Insertable, Debug, Clone, PartialEq)]
+#[diesel(table_name = crate::db::schema::audit_logs)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct CreatingAuditLog<'a> {
+    pub user_id: Option<i32>,
+    pub action: &'a str,
+    pub file_id: Option<Uuid>,
+    pub collection_id: Option<Uuid>,
+    pub details: serde_json::Value,
+    pub impersonated_by: Option<i32>,
 }
@@ -1,9 +1,23 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    audit_logs (id) {
+        id -> Int8,
+        user_id -> Nullable<Int4>,
+        action -> Text,
+        file_id -> Nullable<Uuid>,
+        collection_id -> Nullable<Uuid>,
+        details -> Jsonb,
+        created_at -> Timestamp,
+        impersonated_by -> Nullable<Int4>,
+    }
+}
+
 diesel::table! {
     collection_file_pairs (collection_id, file_id) {
         collection_id -> Uuid,
         file_id -> Uuid,
+        position -> Nullable<Int4>,
     }
 }
 
@@ -13,6 +27,80 @@ diesel::table! {
         name -> Text,
         description -> Nullable<Text>,
         created_at -> Timestamp,
+        version -> Int4,
+    }
+}
+
+diesel::table! {
+    email_change_requests (token) {
+        token -> Text,
+        user_id -> Int4,
+        new_email -> Text,
+        created_at -> Timestamp,
+        expires_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    favorite_collections (user_id, collection_id) {
+        user_id -> Int4,
+        collection_id -> Uuid,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    favorite_files (user_id, file_id) {
+        user_id -> Int4,
+        file_id -> Uuid,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    file_attributes (file_id, key) {
+        file_id -> Uuid,
+        key -> Text,
+        value -> Text,
+    }
+}
+
+diesel::table! {
+    file_comments (id) {
+        id -> Uuid,
+        file_id -> Uuid,
+        user_id -> Int4,
+        content -> Text,
+        created_at -> Timestamp,
+        edited_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    file_versions (id) {
+        id -> Uuid,
+        file_id -> Uuid,
+        version -> Int4,
+        name -> Text,
+        mime -> Text,
+        size -> Int8,
+        hash -> Int8,
+        uploaded_at -> Timestamp,
+        file_metadata -> Nullable<Jsonb>,
+        search_content -> Nullable<Text>,
+        replaced_at -> Timestamp,
+        scan_status -> Nullable<Text>,
+        scan_signature -> Nullable<Text>,
+        scanned_at -> Nullable<Timestamp>,
+        perceptual_hash -> Nullable<Int8>,
+    }
+}
+
+diesel::table! {
+    file_views (user_id, file_id) {
+        user_id -> Int4,
+        file_id -> Uuid,
+        viewed_at -> Timestamp,
     }
 }
 
@@ -24,6 +112,68 @@ diesel::table! {
         size -> Int8,
         hash -> Int8,
         uploaded_at -> Timestamp,
+        file_metadata -> Nullable<Jsonb>,
+        verified_at -> Nullable<Timestamp>,
+        search_content -> Nullable<Text>,
+        download_count -> Int8,
+        served_bytes -> Int8,
+        last_downloaded_at -> Nullable<Timestamp>,
+        scan_status -> Nullable<Text>,
+        scan_signature -> Nullable<Text>,
+        scanned_at -> Nullable<Timestamp>,
+        perceptual_hash -> Nullable<Int8>,
+    }
+}
+
+diesel::table! {
+    password_reset_tokens (token) {
+        token -> Text,
+        user_id -> Int4,
+        created_at -> Timestamp,
+        expires_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    pending_commits (file_id) {
+        file_id -> Uuid,
+        stage -> Text,
+        attempts -> Int4,
+        last_error -> Nullable<Text>,
+        created_at -> Timestamp,
+        next_attempt_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    retention_policies (collection_id) {
+        collection_id -> Uuid,
+        max_age_days -> Nullable<Int4>,
+        keep_latest -> Nullable<Int4>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    shares (id) {
+        id -> Uuid,
+        token -> Text,
+        user_id -> Int4,
+        file_id -> Nullable<Uuid>,
+        collection_id -> Nullable<Uuid>,
+        created_at -> Timestamp,
+        expires_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    staging_file_chunks (staging_file_id, chunk_offset) {
+        staging_file_id -> Uuid,
+        chunk_offset -> Int8,
+        size -> Int8,
+        created_at -> Timestamp,
+        completed_at -> Nullable<Timestamp>,
     }
 }
 
@@ -34,13 +184,55 @@ diesel::table! {
         mime -> Nullable<Text>,
         size -> Int8,
         staged_at -> Timestamp,
+        expected_size -> Nullable<Int8>,
+        expected_hash -> Nullable<Int8>,
+    }
+}
+
+diesel::table! {
+    stats_history (date) {
+        date -> Date,
+        file_count -> Int8,
+        byte_count -> Int8,
+        user_count -> Int8,
+        tag_count -> Int8,
+        recorded_at -> Timestamp,
     }
 }
 
 diesel::table! {
-    tags (name, file_id) {
+    tags (namespace, name, file_id) {
         name -> Text,
         file_id -> Uuid,
+        namespace -> Text,
+    }
+}
+
+diesel::table! {
+    transcode_jobs (id) {
+        id -> Uuid,
+        file_id -> Uuid,
+        profile -> Text,
+        status -> Text,
+        error -> Nullable<Text>,
+        attempts -> Int4,
+        size -> Nullable<Int8>,
+        created_at -> Timestamp,
+        started_at -> Nullable<Timestamp>,
+        finished_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    upload_tickets (token) {
+        token -> Text,
+        user_id -> Int4,
+        user_session_token -> Nullable<Text>,
+        max_size -> Int8,
+        file_id -> Nullable<Uuid>,
+        created_at -> Timestamp,
+        expires_at -> Timestamp,
+        used_at -> Nullable<Timestamp>,
     }
 }
 
@@ -49,6 +241,10 @@ diesel::table! {
         token -> Text,
         user_id -> Int4,
         created_at -> Timestamp,
+        user_agent -> Nullable<Text>,
+        ip_address -> Nullable<Text>,
+        impersonated_by -> Nullable<Int4>,
+        expires_at -> Nullable<Timestamp>,
     }
 }
 
@@ -59,20 +255,64 @@ diesel::table! {
         email -> Text,
         password -> Text,
         joined_at -> Timestamp,
+        display_name -> Nullable<Text>,
+        avatar_file_id -> Nullable<Uuid>,
+        user_preferences -> Jsonb,
+        role -> Text,
+        disabled_at -> Nullable<Timestamp>,
     }
 }
 
+diesel::joinable!(audit_logs -> users (user_id));
 diesel::joinable!(collection_file_pairs -> collections (collection_id));
 diesel::joinable!(collection_file_pairs -> files (file_id));
+diesel::joinable!(email_change_requests -> users (user_id));
+diesel::joinable!(favorite_collections -> collections (collection_id));
+diesel::joinable!(favorite_collections -> users (user_id));
+diesel::joinable!(favorite_files -> files (file_id));
+diesel::joinable!(favorite_files -> users (user_id));
+diesel::joinable!(file_attributes -> files (file_id));
+diesel::joinable!(file_comments -> files (file_id));
+diesel::joinable!(file_comments -> users (user_id));
+diesel::joinable!(file_versions -> files (file_id));
+diesel::joinable!(file_views -> files (file_id));
+diesel::joinable!(file_views -> users (user_id));
+diesel::joinable!(password_reset_tokens -> users (user_id));
+diesel::joinable!(pending_commits -> files (file_id));
+diesel::joinable!(retention_policies -> collections (collection_id));
+diesel::joinable!(shares -> collections (collection_id));
+diesel::joinable!(shares -> files (file_id));
+diesel::joinable!(shares -> users (user_id));
+diesel::joinable!(staging_file_chunks -> staging_files (staging_file_id));
 diesel::joinable!(tags -> files (file_id));
+diesel::joinable!(transcode_jobs -> files (file_id));
+diesel::joinable!(upload_tickets -> user_sessions (user_session_token));
+diesel::joinable!(upload_tickets -> users (user_id));
 diesel::joinable!(user_sessions -> users (user_id));
+diesel::joinable!(users -> files (avatar_file_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
+    audit_logs,
     collection_file_pairs,
     collections,
+    email_change_requests,
+    favorite_collections,
+    favorite_files,
+    file_attributes,
+    file_comments,
+    file_versions,
+    file_views,
     files,
+    password_reset_tokens,
+    pending_commits,
+    retention_policies,
+    shares,
+    staging_file_chunks,
     staging_files,
+    stats_history,
     tags,
+    transcode_jobs,
+    upload_tickets,
     user_sessions,
     users,
 );
@@ -1,5 +1,11 @@
-use rocket::{http::Status, serde::json::Json, Responder};
-use serde::Serialize;
+use crate::config::AppConfig;
+use rocket::{
+    http::{ContentType, MediaType, Status},
+    response::{self, Responder},
+    serde::{json::Json, msgpack::MsgPack},
+    Request,
+};
+use serde::{Deserialize, Serialize};
 
 #[derive(Responder, Serialize, Debug, Clone, PartialEq, Eq, Hash)]
 #[serde(untagged)]
@@ -8,13 +14,172 @@ pub enum ErrorBodyKind {
     Dynamic(String),
 }
 
+/// An invalid field of a request body.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FieldError {
+    pub field: &'static str,
+    pub message: String,
+}
+
+/// A machine-readable code of an error, so that clients can branch on it instead of parsing the message.
+/// Errors without a more specific meaning get the code of their status, e.g. `NOT_FOUND`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ErrorCode {
+    BadRequest,
+    Unauthorized,
+    Forbidden,
+    NotFound,
+    MethodNotAllowed,
+    NotAcceptable,
+    RequestTimeout,
+    Conflict,
+    Gone,
+    LengthRequired,
+    PreconditionFailed,
+    PayloadTooLarge,
+    UnsupportedMediaType,
+    RangeNotSatisfiable,
+    UnprocessableEntity,
+    TooManyRequests,
+    InternalError,
+    BadGateway,
+    ServiceUnavailable,
+    /// Any other `4xx` status.
+    ClientError,
+    /// Any other status.
+    ServerError,
+    /// The request body failed validation; the invalid fields are listed in `fields`.
+    ValidationFailed,
+    /// A query parameter or header has an invalid value.
+    InvalidParameter,
+    InvalidCursor,
+    InvalidCredentials,
+    TooManyLoginAttempts,
+    LoginProviderNotConfigured,
+    LoginDenied,
+    UnverifiedEmail,
+    UserNotFound,
+    /// The user has been disabled, and cannot log in.
+    UserDisabled,
+    /// The user is an admin, and admins cannot be impersonated.
+    AdminNotImpersonable,
+    /// Admins cannot disable themselves, so that they cannot lock themselves out.
+    CannotDisableSelf,
+    UsernameTaken,
+    EmailTaken,
+    SessionNotFound,
+    TokenNotFound,
+    AvatarNotFound,
+    NotAnImage,
+    PreferencesTooLarge,
+    FileNotFound,
+    FileVersionNotFound,
+    CommentNotFound,
+    RenditionNotFound,
+    RenditionNotReady,
+    RenditionFailed,
+    CollectionNotFound,
+    CollectionNameTaken,
+    VersionMismatch,
+    FileNotInCollection,
+    FileAlreadyInCollection,
+    RetentionPolicyNotFound,
+    ShareNotFound,
+    UploadTicketNotFound,
+    StagingFileNotFound,
+    StagingFileNotFilled,
+    UploadNotFound,
+    OffsetExceedsSize,
+    OffsetMismatch,
+    ChunkOverlaps,
+    SizeExceedsExpected,
+    FileTooLarge,
+    HashMismatch,
+    ChecksumMismatch,
+    TooManyTransfers,
+    InsufficientStorage,
+    /// The storage does not support uploading directly to it.
+    DirectUploadUnsupported,
+    /// Nothing has been uploaded directly to the storage yet.
+    DirectUploadMissing,
+    /// The MIME type of the file being promoted is not allowed by the policy.
+    MimeNotAllowed,
+    /// Malware has been found in the file being promoted.
+    FileInfected,
+    /// The file has been promoted despite containing malware, and is not served.
+    FileQuarantined,
+    /// The collection already has the maximum number of files.
+    CollectionFull,
+    /// The config file could not be loaded, e.g. when reloading it.
+    InvalidConfig,
+}
+
+impl ErrorCode {
+    /// The code of errors that have no more specific meaning than their status.
+    pub fn from_status(status: Status) -> Self {
+        match status.code {
+            400 => Self::BadRequest,
+            401 => Self::Unauthorized,
+            403 => Self::Forbidden,
+            404 => Self::NotFound,
+            405 => Self::MethodNotAllowed,
+            406 => Self::NotAcceptable,
+            408 => Self::RequestTimeout,
+            409 => Self::Conflict,
+            410 => Self::Gone,
+            411 => Self::LengthRequired,
+            412 => Self::PreconditionFailed,
+            413 => Self::PayloadTooLarge,
+            415 => Self::UnsupportedMediaType,
+            416 => Self::RangeNotSatisfiable,
+            422 => Self::UnprocessableEntity,
+            429 => Self::TooManyRequests,
+            500 => Self::InternalError,
+            502 => Self::BadGateway,
+            503 => Self::ServiceUnavailable,
+            code if (400..500).contains(&code) => Self::ClientError,
+            _ => Self::ServerError,
+        }
+    }
+}
+
 #[derive(Responder, Serialize, Debug, Clone, PartialEq, Eq, Hash)]
 #[response(content_type = "json")]
 pub struct ErrorBody {
     pub error: ErrorBodyKind,
+    #[response(ignore)]
+    pub error_code: ErrorCode,
+    /// The invalid fields, if the request body failed validation.
+    #[response(ignore)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<FieldError>,
+    /// The period in seconds to send in `Retry-After`, if the client should retry later.
+    #[response(ignore)]
+    #[serde(skip)]
+    pub retry_after: Option<u64>,
+}
+
+/// An error body in the Problem Details format of RFC 7807.
+/// The code and the invalid fields of [`ErrorBody`] are kept as extension members.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ProblemDetails {
+    /// `about:blank` if the error has no more specific meaning than its status.
+    #[serde(rename = "type")]
+    pub problem_type: String,
+    pub title: &'static str,
+    pub status: u16,
+    pub detail: ErrorBodyKind,
+    pub instance: String,
+    pub error_code: ErrorCode,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<FieldError>,
 }
 
-#[derive(Responder, Debug, Clone, PartialEq, Eq, Hash)]
+/// An error response.
+/// The body is in the Problem Details format if `problem_details` is set in the config,
+/// or if the client prefers `application/problem+json` in `Accept`, and is an [`ErrorBody`] otherwise.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Error((Status, Json<ErrorBody>));
 
 impl Error {
@@ -23,6 +188,9 @@ impl Error {
             status,
             Json(ErrorBody {
                 error: ErrorBodyKind::Static(message),
+                error_code: ErrorCode::from_status(status),
+                fields: Vec::new(),
+                retry_after: None,
             }),
         ))
     }
@@ -32,13 +200,108 @@ impl Error {
             status,
             Json(ErrorBody {
                 error: ErrorBodyKind::Dynamic(message.into()),
+                error_code: ErrorCode::from_status(status),
+                fields: Vec::new(),
+                retry_after: None,
+            }),
+        ))
+    }
+
+    /// Creates a `422 Unprocessable Entity` error listing the invalid fields of a request body.
+    pub fn new_validation(fields: Vec<FieldError>) -> Self {
+        Error((
+            Status::UnprocessableEntity,
+            Json(ErrorBody {
+                error: ErrorBodyKind::Static("validation failed"),
+                error_code: ErrorCode::ValidationFailed,
+                fields,
+                retry_after: None,
             }),
         ))
     }
 
+    /// Creates a `404 Not Found` error for a missing resource, e.g. with `FILE_NOT_FOUND`.
+    pub fn new_not_found(code: ErrorCode) -> Self {
+        Self::from(Status::NotFound).with_code(code)
+    }
+
+    /// Replaces the code of the error, which is derived from its status by default.
+    pub fn with_code(mut self, code: ErrorCode) -> Self {
+        self.0 .1.error_code = code;
+        self
+    }
+
+    /// Asks the client to retry after the given period in seconds.
+    pub fn with_retry_after(mut self, seconds: u64) -> Self {
+        self.0 .1.retry_after = Some(seconds);
+        self
+    }
+
     pub fn status(&self) -> Status {
         self.0 .0
     }
+
+    /// The body of the error, e.g. to report it as a part of a batch response.
+    pub fn into_body(self) -> ErrorBody {
+        self.0 .1.into_inner()
+    }
+}
+
+impl<'r> Responder<'r, 'static> for Error {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        let (status, Json(body)) = self.0;
+        let retry_after = body.retry_after;
+        let forced = request
+            .rocket()
+            .state::<AppConfig>()
+            .is_some_and(|app_config| app_config.problem_details);
+        let prefers_problem_details = request.accept().is_some_and(|accept| {
+            let media_type = accept.preferred().media_type();
+            media_type.top() == "application" && media_type.sub() == "problem+json"
+        });
+
+        if !forced && !prefers_problem_details {
+            let mut response = (status, Json(body)).respond_to(request)?;
+            response.set_raw_header("Vary", "Accept");
+
+            if let Some(retry_after) = retry_after {
+                response.set_raw_header("Retry-After", retry_after.to_string());
+            }
+
+            return Ok(response);
+        }
+
+        let problem_type = if body.error_code == ErrorCode::from_status(status) {
+            "about:blank".to_owned()
+        } else {
+            match serde_json::to_value(body.error_code) {
+                Ok(serde_json::Value::String(code)) => format!("urn:poly-tag:error:{}", code),
+                _ => "about:blank".to_owned(),
+            }
+        };
+        let problem = ProblemDetails {
+            problem_type,
+            title: status.reason().unwrap_or("Unknown"),
+            status: status.code,
+            detail: body.error,
+            instance: request.uri().path().to_string(),
+            error_code: body.error_code,
+            fields: body.fields,
+        };
+
+        let mut response = (status, Json(problem)).respond_to(request)?;
+        response.set_header(ContentType::new("application", "problem+json"));
+
+        if !forced {
+            response.set_raw_header("Vary", "Accept");
+        }
+
+        if let Some(retry_after) = retry_after {
+            response.set_raw_header("Retry-After", retry_after.to_string());
+        }
+
+        Ok(response)
+    }
 }
 
 impl From<Status> for Error {
@@ -90,4 +353,40 @@ impl From<Status> for Error {
     }
 }
 
-pub type JsonRes<T> = Result<(Status, Json<T>), Error>;
+/// A successful response body with its status.
+/// The body is serialized to MessagePack if the client prefers `application/msgpack` in `Accept`, and to JSON otherwise.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ApiResponse<T> {
+    pub status: Status,
+    pub body: T,
+}
+
+impl<T> ApiResponse<T> {
+    pub fn new(status: Status, body: T) -> Self {
+        Self { status, body }
+    }
+}
+
+impl<'r, T> Responder<'r, 'static> for ApiResponse<T>
+where
+    T: Serialize,
+{
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        let prefers_msgpack = request
+            .accept()
+            .is_some_and(|accept| accept.preferred().media_type() == &MediaType::MsgPack);
+
+        let mut response = if prefers_msgpack {
+            MsgPack(self.body).respond_to(request)?
+        } else {
+            Json(self.body).respond_to(request)?
+        };
+        response.set_status(self.status);
+        // the representation depends on the request, so caches have to key on it too
+        response.set_raw_header("Vary", "Accept");
+
+        Ok(response)
+    }
+}
+
+pub type JsonRes<T> = Result<ApiResponse<T>, Error>;
@@ -1,8 +1,20 @@
+#[cfg(unix)]
+mod config_reloader;
 mod initial_user_creator;
+mod pending_commit_retrier;
+mod retention_applier;
 mod staging_file_remover;
+mod stats_recorder;
+mod transcoder;
 
+#[cfg(unix)]
+pub use config_reloader::*;
 pub use initial_user_creator::*;
+pub use pending_commit_retrier::*;
+pub use retention_applier::*;
 pub use staging_file_remover::*;
+pub use stats_recorder::*;
+pub use transcoder::*;
 
 use crate::config::AppConfig;
 use chrono::Duration;
@@ -11,11 +23,35 @@ use rocket::{Build, Rocket};
 pub fn register_fairings(rocket: Rocket<Build>, app_config: &AppConfig) -> Rocket<Build> {
     let staging_file_remover = StagingFileRemover::new(
         Duration::new(app_config.expired_staging_file_removal_period as i64, 0).unwrap(),
-        Duration::new(app_config.expired_staging_file_expiration as i64, 0).unwrap(),
+        app_config.expired_staging_file_removal_batch_size,
+        app_config.expired_staging_file_removal_max_batches,
     );
     let initial_user_creator = InitialUserCreator::new();
+    let pending_commit_retrier = PendingCommitRetrier::new(std::time::Duration::from_secs(
+        app_config.pending_commit_retry_period,
+    ));
+    let stats_recorder = StatsRecorder::new(std::time::Duration::from_secs(
+        app_config.stats_history_recording_period,
+    ));
+    let retention_applier = RetentionApplier::new(std::time::Duration::from_secs(
+        app_config.retention_policy_period,
+    ));
+    let transcoder = Transcoder::new(
+        app_config.transcode.enabled,
+        std::time::Duration::from_secs(app_config.transcode.poll_period),
+    );
 
-    rocket
+    let rocket = rocket
         .attach(staging_file_remover)
         .attach(initial_user_creator)
+        .attach(pending_commit_retrier)
+        .attach(stats_recorder)
+        .attach(retention_applier)
+        .attach(transcoder);
+
+    // reloading on `SIGHUP` is only available on Unix; elsewhere the config is reloaded through the admin API
+    #[cfg(unix)]
+    let rocket = rocket.attach(ConfigReloader::new());
+
+    rocket
 }
@@ -0,0 +1,118 @@
+use crate::services::LiveConfigService;
+use parking_lot::Mutex;
+use rocket::{
+    fairing::{Fairing, Info},
+    Orbit, Rocket,
+};
+use std::sync::Arc;
+use tokio::signal::unix::{signal, SignalKind};
+
+/// Reloads the live config on `SIGHUP`.
+pub struct ConfigReloader {
+    stop_signal_sender: Mutex<Option<tokio::sync::oneshot::Sender<()>>>,
+    task_join_handle: Mutex<Option<tokio::task::JoinHandle<()>>>,
+}
+
+impl ConfigReloader {
+    pub fn new() -> Self {
+        ConfigReloader {
+            stop_signal_sender: Mutex::new(None),
+            task_join_handle: Mutex::new(None),
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl Fairing for ConfigReloader {
+    fn info(&self) -> Info {
+        Info {
+            name: "Config Reloader",
+            kind: rocket::fairing::Kind::Liftoff | rocket::fairing::Kind::Shutdown,
+        }
+    }
+
+    async fn on_liftoff(&self, rocket: &Rocket<Orbit>) {
+        log::info!(target: "config_reloader", "Starting config reloader.");
+
+        let (stop_signal_sender, stop_signal_receiver) = tokio::sync::oneshot::channel();
+        let live_config_service = rocket.state::<Arc<LiveConfigService>>().unwrap().clone();
+
+        let task_join_handle = tokio::spawn(reload_config_task(
+            stop_signal_receiver,
+            live_config_service,
+        ));
+
+        let mut stop_signal_sender_lock = self.stop_signal_sender.lock();
+        *stop_signal_sender_lock = Some(stop_signal_sender);
+        drop(stop_signal_sender_lock);
+
+        let mut task_join_handle_lock = self.task_join_handle.lock();
+        *task_join_handle_lock = Some(task_join_handle);
+        drop(task_join_handle_lock);
+
+        log::info!(target: "config_reloader", "Config reloader started.");
+    }
+
+    async fn on_shutdown(&self, _rocket: &Rocket<Orbit>) {
+        log::info!(target: "config_reloader", "Shutting down config reloader.");
+
+        let task_join_handle = {
+            let mut stop_signal_sender_lock = self.stop_signal_sender.lock();
+            let stop_signal_sender = stop_signal_sender_lock.take();
+            drop(stop_signal_sender_lock);
+
+            if let Some(stop_signal_sender) = stop_signal_sender {
+                stop_signal_sender.send(()).ok();
+            }
+
+            let mut task_join_handle_lock = self.task_join_handle.lock();
+            let task_join_handle = task_join_handle_lock.take();
+            drop(task_join_handle_lock);
+
+            task_join_handle
+        };
+
+        if let Some(task_join_handle) = task_join_handle {
+            task_join_handle.await.ok();
+        }
+
+        log::info!(target: "config_reloader", "Config reloader shut down.");
+    }
+}
+
+async fn reload_config_task(
+    mut stop_signal_receiver: tokio::sync::oneshot::Receiver<()>,
+    live_config_service: Arc<LiveConfigService>,
+) {
+    let mut hangup = match signal(SignalKind::hangup()) {
+        Ok(hangup) => hangup,
+        Err(err) => {
+            log::warn!(target: "config_reloader", err:err; "Failed to listen for SIGHUP. The config can still be reloaded by `POST /admin/reload-config`.");
+            return;
+        }
+    };
+
+    loop {
+        tokio::select! {
+            _ = hangup.recv() => {
+                reload_config(&live_config_service);
+            }
+            _ = &mut stop_signal_receiver => {
+                break;
+            }
+        }
+    }
+}
+
+fn reload_config(live_config_service: &LiveConfigService) {
+    match live_config_service.reload() {
+        Ok(config) => {
+            let config = config.as_ref();
+            log::info!(target: "config_reloader", config:serde; "Reloaded config.");
+        }
+        Err(err) => {
+            // the current values are kept, so that a broken config file does not take the server down
+            log::warn!(target: "config_reloader", err:err; "Failed to reload config.");
+        }
+    }
+}
@@ -1,4 +1,7 @@
-use crate::{config::AppConfig, services::UserService};
+use crate::{
+    config::{AppConfig, SeedUser},
+    services::UserService,
+};
 use rocket::{
     fairing::{Fairing, Info},
     Orbit, Rocket,
@@ -26,65 +29,79 @@ impl Fairing for InitialUserCreator {
         let app_config = rocket.state::<AppConfig>().unwrap();
         let user_service = rocket.state::<Arc<UserService>>().unwrap();
 
-        let initial_user_config = match &app_config.initial_user {
-            Some(user) => {
-                log::info!(target: "fairings::initial_user_creator", method = "on_liftoff", fairing = "InitialUserCreator"; "Initial user configuration found.");
-                user
-            }
-            None => {
-                log::info!(target: "fairings::initial_user_creator", method = "on_liftoff", fairing = "InitialUserCreator"; "Initial user configuration not found. Skipping.");
-                return;
-            }
-        };
-
-        let initial_user = user_service
-            .get_user_by_email(&initial_user_config.email)
-            .await;
-        let initial_user = match initial_user {
-            Ok(user) => user,
-            Err(err) => {
-                log::warn!(target: "fairings::initial_user_creator", method = "on_liftoff", fairing = "InitialUserCreator", service = "UserService", err:err; "Error returned when attempting to get initial user. Aborting.");
-                return;
-            }
-        };
+        let seed_users = app_config.all_seed_users();
 
-        match initial_user {
-            Some(initial_user) => {
-                log::info!(target: "fairings::initial_user_creator", method = "on_liftoff", fairing = "InitialUserCreator"; "Initial user already exists. Updating.");
+        if seed_users.is_empty() {
+            log::info!(target: "fairings::initial_user_creator", method = "on_liftoff", fairing = "InitialUserCreator"; "Initial user configuration not found. Skipping.");
+            return;
+        }
 
-                let result = user_service
-                    .set_user_username_by_id(initial_user.id, &initial_user_config.username)
-                    .await;
+        log::info!(target: "fairings::initial_user_creator", method = "on_liftoff", fairing = "InitialUserCreator", count = seed_users.len(); "Initial user configuration found.");
 
-                if let Err(err) = result {
-                    log::warn!(target: "fairings::initial_user_creator", method = "on_liftoff", fairing = "InitialUserCreator", service = "UserService", err:err; "Error returned when attempting to update username of initial user.");
-                }
+        for seed_user in &seed_users {
+            seed(user_service, seed_user).await;
+        }
 
-                let result = user_service
-                    .set_user_password_by_id(initial_user.id, &initial_user_config.password)
-                    .await;
+        log::info!(target: "fairings::initial_user_creator", method = "on_liftoff", fairing = "InitialUserCreator"; "Initial users are ready.");
+    }
+}
 
-                if let Err(err) = result {
-                    log::warn!(target: "fairings::initial_user_creator", method = "on_liftoff", fairing = "InitialUserCreator", service = "UserService", err:err; "Error returned when attempting to update password of initial user.");
-                }
+/// Creates the seed user, or updates the user with the same email to match it.
+async fn seed(user_service: &UserService, seed_user: &SeedUser) {
+    let email = seed_user.email.as_str();
+    let user = user_service.get_user_by_email(email).await;
+    let user = match user {
+        Ok(user) => user,
+        Err(err) => {
+            log::warn!(target: "fairings::initial_user_creator", method = "seed", fairing = "InitialUserCreator", service = "UserService", email, err:err; "Error returned when attempting to get initial user. Skipping.");
+            return;
+        }
+    };
+
+    let user_id = match user {
+        Some(user) => {
+            log::info!(target: "fairings::initial_user_creator", method = "seed", fairing = "InitialUserCreator", email; "Initial user already exists. Updating.");
+
+            let result = user_service
+                .set_user_username_by_id(user.id, &seed_user.username)
+                .await;
+
+            if let Err(err) = result {
+                log::warn!(target: "fairings::initial_user_creator", method = "seed", fairing = "InitialUserCreator", service = "UserService", email, err:err; "Error returned when attempting to update username of initial user.");
+            }
+
+            let result = user_service
+                .set_user_password_by_id(user.id, &seed_user.password)
+                .await;
+
+            if let Err(err) = result {
+                log::warn!(target: "fairings::initial_user_creator", method = "seed", fairing = "InitialUserCreator", service = "UserService", email, err:err; "Error returned when attempting to update password of initial user.");
             }
-            None => {
-                log::info!(target: "fairings::initial_user_creator", method = "on_liftoff", fairing = "InitialUserCreator"; "Initial user does not exist. Creating.");
-
-                let result = user_service
-                    .create_user(
-                        &initial_user_config.username,
-                        &initial_user_config.email,
-                        &initial_user_config.password,
-                    )
-                    .await;
-
-                if let Err(err) = result {
-                    log::warn!(target: "fairings::initial_user_creator", method = "on_liftoff", fairing = "InitialUserCreator", service = "UserService", err:err; "Error returned when attempting to create initial user.");
+
+            user.id
+        }
+        None => {
+            log::info!(target: "fairings::initial_user_creator", method = "seed", fairing = "InitialUserCreator", email; "Initial user does not exist. Creating.");
+
+            let result = user_service
+                .create_user(&seed_user.username, email, &seed_user.password)
+                .await;
+
+            match result {
+                Ok(user) => user.id,
+                Err(err) => {
+                    log::warn!(target: "fairings::initial_user_creator", method = "seed", fairing = "InitialUserCreator", service = "UserService", email, err:err; "Error returned when attempting to create initial user.");
+                    return;
                 }
             }
         }
+    };
+
+    let result = user_service
+        .set_user_role_by_id(user_id, seed_user.role)
+        .await;
 
-        log::info!(target: "fairings::initial_user_creator", method = "on_liftoff", fairing = "InitialUserCreator"; "Initial user is ready.");
+    if let Err(err) = result {
+        log::warn!(target: "fairings::initial_user_creator", method = "seed", fairing = "InitialUserCreator", service = "UserService", email, err:err; "Error returned when attempting to update role of initial user.");
     }
 }
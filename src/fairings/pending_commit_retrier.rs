@@ -0,0 +1,197 @@
+use crate::services::FileService;
+use parking_lot::Mutex;
+use rocket::{
+    fairing::{Fairing, Info},
+    Orbit, Rocket,
+};
+use std::{sync::Arc, time::Duration};
+
+/// Periodically retries file promotions whose storage commit or indexing did not complete.
+pub struct PendingCommitRetrier {
+    period: Duration,
+    stop_signal_sender: Mutex<Option<tokio::sync::oneshot::Sender<()>>>,
+    task_join_handle: Mutex<Option<tokio::task::JoinHandle<()>>>,
+}
+
+impl PendingCommitRetrier {
+    pub fn new(period: Duration) -> Self {
+        PendingCommitRetrier {
+            period,
+            stop_signal_sender: Mutex::new(None),
+            task_join_handle: Mutex::new(None),
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl Fairing for PendingCommitRetrier {
+    fn info(&self) -> Info {
+        Info {
+            name: "Pending Commit Retrier",
+            kind: rocket::fairing::Kind::Liftoff | rocket::fairing::Kind::Shutdown,
+        }
+    }
+
+    async fn on_liftoff(&self, rocket: &Rocket<Orbit>) {
+        let period = self.period;
+
+        log::info!(target: "pending_commit_retrier", period:?; "Starting pending commit retrier.");
+
+        let (stop_signal_sender, stop_signal_receiver) = tokio::sync::oneshot::channel();
+        let file_service = rocket.state::<Arc<FileService>>().unwrap().clone();
+
+        let task_join_handle = tokio::spawn(retry_pending_commits_task(
+            stop_signal_receiver,
+            period,
+            file_service,
+        ));
+
+        let mut stop_signal_sender_lock = self.stop_signal_sender.lock();
+        *stop_signal_sender_lock = Some(stop_signal_sender);
+        drop(stop_signal_sender_lock);
+
+        let mut task_join_handle_lock = self.task_join_handle.lock();
+        *task_join_handle_lock = Some(task_join_handle);
+        drop(task_join_handle_lock);
+
+        log::info!(target: "pending_commit_retrier", "Pending commit retrier started.");
+    }
+
+    async fn on_shutdown(&self, _rocket: &Rocket<Orbit>) {
+        log::info!(target: "pending_commit_retrier", "Shutting down pending commit retrier.");
+
+        let task_join_handle = {
+            let mut stop_signal_sender_lock = self.stop_signal_sender.lock();
+            let stop_signal_sender = stop_signal_sender_lock.take();
+            drop(stop_signal_sender_lock);
+
+            if let Some(stop_signal_sender) = stop_signal_sender {
+                stop_signal_sender.send(()).ok();
+            }
+
+            let mut task_join_handle_lock = self.task_join_handle.lock();
+            let task_join_handle = task_join_handle_lock.take();
+            drop(task_join_handle_lock);
+
+            task_join_handle
+        };
+
+        if let Some(task_join_handle) = task_join_handle {
+            task_join_handle.await.ok();
+        }
+
+        log::info!(target: "pending_commit_retrier", "Pending commit retrier shut down.");
+    }
+}
+
+async fn retry_pending_commits_task(
+    mut stop_signal_receiver: tokio::sync::oneshot::Receiver<()>,
+    period: Duration,
+    file_service: Arc<FileService>,
+) {
+    loop {
+        tokio::select! {
+            _ = tokio::time::sleep(period) => {
+                retry_pending_commits(&file_service).await;
+            }
+            _ = &mut stop_signal_receiver => {
+                break;
+            }
+        }
+    }
+}
+
+async fn retry_pending_commits(file_service: &FileService) {
+    let result = file_service.retry_pending_commits(100).await;
+
+    match result {
+        Ok((0, 0)) => {}
+        Ok((completed_count, failed_count)) => {
+            log::info!(target: "pending_commit_retrier", completed_count, failed_count; "Retried pending commits.");
+        }
+        Err(err) => {
+            // the pending commits are retried in the next period
+            log::warn!(target: "pending_commit_retrier", err:err; "Failed to retry pending commits.");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        config::SearchBackend,
+        services::{FileService, StagingFileService},
+        test::{create_test_rocket_instance_with_config, TestFaults, TestFileDriver},
+    };
+    use rocket::local::asynchronous::Client;
+    use std::{
+        io::Cursor,
+        sync::{atomic::Ordering, Arc},
+        time::Duration,
+    };
+
+    #[rocket::async_test]
+    async fn test_pending_commit_retrier_completes_indexing() {
+        let faults = TestFaults::default();
+        let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance_with_config(
+            TestFileDriver::Memory,
+            SearchBackend::Meilisearch,
+            |app_config| {
+                app_config.pending_commit_retry_period = 1;
+                app_config.test_faults = faults.clone();
+            },
+        )
+        .await;
+        let client = Client::tracked(rocket).await.unwrap();
+        let staging_file_service = client.rocket().state::<Arc<StagingFileService>>().unwrap();
+        let file_service = client.rocket().state::<Arc<FileService>>().unwrap();
+
+        let staging_file = staging_file_service
+            .create_staging_file("file", Some("text/plain"), Some(12), None)
+            .await
+            .unwrap();
+        staging_file_service
+            .fill_staging_file_chunk_by_id(
+                staging_file.id,
+                0,
+                12,
+                Box::pin(Cursor::new(b"file content".to_vec())),
+            )
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+
+        faults.fail_file_indexing.store(true, Ordering::SeqCst);
+
+        let file = file_service
+            .create_file_from_staging_file_id(staging_file.id)
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert!(file_service
+            .get_pending_commit_by_file_id(file.id)
+            .await
+            .unwrap()
+            .is_some());
+
+        faults.fail_file_indexing.store(false, Ordering::SeqCst);
+
+        // the first attempt backs off for a second, and the retrier runs every second
+        for _ in 0..50 {
+            if file_service
+                .get_pending_commit_by_file_id(file.id)
+                .await
+                .unwrap()
+                .is_none()
+            {
+                return;
+            }
+
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+
+        panic!("the pending commit was not retried");
+    }
+}
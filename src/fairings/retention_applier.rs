@@ -0,0 +1,147 @@
+use crate::services::{AppliedRetention, AuditAction, AuditLogService, RetentionService};
+use parking_lot::Mutex;
+use rocket::{
+    fairing::{Fairing, Info},
+    Orbit, Rocket,
+};
+use std::{sync::Arc, time::Duration};
+
+/// The number of files a collection lets go of at most in a period.
+const RETENTION_BATCH_SIZE: u32 = 1000;
+
+/// Periodically applies the retention policies of collections.
+pub struct RetentionApplier {
+    period: Duration,
+    stop_signal_sender: Mutex<Option<tokio::sync::oneshot::Sender<()>>>,
+    task_join_handle: Mutex<Option<tokio::task::JoinHandle<()>>>,
+}
+
+impl RetentionApplier {
+    pub fn new(period: Duration) -> Self {
+        RetentionApplier {
+            period,
+            stop_signal_sender: Mutex::new(None),
+            task_join_handle: Mutex::new(None),
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl Fairing for RetentionApplier {
+    fn info(&self) -> Info {
+        Info {
+            name: "Retention Applier",
+            kind: rocket::fairing::Kind::Liftoff | rocket::fairing::Kind::Shutdown,
+        }
+    }
+
+    async fn on_liftoff(&self, rocket: &Rocket<Orbit>) {
+        let period = self.period;
+
+        log::info!(target: "retention_applier", period:?; "Starting retention applier.");
+
+        let (stop_signal_sender, stop_signal_receiver) = tokio::sync::oneshot::channel();
+        let retention_service = rocket.state::<Arc<RetentionService>>().unwrap().clone();
+        let audit_log_service = rocket.state::<Arc<AuditLogService>>().unwrap().clone();
+
+        let task_join_handle = tokio::spawn(apply_retention_policies_task(
+            stop_signal_receiver,
+            period,
+            retention_service,
+            audit_log_service,
+        ));
+
+        let mut stop_signal_sender_lock = self.stop_signal_sender.lock();
+        *stop_signal_sender_lock = Some(stop_signal_sender);
+        drop(stop_signal_sender_lock);
+
+        let mut task_join_handle_lock = self.task_join_handle.lock();
+        *task_join_handle_lock = Some(task_join_handle);
+        drop(task_join_handle_lock);
+
+        log::info!(target: "retention_applier", "Retention applier started.");
+    }
+
+    async fn on_shutdown(&self, _rocket: &Rocket<Orbit>) {
+        log::info!(target: "retention_applier", "Shutting down retention applier.");
+
+        let task_join_handle = {
+            let mut stop_signal_sender_lock = self.stop_signal_sender.lock();
+            let stop_signal_sender = stop_signal_sender_lock.take();
+            drop(stop_signal_sender_lock);
+
+            if let Some(stop_signal_sender) = stop_signal_sender {
+                stop_signal_sender.send(()).ok();
+            }
+
+            let mut task_join_handle_lock = self.task_join_handle.lock();
+            let task_join_handle = task_join_handle_lock.take();
+            drop(task_join_handle_lock);
+
+            task_join_handle
+        };
+
+        if let Some(task_join_handle) = task_join_handle {
+            task_join_handle.await.ok();
+        }
+
+        log::info!(target: "retention_applier", "Retention applier shut down.");
+    }
+}
+
+async fn apply_retention_policies_task(
+    mut stop_signal_receiver: tokio::sync::oneshot::Receiver<()>,
+    period: Duration,
+    retention_service: Arc<RetentionService>,
+    audit_log_service: Arc<AuditLogService>,
+) {
+    loop {
+        tokio::select! {
+            _ = tokio::time::sleep(period) => {
+                apply_retention_policies(&retention_service, &audit_log_service).await;
+            }
+            _ = &mut stop_signal_receiver => {
+                break;
+            }
+        }
+    }
+}
+
+pub(crate) async fn apply_retention_policies(
+    retention_service: &RetentionService,
+    audit_log_service: &AuditLogService,
+) {
+    let applied = match retention_service.apply_policies(RETENTION_BATCH_SIZE).await {
+        Ok(applied) => applied,
+        Err(err) => {
+            // the policies are applied again in the next period
+            log::warn!(target: "retention_applier", err:err; "Failed to apply retention policies.");
+            return;
+        }
+    };
+
+    for AppliedRetention { policy, file_ids } in applied {
+        let collection_id = policy.collection_id;
+        let file_count = file_ids.len();
+
+        log::info!(target: "retention_applier", collection_id:serde, file_count; "Applied retention policy.");
+
+        let details = serde_json::json!({
+            "maxAgeDays": policy.max_age_days,
+            "keepLatest": policy.keep_latest,
+            "fileIds": file_ids,
+        });
+        let result = audit_log_service
+            .record_system(
+                AuditAction::RetentionApplied,
+                None,
+                Some(collection_id),
+                details,
+            )
+            .await;
+
+        if let Err(err) = result {
+            log::warn!(target: "retention_applier", service = "AuditLogService", collection_id:serde, err:err; "Failed to record the audit log.");
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use crate::services::StagingFileService;
+use crate::services::{ExpiredStagingFileRemovalError, LiveConfigService, StagingFileService};
 use chrono::Duration;
 use parking_lot::Mutex;
 use rocket::{
@@ -7,18 +7,22 @@ use rocket::{
 };
 use std::sync::Arc;
 
+/// Periodically removes expired staging files, and the staging data left behind without a staging file.
+/// The expiration is read from the live config in every period, so that reloading the config changes it.
 pub struct StagingFileRemover {
     period: Duration,
-    expiration: Duration,
+    batch_size: u32,
+    max_batches: u32,
     stop_signal_sender: Mutex<Option<tokio::sync::oneshot::Sender<()>>>,
     task_join_handle: Mutex<Option<tokio::task::JoinHandle<()>>>,
 }
 
 impl StagingFileRemover {
-    pub fn new(period: Duration, expiration: Duration) -> Self {
+    pub fn new(period: Duration, batch_size: u32, max_batches: u32) -> Self {
         StagingFileRemover {
             period,
-            expiration,
+            batch_size: u32::max(1, batch_size),
+            max_batches: u32::max(1, max_batches),
             stop_signal_sender: Mutex::new(None),
             task_join_handle: Mutex::new(None),
         }
@@ -36,18 +40,22 @@ impl Fairing for StagingFileRemover {
 
     async fn on_liftoff(&self, rocket: &Rocket<Orbit>) {
         let period = self.period;
-        let expiration = self.expiration;
+        let batch_size = self.batch_size;
+        let max_batches = self.max_batches;
 
-        log::info!(target: "staging_file_remover", period:%, expiration:%; "Starting staging file remover.");
+        log::info!(target: "staging_file_remover", period:%, batch_size, max_batches; "Starting staging file remover.");
 
         let (stop_signal_sender, stop_signal_receiver) = tokio::sync::oneshot::channel();
         let staging_file_service = rocket.state::<Arc<StagingFileService>>().unwrap().clone();
+        let live_config_service = rocket.state::<Arc<LiveConfigService>>().unwrap().clone();
 
         let task_join_handle = tokio::spawn(remove_expired_staging_files_task(
             stop_signal_receiver,
             period,
-            expiration,
+            batch_size,
+            max_batches,
             staging_file_service,
+            live_config_service,
         ));
 
         let mut stop_signal_sender_lock = self.stop_signal_sender.lock();
@@ -91,8 +99,10 @@ impl Fairing for StagingFileRemover {
 async fn remove_expired_staging_files_task(
     mut stop_signal_receiver: tokio::sync::oneshot::Receiver<()>,
     period: Duration,
-    expiration: Duration,
+    batch_size: u32,
+    max_batches: u32,
     staging_file_service: Arc<StagingFileService>,
+    live_config_service: Arc<LiveConfigService>,
 ) {
     let period = match period.to_std() {
         Ok(period) => period,
@@ -105,7 +115,17 @@ async fn remove_expired_staging_files_task(
     loop {
         tokio::select! {
             _ = tokio::time::sleep(period) => {
-                remove_expired_staging_files(expiration, &staging_file_service).await;
+                let expiration = Duration::seconds(
+                    live_config_service.get().expired_staging_file_expiration as i64,
+                );
+                remove_expired_staging_files(
+                    expiration,
+                    batch_size,
+                    max_batches,
+                    &staging_file_service,
+                )
+                .await;
+                remove_orphaned_staging_files(expiration, &staging_file_service).await;
             }
             _ = &mut stop_signal_receiver => {
                 break;
@@ -114,25 +134,79 @@ async fn remove_expired_staging_files_task(
     }
 }
 
-async fn remove_expired_staging_files(
+pub(crate) async fn remove_expired_staging_files(
     expiration: Duration,
+    batch_size: u32,
+    max_batches: u32,
     staging_file_service: &StagingFileService,
 ) {
-    log::info!(target: "staging_file_remover", expiration:%; "Removing expired staging files.");
+    log::info!(target: "staging_file_remover", expiration:%, batch_size, max_batches; "Removing expired staging files.");
+
+    let mut total_count = 0;
+    let mut io_failed_count = 0;
+
+    // keep removing batches until a batch comes short, which means the expired staging files are drained
+    for batch in 1..=max_batches {
+        let result = staging_file_service
+            .remove_expired_staging_files(expiration, batch_size)
+            .await;
+
+        let (count, io_errs) = match result {
+            Ok(result) => result,
+            Err(err) => {
+                // failing to remove expired staging files is not a critical error, they are retried in the next period
+                log::warn!(target: "staging_file_remover", batch, total_count, err:err; "Failed to remove expired staging files.");
+                return;
+            }
+        };
+
+        total_count += count;
+        io_failed_count += io_errs.len();
 
+        let batch_io_failed_count = io_errs.len();
+        let batch_io_succeeded_count = count - batch_io_failed_count;
+        log::info!(target: "staging_file_remover", batch, count, io_succeeded_count = batch_io_succeeded_count, io_failed_count = batch_io_failed_count, total_count; "Removed a batch of expired staging files.");
+        log_io_errors(&io_errs);
+
+        if count < batch_size as usize {
+            break;
+        }
+
+        if batch == max_batches {
+            log::warn!(target: "staging_file_remover", max_batches, total_count; "Reached the maximum number of batches; the rest is removed in the next period.");
+        }
+    }
+
+    let io_succeeded_count = total_count - io_failed_count;
+    log::info!(target: "staging_file_remover", expiration:%, total_count, io_succeeded_count, io_failed_count; "Removed expired staging files.");
+}
+
+pub(crate) async fn remove_orphaned_staging_files(
+    expiration: Duration,
+    staging_file_service: &StagingFileService,
+) {
     let result = staging_file_service
-        .remove_expired_staging_files(expiration, 100)
+        .remove_orphaned_staging_files(expiration)
         .await;
 
     match result {
-        Ok((total_count, io_errs)) => {
+        Ok((0, io_errs)) if io_errs.is_empty() => {}
+        Ok((removed_count, io_errs)) => {
             let io_failed_count = io_errs.len();
-            let io_succeeded_count = total_count - io_failed_count;
-            log::info!(target: "staging_file_remover", expiration:%, total_count, io_succeeded_count, io_failed_count, io_errs:?; "Removed expired staging files.");
+            log::info!(target: "staging_file_remover", expiration:%, removed_count, io_failed_count; "Removed orphaned staging files.");
+            log_io_errors(&io_errs);
         }
         Err(err) => {
-            // failing to remove expired staging files is not a critical error
-            log::warn!(target: "staging_file_remover", err:err; "Failed to remove expired staging files.");
+            // orphaned staging files are looked for again in the next period
+            log::warn!(target: "staging_file_remover", err:err; "Failed to remove orphaned staging files.");
         }
     }
 }
+
+fn log_io_errors(io_errs: &[ExpiredStagingFileRemovalError]) {
+    for io_err in io_errs {
+        let id = io_err.id;
+        let err = &io_err.error;
+        log::warn!(target: "staging_file_remover", id:serde, err:%; "Failed to remove the data of a staging file.");
+    }
+}
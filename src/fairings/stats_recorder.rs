@@ -0,0 +1,114 @@
+use crate::services::StatsService;
+use parking_lot::Mutex;
+use rocket::{
+    fairing::{Fairing, Info},
+    Orbit, Rocket,
+};
+use std::{sync::Arc, time::Duration};
+
+pub struct StatsRecorder {
+    period: Duration,
+    stop_signal_sender: Mutex<Option<tokio::sync::oneshot::Sender<()>>>,
+    task_join_handle: Mutex<Option<tokio::task::JoinHandle<()>>>,
+}
+
+impl StatsRecorder {
+    pub fn new(period: Duration) -> Self {
+        StatsRecorder {
+            period,
+            stop_signal_sender: Mutex::new(None),
+            task_join_handle: Mutex::new(None),
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl Fairing for StatsRecorder {
+    fn info(&self) -> Info {
+        Info {
+            name: "Stats Recorder",
+            kind: rocket::fairing::Kind::Liftoff | rocket::fairing::Kind::Shutdown,
+        }
+    }
+
+    async fn on_liftoff(&self, rocket: &Rocket<Orbit>) {
+        let period = self.period;
+
+        log::info!(target: "stats_recorder", period:?; "Starting stats recorder.");
+
+        let (stop_signal_sender, stop_signal_receiver) = tokio::sync::oneshot::channel();
+        let stats_service = rocket.state::<Arc<StatsService>>().unwrap().clone();
+
+        let task_join_handle = tokio::spawn(record_stats_task(
+            stop_signal_receiver,
+            period,
+            stats_service,
+        ));
+
+        let mut stop_signal_sender_lock = self.stop_signal_sender.lock();
+        *stop_signal_sender_lock = Some(stop_signal_sender);
+        drop(stop_signal_sender_lock);
+
+        let mut task_join_handle_lock = self.task_join_handle.lock();
+        *task_join_handle_lock = Some(task_join_handle);
+        drop(task_join_handle_lock);
+
+        log::info!(target: "stats_recorder", "Stats recorder started.");
+    }
+
+    async fn on_shutdown(&self, _rocket: &Rocket<Orbit>) {
+        log::info!(target: "stats_recorder", "Shutting down stats recorder.");
+
+        let task_join_handle = {
+            let mut stop_signal_sender_lock = self.stop_signal_sender.lock();
+            let stop_signal_sender = stop_signal_sender_lock.take();
+            drop(stop_signal_sender_lock);
+
+            if let Some(stop_signal_sender) = stop_signal_sender {
+                stop_signal_sender.send(()).ok();
+            }
+
+            let mut task_join_handle_lock = self.task_join_handle.lock();
+            let task_join_handle = task_join_handle_lock.take();
+            drop(task_join_handle_lock);
+
+            task_join_handle
+        };
+
+        if let Some(task_join_handle) = task_join_handle {
+            task_join_handle.await.ok();
+        }
+
+        log::info!(target: "stats_recorder", "Stats recorder shut down.");
+    }
+}
+
+async fn record_stats_task(
+    mut stop_signal_receiver: tokio::sync::oneshot::Receiver<()>,
+    period: Duration,
+    stats_service: Arc<StatsService>,
+) {
+    // record right away, so that short-lived processes still leave a snapshot of the day
+    loop {
+        record_stats(&stats_service).await;
+
+        tokio::select! {
+            _ = tokio::time::sleep(period) => {}
+            _ = &mut stop_signal_receiver => {
+                break;
+            }
+        }
+    }
+}
+
+async fn record_stats(stats_service: &StatsService) {
+    match stats_service.record_snapshot().await {
+        Ok(snapshot) => {
+            log::info!(target: "stats_recorder", snapshot:serde; "Recorded stats snapshot.");
+        }
+        Err(err) => {
+            // failing to record a snapshot is not a critical error, it will be retried on the next period
+            log::warn!(target: "stats_recorder", err:err; "Failed to record stats snapshot.");
+        }
+    }
+}
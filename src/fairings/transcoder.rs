@@ -0,0 +1,140 @@
+use crate::services::TranscodeService;
+use parking_lot::Mutex;
+use rocket::{
+    fairing::{Fairing, Info},
+    Orbit, Rocket,
+};
+use std::{sync::Arc, time::Duration};
+
+pub struct Transcoder {
+    enabled: bool,
+    poll_period: Duration,
+    stop_signal_sender: Mutex<Option<tokio::sync::oneshot::Sender<()>>>,
+    task_join_handle: Mutex<Option<tokio::task::JoinHandle<()>>>,
+}
+
+impl Transcoder {
+    pub fn new(enabled: bool, poll_period: Duration) -> Self {
+        Transcoder {
+            enabled,
+            poll_period,
+            stop_signal_sender: Mutex::new(None),
+            task_join_handle: Mutex::new(None),
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl Fairing for Transcoder {
+    fn info(&self) -> Info {
+        Info {
+            name: "Transcoder",
+            kind: rocket::fairing::Kind::Liftoff | rocket::fairing::Kind::Shutdown,
+        }
+    }
+
+    async fn on_liftoff(&self, rocket: &Rocket<Orbit>) {
+        if !self.enabled {
+            log::info!(target: "transcoder", "Transcoder is disabled. Skipping.");
+            return;
+        }
+
+        let poll_period = self.poll_period;
+
+        log::info!(target: "transcoder", poll_period:?; "Starting transcoder.");
+
+        let (stop_signal_sender, stop_signal_receiver) = tokio::sync::oneshot::channel();
+        let transcode_service = rocket.state::<Arc<TranscodeService>>().unwrap().clone();
+
+        let task_join_handle = tokio::spawn(transcode_task(
+            stop_signal_receiver,
+            poll_period,
+            transcode_service,
+        ));
+
+        let mut stop_signal_sender_lock = self.stop_signal_sender.lock();
+        *stop_signal_sender_lock = Some(stop_signal_sender);
+        drop(stop_signal_sender_lock);
+
+        let mut task_join_handle_lock = self.task_join_handle.lock();
+        *task_join_handle_lock = Some(task_join_handle);
+        drop(task_join_handle_lock);
+
+        log::info!(target: "transcoder", "Transcoder started.");
+    }
+
+    async fn on_shutdown(&self, _rocket: &Rocket<Orbit>) {
+        log::info!(target: "transcoder", "Shutting down transcoder.");
+
+        let task_join_handle = {
+            let mut stop_signal_sender_lock = self.stop_signal_sender.lock();
+            let stop_signal_sender = stop_signal_sender_lock.take();
+            drop(stop_signal_sender_lock);
+
+            if let Some(stop_signal_sender) = stop_signal_sender {
+                stop_signal_sender.send(()).ok();
+            }
+
+            let mut task_join_handle_lock = self.task_join_handle.lock();
+            let task_join_handle = task_join_handle_lock.take();
+            drop(task_join_handle_lock);
+
+            task_join_handle
+        };
+
+        if let Some(task_join_handle) = task_join_handle {
+            task_join_handle.await.ok();
+        }
+
+        log::info!(target: "transcoder", "Transcoder shut down.");
+    }
+}
+
+async fn transcode_task(
+    mut stop_signal_receiver: tokio::sync::oneshot::Receiver<()>,
+    poll_period: Duration,
+    transcode_service: Arc<TranscodeService>,
+) {
+    match transcode_service.requeue_running_jobs().await {
+        Ok(0) => {}
+        Ok(count) => {
+            log::info!(target: "transcoder", count; "Requeued interrupted transcoding jobs.");
+        }
+        Err(err) => {
+            log::warn!(target: "transcoder", err:err; "Failed to requeue interrupted transcoding jobs.");
+        }
+    }
+
+    loop {
+        tokio::select! {
+            _ = tokio::time::sleep(poll_period) => {}
+            _ = &mut stop_signal_receiver => {
+                break;
+            }
+        }
+
+        // an interrupted job is left running and requeued on the next start
+        tokio::select! {
+            _ = run_pending_jobs(&transcode_service) => {}
+            _ = &mut stop_signal_receiver => {
+                break;
+            }
+        }
+    }
+}
+
+async fn run_pending_jobs(transcode_service: &TranscodeService) {
+    loop {
+        match transcode_service.run_next_job().await {
+            Ok(Some(job)) => {
+                log::info!(target: "transcoder", job_id:serde = job.id, file_id:serde = job.file_id, profile = job.profile, status = job.status; "Transcoding job finished.");
+            }
+            Ok(None) => break,
+            Err(err) => {
+                // failing to run a job is not a critical error, it will be retried on the next poll
+                log::warn!(target: "transcoder", err:err; "Failed to run transcoding job.");
+                break;
+            }
+        }
+    }
+}
@@ -1,16 +1,36 @@
-use crate::{db::models::User, dto::Error, services::AuthService};
+use crate::{
+    config::AppConfig,
+    db::models::User,
+    dto::Error,
+    services::{AuditActor, AuthService, UserRole, TUS_VERSION},
+};
 use rocket::{
     http::Status,
     request::{FromRequest, Outcome, Request},
     State,
 };
 use serde::Serialize;
-use std::sync::Arc;
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+};
 
 #[derive(Serialize, Debug, Clone, PartialEq)]
 pub struct AuthUserSession<'a> {
     pub user: User,
     pub token: &'a str,
+    /// The admin who has started the session to act as the user, if it is an impersonation session.
+    pub impersonated_by: Option<i32>,
+}
+
+impl AuthUserSession<'_> {
+    /// Who to record in the audit log for the actions taken through the session.
+    pub fn audit_actor(&self) -> AuditActor {
+        AuditActor {
+            user_id: self.user.id,
+            impersonated_by: self.impersonated_by,
+        }
+    }
 }
 
 fn parse_authorization_header(authorization: &str) -> Option<&str> {
@@ -51,8 +71,11 @@ impl<'r> FromRequest<'r> for AuthUserSession<'r> {
             }
         };
 
-        let user = match auth_service.get_user_from_session(token).await {
-            Ok(Some(user)) => user,
+        let (user, impersonated_by) = match auth_service
+            .get_user_and_impersonator_from_session(token)
+            .await
+        {
+            Ok(Some(session)) => session,
             Ok(None) => return Outcome::Error((Status::Unauthorized, Status::Unauthorized.into())),
             Err(err) => {
                 log::error!(target: "guards::AuthUserSession", guard = "AuthUserSession", service = "AuthService", err:err; "Failed to get user from session.");
@@ -63,7 +86,40 @@ impl<'r> FromRequest<'r> for AuthUserSession<'r> {
             }
         };
 
-        Outcome::Success(AuthUserSession { user, token })
+        Outcome::Success(AuthUserSession {
+            user,
+            token,
+            impersonated_by,
+        })
+    }
+}
+
+/// A session of an admin.
+/// It fails with `401 Unauthorized` like [`AuthUserSession`] without a valid session,
+/// and with `403 Forbidden` if the user is not an admin or the session is an impersonation session.
+pub struct AdminUserSession {
+    pub user: User,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for AdminUserSession {
+    type Error = Error;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let sess = match AuthUserSession::from_request(request).await {
+            Outcome::Success(sess) => sess,
+            Outcome::Error(err) => return Outcome::Error(err),
+            Outcome::Forward(status) => return Outcome::Forward(status),
+        };
+
+        if sess.user.role != UserRole::Admin.name() || sess.impersonated_by.is_some() {
+            return Outcome::Error((
+                Status::Forbidden,
+                Error::new_static(Status::Forbidden, "only admins can access this resource"),
+            ));
+        }
+
+        Outcome::Success(AdminUserSession { user: sess.user })
     }
 }
 
@@ -74,6 +130,99 @@ fn make_bad_request<T>(msg: impl Into<String>) -> Outcome<T, Error> {
     ))
 }
 
+/// The address and the scheme the client has connected with.
+/// Behind a reverse proxy they come from the `X-Forwarded-For` and `X-Forwarded-Proto` headers,
+/// which are honored only if the peer is one of the trusted proxies in the config.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ClientInfo {
+    pub ip: Option<IpAddr>,
+    /// `http` or `https`.
+    pub scheme: &'static str,
+}
+
+impl ClientInfo {
+    fn resolve(request: &Request<'_>, app_config: Option<&AppConfig>) -> Self {
+        let peer_ip = request.remote().map(|remote| remote.ip().to_canonical());
+        let scheme = match app_config {
+            Some(app_config) if app_config.tls.is_some() => "https",
+            _ => "http",
+        };
+
+        let (app_config, peer_ip) = match (app_config, peer_ip) {
+            (Some(app_config), Some(peer_ip)) if app_config.proxy.is_trusted(peer_ip) => {
+                (app_config, peer_ip)
+            }
+            _ => {
+                return Self {
+                    ip: peer_ip,
+                    scheme,
+                }
+            }
+        };
+
+        // each proxy appends the address it has been connected from, so the client is the rightmost untrusted one;
+        // anything to the left of it may have been made up by the client
+        let forwarded_ips = request
+            .headers()
+            .get("X-Forwarded-For")
+            .flat_map(|value| value.split(','))
+            .map(parse_forwarded_ip)
+            .collect::<Vec<_>>();
+        let mut ip = peer_ip;
+
+        for forwarded_ip in forwarded_ips.into_iter().rev() {
+            match forwarded_ip {
+                Some(forwarded_ip) => {
+                    ip = forwarded_ip;
+
+                    if !app_config.proxy.is_trusted(forwarded_ip) {
+                        break;
+                    }
+                }
+                None => break,
+            }
+        }
+
+        // the first proxy, which has been connected by the client, sets the header
+        let scheme = match request
+            .headers()
+            .get_one("X-Forwarded-Proto")
+            .and_then(|value| value.split(',').next())
+            .map(|value| value.trim())
+        {
+            Some(value) if value.eq_ignore_ascii_case("https") => "https",
+            Some(value) if value.eq_ignore_ascii_case("http") => "http",
+            _ => scheme,
+        };
+
+        Self {
+            ip: Some(ip),
+            scheme,
+        }
+    }
+}
+
+/// Parses an address in `X-Forwarded-For`, which some proxies write with a port.
+fn parse_forwarded_ip(value: &str) -> Option<IpAddr> {
+    let value = value.trim();
+
+    value
+        .parse::<IpAddr>()
+        .or_else(|_| value.parse::<SocketAddr>().map(|addr| addr.ip()))
+        .ok()
+        .map(|ip| ip.to_canonical())
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ClientInfo {
+    type Error = Error;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let app_config = request.rocket().state::<AppConfig>();
+        Outcome::Success(Self::resolve(request, app_config))
+    }
+}
+
 #[derive(Serialize, Debug, Clone, PartialEq)]
 pub struct OffsetHeader {
     pub offset: Option<u64>,
@@ -101,6 +250,181 @@ impl<'r> FromRequest<'r> for OffsetHeader {
     }
 }
 
+/// The `X-Finalize` header of a request, asking to promote a staging file once its data has been written.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct FinalizeHeader {
+    pub finalize: bool,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for FinalizeHeader {
+    type Error = Error;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let finalize = match request.headers().get_one("X-Finalize") {
+            Some(finalize) => match finalize.parse::<bool>() {
+                Ok(finalize) => finalize,
+                Err(_) => {
+                    return make_bad_request(format!(
+                        "`X-Finalize` header `{}` is invalid; it should be `true` or `false`.",
+                        finalize
+                    ));
+                }
+            },
+            None => false,
+        };
+
+        Outcome::Success(Self { finalize })
+    }
+}
+
+/// The `Content-Length` header of a request. The request is rejected with `411 Length Required` if it is missing.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ContentLengthHeader {
+    pub content_length: u64,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ContentLengthHeader {
+    type Error = Error;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let content_length = match request.headers().get_one("Content-Length") {
+            Some(content_length) => content_length,
+            None => {
+                return Outcome::Error((
+                    Status::LengthRequired,
+                    Error::new_static(
+                        Status::LengthRequired,
+                        "`Content-Length` header is required",
+                    ),
+                ));
+            }
+        };
+
+        match content_length.parse::<u64>() {
+            Ok(content_length) => Outcome::Success(Self { content_length }),
+            Err(_) => make_bad_request(format!(
+                "content length `{}` in header is invalid; it should be non-negative integer.",
+                content_length
+            )),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct UserAgentHeader<'a> {
+    pub user_agent: Option<&'a str>,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for UserAgentHeader<'r> {
+    type Error = Error;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let user_agent = request.headers().get_one("User-Agent");
+
+        Outcome::Success(Self { user_agent })
+    }
+}
+
+/// The `If-Match` header of a request, carrying the version of a resource the client has last seen.
+/// It accepts a quoted or bare version number, optionally marked weak; `*` is treated as no precondition.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct IfMatchHeader {
+    pub version: Option<i32>,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for IfMatchHeader {
+    type Error = Error;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let if_match = match request.headers().get_one("If-Match") {
+            Some(if_match) => if_match.trim(),
+            None => {
+                return Outcome::Success(Self { version: None });
+            }
+        };
+
+        if if_match == "*" {
+            return Outcome::Success(Self { version: None });
+        }
+
+        let version = if_match.strip_prefix("W/").unwrap_or(if_match);
+        let version = version
+            .strip_prefix('"')
+            .and_then(|version| version.strip_suffix('"'))
+            .unwrap_or(version);
+
+        match version.parse::<i32>() {
+            Ok(version) => Outcome::Success(Self {
+                version: Some(version),
+            }),
+            Err(_) => make_bad_request(format!(
+                "version `{}` in if-match header is invalid; it should be integer.",
+                if_match
+            )),
+        }
+    }
+}
+
+/// The headers of a tus protocol request.
+/// The request is rejected unless its `Tus-Resumable` header names the supported protocol version.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct TusHeaders<'a> {
+    pub upload_length: Option<u64>,
+    pub upload_offset: Option<u64>,
+    pub upload_metadata: Option<&'a str>,
+    pub upload_checksum: Option<&'a str>,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for TusHeaders<'r> {
+    type Error = Error;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let headers = request.headers();
+
+        if headers.get_one("Tus-Resumable") != Some(TUS_VERSION) {
+            return Outcome::Error((
+                Status::PreconditionFailed,
+                Error::new_dynamic(
+                    Status::PreconditionFailed,
+                    format!("`Tus-Resumable` header should be `{}`.", TUS_VERSION),
+                ),
+            ));
+        }
+
+        let parse_length = |name: &str| match headers.get_one(name) {
+            Some(value) => match value.parse::<u64>() {
+                Ok(value) => Ok(Some(value)),
+                Err(_) => Err(format!(
+                    "`{}` header `{}` is invalid; it should be non-negative integer.",
+                    name, value
+                )),
+            },
+            None => Ok(None),
+        };
+
+        let upload_length = match parse_length("Upload-Length") {
+            Ok(upload_length) => upload_length,
+            Err(msg) => return make_bad_request(msg),
+        };
+        let upload_offset = match parse_length("Upload-Offset") {
+            Ok(upload_offset) => upload_offset,
+            Err(msg) => return make_bad_request(msg),
+        };
+
+        Outcome::Success(Self {
+            upload_length,
+            upload_offset,
+            upload_metadata: headers.get_one("Upload-Metadata"),
+            upload_checksum: headers.get_one("Upload-Checksum"),
+        })
+    }
+}
+
 #[derive(Serialize, Debug, Clone, PartialEq)]
 pub struct RangeHeader {
     pub range: Option<(i64, Option<i64>)>,
@@ -191,15 +515,13 @@ impl<'r> FromRequest<'r> for RangeHeader {
                     ));
                 }
             }
-            _ if range.ends_with('-') => {
+            _ if range.ends_with('-') && start < 0 => {
                 // pattern: start-
                 // start must be non-negative integer
-                if start < 0 {
-                    return make_bad_request(format!(
-                        "start `{}` in range header is less than 0.",
-                        start
-                    ));
-                }
+                return make_bad_request(format!(
+                    "start `{}` in range header is less than 0.",
+                    start
+                ));
             }
             _ => {
                 // pattern: start
@@ -1,11 +1,82 @@
+use chrono::{SecondsFormat, Utc};
 use env_logger::Env;
+use log::{
+    kv::{Error as KvError, Key, Value, VisitSource},
+    LevelFilter,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value as JsonValue};
+use std::io::Write;
 
-pub fn setup_logger() {
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Human-readable lines, optionally colored.
+    Pretty,
+    /// One JSON object per line, including the structured key-value pairs.
+    Json,
+}
+
+pub fn setup_logger(format: LogFormat, level: Option<LevelFilter>) {
     let env = Env::new()
         .filter_or("LOG_LEVEL", "info")
         .write_style_or("LOG_STYLE", "auto");
 
-    env_logger::init_from_env(env);
+    let mut builder = env_logger::Builder::from_env(env);
+
+    // let every record through the logger, so that the level can be raised later on by `set_log_level`
+    if level.is_some() {
+        builder.filter_level(LevelFilter::Trace);
+    }
+
+    if format == LogFormat::Json {
+        builder.format(|buf, record| {
+            let mut line = Map::new();
+            line.insert(
+                "timestamp".to_owned(),
+                Utc::now()
+                    .to_rfc3339_opts(SecondsFormat::Micros, true)
+                    .into(),
+            );
+            line.insert("level".to_owned(), record.level().as_str().into());
+            line.insert("target".to_owned(), record.target().into());
+            line.insert("message".to_owned(), record.args().to_string().into());
+
+            let mut visitor = JsonVisitor(Map::new());
+            record.key_values().visit(&mut visitor).ok();
+
+            if !visitor.0.is_empty() {
+                line.insert("kv".to_owned(), JsonValue::Object(visitor.0));
+            }
+
+            serde_json::to_writer(&mut *buf, &line)?;
+            writeln!(buf)
+        });
+    }
+
+    builder.init();
+
+    if let Some(level) = level {
+        set_log_level(level);
+    }
 
     log::info!("Logger initialized.");
 }
+
+/// Changes the maximum level of the logs at runtime.
+/// The default level of `LOG_LEVEL` cannot be raised unless the logger has been set up with a level.
+pub fn set_log_level(level: LevelFilter) {
+    log::set_max_level(level);
+}
+
+/// Collects the key-value pairs of a record into a JSON object.
+struct JsonVisitor(Map<String, JsonValue>);
+
+impl<'kvs> VisitSource<'kvs> for JsonVisitor {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), KvError> {
+        // fall back to the displayed value if the value cannot be represented in JSON
+        let value = serde_json::to_value(&value).unwrap_or_else(|_| value.to_string().into());
+        self.0.insert(key.as_str().to_owned(), value);
+        Ok(())
+    }
+}
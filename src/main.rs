@@ -1,3 +1,7 @@
+// figment::Error, which the config loading returns as is, is larger than the lint allows.
+#![allow(clippy::result_large_err)]
+
+mod commands;
 mod config;
 mod db;
 mod dto;
@@ -6,15 +10,19 @@ mod guards;
 mod logger;
 mod routes;
 mod services;
+mod validation;
 
 #[cfg(test)]
 mod test;
 
-use crate::{config::AppConfig, services::local_file_system::LocalFileSystem};
+use crate::config::{AppConfig, SeedUsers};
 use clap::{Arg, ArgAction, Command, ValueHint};
 use const_format::formatcp;
 use rocket::{catch, catchers, http::Status, Build, Request, Rocket};
-use std::{path::Path, sync::Arc};
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 use thiserror::Error;
 
 fn cli() -> Command {
@@ -38,6 +46,17 @@ fn cli() -> Command {
                 .allow_hyphen_values(true)
                 .num_args(1),
         )
+        .arg(
+            Arg::new("seed-users")
+                .help("Path to a file of users to seed in addition to the ones in the config")
+                .long_help("Path to a file of users to seed in addition to the ones in the config, e.g. a secrets file. The file lists them under `seed_users`, in the same format as the config file.")
+                .long("seed-users")
+                .value_name("PATH")
+                .value_hint(ValueHint::FilePath)
+                .required(false)
+                .allow_hyphen_values(true)
+                .num_args(1),
+        )
         .subcommand(
             Command::new("generate-config")
                 .about("Generate a new config file")
@@ -63,7 +82,146 @@ fn cli() -> Command {
         .subcommand(
             Command::new("test-config")
                 .about("Print the config")
-                .long_about("Print the config from the given file. This is useful for testing the config file.")
+                .long_about("Print the config from the given file. This is useful for testing the config file. Use `config check` to validate it as well.")
+                .arg(
+                    Arg::new("config")
+                        .help("Path to the config file")
+                        .short('c')
+                        .long("config")
+                        .value_name("PATH")
+                        .value_hint(ValueHint::FilePath)
+                        .required(false)
+                        .allow_hyphen_values(true)
+                        .num_args(1),
+                ),
+        )
+        .subcommand(
+            Command::new("preview-collection-names")
+                .about("Preview the collections an import would create")
+                .long_about("Render the collection naming template for the given folders and print the collections an import would create or reuse. Nothing is changed.")
+                .arg(
+                    Arg::new("config")
+                        .help("Path to the config file")
+                        .short('c')
+                        .long("config")
+                        .value_name("PATH")
+                        .value_hint(ValueHint::FilePath)
+                        .required(false)
+                        .allow_hyphen_values(true)
+                        .num_args(1),
+                )
+                .arg(
+                    Arg::new("folders")
+                        .help("Folders to import")
+                        .value_name("FOLDER")
+                        .value_hint(ValueHint::DirPath)
+                        .value_parser(clap::value_parser!(PathBuf))
+                        .required(true)
+                        .num_args(1..),
+                ),
+        )
+        .subcommand(
+            Command::new("create-user")
+                .about("Create a new user")
+                .long_about("Create a new user directly in the database, without starting the server. The password is prompted from the terminal.")
+                .arg(
+                    Arg::new("config")
+                        .help("Path to the config file")
+                        .short('c')
+                        .long("config")
+                        .value_name("PATH")
+                        .value_hint(ValueHint::FilePath)
+                        .required(false)
+                        .allow_hyphen_values(true)
+                        .num_args(1),
+                )
+                .arg(
+                    Arg::new("username")
+                        .help("Username of the new user")
+                        .long("username")
+                        .value_name("USERNAME")
+                        .required(true)
+                        .num_args(1),
+                )
+                .arg(
+                    Arg::new("email")
+                        .help("Email of the new user")
+                        .long("email")
+                        .value_name("EMAIL")
+                        .required(true)
+                        .num_args(1),
+                ),
+        )
+        .subcommand(
+            Command::new("import")
+                .about("Import a directory tree")
+                .long_about("Import the files under the given directory. Each sub-directory with files becomes a collection named by the configured template. The progress is kept in a journal, so running the same import again resumes it.")
+                .arg(
+                    Arg::new("config")
+                        .help("Path to the config file")
+                        .short('c')
+                        .long("config")
+                        .value_name("PATH")
+                        .value_hint(ValueHint::FilePath)
+                        .required(false)
+                        .allow_hyphen_values(true)
+                        .num_args(1),
+                )
+                .arg(
+                    Arg::new("journal")
+                        .help("Path to the progress journal [default: import-journal.jsonl]")
+                        .long("journal")
+                        .value_name("PATH")
+                        .value_hint(ValueHint::FilePath)
+                        .value_parser(clap::value_parser!(PathBuf))
+                        .required(false)
+                        .num_args(1),
+                )
+                .arg(
+                    Arg::new("sidecar-tags")
+                        .help("Apply the tags in `<file name>.json` next to each file")
+                        .long("sidecar-tags")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("directory")
+                        .help("Directory to import")
+                        .value_name("DIRECTORY")
+                        .value_hint(ValueHint::DirPath)
+                        .value_parser(clap::value_parser!(PathBuf))
+                        .required(true)
+                        .num_args(1),
+                ),
+        )
+        .subcommand(
+            Command::new("export")
+                .about("Export a backup of the instance")
+                .long_about("Export all the metadata and file contents into a directory, which can be restored into an empty instance with `import-backup`. Passwords are not exported.")
+                .arg(
+                    Arg::new("config")
+                        .help("Path to the config file")
+                        .short('c')
+                        .long("config")
+                        .value_name("PATH")
+                        .value_hint(ValueHint::FilePath)
+                        .required(false)
+                        .allow_hyphen_values(true)
+                        .num_args(1),
+                )
+                .arg(
+                    Arg::new("directory")
+                        .help("Directory to export into; it must be empty or not exist")
+                        .value_name("DIRECTORY")
+                        .value_hint(ValueHint::DirPath)
+                        .value_parser(clap::value_parser!(PathBuf))
+                        .required(true)
+                        .num_args(1),
+                ),
+        )
+        .subcommand(
+            Command::new("import-backup")
+                .about("Restore a backup into an empty instance")
+                .long_about("Restore a backup made by `export` into an empty instance, keeping all the IDs. Restored users get random passwords, since passwords are not exported.")
                 .arg(
                     Arg::new("config")
                         .help("Path to the config file")
@@ -74,6 +232,160 @@ fn cli() -> Command {
                         .required(false)
                         .allow_hyphen_values(true)
                         .num_args(1),
+                )
+                .arg(
+                    Arg::new("directory")
+                        .help("Directory of the backup")
+                        .value_name("DIRECTORY")
+                        .value_hint(ValueHint::DirPath)
+                        .value_parser(clap::value_parser!(PathBuf))
+                        .required(true)
+                        .num_args(1),
+                ),
+        )
+        .subcommand(
+            Command::new("gc")
+                .about("Reconcile the stored blobs against the files")
+                .long_about("Report the blobs no file refers to, and the files whose blobs are missing. The orphaned blobs are removed only if `--delete` is given.")
+                .arg(
+                    Arg::new("config")
+                        .help("Path to the config file")
+                        .short('c')
+                        .long("config")
+                        .value_name("PATH")
+                        .value_hint(ValueHint::FilePath)
+                        .required(false)
+                        .allow_hyphen_values(true)
+                        .num_args(1),
+                )
+                .arg(
+                    Arg::new("delete")
+                        .help("Remove the orphaned blobs")
+                        .long("delete")
+                        .action(ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("bench-read")
+                .about("Measure the read throughput of a file")
+                .long_about("Read a file with a buffered reader and with positional reads, and print the throughput of each. The buffer size of positional reads is taken from `file_read.buffer_size`. Nothing is changed.")
+                .arg(
+                    Arg::new("config")
+                        .help("Path to the config file")
+                        .short('c')
+                        .long("config")
+                        .value_name("PATH")
+                        .value_hint(ValueHint::FilePath)
+                        .required(false)
+                        .allow_hyphen_values(true)
+                        .num_args(1),
+                )
+                .arg(
+                    Arg::new("iterations")
+                        .help("Number of times to read the file per reader")
+                        .short('n')
+                        .long("iterations")
+                        .value_name("COUNT")
+                        .value_parser(clap::value_parser!(u32).range(1..))
+                        .default_value("3")
+                        .num_args(1),
+                )
+                .arg(
+                    Arg::new("file")
+                        .help("File to read")
+                        .value_name("FILE")
+                        .value_hint(ValueHint::FilePath)
+                        .value_parser(clap::value_parser!(PathBuf))
+                        .required(true)
+                        .num_args(1),
+                ),
+        )
+        .subcommand(
+            Command::new("migrate-storage")
+                .about("Move the file data to another storage")
+                .long_about("Copy the data of every file from the storage of the config to the storage of the target config, verifying each copy against the recorded hash. Running it again resumes an interrupted migration. The source storage is not modified, and the config is not switched over.")
+                .arg(
+                    Arg::new("config")
+                        .help("Path to the config file")
+                        .short('c')
+                        .long("config")
+                        .value_name("PATH")
+                        .value_hint(ValueHint::FilePath)
+                        .required(false)
+                        .allow_hyphen_values(true)
+                        .num_args(1),
+                )
+                .arg(
+                    Arg::new("target-config")
+                        .help("Path to the config file whose storage the data is moved to")
+                        .short('t')
+                        .long("target-config")
+                        .value_name("PATH")
+                        .value_hint(ValueHint::FilePath)
+                        .required(true)
+                        .allow_hyphen_values(true)
+                        .num_args(1),
+                ),
+        )
+        .subcommand(
+            Command::new("migrate")
+                .about("Manage the database migrations")
+                .long_about("Run, inspect or revert the database migrations without starting the server.")
+                .subcommand_required(true)
+                .arg(
+                    Arg::new("config")
+                        .help("Path to the config file")
+                        .short('c')
+                        .long("config")
+                        .value_name("PATH")
+                        .value_hint(ValueHint::FilePath)
+                        .required(false)
+                        .allow_hyphen_values(true)
+                        .global(true)
+                        .num_args(1),
+                )
+                .subcommand(
+                    Command::new("run")
+                        .about("Run all pending migrations"),
+                )
+                .subcommand(
+                    Command::new("status")
+                        .about("List the migrations and whether they have been applied"),
+                )
+                .subcommand(
+                    Command::new("revert")
+                        .about("Revert the most recently applied migrations")
+                        .arg(
+                            Arg::new("steps")
+                                .help("Number of migrations to revert")
+                                .long("steps")
+                                .value_name("N")
+                                .value_parser(clap::value_parser!(usize))
+                                .default_value("1")
+                                .num_args(1),
+                        ),
+                ),
+        )
+        .subcommand(
+            Command::new("config")
+                .about("Inspect the config")
+                .subcommand_required(true)
+                .arg(
+                    Arg::new("config")
+                        .help("Path to the config file")
+                        .short('c')
+                        .long("config")
+                        .value_name("PATH")
+                        .value_hint(ValueHint::FilePath)
+                        .required(false)
+                        .allow_hyphen_values(true)
+                        .global(true)
+                        .num_args(1),
+                )
+                .subcommand(
+                    Command::new("check")
+                        .about("Validate the config")
+                        .long_about("Validate the config and print where each value comes from: an environment variable, the config file, or the default. The paths have to be writable, the database and the search server reachable, and the periods non-zero. Exits with a non-zero status if any check fails."),
                 ),
         )
 }
@@ -90,6 +402,32 @@ pub enum AppError {
     FigmentError(#[from] figment::Error),
     #[error("{0}")]
     SearchServiceError(#[from] services::SearchServiceError),
+    #[error("{0}")]
+    CollectionNamingServiceError(#[from] services::CollectionNamingServiceError),
+    #[error("{0}")]
+    UserServiceError(#[from] services::UserServiceError),
+    #[error("the passwords do not match")]
+    PasswordMismatch,
+    #[error("the password must not be empty")]
+    EmptyPassword,
+    #[error("{0}")]
+    CollectionServiceError(#[from] services::CollectionServiceError),
+    #[error("`{}` is not a directory", .0.display())]
+    NotADirectory(PathBuf),
+    #[error("invalid import journal: {0}")]
+    InvalidImportJournal(serde_json::Error),
+    #[error("{0}")]
+    BackupServiceError(#[from] services::BackupServiceError),
+    #[error("{0}")]
+    GcServiceError(#[from] services::GcServiceError),
+    #[error("{0}")]
+    MailerServiceError(#[from] services::MailerServiceError),
+    #[error("{0}")]
+    StorageMigrationServiceError(#[from] services::StorageMigrationServiceError),
+    #[error("the source and target storages are the same")]
+    SameStorage,
+    #[error("{0} problem(s) have been found in the config")]
+    ConfigCheckFailed(usize),
 }
 
 #[rocket::main]
@@ -106,9 +444,81 @@ async fn main() {
             let config_path = sub_matches.get_one::<String>("config");
             test_config(config_path)
         }
+        Some(("preview-collection-names", sub_matches)) => {
+            let config_path = sub_matches.get_one::<String>("config");
+            let folders = sub_matches
+                .get_many::<PathBuf>("folders")
+                .unwrap()
+                .cloned()
+                .collect();
+            commands::preview_collection_names::preview_collection_names(config_path, folders).await
+        }
+        Some(("create-user", sub_matches)) => {
+            let config_path = sub_matches.get_one::<String>("config");
+            let username = sub_matches.get_one::<String>("username").unwrap();
+            let email = sub_matches.get_one::<String>("email").unwrap();
+            commands::create_user::create_user(config_path, username, email).await
+        }
+        Some(("import", sub_matches)) => {
+            let config_path = sub_matches.get_one::<String>("config");
+            let directory = sub_matches.get_one::<PathBuf>("directory").unwrap().clone();
+            let journal = sub_matches.get_one::<PathBuf>("journal").cloned();
+            let sidecar_tags = sub_matches.get_flag("sidecar-tags");
+            commands::import::import(config_path, directory, journal, sidecar_tags).await
+        }
+        Some(("export", sub_matches)) => {
+            let config_path = sub_matches.get_one::<String>("config");
+            let directory = sub_matches.get_one::<PathBuf>("directory").unwrap();
+            commands::backup::export(config_path, directory).await
+        }
+        Some(("import-backup", sub_matches)) => {
+            let config_path = sub_matches.get_one::<String>("config");
+            let directory = sub_matches.get_one::<PathBuf>("directory").unwrap();
+            commands::backup::import_backup(config_path, directory).await
+        }
+        Some(("gc", sub_matches)) => {
+            let config_path = sub_matches.get_one::<String>("config");
+            let delete = sub_matches.get_flag("delete");
+            commands::gc::gc(config_path, delete).await
+        }
+        Some(("bench-read", sub_matches)) => {
+            let config_path = sub_matches.get_one::<String>("config");
+            let file = sub_matches.get_one::<PathBuf>("file").unwrap();
+            let iterations = *sub_matches.get_one::<u32>("iterations").unwrap();
+            commands::bench_read::bench_read(config_path, file, iterations).await
+        }
+        Some(("migrate-storage", sub_matches)) => {
+            let config_path = sub_matches.get_one::<String>("config");
+            let target_config_path = sub_matches.get_one::<String>("target-config").unwrap();
+            commands::migrate_storage::migrate_storage(config_path, target_config_path).await
+        }
+        Some(("migrate", sub_matches)) => match sub_matches.subcommand() {
+            Some(("run", sub_matches)) => {
+                let config_path = sub_matches.get_one::<String>("config");
+                commands::migrate::run(config_path)
+            }
+            Some(("status", sub_matches)) => {
+                let config_path = sub_matches.get_one::<String>("config");
+                commands::migrate::status(config_path)
+            }
+            Some(("revert", sub_matches)) => {
+                let config_path = sub_matches.get_one::<String>("config");
+                let steps = *sub_matches.get_one::<usize>("steps").unwrap();
+                commands::migrate::revert(config_path, steps)
+            }
+            _ => unreachable!(),
+        },
+        Some(("config", sub_matches)) => match sub_matches.subcommand() {
+            Some(("check", sub_matches)) => {
+                let config_path = sub_matches.get_one::<String>("config");
+                commands::config::check(config_path).await
+            }
+            _ => unreachable!(),
+        },
         _ => {
             let config_path = cli_matches.get_one::<String>("config");
-            run_server(config_path).await
+            let seed_users_path = cli_matches.get_one::<String>("seed-users");
+            run_server(config_path, seed_users_path).await
         }
     };
 
@@ -131,6 +541,8 @@ async fn main() {
 
         eprintln!("Command failed.");
         eprintln!("{}", err);
+
+        std::process::exit(1);
     }
 }
 
@@ -188,10 +600,50 @@ fn test_config(config_path: Option<impl AsRef<Path> + Clone>) -> Result<(), AppE
     println!("[Loaded Configuration]");
     println!("- address: {}", rocket_config.address);
     println!("- port: {}", rocket_config.port);
+    match &app_config.tls {
+        Some(tls) => {
+            println!("- tls:");
+            println!("    - certs: {}", tls.certs.display());
+            println!("    - key: {}", tls.key.display());
+            println!(
+                "    - client_ca: {}",
+                tls.client_ca
+                    .as_ref()
+                    .map(|client_ca| client_ca.display().to_string())
+                    .unwrap_or_else(|| "(none)".to_owned())
+            );
+            println!("    - client_auth_mandatory: {}", tls.client_auth_mandatory);
+        }
+        None => println!("- tls: (none)"),
+    }
+    println!("- proxy:");
+    println!(
+        "    - trusted_proxies: [{}]",
+        app_config
+            .proxy
+            .trusted_proxies
+            .iter()
+            .map(|cidr| cidr.to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+    println!("- log_format: {:?}", app_config.log_format);
+    println!(
+        "- log_level: {}",
+        app_config
+            .log_level
+            .map(|log_level| log_level.to_string())
+            .unwrap_or_else(|| "(LOG_LEVEL)".to_owned())
+    );
     println!("- file_base_path: {}", app_config.file_base_path.display());
     println!("- temp_base_path: {}", app_config.temp_base_path.display());
     println!("- database_url_base: {}", app_config.database_url_base);
     println!("- database_name: {}", app_config.database_name);
+    println!(
+        "- database_replica_urls: {:?}",
+        app_config.database_replica_urls
+    );
+    println!("- id_version: {:?}", app_config.id_version);
 
     println!("- limits:");
     println!("    - form: {}", rocket_config.limits.get("form").unwrap());
@@ -221,16 +673,323 @@ fn test_config(config_path: Option<impl AsRef<Path> + Clone>) -> Result<(), AppE
         "- expired_staging_file_expiration: {}",
         app_config.expired_staging_file_expiration
     );
+    println!(
+        "- expired_staging_file_removal_batch_size: {}",
+        app_config.expired_staging_file_removal_batch_size
+    );
+    println!(
+        "- expired_staging_file_removal_max_batches: {}",
+        app_config.expired_staging_file_removal_max_batches
+    );
+    println!(
+        "- direct_upload_expiration: {}",
+        app_config.direct_upload_expiration
+    );
+    println!(
+        "- pending_commit_retry_period: {}",
+        app_config.pending_commit_retry_period
+    );
+    println!(
+        "- auto_promote_staging_files: {}",
+        app_config.auto_promote_staging_files
+    );
+    println!(
+        "- unique_collection_names: {}",
+        app_config.unique_collection_names
+    );
+    println!("- problem_details: {}", app_config.problem_details);
+    println!(
+        "- stats_history_recording_period: {}",
+        app_config.stats_history_recording_period
+    );
+    println!(
+        "- retention_policy_period: {}",
+        app_config.retention_policy_period
+    );
+    println!(
+        "- ffprobe_path: {}",
+        app_config
+            .ffprobe_path
+            .as_ref()
+            .map(|path| path.display().to_string())
+            .unwrap_or_else(|| "(none)".to_owned())
+    );
+    println!(
+        "- pdftotext_path: {}",
+        app_config
+            .pdftotext_path
+            .as_ref()
+            .map(|path| path.display().to_string())
+            .unwrap_or_else(|| "(none)".to_owned())
+    );
+    println!("- seed_users:");
+    for seed_user in &app_config.seed_users {
+        println!(
+            "    - {} <{}> ({})",
+            seed_user.username,
+            seed_user.email,
+            seed_user.role.name()
+        );
+    }
+
+    println!("- database_pool:");
+    println!(
+        "    - max_size: {}",
+        app_config
+            .database_pool
+            .max_size
+            .map(|max_size| max_size.to_string())
+            .unwrap_or_else(|| "(default)".to_owned())
+    );
+    println!(
+        "    - wait_timeout: {:?}",
+        app_config.database_pool.wait_timeout
+    );
+    println!(
+        "    - create_timeout: {:?}",
+        app_config.database_pool.create_timeout
+    );
+    println!(
+        "    - recycle_timeout: {:?}",
+        app_config.database_pool.recycle_timeout
+    );
+    println!(
+        "    - statement_timeout: {:?}",
+        app_config.database_pool.statement_timeout
+    );
+    println!(
+        "    - slow_query_threshold: {:?}",
+        app_config.database_pool.slow_query_threshold
+    );
+    println!("- file_cache:");
+    println!("    - capacity: {}", app_config.file_cache.capacity);
+    println!("    - ttl: {}", app_config.file_cache.ttl);
+    println!("- file_versions:");
+    println!(
+        "    - replace_by_name: {}",
+        app_config.file_versions.replace_by_name
+    );
+    println!(
+        "    - max_versions: {}",
+        app_config.file_versions.max_versions
+    );
+    println!("- read_ahead:");
+    println!("    - enabled: {}", app_config.read_ahead.enabled);
+    println!(
+        "    - max_range_size: {}",
+        app_config.read_ahead.max_range_size
+    );
+    println!("    - buffer_size: {}", app_config.read_ahead.buffer_size);
+    println!("    - max_buffers: {}", app_config.read_ahead.max_buffers);
+    println!("    - idle_timeout: {}", app_config.read_ahead.idle_timeout);
+    println!("- transfers:");
+    println!(
+        "    - max_concurrent_uploads: {}",
+        app_config.transfers.max_concurrent_uploads
+    );
+    println!(
+        "    - max_concurrent_downloads: {}",
+        app_config.transfers.max_concurrent_downloads
+    );
+    println!("    - retry_after: {}", app_config.transfers.retry_after);
+    println!(
+        "    - free_space_reserve: {}",
+        app_config.transfers.free_space_reserve
+    );
+    println!(
+        "    - max_upload_rate: {}",
+        app_config.transfers.max_upload_rate
+    );
+    println!(
+        "    - max_download_rate: {}",
+        app_config.transfers.max_download_rate
+    );
+    println!("    - role_rates: {:?}", app_config.transfers.role_rates);
+    println!("- storage: {:?}", app_config.storage);
+    println!("- file_read:");
+    println!("    - positional: {}", app_config.file_read.positional);
+    println!("    - buffer_size: {}", app_config.file_read.buffer_size);
+    println!("- file_data_cache:");
+    println!("    - max_age: {}", app_config.file_data_cache.max_age);
+    println!("    - public: {}", app_config.file_data_cache.public);
+
+    println!("- import:");
+    println!(
+        "    - collection_name_template: {}",
+        app_config.import.collection_name_template
+    );
+    println!(
+        "    - collection_name_collision: {:?}",
+        app_config.import.collection_name_collision
+    );
+
+    println!("- transcode:");
+    println!("    - enabled: {}", app_config.transcode.enabled);
+    println!(
+        "    - ffmpeg_path: {}",
+        app_config.transcode.ffmpeg_path.display()
+    );
+    println!(
+        "    - rendition_base_path: {}",
+        app_config.transcode.rendition_base_path.display()
+    );
+    println!("    - poll_period: {}", app_config.transcode.poll_period);
+    println!("    - max_attempts: {}", app_config.transcode.max_attempts);
+
+    println!("- scanner:");
+    println!("    - enabled: {}", app_config.scanner.enabled);
+    println!("    - clamd_address: {}", app_config.scanner.clamd_address);
+    println!("    - timeout: {}", app_config.scanner.timeout);
+    println!("    - on_infected: {:?}", app_config.scanner.on_infected);
+
+    println!("- perceptual_hash:");
+    println!("    - enabled: {}", app_config.perceptual_hash.enabled);
+    println!(
+        "    - ffmpeg_path: {}",
+        app_config.perceptual_hash.ffmpeg_path.display()
+    );
+    println!(
+        "    - max_distance: {}",
+        app_config.perceptual_hash.max_distance
+    );
+
+    println!("- image_transform:");
+    println!("    - enabled: {}", app_config.image_transform.enabled);
+    println!(
+        "    - ffmpeg_path: {}",
+        app_config.image_transform.ffmpeg_path.display()
+    );
+    println!(
+        "    - cache_base_path: {}",
+        app_config.image_transform.cache_base_path.display()
+    );
+    println!(
+        "    - max_dimension: {}",
+        app_config.image_transform.max_dimension
+    );
+    println!(
+        "    - max_concurrent_transforms: {}",
+        app_config.image_transform.max_concurrent_transforms
+    );
+    println!(
+        "    - max_cache_size: {}",
+        app_config.image_transform.max_cache_size
+    );
+
+    println!("- mime_policy:");
+    println!("    - allow: [{}]", app_config.mime_policy.allow.join(", "));
+    println!("    - deny: [{}]", app_config.mime_policy.deny.join(", "));
+
+    println!("- collection_limits:");
+    println!(
+        "    - max_files: {}",
+        app_config.collection_limits.max_files
+    );
+    println!(
+        "    - max_name_length: {}",
+        app_config.collection_limits.max_name_length
+    );
+    println!(
+        "    - max_description_length: {}",
+        app_config.collection_limits.max_description_length
+    );
+
+    println!("- upload_ticket:");
+    println!("    - expiration: {}", app_config.upload_ticket.expiration);
+    println!("    - max_count: {}", app_config.upload_ticket.max_count);
+
+    println!("- oidc:");
+    println!("    - enabled: {}", app_config.oidc.enabled);
+    println!("    - issuer_url: {}", app_config.oidc.issuer_url);
+    println!("    - client_id: {}", app_config.oidc.client_id);
+    println!("    - redirect_url: {}", app_config.oidc.redirect_url);
+    println!("    - scopes: {}", app_config.oidc.scopes.join(" "));
+    println!(
+        "    - login_expiration: {}",
+        app_config.oidc.login_expiration
+    );
+
+    println!("- mailer:");
+    println!("    - transport: {:?}", app_config.mailer.transport);
+    println!("    - from: {}", app_config.mailer.from);
+    println!("    - smtp_host: {}", app_config.mailer.smtp_host);
+    println!("    - smtp_port: {}", app_config.mailer.smtp_port);
+    println!("    - smtp_tls: {:?}", app_config.mailer.smtp_tls);
+    println!(
+        "    - smtp_username: {}",
+        app_config.mailer.smtp_username.as_deref().unwrap_or("")
+    );
+
+    println!("- password_reset:");
+    println!("    - expiration: {}", app_config.password_reset.expiration);
+    println!(
+        "    - url_template: {}",
+        app_config.password_reset.url_template
+    );
+
+    println!("- email_change:");
+    println!("    - expiration: {}", app_config.email_change.expiration);
+    println!(
+        "    - url_template: {}",
+        app_config.email_change.url_template
+    );
+
+    println!("- impersonation:");
+    println!("    - expiration: {}", app_config.impersonation.expiration);
+
+    println!("- login_throttle:");
+    println!("    - enabled: {}", app_config.login_throttle.enabled);
+    println!(
+        "    - max_failures: {}",
+        app_config.login_throttle.max_failures
+    );
+    println!(
+        "    - ip_max_failures: {}",
+        app_config.login_throttle.ip_max_failures
+    );
+    println!("    - base_delay: {}", app_config.login_throttle.base_delay);
+    println!("    - max_delay: {}", app_config.login_throttle.max_delay);
+    println!(
+        "    - lockout_duration: {}",
+        app_config.login_throttle.lockout_duration
+    );
+    println!(
+        "    - failure_window: {}",
+        app_config.login_throttle.failure_window
+    );
+
+    println!("- search:");
+    println!("    - backend: {:?}", app_config.search.backend);
+    println!("    - typo_tolerance: {}", app_config.search.typo_tolerance);
+    println!(
+        "    - min_word_size_for_one_typo: {}",
+        app_config.search.min_word_size_for_one_typo
+    );
+    println!(
+        "    - min_word_size_for_two_typos: {}",
+        app_config.search.min_word_size_for_two_typos
+    );
+    println!("    - ranking_rules: {:?}", app_config.search.ranking_rules);
+    println!("    - stop_words: {:?}", app_config.search.stop_words);
+    println!("    - synonyms: {:?}", app_config.search.synonyms);
+    println!(
+        "    - filterable_file_attributes: {:?}",
+        app_config.search.filterable_file_attributes
+    );
 
     Ok(())
 }
 
-async fn run_server(config_path: Option<impl AsRef<Path> + Clone>) -> Result<(), AppError> {
-    logger::setup_logger();
+async fn run_server(
+    config_path: Option<impl AsRef<Path> + Clone>,
+    seed_users_path: Option<impl AsRef<Path>>,
+) -> Result<(), AppError> {
+    let mut app_config = AppConfig::load(config_path.clone())?;
+
+    logger::setup_logger(app_config.log_format, app_config.log_level);
 
     log::info!(target: "init", "Launching the server.");
 
-    let app_config = AppConfig::load(config_path.clone())?;
     let rocket = create_rocket_instance(&app_config)?;
 
     if let Some(config_path) = &config_path {
@@ -241,6 +1000,14 @@ async fn run_server(config_path: Option<impl AsRef<Path> + Clone>) -> Result<(),
 
     log::info!(target: "init", app_config:serde; "Configuration has been loaded.");
 
+    // the seed users are joined after the configuration is logged, to keep their passwords out of the logs
+    if let Some(seed_users_path) = &seed_users_path {
+        let seed_users = SeedUsers::load(seed_users_path)?;
+        let count = seed_users.seed_users.len();
+        log::info!(target: "init", count; "Seed users have been loaded.");
+        app_config.seed_users.extend(seed_users.seed_users);
+    }
+
     let rocket = setup_rocket_instance(app_config, rocket).await?;
     let _rocket = rocket.launch().await?;
 
@@ -268,7 +1035,13 @@ pub async fn setup_rocket_instance(
     db::run_migrations(database_url_base, database_name)?;
 
     log::info!(target: "db", database_url_base, database_name; "Creating database connection pool.");
-    let db_pool = db::create_database_connection_pool(database_url_base, database_name);
+    let db_pool_metrics = Arc::new(db::DatabasePoolMetrics::default());
+    let db_pool = db::create_database_connection_pool(
+        database_url_base,
+        database_name,
+        &app_config.database_pool,
+        Some(db_pool_metrics.clone()),
+    );
     let db_pool = match db_pool {
         Ok(db_pool) => db_pool,
         Err(err) => {
@@ -277,14 +1050,36 @@ pub async fn setup_rocket_instance(
         }
     };
 
-    let temp_base_path = &app_config.temp_base_path;
-    let file_base_path = &app_config.file_base_path;
-    let file_driver = LocalFileSystem::new(temp_base_path, file_base_path).await?;
+    let replica_count = app_config.database_replica_urls.len();
+    log::info!(target: "db", replica_count; "Creating read replica connection pools.");
+    let read_pool = db::create_read_pool(
+        db_pool.clone(),
+        &app_config.database_replica_urls,
+        &app_config.database_pool,
+    );
+    let read_pool = match read_pool {
+        Ok(read_pool) => read_pool,
+        Err(err) => {
+            log::error!(target: "db", replica_count, err:err; "Failed to create read replica connection pools.");
+            return Err(err.into());
+        }
+    };
+
+    let file_driver = services::create_file_driver(&app_config).await?;
 
     let rocket = rocket.register("/", catchers![default_catcher]);
-    let rocket = services::register_search_service(rocket, &app_config).await?;
     let rocket =
-        services::register_services(rocket, db_pool, file_base_path, Arc::new(file_driver));
+        services::register_search_service(rocket, &app_config, db_pool.clone(), read_pool.clone())
+            .await?;
+    let rocket = services::register_mailer_service(rocket, &app_config)?;
+    let rocket = services::register_services(
+        rocket,
+        &app_config,
+        db_pool,
+        read_pool,
+        db_pool_metrics,
+        file_driver,
+    );
     let rocket = fairings::register_fairings(rocket, &app_config);
     let rocket = routes::register_routes(rocket);
 
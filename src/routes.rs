@@ -1,18 +1,33 @@
+pub mod admin;
+pub mod auth;
 pub mod collection;
+pub mod email_change;
 pub mod file;
+pub mod graphql;
+pub mod password_reset;
+pub mod share;
 pub mod staging_file;
 pub mod tag;
+pub mod tus;
+pub mod upload;
 pub mod user;
 pub mod user_session;
 
 use rocket::{Build, Rocket};
 
 pub fn register_routes(rocket: Rocket<Build>) -> Rocket<Build> {
+    let rocket = admin::controllers::register_routes(rocket);
+    let rocket = auth::controllers::register_routes(rocket);
     let rocket = collection::controllers::register_routes(rocket);
+    let rocket = email_change::controllers::register_routes(rocket);
     let rocket = file::controllers::register_routes(rocket);
+    let rocket = graphql::controllers::register_routes(rocket);
+    let rocket = password_reset::controllers::register_routes(rocket);
+    let rocket = share::controllers::register_routes(rocket);
     let rocket = staging_file::controllers::register_routes(rocket);
     let rocket = tag::controllers::register_routes(rocket);
+    let rocket = tus::controllers::register_routes(rocket);
+    let rocket = upload::controllers::register_routes(rocket);
     let rocket = user::controllers::register_routes(rocket);
-    let rocket = user_session::controllers::register_routes(rocket);
-    rocket
+    user_session::controllers::register_routes(rocket)
 }
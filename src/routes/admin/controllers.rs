@@ -0,0 +1,510 @@
+use super::dto::{AuditLogList, SettingUserRole, StatsHistory};
+use crate::{
+    config::{AppConfig, AppSearch},
+    db::models::{ImpersonationSession, User},
+    dto::{ApiResponse, Error, ErrorCode, JsonRes},
+    guards::{AdminUserSession, ClientInfo, UserAgentHeader},
+    services::{
+        AuditAction, AuditActor, AuditLogService, AuthService, ConsistencyReport,
+        ConsistencyService, DatabasePoolService, DatabasePoolStats, FileCacheStats, FileService,
+        FileStorageInfo, FreeSpaceService, FreeSpaceStats, GcReport, GcService, LiveConfig,
+        LiveConfigService, ReadAheadService, ReadAheadStats, ReindexReport, ReindexService,
+        SearchService, SearchServiceError, StatsMetric, StatsService, StatsSummary, UserRole,
+        UserService,
+    },
+};
+use chrono::{Days, Duration, Utc};
+use rocket::{get, http::Status, post, put, routes, serde::json::Json, Build, Rocket, State};
+use std::sync::Arc;
+use uuid::Uuid;
+
+pub fn register_routes(rocket: Rocket<Build>) -> Rocket<Build> {
+    rocket.mount(
+        "/admin",
+        routes![
+            check_consistency,
+            repair_consistency,
+            check_gc,
+            sweep_gc,
+            get_read_ahead_stats,
+            get_database_pool_stats,
+            get_file_cache_stats,
+            get_file_storage_info,
+            get_free_space,
+            get_summary,
+            get_stats_history,
+            get_search_settings,
+            update_search_settings,
+            reindex_search,
+            get_audit_logs,
+            set_user_role,
+            impersonate_user,
+            reload_config
+        ],
+    )
+}
+
+#[get("/consistency")]
+async fn check_consistency(
+    #[allow(unused_variables)] sess: AdminUserSession,
+    consistency_service: &State<Arc<ConsistencyService>>,
+) -> JsonRes<ConsistencyReport> {
+    let report = consistency_service.check().await;
+
+    let report = match report {
+        Ok(report) => report,
+        Err(err) => {
+            log::error!(target: "routes::admin::controllers", controller = "check_consistency", service = "ConsistencyService", err:err; "Error returned from service.");
+            return Err(Status::InternalServerError.into());
+        }
+    };
+
+    Ok(ApiResponse::new(Status::Ok, report))
+}
+
+#[post("/consistency/repair")]
+async fn repair_consistency(
+    #[allow(unused_variables)] sess: AdminUserSession,
+    consistency_service: &State<Arc<ConsistencyService>>,
+) -> JsonRes<ConsistencyReport> {
+    let report = consistency_service.repair().await;
+
+    let report = match report {
+        Ok(report) => report,
+        Err(err) => {
+            log::error!(target: "routes::admin::controllers", controller = "repair_consistency", service = "ConsistencyService", err:err; "Error returned from service.");
+            return Err(Status::InternalServerError.into());
+        }
+    };
+
+    if !report.is_healthy() {
+        log::warn!(target: "routes::admin::controllers", controller = "repair_consistency", report:serde; "Inconsistencies have been repaired.");
+    }
+
+    Ok(ApiResponse::new(Status::Ok, report))
+}
+
+#[get("/gc")]
+async fn check_gc(
+    #[allow(unused_variables)] sess: AdminUserSession,
+    gc_service: &State<Arc<GcService>>,
+) -> JsonRes<GcReport> {
+    let report = gc_service.check().await;
+
+    let report = match report {
+        Ok(report) => report,
+        Err(err) => {
+            log::error!(target: "routes::admin::controllers", controller = "check_gc", service = "GcService", err:err; "Error returned from service.");
+            return Err(Status::InternalServerError.into());
+        }
+    };
+
+    Ok(ApiResponse::new(Status::Ok, report))
+}
+
+#[post("/gc/sweep")]
+async fn sweep_gc(
+    #[allow(unused_variables)] sess: AdminUserSession,
+    gc_service: &State<Arc<GcService>>,
+) -> JsonRes<GcReport> {
+    let report = gc_service.sweep().await;
+
+    let report = match report {
+        Ok(report) => report,
+        Err(err) => {
+            log::error!(target: "routes::admin::controllers", controller = "sweep_gc", service = "GcService", err:err; "Error returned from service.");
+            return Err(Status::InternalServerError.into());
+        }
+    };
+
+    if !report.orphaned_blobs.is_empty() {
+        let count = report.orphaned_blobs.len();
+        let size_on_disk = report.orphaned_size_on_disk();
+        log::warn!(target: "routes::admin::controllers", controller = "sweep_gc", count, size_on_disk; "Orphaned blobs have been removed.");
+    }
+
+    Ok(ApiResponse::new(Status::Ok, report))
+}
+
+#[get("/read-ahead")]
+async fn get_read_ahead_stats(
+    #[allow(unused_variables)] sess: AdminUserSession,
+    read_ahead_service: &State<Arc<ReadAheadService>>,
+) -> JsonRes<ReadAheadStats> {
+    Ok(ApiResponse::new(Status::Ok, read_ahead_service.stats()))
+}
+
+#[get("/free-space")]
+async fn get_free_space(
+    #[allow(unused_variables)] sess: AdminUserSession,
+    free_space_service: &State<Arc<FreeSpaceService>>,
+) -> JsonRes<FreeSpaceStats> {
+    let stats = free_space_service.stats().await;
+
+    let stats = match stats {
+        Ok(stats) => stats,
+        Err(err) => {
+            log::error!(target: "routes::admin::controllers", controller = "get_free_space", service = "FreeSpaceService", err:err; "Error returned from service.");
+            return Err(Status::InternalServerError.into());
+        }
+    };
+
+    Ok(ApiResponse::new(Status::Ok, stats))
+}
+
+#[get("/database-pool")]
+async fn get_database_pool_stats(
+    #[allow(unused_variables)] sess: AdminUserSession,
+    database_pool_service: &State<Arc<DatabasePoolService>>,
+) -> JsonRes<DatabasePoolStats> {
+    Ok(ApiResponse::new(Status::Ok, database_pool_service.stats()))
+}
+
+#[get("/file-cache")]
+async fn get_file_cache_stats(
+    #[allow(unused_variables)] sess: AdminUserSession,
+    file_service: &State<Arc<FileService>>,
+) -> JsonRes<FileCacheStats> {
+    Ok(ApiResponse::new(
+        Status::Ok,
+        file_service.file_cache_stats(),
+    ))
+}
+
+#[get("/files/<file_id>/storage")]
+async fn get_file_storage_info(
+    #[allow(unused_variables)] sess: AdminUserSession,
+    file_service: &State<Arc<FileService>>,
+    file_id: Uuid,
+) -> JsonRes<FileStorageInfo> {
+    let info = file_service.get_file_storage_info_by_id(file_id).await;
+
+    let info = match info {
+        Ok(Some(info)) => info,
+        Ok(None) => {
+            return Err(Error::new_not_found(ErrorCode::FileNotFound));
+        }
+        Err(err) => {
+            log::error!(target: "routes::admin::controllers", controller = "get_file_storage_info", service = "FileService", file_id:serde, err:err; "Error returned from service.");
+            return Err(Status::InternalServerError.into());
+        }
+    };
+
+    Ok(ApiResponse::new(Status::Ok, info))
+}
+
+#[get("/summary")]
+async fn get_summary(
+    #[allow(unused_variables)] sess: AdminUserSession,
+    stats_service: &State<Arc<StatsService>>,
+) -> JsonRes<StatsSummary> {
+    let summary = stats_service.get_summary().await;
+
+    let summary = match summary {
+        Ok(summary) => summary,
+        Err(err) => {
+            log::error!(target: "routes::admin::controllers", controller = "get_summary", service = "StatsService", err:err; "Error returned from service.");
+            return Err(Status::InternalServerError.into());
+        }
+    };
+
+    Ok(ApiResponse::new(Status::Ok, summary))
+}
+
+/// Parses a period such as `90d`, `12w`, `6m` or `1y` into the number of days.
+/// A month is 30 days and a year is 365 days.
+fn parse_period_days(period: &str) -> Option<u64> {
+    let unit = match period.chars().last()? {
+        'd' => 1,
+        'w' => 7,
+        'm' => 30,
+        'y' => 365,
+        _ => return None,
+    };
+    let count = period[..period.len() - 1].parse::<u64>().ok()?;
+
+    count.checked_mul(unit)
+}
+
+#[get("/stats/history?<metric>&<period>")]
+async fn get_stats_history(
+    #[allow(unused_variables)] sess: AdminUserSession,
+    stats_service: &State<Arc<StatsService>>,
+    metric: &str,
+    period: Option<&str>,
+) -> JsonRes<StatsHistory> {
+    let metric = StatsMetric::from_name(metric).ok_or_else(|| {
+        Error::new_dynamic(
+            Status::BadRequest,
+            format!(
+                "unknown metric `{}`; expected one of files, bytes, users, tags",
+                metric
+            ),
+        )
+        .with_code(ErrorCode::InvalidParameter)
+    })?;
+
+    let period = period.unwrap_or("30d");
+    let since = parse_period_days(period)
+        .and_then(|days| Utc::now().date_naive().checked_sub_days(Days::new(days)));
+    let since = match since {
+        Some(since) => since,
+        None => {
+            return Err(Error::new_dynamic(
+                Status::BadRequest,
+                format!(
+                    "period `{}` is invalid; it should be a number followed by d, w, m or y",
+                    period
+                ),
+            )
+            .with_code(ErrorCode::InvalidParameter));
+        }
+    };
+
+    let points = stats_service.get_history(metric, since).await;
+
+    let points = match points {
+        Ok(points) => points,
+        Err(err) => {
+            log::error!(target: "routes::admin::controllers", controller = "get_stats_history", service = "StatsService", metric:serde, err:err; "Error returned from service.");
+            return Err(Status::InternalServerError.into());
+        }
+    };
+
+    Ok(ApiResponse::new(
+        Status::Ok,
+        StatsHistory {
+            metric,
+            since,
+            points,
+        },
+    ))
+}
+
+#[get("/search/settings")]
+async fn get_search_settings(
+    #[allow(unused_variables)] sess: AdminUserSession,
+    search_service: &State<Arc<dyn SearchService + Send + Sync>>,
+) -> JsonRes<AppSearch> {
+    Ok(ApiResponse::new(Status::Ok, search_service.settings()))
+}
+
+/// Updates the relevance settings of the search indices.
+/// The settings are not persisted; the ones in the config are applied again on the next restart.
+#[put("/search/settings", data = "<body>")]
+async fn update_search_settings(
+    #[allow(unused_variables)] sess: AdminUserSession,
+    search_service: &State<Arc<dyn SearchService + Send + Sync>>,
+    body: Json<AppSearch>,
+) -> JsonRes<AppSearch> {
+    let result = search_service.update_settings(&body).await;
+
+    match result {
+        Ok(()) => {}
+        Err(SearchServiceError::SettingsRejected(err)) => {
+            return Err(Error::new_dynamic(
+                Status::UnprocessableEntity,
+                err.error_message,
+            ));
+        }
+        Err(err) => {
+            let body = body.into_inner();
+            log::error!(target: "routes::admin::controllers", controller = "update_search_settings", service = "SearchService", body:serde, err:err; "Error returned from service.");
+            return Err(Status::InternalServerError.into());
+        }
+    }
+
+    Ok(ApiResponse::new(Status::Ok, body.into_inner()))
+}
+
+/// Rebuilds the search indices from the database.
+/// It runs until every document has been indexed, so it may take a while on a large library.
+#[post("/search/reindex")]
+async fn reindex_search(
+    #[allow(unused_variables)] sess: AdminUserSession,
+    reindex_service: &State<Arc<ReindexService>>,
+) -> JsonRes<ReindexReport> {
+    let report = reindex_service.reindex().await;
+
+    let report = match report {
+        Ok(report) => report,
+        Err(err) => {
+            log::error!(target: "routes::admin::controllers", controller = "reindex_search", service = "ReindexService", err:err; "Error returned from service.");
+            return Err(Status::InternalServerError.into());
+        }
+    };
+
+    log::warn!(target: "routes::admin::controllers", controller = "reindex_search", report:serde; "Search indices have been rebuilt.");
+
+    Ok(ApiResponse::new(Status::Ok, report))
+}
+
+/// Lists the actions of all users, or of the given user only, from the most recent one.
+#[get("/audit-logs?<user_id>&<last_audit_log_id>&<limit>")]
+async fn get_audit_logs(
+    #[allow(unused_variables)] sess: AdminUserSession,
+    audit_log_service: &State<Arc<AuditLogService>>,
+    user_id: Option<i32>,
+    last_audit_log_id: Option<i64>,
+    limit: Option<u32>,
+) -> JsonRes<AuditLogList> {
+    let limit = limit.unwrap_or(25);
+    let limit = u32::max(1, limit);
+    let limit = u32::min(limit, 100);
+
+    let audit_logs = audit_log_service
+        .get_audit_logs(user_id, last_audit_log_id, limit)
+        .await;
+
+    let page = match audit_logs {
+        Ok(page) => page,
+        Err(err) => {
+            log::error!(target: "routes::admin::controllers", controller = "get_audit_logs", service = "AuditLogService", user_id:serde, last_audit_log_id:serde, limit, err:err; "Error returned from service.");
+            return Err(Status::InternalServerError.into());
+        }
+    };
+
+    Ok(ApiResponse::new(
+        Status::Ok,
+        AuditLogList {
+            audit_logs: page.items,
+            user_id,
+            last_audit_log_id,
+            limit,
+            next_cursor: page.next_cursor,
+        },
+    ))
+}
+
+/// Changes the role of a user.
+/// Admins cannot change their own role, so that the last admin cannot lock everyone out by accident.
+#[put("/users/<user_id>/role", data = "<body>")]
+async fn set_user_role(
+    sess: AdminUserSession,
+    user_service: &State<Arc<UserService>>,
+    user_id: i32,
+    body: Json<SettingUserRole>,
+) -> JsonRes<User> {
+    if user_id == sess.user.id {
+        return Err(Error::new_static(
+            Status::Conflict,
+            "admins cannot change their own role",
+        ));
+    }
+
+    let role = body.role;
+    let user = user_service.set_user_role_by_id(user_id, role).await;
+
+    let user = match user {
+        Ok(Some(user)) => user,
+        Ok(None) => {
+            return Err(Error::new_not_found(ErrorCode::UserNotFound));
+        }
+        Err(err) => {
+            log::error!(target: "routes::admin::controllers", controller = "set_user_role", service = "UserService", user_id:serde, role:serde, err:err; "Error returned from service.");
+            return Err(Status::InternalServerError.into());
+        }
+    };
+
+    log::warn!(target: "routes::admin::controllers", controller = "set_user_role", admin_id = sess.user.id, user_id:serde, role:serde; "Role of a user has been changed.");
+
+    Ok(ApiResponse::new(Status::Ok, user))
+}
+
+/// Starts a short-lived session acting as a user, to reproduce issues specific to them.
+/// The actions taken through the session are recorded in the audit log as taken by the admin on behalf of the user.
+/// Admins cannot be impersonated, and the session cannot access the admin routes.
+#[allow(clippy::too_many_arguments)]
+#[post("/impersonate/<user_id>")]
+async fn impersonate_user(
+    sess: AdminUserSession,
+    app_config: &State<AppConfig>,
+    auth_service: &State<Arc<AuthService>>,
+    user_service: &State<Arc<UserService>>,
+    audit_log_service: &State<Arc<AuditLogService>>,
+    user_agent: UserAgentHeader<'_>,
+    client_info: ClientInfo,
+    user_id: i32,
+) -> JsonRes<ImpersonationSession> {
+    let user = user_service.get_user_by_id(user_id).await;
+
+    let user = match user {
+        Ok(Some(user)) => user,
+        Ok(None) => {
+            return Err(Error::new_not_found(ErrorCode::UserNotFound));
+        }
+        Err(err) => {
+            log::error!(target: "routes::admin::controllers", controller = "impersonate_user", service = "UserService", user_id:serde, err:err; "Error returned from service.");
+            return Err(Status::InternalServerError.into());
+        }
+    };
+
+    if user.role == UserRole::Admin.name() {
+        return Err(
+            Error::new_static(Status::Forbidden, "admins cannot be impersonated")
+                .with_code(ErrorCode::AdminNotImpersonable),
+        );
+    }
+
+    let expiration = Duration::seconds(app_config.impersonation.expiration as i64);
+    let impersonation_session = auth_service
+        .create_impersonation_session(
+            sess.user.id,
+            user_id,
+            expiration,
+            user_agent.user_agent,
+            client_info.ip,
+        )
+        .await;
+
+    let impersonation_session = match impersonation_session {
+        Ok(impersonation_session) => impersonation_session,
+        Err(err) => {
+            log::error!(target: "routes::admin::controllers", controller = "impersonate_user", service = "AuthService", user_id:serde, err:err; "Error returned from service.");
+            return Err(Status::InternalServerError.into());
+        }
+    };
+
+    let actor = AuditActor {
+        user_id,
+        impersonated_by: Some(sess.user.id),
+    };
+    let details = serde_json::json!({ "expires_at": impersonation_session.expires_at });
+
+    if let Err(err) = audit_log_service
+        .record(actor, AuditAction::UserImpersonated, None, None, details)
+        .await
+    {
+        log::warn!(target: "routes::admin::controllers", controller = "impersonate_user", service = "AuditLogService", user_id:serde, err:err; "Failed to record the audit log.");
+    }
+
+    log::warn!(target: "routes::admin::controllers", controller = "impersonate_user", admin_id = sess.user.id, user_id:serde, client_ip:? = client_info.ip; "Impersonation session has been started.");
+
+    Ok(ApiResponse::new(Status::Created, impersonation_session))
+}
+
+/// Loads the config file again, and applies the values that can be changed without a restart:
+/// the file size limit, the staging file expiration and the log level.
+/// The same happens on `SIGHUP`. The current values are kept if the config cannot be loaded.
+#[post("/reload-config")]
+async fn reload_config(
+    #[allow(unused_variables)] sess: AdminUserSession,
+    live_config_service: &State<Arc<LiveConfigService>>,
+) -> JsonRes<LiveConfig> {
+    let config = live_config_service.reload();
+
+    let config = match config {
+        Ok(config) => config,
+        Err(err) => {
+            log::warn!(target: "routes::admin::controllers", controller = "reload_config", service = "LiveConfigService", err:err; "Error returned from service.");
+            return Err(
+                Error::new_dynamic(Status::UnprocessableEntity, err.to_string())
+                    .with_code(ErrorCode::InvalidConfig),
+            );
+        }
+    };
+
+    log::warn!(target: "routes::admin::controllers", controller = "reload_config", config:serde = config.as_ref(); "Config has been reloaded.");
+
+    Ok(ApiResponse::new(Status::Ok, config.as_ref().clone()))
+}
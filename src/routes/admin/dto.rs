@@ -0,0 +1,28 @@
+use crate::{
+    db::models::AuditLog,
+    services::{StatsMetric, StatsPoint, UserRole},
+};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize)]
+pub struct StatsHistory {
+    pub metric: StatsMetric,
+    pub since: NaiveDate,
+    pub points: Vec<StatsPoint>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct AuditLogList {
+    pub audit_logs: Vec<AuditLog>,
+    pub user_id: Option<i32>,
+    pub last_audit_log_id: Option<i64>,
+    pub limit: u32,
+    /// The cursor to fetch the next page with, or `None` if this is the last page.
+    pub next_cursor: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SettingUserRole {
+    pub role: UserRole,
+}
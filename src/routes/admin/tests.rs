@@ -0,0 +1,1108 @@
+use super::dto::{AuditLogList, SettingUserRole, StatsHistory};
+use crate::{
+    config::{AppConfig, AppSearch, SearchBackend},
+    db::models::{File, ImpersonationSession, User, UserSession},
+    routes::file::dto::{FileSearchResult, SearchingFile},
+    services::{
+        AuthService, ConsistencyReport, DatabasePoolStats, FileCacheStats, FileDriver, FileService,
+        FileStorageInfo, FreeSpaceStats, GcReport, LiveConfig, LiveConfigService, ReadAheadStats,
+        ReindexReport, StagingFileService, StatsMetric, StatsService, StatsSummary, UserRole,
+        UserService,
+    },
+    test::{
+        create_test_rocket_instance, create_test_rocket_instance_with_file_driver,
+        create_test_rocket_instance_with_options,
+        helpers::{
+            create_file, create_filled_staging_file, create_initial_admin_user,
+            create_initial_user, create_user,
+        },
+        TestFileDriver,
+    },
+};
+use chrono::Duration;
+use rocket::{
+    http::{Accept, ContentType, Header, Status},
+    local::asynchronous::Client,
+};
+use std::sync::Arc;
+use uuid::Uuid;
+
+#[rocket::async_test]
+async fn test_check_consistency() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    let (_initial_user, initial_user_session) =
+        create_initial_admin_user(auth_service, user_service).await;
+
+    let response = client
+        .get("/admin/consistency")
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let report = response.into_json::<ConsistencyReport>().await.unwrap();
+
+    assert_eq!(status, Status::Ok);
+    assert!(report.is_healthy());
+}
+
+#[rocket::async_test]
+async fn test_repair_consistency() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    let (_initial_user, initial_user_session) =
+        create_initial_admin_user(auth_service, user_service).await;
+
+    let response = client
+        .post("/admin/consistency/repair")
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let report = response.into_json::<ConsistencyReport>().await.unwrap();
+
+    assert_eq!(status, Status::Ok);
+    assert!(report.dangling_collection_file_pairs.is_empty());
+    assert!(report.dangling_tags.is_empty());
+}
+
+#[rocket::async_test]
+async fn test_repair_consistency_blobs() {
+    let (rocket, _database_dropper, _index_dropper) =
+        create_test_rocket_instance_with_file_driver(TestFileDriver::Memory).await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let staging_file_service = client.rocket().state::<Arc<StagingFileService>>().unwrap();
+    let file_service = client.rocket().state::<Arc<FileService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+    let file_driver = client
+        .rocket()
+        .state::<Arc<dyn FileDriver + Send + Sync>>()
+        .unwrap();
+
+    let (_initial_user, initial_user_session) =
+        create_initial_admin_user(auth_service, user_service).await;
+
+    let kept_file = create_file(
+        &client,
+        staging_file_service,
+        file_service,
+        &initial_user_session,
+        "kept",
+        Some("text/plain"),
+        "kept content",
+    )
+    .await;
+    let missing_file = create_file(
+        &client,
+        staging_file_service,
+        file_service,
+        &initial_user_session,
+        "missing",
+        Some("text/plain"),
+        "missing content",
+    )
+    .await;
+
+    // the memory driver is owned by this test, so its blobs can be broken freely
+    file_driver.remove(missing_file.id).await.unwrap();
+
+    let orphan_id = Uuid::new_v4();
+    assert!(file_driver.copy(kept_file.id, orphan_id).await.unwrap());
+
+    let response = client
+        .get("/admin/consistency")
+        .header(Accept::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let report = response.into_json::<ConsistencyReport>().await.unwrap();
+
+    assert_eq!(status, Status::Ok);
+    assert!(!report.is_healthy());
+    assert_eq!(
+        report
+            .orphaned_blobs
+            .iter()
+            .map(|blob| blob.id)
+            .collect::<Vec<_>>(),
+        vec![orphan_id]
+    );
+    assert_eq!(report.missing_blob_file_ids, vec![missing_file.id]);
+
+    let response = client
+        .post("/admin/consistency/repair")
+        .header(Accept::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let report = response.into_json::<ConsistencyReport>().await.unwrap();
+
+    assert_eq!(status, Status::Ok);
+    assert_eq!(
+        report
+            .orphaned_blobs
+            .iter()
+            .map(|blob| blob.id)
+            .collect::<Vec<_>>(),
+        vec![orphan_id]
+    );
+    assert_eq!(report.missing_blob_file_ids, vec![missing_file.id]);
+
+    assert!(!file_driver.exists(orphan_id).await.unwrap());
+    assert!(file_service
+        .get_file_by_id(missing_file.id)
+        .await
+        .unwrap()
+        .is_none());
+    assert!(file_service
+        .get_file_by_id(kept_file.id)
+        .await
+        .unwrap()
+        .is_some());
+
+    let response = client
+        .get("/admin/consistency")
+        .header(Accept::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let report = response.into_json::<ConsistencyReport>().await.unwrap();
+
+    assert_eq!(status, Status::Ok);
+    assert!(report.is_healthy());
+}
+
+#[rocket::async_test]
+async fn test_check_gc() {
+    let (rocket, _database_dropper, _index_dropper) =
+        create_test_rocket_instance_with_file_driver(TestFileDriver::Local).await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let app_config = client.rocket().state::<AppConfig>().unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let staging_file_service = client.rocket().state::<Arc<StagingFileService>>().unwrap();
+    let file_service = client.rocket().state::<Arc<FileService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    let (_initial_user, initial_user_session) =
+        create_initial_admin_user(auth_service, user_service).await;
+
+    let file = create_file(
+        &client,
+        staging_file_service,
+        file_service,
+        &initial_user_session,
+        "file",
+        Some("text/plain"),
+        "file content",
+    )
+    .await;
+
+    // the storage is shared between tests, so the orphan is only checked, never swept here
+    let orphan_id = Uuid::new_v4();
+    let orphan_path = app_config.file_base_path.join(orphan_id.to_string());
+    tokio::fs::write(&orphan_path, "orphan").await.unwrap();
+
+    let response = client
+        .get("/admin/gc")
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    tokio::fs::remove_file(&orphan_path).await.unwrap();
+
+    let status = response.status();
+    let report = response.into_json::<GcReport>().await.unwrap();
+
+    assert_eq!(status, Status::Ok);
+    assert!(report
+        .orphaned_blobs
+        .iter()
+        .any(|blob| blob.id == orphan_id));
+    assert!(report.orphaned_blobs.iter().all(|blob| blob.id != file.id));
+    assert!(!report.missing_blob_file_ids.contains(&file.id));
+}
+
+#[rocket::async_test]
+async fn test_get_read_ahead_stats() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    let (_initial_user, initial_user_session) =
+        create_initial_admin_user(auth_service, user_service).await;
+
+    let response = client
+        .get("/admin/read-ahead")
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let stats = response.into_json::<ReadAheadStats>().await.unwrap();
+
+    assert_eq!(status, Status::Ok);
+    assert_eq!(stats.hits, 0);
+    assert_eq!(stats.active_buffers, 0);
+}
+
+#[rocket::async_test]
+async fn test_get_database_pool_stats() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    let (_initial_user, initial_user_session) =
+        create_initial_admin_user(auth_service, user_service).await;
+
+    let response = client
+        .get("/admin/database-pool")
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let stats = response.into_json::<DatabasePoolStats>().await.unwrap();
+
+    assert_eq!(status, Status::Ok);
+    // creating the initial user and authenticating the request have used the pool
+    assert!(0 < stats.created);
+    assert!(stats.size <= stats.max_size);
+}
+
+#[rocket::async_test]
+async fn test_get_file_cache_stats() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let staging_file_service = client.rocket().state::<Arc<StagingFileService>>().unwrap();
+    let file_service = client.rocket().state::<Arc<FileService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    let (_initial_user, initial_user_session) =
+        create_initial_admin_user(auth_service, user_service).await;
+
+    let file = create_file(
+        &client,
+        staging_file_service,
+        file_service,
+        &initial_user_session,
+        "file",
+        Some("text/plain"),
+        "file content",
+    )
+    .await;
+
+    for _ in 0..2 {
+        let response = client
+            .get(format!("/files/{}/data", file.id))
+            .header(Header::new(
+                "Authorization",
+                format!("Bearer {}", initial_user_session.token),
+            ))
+            .dispatch()
+            .await;
+
+        assert_eq!(response.status(), Status::Ok);
+    }
+
+    let response = client
+        .get("/admin/file-cache")
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let stats = response.into_json::<FileCacheStats>().await.unwrap();
+
+    assert_eq!(status, Status::Ok);
+    assert!(stats.enabled);
+    // the second read is served from the cache
+    assert!(1 <= stats.hits);
+
+    // removed files are dropped from the cache
+    file_service.remove_file_by_id(file.id).await.unwrap();
+
+    assert_eq!(file_service.get_file_by_id(file.id).await.unwrap(), None);
+}
+
+#[rocket::async_test]
+async fn test_get_file_storage_info() {
+    let (rocket, _database_dropper, _index_dropper) =
+        create_test_rocket_instance_with_file_driver(TestFileDriver::Local).await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let staging_file_service = client.rocket().state::<Arc<StagingFileService>>().unwrap();
+    let file_service = client.rocket().state::<Arc<FileService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    let (_initial_user, initial_user_session) =
+        create_initial_admin_user(auth_service, user_service).await;
+
+    let file = create_file(
+        &client,
+        staging_file_service,
+        file_service,
+        &initial_user_session,
+        "file",
+        Some("text/plain"),
+        "file content",
+    )
+    .await;
+
+    let response = client
+        .get(format!("/admin/files/{}/storage", file.id))
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let info = response.into_json::<FileStorageInfo>().await.unwrap();
+
+    assert_eq!(status, Status::Ok);
+    assert_eq!(info.file_id, file.id);
+    assert_eq!(info.size, file.size);
+    assert_eq!(info.storage.driver, "local");
+    assert!(info.storage.location.ends_with(&file.id.to_string()));
+    assert!(info.storage.size_on_disk.is_some());
+    assert!(info.verified_at.is_some());
+}
+
+#[rocket::async_test]
+async fn test_get_free_space() {
+    let (rocket, _database_dropper, _index_dropper) =
+        create_test_rocket_instance_with_file_driver(TestFileDriver::Local).await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+    let app_config = client.rocket().state::<AppConfig>().unwrap();
+
+    let (_initial_user, initial_user_session) =
+        create_initial_admin_user(auth_service, user_service).await;
+
+    let response = client
+        .get("/admin/free-space")
+        .header(Accept::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let stats = response.into_json::<FreeSpaceStats>().await.unwrap();
+
+    assert_eq!(status, Status::Ok);
+    assert!(stats.staging.is_some());
+    assert!(stats.resident.is_some());
+    assert_eq!(
+        stats.reserve,
+        app_config.transfers.free_space_reserve.as_u64()
+    );
+}
+
+#[rocket::async_test]
+async fn test_get_stats_history() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let staging_file_service = client.rocket().state::<Arc<StagingFileService>>().unwrap();
+    let file_service = client.rocket().state::<Arc<FileService>>().unwrap();
+    let stats_service = client.rocket().state::<Arc<StatsService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    let (_initial_user, initial_user_session) =
+        create_initial_admin_user(auth_service, user_service).await;
+
+    let file = create_file(
+        &client,
+        staging_file_service,
+        file_service,
+        &initial_user_session,
+        "file",
+        Some("text/plain"),
+        "file content",
+    )
+    .await;
+
+    let snapshot = stats_service.record_snapshot().await.unwrap();
+
+    assert_eq!(snapshot.file_count, 1);
+    assert_eq!(snapshot.byte_count, file.size);
+    assert_eq!(snapshot.user_count, 1);
+
+    let response = client
+        .get("/admin/stats/history?metric=bytes&period=90d")
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let history = response.into_json::<StatsHistory>().await.unwrap();
+
+    assert_eq!(status, Status::Ok);
+    assert_eq!(history.metric, StatsMetric::Bytes);
+    assert_eq!(history.points.len(), 1);
+    assert_eq!(history.points[0].date, snapshot.date);
+    assert_eq!(history.points[0].value, file.size);
+
+    let response = client
+        .get("/admin/stats/history?metric=bytes&period=90")
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::BadRequest);
+}
+
+#[rocket::async_test]
+async fn test_update_search_settings() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    let (_initial_user, initial_user_session) =
+        create_initial_admin_user(auth_service, user_service).await;
+
+    let settings = AppSearch {
+        typo_tolerance: false,
+        stop_words: vec!["the".to_owned()],
+        synonyms: [("movie".to_owned(), vec!["film".to_owned()])]
+            .into_iter()
+            .collect(),
+        ..Default::default()
+    };
+
+    let response = client
+        .put("/admin/search/settings")
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .body(serde_json::to_string(&settings).unwrap())
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::Ok);
+
+    let response = client
+        .get("/admin/search/settings")
+        .header(Accept::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let current_settings = response.into_json::<AppSearch>().await.unwrap();
+
+    assert_eq!(status, Status::Ok);
+    assert_eq!(current_settings, settings);
+
+    let response = client
+        .put("/admin/search/settings")
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .body(
+            serde_json::to_string(&AppSearch {
+                ranking_rules: vec!["unknown".to_owned()],
+                ..Default::default()
+            })
+            .unwrap(),
+        )
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+}
+
+#[rocket::async_test]
+async fn test_reload_config() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+    let live_config_service = client.rocket().state::<Arc<LiveConfigService>>().unwrap();
+    let app_config = client.rocket().state::<AppConfig>().unwrap();
+
+    let (_initial_user, initial_user_session) =
+        create_initial_admin_user(auth_service, user_service).await;
+
+    let response = client
+        .post("/admin/reload-config")
+        .header(Accept::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let config = response.into_json::<LiveConfig>().await.unwrap();
+
+    assert_eq!(status, Status::Ok);
+    assert_eq!(config.file_limit, app_config.limits.file);
+    assert_eq!(&config, live_config_service.get().as_ref());
+}
+
+#[rocket::async_test]
+async fn test_admin_user_session() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    let (initial_user, initial_user_session) =
+        create_initial_user(auth_service, user_service).await;
+
+    let response = client
+        .get("/admin/consistency")
+        .header(Accept::JSON)
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::Unauthorized);
+
+    let response = client
+        .get("/admin/consistency")
+        .header(Accept::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::Forbidden);
+
+    user_service
+        .set_user_role_by_id(initial_user.id, UserRole::Admin)
+        .await
+        .unwrap();
+
+    let response = client
+        .get("/admin/consistency")
+        .header(Accept::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::Ok);
+}
+
+#[rocket::async_test]
+async fn test_get_summary() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let staging_file_service = client.rocket().state::<Arc<StagingFileService>>().unwrap();
+    let file_service = client.rocket().state::<Arc<FileService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    let (_initial_user, initial_user_session) =
+        create_initial_admin_user(auth_service, user_service).await;
+
+    let file = create_file(
+        &client,
+        staging_file_service,
+        file_service,
+        &initial_user_session,
+        "file",
+        Some("text/plain"),
+        "file content",
+    )
+    .await;
+
+    let response = client
+        .get("/admin/summary")
+        .header(Accept::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let summary = response.into_json::<StatsSummary>().await.unwrap();
+
+    assert_eq!(status, Status::Ok);
+    assert_eq!(summary.file_count, 1);
+    assert_eq!(summary.byte_count, file.size);
+    assert_eq!(summary.user_count, 1);
+    assert_eq!(summary.collection_count, 0);
+    assert_eq!(summary.staging_file_count, 0);
+}
+
+#[rocket::async_test]
+async fn test_reindex_search() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let staging_file_service = client.rocket().state::<Arc<StagingFileService>>().unwrap();
+    let file_service = client.rocket().state::<Arc<FileService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    let (_initial_user, initial_user_session) =
+        create_initial_admin_user(auth_service, user_service).await;
+
+    for name in ["first", "second"] {
+        create_file(
+            &client,
+            staging_file_service,
+            file_service,
+            &initial_user_session,
+            name,
+            Some("text/plain"),
+            "file content",
+        )
+        .await;
+    }
+
+    let response = client
+        .post("/admin/search/reindex")
+        .header(Accept::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let report = response.into_json::<ReindexReport>().await.unwrap();
+
+    assert_eq!(status, Status::Ok);
+    assert_eq!(report.collections, 0);
+    assert_eq!(report.files, 2);
+    assert_eq!(report.collection_files, 0);
+    assert_eq!(report.failures, 0);
+}
+
+#[rocket::async_test]
+async fn test_reindex_search_keeps_content() {
+    let (rocket, _database_dropper, _index_dropper) =
+        create_test_rocket_instance_with_options(TestFileDriver::Memory, SearchBackend::Postgres)
+            .await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let staging_file_service = client.rocket().state::<Arc<StagingFileService>>().unwrap();
+    let file_service = client.rocket().state::<Arc<FileService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    let (_initial_user, initial_user_session) =
+        create_initial_admin_user(auth_service, user_service).await;
+
+    let file = create_file(
+        &client,
+        staging_file_service,
+        file_service,
+        &initial_user_session,
+        "note",
+        Some("text/plain"),
+        "quarterly revenue figures",
+    )
+    .await;
+
+    let response = client
+        .post("/admin/search/reindex")
+        .header(Accept::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::Ok);
+
+    // the text of the file is still searchable once it has been indexed again
+    let response = client
+        .post("/files/search")
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .body(
+            serde_json::to_string(&SearchingFile {
+                query: "revenue",
+                filter_mime: None,
+                filter_size: None,
+                filter_hash: None,
+                filter_uploaded_at: None,
+                filter_metadata: Default::default(),
+                filter_tags: vec![],
+                filter_attributes: Default::default(),
+                cursor: None,
+                limit: None,
+            })
+            .unwrap(),
+        )
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let result = response.into_json::<FileSearchResult>().await.unwrap();
+
+    assert_eq!(status, Status::Ok);
+    assert_eq!(result.files, vec![file]);
+}
+
+#[rocket::async_test]
+async fn test_get_audit_logs() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let staging_file_service = client.rocket().state::<Arc<StagingFileService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    let (initial_user, initial_user_session) =
+        create_initial_admin_user(auth_service, user_service).await;
+
+    let file = upload_file(&client, staging_file_service, &initial_user_session).await;
+
+    let response = client
+        .get(format!("/admin/audit-logs?user_id={}", initial_user.id))
+        .header(Accept::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let audit_log_list = response.into_json::<AuditLogList>().await.unwrap();
+
+    assert_eq!(status, Status::Ok);
+    assert_eq!(audit_log_list.user_id, Some(initial_user.id));
+    assert!(audit_log_list
+        .audit_logs
+        .iter()
+        .any(|audit_log| audit_log.file_id == Some(file.id)));
+    assert!(audit_log_list
+        .audit_logs
+        .iter()
+        .all(|audit_log| audit_log.user_id == Some(initial_user.id)));
+
+    let response = client
+        .get(format!("/admin/audit-logs?user_id={}", initial_user.id + 1))
+        .header(Accept::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let audit_log_list = response.into_json::<AuditLogList>().await.unwrap();
+
+    assert_eq!(status, Status::Ok);
+    assert!(audit_log_list.audit_logs.is_empty());
+}
+
+/// Uploads a file through `POST /files/<staging_file_id>`, so that the upload is recorded in the audit log.
+async fn upload_file(
+    client: &Client,
+    staging_file_service: &StagingFileService,
+    user_session: &UserSession,
+) -> File {
+    let staging_file = create_filled_staging_file(
+        client,
+        staging_file_service,
+        user_session,
+        "file",
+        Some("text/plain"),
+        "file content",
+    )
+    .await;
+
+    let response = client
+        .post(format!("/files/{}", staging_file.id))
+        .header(Accept::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::Created);
+
+    response.into_json::<File>().await.unwrap()
+}
+
+#[rocket::async_test]
+async fn test_set_user_role() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    let (initial_user, initial_user_session) =
+        create_initial_admin_user(auth_service, user_service).await;
+    let other_user = create_user("other", user_service).await;
+
+    let response = client
+        .put(format!("/admin/users/{}/role", other_user.id))
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .body(
+            serde_json::to_string(&SettingUserRole {
+                role: UserRole::Admin,
+            })
+            .unwrap(),
+        )
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let user = response.into_json::<User>().await.unwrap();
+
+    assert_eq!(status, Status::Ok);
+    assert_eq!(user.id, other_user.id);
+    assert_eq!(user.role, UserRole::Admin.name());
+
+    let response = client
+        .put(format!("/admin/users/{}/role", initial_user.id))
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .body(
+            serde_json::to_string(&SettingUserRole {
+                role: UserRole::User,
+            })
+            .unwrap(),
+        )
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::Conflict);
+
+    let response = client
+        .put(format!("/admin/users/{}/role", other_user.id + 1))
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .body(
+            serde_json::to_string(&SettingUserRole {
+                role: UserRole::User,
+            })
+            .unwrap(),
+        )
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+#[rocket::async_test]
+async fn test_impersonate_user() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let staging_file_service = client.rocket().state::<Arc<StagingFileService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    let (initial_user, initial_user_session) =
+        create_initial_admin_user(auth_service, user_service).await;
+    let other_user = create_user("other", user_service).await;
+
+    let response = client
+        .post(format!("/admin/impersonate/{}", other_user.id))
+        .header(Accept::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let impersonation_session = response.into_json::<ImpersonationSession>().await.unwrap();
+
+    assert_eq!(status, Status::Created);
+    assert_eq!(impersonation_session.user_id, other_user.id);
+    assert_eq!(impersonation_session.impersonated_by, initial_user.id);
+    assert!(impersonation_session.created_at < impersonation_session.expires_at);
+
+    let response = client
+        .get("/admin/consistency")
+        .header(Accept::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", impersonation_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::Forbidden);
+
+    let file = upload_file(
+        &client,
+        staging_file_service,
+        &UserSession {
+            user_id: impersonation_session.user_id,
+            token: impersonation_session.token.clone(),
+            created_at: impersonation_session.created_at,
+        },
+    )
+    .await;
+
+    let response = client
+        .get(format!("/admin/audit-logs?user_id={}", other_user.id))
+        .header(Accept::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let audit_log_list = response.into_json::<AuditLogList>().await.unwrap();
+
+    assert_eq!(status, Status::Ok);
+    assert!(audit_log_list
+        .audit_logs
+        .iter()
+        .any(|audit_log| audit_log.action == "user_impersonated"));
+    assert!(audit_log_list
+        .audit_logs
+        .iter()
+        .any(|audit_log| audit_log.file_id == Some(file.id)));
+    assert!(audit_log_list
+        .audit_logs
+        .iter()
+        .all(|audit_log| audit_log.impersonated_by == Some(initial_user.id)));
+
+    let response = client
+        .post(format!("/admin/impersonate/{}", initial_user.id))
+        .header(Accept::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let body = response.into_json::<serde_json::Value>().await.unwrap();
+
+    assert_eq!(status, Status::Forbidden);
+    assert_eq!(body["error_code"], "ADMIN_NOT_IMPERSONABLE");
+
+    let expired_session = auth_service
+        .create_impersonation_session(
+            initial_user.id,
+            other_user.id,
+            Duration::seconds(-1),
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+    let user = auth_service
+        .get_user_from_session(&expired_session.token)
+        .await
+        .unwrap();
+
+    assert_eq!(user, None);
+}
@@ -0,0 +1,4 @@
+pub mod controllers;
+
+#[cfg(test)]
+mod tests;
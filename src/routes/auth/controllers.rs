@@ -0,0 +1,94 @@
+use crate::{
+    db::models::UserSession,
+    dto::{ApiResponse, Error, ErrorCode, JsonRes},
+    guards::{ClientInfo, UserAgentHeader},
+    services::{AuthService, AuthServiceError, OidcError, OidcLoginError},
+};
+use rocket::{get, http::Status, response::Redirect, routes, Build, Rocket, State};
+use std::sync::Arc;
+
+pub fn register_routes(rocket: Rocket<Build>) -> Rocket<Build> {
+    rocket.mount("/auth", routes![start_oidc_login, finish_oidc_login])
+}
+
+#[get("/oidc/login")]
+async fn start_oidc_login(auth_service: &State<Arc<AuthService>>) -> Result<Redirect, Error> {
+    let url = auth_service.start_oidc_login().await;
+
+    let url = match url {
+        Ok(url) => url,
+        Err(OidcLoginError::Disabled) => {
+            return Err(Error::new_not_found(ErrorCode::LoginProviderNotConfigured));
+        }
+        Err(err) => {
+            log::error!(target: "routes::auth::controllers", controller = "start_oidc_login", service = "AuthService", err:err; "Error returned from service.");
+            return Err(Status::BadGateway.into());
+        }
+    };
+
+    Ok(Redirect::to(url))
+}
+
+#[get("/oidc/callback?<code>&<state>&<error>")]
+async fn finish_oidc_login(
+    auth_service: &State<Arc<AuthService>>,
+    user_agent: UserAgentHeader<'_>,
+    client_info: ClientInfo,
+    code: Option<&str>,
+    state: Option<&str>,
+    error: Option<&str>,
+) -> JsonRes<UserSession> {
+    if let Some(error) = error {
+        return Err(Error::new_dynamic(
+            Status::Unauthorized,
+            format!("the identity provider denied the login: {}", error),
+        )
+        .with_code(ErrorCode::LoginDenied));
+    }
+
+    let (code, state) = match (code, state) {
+        (Some(code), Some(state)) => (code, state),
+        _ => {
+            return Err(
+                Error::new_static(Status::BadRequest, "`code` and `state` are required")
+                    .with_code(ErrorCode::InvalidParameter),
+            );
+        }
+    };
+
+    let user_session = auth_service
+        .finish_oidc_login(code, state, user_agent.user_agent, client_info.ip)
+        .await;
+
+    let user_session = match user_session {
+        Ok(user_session) => user_session,
+        Err(OidcLoginError::Disabled) => {
+            return Err(Error::new_not_found(ErrorCode::LoginProviderNotConfigured));
+        }
+        Err(OidcLoginError::Oidc(err @ OidcError::InvalidState)) => {
+            return Err(Error::new_dynamic(Status::BadRequest, err.to_string())
+                .with_code(ErrorCode::InvalidParameter));
+        }
+        Err(OidcLoginError::Oidc(err @ OidcError::UnverifiedEmail)) => {
+            return Err(Error::new_dynamic(Status::Forbidden, err.to_string())
+                .with_code(ErrorCode::UnverifiedEmail));
+        }
+        Err(OidcLoginError::Error(err @ AuthServiceError::UserDisabled)) => {
+            return Err(Error::new_dynamic(Status::Forbidden, err.to_string())
+                .with_code(ErrorCode::UserDisabled));
+        }
+        Err(OidcLoginError::Oidc(err)) => {
+            log::error!(target: "routes::auth::controllers", controller = "finish_oidc_login", service = "AuthService", err:err; "Error returned from service.");
+            return Err(Status::BadGateway.into());
+        }
+        Err(err) => {
+            log::error!(target: "routes::auth::controllers", controller = "finish_oidc_login", service = "AuthService", err:err; "Error returned from service.");
+            return Err(Status::InternalServerError.into());
+        }
+    };
+
+    let user_id = user_session.user_id;
+    log::info!(target: "routes::auth::controllers", controller = "finish_oidc_login", user_id; "User logged in through OIDC.");
+
+    Ok(ApiResponse::new(Status::Created, user_session))
+}
@@ -0,0 +1,102 @@
+use crate::{
+    config::{AppOidc, SearchBackend},
+    db::models::UserSession,
+    services::{StubOidcProvider, UserService},
+    test::{
+        create_test_rocket_instance, create_test_rocket_instance_with_config, helpers::create_user,
+        TestFileDriver,
+    },
+};
+use chrono::Utc;
+use rocket::{
+    http::{Accept, Status},
+    local::asynchronous::Client,
+};
+use serde_json::json;
+use std::sync::Arc;
+use url::Url;
+
+#[rocket::async_test]
+async fn test_oidc_login_disabled() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+    let client = Client::tracked(rocket).await.unwrap();
+
+    let response = client.get("/auth/oidc/login").dispatch().await;
+    assert_eq!(response.status(), Status::NotFound);
+
+    let response = client
+        .get("/auth/oidc/callback?code=code&state=state")
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+#[rocket::async_test]
+async fn test_oidc_login_username_collision() {
+    let provider = StubOidcProvider::start().await;
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance_with_config(
+        TestFileDriver::Memory,
+        SearchBackend::Postgres,
+        |app_config| {
+            app_config.oidc = AppOidc {
+                enabled: true,
+                issuer_url: provider.issuer_url().to_owned(),
+                client_id: "poly-tag".to_owned(),
+                client_secret: "secret".to_owned(),
+                redirect_url: "http://localhost:8000/auth/oidc/callback".to_owned(),
+                ..Default::default()
+            };
+        },
+    )
+    .await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    // the username given by the provider is taken by another user
+    let other_user = create_user("taken", user_service).await;
+
+    let response = client.get("/auth/oidc/login").dispatch().await;
+    assert_eq!(response.status(), Status::SeeOther);
+
+    let location = Url::parse(response.headers().get_one("Location").unwrap()).unwrap();
+    let query_param = |name: &str| {
+        location
+            .query_pairs()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.into_owned())
+            .unwrap()
+    };
+    let state = query_param("state");
+    let nonce = query_param("nonce");
+
+    provider.set_claims(json!({
+        "iss": provider.issuer_url(),
+        "aud": "poly-tag",
+        "exp": Utc::now().timestamp() + 60,
+        "nonce": nonce,
+        "email": "oidc_user@example.com",
+        "email_verified": true,
+        "preferred_username": other_user.username,
+    }));
+
+    let response = client
+        .get(format!("/auth/oidc/callback?code=code&state={}", state))
+        .header(Accept::JSON)
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let user_session = response.into_json::<UserSession>().await.unwrap();
+
+    assert_eq!(status, Status::Created);
+
+    let user = user_service
+        .get_user_by_id(user_session.user_id)
+        .await
+        .unwrap()
+        .unwrap();
+
+    assert_ne!(user.id, other_user.id);
+    assert_eq!(user.username, "oidc_user@example.com");
+    assert_eq!(user.email, "oidc_user@example.com");
+}
@@ -1,16 +1,22 @@
 use super::dto::{
-    AddingCollectionFile, CollectionFileList, CollectionFileSearchResult, CollectionList,
-    CollectionSearchResult, CreatingCollection, SearchingCollection, SearchingCollectionFile,
-    UpdatingCollection,
+    AddingCollectionFile, CollectionFileList, CollectionFileOrder, CollectionFileSearchResult,
+    CollectionList, CollectionSearchResult, CreatingCollection, ListedCollection,
+    MergingCollection, OrderingCollectionFiles, RetentionPreview, SearchingCollection,
+    SearchingCollectionFile, SettingRetentionPolicy, UpdatingCollection,
 };
 use crate::{
-    db::models::{Collection, CollectionFilePair, File},
-    dto::{Error, JsonRes},
-    guards::AuthUserSession,
+    db::models::{Collection, CollectionFilePair, File, RetentionPolicy},
+    dto::{ApiResponse, Error, ErrorCode, FieldError, JsonRes},
+    guards::{AuthUserSession, IfMatchHeader},
+    routes::file::controllers::{list_files, parse_search_cursor},
     services::{
-        AddFileToCollectionError, CollectionFilePairService, CollectionService,
-        RemoveFileFromCollectionError, SearchService,
+        AddFileToCollectionError, AuditAction, AuditActor, AuditLogService,
+        CollectionFilePairService, CollectionMerge, CollectionService, CollectionServiceError,
+        FavoriteService, ListOrder, MergeCollectionsError, RemoveFileFromCollectionError,
+        RetentionService, SearchCursor, SearchService, SetFileOrderInCollectionError,
+        UnitOfWorkService,
     },
+    validation::Validate,
 };
 use rocket::{
     delete, get, http::Status, post, put, routes, serde::json::Json, Build, Rocket, State,
@@ -24,31 +30,98 @@ pub fn register_routes(rocket: Rocket<Build>) -> Rocket<Build> {
         routes![
             create_collection,
             remove_collection,
+            add_favorite_collection,
+            remove_favorite_collection,
             search_collections,
             get_collections,
             get_collection,
             update_collection,
+            merge_collection,
             add_file_to_collection,
             remove_file_from_collection,
+            set_file_order_in_collection,
             search_files_in_collection,
             get_files_in_collection,
             get_file_in_collection,
+            get_retention_policy,
+            set_retention_policy,
+            remove_retention_policy,
+            preview_retention_policy,
         ],
     )
 }
 
-#[post("/", data = "<body>")]
+/// Marks the collections the user has marked as favorites, for list responses.
+pub(crate) async fn list_collections(
+    favorite_service: &FavoriteService,
+    user_id: i32,
+    collections: Vec<Collection>,
+) -> Result<Vec<ListedCollection>, Error> {
+    let collection_ids = collections
+        .iter()
+        .map(|collection| collection.id)
+        .collect::<Vec<_>>();
+    let favorite_collection_ids = favorite_service
+        .get_favorite_collection_ids(user_id, &collection_ids)
+        .await;
+
+    let favorite_collection_ids = match favorite_collection_ids {
+        Ok(favorite_collection_ids) => favorite_collection_ids,
+        Err(err) => {
+            log::error!(target: "routes::collection::controllers", service = "FavoriteService", user_id, err:err; "Error returned from service.");
+            return Err(Status::InternalServerError.into());
+        }
+    };
+
+    Ok(ListedCollection::from_collections(
+        collections,
+        &favorite_collection_ids,
+    ))
+}
+
+/// Creates a collection, and adds the files in `file_ids` to it.
+/// The collection and its files are created together, so nothing is created if any of the files cannot be added.
+#[post("/?<allow_duplicate>", data = "<body>")]
 async fn create_collection(
-    #[allow(unused_variables)] sess: AuthUserSession<'_>,
+    sess: AuthUserSession<'_>,
+    unit_of_work_service: &State<Arc<UnitOfWorkService>>,
     collection_service: &State<Arc<CollectionService>>,
+    collection_file_pair_service: &State<Arc<CollectionFilePairService>>,
+    audit_log_service: &State<Arc<AuditLogService>>,
+    allow_duplicate: Option<bool>,
     body: Json<CreatingCollection<'_>>,
 ) -> JsonRes<Collection> {
+    body.validate()?;
+
+    let mut uow = match unit_of_work_service.begin().await {
+        Ok(uow) => uow,
+        Err(err) => {
+            log::error!(target: "routes::collection::controllers", controller = "create_collection", service = "UnitOfWorkService", err:err; "Error returned from service.");
+            return Err(Status::InternalServerError.into());
+        }
+    };
+
     let collection = collection_service
-        .create_collection(body.name, body.description)
+        .create_collection(
+            body.name,
+            body.description,
+            allow_duplicate.unwrap_or(false),
+            Some(&mut uow),
+        )
         .await;
 
     let collection = match collection {
         Ok(collection) => collection,
+        Err(err @ CollectionServiceError::NameTaken(_)) => {
+            return Err(Error::new_dynamic(Status::Conflict, err.to_string())
+                .with_code(ErrorCode::CollectionNameTaken));
+        }
+        Err(CollectionServiceError::NameTooLong { max_length }) => {
+            return Err(length_exceeded("name", max_length));
+        }
+        Err(CollectionServiceError::DescriptionTooLong { max_length }) => {
+            return Err(length_exceeded("description", max_length));
+        }
         Err(err) => {
             let body = body.into_inner();
             log::error!(target: "routes::collection::controllers", controller = "create_collection", service = "CollectionService", body:serde, err:err; "Error returned from service.");
@@ -56,13 +129,72 @@ async fn create_collection(
         }
     };
 
-    Ok((Status::Created, Json(collection)))
+    for &file_id in &body.file_ids {
+        let pair = collection_file_pair_service
+            .add_file_to_collection(collection.id, file_id, Some(&mut uow))
+            .await;
+
+        match pair {
+            Ok(_) => {}
+            Err(err @ AddFileToCollectionError::AlreadyExists { .. }) => {
+                return Err(Error::new_dynamic(Status::Conflict, err.to_string())
+                    .with_code(ErrorCode::FileAlreadyInCollection));
+            }
+            Err(err @ AddFileToCollectionError::InvalidFile { .. }) => {
+                return Err(
+                    Error::new_dynamic(Status::UnprocessableEntity, err.to_string())
+                        .with_code(ErrorCode::FileNotFound),
+                );
+            }
+            Err(err @ AddFileToCollectionError::TooManyFiles { .. }) => {
+                return Err(
+                    Error::new_dynamic(Status::UnprocessableEntity, err.to_string())
+                        .with_code(ErrorCode::CollectionFull),
+                );
+            }
+            Err(err) => {
+                let collection_id = collection.id;
+                log::error!(target: "routes::collection::controllers", controller = "create_collection", service = "CollectionFilePairService", collection_id:serde, file_id:serde, err:err; "Error returned from service.");
+                return Err(Status::InternalServerError.into());
+            }
+        }
+    }
+
+    if let Err(err) = uow.commit().await {
+        log::error!(target: "routes::collection::controllers", controller = "create_collection", service = "UnitOfWorkService", err:err; "Error returned from service.");
+        return Err(Status::InternalServerError.into());
+    }
+
+    record_collection_action(
+        audit_log_service,
+        sess.audit_actor(),
+        AuditAction::CollectionCreated,
+        collection.id,
+        None,
+        serde_json::json!({ "name": collection.name }),
+    )
+    .await;
+
+    for &file_id in &body.file_ids {
+        record_collection_action(
+            audit_log_service,
+            sess.audit_actor(),
+            AuditAction::CollectionFileAdded,
+            collection.id,
+            Some(file_id),
+            serde_json::json!({}),
+        )
+        .await;
+    }
+
+    Ok(ApiResponse::new(Status::Created, collection))
 }
 
 #[delete("/<collection_id>")]
 async fn remove_collection(
-    #[allow(unused_variables)] sess: AuthUserSession<'_>,
+    sess: AuthUserSession<'_>,
     collection_service: &State<Arc<CollectionService>>,
+    audit_log_service: &State<Arc<AuditLogService>>,
     collection_id: Uuid,
 ) -> JsonRes<Collection> {
     let collection = collection_service
@@ -72,7 +204,7 @@ async fn remove_collection(
     let collection = match collection {
         Ok(Some(collection)) => collection,
         Ok(None) => {
-            return Err(Status::NotFound.into());
+            return Err(Error::new_not_found(ErrorCode::CollectionNotFound));
         }
         Err(err) => {
             log::error!(target: "routes::collection::controllers", controller = "remove_collection", service = "CollectionService", collection_id:serde, err:err; "Error returned from service.");
@@ -80,16 +212,82 @@ async fn remove_collection(
         }
     };
 
-    Ok((Status::Ok, Json(collection)))
+    record_collection_action(
+        audit_log_service,
+        sess.audit_actor(),
+        AuditAction::CollectionRemoved,
+        collection.id,
+        None,
+        serde_json::json!({ "name": collection.name }),
+    )
+    .await;
+
+    Ok(ApiResponse::new(Status::Ok, collection))
+}
+
+#[put("/<collection_id>/favorite")]
+async fn add_favorite_collection(
+    sess: AuthUserSession<'_>,
+    favorite_service: &State<Arc<FavoriteService>>,
+    collection_id: Uuid,
+) -> Result<Status, Error> {
+    let added = favorite_service
+        .add_favorite_collection(sess.user.id, collection_id)
+        .await;
+
+    match added {
+        Ok(true) => {}
+        Ok(false) => {
+            return Err(Error::new_not_found(ErrorCode::CollectionNotFound));
+        }
+        Err(err) => {
+            log::error!(target: "routes::collection::controllers", controller = "add_favorite_collection", service = "FavoriteService", collection_id:serde, err:err; "Error returned from service.");
+            return Err(Status::InternalServerError.into());
+        }
+    }
+
+    Ok(Status::NoContent)
+}
+
+#[delete("/<collection_id>/favorite")]
+async fn remove_favorite_collection(
+    sess: AuthUserSession<'_>,
+    favorite_service: &State<Arc<FavoriteService>>,
+    collection_id: Uuid,
+) -> Result<Status, Error> {
+    let removed = favorite_service
+        .remove_favorite_collection(sess.user.id, collection_id)
+        .await;
+
+    match removed {
+        Ok(true) => {}
+        Ok(false) => {
+            return Err(Error::new_not_found(ErrorCode::CollectionNotFound));
+        }
+        Err(err) => {
+            log::error!(target: "routes::collection::controllers", controller = "remove_favorite_collection", service = "FavoriteService", collection_id:serde, err:err; "Error returned from service.");
+            return Err(Status::InternalServerError.into());
+        }
+    }
+
+    Ok(Status::NoContent)
 }
 
 #[post("/search", data = "<body>")]
 async fn search_collections(
     #[allow(unused_variables)] sess: AuthUserSession<'_>,
-    search_service: &State<Arc<SearchService>>,
+    search_service: &State<Arc<dyn SearchService + Send + Sync>>,
     body: Json<SearchingCollection<'_>>,
 ) -> JsonRes<CollectionSearchResult> {
-    let collections = search_service.search_collections(body.query).await;
+    body.validate()?;
+
+    let cursor = parse_search_cursor(body.cursor)?;
+    let limit = body.limit.unwrap_or(25);
+    let limit = u32::max(1, limit);
+    let limit = u32::min(limit, 100);
+    let collections = search_service
+        .search_collections(body.query, cursor, limit)
+        .await;
 
     let collections = match collections {
         Ok(collections) => collections,
@@ -100,38 +298,66 @@ async fn search_collections(
         }
     };
 
-    Ok((Status::Ok, Json(CollectionSearchResult { collections })))
+    Ok(ApiResponse::new(
+        Status::Ok,
+        CollectionSearchResult {
+            collections: collections.items,
+            next_cursor: collections.next_cursor.map(SearchCursor::encode),
+        },
+    ))
 }
 
-#[get("/?<last_collection_id>&<limit>")]
+#[get("/?<last_collection_id>&<order>&<limit>&<with_total>")]
 async fn get_collections(
-    #[allow(unused_variables)] sess: AuthUserSession<'_>,
+    sess: AuthUserSession<'_>,
     collection_service: &State<Arc<CollectionService>>,
+    favorite_service: &State<Arc<FavoriteService>>,
     last_collection_id: Option<Uuid>,
+    order: Option<&str>,
     limit: Option<u32>,
+    with_total: Option<bool>,
 ) -> JsonRes<CollectionList> {
+    let order = match order {
+        Some(order) => ListOrder::from_name(order).ok_or_else(|| {
+            Error::new_dynamic(
+                Status::BadRequest,
+                format!("unknown order `{}`; expected one of name, id", order),
+            )
+            .with_code(ErrorCode::InvalidParameter)
+        })?,
+        None => ListOrder::Name,
+    };
     let limit = limit.unwrap_or(25);
     let limit = u32::max(1, limit);
     let limit = u32::min(limit, 100);
     let collections = collection_service
-        .get_collections(last_collection_id, limit)
+        .get_collections(
+            last_collection_id,
+            order,
+            limit,
+            with_total.unwrap_or(false),
+        )
         .await;
 
-    let collections = match collections {
-        Ok(collections) => collections,
+    let page = match collections {
+        Ok(page) => page,
         Err(err) => {
             log::error!(target: "routes::collection::controllers", controller = "get_collections", service = "CollectionService", last_collection_id:serde, limit, err:err; "Error returned from service.");
             return Err(Status::InternalServerError.into());
         }
     };
 
-    Ok((
+    let collections = list_collections(favorite_service, sess.user.id, page.items).await?;
+
+    Ok(ApiResponse::new(
         Status::Ok,
-        Json(CollectionList {
+        CollectionList {
             collections,
             last_collection_id,
             limit,
-        }),
+            next_cursor: page.next_cursor,
+            total: page.total,
+        },
     ))
 }
 
@@ -146,7 +372,7 @@ async fn get_collection(
     let collection = match collection {
         Ok(Some(collection)) => collection,
         Ok(None) => {
-            return Err(Status::NotFound.into());
+            return Err(Error::new_not_found(ErrorCode::CollectionNotFound));
         }
         Err(err) => {
             log::error!(target: "routes::collection::controllers", controller = "get_collection", service = "CollectionService", collection_id:serde, err:err; "Error returned from service.");
@@ -154,24 +380,54 @@ async fn get_collection(
         }
     };
 
-    Ok((Status::Ok, Json(collection)))
+    Ok(ApiResponse::new(Status::Ok, collection))
 }
 
-#[put("/<collection_id>", data = "<body>")]
+/// Updates a collection.
+/// If the `If-Match` header carries a version, the update is rejected with `412 Precondition Failed`
+/// when the collection has been updated since that version.
+#[put("/<collection_id>?<allow_duplicate>", data = "<body>")]
 async fn update_collection(
-    #[allow(unused_variables)] sess: AuthUserSession<'_>,
+    sess: AuthUserSession<'_>,
     collection_service: &State<Arc<CollectionService>>,
+    audit_log_service: &State<Arc<AuditLogService>>,
+    if_match: IfMatchHeader,
     collection_id: Uuid,
+    allow_duplicate: Option<bool>,
     body: Json<UpdatingCollection<'_>>,
 ) -> JsonRes<Collection> {
+    body.validate()?;
+
     let collection = collection_service
-        .update_collection_by_id(collection_id, body.name, body.description)
+        .update_collection_by_id(
+            collection_id,
+            body.name,
+            body.description,
+            allow_duplicate.unwrap_or(false),
+            if_match.version,
+        )
         .await;
 
     let collection = match collection {
         Ok(Some(collection)) => collection,
         Ok(None) => {
-            return Err(Status::NotFound.into());
+            return Err(Error::new_not_found(ErrorCode::CollectionNotFound));
+        }
+        Err(err @ CollectionServiceError::NameTaken(_)) => {
+            return Err(Error::new_dynamic(Status::Conflict, err.to_string())
+                .with_code(ErrorCode::CollectionNameTaken));
+        }
+        Err(CollectionServiceError::NameTooLong { max_length }) => {
+            return Err(length_exceeded("name", max_length));
+        }
+        Err(CollectionServiceError::DescriptionTooLong { max_length }) => {
+            return Err(length_exceeded("description", max_length));
+        }
+        Err(err @ CollectionServiceError::VersionMismatch { .. }) => {
+            return Err(
+                Error::new_dynamic(Status::PreconditionFailed, err.to_string())
+                    .with_code(ErrorCode::VersionMismatch),
+            );
         }
         Err(err) => {
             let body = body.into_inner();
@@ -180,34 +436,123 @@ async fn update_collection(
         }
     };
 
-    Ok((Status::Ok, Json(collection)))
+    record_collection_action(
+        audit_log_service,
+        sess.audit_actor(),
+        AuditAction::CollectionUpdated,
+        collection.id,
+        None,
+        serde_json::json!({ "name": collection.name }),
+    )
+    .await;
+
+    Ok(ApiResponse::new(Status::Ok, collection))
+}
+
+/// Moves every file of the source collection into the collection, skipping the files it already has.
+/// The source collection is removed afterwards if `remove_source` is set.
+#[post("/<collection_id>/merge", data = "<body>")]
+async fn merge_collection(
+    sess: AuthUserSession<'_>,
+    collection_file_pair_service: &State<Arc<CollectionFilePairService>>,
+    audit_log_service: &State<Arc<AuditLogService>>,
+    collection_id: Uuid,
+    body: Json<MergingCollection>,
+) -> JsonRes<CollectionMerge> {
+    let merge = collection_file_pair_service
+        .merge_collections(collection_id, body.source_collection_id, body.remove_source)
+        .await;
+
+    let merge = match merge {
+        Ok(merge) => merge,
+        Err(err) => match err {
+            MergeCollectionsError::SameCollection { .. } => {
+                return Err(Error::new_dynamic(
+                    Status::UnprocessableEntity,
+                    err.to_string(),
+                ));
+            }
+            MergeCollectionsError::InvalidCollection { .. } => {
+                return Err(Error::new_dynamic(Status::NotFound, err.to_string())
+                    .with_code(ErrorCode::CollectionNotFound));
+            }
+            MergeCollectionsError::TooManyFiles { .. } => {
+                return Err(
+                    Error::new_dynamic(Status::UnprocessableEntity, err.to_string())
+                        .with_code(ErrorCode::CollectionFull),
+                );
+            }
+            MergeCollectionsError::Error(err) => {
+                let body = body.into_inner();
+                log::error!(target: "routes::collection::controllers", controller = "merge_collection", service = "CollectionFilePairService", collection_id:serde, body:serde, err:err; "Error returned from service.");
+                return Err(Status::InternalServerError.into());
+            }
+        },
+    };
+
+    record_collection_action(
+        audit_log_service,
+        sess.audit_actor(),
+        AuditAction::CollectionMerged,
+        collection_id,
+        None,
+        serde_json::json!({
+            "source_collection_id": body.source_collection_id,
+            "moved_count": merge.moved_count,
+            "skipped_count": merge.skipped_count,
+        }),
+    )
+    .await;
+
+    if merge.source_removed {
+        record_collection_action(
+            audit_log_service,
+            sess.audit_actor(),
+            AuditAction::CollectionRemoved,
+            body.source_collection_id,
+            None,
+            serde_json::json!({}),
+        )
+        .await;
+    }
+
+    Ok(ApiResponse::new(Status::Ok, merge))
 }
 
 #[post("/<collection_id>/files", data = "<body>")]
 async fn add_file_to_collection(
-    #[allow(unused_variables)] sess: AuthUserSession<'_>,
+    sess: AuthUserSession<'_>,
     collection_file_pair_service: &State<Arc<CollectionFilePairService>>,
+    audit_log_service: &State<Arc<AuditLogService>>,
     collection_id: Uuid,
     body: Json<AddingCollectionFile>,
 ) -> JsonRes<CollectionFilePair> {
     let pair = collection_file_pair_service
-        .add_file_to_collection(collection_id, body.file_id)
+        .add_file_to_collection(collection_id, body.file_id, None)
         .await;
 
     let pair = match pair {
         Ok(pair) => pair,
         Err(err) => match err {
             AddFileToCollectionError::AlreadyExists { .. } => {
-                return Err(Error::new_dynamic(Status::Conflict, err.to_string()));
+                return Err(Error::new_dynamic(Status::Conflict, err.to_string())
+                    .with_code(ErrorCode::FileAlreadyInCollection));
             }
             AddFileToCollectionError::InvalidCollection { .. } => {
-                return Err(Error::new_dynamic(Status::NotFound, err.to_string()));
+                return Err(Error::new_dynamic(Status::NotFound, err.to_string())
+                    .with_code(ErrorCode::CollectionNotFound));
             }
             AddFileToCollectionError::InvalidFile { .. } => {
-                return Err(Error::new_dynamic(
-                    Status::UnprocessableEntity,
-                    err.to_string(),
-                ));
+                return Err(
+                    Error::new_dynamic(Status::UnprocessableEntity, err.to_string())
+                        .with_code(ErrorCode::FileNotFound),
+                );
+            }
+            AddFileToCollectionError::TooManyFiles { .. } => {
+                return Err(
+                    Error::new_dynamic(Status::UnprocessableEntity, err.to_string())
+                        .with_code(ErrorCode::CollectionFull),
+                );
             }
             AddFileToCollectionError::Error(err) => {
                 let body = body.into_inner();
@@ -217,13 +562,24 @@ async fn add_file_to_collection(
         },
     };
 
-    Ok((Status::Created, Json(pair)))
+    record_collection_action(
+        audit_log_service,
+        sess.audit_actor(),
+        AuditAction::CollectionFileAdded,
+        pair.collection_id,
+        Some(pair.file_id),
+        serde_json::json!({}),
+    )
+    .await;
+
+    Ok(ApiResponse::new(Status::Created, pair))
 }
 
 #[delete("/<collection_id>/files/<file_id>")]
 async fn remove_file_from_collection(
-    #[allow(unused_variables)] sess: AuthUserSession<'_>,
+    sess: AuthUserSession<'_>,
     collection_file_pair_service: &State<Arc<CollectionFilePairService>>,
+    audit_log_service: &State<Arc<AuditLogService>>,
     collection_id: Uuid,
     file_id: Uuid,
 ) -> JsonRes<Option<CollectionFilePair>> {
@@ -235,10 +591,12 @@ async fn remove_file_from_collection(
         Ok(pair) => pair,
         Err(err) => match err {
             RemoveFileFromCollectionError::InvalidCollection { .. } => {
-                return Err(Error::new_dynamic(Status::NotFound, err.to_string()));
+                return Err(Error::new_dynamic(Status::NotFound, err.to_string())
+                    .with_code(ErrorCode::CollectionNotFound));
             }
             RemoveFileFromCollectionError::InvalidFile { .. } => {
-                return Err(Error::new_dynamic(Status::NotFound, err.to_string()));
+                return Err(Error::new_dynamic(Status::NotFound, err.to_string())
+                    .with_code(ErrorCode::FileNotFound));
             }
             RemoveFileFromCollectionError::Error(err) => {
                 log::error!(target: "routes::collection::controllers", controller = "remove_file_from_collection", service = "CollectionFilePairService", collection_id:serde, file_id:serde, err:err; "Error returned from service.");
@@ -247,16 +605,73 @@ async fn remove_file_from_collection(
         },
     };
 
-    Ok((Status::Ok, Json(pair)))
+    if let Some(pair) = &pair {
+        record_collection_action(
+            audit_log_service,
+            sess.audit_actor(),
+            AuditAction::CollectionFileRemoved,
+            pair.collection_id,
+            Some(pair.file_id),
+            serde_json::json!({}),
+        )
+        .await;
+    }
+
+    Ok(ApiResponse::new(Status::Ok, pair))
+}
+
+#[put("/<collection_id>/files/order", data = "<body>")]
+async fn set_file_order_in_collection(
+    #[allow(unused_variables)] sess: AuthUserSession<'_>,
+    collection_file_pair_service: &State<Arc<CollectionFilePairService>>,
+    collection_id: Uuid,
+    body: Json<OrderingCollectionFiles>,
+) -> JsonRes<CollectionFileOrder> {
+    let ordered_count = collection_file_pair_service
+        .set_file_order_in_collection(collection_id, &body.file_ids)
+        .await;
+
+    let ordered_count = match ordered_count {
+        Ok(ordered_count) => ordered_count,
+        Err(err) => match err {
+            SetFileOrderInCollectionError::InvalidCollection { .. } => {
+                return Err(Error::new_dynamic(Status::NotFound, err.to_string())
+                    .with_code(ErrorCode::CollectionNotFound));
+            }
+            SetFileOrderInCollectionError::InvalidFiles { .. }
+            | SetFileOrderInCollectionError::DuplicateFile { .. } => {
+                return Err(
+                    Error::new_dynamic(Status::UnprocessableEntity, err.to_string())
+                        .with_code(ErrorCode::FileNotInCollection),
+                );
+            }
+            SetFileOrderInCollectionError::Error(err) => {
+                let body = body.into_inner();
+                log::error!(target: "routes::collection::controllers", controller = "set_file_order_in_collection", service = "CollectionFilePairService", collection_id:serde, body:serde, err:err; "Error returned from service.");
+                return Err(Status::InternalServerError.into());
+            }
+        },
+    };
+
+    Ok(ApiResponse::new(
+        Status::Ok,
+        CollectionFileOrder { ordered_count },
+    ))
 }
 
 #[post("/<collection_id>/files/search", data = "<body>")]
 async fn search_files_in_collection(
     #[allow(unused_variables)] sess: AuthUserSession<'_>,
-    search_service: &State<Arc<SearchService>>,
+    search_service: &State<Arc<dyn SearchService + Send + Sync>>,
     collection_id: Uuid,
     body: Json<SearchingCollectionFile<'_>>,
 ) -> JsonRes<CollectionFileSearchResult> {
+    body.validate()?;
+
+    let cursor = parse_search_cursor(body.cursor)?;
+    let limit = body.limit.unwrap_or(25);
+    let limit = u32::max(1, limit);
+    let limit = u32::min(limit, 100);
     let files = search_service
         .search_collection_files(
             collection_id,
@@ -265,6 +680,8 @@ async fn search_files_in_collection(
             body.filter_size,
             body.filter_hash,
             body.filter_uploaded_at,
+            cursor,
+            limit,
         )
         .await;
 
@@ -277,13 +694,20 @@ async fn search_files_in_collection(
         }
     };
 
-    Ok((Status::Ok, Json(CollectionFileSearchResult { files })))
+    Ok(ApiResponse::new(
+        Status::Ok,
+        CollectionFileSearchResult {
+            files: files.items,
+            next_cursor: files.next_cursor.map(SearchCursor::encode),
+        },
+    ))
 }
 
 #[get("/<collection_id>/files?<last_file_id>&<limit>")]
 async fn get_files_in_collection(
-    #[allow(unused_variables)] sess: AuthUserSession<'_>,
+    sess: AuthUserSession<'_>,
     collection_file_pair_service: &State<Arc<CollectionFilePairService>>,
+    favorite_service: &State<Arc<FavoriteService>>,
     collection_id: Uuid,
     last_file_id: Option<Uuid>,
     limit: Option<u32>,
@@ -303,13 +727,15 @@ async fn get_files_in_collection(
         }
     };
 
-    Ok((
+    let files = list_files(favorite_service, sess.user.id, files).await?;
+
+    Ok(ApiResponse::new(
         Status::Ok,
-        Json(CollectionFileList {
+        CollectionFileList {
             files,
             last_file_id,
             limit,
-        }),
+        },
     ))
 }
 
@@ -327,7 +753,7 @@ async fn get_file_in_collection(
     let file = match file {
         Ok(Some(file)) => file,
         Ok(None) => {
-            return Err(Status::NotFound.into());
+            return Err(Error::new_not_found(ErrorCode::FileNotInCollection));
         }
         Err(err) => {
             log::error!(target: "routes::collection::controllers", controller = "get_file_in_collection", service = "CollectionFilePairService", collection_id:serde, file_id:serde, err:err; "Error returned from service.");
@@ -335,5 +761,137 @@ async fn get_file_in_collection(
         }
     };
 
-    Ok((Status::Ok, Json(file)))
+    Ok(ApiResponse::new(Status::Ok, file))
+}
+
+#[get("/<collection_id>/retention")]
+async fn get_retention_policy(
+    #[allow(unused_variables)] sess: AuthUserSession<'_>,
+    retention_service: &State<Arc<RetentionService>>,
+    collection_id: Uuid,
+) -> JsonRes<RetentionPolicy> {
+    let policy = retention_service.get_policy(collection_id).await;
+
+    let policy = match policy {
+        Ok(Some(policy)) => policy,
+        Ok(None) => {
+            return Err(Error::new_not_found(ErrorCode::RetentionPolicyNotFound));
+        }
+        Err(err) => {
+            log::error!(target: "routes::collection::controllers", controller = "get_retention_policy", service = "RetentionService", collection_id:serde, err:err; "Error returned from service.");
+            return Err(Status::InternalServerError.into());
+        }
+    };
+
+    Ok(ApiResponse::new(Status::Ok, policy))
+}
+
+/// Attaches a retention policy to a collection, replacing the one it has.
+/// The policy is applied periodically; see `GET /collections/<collection_id>/retention/preview` for what it would let go of.
+#[put("/<collection_id>/retention", data = "<body>")]
+async fn set_retention_policy(
+    #[allow(unused_variables)] sess: AuthUserSession<'_>,
+    retention_service: &State<Arc<RetentionService>>,
+    collection_id: Uuid,
+    body: Json<SettingRetentionPolicy>,
+) -> JsonRes<RetentionPolicy> {
+    body.validate()?;
+
+    let policy = retention_service
+        .set_policy(collection_id, body.max_age_days, body.keep_latest)
+        .await;
+
+    let policy = match policy {
+        Ok(Some(policy)) => policy,
+        Ok(None) => {
+            return Err(Error::new_not_found(ErrorCode::CollectionNotFound));
+        }
+        Err(err) => {
+            let body = body.into_inner();
+            log::error!(target: "routes::collection::controllers", controller = "set_retention_policy", service = "RetentionService", collection_id:serde, body:serde, err:err; "Error returned from service.");
+            return Err(Status::InternalServerError.into());
+        }
+    };
+
+    Ok(ApiResponse::new(Status::Ok, policy))
+}
+
+#[delete("/<collection_id>/retention")]
+async fn remove_retention_policy(
+    #[allow(unused_variables)] sess: AuthUserSession<'_>,
+    retention_service: &State<Arc<RetentionService>>,
+    collection_id: Uuid,
+) -> JsonRes<RetentionPolicy> {
+    let policy = retention_service.remove_policy(collection_id).await;
+
+    let policy = match policy {
+        Ok(Some(policy)) => policy,
+        Ok(None) => {
+            return Err(Error::new_not_found(ErrorCode::RetentionPolicyNotFound));
+        }
+        Err(err) => {
+            log::error!(target: "routes::collection::controllers", controller = "remove_retention_policy", service = "RetentionService", collection_id:serde, err:err; "Error returned from service.");
+            return Err(Status::InternalServerError.into());
+        }
+    };
+
+    Ok(ApiResponse::new(Status::Ok, policy))
+}
+
+/// Lists the files the retention policy of a collection would let go of if it were applied now.
+#[get("/<collection_id>/retention/preview?<limit>")]
+async fn preview_retention_policy(
+    #[allow(unused_variables)] sess: AuthUserSession<'_>,
+    retention_service: &State<Arc<RetentionService>>,
+    collection_id: Uuid,
+    limit: Option<u32>,
+) -> JsonRes<RetentionPreview> {
+    let limit = limit.unwrap_or(25);
+    let limit = u32::max(1, limit);
+    let limit = u32::min(limit, 100);
+    let files = retention_service.preview_policy(collection_id, limit).await;
+
+    let files = match files {
+        Ok(Some(files)) => files,
+        Ok(None) => {
+            return Err(Error::new_not_found(ErrorCode::RetentionPolicyNotFound));
+        }
+        Err(err) => {
+            log::error!(target: "routes::collection::controllers", controller = "preview_retention_policy", service = "RetentionService", collection_id:serde, limit, err:err; "Error returned from service.");
+            return Err(Status::InternalServerError.into());
+        }
+    };
+
+    Ok(ApiResponse::new(
+        Status::Ok,
+        RetentionPreview { files, limit },
+    ))
+}
+
+/// Records an action on a collection in the audit log.
+/// Failures are only logged, as the action itself has succeeded.
+async fn record_collection_action(
+    audit_log_service: &AuditLogService,
+    actor: AuditActor,
+    action: AuditAction,
+    collection_id: Uuid,
+    file_id: Option<Uuid>,
+    details: serde_json::Value,
+) {
+    let result = audit_log_service
+        .record(actor, action, file_id, Some(collection_id), details)
+        .await;
+
+    if let Err(err) = result {
+        let user_id = actor.user_id;
+        log::warn!(target: "routes::collection::controllers", service = "AuditLogService", user_id, collection_id:serde, err:err; "Failed to record the audit log.");
+    }
+}
+
+/// Responds to a name or description exceeding the configured limits, in the same shape as request validation.
+fn length_exceeded(field: &'static str, max_length: usize) -> Error {
+    Error::new_validation(vec![FieldError {
+        field,
+        message: format!("should be at most {} characters long", max_length),
+    }])
 }
@@ -1,17 +1,56 @@
-use crate::db::models::{Collection, File};
+use crate::{
+    db::models::{Collection, File},
+    routes::file::dto::ListedFile,
+    validation::{
+        FieldErrors, Validate, MAX_BATCH_SIZE, MAX_DESCRIPTION_LENGTH, MAX_NAME_LENGTH,
+        MAX_SEARCH_LIMIT,
+    },
+};
 use chrono::NaiveDateTime;
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 use uuid::Uuid;
 
 #[derive(Serialize, Deserialize)]
 pub struct CreatingCollection<'a> {
     pub name: &'a str,
     pub description: Option<&'a str>,
+    /// The files to add to the collection, in order. The collection is not created if any of them cannot be added.
+    #[serde(default)]
+    pub file_ids: Vec<Uuid>,
+}
+
+impl Validate for CreatingCollection<'_> {
+    fn validate_fields(&self, errors: &mut FieldErrors) {
+        errors.check_text("name", self.name, MAX_NAME_LENGTH);
+
+        if let Some(description) = self.description {
+            errors.check_max_length("description", description, MAX_DESCRIPTION_LENGTH);
+        }
+
+        if MAX_BATCH_SIZE < self.file_ids.len() {
+            errors.add(
+                "file_ids",
+                format!("should have at most {} IDs", MAX_BATCH_SIZE),
+            );
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct SearchingCollection<'a> {
     pub query: &'a str,
+    /// The cursor returned with the previous page, or `None` for the first page.
+    #[serde(default)]
+    pub cursor: Option<&'a str>,
+    #[serde(default)]
+    pub limit: Option<u32>,
+}
+
+impl Validate for SearchingCollection<'_> {
+    fn validate_fields(&self, errors: &mut FieldErrors) {
+        errors.check_limit("limit", self.limit, MAX_SEARCH_LIMIT);
+    }
 }
 
 #[derive(Serialize, Deserialize)]
@@ -20,16 +59,64 @@ pub struct UpdatingCollection<'a> {
     pub description: Option<&'a str>,
 }
 
+impl Validate for UpdatingCollection<'_> {
+    fn validate_fields(&self, errors: &mut FieldErrors) {
+        errors.check_text("name", self.name, MAX_NAME_LENGTH);
+
+        if let Some(description) = self.description {
+            errors.check_max_length("description", description, MAX_DESCRIPTION_LENGTH);
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct CollectionSearchResult {
     pub collections: Vec<Collection>,
+    /// The cursor to fetch the next page with, or `None` if this is the last page.
+    pub next_cursor: Option<String>,
+}
+
+/// A collection in a list, along with whether the requesting user has marked it as a favorite.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ListedCollection {
+    #[serde(flatten)]
+    pub collection: Collection,
+    pub is_favorite: bool,
+}
+
+impl ListedCollection {
+    pub fn from_collections(
+        collections: Vec<Collection>,
+        favorite_collection_ids: &HashSet<Uuid>,
+    ) -> Vec<Self> {
+        collections
+            .into_iter()
+            .map(|collection| Self {
+                is_favorite: favorite_collection_ids.contains(&collection.id),
+                collection,
+            })
+            .collect()
+    }
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct CollectionList {
-    pub collections: Vec<Collection>,
+    pub collections: Vec<ListedCollection>,
     pub last_collection_id: Option<Uuid>,
     pub limit: u32,
+    /// The cursor to fetch the next page with, or `None` if this is the last page.
+    pub next_cursor: Option<Uuid>,
+    /// The number of all items, if `with_total` has been requested.
+    pub total: Option<i64>,
+}
+
+/// A collection to merge into another.
+#[derive(Serialize, Deserialize)]
+pub struct MergingCollection {
+    pub source_collection_id: Uuid,
+    /// Whether to remove the source collection once its files have been moved.
+    #[serde(default)]
+    pub remove_source: bool,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -37,6 +124,16 @@ pub struct AddingCollectionFile {
     pub file_id: Uuid,
 }
 
+#[derive(Serialize, Deserialize)]
+pub struct OrderingCollectionFiles {
+    pub file_ids: Vec<Uuid>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CollectionFileOrder {
+    pub ordered_count: usize,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct SearchingCollectionFile<'a> {
     pub query: &'a str,
@@ -44,16 +141,65 @@ pub struct SearchingCollectionFile<'a> {
     pub filter_size: Option<(u32, u32)>,
     pub filter_hash: Option<u32>,
     pub filter_uploaded_at: Option<(NaiveDateTime, NaiveDateTime)>,
+    /// The cursor returned with the previous page, or `None` for the first page.
+    #[serde(default)]
+    pub cursor: Option<&'a str>,
+    #[serde(default)]
+    pub limit: Option<u32>,
+}
+
+impl Validate for SearchingCollectionFile<'_> {
+    fn validate_fields(&self, errors: &mut FieldErrors) {
+        errors.check_range("filter_size", self.filter_size.as_ref());
+        errors.check_range("filter_uploaded_at", self.filter_uploaded_at.as_ref());
+        errors.check_limit("limit", self.limit, MAX_SEARCH_LIMIT);
+    }
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct CollectionFileSearchResult {
     pub files: Vec<File>,
+    /// The cursor to fetch the next page with, or `None` if this is the last page.
+    pub next_cursor: Option<String>,
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct CollectionFileList {
-    pub files: Vec<File>,
+    pub files: Vec<ListedFile>,
     pub last_file_id: Option<Uuid>,
     pub limit: u32,
 }
+
+/// A retention policy to attach to a collection. At least one of the rules should be set.
+#[derive(Serialize, Deserialize)]
+pub struct SettingRetentionPolicy {
+    /// Files uploaded more than this many days ago are let go of.
+    #[serde(default)]
+    pub max_age_days: Option<i32>,
+    /// Only this many of the most recently uploaded files are kept.
+    #[serde(default)]
+    pub keep_latest: Option<i32>,
+}
+
+impl Validate for SettingRetentionPolicy {
+    fn validate_fields(&self, errors: &mut FieldErrors) {
+        if self.max_age_days.is_none() && self.keep_latest.is_none() {
+            errors.add("max_age_days", "should be set if keep_latest is not set");
+        }
+
+        if matches!(self.max_age_days, Some(max_age_days) if max_age_days < 1) {
+            errors.add("max_age_days", "should be at least 1");
+        }
+
+        if matches!(self.keep_latest, Some(keep_latest) if keep_latest < 0) {
+            errors.add("keep_latest", "should not be negative");
+        }
+    }
+}
+
+/// The files the retention policy of a collection would let go of if it were applied now, from the oldest file.
+#[derive(Serialize, Deserialize)]
+pub struct RetentionPreview {
+    pub files: Vec<File>,
+    pub limit: u32,
+}
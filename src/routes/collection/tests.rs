@@ -1,16 +1,22 @@
 use super::dto::{
-    AddingCollectionFile, CollectionFileList, CollectionList, CreatingCollection,
-    UpdatingCollection,
+    AddingCollectionFile, CollectionFileList, CollectionFileOrder, CollectionList,
+    CollectionSearchResult, CreatingCollection, MergingCollection, OrderingCollectionFiles,
+    RetentionPreview, SearchingCollection, SettingRetentionPolicy, UpdatingCollection,
 };
 use crate::{
-    db::models::{Collection, CollectionFilePair, File},
+    config::SearchBackend,
+    db::models::{Collection, CollectionFilePair, File, RetentionPolicy},
+    fairings::apply_retention_policies,
     services::{
-        AuthService, CollectionFilePairService, CollectionService, FileService, StagingFileService,
-        UserService,
+        AuditLogService, AuthService, CollectionFilePairService, CollectionMerge,
+        CollectionService, CollectionServiceError, FileService, ListOrder, RetentionService,
+        StagingFileService, UserService,
     },
     test::{
-        create_test_rocket_instance,
-        helpers::{create_file, create_initial_user},
+        create_test_rocket_instance, create_test_rocket_instance_with_config,
+        create_test_rocket_instance_with_options,
+        helpers::{create_file, create_initial_user, unlist_collections, unlist_files},
+        TestFileDriver,
     },
 };
 use rocket::{
@@ -41,7 +47,14 @@ async fn test_create_collection() {
             "Authorization",
             format!("Bearer {}", initial_user_session.token),
         ))
-        .body(serde_json::to_string(&CreatingCollection { name, description }).unwrap())
+        .body(
+            serde_json::to_string(&CreatingCollection {
+                name,
+                description,
+                file_ids: Vec::new(),
+            })
+            .unwrap(),
+        )
         .dispatch()
         .await;
 
@@ -50,13 +63,7 @@ async fn test_create_collection() {
 
     assert_eq!(status, Status::Created);
     assert_eq!(created_collection.name, name);
-    assert_eq!(
-        created_collection
-            .description
-            .as_ref()
-            .map(|description| description.as_str()),
-        description
-    );
+    assert_eq!(created_collection.description.as_deref(), description);
 
     let raw_created_collection = collection_service
         .get_collection_by_id(created_collection.id)
@@ -67,6 +74,214 @@ async fn test_create_collection() {
     assert_eq!(raw_created_collection, created_collection);
 }
 
+#[rocket::async_test]
+async fn test_create_collection_duplicate_name() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance_with_config(
+        TestFileDriver::Memory,
+        SearchBackend::Meilisearch,
+        |app_config| app_config.unique_collection_names = true,
+    )
+    .await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let collection_service = client.rocket().state::<Arc<CollectionService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    let (_initial_user, initial_user_session) =
+        create_initial_user(auth_service, user_service).await;
+
+    let collection = collection_service
+        .create_collection("collection", None, false, None)
+        .await
+        .unwrap();
+    let other_collection = collection_service
+        .create_collection("other collection", None, false, None)
+        .await
+        .unwrap();
+
+    let response = client
+        .post("/collections")
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .body(
+            serde_json::to_string(&CreatingCollection {
+                name: &collection.name,
+                description: None,
+                file_ids: Vec::new(),
+            })
+            .unwrap(),
+        )
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::Conflict);
+
+    let response = client
+        .put(format!("/collections/{}", other_collection.id))
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .body(
+            serde_json::to_string(&UpdatingCollection {
+                name: &collection.name,
+                description: None,
+            })
+            .unwrap(),
+        )
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::Conflict);
+
+    // renaming a collection to its own name is not a duplicate
+    let response = client
+        .put(format!("/collections/{}", collection.id))
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .body(
+            serde_json::to_string(&UpdatingCollection {
+                name: &collection.name,
+                description: Some("description"),
+            })
+            .unwrap(),
+        )
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::Ok);
+
+    let response = client
+        .post("/collections?allow_duplicate=true")
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .body(
+            serde_json::to_string(&CreatingCollection {
+                name: &collection.name,
+                description: None,
+                file_ids: Vec::new(),
+            })
+            .unwrap(),
+        )
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let created_collection = response.into_json::<Collection>().await.unwrap();
+
+    assert_eq!(status, Status::Created);
+    assert_eq!(created_collection.name, collection.name);
+    assert_ne!(created_collection.id, collection.id);
+}
+
+#[rocket::async_test]
+async fn test_create_collection_with_files() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let collection_service = client.rocket().state::<Arc<CollectionService>>().unwrap();
+    let collection_file_pair_service = client
+        .rocket()
+        .state::<Arc<CollectionFilePairService>>()
+        .unwrap();
+    let staging_file_service = client.rocket().state::<Arc<StagingFileService>>().unwrap();
+    let file_service = client.rocket().state::<Arc<FileService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    let (_initial_user, initial_user_session) =
+        create_initial_user(auth_service, user_service).await;
+
+    let mut files = Vec::new();
+
+    for index in 0..2 {
+        let file = create_file(
+            &client,
+            staging_file_service,
+            file_service,
+            &initial_user_session,
+            &format!("file{}", index),
+            Some("video/mp4"),
+            "file content",
+        )
+        .await;
+        files.push(file);
+    }
+
+    // a missing file fails the whole creation, leaving no collection behind
+    let response = client
+        .post("/collections")
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .body(
+            serde_json::to_string(&CreatingCollection {
+                name: "collection",
+                description: None,
+                file_ids: vec![files[0].id, uuid::Uuid::new_v4()],
+            })
+            .unwrap(),
+        )
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+
+    let collections = collection_service
+        .get_collections(None, ListOrder::Id, 100, false)
+        .await
+        .unwrap();
+
+    assert!(collections.items.is_empty());
+
+    let response = client
+        .post("/collections")
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .body(
+            serde_json::to_string(&CreatingCollection {
+                name: "collection",
+                description: None,
+                file_ids: files.iter().map(|file| file.id).collect(),
+            })
+            .unwrap(),
+        )
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let created_collection = response.into_json::<Collection>().await.unwrap();
+
+    assert_eq!(status, Status::Created);
+
+    let raw_files = collection_file_pair_service
+        .get_files_in_collection(created_collection.id, None, 100)
+        .await
+        .unwrap();
+
+    assert_eq!(raw_files, files);
+}
+
 #[rocket::async_test]
 async fn test_remove_collection() {
     let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
@@ -79,7 +294,7 @@ async fn test_remove_collection() {
         create_initial_user(auth_service, user_service).await;
 
     let collection = collection_service
-        .create_collection("collection", Some("collection description"))
+        .create_collection("collection", Some("collection description"), false, None)
         .await
         .unwrap();
 
@@ -121,15 +336,15 @@ async fn test_get_collections() {
 
     let collections = vec![
         collection_service
-            .create_collection("collection0", Some("collection0 description"))
+            .create_collection("collection0", Some("collection0 description"), false, None)
             .await
             .unwrap(),
         collection_service
-            .create_collection("collection1", Some("collection1 description"))
+            .create_collection("collection1", Some("collection1 description"), false, None)
             .await
             .unwrap(),
         collection_service
-            .create_collection("collection2", Some("collection2 description"))
+            .create_collection("collection2", Some("collection2 description"), false, None)
             .await
             .unwrap(),
     ];
@@ -151,17 +366,26 @@ async fn test_get_collections() {
     assert_eq!(status, Status::Ok);
     assert_eq!(retrieved_collections.last_collection_id, None);
     assert_eq!(retrieved_collections.limit, collections.len() as u32);
-    assert_eq!(retrieved_collections.collections, collections);
+    assert_eq!(
+        unlist_collections(&retrieved_collections.collections),
+        collections
+    );
 
     let raw_retrieved_collections = collection_service
         .get_collections(
             retrieved_collections.last_collection_id,
+            ListOrder::Name,
             retrieved_collections.limit,
+            false,
         )
         .await
-        .unwrap();
+        .unwrap()
+        .items;
 
-    assert_eq!(raw_retrieved_collections, retrieved_collections.collections);
+    assert_eq!(
+        raw_retrieved_collections,
+        unlist_collections(&retrieved_collections.collections)
+    );
 }
 
 #[rocket::async_test]
@@ -175,29 +399,29 @@ async fn test_get_collections_paginations() {
     let (_initial_user, initial_user_session) =
         create_initial_user(auth_service, user_service).await;
 
-    let collections = vec![
+    let collections = [
         collection_service
-            .create_collection("collection0", Some("collection0 description"))
+            .create_collection("collection0", Some("collection0 description"), false, None)
             .await
             .unwrap(),
         collection_service
-            .create_collection("collection1", Some("collection1 description"))
+            .create_collection("collection1", Some("collection1 description"), false, None)
             .await
             .unwrap(),
         collection_service
-            .create_collection("collection2", Some("collection2 description"))
+            .create_collection("collection2", Some("collection2 description"), false, None)
             .await
             .unwrap(),
         collection_service
-            .create_collection("collection3", Some("collection3 description"))
+            .create_collection("collection3", Some("collection3 description"), false, None)
             .await
             .unwrap(),
         collection_service
-            .create_collection("collection4", Some("collection4 description"))
+            .create_collection("collection4", Some("collection4 description"), false, None)
             .await
             .unwrap(),
         collection_service
-            .create_collection("collection5", Some("collection5 description"))
+            .create_collection("collection5", Some("collection5 description"), false, None)
             .await
             .unwrap(),
     ];
@@ -237,18 +461,123 @@ async fn test_get_collections_paginations() {
             }
         );
         assert_eq!(retrieved_collections.limit, collections.len() as u32);
-        assert_eq!(retrieved_collections.collections, collections[index..]);
+        assert_eq!(
+            unlist_collections(&retrieved_collections.collections),
+            collections[index..]
+        );
 
         let raw_retrieved_collections = collection_service
             .get_collections(
                 retrieved_collections.last_collection_id,
+                ListOrder::Name,
                 retrieved_collections.limit,
+                false,
             )
             .await
+            .unwrap()
+            .items;
+
+        assert_eq!(
+            raw_retrieved_collections,
+            unlist_collections(&retrieved_collections.collections)
+        );
+    }
+}
+
+#[rocket::async_test]
+async fn test_search_collections_paginations() {
+    let (rocket, _database_dropper, _index_dropper) =
+        create_test_rocket_instance_with_options(TestFileDriver::Memory, SearchBackend::Postgres)
+            .await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let collection_service = client.rocket().state::<Arc<CollectionService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    let (_initial_user, initial_user_session) =
+        create_initial_user(auth_service, user_service).await;
+
+    let mut collections = vec![];
+
+    for index in 0..5 {
+        collections.push(
+            collection_service
+                .create_collection(&format!("collection{}", index), None, false, None)
+                .await
+                .unwrap(),
+        );
+    }
+
+    let mut paged_collections = vec![];
+    let mut cursor: Option<String> = None;
+
+    loop {
+        let response = client
+            .post("/collections/search")
+            .header(Accept::JSON)
+            .header(ContentType::JSON)
+            .header(Header::new(
+                "Authorization",
+                format!("Bearer {}", initial_user_session.token),
+            ))
+            .body(
+                serde_json::to_string(&SearchingCollection {
+                    query: "",
+                    cursor: cursor.as_deref(),
+                    limit: Some(2),
+                })
+                .unwrap(),
+            )
+            .dispatch()
+            .await;
+
+        let status = response.status();
+        let result = response
+            .into_json::<CollectionSearchResult>()
+            .await
             .unwrap();
 
-        assert_eq!(raw_retrieved_collections, retrieved_collections.collections);
+        assert_eq!(status, Status::Ok);
+        assert!(result.collections.len() <= 2);
+
+        paged_collections.extend(result.collections);
+        cursor = result.next_cursor;
+
+        if cursor.is_none() {
+            break;
+        }
     }
+
+    // every collection is returned once, across the pages
+    collections.sort_by_key(|collection| collection.id);
+    paged_collections.sort_by_key(|collection| collection.id);
+
+    assert_eq!(paged_collections, collections);
+
+    let response = client
+        .post("/collections/search")
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .body(
+            serde_json::to_string(&SearchingCollection {
+                query: "",
+                cursor: Some("not a cursor"),
+                limit: Some(2),
+            })
+            .unwrap(),
+        )
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let body = response.into_json::<serde_json::Value>().await.unwrap();
+
+    assert_eq!(status, Status::BadRequest);
+    assert_eq!(body["error_code"], "INVALID_CURSOR");
 }
 
 #[rocket::async_test]
@@ -263,7 +592,7 @@ async fn test_get_collection() {
         create_initial_user(auth_service, user_service).await;
 
     let collection = collection_service
-        .create_collection("collection", Some("collection description"))
+        .create_collection("collection", Some("collection description"), false, None)
         .await
         .unwrap();
 
@@ -293,6 +622,42 @@ async fn test_get_collection() {
     assert_eq!(raw_retrieved_collection, retrieved_collection);
 }
 
+#[rocket::async_test]
+async fn test_get_collection_msgpack() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let collection_service = client.rocket().state::<Arc<CollectionService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    let (_initial_user, initial_user_session) =
+        create_initial_user(auth_service, user_service).await;
+
+    let collection = collection_service
+        .create_collection("collection", Some("collection description"), false, None)
+        .await
+        .unwrap();
+
+    let response = client
+        .get(format!("/collections/{}", collection.id))
+        .header(Accept::MsgPack)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let content_type = response.content_type();
+    let body = response.into_bytes().await.unwrap();
+    let retrieved_collection = rocket::serde::msgpack::from_slice::<Collection>(&body).unwrap();
+
+    assert_eq!(status, Status::Ok);
+    assert_eq!(content_type, Some(ContentType::MsgPack));
+    assert_eq!(retrieved_collection, collection);
+}
+
 #[rocket::async_test]
 async fn test_update_collection() {
     let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
@@ -305,7 +670,7 @@ async fn test_update_collection() {
         create_initial_user(auth_service, user_service).await;
 
     let collection = collection_service
-        .create_collection("collection", Some("collection description"))
+        .create_collection("collection", Some("collection description"), false, None)
         .await
         .unwrap();
 
@@ -346,6 +711,105 @@ async fn test_update_collection() {
     assert_eq!(raw_updated_collection, updated_collection);
 }
 
+#[rocket::async_test]
+async fn test_update_collection_stale_version() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let collection_service = client.rocket().state::<Arc<CollectionService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    let (_initial_user, initial_user_session) =
+        create_initial_user(auth_service, user_service).await;
+
+    let collection = collection_service
+        .create_collection("collection", None, false, None)
+        .await
+        .unwrap();
+
+    let response = client
+        .put(format!("/collections/{}", collection.id))
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .header(Header::new(
+            "If-Match",
+            format!("\"{}\"", collection.version),
+        ))
+        .body(
+            serde_json::to_string(&UpdatingCollection {
+                name: "first",
+                description: None,
+            })
+            .unwrap(),
+        )
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let updated_collection = response.into_json::<Collection>().await.unwrap();
+
+    assert_eq!(status, Status::Ok);
+    assert_eq!(updated_collection.version, collection.version + 1);
+
+    // the second writer still holds the old version, so its update must not overwrite the first one
+    let response = client
+        .put(format!("/collections/{}", collection.id))
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .header(Header::new(
+            "If-Match",
+            format!("\"{}\"", collection.version),
+        ))
+        .body(
+            serde_json::to_string(&UpdatingCollection {
+                name: "second",
+                description: None,
+            })
+            .unwrap(),
+        )
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::PreconditionFailed);
+
+    let raw_collection = collection_service
+        .get_collection_by_id(collection.id)
+        .await
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(raw_collection, updated_collection);
+
+    let response = client
+        .put(format!("/collections/{}", collection.id))
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .header(Header::new("If-Match", "not a version"))
+        .body(
+            serde_json::to_string(&UpdatingCollection {
+                name: "second",
+                description: None,
+            })
+            .unwrap(),
+        )
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::BadRequest);
+}
+
 #[rocket::async_test]
 async fn test_add_file_to_collection() {
     let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
@@ -364,50 +828,274 @@ async fn test_add_file_to_collection() {
         create_initial_user(auth_service, user_service).await;
 
     let collection = collection_service
-        .create_collection("collection", Some("collection description"))
+        .create_collection("collection", Some("collection description"), false, None)
+        .await
+        .unwrap();
+
+    let file = create_file(
+        &client,
+        staging_file_service,
+        file_service,
+        &initial_user_session,
+        "file",
+        Some("video/mp4"),
+        "file content",
+    )
+    .await;
+
+    let response = client
+        .post(format!("/collections/{}/files", collection.id))
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .body(serde_json::to_string(&AddingCollectionFile { file_id: file.id }).unwrap())
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let added_collection_file_pair = response.into_json::<CollectionFilePair>().await.unwrap();
+
+    assert_eq!(status, Status::Created);
+    assert_eq!(added_collection_file_pair.collection_id, collection.id);
+    assert_eq!(added_collection_file_pair.file_id, file.id);
+
+    let raw_added_file = collection_file_pair_service
+        .get_file_in_collection_by_id(
+            added_collection_file_pair.collection_id,
+            added_collection_file_pair.file_id,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(raw_added_file, file);
+}
+
+#[rocket::async_test]
+async fn test_collection_limits() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance_with_config(
+        TestFileDriver::Memory,
+        SearchBackend::Meilisearch,
+        |app_config| {
+            app_config.collection_limits.max_files = 1;
+            app_config.collection_limits.max_name_length = 10;
+        },
+    )
+    .await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let collection_service = client.rocket().state::<Arc<CollectionService>>().unwrap();
+    let collection_file_pair_service = client
+        .rocket()
+        .state::<Arc<CollectionFilePairService>>()
+        .unwrap();
+    let staging_file_service = client.rocket().state::<Arc<StagingFileService>>().unwrap();
+    let file_service = client.rocket().state::<Arc<FileService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    let (_initial_user, initial_user_session) =
+        create_initial_user(auth_service, user_service).await;
+
+    let result = collection_service
+        .create_collection("long collection", None, false, None)
+        .await;
+
+    assert!(matches!(
+        result,
+        Err(CollectionServiceError::NameTooLong { max_length: 10 })
+    ));
+
+    let response = client
+        .post("/collections")
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .body(
+            serde_json::to_string(&CreatingCollection {
+                name: "long collection",
+                description: None,
+                file_ids: Vec::new(),
+            })
+            .unwrap(),
+        )
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+
+    let collection = collection_service
+        .create_collection("collection", None, false, None)
         .await
         .unwrap();
 
     let file = create_file(
         &client,
         staging_file_service,
-        &file_service,
+        file_service,
+        &initial_user_session,
+        "file",
+        Some("video/mp4"),
+        "file content",
+    )
+    .await;
+    let other_file = create_file(
+        &client,
+        staging_file_service,
+        file_service,
+        &initial_user_session,
+        "other file",
+        Some("video/mp4"),
+        "other file content",
+    )
+    .await;
+
+    collection_file_pair_service
+        .add_file_to_collection(collection.id, file.id, None)
+        .await
+        .unwrap();
+
+    let response = client
+        .post(format!("/collections/{}/files", collection.id))
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .body(
+            serde_json::to_string(&AddingCollectionFile {
+                file_id: other_file.id,
+            })
+            .unwrap(),
+        )
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let body = response.into_json::<serde_json::Value>().await.unwrap();
+
+    assert_eq!(status, Status::UnprocessableEntity);
+    assert_eq!(body["error_code"], "COLLECTION_FULL");
+
+    let raw_other_file = collection_file_pair_service
+        .get_file_in_collection_by_id(collection.id, other_file.id)
+        .await
+        .unwrap();
+
+    assert_eq!(raw_other_file, None);
+}
+
+#[rocket::async_test]
+async fn test_merge_collection() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let collection_service = client.rocket().state::<Arc<CollectionService>>().unwrap();
+    let collection_file_pair_service = client
+        .rocket()
+        .state::<Arc<CollectionFilePairService>>()
+        .unwrap();
+    let staging_file_service = client.rocket().state::<Arc<StagingFileService>>().unwrap();
+    let file_service = client.rocket().state::<Arc<FileService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    let (_initial_user, initial_user_session) =
+        create_initial_user(auth_service, user_service).await;
+
+    let collection = collection_service
+        .create_collection("collection", None, false, None)
+        .await
+        .unwrap();
+    let source_collection = collection_service
+        .create_collection("source collection", None, false, None)
+        .await
+        .unwrap();
+
+    let shared_file = create_file(
+        &client,
+        staging_file_service,
+        file_service,
         &initial_user_session,
-        "file",
+        "shared file",
         Some("video/mp4"),
-        "file content",
+        "shared file content",
+    )
+    .await;
+    let source_file = create_file(
+        &client,
+        staging_file_service,
+        file_service,
+        &initial_user_session,
+        "source file",
+        Some("video/mp4"),
+        "source file content",
     )
     .await;
 
+    collection_file_pair_service
+        .add_file_to_collection(collection.id, shared_file.id, None)
+        .await
+        .unwrap();
+
+    for file in [&shared_file, &source_file] {
+        collection_file_pair_service
+            .add_file_to_collection(source_collection.id, file.id, None)
+            .await
+            .unwrap();
+    }
+
     let response = client
-        .post(format!("/collections/{}/files", collection.id))
+        .post(format!("/collections/{}/merge", collection.id))
         .header(Accept::JSON)
         .header(ContentType::JSON)
         .header(Header::new(
             "Authorization",
             format!("Bearer {}", initial_user_session.token),
         ))
-        .body(serde_json::to_string(&AddingCollectionFile { file_id: file.id }).unwrap())
+        .body(
+            serde_json::to_string(&MergingCollection {
+                source_collection_id: source_collection.id,
+                remove_source: true,
+            })
+            .unwrap(),
+        )
         .dispatch()
         .await;
 
     let status = response.status();
-    let added_collection_file_pair = response.into_json::<CollectionFilePair>().await.unwrap();
+    let merge = response.into_json::<CollectionMerge>().await.unwrap();
 
-    assert_eq!(status, Status::Created);
-    assert_eq!(added_collection_file_pair.collection_id, collection.id);
-    assert_eq!(added_collection_file_pair.file_id, file.id);
+    assert_eq!(status, Status::Ok);
+    assert_eq!(
+        merge,
+        CollectionMerge {
+            moved_count: 1,
+            skipped_count: 1,
+            source_removed: true,
+        }
+    );
 
-    let raw_added_file = collection_file_pair_service
-        .get_file_in_collection_by_id(
-            added_collection_file_pair.collection_id,
-            added_collection_file_pair.file_id,
-        )
+    for file in [&shared_file, &source_file] {
+        let raw_file = collection_file_pair_service
+            .get_file_in_collection_by_id(collection.id, file.id)
+            .await
+            .unwrap();
+
+        assert_eq!(raw_file.as_ref(), Some(file));
+    }
+
+    let raw_source_collection = collection_service
+        .get_collection_by_id(source_collection.id)
         .await
-        .unwrap()
         .unwrap();
 
-    assert_eq!(raw_added_file, file);
+    assert_eq!(raw_source_collection, None);
 }
 
 #[rocket::async_test]
@@ -428,14 +1116,14 @@ async fn test_remove_file_to_collection() {
         create_initial_user(auth_service, user_service).await;
 
     let collection = collection_service
-        .create_collection("collection", Some("collection description"))
+        .create_collection("collection", Some("collection description"), false, None)
         .await
         .unwrap();
 
     let file = create_file(
         &client,
         staging_file_service,
-        &file_service,
+        file_service,
         &initial_user_session,
         "file",
         Some("video/mp4"),
@@ -444,7 +1132,7 @@ async fn test_remove_file_to_collection() {
     .await;
 
     collection_file_pair_service
-        .add_file_to_collection(collection.id, file.id)
+        .add_file_to_collection(collection.id, file.id, None)
         .await
         .unwrap();
 
@@ -496,7 +1184,7 @@ async fn test_get_files_in_collection() {
         create_initial_user(auth_service, user_service).await;
 
     let collection = collection_service
-        .create_collection("collection", Some("collection description"))
+        .create_collection("collection", Some("collection description"), false, None)
         .await
         .unwrap();
 
@@ -535,7 +1223,7 @@ async fn test_get_files_in_collection() {
 
     for file in &files {
         collection_file_pair_service
-            .add_file_to_collection(collection.id, file.id)
+            .add_file_to_collection(collection.id, file.id, None)
             .await
             .unwrap();
     }
@@ -561,7 +1249,7 @@ async fn test_get_files_in_collection() {
     assert_eq!(status, Status::Ok);
     assert_eq!(retrieved_files.last_file_id, None);
     assert_eq!(retrieved_files.limit, files.len() as u32);
-    assert_eq!(retrieved_files.files, files);
+    assert_eq!(unlist_files(&retrieved_files.files), files);
 
     let raw_retrieved_files = collection_file_pair_service
         .get_files_in_collection(
@@ -572,7 +1260,7 @@ async fn test_get_files_in_collection() {
         .await
         .unwrap();
 
-    assert_eq!(raw_retrieved_files, retrieved_files.files);
+    assert_eq!(raw_retrieved_files, unlist_files(&retrieved_files.files));
 }
 
 #[rocket::async_test]
@@ -593,7 +1281,7 @@ async fn test_get_files_in_collection_paginations() {
         create_initial_user(auth_service, user_service).await;
 
     let collection = collection_service
-        .create_collection("collection", Some("collection description"))
+        .create_collection("collection", Some("collection description"), false, None)
         .await
         .unwrap();
 
@@ -662,7 +1350,7 @@ async fn test_get_files_in_collection_paginations() {
 
     for file in &files {
         collection_file_pair_service
-            .add_file_to_collection(collection.id, file.id)
+            .add_file_to_collection(collection.id, file.id, None)
             .await
             .unwrap();
     }
@@ -703,7 +1391,7 @@ async fn test_get_files_in_collection_paginations() {
             }
         );
         assert_eq!(retrieved_files.limit, files.len() as u32);
-        assert_eq!(retrieved_files.files, files[index..]);
+        assert_eq!(unlist_files(&retrieved_files.files), files[index..]);
 
         let raw_retrieved_files = collection_file_pair_service
             .get_files_in_collection(
@@ -714,7 +1402,7 @@ async fn test_get_files_in_collection_paginations() {
             .await
             .unwrap();
 
-        assert_eq!(raw_retrieved_files, retrieved_files.files);
+        assert_eq!(raw_retrieved_files, unlist_files(&retrieved_files.files));
     }
 }
 
@@ -736,7 +1424,7 @@ async fn test_get_file_in_collection() {
         create_initial_user(auth_service, user_service).await;
 
     let collection = collection_service
-        .create_collection("collection", Some("collection description"))
+        .create_collection("collection", Some("collection description"), false, None)
         .await
         .unwrap();
 
@@ -752,7 +1440,7 @@ async fn test_get_file_in_collection() {
     .await;
 
     collection_file_pair_service
-        .add_file_to_collection(collection.id, file.id)
+        .add_file_to_collection(collection.id, file.id, None)
         .await
         .unwrap();
 
@@ -781,3 +1469,272 @@ async fn test_get_file_in_collection() {
 
     assert_eq!(raw_retrieved_file, retrieved_file);
 }
+
+#[rocket::async_test]
+async fn test_set_file_order_in_collection() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let collection_service = client.rocket().state::<Arc<CollectionService>>().unwrap();
+    let collection_file_pair_service = client
+        .rocket()
+        .state::<Arc<CollectionFilePairService>>()
+        .unwrap();
+    let staging_file_service = client.rocket().state::<Arc<StagingFileService>>().unwrap();
+    let file_service = client.rocket().state::<Arc<FileService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    let (_initial_user, initial_user_session) =
+        create_initial_user(auth_service, user_service).await;
+
+    let collection = collection_service
+        .create_collection("collection", Some("collection description"), false, None)
+        .await
+        .unwrap();
+
+    let files = vec![
+        create_file(
+            &client,
+            staging_file_service,
+            file_service,
+            &initial_user_session,
+            "file0",
+            Some("video/mp4"),
+            "file0 content",
+        )
+        .await,
+        create_file(
+            &client,
+            staging_file_service,
+            file_service,
+            &initial_user_session,
+            "file1",
+            Some("image/png"),
+            "file1 content",
+        )
+        .await,
+        create_file(
+            &client,
+            staging_file_service,
+            file_service,
+            &initial_user_session,
+            "file2",
+            Some("text/plain"),
+            "file2 content",
+        )
+        .await,
+    ];
+
+    for file in &files {
+        collection_file_pair_service
+            .add_file_to_collection(collection.id, file.id, None)
+            .await
+            .unwrap();
+    }
+
+    let response = client
+        .put(format!("/collections/{}/files/order", collection.id))
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .body(
+            serde_json::to_string(&OrderingCollectionFiles {
+                file_ids: vec![files[2].id, files[0].id],
+            })
+            .unwrap(),
+        )
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let file_order = response.into_json::<CollectionFileOrder>().await.unwrap();
+
+    assert_eq!(status, Status::Ok);
+    assert_eq!(file_order.ordered_count, 2);
+
+    // ordered files come first, the rest follow by name
+    let expected_files = vec![files[2].clone(), files[0].clone(), files[1].clone()];
+
+    let raw_retrieved_files = collection_file_pair_service
+        .get_files_in_collection(collection.id, None, files.len() as u32)
+        .await
+        .unwrap();
+
+    assert_eq!(raw_retrieved_files, expected_files);
+
+    for index in 0..expected_files.len() - 1 {
+        let raw_retrieved_files = collection_file_pair_service
+            .get_files_in_collection(collection.id, Some(expected_files[index].id), 1)
+            .await
+            .unwrap();
+
+        assert_eq!(raw_retrieved_files, vec![expected_files[index + 1].clone()]);
+    }
+
+    let response = client
+        .put(format!("/collections/{}/files/order", collection.id))
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .body(
+            serde_json::to_string(&OrderingCollectionFiles {
+                file_ids: vec![files[1].id, files[1].id],
+            })
+            .unwrap(),
+        )
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+}
+
+#[rocket::async_test]
+async fn test_retention_policy() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let collection_service = client.rocket().state::<Arc<CollectionService>>().unwrap();
+    let collection_file_pair_service = client
+        .rocket()
+        .state::<Arc<CollectionFilePairService>>()
+        .unwrap();
+    let staging_file_service = client.rocket().state::<Arc<StagingFileService>>().unwrap();
+    let file_service = client.rocket().state::<Arc<FileService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+    let retention_service = client.rocket().state::<Arc<RetentionService>>().unwrap();
+    let audit_log_service = client.rocket().state::<Arc<AuditLogService>>().unwrap();
+
+    let (_initial_user, initial_user_session) =
+        create_initial_user(auth_service, user_service).await;
+
+    let collection = collection_service
+        .create_collection("collection", None, false, None)
+        .await
+        .unwrap();
+    let mut files = vec![];
+
+    for index in 0..3 {
+        let file = create_file(
+            &client,
+            staging_file_service,
+            file_service,
+            &initial_user_session,
+            &format!("file {}", index),
+            Some("text/plain"),
+            "file content",
+        )
+        .await;
+        collection_file_pair_service
+            .add_file_to_collection(collection.id, file.id, None)
+            .await
+            .unwrap();
+        files.push(file);
+    }
+
+    let response = client
+        .put(format!("/collections/{}/retention", collection.id))
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .body(
+            serde_json::to_string(&SettingRetentionPolicy {
+                max_age_days: None,
+                keep_latest: None,
+            })
+            .unwrap(),
+        )
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+
+    let response = client
+        .put(format!("/collections/{}/retention", collection.id))
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .body(
+            serde_json::to_string(&SettingRetentionPolicy {
+                max_age_days: Some(90),
+                keep_latest: Some(1),
+            })
+            .unwrap(),
+        )
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let policy = response.into_json::<RetentionPolicy>().await.unwrap();
+
+    assert_eq!(status, Status::Ok);
+    assert_eq!(policy.collection_id, collection.id);
+    assert_eq!(policy.max_age_days, Some(90));
+    assert_eq!(policy.keep_latest, Some(1));
+
+    let response = client
+        .get(format!("/collections/{}/retention/preview", collection.id))
+        .header(Accept::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let preview = response.into_json::<RetentionPreview>().await.unwrap();
+
+    assert_eq!(status, Status::Ok);
+    assert_eq!(preview.files, files[..2]);
+
+    apply_retention_policies(retention_service, audit_log_service).await;
+
+    let remaining_files = collection_file_pair_service
+        .get_files_in_collection(collection.id, None, 10)
+        .await
+        .unwrap();
+
+    assert_eq!(remaining_files, files[2..]);
+
+    // the files are only removed from the collection
+    for file in &files[..2] {
+        let file = file_service.get_file_by_id(file.id).await.unwrap();
+        assert!(file.is_some());
+    }
+
+    let response = client
+        .delete(format!("/collections/{}/retention", collection.id))
+        .header(Accept::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::Ok);
+
+    let response = client
+        .get(format!("/collections/{}/retention/preview", collection.id))
+        .header(Accept::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::NotFound);
+}
@@ -0,0 +1,41 @@
+use crate::{
+    db::models::User,
+    dto::{ApiResponse, Error, ErrorCode, JsonRes},
+    services::{EmailChangeService, EmailChangeServiceError},
+};
+use rocket::{http::Status, post, routes, Build, Rocket, State};
+use std::sync::Arc;
+
+pub fn register_routes(rocket: Rocket<Build>) -> Rocket<Build> {
+    rocket.mount("/email-changes", routes![confirm_email_change])
+}
+
+/// Confirms an email change with the token mailed to the new email.
+#[post("/<token>")]
+async fn confirm_email_change(
+    email_change_service: &State<Arc<EmailChangeService>>,
+    token: &str,
+) -> JsonRes<User> {
+    let user = email_change_service.confirm_email_change(token).await;
+
+    let user = match user {
+        Ok(Some(user)) => user,
+        Ok(None) => {
+            return Err(Error::new_not_found(ErrorCode::TokenNotFound));
+        }
+        Err(EmailChangeServiceError::EmailTaken(_)) => {
+            return Err(
+                Error::new_static(Status::Conflict, "the email is already in use")
+                    .with_code(ErrorCode::EmailTaken),
+            );
+        }
+        Err(err) => {
+            log::error!(target: "routes::email_change::controllers", controller = "confirm_email_change", service = "EmailChangeService", err:err; "Error returned from service.");
+            return Err(Status::InternalServerError.into());
+        }
+    };
+
+    log::info!(target: "routes::email_change::controllers", controller = "confirm_email_change", user_id = user.id; "Email changed.");
+
+    Ok(ApiResponse::new(Status::Ok, user))
+}
@@ -0,0 +1,98 @@
+use crate::{
+    db::models::User,
+    services::{AuthService, EmailChangeService, EmailChangeServiceError, UserService},
+    test::{
+        create_test_rocket_instance,
+        helpers::{create_initial_user, create_user},
+    },
+};
+use rocket::{
+    http::{Accept, Status},
+    local::asynchronous::Client,
+};
+use std::sync::Arc;
+
+#[rocket::async_test]
+async fn test_confirm_email_change() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+    let email_change_service = client.rocket().state::<Arc<EmailChangeService>>().unwrap();
+
+    let (initial_user, _initial_user_session) =
+        create_initial_user(auth_service, user_service).await;
+    let request = email_change_service
+        .create_email_change_request(initial_user.id, "new@example.com")
+        .await
+        .unwrap()
+        .unwrap();
+
+    // the email is not changed until the request is confirmed
+    let user = user_service
+        .get_user_by_id(initial_user.id)
+        .await
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(user.email, initial_user.email);
+
+    let response = client
+        .post(format!("/email-changes/{}", request.token))
+        .header(Accept::JSON)
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let user = response.into_json::<User>().await.unwrap();
+
+    assert_eq!(status, Status::Ok);
+    assert_eq!(user.id, initial_user.id);
+    assert_eq!(user.email, "new@example.com");
+
+    // the token can only be used once
+    let response = client
+        .post(format!("/email-changes/{}", request.token))
+        .header(Accept::JSON)
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+#[rocket::async_test]
+async fn test_confirm_email_change_taken() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+    let email_change_service = client.rocket().state::<Arc<EmailChangeService>>().unwrap();
+
+    let (initial_user, _initial_user_session) =
+        create_initial_user(auth_service, user_service).await;
+    let request = email_change_service
+        .create_email_change_request(initial_user.id, "user_user@example.com")
+        .await
+        .unwrap()
+        .unwrap();
+
+    // another user takes the email before the request is confirmed
+    create_user("user", user_service).await;
+
+    let response = client
+        .post(format!("/email-changes/{}", request.token))
+        .header(Accept::JSON)
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::Conflict);
+
+    let result = email_change_service
+        .create_email_change_request(initial_user.id, "user_user@example.com")
+        .await;
+
+    assert!(matches!(
+        result,
+        Err(EmailChangeServiceError::EmailTaken(_))
+    ));
+}
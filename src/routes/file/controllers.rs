@@ -1,18 +1,37 @@
-use super::dto::{FileData, FileList, FileSearchResult, SearchingFile};
+use super::dto::{
+    content_disposition, ArchivingFiles, CommentList, CreatingComment, DuplicateFileGroupList,
+    FileAttributes, FileBatch, FileData, FileDataHead, FileList, FilePromotion, FilePromotionBatch,
+    FileSearchResult, FileVersionList, FileWithMetadata, GettingFiles, ListedFile, PromotingFiles,
+    RandomFileList, RecentFileList, RenditionList, SearchingFile, SimilarFileList, UpdatingComment,
+};
 use crate::{
-    db::models::File,
-    dto::{Error, JsonRes},
+    config::{AppConfig, AppFileDataCache},
+    db::models::{File, FileComment, TranscodeJob},
+    dto::{ApiResponse, Error, ErrorCode, JsonRes},
     guards::{AuthUserSession, RangeHeader},
-    services::{FileService, FileServiceError, ReadError, ReadRange, SearchService},
+    routes::collection::{controllers::list_collections, dto::CollectionList},
+    services::{
+        ArchiveService, AuditAction, AuditActor, AuditLogService, CollectionFilePairService,
+        FavoriteService, FileAttributeService, FileCommentService, FileFilter, FileScan,
+        FileService, FileServiceError, FileSort, FileStats, FileViewService, ImageFormat,
+        ImageTransform, ImageTransformService, ImageTransformServiceError, ReadAheadService,
+        ReadError, ReadRange, RenditionProfile, SearchCursor, SearchService, SortDirection,
+        TranscodeService, TransferLimitService, UserRole, HLS_PLAYLIST_NAME,
+    },
+    validation::{FieldErrors, Validate, MAX_BATCH_SIZE},
 };
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
 use rocket::{
-    delete, get,
+    delete, get, head,
     http::{Status, StatusClass},
-    post, routes,
+    post, put, routes,
     serde::json::Json,
     Build, Rocket, State,
 };
-use std::sync::Arc;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
 use uuid::Uuid;
 
 pub fn register_routes(rocket: Rocket<Build>) -> Rocket<Build> {
@@ -20,57 +39,321 @@ pub fn register_routes(rocket: Rocket<Build>) -> Rocket<Build> {
         "/files",
         routes![
             create_file,
+            create_files,
             remove_file,
+            add_favorite_file,
+            remove_favorite_file,
             search_files,
             get_files,
+            get_recent_files,
+            get_random_files,
+            get_uncollected_files,
+            get_untagged_files,
+            get_duplicate_files,
             get_file,
-            get_file_data
+            get_files_by_ids,
+            archive_files,
+            get_file_stats,
+            get_file_scan,
+            get_similar_files,
+            get_file_attributes,
+            set_file_attributes,
+            get_file_versions,
+            restore_file_version,
+            create_comment,
+            get_comments,
+            update_comment,
+            remove_comment,
+            get_collections_for_file,
+            get_file_data,
+            get_file_data_head,
+            get_renditions,
+            request_rendition,
+            get_rendition_data,
+            get_hls_playlist,
+            get_hls_segment
         ],
     )
 }
 
-fn map_file_service_err(err: &FileServiceError) -> Error {
+pub(crate) fn map_file_service_err(err: &FileServiceError) -> Error {
     match err {
         FileServiceError::FileNotYetFilled => {
             Error::new_dynamic(Status::UnprocessableEntity, "staging file not yet filled")
+                .with_code(ErrorCode::StagingFileNotFilled)
+        }
+        FileServiceError::HashMismatch { .. } => {
+            Error::new_dynamic(Status::UnprocessableEntity, err.to_string())
+                .with_code(ErrorCode::HashMismatch)
+        }
+        FileServiceError::MimeNotAllowed { .. } => {
+            Error::new_dynamic(Status::UnprocessableEntity, err.to_string())
+                .with_code(ErrorCode::MimeNotAllowed)
         }
+        FileServiceError::Infected { .. } => {
+            Error::new_dynamic(Status::UnprocessableEntity, err.to_string())
+                .with_code(ErrorCode::FileInfected)
+        }
+        // the file cannot be promoted unscanned, so it has to be retried once the scanner is back
+        FileServiceError::Scanner(_) => Status::ServiceUnavailable.into(),
         _ => Status::InternalServerError.into(),
     }
 }
 
-#[post("/<staging_file_id>")]
+/// Records the upload of a file in the audit log.
+/// Failures are only logged, as the upload itself has succeeded.
+pub(crate) async fn record_file_upload(
+    audit_log_service: &AuditLogService,
+    actor: AuditActor,
+    file: &File,
+) {
+    let details = serde_json::json!({
+        "name": file.name,
+        "mime": file.mime,
+        "size": file.size,
+    });
+    let result = audit_log_service
+        .record(
+            actor,
+            AuditAction::FileUploaded,
+            Some(file.id),
+            None,
+            details,
+        )
+        .await;
+
+    if let Err(err) = result {
+        let user_id = actor.user_id;
+        let file_id = file.id;
+        log::warn!(target: "routes::file::controllers", service = "AuditLogService", user_id, file_id:serde, err:err; "Failed to record the audit log.");
+    }
+}
+
+/// Marks the files the user has marked as favorites, for list responses.
+pub(crate) async fn list_files(
+    favorite_service: &FavoriteService,
+    user_id: i32,
+    files: Vec<File>,
+) -> Result<Vec<ListedFile>, Error> {
+    let file_ids = files.iter().map(|file| file.id).collect::<Vec<_>>();
+    let favorite_file_ids = favorite_service
+        .get_favorite_file_ids(user_id, &file_ids)
+        .await;
+
+    let favorite_file_ids = match favorite_file_ids {
+        Ok(favorite_file_ids) => favorite_file_ids,
+        Err(err) => {
+            log::error!(target: "routes::file::controllers", service = "FavoriteService", user_id, err:err; "Error returned from service.");
+            return Err(Status::InternalServerError.into());
+        }
+    };
+
+    Ok(ListedFile::from_files(files, &favorite_file_ids))
+}
+
+pub(crate) fn parse_search_cursor(cursor: Option<&str>) -> Result<Option<SearchCursor>, Error> {
+    match cursor {
+        Some(cursor) => match SearchCursor::decode(cursor) {
+            Some(cursor) => Ok(Some(cursor)),
+            None => Err(
+                Error::new_static(Status::BadRequest, "the cursor is invalid")
+                    .with_code(ErrorCode::InvalidCursor),
+            ),
+        },
+        None => Ok(None),
+    }
+}
+
+/// Creates a file from a staging file.
+/// If `replaces_file_id` is given, or `file_versions.replace_by_name` is set and a file with the same name exists,
+/// the staging file replaces that file instead, keeping its current data as a former version.
+/// Admins can promote a file whose MIME type is not allowed by the policy with `allow_any_mime=true`.
+#[allow(clippy::too_many_arguments)]
+#[post("/<staging_file_id>?<replaces_file_id>&<allow_any_mime>")]
 async fn create_file(
-    #[allow(unused_variables)] sess: AuthUserSession<'_>,
+    sess: AuthUserSession<'_>,
+    app_config: &State<AppConfig>,
     file_service: &State<Arc<FileService>>,
+    audit_log_service: &State<Arc<AuditLogService>>,
+    read_ahead_service: &State<Arc<ReadAheadService>>,
     staging_file_id: Uuid,
+    replaces_file_id: Option<Uuid>,
+    allow_any_mime: Option<bool>,
 ) -> JsonRes<File> {
-    let file = file_service
-        .create_file_from_staging_file_id(staging_file_id)
+    let allow_any_mime = check_allow_any_mime(&sess, allow_any_mime)?;
+    let (status, file) = promote_staging_file(
+        &sess,
+        app_config,
+        file_service,
+        audit_log_service,
+        read_ahead_service,
+        staging_file_id,
+        replaces_file_id,
+        allow_any_mime,
+    )
+    .await?;
+
+    Ok(ApiResponse::new(status, file))
+}
+
+/// Creates files from many staging files at once, so that clients uploading many small files
+/// do not need a round trip per file.
+/// Each staging file is promoted on its own as `POST /files/<staging_file_id>` would,
+/// and its outcome is reported in the result, so a failure does not affect the others.
+#[post("/batch?<allow_any_mime>", data = "<body>")]
+async fn create_files(
+    sess: AuthUserSession<'_>,
+    app_config: &State<AppConfig>,
+    file_service: &State<Arc<FileService>>,
+    audit_log_service: &State<Arc<AuditLogService>>,
+    read_ahead_service: &State<Arc<ReadAheadService>>,
+    allow_any_mime: Option<bool>,
+    body: Json<PromotingFiles>,
+) -> JsonRes<FilePromotionBatch> {
+    body.validate()?;
+
+    let allow_any_mime = check_allow_any_mime(&sess, allow_any_mime)?;
+
+    let mut seen_ids = HashSet::with_capacity(body.staging_file_ids.len());
+    let mut results = Vec::with_capacity(body.staging_file_ids.len());
+
+    for &staging_file_id in &body.staging_file_ids {
+        if !seen_ids.insert(staging_file_id) {
+            continue;
+        }
+
+        let promotion = promote_staging_file(
+            &sess,
+            app_config,
+            file_service,
+            audit_log_service,
+            read_ahead_service,
+            staging_file_id,
+            None,
+            allow_any_mime,
+        )
         .await;
 
+        results.push(match promotion {
+            Ok((status, file)) => FilePromotion {
+                staging_file_id,
+                status: status.code,
+                file: Some(file),
+                error: None,
+            },
+            Err(error) => FilePromotion {
+                staging_file_id,
+                status: error.status().code,
+                file: None,
+                error: Some(error.into_body()),
+            },
+        });
+    }
+
+    Ok(ApiResponse::new(Status::Ok, FilePromotionBatch { results }))
+}
+
+/// Fails with `403 Forbidden` if a user other than an admin asks to override the MIME policy.
+fn check_allow_any_mime(
+    sess: &AuthUserSession<'_>,
+    allow_any_mime: Option<bool>,
+) -> Result<bool, Error> {
+    let allow_any_mime = allow_any_mime.unwrap_or(false);
+
+    if allow_any_mime && sess.user.role != UserRole::Admin.name() {
+        return Err(Error::new_static(
+            Status::Forbidden,
+            "only admins can override the MIME policy",
+        ));
+    }
+
+    Ok(allow_any_mime)
+}
+
+/// Promotes a staging file into a file, or into a new version of the file it replaces.
+/// Returns the status to respond with along with the file: `201 Created` for a new file, and `200 OK` for a replaced one.
+#[allow(clippy::too_many_arguments)]
+async fn promote_staging_file(
+    sess: &AuthUserSession<'_>,
+    app_config: &AppConfig,
+    file_service: &FileService,
+    audit_log_service: &AuditLogService,
+    read_ahead_service: &ReadAheadService,
+    staging_file_id: Uuid,
+    replaces_file_id: Option<Uuid>,
+    allow_any_mime: bool,
+) -> Result<(Status, File), Error> {
+    let replaces_file_id = match replaces_file_id {
+        Some(replaces_file_id) => Some(replaces_file_id),
+        None if app_config.file_versions.replace_by_name => {
+            match file_service
+                .find_file_by_staging_file_name(staging_file_id)
+                .await
+            {
+                Ok(file_id) => file_id,
+                Err(err) => {
+                    log::error!(target: "routes::file::controllers", controller = "create_file", service = "FileService", staging_file_id:serde, err:err; "Error returned from service.");
+                    return Err(map_file_service_err(&err));
+                }
+            }
+        }
+        None => None,
+    };
+
+    let file = match replaces_file_id {
+        Some(replaces_file_id) => {
+            file_service
+                .replace_file_from_staging_file_id(
+                    replaces_file_id,
+                    staging_file_id,
+                    !allow_any_mime,
+                )
+                .await
+        }
+        None => {
+            file_service
+                .create_file_from_staging_file_id_with_policy(staging_file_id, !allow_any_mime)
+                .await
+        }
+    };
+
     let file = match file {
         Ok(Some(file)) => file,
         Ok(None) => {
-            return Err(Status::NotFound.into());
+            return Err(Error::new_not_found(ErrorCode::StagingFileNotFound));
         }
         Err(err) => {
             let error = map_file_service_err(&err);
 
             if error.status().class() == StatusClass::ServerError {
-                log::error!(target: "routes::file::controllers", controller = "create_file", service = "FileService", staging_file_id:serde, err:err; "Error returned from service.");
+                log::error!(target: "routes::file::controllers", controller = "create_file", service = "FileService", staging_file_id:serde, replaces_file_id:serde, err:err; "Error returned from service.");
             }
 
             return Err(error);
         }
     };
 
-    Ok((Status::Created, Json(file)))
+    record_file_upload(audit_log_service, sess.audit_actor(), &file).await;
+
+    // a replaced file keeps its ID, so nothing has been created
+    let status = if Some(file.id) == replaces_file_id {
+        read_ahead_service.invalidate_file(file.id);
+        Status::Ok
+    } else {
+        Status::Created
+    };
+
+    Ok((status, file))
 }
 
 #[delete("/<file_id>")]
 async fn remove_file(
-    #[allow(unused_variables)] sess: AuthUserSession<'_>,
+    sess: AuthUserSession<'_>,
     file_service: &State<Arc<FileService>>,
+    audit_log_service: &State<Arc<AuditLogService>>,
+    read_ahead_service: &State<Arc<ReadAheadService>>,
+    transcode_service: &State<Arc<TranscodeService>>,
+    image_transform_service: &State<Arc<ImageTransformService>>,
     file_id: Uuid,
 ) -> JsonRes<File> {
     let file = file_service.remove_file_by_id(file_id).await;
@@ -78,7 +361,7 @@ async fn remove_file(
     let file = match file {
         Ok(Some(file)) => file,
         Ok(None) => {
-            return Err(Status::NotFound.into());
+            return Err(Error::new_not_found(ErrorCode::FileNotFound));
         }
         Err(err) => {
             log::error!(target: "routes::file::controllers", controller = "remove_file", service = "FileService", file_id:serde, err:err; "Error returned from service.");
@@ -86,15 +369,112 @@ async fn remove_file(
         }
     };
 
-    Ok((Status::Ok, Json(file)))
+    read_ahead_service.invalidate_file(file_id);
+
+    if let Err(err) = transcode_service.remove_renditions(file_id).await {
+        // leftover renditions only waste storage, so it is not critical
+        log::warn!(target: "routes::file::controllers", controller = "remove_file", service = "TranscodeService", file_id:serde, err:err; "Error returned from service.");
+    }
+
+    if let Err(err) = image_transform_service.remove_cached(file_id).await {
+        // so do leftover transformed images
+        log::warn!(target: "routes::file::controllers", controller = "remove_file", service = "ImageTransformService", file_id:serde, err:err; "Error returned from service.");
+    }
+
+    let details = serde_json::json!({ "name": file.name });
+
+    if let Err(err) = audit_log_service
+        .record(
+            sess.audit_actor(),
+            AuditAction::FileRemoved,
+            Some(file_id),
+            None,
+            details,
+        )
+        .await
+    {
+        log::warn!(target: "routes::file::controllers", controller = "remove_file", service = "AuditLogService", file_id:serde, err:err; "Failed to record the audit log.");
+    }
+
+    Ok(ApiResponse::new(Status::Ok, file))
+}
+
+#[put("/<file_id>/favorite")]
+async fn add_favorite_file(
+    sess: AuthUserSession<'_>,
+    favorite_service: &State<Arc<FavoriteService>>,
+    file_id: Uuid,
+) -> Result<Status, Error> {
+    let added = favorite_service
+        .add_favorite_file(sess.user.id, file_id)
+        .await;
+
+    match added {
+        Ok(true) => {}
+        Ok(false) => {
+            return Err(Error::new_not_found(ErrorCode::FileNotFound));
+        }
+        Err(err) => {
+            log::error!(target: "routes::file::controllers", controller = "add_favorite_file", service = "FavoriteService", file_id:serde, err:err; "Error returned from service.");
+            return Err(Status::InternalServerError.into());
+        }
+    }
+
+    Ok(Status::NoContent)
+}
+
+#[delete("/<file_id>/favorite")]
+async fn remove_favorite_file(
+    sess: AuthUserSession<'_>,
+    favorite_service: &State<Arc<FavoriteService>>,
+    file_id: Uuid,
+) -> Result<Status, Error> {
+    let removed = favorite_service
+        .remove_favorite_file(sess.user.id, file_id)
+        .await;
+
+    match removed {
+        Ok(true) => {}
+        Ok(false) => {
+            return Err(Error::new_not_found(ErrorCode::FileNotFound));
+        }
+        Err(err) => {
+            log::error!(target: "routes::file::controllers", controller = "remove_favorite_file", service = "FavoriteService", file_id:serde, err:err; "Error returned from service.");
+            return Err(Status::InternalServerError.into());
+        }
+    }
+
+    Ok(Status::NoContent)
 }
 
 #[post("/search", data = "<body>")]
 async fn search_files(
     #[allow(unused_variables)] sess: AuthUserSession<'_>,
-    search_service: &State<Arc<SearchService>>,
+    search_service: &State<Arc<dyn SearchService + Send + Sync>>,
     body: Json<SearchingFile<'_>>,
 ) -> JsonRes<FileSearchResult> {
+    body.validate()?;
+
+    // only the filterable attributes are indexed, so the others would never match
+    let filterable_file_attributes = search_service.settings().filterable_file_attributes;
+    let mut errors = FieldErrors::default();
+
+    for key in body.filter_attributes.keys() {
+        if !filterable_file_attributes.contains(key) {
+            errors.add(
+                "filter_attributes",
+                format!("attribute `{}` is not filterable", key),
+            );
+        }
+    }
+
+    errors.into_result()?;
+
+    let cursor = parse_search_cursor(body.cursor)?;
+    let limit = body.limit.unwrap_or(25);
+    let limit = u32::max(1, limit);
+    let limit = u32::min(limit, 100);
+
     let files = search_service
         .search_files(
             body.query,
@@ -102,6 +482,11 @@ async fn search_files(
             body.filter_size,
             body.filter_hash,
             body.filter_uploaded_at,
+            &body.filter_metadata,
+            &body.filter_tags,
+            &body.filter_attributes,
+            cursor,
+            limit,
         )
         .await;
 
@@ -114,132 +499,1496 @@ async fn search_files(
         }
     };
 
-    Ok((Status::Ok, Json(FileSearchResult { files })))
+    Ok(ApiResponse::new(
+        Status::Ok,
+        FileSearchResult {
+            files: files.files.items,
+            facets: files.facets,
+            next_cursor: files.files.next_cursor.map(SearchCursor::encode),
+        },
+    ))
 }
 
-#[get("/?<last_file_id>&<limit>")]
+/// Lists files, optionally filtered and sorted.
+/// `order` is the former name of `sort`, and is accepted for compatibility.
+#[allow(clippy::too_many_arguments)]
+#[get("/?<last_file_id>&<sort>&<order>&<direction>&<mime>&<min_size>&<max_size>&<uploaded_after>&<uploaded_before>&<limit>&<with_total>")]
 async fn get_files(
-    #[allow(unused_variables)] sess: AuthUserSession<'_>,
+    sess: AuthUserSession<'_>,
     file_service: &State<Arc<FileService>>,
+    favorite_service: &State<Arc<FavoriteService>>,
     last_file_id: Option<Uuid>,
+    sort: Option<&str>,
+    order: Option<&str>,
+    direction: Option<&str>,
+    mime: Option<&str>,
+    min_size: Option<i64>,
+    max_size: Option<i64>,
+    uploaded_after: Option<&str>,
+    uploaded_before: Option<&str>,
     limit: Option<u32>,
+    with_total: Option<bool>,
 ) -> JsonRes<FileList> {
+    let sort = match sort.or(order) {
+        Some(sort) => FileSort::from_name(sort).ok_or_else(|| {
+            Error::new_dynamic(
+                Status::BadRequest,
+                format!(
+                    "unknown sort `{}`; expected one of name, id, size, uploaded_at, downloads",
+                    sort
+                ),
+            )
+            .with_code(ErrorCode::InvalidParameter)
+        })?,
+        None => FileSort::Name,
+    };
+    let direction = match direction {
+        Some(direction) => SortDirection::from_name(direction).ok_or_else(|| {
+            Error::new_dynamic(
+                Status::BadRequest,
+                format!(
+                    "unknown direction `{}`; expected one of asc, desc",
+                    direction
+                ),
+            )
+            .with_code(ErrorCode::InvalidParameter)
+        })?,
+        None => SortDirection::Asc,
+    };
+    let filter = FileFilter {
+        mime: mime.map(|mime| mime.to_owned()),
+        min_size,
+        max_size,
+        uploaded_after: uploaded_after
+            .map(|value| parse_date_time("uploaded_after", value))
+            .transpose()?,
+        uploaded_before: uploaded_before
+            .map(|value| parse_date_time("uploaded_before", value))
+            .transpose()?,
+    };
     let limit = limit.unwrap_or(25);
     let limit = u32::max(1, limit);
     let limit = u32::min(limit, 100);
-    let files = file_service.get_files(last_file_id, limit).await;
+    let files = file_service
+        .get_files(
+            last_file_id,
+            &filter,
+            sort,
+            direction,
+            limit,
+            with_total.unwrap_or(false),
+        )
+        .await;
 
-    let files = match files {
-        Ok(files) => files,
+    let page = match files {
+        Ok(page) => page,
         Err(err) => {
-            log::error!(target: "routes::file::controllers", controller = "get_files", service = "FileService", last_file_id:serde, limit, err:err; "Error returned from service.");
+            log::error!(target: "routes::file::controllers", controller = "get_files", service = "FileService", last_file_id:serde, filter:serde, limit, err:err; "Error returned from service.");
             return Err(Status::InternalServerError.into());
         }
     };
 
-    Ok((
+    let files = list_files(favorite_service, sess.user.id, page.items).await?;
+
+    Ok(ApiResponse::new(
         Status::Ok,
-        Json(FileList {
+        FileList {
             files,
             last_file_id,
             limit,
-        }),
+            next_cursor: page.next_cursor,
+            total: page.total,
+        },
     ))
 }
 
-#[get("/<file_id>")]
-async fn get_file(
-    #[allow(unused_variables)] sess: AuthUserSession<'_>,
+/// Parses an RFC 3339 date-time or a `YYYY-MM-DD` date, which is taken as its midnight, both in UTC.
+fn parse_date_time(name: &str, value: &str) -> Result<NaiveDateTime, Error> {
+    if let Ok(date_time) = DateTime::parse_from_rfc3339(value) {
+        return Ok(date_time.naive_utc());
+    }
+
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        return Ok(date.and_time(Default::default()));
+    }
+
+    Err(Error::new_dynamic(
+        Status::BadRequest,
+        format!(
+            "{} `{}` is invalid; it should be an RFC 3339 date-time or a YYYY-MM-DD date.",
+            name, value
+        ),
+    )
+    .with_code(ErrorCode::InvalidParameter))
+}
+
+/// Lists the most recently uploaded files, or the files the user viewed most recently if `kind` is `viewed`.
+#[get("/recent?<kind>&<limit>")]
+async fn get_recent_files(
+    sess: AuthUserSession<'_>,
     file_service: &State<Arc<FileService>>,
-    file_id: Uuid,
-) -> JsonRes<File> {
-    let file = file_service.get_file_by_id(file_id).await;
+    file_view_service: &State<Arc<FileViewService>>,
+    favorite_service: &State<Arc<FavoriteService>>,
+    kind: Option<&str>,
+    limit: Option<u32>,
+) -> JsonRes<RecentFileList> {
+    let limit = limit.unwrap_or(25);
+    let limit = u32::max(1, limit);
+    let limit = u32::min(limit, 100);
 
-    let file = match file {
-        Ok(Some(file)) => file,
-        Ok(None) => {
-            return Err(Status::NotFound.into());
+    let files = match kind.unwrap_or("uploaded") {
+        "uploaded" => {
+            let files = file_service
+                .get_files(
+                    None,
+                    &FileFilter::default(),
+                    FileSort::UploadedAt,
+                    SortDirection::Desc,
+                    limit,
+                    false,
+                )
+                .await;
+
+            match files {
+                Ok(page) => page.items,
+                Err(err) => {
+                    log::error!(target: "routes::file::controllers", controller = "get_recent_files", service = "FileService", limit, err:err; "Error returned from service.");
+                    return Err(Status::InternalServerError.into());
+                }
+            }
         }
+        "viewed" => {
+            let files = file_view_service
+                .get_recently_viewed_files(sess.user.id, limit)
+                .await;
+
+            match files {
+                Ok(files) => files,
+                Err(err) => {
+                    log::error!(target: "routes::file::controllers", controller = "get_recent_files", service = "FileViewService", limit, err:err; "Error returned from service.");
+                    return Err(Status::InternalServerError.into());
+                }
+            }
+        }
+        kind => {
+            return Err(Error::new_dynamic(
+                Status::BadRequest,
+                format!("unknown kind `{}`; expected one of uploaded, viewed", kind),
+            )
+            .with_code(ErrorCode::InvalidParameter));
+        }
+    };
+
+    let files = list_files(favorite_service, sess.user.id, files).await?;
+
+    Ok(ApiResponse::new(
+        Status::Ok,
+        RecentFileList { files, limit },
+    ))
+}
+
+/// Retrieves the files that belong to no collection, so that unsorted uploads can be triaged.
+#[get("/uncollected?<last_file_id>&<limit>&<with_total>")]
+async fn get_uncollected_files(
+    sess: AuthUserSession<'_>,
+    file_service: &State<Arc<FileService>>,
+    favorite_service: &State<Arc<FavoriteService>>,
+    last_file_id: Option<Uuid>,
+    limit: Option<u32>,
+    with_total: Option<bool>,
+) -> JsonRes<FileList> {
+    let limit = limit.unwrap_or(25);
+    let limit = u32::max(1, limit);
+    let limit = u32::min(limit, 100);
+    let files = file_service
+        .get_uncollected_files(last_file_id, limit, with_total.unwrap_or(false))
+        .await;
+
+    let page = match files {
+        Ok(page) => page,
         Err(err) => {
-            log::error!(target: "routes::file::controllers", controller = "get_file", service = "FileService", file_id:serde, err:err; "Error returned from service.");
-            return Err(map_file_service_err(&err));
+            log::error!(target: "routes::file::controllers", controller = "get_uncollected_files", service = "FileService", last_file_id:serde, limit, err:err; "Error returned from service.");
+            return Err(Status::InternalServerError.into());
         }
     };
 
-    Ok((Status::Ok, Json(file)))
+    let files = list_files(favorite_service, sess.user.id, page.items).await?;
+
+    Ok(ApiResponse::new(
+        Status::Ok,
+        FileList {
+            files,
+            last_file_id,
+            limit,
+            next_cursor: page.next_cursor,
+            total: page.total,
+        },
+    ))
 }
 
-#[get("/<file_id>/data")]
-async fn get_file_data(
-    #[allow(unused_variables)] sess: AuthUserSession<'_>,
+/// Retrieves the files that have no tags, along with the number of all of them to track the progress of tagging.
+/// `mime` is an exact MIME type, or a type followed by `/*` to match all of its subtypes.
+#[get("/untagged?<last_file_id>&<mime>&<limit>")]
+async fn get_untagged_files(
+    sess: AuthUserSession<'_>,
     file_service: &State<Arc<FileService>>,
-    range_header: RangeHeader,
-    file_id: Uuid,
-) -> Result<FileData, Error> {
-    let file = file_service.get_file_by_id(file_id).await;
-    let file = match file {
-        Ok(Some(file)) => file,
-        Ok(None) => {
-            return Err(Status::NotFound.into());
+    favorite_service: &State<Arc<FavoriteService>>,
+    last_file_id: Option<Uuid>,
+    mime: Option<&str>,
+    limit: Option<u32>,
+) -> JsonRes<FileList> {
+    let filter = FileFilter {
+        mime: mime.map(|mime| mime.to_owned()),
+        ..Default::default()
+    };
+    let limit = limit.unwrap_or(25);
+    let limit = u32::max(1, limit);
+    let limit = u32::min(limit, 100);
+    let files = file_service
+        .get_untagged_files(last_file_id, &filter, limit)
+        .await;
+
+    let page = match files {
+        Ok(page) => page,
+        Err(err) => {
+            log::error!(target: "routes::file::controllers", controller = "get_untagged_files", service = "FileService", last_file_id:serde, filter:serde, limit, err:err; "Error returned from service.");
+            return Err(Status::InternalServerError.into());
         }
+    };
+
+    let files = list_files(favorite_service, sess.user.id, page.items).await?;
+
+    Ok(ApiResponse::new(
+        Status::Ok,
+        FileList {
+            files,
+            last_file_id,
+            limit,
+            next_cursor: page.next_cursor,
+            total: page.total,
+        },
+    ))
+}
+
+/// Retrieves a random sample of files, e.g. for shuffling or screensavers.
+/// `filter_mime` is an exact MIME type, or a type followed by `/*` to match all of its subtypes.
+#[get("/random?<count>&<filter_mime>")]
+async fn get_random_files(
+    sess: AuthUserSession<'_>,
+    file_service: &State<Arc<FileService>>,
+    favorite_service: &State<Arc<FavoriteService>>,
+    count: Option<u32>,
+    filter_mime: Option<&str>,
+) -> JsonRes<RandomFileList> {
+    let count = count.unwrap_or(1);
+    let count = u32::max(1, count);
+    let count = u32::min(count, 100);
+    let filter = FileFilter {
+        mime: filter_mime.map(|mime| mime.to_owned()),
+        ..Default::default()
+    };
+    let files = file_service.get_random_files(&filter, count).await;
+
+    let files = match files {
+        Ok(files) => files,
         Err(err) => {
-            log::error!(target: "routes::file::controllers", controller = "get_file", service = "FileService", file_id:serde, err:err; "Error returned from service.");
-            return Err(map_file_service_err(&err));
+            log::error!(target: "routes::file::controllers", controller = "get_random_files", service = "FileService", count, filter_mime, err:err; "Error returned from service.");
+            return Err(Status::InternalServerError.into());
         }
     };
 
-    let read_range = match range_header.range {
-        None => ReadRange::Full,
-        Some((start, None)) => {
-            if start < 0 {
-                ReadRange::Suffix((-start) as u32)
-            } else {
-                ReadRange::Start(start as u64)
-            }
+    let files = list_files(favorite_service, sess.user.id, files).await?;
+
+    Ok(ApiResponse::new(
+        Status::Ok,
+        RandomFileList { files, count },
+    ))
+}
+
+#[get("/duplicates?<last_size>&<last_hash>&<limit>")]
+async fn get_duplicate_files(
+    #[allow(unused_variables)] sess: AuthUserSession<'_>,
+    file_service: &State<Arc<FileService>>,
+    last_size: Option<i64>,
+    last_hash: Option<i64>,
+    limit: Option<u32>,
+) -> JsonRes<DuplicateFileGroupList> {
+    let last_group = match (last_size, last_hash) {
+        (Some(last_size), Some(last_hash)) => Some((last_size, last_hash)),
+        (None, None) => None,
+        _ => {
+            return Err(Error::new_dynamic(
+                Status::BadRequest,
+                "`last_size` and `last_hash` must be provided together",
+            )
+            .with_code(ErrorCode::InvalidParameter));
         }
-        Some((start, Some(end))) => ReadRange::Range(start as u64, end as u64),
     };
 
-    let data = file_service
-        .get_file_data_by_id(file_id, read_range.clone())
-        .await;
-    let data = match data {
-        Ok(Some(data)) => data,
-        Ok(None) => {
-            return Err(Status::NotFound.into());
+    let limit = limit.unwrap_or(25);
+    let limit = u32::max(1, limit);
+    let limit = u32::min(limit, 100);
+    let groups = file_service.find_duplicate_groups(last_group, limit).await;
+
+    let groups = match groups {
+        Ok(groups) => groups,
+        Err(err) => {
+            log::error!(target: "routes::file::controllers", controller = "get_duplicate_files", service = "FileService", last_size, last_hash, limit, err:err; "Error returned from service.");
+            return Err(Status::InternalServerError.into());
         }
-        Err(err) => match err {
-            ReadError::RangeStartExceedsFileSize { start, file_size } => {
-                return Err(Error::new_dynamic(
-                    Status::RangeNotSatisfiable,
-                    format!(
-                        "the start of the range {} (inclusive) exceeds the file size {}",
-                        start, file_size
-                    ),
-                ));
-            }
-            ReadError::RangeEndExceedsFileSize { end, file_size } => {
-                return Err(Error::new_dynamic(
-                    Status::RangeNotSatisfiable,
-                    format!(
-                        "the end of the range {} (inclusive) exceeds the file size {}",
-                        end, file_size
-                    ),
-                ));
-            }
-            ReadError::Read { io_error } => {
-                log::error!(target: "routes::file::controllers", controller = "get_file_data", service = "FileService", file_id:serde, io_error:err; "Error returned from service.");
-                return Err(Status::InternalServerError.into());
-            }
-        },
     };
 
-    Ok(FileData {
-        status: match read_range {
-            ReadRange::Full => Status::Ok,
-            _ => Status::PartialContent,
-        },
+    Ok(ApiResponse::new(
+        Status::Ok,
+        DuplicateFileGroupList {
+            groups,
+            last_size,
+            last_hash,
+            limit,
+        },
+    ))
+}
+
+/// Retrieves many files at once, e.g. to show the files found by a search.
+/// Duplicate IDs are returned once.
+#[post("/batch-get", data = "<body>")]
+async fn get_files_by_ids(
+    #[allow(unused_variables)] sess: AuthUserSession<'_>,
+    file_service: &State<Arc<FileService>>,
+    body: Json<GettingFiles>,
+) -> JsonRes<FileBatch> {
+    body.validate()?;
+
+    let mut seen_ids = HashSet::with_capacity(body.ids.len());
+    let file_ids = body
+        .ids
+        .iter()
+        .copied()
+        .filter(|file_id| seen_ids.insert(*file_id))
+        .collect::<Vec<_>>();
+    let files = file_service.get_files_by_ids(&file_ids).await;
+
+    let files = match files {
+        Ok(files) => files,
+        Err(err) => {
+            log::error!(target: "routes::file::controllers", controller = "get_files_by_ids", service = "FileService", file_ids:serde, err:err; "Error returned from service.");
+            return Err(map_file_service_err(&err));
+        }
+    };
+
+    let mut files_by_id = files
+        .into_iter()
+        .map(|file| (file.id, file))
+        .collect::<HashMap<_, _>>();
+    let mut batch = FileBatch {
+        files: Vec::with_capacity(files_by_id.len()),
+        missing_ids: Vec::new(),
+    };
+
+    for file_id in file_ids {
+        match files_by_id.remove(&file_id) {
+            Some(file) => batch.files.push(file),
+            None => batch.missing_ids.push(file_id),
+        }
+    }
+
+    Ok(ApiResponse::new(Status::Ok, batch))
+}
+
+/// Streams a ZIP archive of the files with the given IDs, in that order, or of the files best matching a search query.
+/// At most `MAX_BATCH_SIZE` files are archived; missing files are skipped, and quarantined files are left out.
+/// The archive takes a download slot until it has been sent.
+#[post("/archive", data = "<body>")]
+async fn archive_files(
+    sess: AuthUserSession<'_>,
+    file_service: &State<Arc<FileService>>,
+    search_service: &State<Arc<dyn SearchService + Send + Sync>>,
+    archive_service: &State<Arc<ArchiveService>>,
+    transfer_limit_service: &State<Arc<TransferLimitService>>,
+    body: Json<ArchivingFiles<'_>>,
+) -> Result<FileData, Error> {
+    body.validate()?;
+
+    let files = match body.query {
+        Some(query) => {
+            let files = search_service
+                .search_files(
+                    query,
+                    None,
+                    None,
+                    None,
+                    None,
+                    &Default::default(),
+                    &[],
+                    &Default::default(),
+                    None,
+                    MAX_BATCH_SIZE as u32,
+                )
+                .await;
+
+            match files {
+                Ok(files) => files.files.items,
+                Err(err) => {
+                    log::error!(target: "routes::file::controllers", controller = "archive_files", service = "SearchService", query, err:err; "Error returned from service.");
+                    return Err(Status::InternalServerError.into());
+                }
+            }
+        }
+        None => {
+            let mut seen_ids = HashSet::with_capacity(body.ids.len());
+            let file_ids = body
+                .ids
+                .iter()
+                .copied()
+                .filter(|file_id| seen_ids.insert(*file_id))
+                .collect::<Vec<_>>();
+            let files = match file_service.get_files_by_ids(&file_ids).await {
+                Ok(files) => files,
+                Err(err) => {
+                    log::error!(target: "routes::file::controllers", controller = "archive_files", service = "FileService", file_ids:serde, err:err; "Error returned from service.");
+                    return Err(map_file_service_err(&err));
+                }
+            };
+
+            let mut files_by_id = files
+                .into_iter()
+                .map(|file| (file.id, file))
+                .collect::<HashMap<_, _>>();
+
+            file_ids
+                .into_iter()
+                .filter_map(|file_id| files_by_id.remove(&file_id))
+                .collect()
+        }
+    };
+
+    let mut archived_files = Vec::with_capacity(files.len());
+
+    for file in files {
+        match file_service.get_file_scan_by_id(file.id).await {
+            Ok(Some(scan)) if scan.is_quarantined() => {}
+            Ok(_) => archived_files.push(file),
+            Err(err) => {
+                let file_id = file.id;
+                log::error!(target: "routes::file::controllers", controller = "archive_files", service = "FileService", file_id:serde, err:err; "Error returned from service.");
+                return Err(map_file_service_err(&err));
+            }
+        }
+    }
+
+    let permit = transfer_limit_service
+        .try_acquire_download()
+        .ok_or_else(|| too_many_transfers(transfer_limit_service))?;
+    let data = archive_service.stream_zip(archived_files);
+
+    Ok(FileData {
+        status: Status::Ok,
+        mime: "application/zip".to_owned(),
+        etag: None,
+        disposition: Some(content_disposition(true, "files.zip")),
+        accept_ranges: false,
+        cache_control: None,
+        content_length: None,
+        content_range: None,
+        data: permit.hold_while_reading(
+            transfer_limit_service.throttle_download(Some(&sess.user.role), data),
+        ),
+    })
+}
+
+#[get("/<file_id>")]
+async fn get_file(
+    #[allow(unused_variables)] sess: AuthUserSession<'_>,
+    file_service: &State<Arc<FileService>>,
+    file_comment_service: &State<Arc<FileCommentService>>,
+    file_id: Uuid,
+) -> JsonRes<FileWithMetadata> {
+    let file = file_service.get_file_by_id(file_id).await;
+
+    let file = match file {
+        Ok(Some(file)) => file,
+        Ok(None) => {
+            return Err(Error::new_not_found(ErrorCode::FileNotFound));
+        }
+        Err(err) => {
+            log::error!(target: "routes::file::controllers", controller = "get_file", service = "FileService", file_id:serde, err:err; "Error returned from service.");
+            return Err(map_file_service_err(&err));
+        }
+    };
+
+    let metadata = file_service.get_file_metadata_by_id(file_id).await;
+
+    let metadata = match metadata {
+        Ok(metadata) => metadata,
+        Err(err) => {
+            log::error!(target: "routes::file::controllers", controller = "get_file", service = "FileService", file_id:serde, err:err; "Error returned from service.");
+            return Err(map_file_service_err(&err));
+        }
+    };
+
+    let comment_count = file_comment_service.count_comments(file_id).await;
+
+    let comment_count = match comment_count {
+        Ok(comment_count) => comment_count,
+        Err(err) => {
+            log::error!(target: "routes::file::controllers", controller = "get_file", service = "FileCommentService", file_id:serde, err:err; "Error returned from service.");
+            return Err(Status::InternalServerError.into());
+        }
+    };
+
+    Ok(ApiResponse::new(
+        Status::Ok,
+        FileWithMetadata {
+            file,
+            metadata,
+            comment_count,
+        },
+    ))
+}
+
+#[get("/<file_id>/stats")]
+async fn get_file_stats(
+    #[allow(unused_variables)] sess: AuthUserSession<'_>,
+    file_service: &State<Arc<FileService>>,
+    file_id: Uuid,
+) -> JsonRes<FileStats> {
+    let stats = file_service.get_file_stats_by_id(file_id).await;
+
+    let stats = match stats {
+        Ok(Some(stats)) => stats,
+        Ok(None) => {
+            return Err(Error::new_not_found(ErrorCode::FileNotFound));
+        }
+        Err(err) => {
+            log::error!(target: "routes::file::controllers", controller = "get_file_stats", service = "FileService", file_id:serde, err:err; "Error returned from service.");
+            return Err(map_file_service_err(&err));
+        }
+    };
+
+    Ok(ApiResponse::new(Status::Ok, stats))
+}
+
+#[get("/<file_id>/scan")]
+async fn get_file_scan(
+    #[allow(unused_variables)] sess: AuthUserSession<'_>,
+    file_service: &State<Arc<FileService>>,
+    file_id: Uuid,
+) -> JsonRes<FileScan> {
+    let scan = file_service.get_file_scan_by_id(file_id).await;
+
+    let scan = match scan {
+        Ok(Some(scan)) => scan,
+        Ok(None) => {
+            return Err(Error::new_not_found(ErrorCode::FileNotFound));
+        }
+        Err(err) => {
+            log::error!(target: "routes::file::controllers", controller = "get_file_scan", service = "FileService", file_id:serde, err:err; "Error returned from service.");
+            return Err(map_file_service_err(&err));
+        }
+    };
+
+    Ok(ApiResponse::new(Status::Ok, scan))
+}
+
+/// Retrieves the images that look similar to a file, from the most similar one.
+/// `max_distance` narrows the maximum Hamming distance between the perceptual hashes of similar images.
+#[get("/<file_id>/similar?<max_distance>&<limit>")]
+async fn get_similar_files(
+    #[allow(unused_variables)] sess: AuthUserSession<'_>,
+    file_service: &State<Arc<FileService>>,
+    file_id: Uuid,
+    max_distance: Option<u32>,
+    limit: Option<u32>,
+) -> JsonRes<SimilarFileList> {
+    let limit = limit.unwrap_or(25);
+    let limit = u32::max(1, limit);
+    let limit = u32::min(limit, 100);
+    let files = file_service
+        .get_similar_files(file_id, max_distance, limit)
+        .await;
+
+    let files = match files {
+        Ok(Some(files)) => files,
+        Ok(None) => {
+            return Err(Error::new_not_found(ErrorCode::FileNotFound));
+        }
+        Err(err) => {
+            log::error!(target: "routes::file::controllers", controller = "get_similar_files", service = "FileService", file_id:serde, max_distance, limit, err:err; "Error returned from service.");
+            return Err(map_file_service_err(&err));
+        }
+    };
+
+    Ok(ApiResponse::new(
+        Status::Ok,
+        SimilarFileList {
+            files,
+            max_distance,
+            limit,
+        },
+    ))
+}
+
+#[get("/<file_id>/attributes")]
+async fn get_file_attributes(
+    #[allow(unused_variables)] sess: AuthUserSession<'_>,
+    file_attribute_service: &State<Arc<FileAttributeService>>,
+    file_id: Uuid,
+) -> JsonRes<FileAttributes> {
+    let attributes = file_attribute_service.get_attributes(file_id).await;
+
+    let attributes = match attributes {
+        Ok(Some(attributes)) => attributes,
+        Ok(None) => {
+            return Err(Error::new_not_found(ErrorCode::FileNotFound));
+        }
+        Err(err) => {
+            log::error!(target: "routes::file::controllers", controller = "get_file_attributes", service = "FileAttributeService", file_id:serde, err:err; "Error returned from service.");
+            return Err(Status::InternalServerError.into());
+        }
+    };
+
+    Ok(ApiResponse::new(Status::Ok, FileAttributes { attributes }))
+}
+
+/// Replaces all the attributes of a file.
+#[put("/<file_id>/attributes", data = "<body>")]
+async fn set_file_attributes(
+    #[allow(unused_variables)] sess: AuthUserSession<'_>,
+    file_attribute_service: &State<Arc<FileAttributeService>>,
+    file_id: Uuid,
+    body: Json<FileAttributes>,
+) -> JsonRes<FileAttributes> {
+    body.validate()?;
+
+    let attributes = file_attribute_service
+        .set_attributes(file_id, &body.attributes)
+        .await;
+
+    let attributes = match attributes {
+        Ok(Some(attributes)) => attributes,
+        Ok(None) => {
+            return Err(Error::new_not_found(ErrorCode::FileNotFound));
+        }
+        Err(err) => {
+            log::error!(target: "routes::file::controllers", controller = "set_file_attributes", service = "FileAttributeService", file_id:serde, err:err; "Error returned from service.");
+            return Err(Status::InternalServerError.into());
+        }
+    };
+
+    Ok(ApiResponse::new(Status::Ok, FileAttributes { attributes }))
+}
+
+#[get("/<file_id>/versions")]
+async fn get_file_versions(
+    #[allow(unused_variables)] sess: AuthUserSession<'_>,
+    file_service: &State<Arc<FileService>>,
+    file_id: Uuid,
+) -> JsonRes<FileVersionList> {
+    let versions = file_service.get_file_versions(file_id).await;
+
+    let versions = match versions {
+        Ok(Some(versions)) => versions,
+        Ok(None) => {
+            return Err(Error::new_not_found(ErrorCode::FileNotFound));
+        }
+        Err(err) => {
+            log::error!(target: "routes::file::controllers", controller = "get_file_versions", service = "FileService", file_id:serde, err:err; "Error returned from service.");
+            return Err(map_file_service_err(&err));
+        }
+    };
+
+    Ok(ApiResponse::new(Status::Ok, FileVersionList { versions }))
+}
+
+/// Restores a former version of a file. The current data is kept as the newest former version.
+#[post("/<file_id>/versions/<version>/restore")]
+async fn restore_file_version(
+    #[allow(unused_variables)] sess: AuthUserSession<'_>,
+    file_service: &State<Arc<FileService>>,
+    read_ahead_service: &State<Arc<ReadAheadService>>,
+    file_id: Uuid,
+    version: i32,
+) -> JsonRes<File> {
+    let file = file_service.restore_file_version(file_id, version).await;
+
+    let file = match file {
+        Ok(Some(file)) => file,
+        Ok(None) => {
+            return Err(Error::new_not_found(ErrorCode::FileVersionNotFound));
+        }
+        Err(err) => {
+            log::error!(target: "routes::file::controllers", controller = "restore_file_version", service = "FileService", file_id:serde, version, err:err; "Error returned from service.");
+            return Err(map_file_service_err(&err));
+        }
+    };
+
+    read_ahead_service.invalidate_file(file_id);
+
+    Ok(ApiResponse::new(Status::Ok, file))
+}
+
+#[post("/<file_id>/comments", data = "<body>")]
+async fn create_comment(
+    sess: AuthUserSession<'_>,
+    file_comment_service: &State<Arc<FileCommentService>>,
+    file_id: Uuid,
+    body: Json<CreatingComment<'_>>,
+) -> JsonRes<FileComment> {
+    body.validate()?;
+
+    let comment = file_comment_service
+        .create_comment(file_id, sess.user.id, body.content)
+        .await;
+
+    let comment = match comment {
+        Ok(Some(comment)) => comment,
+        Ok(None) => {
+            return Err(Error::new_not_found(ErrorCode::FileNotFound));
+        }
+        Err(err) => {
+            log::error!(target: "routes::file::controllers", controller = "create_comment", service = "FileCommentService", file_id:serde, err:err; "Error returned from service.");
+            return Err(Status::InternalServerError.into());
+        }
+    };
+
+    Ok(ApiResponse::new(Status::Created, comment))
+}
+
+#[get("/<file_id>/comments?<last_comment_id>&<limit>")]
+async fn get_comments(
+    #[allow(unused_variables)] sess: AuthUserSession<'_>,
+    file_service: &State<Arc<FileService>>,
+    file_comment_service: &State<Arc<FileCommentService>>,
+    file_id: Uuid,
+    last_comment_id: Option<Uuid>,
+    limit: Option<u32>,
+) -> JsonRes<CommentList> {
+    match file_service.get_file_by_id(file_id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => {
+            return Err(Error::new_not_found(ErrorCode::FileNotFound));
+        }
+        Err(err) => {
+            log::error!(target: "routes::file::controllers", controller = "get_comments", service = "FileService", file_id:serde, err:err; "Error returned from service.");
+            return Err(map_file_service_err(&err));
+        }
+    }
+
+    let limit = limit.unwrap_or(25);
+    let limit = u32::max(1, limit);
+    let limit = u32::min(limit, 100);
+    let comments = file_comment_service
+        .get_comments(file_id, last_comment_id, limit)
+        .await;
+
+    let page = match comments {
+        Ok(page) => page,
+        Err(err) => {
+            log::error!(target: "routes::file::controllers", controller = "get_comments", service = "FileCommentService", file_id:serde, last_comment_id:serde, limit, err:err; "Error returned from service.");
+            return Err(Status::InternalServerError.into());
+        }
+    };
+
+    Ok(ApiResponse::new(
+        Status::Ok,
+        CommentList {
+            comments: page.items,
+            last_comment_id,
+            limit,
+            next_cursor: page.next_cursor,
+        },
+    ))
+}
+
+/// Edits a comment. Only the author of the comment can edit it.
+#[put("/<file_id>/comments/<comment_id>", data = "<body>")]
+async fn update_comment(
+    sess: AuthUserSession<'_>,
+    file_comment_service: &State<Arc<FileCommentService>>,
+    file_id: Uuid,
+    comment_id: Uuid,
+    body: Json<UpdatingComment<'_>>,
+) -> JsonRes<FileComment> {
+    body.validate()?;
+
+    let comment = file_comment_service
+        .update_comment(file_id, comment_id, sess.user.id, body.content)
+        .await;
+
+    let comment = match comment {
+        Ok(Some(comment)) => comment,
+        Ok(None) => {
+            return Err(Error::new_not_found(ErrorCode::CommentNotFound));
+        }
+        Err(err) => {
+            log::error!(target: "routes::file::controllers", controller = "update_comment", service = "FileCommentService", file_id:serde, comment_id:serde, err:err; "Error returned from service.");
+            return Err(Status::InternalServerError.into());
+        }
+    };
+
+    Ok(ApiResponse::new(Status::Ok, comment))
+}
+
+/// Removes a comment. Only the author of the comment can remove it.
+#[delete("/<file_id>/comments/<comment_id>")]
+async fn remove_comment(
+    sess: AuthUserSession<'_>,
+    file_comment_service: &State<Arc<FileCommentService>>,
+    file_id: Uuid,
+    comment_id: Uuid,
+) -> JsonRes<FileComment> {
+    let comment = file_comment_service
+        .remove_comment(file_id, comment_id, sess.user.id)
+        .await;
+
+    let comment = match comment {
+        Ok(Some(comment)) => comment,
+        Ok(None) => {
+            return Err(Error::new_not_found(ErrorCode::CommentNotFound));
+        }
+        Err(err) => {
+            log::error!(target: "routes::file::controllers", controller = "remove_comment", service = "FileCommentService", file_id:serde, comment_id:serde, err:err; "Error returned from service.");
+            return Err(Status::InternalServerError.into());
+        }
+    };
+
+    Ok(ApiResponse::new(Status::Ok, comment))
+}
+
+#[get("/<file_id>/collections?<last_collection_id>&<limit>")]
+async fn get_collections_for_file(
+    sess: AuthUserSession<'_>,
+    collection_file_pair_service: &State<Arc<CollectionFilePairService>>,
+    favorite_service: &State<Arc<FavoriteService>>,
+    file_id: Uuid,
+    last_collection_id: Option<Uuid>,
+    limit: Option<u32>,
+) -> JsonRes<CollectionList> {
+    let limit = limit.unwrap_or(25);
+    let limit = u32::max(1, limit);
+    let limit = u32::min(limit, 100);
+    let collections = collection_file_pair_service
+        .get_collections_for_file(file_id, last_collection_id, limit)
+        .await;
+
+    let page = match collections {
+        Ok(page) => page,
+        Err(err) => {
+            log::error!(target: "routes::file::controllers", controller = "get_collections_for_file", service = "CollectionFilePairService", file_id:serde, last_collection_id:serde, limit, err:err; "Error returned from service.");
+            return Err(Status::InternalServerError.into());
+        }
+    };
+
+    let collections = list_collections(favorite_service, sess.user.id, page.items).await?;
+
+    Ok(ApiResponse::new(
+        Status::Ok,
+        CollectionList {
+            collections,
+            last_collection_id,
+            limit,
+            next_cursor: page.next_cursor,
+            total: page.total,
+        },
+    ))
+}
+
+/// Reads the data of a file.
+/// Images can be shrunk to fit in `width` and `height`, and converted into `format`, which defaults to WebP.
+/// Transformed images are always served whole, regardless of the range header.
+/// Responses carry the `Cache-Control` header configured by `file_data_cache`, including partial ones.
+#[allow(clippy::too_many_arguments)]
+#[get("/<file_id>/data?<download>&<width>&<height>&<format>")]
+async fn get_file_data(
+    sess: AuthUserSession<'_>,
+    app_config: &State<AppConfig>,
+    file_service: &State<Arc<FileService>>,
+    file_view_service: &State<Arc<FileViewService>>,
+    read_ahead_service: &State<Arc<ReadAheadService>>,
+    transfer_limit_service: &State<Arc<TransferLimitService>>,
+    image_transform_service: &State<Arc<ImageTransformService>>,
+    range_header: RangeHeader,
+    file_id: Uuid,
+    download: Option<bool>,
+    width: Option<u32>,
+    height: Option<u32>,
+    format: Option<&str>,
+) -> Result<FileData, Error> {
+    let file = file_service.get_file_by_id(file_id).await;
+    let file = match file {
+        Ok(Some(file)) => file,
+        Ok(None) => {
+            return Err(Error::new_not_found(ErrorCode::FileNotFound));
+        }
+        Err(err) => {
+            log::error!(target: "routes::file::controllers", controller = "get_file", service = "FileService", file_id:serde, err:err; "Error returned from service.");
+            return Err(map_file_service_err(&err));
+        }
+    };
+
+    let mut data = if width.is_some() || height.is_some() || format.is_some() {
+        let transform =
+            parse_image_transform(image_transform_service, &file, width, height, format)?;
+
+        read_transformed_image(
+            file_service,
+            transfer_limit_service,
+            image_transform_service,
+            &sess.user.role,
+            file,
+            transform,
+            download.unwrap_or(false),
+        )
+        .await?
+    } else {
+        let read_range = read_range_of(&range_header);
+        let served_bytes = read_range.byte_count(file.size as u64);
+
+        let data = read_file_data(
+            file_service,
+            transfer_limit_service,
+            read_ahead_service,
+            sess.token,
+            Some(&sess.user.role),
+            file,
+            range_header,
+            download.unwrap_or(false),
+        )
+        .await?;
+
+        // failures to record the download or the view should not fail the read
+        if let Err(err) = file_service
+            .record_file_download(file_id, served_bytes, read_range.starts_at_beginning())
+            .await
+        {
+            log::warn!(target: "routes::file::controllers", controller = "get_file_data", service = "FileService", file_id:serde, err:err; "Failed to record file download.");
+        }
+
+        data
+    };
+
+    if let Err(err) = file_view_service
+        .record_file_view(sess.user.id, file_id)
+        .await
+    {
+        log::warn!(target: "routes::file::controllers", controller = "get_file_data", service = "FileViewService", file_id:serde, err:err; "Failed to record file view.");
+    }
+
+    data.cache_control = file_data_cache_control(&app_config.file_data_cache);
+
+    Ok(data)
+}
+
+/// Builds the `Cache-Control` header of file data, or `None` if caching is not configured.
+fn file_data_cache_control(config: &AppFileDataCache) -> Option<String> {
+    if config.max_age == 0 {
+        return None;
+    }
+
+    let visibility = if config.public { "public" } else { "private" };
+
+    Some(format!("{}, max-age={}", visibility, config.max_age))
+}
+
+fn read_range_of(range_header: &RangeHeader) -> ReadRange {
+    match range_header.range {
+        None => ReadRange::Full,
+        Some((start, None)) => {
+            if start < 0 {
+                ReadRange::Suffix((-start) as u32)
+            } else {
+                ReadRange::Start(start as u64)
+            }
+        }
+        Some((start, Some(end))) => ReadRange::Range(start as u64, end as u64),
+    }
+}
+
+/// Reads the data of the given file into a data response, honoring the range header.
+/// The `session_key` identifies the reader for read-ahead buffering.
+/// A download slot is taken until the response has been sent, or the read is rejected if none is free.
+/// The download is slowed down to the download speed of the `role` of the reader, or the global one for anonymous readers.
+/// Quarantined files are refused with `403 Forbidden`.
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn read_file_data(
+    file_service: &FileService,
+    transfer_limit_service: &TransferLimitService,
+    read_ahead_service: &ReadAheadService,
+    session_key: &str,
+    role: Option<&str>,
+    file: File,
+    range_header: RangeHeader,
+    download: bool,
+) -> Result<FileData, Error> {
+    let file_id = file.id;
+
+    ensure_not_quarantined(file_service, file_id).await?;
+
+    let read_range = read_range_of(&range_header);
+    let permit = transfer_limit_service
+        .try_acquire_download()
+        .ok_or_else(|| too_many_transfers(transfer_limit_service))?;
+
+    let data = read_ahead_service
+        .read(session_key, file_id, file.size as u64, read_range.clone())
+        .await;
+    let data = match data {
+        Ok(Some(data)) => data,
+        Ok(None) => {
+            return Err(Error::new_not_found(ErrorCode::FileNotFound));
+        }
+        Err(err) => match err {
+            ReadError::RangeStartExceedsFileSize { start, file_size } => {
+                return Err(Error::new_dynamic(
+                    Status::RangeNotSatisfiable,
+                    format!(
+                        "the start of the range {} (inclusive) exceeds the file size {}",
+                        start, file_size
+                    ),
+                ));
+            }
+            ReadError::RangeEndExceedsFileSize { end, file_size } => {
+                return Err(Error::new_dynamic(
+                    Status::RangeNotSatisfiable,
+                    format!(
+                        "the end of the range {} (inclusive) exceeds the file size {}",
+                        end, file_size
+                    ),
+                ));
+            }
+            ReadError::Read { io_error } => {
+                log::error!(target: "routes::file::controllers", controller = "read_file_data", service = "ReadAheadService", file_id:serde, io_error:err; "Error returned from service.");
+                return Err(Status::InternalServerError.into());
+            }
+        },
+    };
+
+    let file_size = file.size as u64;
+    let (status, content_range) = match read_range {
+        ReadRange::Full => (Status::Ok, None),
+        _ => (
+            Status::PartialContent,
+            Some(read_range.content_range(file_size)),
+        ),
+    };
+
+    Ok(FileData {
+        status,
+        etag: Some(file_etag(&file)),
+        disposition: Some(content_disposition(download, &file.name)),
+        accept_ranges: true,
+        cache_control: None,
+        content_length: Some(read_range.byte_count(file_size)),
+        content_range,
+        mime: file.mime,
+        data: permit.hold_while_reading(transfer_limit_service.throttle_download(role, data)),
+    })
+}
+
+/// Refuses to serve the data of a quarantined file with `403 Forbidden`.
+async fn ensure_not_quarantined(file_service: &FileService, file_id: Uuid) -> Result<(), Error> {
+    match file_service.get_file_scan_by_id(file_id).await {
+        Ok(Some(scan)) if scan.is_quarantined() => Err(Error::new_dynamic(
+            Status::Forbidden,
+            "the file has been quarantined for containing malware",
+        )
+        .with_code(ErrorCode::FileQuarantined)),
+        Ok(_) => Ok(()),
+        Err(err) => {
+            log::error!(target: "routes::file::controllers", controller = "read_file_data", service = "FileService", file_id:serde, err:err; "Error returned from service.");
+            Err(map_file_service_err(&err))
+        }
+    }
+}
+
+/// Validates the parameters of an image transform.
+/// Fails with `501 Not Implemented` if image transforms are disabled, and `422 Unprocessable Entity` if the file is not an image.
+fn parse_image_transform(
+    image_transform_service: &ImageTransformService,
+    file: &File,
+    width: Option<u32>,
+    height: Option<u32>,
+    format: Option<&str>,
+) -> Result<ImageTransform, Error> {
+    if !image_transform_service.is_enabled() {
+        return Err(Error::new_static(
+            Status::NotImplemented,
+            "image transforms are disabled",
+        ));
+    }
+
+    let max_dimension = image_transform_service.max_dimension();
+    let mut errors = FieldErrors::default();
+
+    for (field, dimension) in [("width", width), ("height", height)] {
+        if let Some(dimension) = dimension {
+            if dimension == 0 || max_dimension < dimension {
+                errors.add(field, format!("should be between 1 and {}", max_dimension));
+            }
+        }
+    }
+
+    let format = match format {
+        Some(format) => match ImageFormat::from_name(format) {
+            Some(format) => format,
+            None => {
+                errors.add("format", "should be one of `webp`, `jpeg` and `png`");
+                ImageFormat::Webp
+            }
+        },
+        None => ImageFormat::Webp,
+    };
+
+    errors.into_result()?;
+
+    if !file.mime.starts_with("image/") {
+        return Err(Error::new_static(
+            Status::UnprocessableEntity,
+            "only images can be transformed",
+        )
+        .with_code(ErrorCode::NotAnImage));
+    }
+
+    Ok(ImageTransform {
+        width: width.map(|width| image_transform_service.snap_dimension(width)),
+        height: height.map(|height| image_transform_service.snap_dimension(height)),
+        format,
+    })
+}
+
+/// Transforms an image into a data response, like [`read_file_data`] does for the original data.
+async fn read_transformed_image(
+    file_service: &FileService,
+    transfer_limit_service: &TransferLimitService,
+    image_transform_service: &ImageTransformService,
+    role: &str,
+    file: File,
+    transform: ImageTransform,
+    download: bool,
+) -> Result<FileData, Error> {
+    let file_id = file.id;
+
+    ensure_not_quarantined(file_service, file_id).await?;
+
+    let permit = transfer_limit_service
+        .try_acquire_download()
+        .ok_or_else(|| too_many_transfers(transfer_limit_service))?;
+
+    let data = match image_transform_service
+        .open_transformed(&file, &transform)
+        .await
+    {
+        Ok(data) => data,
+        Err(err @ ImageTransformServiceError::FFmpeg { .. }) => {
+            // most likely an image format that ffmpeg does not understand
+            log::warn!(target: "routes::file::controllers", controller = "read_transformed_image", service = "ImageTransformService", file_id:serde, err:err; "Failed to transform an image.");
+            return Err(Error::new_static(
+                Status::UnprocessableEntity,
+                "the image could not be transformed",
+            )
+            .with_code(ErrorCode::NotAnImage));
+        }
+        Err(err) => {
+            log::error!(target: "routes::file::controllers", controller = "read_transformed_image", service = "ImageTransformService", file_id:serde, err:err; "Error returned from service.");
+            return Err(Status::InternalServerError.into());
+        }
+    };
+
+    let stem = match file.name.rfind('.') {
+        Some(index) if 0 < index => &file.name[..index],
+        _ => &file.name,
+    };
+
+    Ok(FileData {
+        status: Status::Ok,
+        etag: Some(format!(
+            "\"{}-{:x}-{:016x}-{}\"",
+            file.id.simple(),
+            file.size,
+            file.hash as u64,
+            transform.key()
+        )),
+        disposition: Some(content_disposition(
+            download,
+            &format!("{}.{}", stem, transform.format.name()),
+        )),
+        accept_ranges: false,
+        cache_control: None,
+        content_length: None,
+        content_range: None,
+        mime: transform.format.mime().to_owned(),
+        data: permit.hold_while_reading(
+            transfer_limit_service.throttle_download(Some(role), Box::pin(data)),
+        ),
+    })
+}
+
+/// Rejects a transfer of file data while all the slots for it are taken.
+pub(crate) fn too_many_transfers(transfer_limit_service: &TransferLimitService) -> Error {
+    Error::new_static(
+        Status::ServiceUnavailable,
+        "too many transfers are in progress; retry later",
+    )
+    .with_code(ErrorCode::TooManyTransfers)
+    .with_retry_after(transfer_limit_service.retry_after())
+}
+
+/// Reads the metadata of the data of a file, as `GET /files/<file_id>/data` would send it.
+/// Quarantined files are refused the same way, and the same `Cache-Control` header is sent.
+#[head("/<file_id>/data")]
+async fn get_file_data_head(
+    #[allow(unused_variables)] sess: AuthUserSession<'_>,
+    app_config: &State<AppConfig>,
+    file_service: &State<Arc<FileService>>,
+    file_id: Uuid,
+) -> Result<FileDataHead, Error> {
+    let file = file_service.get_file_by_id(file_id).await;
+    let file = match file {
+        Ok(Some(file)) => file,
+        Ok(None) => {
+            return Err(Error::new_not_found(ErrorCode::FileNotFound));
+        }
+        Err(err) => {
+            log::error!(target: "routes::file::controllers", controller = "get_file_data_head", service = "FileService", file_id:serde, err:err; "Error returned from service.");
+            return Err(map_file_service_err(&err));
+        }
+    };
+
+    ensure_not_quarantined(file_service, file_id).await?;
+
+    Ok(FileDataHead {
+        etag: file_etag(&file),
+        cache_control: file_data_cache_control(&app_config.file_data_cache),
         mime: file.mime,
-        data,
+        size: file.size as u64,
+    })
+}
+
+/// Builds a strong entity tag from the ID, the size and the content hash of the file.
+/// The hash is only 32 bits wide, so it cannot tell the contents of files apart by itself.
+fn file_etag(file: &File) -> String {
+    format!(
+        "\"{}-{:x}-{:016x}\"",
+        file.id.simple(),
+        file.size,
+        file.hash as u64
+    )
+}
+
+fn parse_rendition_profile(profile: &str) -> Result<RenditionProfile, Error> {
+    RenditionProfile::from_name(profile).ok_or_else(|| {
+        Error::new_dynamic(
+            Status::BadRequest,
+            format!(
+                "unknown rendition profile `{}`; expected one of mp4, webm, mp3, hls",
+                profile
+            ),
+        )
+        .with_code(ErrorCode::InvalidParameter)
+    })
+}
+
+fn check_rendition_ready(job: &TranscodeJob) -> Result<(), Error> {
+    match job.status.as_str() {
+        "completed" => Ok(()),
+        "failed" => Err(Error::new_dynamic(
+            Status::UnprocessableEntity,
+            format!(
+                "rendition failed: {}",
+                job.error.as_deref().unwrap_or("unknown error")
+            ),
+        )
+        .with_code(ErrorCode::RenditionFailed)),
+        _ => Err(
+            Error::new_dynamic(Status::Conflict, "rendition is not ready yet")
+                .with_code(ErrorCode::RenditionNotReady),
+        ),
+    }
+}
+
+#[get("/<file_id>/renditions")]
+async fn get_renditions(
+    #[allow(unused_variables)] sess: AuthUserSession<'_>,
+    file_service: &State<Arc<FileService>>,
+    transcode_service: &State<Arc<TranscodeService>>,
+    file_id: Uuid,
+) -> JsonRes<RenditionList> {
+    let file = file_service.get_file_by_id(file_id).await;
+
+    match file {
+        Ok(Some(_)) => {}
+        Ok(None) => {
+            return Err(Error::new_not_found(ErrorCode::FileNotFound));
+        }
+        Err(err) => {
+            log::error!(target: "routes::file::controllers", controller = "get_renditions", service = "FileService", file_id:serde, err:err; "Error returned from service.");
+            return Err(map_file_service_err(&err));
+        }
+    }
+
+    let renditions = transcode_service.get_renditions(file_id).await;
+
+    let renditions = match renditions {
+        Ok(renditions) => renditions,
+        Err(err) => {
+            log::error!(target: "routes::file::controllers", controller = "get_renditions", service = "TranscodeService", file_id:serde, err:err; "Error returned from service.");
+            return Err(Status::InternalServerError.into());
+        }
+    };
+
+    Ok(ApiResponse::new(Status::Ok, RenditionList { renditions }))
+}
+
+#[post("/<file_id>/renditions/<profile>")]
+async fn request_rendition(
+    #[allow(unused_variables)] sess: AuthUserSession<'_>,
+    transcode_service: &State<Arc<TranscodeService>>,
+    file_id: Uuid,
+    profile: &str,
+) -> JsonRes<TranscodeJob> {
+    let profile = parse_rendition_profile(profile)?;
+    let job = transcode_service.request_rendition(file_id, profile).await;
+
+    let job = match job {
+        Ok(Some(job)) => job,
+        Ok(None) => {
+            return Err(Error::new_not_found(ErrorCode::FileNotFound));
+        }
+        Err(err) => {
+            let profile = profile.name();
+            log::error!(target: "routes::file::controllers", controller = "request_rendition", service = "TranscodeService", file_id:serde, profile, err:err; "Error returned from service.");
+            return Err(Status::InternalServerError.into());
+        }
+    };
+
+    let status = match job.status.as_str() {
+        "completed" => Status::Ok,
+        _ => Status::Accepted,
+    };
+
+    Ok(ApiResponse::new(status, job))
+}
+
+#[get("/<file_id>/renditions/<profile>")]
+async fn get_rendition_data(
+    #[allow(unused_variables)] sess: AuthUserSession<'_>,
+    transcode_service: &State<Arc<TranscodeService>>,
+    file_id: Uuid,
+    profile: &str,
+) -> Result<FileData, Error> {
+    let profile = parse_rendition_profile(profile)?;
+    let job = transcode_service.get_rendition(file_id, profile).await;
+
+    let job = match job {
+        Ok(Some(job)) => job,
+        Ok(None) => {
+            return Err(Error::new_not_found(ErrorCode::RenditionNotFound));
+        }
+        Err(err) => {
+            let profile = profile.name();
+            log::error!(target: "routes::file::controllers", controller = "get_rendition_data", service = "TranscodeService", file_id:serde, profile, err:err; "Error returned from service.");
+            return Err(Status::InternalServerError.into());
+        }
+    };
+
+    check_rendition_ready(&job)?;
+
+    let data = transcode_service.open_rendition(file_id, profile).await;
+
+    let data = match data {
+        Ok(Some(data)) => data,
+        Ok(None) => {
+            return Err(Error::new_not_found(ErrorCode::RenditionNotFound));
+        }
+        Err(err) => {
+            let profile = profile.name();
+            log::error!(target: "routes::file::controllers", controller = "get_rendition_data", service = "TranscodeService", file_id:serde, profile, err:err; "Error returned from service.");
+            return Err(Status::InternalServerError.into());
+        }
+    };
+
+    Ok(FileData {
+        status: Status::Ok,
+        mime: profile.mime().to_owned(),
+        etag: None,
+        disposition: None,
+        accept_ranges: false,
+        cache_control: None,
+        content_length: None,
+        content_range: None,
+        data: Box::pin(data),
+    })
+}
+
+#[get("/<file_id>/hls/playlist.m3u8")]
+async fn get_hls_playlist(
+    #[allow(unused_variables)] sess: AuthUserSession<'_>,
+    transcode_service: &State<Arc<TranscodeService>>,
+    file_id: Uuid,
+) -> Result<FileData, Error> {
+    get_hls_file(transcode_service, file_id, HLS_PLAYLIST_NAME).await
+}
+
+#[get("/<file_id>/hls/<segment>", rank = 2)]
+async fn get_hls_segment(
+    #[allow(unused_variables)] sess: AuthUserSession<'_>,
+    transcode_service: &State<Arc<TranscodeService>>,
+    file_id: Uuid,
+    segment: &str,
+) -> Result<FileData, Error> {
+    get_hls_file(transcode_service, file_id, segment).await
+}
+
+async fn get_hls_file(
+    transcode_service: &TranscodeService,
+    file_id: Uuid,
+    name: &str,
+) -> Result<FileData, Error> {
+    let job = transcode_service
+        .get_rendition(file_id, RenditionProfile::Hls)
+        .await;
+
+    let job = match job {
+        Ok(Some(job)) => job,
+        Ok(None) => {
+            return Err(Error::new_not_found(ErrorCode::RenditionNotFound));
+        }
+        Err(err) => {
+            log::error!(target: "routes::file::controllers", controller = "get_hls_file", service = "TranscodeService", file_id:serde, name, err:err; "Error returned from service.");
+            return Err(Status::InternalServerError.into());
+        }
+    };
+
+    check_rendition_ready(&job)?;
+
+    let data = transcode_service.open_hls_file(file_id, name).await;
+
+    let data = match data {
+        Ok(Some(data)) => data,
+        Ok(None) => {
+            return Err(Error::new_not_found(ErrorCode::RenditionNotFound));
+        }
+        Err(err) => {
+            log::error!(target: "routes::file::controllers", controller = "get_hls_file", service = "TranscodeService", file_id:serde, name, err:err; "Error returned from service.");
+            return Err(Status::InternalServerError.into());
+        }
+    };
+
+    let mime = if name == HLS_PLAYLIST_NAME {
+        RenditionProfile::Hls.mime()
+    } else {
+        "video/mp2t"
+    };
+
+    Ok(FileData {
+        status: Status::Ok,
+        mime: mime.to_owned(),
+        etag: None,
+        disposition: None,
+        accept_ranges: false,
+        cache_control: None,
+        content_length: None,
+        content_range: None,
+        data: Box::pin(data),
     })
 }
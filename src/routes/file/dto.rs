@@ -1,4 +1,14 @@
-use crate::db::models::File;
+use crate::{
+    db::models::{File, FileComment, FileVersion, TranscodeJob},
+    dto::ErrorBody,
+    services::{
+        DuplicateFileGroup, FileFacets, FileMetadata, FileMetadataFilter, SimilarFile, TagFilter,
+    },
+    validation::{
+        FieldErrors, Validate, MAX_ATTRIBUTE_KEY_LENGTH, MAX_ATTRIBUTE_VALUE_LENGTH,
+        MAX_BATCH_SIZE, MAX_COMMENT_LENGTH, MAX_FILE_ATTRIBUTES, MAX_SEARCH_LIMIT,
+    },
+};
 use chrono::NaiveDateTime;
 use rocket::{
     http::{Header, Status},
@@ -6,16 +16,14 @@ use rocket::{
     Request, Response,
 };
 use serde::{Deserialize, Serialize};
-use std::pin::Pin;
+use std::{
+    collections::{BTreeMap, HashSet},
+    io::Cursor,
+    pin::Pin,
+};
 use tokio::io::AsyncRead;
 use uuid::Uuid;
 
-#[derive(Serialize, Deserialize)]
-pub struct CreatingFile<'a> {
-    pub name: &'a str,
-    pub mime: Option<&'a str>,
-}
-
 #[derive(Serialize, Deserialize)]
 pub struct SearchingFile<'a> {
     pub query: &'a str,
@@ -23,40 +31,404 @@ pub struct SearchingFile<'a> {
     pub filter_size: Option<(u32, u32)>,
     pub filter_hash: Option<u32>,
     pub filter_uploaded_at: Option<(NaiveDateTime, NaiveDateTime)>,
+    #[serde(default)]
+    pub filter_metadata: FileMetadataFilter,
+    #[serde(default)]
+    pub filter_tags: Vec<TagFilter>,
+    /// The attributes files should have, by key. Only filterable attributes can be given.
+    #[serde(default)]
+    pub filter_attributes: BTreeMap<String, String>,
+    /// The cursor returned with the previous page, or `None` for the first page.
+    #[serde(default)]
+    pub cursor: Option<&'a str>,
+    #[serde(default)]
+    pub limit: Option<u32>,
+}
+
+impl Validate for SearchingFile<'_> {
+    fn validate_fields(&self, errors: &mut FieldErrors) {
+        errors.check_range("filter_size", self.filter_size.as_ref());
+        errors.check_range("filter_uploaded_at", self.filter_uploaded_at.as_ref());
+        errors.check_limit("limit", self.limit, MAX_SEARCH_LIMIT);
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct GettingFiles {
+    pub ids: Vec<Uuid>,
+}
+
+impl Validate for GettingFiles {
+    fn validate_fields(&self, errors: &mut FieldErrors) {
+        if MAX_BATCH_SIZE < self.ids.len() {
+            errors.add("ids", format!("should have at most {} IDs", MAX_BATCH_SIZE));
+        }
+    }
+}
+
+/// The files to archive: either `ids`, or the files matching the search `query`.
+#[derive(Serialize, Deserialize)]
+pub struct ArchivingFiles<'a> {
+    #[serde(default)]
+    pub ids: Vec<Uuid>,
+    #[serde(default)]
+    pub query: Option<&'a str>,
+}
+
+impl Validate for ArchivingFiles<'_> {
+    fn validate_fields(&self, errors: &mut FieldErrors) {
+        if MAX_BATCH_SIZE < self.ids.len() {
+            errors.add("ids", format!("should have at most {} IDs", MAX_BATCH_SIZE));
+        }
+
+        if self.ids.is_empty() == self.query.is_none() {
+            errors.add("ids", "either `ids` or `query` should be given");
+        }
+    }
+}
+
+/// Staging files to promote at once.
+#[derive(Serialize, Deserialize)]
+pub struct PromotingFiles {
+    pub staging_file_ids: Vec<Uuid>,
+}
+
+impl Validate for PromotingFiles {
+    fn validate_fields(&self, errors: &mut FieldErrors) {
+        if MAX_BATCH_SIZE < self.staging_file_ids.len() {
+            errors.add(
+                "staging_file_ids",
+                format!("should have at most {} IDs", MAX_BATCH_SIZE),
+            );
+        }
+    }
+}
+
+/// The outcome of promoting one staging file of a batch.
+/// Exactly one of `file` and `error` is set, and `status` is what promoting it alone would have responded with.
+#[derive(Serialize)]
+pub struct FilePromotion {
+    pub staging_file_id: Uuid,
+    pub status: u16,
+    pub file: Option<File>,
+    pub error: Option<ErrorBody>,
+}
+
+/// The outcomes of a batch promotion, one per distinct staging file ID in the request order.
+#[derive(Serialize)]
+pub struct FilePromotionBatch {
+    pub results: Vec<FilePromotion>,
+}
+
+/// The files found for a batch request, in the order of the requested IDs.
+#[derive(Serialize, Deserialize)]
+pub struct FileBatch {
+    pub files: Vec<File>,
+    /// The requested IDs that no file has.
+    pub missing_ids: Vec<Uuid>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct FileWithMetadata {
+    #[serde(flatten)]
+    pub file: File,
+    pub metadata: Option<FileMetadata>,
+    pub comment_count: i64,
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct FileSearchResult {
     pub files: Vec<File>,
+    /// The number of matching files per MIME type, tag and size bucket.
+    pub facets: FileFacets,
+    /// The cursor to fetch the next page with, or `None` if this is the last page.
+    pub next_cursor: Option<String>,
+}
+
+/// A file in a list, along with whether the requesting user has marked it as a favorite.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ListedFile {
+    #[serde(flatten)]
+    pub file: File,
+    pub is_favorite: bool,
+}
+
+impl ListedFile {
+    pub fn from_files(files: Vec<File>, favorite_file_ids: &HashSet<Uuid>) -> Vec<Self> {
+        files
+            .into_iter()
+            .map(|file| Self {
+                is_favorite: favorite_file_ids.contains(&file.id),
+                file,
+            })
+            .collect()
+    }
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct FileList {
-    pub files: Vec<File>,
+    pub files: Vec<ListedFile>,
     pub last_file_id: Option<Uuid>,
     pub limit: u32,
+    /// The cursor to fetch the next page with, or `None` if this is the last page.
+    pub next_cursor: Option<Uuid>,
+    /// The number of all items, if `with_total` has been requested.
+    pub total: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RecentFileList {
+    pub files: Vec<ListedFile>,
+    pub limit: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RandomFileList {
+    pub files: Vec<ListedFile>,
+    pub count: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SimilarFileList {
+    pub files: Vec<SimilarFile>,
+    pub max_distance: Option<u32>,
+    pub limit: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct DuplicateFileGroupList {
+    pub groups: Vec<DuplicateFileGroup>,
+    pub last_size: Option<i64>,
+    pub last_hash: Option<i64>,
+    pub limit: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct FileAttributes {
+    pub attributes: BTreeMap<String, String>,
+}
+
+impl Validate for FileAttributes {
+    fn validate_fields(&self, errors: &mut FieldErrors) {
+        if MAX_FILE_ATTRIBUTES < self.attributes.len() {
+            errors.add(
+                "attributes",
+                format!("should have at most {} attributes", MAX_FILE_ATTRIBUTES),
+            );
+        }
+
+        for (key, value) in &self.attributes {
+            // keys become field names in the search index, so they are kept to a safe set of characters
+            let is_valid_key = !key.is_empty()
+                && key.len() <= MAX_ATTRIBUTE_KEY_LENGTH
+                && key
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+
+            if !is_valid_key {
+                errors.add(
+                    "attributes",
+                    format!(
+                        "key `{}` should be 1 to {} letters, digits, `_` or `-`",
+                        key, MAX_ATTRIBUTE_KEY_LENGTH
+                    ),
+                );
+            }
+
+            errors.check_max_length("attributes", value, MAX_ATTRIBUTE_VALUE_LENGTH);
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CreatingComment<'a> {
+    pub content: &'a str,
+}
+
+impl Validate for CreatingComment<'_> {
+    fn validate_fields(&self, errors: &mut FieldErrors) {
+        errors.check_text("content", self.content, MAX_COMMENT_LENGTH);
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct UpdatingComment<'a> {
+    pub content: &'a str,
+}
+
+impl Validate for UpdatingComment<'_> {
+    fn validate_fields(&self, errors: &mut FieldErrors) {
+        errors.check_text("content", self.content, MAX_COMMENT_LENGTH);
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CommentList {
+    pub comments: Vec<FileComment>,
+    pub last_comment_id: Option<Uuid>,
+    pub limit: u32,
+    /// The cursor to fetch the next page with, or `None` if this is the last page.
+    pub next_cursor: Option<Uuid>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct FileVersionList {
+    /// The former versions of the file, newest first.
+    pub versions: Vec<FileVersion>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RenditionList {
+    pub renditions: Vec<TranscodeJob>,
 }
 
 pub struct FileData {
     pub status: Status,
     pub mime: String,
+    pub etag: Option<String>,
+    pub disposition: Option<String>,
+    /// Whether the data can also be read in ranges, advertised with `Accept-Ranges`.
+    pub accept_ranges: bool,
+    pub cache_control: Option<String>,
+    /// The number of bytes in the data, sent as `Content-Length` if known.
+    pub content_length: Option<u64>,
+    /// The range of the file the data covers, sent as `Content-Range` with `206 Partial Content`.
+    pub content_range: Option<String>,
     pub data: Pin<Box<dyn AsyncRead + Send>>,
 }
 
 #[rocket::async_trait]
 impl<'r> Responder<'r, 'static> for FileData {
-    fn respond_to(self, _: &'r Request<'_>) -> Result<'static> {
-        let range_unit = if self.status.code == 206 {
-            "bytes"
-        } else {
-            "none"
-        };
+    fn respond_to(self, request: &'r Request<'_>) -> Result<'static> {
+        // the client already has the data if it holds the same entity tag, whatever the range is
+        if let (Some(etag), Some(if_none_match)) =
+            (&self.etag, request.headers().get_one("If-None-Match"))
+        {
+            if etag_matches(if_none_match, etag) {
+                let mut response = Response::build();
+                response
+                    .header(Header::new("ETag", etag.clone()))
+                    .status(Status::NotModified);
+
+                if let Some(cache_control) = self.cache_control {
+                    response.header(Header::new("Cache-Control", cache_control));
+                }
+
+                return response.ok();
+            }
+        }
+
+        let range_unit = if self.accept_ranges { "bytes" } else { "none" };
 
-        Response::build()
+        let mut response = Response::build();
+        response
             .header(Header::new("Accept-Ranges", range_unit))
             .header(Header::new("Content-Type", self.mime))
-            .status(self.status)
-            .streamed_body(ReaderStream::one(self.data))
+            .status(self.status);
+
+        if let Some(etag) = self.etag {
+            response.header(Header::new("ETag", etag));
+        }
+
+        if let Some(content_length) = self.content_length {
+            response.header(Header::new("Content-Length", content_length.to_string()));
+        }
+
+        if let Some(content_range) = self.content_range {
+            response.header(Header::new("Content-Range", content_range));
+        }
+
+        if let Some(disposition) = self.disposition {
+            response.header(Header::new("Content-Disposition", disposition));
+        }
+
+        if let Some(cache_control) = self.cache_control {
+            response.header(Header::new("Cache-Control", cache_control));
+        }
+
+        response.streamed_body(ReaderStream::one(self.data)).ok()
+    }
+}
+
+/// Tells whether an `If-None-Match` header value matches `etag`, comparing the tags weakly.
+fn etag_matches(if_none_match: &str, etag: &str) -> bool {
+    let etag = etag.trim_start_matches("W/");
+
+    if_none_match.trim() == "*"
+        || if_none_match
+            .split(',')
+            .any(|candidate| candidate.trim().trim_start_matches("W/") == etag)
+}
+
+/// Builds a `Content-Disposition` header value for the given filename.
+///
+/// The `filename` parameter carries an ASCII-only fallback, and `filename*` carries the
+/// RFC 5987-encoded original name for clients that understand it.
+pub fn content_disposition(attachment: bool, filename: &str) -> String {
+    let disposition_type = if attachment { "attachment" } else { "inline" };
+    let fallback = filename
+        .chars()
+        .map(|c| match c {
+            '"' | '\\' => '_',
+            c if c.is_ascii() && !c.is_ascii_control() => c,
+            _ => '_',
+        })
+        .collect::<String>();
+    let mut encoded = String::with_capacity(filename.len());
+
+    for byte in filename.bytes() {
+        match byte {
+            b'A'..=b'Z'
+            | b'a'..=b'z'
+            | b'0'..=b'9'
+            | b'!'
+            | b'#'
+            | b'$'
+            | b'&'
+            | b'+'
+            | b'-'
+            | b'.'
+            | b'^'
+            | b'_'
+            | b'`'
+            | b'|'
+            | b'~' => encoded.push(byte as char),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+
+    format!(
+        "{}; filename=\"{}\"; filename*=UTF-8''{}",
+        disposition_type, fallback, encoded
+    )
+}
+
+/// Metadata-only counterpart of [`FileData`], used to answer `HEAD` requests without opening a reader.
+pub struct FileDataHead {
+    pub mime: String,
+    pub size: u64,
+    pub etag: String,
+    pub cache_control: Option<String>,
+}
+
+#[rocket::async_trait]
+impl<'r> Responder<'r, 'static> for FileDataHead {
+    fn respond_to(self, _: &'r Request<'_>) -> Result<'static> {
+        let mut response = Response::build();
+        response
+            .header(Header::new("Accept-Ranges", "bytes"))
+            .header(Header::new("Content-Type", self.mime))
+            .header(Header::new("Content-Length", self.size.to_string()))
+            .header(Header::new("ETag", self.etag))
+            .status(Status::Ok);
+
+        if let Some(cache_control) = self.cache_control {
+            response.header(Header::new("Cache-Control", cache_control));
+        }
+
+        // The body is empty, but the preset size makes the server report the real file size.
+        response
+            .sized_body(self.size as usize, Cursor::new(Vec::new()))
             .ok()
     }
 }
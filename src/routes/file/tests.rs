@@ -1,18 +1,39 @@
-use super::dto::FileList;
+use super::dto::{
+    ArchivingFiles, CommentList, DuplicateFileGroupList, FileAttributes, FileBatch, FileList,
+    FileSearchResult, FileVersionList, FileWithMetadata, GettingFiles, PromotingFiles,
+    RandomFileList, RecentFileList, RenditionList, SearchingFile, SimilarFileList,
+};
 use crate::{
-    db::models::File,
-    services::{AuthService, FileService, ReadRange, StagingFileService, UserService},
+    config::{AppTransferRates, InfectedFileAction, SearchBackend},
+    db::models::{File, FileComment, TranscodeJob},
+    routes::{collection::dto::CollectionList, user::dto::FavoriteList},
+    services::{
+        AuthService, CollectionFilePairService, CollectionService, FileDriver, FileFilter,
+        FileService, FileSort, FileStats, ReadRange, SortDirection, StagingFileService, TagFilter,
+        TagService, UserRole, UserService,
+    },
     test::{
-        create_test_rocket_instance,
-        helpers::{create_file, create_filled_staging_file, create_initial_user},
+        create_test_rocket_instance, create_test_rocket_instance_with_config,
+        create_test_rocket_instance_with_file_driver, create_test_rocket_instance_with_options,
+        helpers::{
+            create_file, create_filled_staging_file, create_initial_user, create_user,
+            unlist_collections, unlist_files,
+        },
+        TestFileDriver,
     },
 };
 use rocket::{
+    data::ToByteUnit,
     http::{Accept, ContentType, Header, Status},
     local::asynchronous::Client,
 };
-use std::sync::Arc;
-use tokio::io::AsyncReadExt;
+use std::{
+    collections::BTreeMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use uuid::Uuid;
 
 #[rocket::async_test]
 async fn test_create_file() {
@@ -70,7 +91,7 @@ async fn test_create_file() {
 }
 
 #[rocket::async_test]
-async fn test_remove_file() {
+async fn test_create_files() {
     let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
     let client = Client::tracked(rocket).await.unwrap();
     let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
@@ -81,93 +102,105 @@ async fn test_remove_file() {
     let (_initial_user, initial_user_session) =
         create_initial_user(auth_service, user_service).await;
 
-    let file = create_file(
+    let first_staging_file = create_filled_staging_file(
         &client,
         staging_file_service,
-        &file_service,
         &initial_user_session,
-        "file",
-        Some("video/mp4"),
-        "file content",
+        "first",
+        Some("text/plain"),
+        "first content",
     )
     .await;
+    let second_staging_file = create_filled_staging_file(
+        &client,
+        staging_file_service,
+        &initial_user_session,
+        "second",
+        Some("text/plain"),
+        "second content",
+    )
+    .await;
+    let missing_id = Uuid::new_v4();
 
     let response = client
-        .delete(format!("/files/{}", file.id))
+        .post("/files/batch")
         .header(Accept::JSON)
         .header(ContentType::JSON)
         .header(Header::new(
             "Authorization",
             format!("Bearer {}", initial_user_session.token),
         ))
+        .body(
+            serde_json::to_string(&PromotingFiles {
+                staging_file_ids: vec![
+                    first_staging_file.id,
+                    missing_id,
+                    second_staging_file.id,
+                    first_staging_file.id,
+                ],
+            })
+            .unwrap(),
+        )
         .dispatch()
         .await;
 
     let status = response.status();
-    let removed_file = response.into_json::<File>().await.unwrap();
+    let body = response.into_json::<serde_json::Value>().await.unwrap();
+    let results = body["results"].as_array().unwrap();
 
     assert_eq!(status, Status::Ok);
-    assert_eq!(removed_file, file);
+    assert_eq!(results.len(), 3);
 
-    let raw_removed_file = file_service.get_file_by_id(removed_file.id).await.unwrap();
+    assert_eq!(results[0]["status"], 201);
+    assert_eq!(results[0]["file"]["name"], "first");
+    assert!(results[0]["error"].is_null());
 
-    assert_eq!(raw_removed_file, None);
+    assert_eq!(results[1]["staging_file_id"], missing_id.to_string());
+    assert_eq!(results[1]["status"], 404);
+    assert!(results[1]["file"].is_null());
+    assert_eq!(results[1]["error"]["error_code"], "STAGING_FILE_NOT_FOUND");
 
-    let raw_removed_file_data = file_service
-        .get_file_data_by_id(removed_file.id, ReadRange::Full)
+    assert_eq!(results[2]["status"], 201);
+    assert_eq!(results[2]["file"]["name"], "second");
+
+    let created_file = serde_json::from_value::<File>(results[2]["file"].clone()).unwrap();
+    let raw_created_file = file_service
+        .get_file_by_id(created_file.id)
         .await
+        .unwrap()
         .unwrap();
 
-    assert!(raw_removed_file_data.is_none());
+    assert_eq!(raw_created_file, created_file);
 }
 
 #[rocket::async_test]
-async fn test_get_files() {
-    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+async fn test_create_file_mime_not_allowed() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance_with_config(
+        TestFileDriver::Memory,
+        SearchBackend::Meilisearch,
+        |app_config| app_config.mime_policy.deny = vec!["application/x-dosexec".to_owned()],
+    )
+    .await;
     let client = Client::tracked(rocket).await.unwrap();
     let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
     let staging_file_service = client.rocket().state::<Arc<StagingFileService>>().unwrap();
-    let file_service = client.rocket().state::<Arc<FileService>>().unwrap();
     let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
 
-    let (_initial_user, initial_user_session) =
+    let (initial_user, initial_user_session) =
         create_initial_user(auth_service, user_service).await;
 
-    let files = vec![
-        create_file(
-            &client,
-            staging_file_service,
-            file_service,
-            &initial_user_session,
-            "file0",
-            Some("video/mp4"),
-            "file0 content",
-        )
-        .await,
-        create_file(
-            &client,
-            staging_file_service,
-            file_service,
-            &initial_user_session,
-            "file1",
-            Some("image/png"),
-            "file1 content",
-        )
-        .await,
-        create_file(
-            &client,
-            staging_file_service,
-            file_service,
-            &initial_user_session,
-            "file2",
-            Some("text/plain"),
-            "file2 content",
-        )
-        .await,
-    ];
+    let filled_staging_file = create_filled_staging_file(
+        &client,
+        staging_file_service,
+        &initial_user_session,
+        "file.exe",
+        Some("application/x-dosexec"),
+        "file content",
+    )
+    .await;
 
     let response = client
-        .get(format!("/files?limit={}", files.len()))
+        .post(format!("/files/{}", filled_staging_file.id))
         .header(Accept::JSON)
         .header(ContentType::JSON)
         .header(Header::new(
@@ -178,149 +211,102 @@ async fn test_get_files() {
         .await;
 
     let status = response.status();
-    let retrieved_files = response.into_json::<FileList>().await.unwrap();
+    let body = response.into_json::<serde_json::Value>().await.unwrap();
 
-    assert_eq!(status, Status::Ok);
-    assert_eq!(retrieved_files.last_file_id, None);
-    assert_eq!(retrieved_files.limit, files.len() as u32);
-    assert_eq!(retrieved_files.files, files);
+    assert_eq!(status, Status::UnprocessableEntity);
+    assert_eq!(body["error_code"], "MIME_NOT_ALLOWED");
 
-    let raw_retrieved_files = file_service
-        .get_files(retrieved_files.last_file_id, retrieved_files.limit)
+    // only admins can override the policy
+    let response = client
+        .post(format!(
+            "/files/{}?allow_any_mime=true",
+            filled_staging_file.id
+        ))
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::Forbidden);
+
+    user_service
+        .set_user_role_by_id(initial_user.id, UserRole::Admin)
         .await
         .unwrap();
 
-    assert_eq!(raw_retrieved_files, retrieved_files.files);
-}
+    let response = client
+        .post(format!(
+            "/files/{}?allow_any_mime=true",
+            filled_staging_file.id
+        ))
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
 
-#[rocket::async_test]
-async fn test_get_files_paginations() {
-    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
-    let client = Client::tracked(rocket).await.unwrap();
-    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
-    let staging_file_service = client.rocket().state::<Arc<StagingFileService>>().unwrap();
-    let file_service = client.rocket().state::<Arc<FileService>>().unwrap();
-    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+    let status = response.status();
+    let created_file = response.into_json::<File>().await.unwrap();
 
-    let (_initial_user, initial_user_session) =
-        create_initial_user(auth_service, user_service).await;
+    assert_eq!(status, Status::Created);
+    assert_eq!(created_file.mime, "application/x-dosexec");
+}
 
-    let files = vec![
-        create_file(
-            &client,
-            staging_file_service,
-            file_service,
-            &initial_user_session,
-            "file0",
-            Some("video/mp4"),
-            "file0 content",
-        )
-        .await,
-        create_file(
-            &client,
-            staging_file_service,
-            file_service,
-            &initial_user_session,
-            "file1",
-            Some("image/png"),
-            "file1 content",
-        )
-        .await,
-        create_file(
-            &client,
-            staging_file_service,
-            file_service,
-            &initial_user_session,
-            "file2",
-            Some("text/plain"),
-            "file2 content",
-        )
-        .await,
-        create_file(
-            &client,
-            staging_file_service,
-            file_service,
-            &initial_user_session,
-            "file3",
-            Some("text/html"),
-            "file3 content",
-        )
-        .await,
-        create_file(
-            &client,
-            staging_file_service,
-            file_service,
-            &initial_user_session,
-            "file4",
-            Some("image/jpeg"),
-            "file4 content",
-        )
-        .await,
-        create_file(
-            &client,
-            staging_file_service,
-            file_service,
-            &initial_user_session,
-            "file5",
-            Some("audio/mpeg"),
-            "file5 content",
-        )
-        .await,
-    ];
+/// Serves a fake `clamd` on an ephemeral port, answering every scan with `reply`.
+/// Returns the address to connect to.
+async fn spawn_fake_clamd(reply: &'static str) -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap().to_string();
 
-    for index in 0..=files.len() {
-        let url = if index == 0 {
-            format!("/files?limit={}", files.len())
-        } else {
-            format!(
-                "/files?last_file_id={}&limit={}",
-                files[index - 1].id,
-                files.len()
-            )
-        };
+    tokio::spawn(async move {
+        loop {
+            let (mut stream, _) = listener.accept().await.unwrap();
 
-        let response = client
-            .get(url)
-            .header(Accept::JSON)
-            .header(ContentType::JSON)
-            .header(Header::new(
-                "Authorization",
-                format!("Bearer {}", initial_user_session.token),
-            ))
-            .dispatch()
-            .await;
+            // `zINSTREAM\0`, followed by chunks prefixed by their length, up to an empty one
+            let mut command = [0u8; 10];
+            stream.read_exact(&mut command).await.unwrap();
 
-        let status = response.status();
-        let retrieved_files = response.into_json::<FileList>().await.unwrap();
+            loop {
+                let len = stream.read_u32().await.unwrap();
 
-        assert_eq!(status, Status::Ok);
-        assert_eq!(
-            retrieved_files.last_file_id,
-            if index == 0 {
-                None
-            } else {
-                Some(files[index - 1].id)
+                if len == 0 {
+                    break;
+                }
+
+                let mut chunk = vec![0u8; len as usize];
+                stream.read_exact(&mut chunk).await.unwrap();
             }
-        );
-        assert_eq!(retrieved_files.limit, files.len() as u32);
-        assert_eq!(retrieved_files.files, files[index..]);
 
-        let raw_retrieved_files = file_service
-            .get_files(retrieved_files.last_file_id, retrieved_files.limit)
-            .await
-            .unwrap();
+            stream.write_all(reply.as_bytes()).await.unwrap();
+            stream.write_all(b"\0").await.unwrap();
+        }
+    });
 
-        assert_eq!(raw_retrieved_files, retrieved_files.files);
-    }
+    address
 }
 
 #[rocket::async_test]
-async fn test_get_file() {
-    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+async fn test_create_file_infected() {
+    let clamd_address = spawn_fake_clamd("stream: Eicar-Test-Signature FOUND").await;
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance_with_config(
+        TestFileDriver::Memory,
+        SearchBackend::Meilisearch,
+        |app_config| {
+            app_config.scanner.enabled = true;
+            app_config.scanner.clamd_address = clamd_address;
+        },
+    )
+    .await;
     let client = Client::tracked(rocket).await.unwrap();
     let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
     let staging_file_service = client.rocket().state::<Arc<StagingFileService>>().unwrap();
-    let file_service = client.rocket().state::<Arc<FileService>>().unwrap();
     let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
 
     let (_initial_user, initial_user_session) =
@@ -331,7 +317,7 @@ async fn test_get_file() {
         staging_file_service,
         &initial_user_session,
         "file",
-        Some("video/mp4"),
+        Some("text/plain"),
         "file content",
     )
     .await;
@@ -347,37 +333,34 @@ async fn test_get_file() {
         .dispatch()
         .await;
 
-    let file = response.into_json::<File>().await.unwrap();
-
-    let response = client
-        .get(format!("/files/{}", file.id))
-        .header(Accept::JSON)
-        .header(ContentType::JSON)
-        .header(Header::new(
-            "Authorization",
-            format!("Bearer {}", initial_user_session.token),
-        ))
-        .dispatch()
-        .await;
-
     let status = response.status();
-    let retrieved_file = response.into_json::<File>().await.unwrap();
+    let body = response.into_json::<serde_json::Value>().await.unwrap();
 
-    assert_eq!(status, Status::Ok);
-    assert_eq!(retrieved_file, file);
+    assert_eq!(status, Status::UnprocessableEntity);
+    assert_eq!(body["error_code"], "FILE_INFECTED");
 
-    let raw_retrieved_file = file_service
-        .get_file_by_id(retrieved_file.id)
+    // the staging file is kept, and expires as usual
+    let staging_file = staging_file_service
+        .get_staging_file_by_id(filled_staging_file.id)
         .await
-        .unwrap()
         .unwrap();
 
-    assert_eq!(raw_retrieved_file, retrieved_file);
+    assert!(staging_file.is_some());
 }
 
 #[rocket::async_test]
-async fn test_get_file_data_range_full() {
-    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+async fn test_create_file_quarantined() {
+    let clamd_address = spawn_fake_clamd("stream: Eicar-Test-Signature FOUND").await;
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance_with_config(
+        TestFileDriver::Memory,
+        SearchBackend::Meilisearch,
+        |app_config| {
+            app_config.scanner.enabled = true;
+            app_config.scanner.clamd_address = clamd_address;
+            app_config.scanner.on_infected = InfectedFileAction::Quarantine;
+        },
+    )
+    .await;
     let client = Client::tracked(rocket).await.unwrap();
     let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
     let staging_file_service = client.rocket().state::<Arc<StagingFileService>>().unwrap();
@@ -387,22 +370,20 @@ async fn test_get_file_data_range_full() {
     let (_initial_user, initial_user_session) =
         create_initial_user(auth_service, user_service).await;
 
-    let file_content = "file content";
-
-    let filled_staging_file = create_filled_staging_file(
+    let file = create_file(
         &client,
         staging_file_service,
+        file_service,
         &initial_user_session,
         "file",
-        Some("video/mp4"),
-        file_content,
+        Some("text/plain"),
+        "file content",
     )
     .await;
 
     let response = client
-        .post(format!("/files/{}", filled_staging_file.id))
+        .get(format!("/files/{}/scan", file.id))
         .header(Accept::JSON)
-        .header(ContentType::JSON)
         .header(Header::new(
             "Authorization",
             format!("Bearer {}", initial_user_session.token),
@@ -410,9 +391,14 @@ async fn test_get_file_data_range_full() {
         .dispatch()
         .await;
 
-    let file = response.into_json::<File>().await.unwrap();
+    let status = response.status();
+    let body = response.into_json::<serde_json::Value>().await.unwrap();
 
-    let response = client
+    assert_eq!(status, Status::Ok);
+    assert_eq!(body["status"], "infected");
+    assert_eq!(body["signature"], "Eicar-Test-Signature");
+
+    let response = client
         .get(format!("/files/{}/data", file.id))
         .header(Header::new(
             "Authorization",
@@ -421,33 +407,3507 @@ async fn test_get_file_data_range_full() {
         .dispatch()
         .await;
 
-    let status = response.status();
-    let content_type = response.content_type().unwrap();
-    let retrieved_file_data = response.into_string().await.unwrap();
-
-    assert_eq!(status, Status::Ok);
-    assert!(content_type.is_mp4());
-    assert_eq!(retrieved_file_data, file_content);
-
-    let mut raw_retrieved_file_data = file_service
-        .get_file_data_by_id(file.id, ReadRange::Full)
-        .await
-        .unwrap()
-        .unwrap();
-    let raw_retrieved_file_data = {
-        let mut buffer = String::with_capacity(file_content.len());
-        raw_retrieved_file_data
-            .read_to_string(&mut buffer)
-            .await
-            .unwrap();
-        buffer
-    };
-
-    assert_eq!(raw_retrieved_file_data, file_content);
+    let status = response.status();
+    let body = response.into_json::<serde_json::Value>().await.unwrap();
+
+    assert_eq!(status, Status::Forbidden);
+    assert_eq!(body["error_code"], "FILE_QUARANTINED");
+
+    // probing the data is refused the same way
+    let response = client
+        .head(format!("/files/{}/data", file.id))
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::Forbidden);
+}
+
+#[rocket::async_test]
+async fn test_remove_file() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let staging_file_service = client.rocket().state::<Arc<StagingFileService>>().unwrap();
+    let file_service = client.rocket().state::<Arc<FileService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    let (_initial_user, initial_user_session) =
+        create_initial_user(auth_service, user_service).await;
+
+    let file = create_file(
+        &client,
+        staging_file_service,
+        file_service,
+        &initial_user_session,
+        "file",
+        Some("video/mp4"),
+        "file content",
+    )
+    .await;
+
+    let response = client
+        .delete(format!("/files/{}", file.id))
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let removed_file = response.into_json::<File>().await.unwrap();
+
+    assert_eq!(status, Status::Ok);
+    assert_eq!(removed_file, file);
+
+    let raw_removed_file = file_service.get_file_by_id(removed_file.id).await.unwrap();
+
+    assert_eq!(raw_removed_file, None);
+
+    let raw_removed_file_data = file_service
+        .get_file_data_by_id(removed_file.id, ReadRange::Full)
+        .await
+        .unwrap();
+
+    assert!(raw_removed_file_data.is_none());
+}
+
+#[rocket::async_test]
+async fn test_get_files_from_replica() {
+    // the test database itself stands in for a replica, as it is always in sync
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance_with_config(
+        TestFileDriver::Memory,
+        SearchBackend::Meilisearch,
+        |app_config| {
+            let replica_url = format!(
+                "{}/{}",
+                app_config.database_url_base.trim_end_matches('/'),
+                app_config.database_name
+            );
+            app_config.database_replica_urls = vec![replica_url];
+        },
+    )
+    .await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let staging_file_service = client.rocket().state::<Arc<StagingFileService>>().unwrap();
+    let file_service = client.rocket().state::<Arc<FileService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    let (_initial_user, initial_user_session) =
+        create_initial_user(auth_service, user_service).await;
+
+    let file = create_file(
+        &client,
+        staging_file_service,
+        file_service,
+        &initial_user_session,
+        "file",
+        Some("text/plain"),
+        "file content",
+    )
+    .await;
+
+    let response = client
+        .get("/files")
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let retrieved_files = response.into_json::<FileList>().await.unwrap();
+
+    assert_eq!(status, Status::Ok);
+    assert_eq!(unlist_files(&retrieved_files.files), vec![file]);
+}
+
+#[rocket::async_test]
+async fn test_get_files() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let staging_file_service = client.rocket().state::<Arc<StagingFileService>>().unwrap();
+    let file_service = client.rocket().state::<Arc<FileService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    let (_initial_user, initial_user_session) =
+        create_initial_user(auth_service, user_service).await;
+
+    let files = vec![
+        create_file(
+            &client,
+            staging_file_service,
+            file_service,
+            &initial_user_session,
+            "file0",
+            Some("video/mp4"),
+            "file0 content",
+        )
+        .await,
+        create_file(
+            &client,
+            staging_file_service,
+            file_service,
+            &initial_user_session,
+            "file1",
+            Some("image/png"),
+            "file1 content",
+        )
+        .await,
+        create_file(
+            &client,
+            staging_file_service,
+            file_service,
+            &initial_user_session,
+            "file2",
+            Some("text/plain"),
+            "file2 content",
+        )
+        .await,
+    ];
+
+    let response = client
+        .get(format!("/files?limit={}", files.len()))
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let retrieved_files = response.into_json::<FileList>().await.unwrap();
+
+    assert_eq!(status, Status::Ok);
+    assert_eq!(retrieved_files.last_file_id, None);
+    assert_eq!(retrieved_files.limit, files.len() as u32);
+    assert_eq!(unlist_files(&retrieved_files.files), files);
+
+    let raw_retrieved_files = file_service
+        .get_files(
+            retrieved_files.last_file_id,
+            &FileFilter::default(),
+            FileSort::Name,
+            SortDirection::Asc,
+            retrieved_files.limit,
+            false,
+        )
+        .await
+        .unwrap()
+        .items;
+
+    assert_eq!(raw_retrieved_files, unlist_files(&retrieved_files.files));
+}
+
+#[rocket::async_test]
+async fn test_get_files_paginations() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let staging_file_service = client.rocket().state::<Arc<StagingFileService>>().unwrap();
+    let file_service = client.rocket().state::<Arc<FileService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    let (_initial_user, initial_user_session) =
+        create_initial_user(auth_service, user_service).await;
+
+    let files = [
+        create_file(
+            &client,
+            staging_file_service,
+            file_service,
+            &initial_user_session,
+            "file0",
+            Some("video/mp4"),
+            "file0 content",
+        )
+        .await,
+        create_file(
+            &client,
+            staging_file_service,
+            file_service,
+            &initial_user_session,
+            "file1",
+            Some("image/png"),
+            "file1 content",
+        )
+        .await,
+        create_file(
+            &client,
+            staging_file_service,
+            file_service,
+            &initial_user_session,
+            "file2",
+            Some("text/plain"),
+            "file2 content",
+        )
+        .await,
+        create_file(
+            &client,
+            staging_file_service,
+            file_service,
+            &initial_user_session,
+            "file3",
+            Some("text/html"),
+            "file3 content",
+        )
+        .await,
+        create_file(
+            &client,
+            staging_file_service,
+            file_service,
+            &initial_user_session,
+            "file4",
+            Some("image/jpeg"),
+            "file4 content",
+        )
+        .await,
+        create_file(
+            &client,
+            staging_file_service,
+            file_service,
+            &initial_user_session,
+            "file5",
+            Some("audio/mpeg"),
+            "file5 content",
+        )
+        .await,
+    ];
+
+    for index in 0..=files.len() {
+        let url = if index == 0 {
+            format!("/files?limit={}", files.len())
+        } else {
+            format!(
+                "/files?last_file_id={}&limit={}",
+                files[index - 1].id,
+                files.len()
+            )
+        };
+
+        let response = client
+            .get(url)
+            .header(Accept::JSON)
+            .header(ContentType::JSON)
+            .header(Header::new(
+                "Authorization",
+                format!("Bearer {}", initial_user_session.token),
+            ))
+            .dispatch()
+            .await;
+
+        let status = response.status();
+        let retrieved_files = response.into_json::<FileList>().await.unwrap();
+
+        assert_eq!(status, Status::Ok);
+        assert_eq!(
+            retrieved_files.last_file_id,
+            if index == 0 {
+                None
+            } else {
+                Some(files[index - 1].id)
+            }
+        );
+        assert_eq!(retrieved_files.limit, files.len() as u32);
+        assert_eq!(unlist_files(&retrieved_files.files), files[index..]);
+
+        let raw_retrieved_files = file_service
+            .get_files(
+                retrieved_files.last_file_id,
+                &FileFilter::default(),
+                FileSort::Name,
+                SortDirection::Asc,
+                retrieved_files.limit,
+                false,
+            )
+            .await
+            .unwrap()
+            .items;
+
+        assert_eq!(raw_retrieved_files, unlist_files(&retrieved_files.files));
+    }
+}
+
+#[rocket::async_test]
+async fn test_get_files_ordered_by_id() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let staging_file_service = client.rocket().state::<Arc<StagingFileService>>().unwrap();
+    let file_service = client.rocket().state::<Arc<FileService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    let (_initial_user, initial_user_session) =
+        create_initial_user(auth_service, user_service).await;
+
+    let mut files = Vec::new();
+
+    for index in 0..4 {
+        files.push(
+            create_file(
+                &client,
+                staging_file_service,
+                file_service,
+                &initial_user_session,
+                format!("file{}", index),
+                Some("text/plain"),
+                format!("file{} content", index),
+            )
+            .await,
+        );
+    }
+
+    files.sort_by_key(|file| file.id);
+
+    for index in 0..=files.len() {
+        let url = if index == 0 {
+            format!("/files?order=id&limit={}", files.len())
+        } else {
+            format!(
+                "/files?last_file_id={}&order=id&limit={}",
+                files[index - 1].id,
+                files.len()
+            )
+        };
+
+        let response = client
+            .get(url)
+            .header(Accept::JSON)
+            .header(ContentType::JSON)
+            .header(Header::new(
+                "Authorization",
+                format!("Bearer {}", initial_user_session.token),
+            ))
+            .dispatch()
+            .await;
+
+        let status = response.status();
+        let retrieved_files = response.into_json::<FileList>().await.unwrap();
+
+        assert_eq!(status, Status::Ok);
+        assert_eq!(unlist_files(&retrieved_files.files), files[index..]);
+    }
+
+    let response = client
+        .get("/files?order=unknown")
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::BadRequest);
+}
+
+#[rocket::async_test]
+async fn test_get_files_next_cursor_and_total() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let staging_file_service = client.rocket().state::<Arc<StagingFileService>>().unwrap();
+    let file_service = client.rocket().state::<Arc<FileService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    let (_initial_user, initial_user_session) =
+        create_initial_user(auth_service, user_service).await;
+
+    let mut files = Vec::new();
+
+    for index in 0..3 {
+        files.push(
+            create_file(
+                &client,
+                staging_file_service,
+                file_service,
+                &initial_user_session,
+                format!("file{}", index),
+                Some("text/plain"),
+                format!("file{} content", index),
+            )
+            .await,
+        );
+    }
+
+    let response = client
+        .get("/files?limit=2&with_total=true")
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let retrieved_files = response.into_json::<FileList>().await.unwrap();
+
+    assert_eq!(status, Status::Ok);
+    assert_eq!(unlist_files(&retrieved_files.files), files[..2]);
+    assert_eq!(retrieved_files.next_cursor, Some(files[1].id));
+    assert_eq!(retrieved_files.total, Some(3));
+
+    let response = client
+        .get(format!(
+            "/files?last_file_id={}&limit=2",
+            retrieved_files.next_cursor.unwrap()
+        ))
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let retrieved_files = response.into_json::<FileList>().await.unwrap();
+
+    assert_eq!(status, Status::Ok);
+    assert_eq!(unlist_files(&retrieved_files.files), files[2..]);
+    assert_eq!(retrieved_files.next_cursor, None);
+    assert_eq!(retrieved_files.total, None);
+}
+
+#[rocket::async_test]
+async fn test_get_files_filtered_and_sorted() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let staging_file_service = client.rocket().state::<Arc<StagingFileService>>().unwrap();
+    let file_service = client.rocket().state::<Arc<FileService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    let (_initial_user, initial_user_session) =
+        create_initial_user(auth_service, user_service).await;
+
+    let mut files = Vec::new();
+
+    for (name, mime, content) in [
+        ("file0", "image/png", "a"),
+        ("file1", "image/jpeg", "abc"),
+        ("file2", "text/plain", "ab"),
+        ("file3", "image/png", "abcd"),
+    ] {
+        files.push(
+            create_file(
+                &client,
+                staging_file_service,
+                file_service,
+                &initial_user_session,
+                name,
+                Some(mime),
+                content,
+            )
+            .await,
+        );
+    }
+
+    let response = client
+        .get("/files?mime=image/*&min_size=2&sort=size&direction=desc")
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let retrieved_files = response.into_json::<FileList>().await.unwrap();
+
+    assert_eq!(status, Status::Ok);
+    assert_eq!(
+        unlist_files(&retrieved_files.files),
+        vec![files[3].clone(), files[1].clone()]
+    );
+
+    let response = client
+        .get("/files?mime=image/png&uploaded_after=2000-01-01")
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let retrieved_files = response.into_json::<FileList>().await.unwrap();
+
+    assert_eq!(status, Status::Ok);
+    assert_eq!(
+        unlist_files(&retrieved_files.files),
+        vec![files[0].clone(), files[3].clone()]
+    );
+
+    let response = client
+        .get("/files?uploaded_before=yesterday")
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::BadRequest);
+}
+
+#[rocket::async_test]
+async fn test_get_collections_for_file() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let collection_service = client.rocket().state::<Arc<CollectionService>>().unwrap();
+    let collection_file_pair_service = client
+        .rocket()
+        .state::<Arc<CollectionFilePairService>>()
+        .unwrap();
+    let staging_file_service = client.rocket().state::<Arc<StagingFileService>>().unwrap();
+    let file_service = client.rocket().state::<Arc<FileService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    let (_initial_user, initial_user_session) =
+        create_initial_user(auth_service, user_service).await;
+
+    let file = create_file(
+        &client,
+        staging_file_service,
+        file_service,
+        &initial_user_session,
+        "file",
+        Some("text/plain"),
+        "file content",
+    )
+    .await;
+
+    let mut collections = Vec::new();
+
+    for index in 0..3 {
+        let collection = collection_service
+            .create_collection(&format!("collection{}", index), None, false, None)
+            .await
+            .unwrap();
+
+        // the last collection does not contain the file
+        if index < 2 {
+            collection_file_pair_service
+                .add_file_to_collection(collection.id, file.id, None)
+                .await
+                .unwrap();
+            collections.push(collection);
+        }
+    }
+
+    let response = client
+        .get(format!("/files/{}/collections?limit=1", file.id))
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let retrieved_collections = response.into_json::<CollectionList>().await.unwrap();
+
+    assert_eq!(status, Status::Ok);
+    assert_eq!(
+        unlist_collections(&retrieved_collections.collections),
+        collections[..1]
+    );
+    assert_eq!(retrieved_collections.next_cursor, Some(collections[0].id));
+
+    let response = client
+        .get(format!(
+            "/files/{}/collections?last_collection_id={}&limit=1",
+            file.id, collections[0].id
+        ))
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let retrieved_collections = response.into_json::<CollectionList>().await.unwrap();
+
+    assert_eq!(status, Status::Ok);
+    assert_eq!(
+        unlist_collections(&retrieved_collections.collections),
+        collections[1..]
+    );
+    assert_eq!(retrieved_collections.next_cursor, None);
+}
+
+#[rocket::async_test]
+async fn test_get_duplicate_files() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let staging_file_service = client.rocket().state::<Arc<StagingFileService>>().unwrap();
+    let file_service = client.rocket().state::<Arc<FileService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    let (_initial_user, initial_user_session) =
+        create_initial_user(auth_service, user_service).await;
+
+    let duplicated_files = vec![
+        create_file(
+            &client,
+            staging_file_service,
+            file_service,
+            &initial_user_session,
+            "file0",
+            Some("text/plain"),
+            "duplicated content",
+        )
+        .await,
+        create_file(
+            &client,
+            staging_file_service,
+            file_service,
+            &initial_user_session,
+            "file1",
+            Some("text/plain"),
+            "duplicated content",
+        )
+        .await,
+    ];
+    create_file(
+        &client,
+        staging_file_service,
+        file_service,
+        &initial_user_session,
+        "file2",
+        Some("text/plain"),
+        "unique content",
+    )
+    .await;
+
+    let response = client
+        .get("/files/duplicates")
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let retrieved_groups = response
+        .into_json::<DuplicateFileGroupList>()
+        .await
+        .unwrap();
+
+    assert_eq!(status, Status::Ok);
+    assert_eq!(retrieved_groups.groups.len(), 1);
+    assert_eq!(retrieved_groups.groups[0].size, duplicated_files[0].size);
+    assert_eq!(retrieved_groups.groups[0].hash, duplicated_files[0].hash);
+    assert_eq!(retrieved_groups.groups[0].files, duplicated_files);
+
+    let group = &retrieved_groups.groups[0];
+    let raw_retrieved_groups = file_service
+        .find_duplicate_groups(Some((group.size, group.hash)), retrieved_groups.limit)
+        .await
+        .unwrap();
+
+    assert!(raw_retrieved_groups.is_empty());
+}
+
+#[rocket::async_test]
+async fn test_get_file() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let staging_file_service = client.rocket().state::<Arc<StagingFileService>>().unwrap();
+    let file_service = client.rocket().state::<Arc<FileService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    let (_initial_user, initial_user_session) =
+        create_initial_user(auth_service, user_service).await;
+
+    let filled_staging_file = create_filled_staging_file(
+        &client,
+        staging_file_service,
+        &initial_user_session,
+        "file",
+        Some("video/mp4"),
+        "file content",
+    )
+    .await;
+
+    let response = client
+        .post(format!("/files/{}", filled_staging_file.id))
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    let file = response.into_json::<File>().await.unwrap();
+
+    let response = client
+        .get(format!("/files/{}", file.id))
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let retrieved_file = response.into_json::<File>().await.unwrap();
+
+    assert_eq!(status, Status::Ok);
+    assert_eq!(retrieved_file, file);
+
+    let raw_retrieved_file = file_service
+        .get_file_by_id(retrieved_file.id)
+        .await
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(raw_retrieved_file, retrieved_file);
+}
+
+#[rocket::async_test]
+async fn test_get_file_error_code() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    let (_initial_user, initial_user_session) =
+        create_initial_user(auth_service, user_service).await;
+
+    let response = client
+        .get(format!("/files/{}", Uuid::new_v4()))
+        .header(Accept::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let body = response.into_json::<serde_json::Value>().await.unwrap();
+
+    assert_eq!(status, Status::NotFound);
+    assert_eq!(body["error_code"], "FILE_NOT_FOUND");
+
+    let response = client
+        .get("/files?sort=unknown")
+        .header(Accept::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let body = response.into_json::<serde_json::Value>().await.unwrap();
+
+    assert_eq!(status, Status::BadRequest);
+    assert_eq!(body["error_code"], "INVALID_PARAMETER");
+}
+
+#[rocket::async_test]
+async fn test_get_file_problem_details() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    let (_initial_user, initial_user_session) =
+        create_initial_user(auth_service, user_service).await;
+
+    let file_id = Uuid::new_v4();
+    let response = client
+        .get(format!("/files/{}", file_id))
+        .header(Header::new("Accept", "application/problem+json"))
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let content_type = response.content_type().unwrap();
+    let body = response.into_json::<serde_json::Value>().await.unwrap();
+
+    assert_eq!(status, Status::NotFound);
+    assert_eq!(
+        content_type,
+        ContentType::new("application", "problem+json")
+    );
+    assert_eq!(body["type"], "urn:poly-tag:error:FILE_NOT_FOUND");
+    assert_eq!(body["title"], "Not Found");
+    assert_eq!(body["status"], 404);
+    assert_eq!(body["detail"], "not found");
+    assert_eq!(body["instance"], format!("/files/{}", file_id));
+    assert_eq!(body["error_code"], "FILE_NOT_FOUND");
+}
+
+#[rocket::async_test]
+async fn test_get_files_by_ids() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let staging_file_service = client.rocket().state::<Arc<StagingFileService>>().unwrap();
+    let file_service = client.rocket().state::<Arc<FileService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    let (_initial_user, initial_user_session) =
+        create_initial_user(auth_service, user_service).await;
+
+    let first_file = create_file(
+        &client,
+        staging_file_service,
+        file_service,
+        &initial_user_session,
+        "first",
+        Some("text/plain"),
+        "first content",
+    )
+    .await;
+    let second_file = create_file(
+        &client,
+        staging_file_service,
+        file_service,
+        &initial_user_session,
+        "second",
+        Some("text/plain"),
+        "second content",
+    )
+    .await;
+    let missing_id = Uuid::new_v4();
+
+    let response = client
+        .post("/files/batch-get")
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .body(
+            serde_json::to_string(&GettingFiles {
+                ids: vec![second_file.id, missing_id, first_file.id, second_file.id],
+            })
+            .unwrap(),
+        )
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let batch = response.into_json::<FileBatch>().await.unwrap();
+
+    assert_eq!(status, Status::Ok);
+    assert_eq!(batch.files, vec![second_file, first_file]);
+    assert_eq!(batch.missing_ids, vec![missing_id]);
+
+    let response = client
+        .post("/files/batch-get")
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .body(
+            serde_json::to_string(&GettingFiles {
+                ids: (0..101).map(|_| Uuid::new_v4()).collect(),
+            })
+            .unwrap(),
+        )
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+}
+
+#[rocket::async_test]
+async fn test_archive_files() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let staging_file_service = client.rocket().state::<Arc<StagingFileService>>().unwrap();
+    let file_service = client.rocket().state::<Arc<FileService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    let (_initial_user, initial_user_session) =
+        create_initial_user(auth_service, user_service).await;
+
+    let first_file = create_file(
+        &client,
+        staging_file_service,
+        file_service,
+        &initial_user_session,
+        "file.txt",
+        Some("text/plain"),
+        "first content",
+    )
+    .await;
+    let second_file = create_file(
+        &client,
+        staging_file_service,
+        file_service,
+        &initial_user_session,
+        "file.txt",
+        Some("text/plain"),
+        "second content",
+    )
+    .await;
+
+    let response = client
+        .post("/files/archive")
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .body(
+            serde_json::to_string(&ArchivingFiles {
+                ids: vec![first_file.id, Uuid::new_v4(), second_file.id],
+                query: None,
+            })
+            .unwrap(),
+        )
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.content_type(), Some(ContentType::ZIP));
+
+    let archive = response.into_bytes().await.unwrap();
+    let contains = |needle: &[u8]| archive.windows(needle.len()).any(|window| window == needle);
+
+    assert!(archive.starts_with(b"PK\x03\x04"));
+    assert!(contains(b"first content"));
+    assert!(contains(b"second content"));
+    // the second file is renamed, as its name has been taken
+    assert!(contains(b"file (1).txt"));
+
+    // the end of central directory record, with the number of entries
+    let end = &archive[archive.len() - 22..];
+    assert!(end.starts_with(b"PK\x05\x06"));
+    assert_eq!(u16::from_le_bytes([end[10], end[11]]), 2);
+
+    let response = client
+        .post("/files/archive")
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .body(
+            serde_json::to_string(&ArchivingFiles {
+                ids: vec![],
+                query: None,
+            })
+            .unwrap(),
+        )
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+}
+
+#[rocket::async_test]
+async fn test_replace_and_restore_file_version() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let staging_file_service = client.rocket().state::<Arc<StagingFileService>>().unwrap();
+    let file_service = client.rocket().state::<Arc<FileService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    let (_initial_user, initial_user_session) =
+        create_initial_user(auth_service, user_service).await;
+
+    let file = create_file(
+        &client,
+        staging_file_service,
+        file_service,
+        &initial_user_session,
+        "file",
+        Some("text/plain"),
+        "first content",
+    )
+    .await;
+    let staging_file = create_filled_staging_file(
+        &client,
+        staging_file_service,
+        &initial_user_session,
+        "file",
+        Some("text/plain"),
+        "second content",
+    )
+    .await;
+
+    let response = client
+        .post(format!(
+            "/files/{}?replaces_file_id={}",
+            staging_file.id, file.id
+        ))
+        .header(Accept::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let replaced_file = response.into_json::<File>().await.unwrap();
+
+    assert_eq!(status, Status::Ok);
+    assert_eq!(replaced_file.id, file.id);
+    assert_eq!(replaced_file.size, "second content".len() as i64);
+
+    let response = client
+        .get(format!("/files/{}/versions", file.id))
+        .header(Accept::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let version_list = response.into_json::<FileVersionList>().await.unwrap();
+
+    assert_eq!(status, Status::Ok);
+    assert_eq!(version_list.versions.len(), 1);
+    assert_eq!(version_list.versions[0].id, staging_file.id);
+    assert_eq!(version_list.versions[0].version, 1);
+    assert_eq!(version_list.versions[0].size, file.size);
+    assert_eq!(version_list.versions[0].hash, file.hash);
+
+    let response = client
+        .post(format!("/files/{}/versions/1/restore", file.id))
+        .header(Accept::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let restored_file = response.into_json::<File>().await.unwrap();
+
+    assert_eq!(status, Status::Ok);
+    assert_eq!(restored_file, file);
+
+    let mut restored_file_data = file_service
+        .get_file_data_by_id(file.id, ReadRange::Full)
+        .await
+        .unwrap()
+        .unwrap();
+    let restored_file_data = {
+        let mut buffer = String::new();
+        restored_file_data
+            .read_to_string(&mut buffer)
+            .await
+            .unwrap();
+        buffer
+    };
+
+    assert_eq!(restored_file_data, "first content");
+
+    let versions = file_service
+        .get_file_versions(file.id)
+        .await
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(versions.len(), 1);
+    assert_eq!(versions[0].version, 2);
+    assert_eq!(versions[0].size, "second content".len() as i64);
+
+    let response = client
+        .post(format!("/files/{}/versions/1/restore", file.id))
+        .header(Accept::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+#[rocket::async_test]
+async fn test_file_comments() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let staging_file_service = client.rocket().state::<Arc<StagingFileService>>().unwrap();
+    let file_service = client.rocket().state::<Arc<FileService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    let (initial_user, initial_user_session) =
+        create_initial_user(auth_service, user_service).await;
+    let other_user = create_user("other", user_service).await;
+    let other_user_session = auth_service
+        .create_user_session(other_user.id, None, None)
+        .await
+        .unwrap();
+
+    let file = create_file(
+        &client,
+        staging_file_service,
+        file_service,
+        &initial_user_session,
+        "file",
+        Some("text/plain"),
+        "file content",
+    )
+    .await;
+
+    let mut comments = Vec::new();
+
+    for content in ["first comment", "second comment"] {
+        let response = client
+            .post(format!("/files/{}/comments", file.id))
+            .header(Accept::JSON)
+            .header(ContentType::JSON)
+            .header(Header::new(
+                "Authorization",
+                format!("Bearer {}", initial_user_session.token),
+            ))
+            .body(serde_json::json!({ "content": content }).to_string())
+            .dispatch()
+            .await;
+
+        let status = response.status();
+        let comment = response.into_json::<FileComment>().await.unwrap();
+
+        assert_eq!(status, Status::Created);
+        assert_eq!(comment.file_id, file.id);
+        assert_eq!(comment.user_id, initial_user.id);
+        assert_eq!(comment.content, content);
+
+        comments.push(comment);
+    }
+
+    let response = client
+        .get(format!("/files/{}/comments?limit=1", file.id))
+        .header(Accept::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let comment_list = response.into_json::<CommentList>().await.unwrap();
+
+    assert_eq!(status, Status::Ok);
+    assert_eq!(comment_list.comments, vec![comments[0].clone()]);
+    assert_eq!(comment_list.next_cursor, Some(comments[0].id));
+
+    // only the author can edit a comment
+    let response = client
+        .put(format!("/files/{}/comments/{}", file.id, comments[1].id))
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", other_user_session.token),
+        ))
+        .body(serde_json::json!({ "content": "edited comment" }).to_string())
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::NotFound);
+
+    let response = client
+        .put(format!("/files/{}/comments/{}", file.id, comments[1].id))
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .body(serde_json::json!({ "content": "edited comment" }).to_string())
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let edited_comment = response.into_json::<FileComment>().await.unwrap();
+
+    assert_eq!(status, Status::Ok);
+    assert_eq!(edited_comment.content, "edited comment");
+    assert!(edited_comment.edited_at.is_some());
+
+    let response = client
+        .delete(format!("/files/{}/comments/{}", file.id, comments[0].id))
+        .header(Accept::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::Ok);
+
+    let response = client
+        .get(format!("/files/{}", file.id))
+        .header(Accept::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    let retrieved_file = response.into_json::<FileWithMetadata>().await.unwrap();
+
+    assert_eq!(retrieved_file.comment_count, 1);
+
+    let response = client
+        .post(format!("/files/{}/comments", Uuid::new_v4()))
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .body(serde_json::json!({ "content": "comment" }).to_string())
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+#[rocket::async_test]
+async fn test_get_file_metadata() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let staging_file_service = client.rocket().state::<Arc<StagingFileService>>().unwrap();
+    let file_service = client.rocket().state::<Arc<FileService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    let (_initial_user, initial_user_session) =
+        create_initial_user(auth_service, user_service).await;
+
+    // the PNG signature followed by an IHDR chunk of a 2x3 image
+    let png_header: &[u8] = &[
+        0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a, 0x00, 0x00, 0x00, 0x0d, 0x49, 0x48, 0x44,
+        0x52, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x03, 0x08, 0x02, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00,
+    ];
+
+    let file = create_file(
+        &client,
+        staging_file_service,
+        file_service,
+        &initial_user_session,
+        "image",
+        Some("image/png"),
+        png_header,
+    )
+    .await;
+
+    let response = client
+        .get(format!("/files/{}", file.id))
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let retrieved_file = response.into_json::<FileWithMetadata>().await.unwrap();
+
+    assert_eq!(status, Status::Ok);
+    assert_eq!(retrieved_file.file, file);
+
+    let metadata = retrieved_file.metadata.unwrap();
+
+    assert_eq!(metadata.width, Some(2));
+    assert_eq!(metadata.height, Some(3));
+
+    let raw_retrieved_metadata = file_service.get_file_metadata_by_id(file.id).await.unwrap();
+
+    assert_eq!(raw_retrieved_metadata, Some(metadata));
+}
+
+#[rocket::async_test]
+async fn test_get_file_data_range_full() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let staging_file_service = client.rocket().state::<Arc<StagingFileService>>().unwrap();
+    let file_service = client.rocket().state::<Arc<FileService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    let (_initial_user, initial_user_session) =
+        create_initial_user(auth_service, user_service).await;
+
+    let file_content = "file content";
+
+    let filled_staging_file = create_filled_staging_file(
+        &client,
+        staging_file_service,
+        &initial_user_session,
+        "file",
+        Some("video/mp4"),
+        file_content,
+    )
+    .await;
+
+    let response = client
+        .post(format!("/files/{}", filled_staging_file.id))
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    let file = response.into_json::<File>().await.unwrap();
+
+    let response = client
+        .get(format!("/files/{}/data", file.id))
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let content_type = response.content_type().unwrap();
+    let retrieved_file_data = response.into_string().await.unwrap();
+
+    assert_eq!(status, Status::Ok);
+    assert!(content_type.is_mp4());
+    assert_eq!(retrieved_file_data, file_content);
+
+    let mut raw_retrieved_file_data = file_service
+        .get_file_data_by_id(file.id, ReadRange::Full)
+        .await
+        .unwrap()
+        .unwrap();
+    let raw_retrieved_file_data = {
+        let mut buffer = String::with_capacity(file_content.len());
+        raw_retrieved_file_data
+            .read_to_string(&mut buffer)
+            .await
+            .unwrap();
+        buffer
+    };
+
+    assert_eq!(raw_retrieved_file_data, file_content);
+}
+
+#[rocket::async_test]
+async fn test_get_file_data_too_many_downloads() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance_with_config(
+        TestFileDriver::Memory,
+        SearchBackend::Meilisearch,
+        |app_config| app_config.transfers.max_concurrent_downloads = 1,
+    )
+    .await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let staging_file_service = client.rocket().state::<Arc<StagingFileService>>().unwrap();
+    let file_service = client.rocket().state::<Arc<FileService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    let (_initial_user, initial_user_session) =
+        create_initial_user(auth_service, user_service).await;
+
+    let file = create_file(
+        &client,
+        staging_file_service,
+        file_service,
+        &initial_user_session,
+        "file",
+        Some("text/plain"),
+        "file content",
+    )
+    .await;
+
+    // the slot is kept until the body of the first download has been dropped
+    let first_response = client
+        .get(format!("/files/{}/data", file.id))
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    assert_eq!(first_response.status(), Status::Ok);
+
+    let response = client
+        .get(format!("/files/{}/data", file.id))
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::ServiceUnavailable);
+    assert_eq!(response.headers().get_one("Retry-After"), Some("5"));
+
+    drop(response);
+    drop(first_response);
+
+    let response = client
+        .get(format!("/files/{}/data", file.id))
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(
+        response.into_string().await,
+        Some("file content".to_owned())
+    );
+}
+
+#[rocket::async_test]
+async fn test_get_file_data_throttled() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance_with_config(
+        TestFileDriver::Memory,
+        SearchBackend::Meilisearch,
+        |app_config| {
+            app_config.transfers.max_download_rate = 8.bytes();
+            app_config.transfers.role_rates.insert(
+                UserRole::Admin.name().to_owned(),
+                AppTransferRates {
+                    max_upload_rate: None,
+                    max_download_rate: Some(0.bytes()),
+                },
+            );
+        },
+    )
+    .await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let staging_file_service = client.rocket().state::<Arc<StagingFileService>>().unwrap();
+    let file_service = client.rocket().state::<Arc<FileService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    let (initial_user, initial_user_session) =
+        create_initial_user(auth_service, user_service).await;
+
+    let file_content = "file content";
+    let file = create_file(
+        &client,
+        staging_file_service,
+        file_service,
+        &initial_user_session,
+        "file",
+        Some("text/plain"),
+        file_content,
+    )
+    .await;
+
+    let started_at = Instant::now();
+    let response = client
+        .get(format!("/files/{}/data", file.id))
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.into_string().await.unwrap(), file_content);
+    // 12 bytes at 8 bytes per second
+    assert!(Duration::from_secs(1) <= started_at.elapsed());
+
+    // admins are not limited
+    user_service
+        .set_user_role_by_id(initial_user.id, UserRole::Admin)
+        .await
+        .unwrap();
+
+    let started_at = Instant::now();
+    let response = client
+        .get(format!("/files/{}/data", file.id))
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.into_string().await.unwrap(), file_content);
+    assert!(started_at.elapsed() < Duration::from_secs(1));
+}
+
+#[rocket::async_test]
+async fn test_get_file_data_cache_headers() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance_with_config(
+        TestFileDriver::Memory,
+        SearchBackend::Meilisearch,
+        |app_config| {
+            app_config.file_data_cache.max_age = 3600;
+            app_config.file_data_cache.public = true;
+        },
+    )
+    .await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let staging_file_service = client.rocket().state::<Arc<StagingFileService>>().unwrap();
+    let file_service = client.rocket().state::<Arc<FileService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    let (_initial_user, initial_user_session) =
+        create_initial_user(auth_service, user_service).await;
+
+    let file = create_file(
+        &client,
+        staging_file_service,
+        file_service,
+        &initial_user_session,
+        "file",
+        Some("text/plain"),
+        "file content",
+    )
+    .await;
+
+    let response = client
+        .get(format!("/files/{}/data", file.id))
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(
+        response.headers().get_one("Cache-Control"),
+        Some("public, max-age=3600")
+    );
+    assert_eq!(response.headers().get_one("Accept-Ranges"), Some("bytes"));
+
+    let etag = response.headers().get_one("ETag").map(str::to_owned);
+    assert!(etag
+        .as_deref()
+        .is_some_and(|etag| etag.contains(&file.id.simple().to_string())));
+
+    // probing the data sends the same caching headers
+    let response = client
+        .head(format!("/files/{}/data", file.id))
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(
+        response.headers().get_one("Cache-Control"),
+        Some("public, max-age=3600")
+    );
+    assert_eq!(response.headers().get_one("ETag").map(str::to_owned), etag);
+
+    // partial responses are cached the same way, under the same `ETag`
+    let response = client
+        .get(format!("/files/{}/data", file.id))
+        .header(Header::new("Range", "bytes=0-3"))
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::PartialContent);
+    assert_eq!(
+        response.headers().get_one("Cache-Control"),
+        Some("public, max-age=3600")
+    );
+    assert_eq!(response.headers().get_one("Accept-Ranges"), Some("bytes"));
+    assert_eq!(response.headers().get_one("ETag").map(str::to_owned), etag);
+    assert_eq!(
+        response.headers().get_one("Content-Range"),
+        Some("bytes 0-3/12")
+    );
+    assert_eq!(response.headers().get_one("Content-Length"), Some("4"));
+    assert_eq!(response.into_string().await.unwrap(), "file");
+
+    let response = client
+        .get(format!("/files/{}/data", file.id))
+        .header(Header::new("Range", "bytes=-7"))
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::PartialContent);
+    assert_eq!(
+        response.headers().get_one("Content-Range"),
+        Some("bytes 5-11/12")
+    );
+    assert_eq!(response.into_string().await.unwrap(), "content");
+
+    // a client holding the same entity tag is told that its copy is still fresh
+    let response = client
+        .get(format!("/files/{}/data", file.id))
+        .header(Header::new("If-None-Match", etag.clone().unwrap()))
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::NotModified);
+    assert_eq!(response.headers().get_one("ETag").map(str::to_owned), etag);
+    assert_eq!(
+        response.headers().get_one("Cache-Control"),
+        Some("public, max-age=3600")
+    );
+
+    let response = client
+        .get(format!("/files/{}/data", file.id))
+        .header(Header::new("If-None-Match", "\"0000000000000000\""))
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.headers().get_one("Content-Length"), Some("12"));
+    assert_eq!(response.headers().get_one("Content-Range"), None);
+}
+
+#[rocket::async_test]
+async fn test_get_file_data_image_transform() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let staging_file_service = client.rocket().state::<Arc<StagingFileService>>().unwrap();
+    let file_service = client.rocket().state::<Arc<FileService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    let (_initial_user, initial_user_session) =
+        create_initial_user(auth_service, user_service).await;
+
+    let file = create_file(
+        &client,
+        staging_file_service,
+        file_service,
+        &initial_user_session,
+        "file",
+        Some("text/plain"),
+        "file content",
+    )
+    .await;
+
+    // image transforms are disabled by default
+    let response = client
+        .get(format!("/files/{}/data?width=100", file.id))
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::NotImplemented);
+
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance_with_config(
+        TestFileDriver::Memory,
+        SearchBackend::Meilisearch,
+        |app_config| app_config.image_transform.enabled = true,
+    )
+    .await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let staging_file_service = client.rocket().state::<Arc<StagingFileService>>().unwrap();
+    let file_service = client.rocket().state::<Arc<FileService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    let (_initial_user, initial_user_session) =
+        create_initial_user(auth_service, user_service).await;
+
+    let file = create_file(
+        &client,
+        staging_file_service,
+        file_service,
+        &initial_user_session,
+        "file",
+        Some("text/plain"),
+        "file content",
+    )
+    .await;
+
+    for query in ["width=0", "height=100000", "format=gif"] {
+        let response = client
+            .get(format!("/files/{}/data?{}", file.id, query))
+            .header(Header::new(
+                "Authorization",
+                format!("Bearer {}", initial_user_session.token),
+            ))
+            .dispatch()
+            .await;
+
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+
+        let body = response.into_json::<serde_json::Value>().await.unwrap();
+        assert_eq!(body["error_code"], "VALIDATION_FAILED");
+    }
+
+    let response = client
+        .get(format!("/files/{}/data?width=100", file.id))
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+
+    let body = response.into_json::<serde_json::Value>().await.unwrap();
+    assert_eq!(body["error_code"], "NOT_AN_IMAGE");
+}
+
+#[rocket::async_test]
+async fn test_get_file_data_range_start() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let staging_file_service = client.rocket().state::<Arc<StagingFileService>>().unwrap();
+    let file_service = client.rocket().state::<Arc<FileService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    let (_initial_user, initial_user_session) =
+        create_initial_user(auth_service, user_service).await;
+
+    let file_content = "file content";
+    let range_start = 5;
+
+    let filled_staging_file = create_filled_staging_file(
+        &client,
+        staging_file_service,
+        &initial_user_session,
+        "file",
+        Some("video/mp4"),
+        file_content,
+    )
+    .await;
+
+    let file_content = &file_content[range_start..];
+
+    let response = client
+        .post(format!("/files/{}", filled_staging_file.id))
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    let file = response.into_json::<File>().await.unwrap();
+
+    let response = client
+        .get(format!("/files/{}/data", file.id))
+        .header(Header::new("Range", format!("bytes={}", range_start)))
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let content_type = response.content_type().unwrap();
+    let retrieved_file_data = response.into_string().await.unwrap();
+
+    assert_eq!(status, Status::PartialContent);
+    assert!(content_type.is_mp4());
+    assert_eq!(retrieved_file_data, file_content);
+
+    let mut raw_retrieved_file_data = file_service
+        .get_file_data_by_id(file.id, ReadRange::Start(range_start as u64))
+        .await
+        .unwrap()
+        .unwrap();
+    let raw_retrieved_file_data = {
+        let mut buffer = String::with_capacity(file_content.len());
+        raw_retrieved_file_data
+            .read_to_string(&mut buffer)
+            .await
+            .unwrap();
+        buffer
+    };
+
+    assert_eq!(raw_retrieved_file_data, file_content);
+}
+
+#[rocket::async_test]
+async fn test_get_file_data_range_min() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let staging_file_service = client.rocket().state::<Arc<StagingFileService>>().unwrap();
+    let file_service = client.rocket().state::<Arc<FileService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    let (_initial_user, initial_user_session) =
+        create_initial_user(auth_service, user_service).await;
+
+    let file_content = "file content";
+    let range_start = 0;
+
+    let filled_staging_file = create_filled_staging_file(
+        &client,
+        staging_file_service,
+        &initial_user_session,
+        "file",
+        Some("video/mp4"),
+        file_content,
+    )
+    .await;
+
+    let file_content = &file_content[range_start..];
+
+    let response = client
+        .post(format!("/files/{}", filled_staging_file.id))
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    let file = response.into_json::<File>().await.unwrap();
+
+    let response = client
+        .get(format!("/files/{}/data", file.id))
+        .header(Header::new("Range", format!("bytes={}", range_start)))
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let content_type = response.content_type().unwrap();
+    let retrieved_file_data = response.into_string().await.unwrap();
+
+    assert_eq!(status, Status::PartialContent);
+    assert!(content_type.is_mp4());
+    assert_eq!(retrieved_file_data, file_content);
+
+    let mut raw_retrieved_file_data = file_service
+        .get_file_data_by_id(file.id, ReadRange::Start(range_start as u64))
+        .await
+        .unwrap()
+        .unwrap();
+    let raw_retrieved_file_data = {
+        let mut buffer = String::with_capacity(file_content.len());
+        raw_retrieved_file_data
+            .read_to_string(&mut buffer)
+            .await
+            .unwrap();
+        buffer
+    };
+
+    assert_eq!(raw_retrieved_file_data, file_content);
+}
+
+#[rocket::async_test]
+async fn test_get_file_data_range_max() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let staging_file_service = client.rocket().state::<Arc<StagingFileService>>().unwrap();
+    let file_service = client.rocket().state::<Arc<FileService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    let (_initial_user, initial_user_session) =
+        create_initial_user(auth_service, user_service).await;
+
+    let file_content = "file content";
+    let range_start = file_content.len() - 1;
+
+    let filled_staging_file = create_filled_staging_file(
+        &client,
+        staging_file_service,
+        &initial_user_session,
+        "file",
+        Some("video/mp4"),
+        file_content,
+    )
+    .await;
+
+    let file_content = &file_content[range_start..];
+
+    let response = client
+        .post(format!("/files/{}", filled_staging_file.id))
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    let file = response.into_json::<File>().await.unwrap();
+
+    let response = client
+        .get(format!("/files/{}/data", file.id))
+        .header(Header::new("Range", format!("bytes={}", range_start)))
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let content_type = response.content_type().unwrap();
+    let retrieved_file_data = response.into_string().await.unwrap();
+
+    assert_eq!(status, Status::PartialContent);
+    assert!(content_type.is_mp4());
+    assert_eq!(retrieved_file_data, file_content);
+
+    let mut raw_retrieved_file_data = file_service
+        .get_file_data_by_id(file.id, ReadRange::Start(range_start as u64))
+        .await
+        .unwrap()
+        .unwrap();
+    let raw_retrieved_file_data = {
+        let mut buffer = String::with_capacity(file_content.len());
+        raw_retrieved_file_data
+            .read_to_string(&mut buffer)
+            .await
+            .unwrap();
+        buffer
+    };
+
+    assert_eq!(raw_retrieved_file_data, file_content);
+}
+
+#[rocket::async_test]
+async fn test_get_file_data_range_end() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let staging_file_service = client.rocket().state::<Arc<StagingFileService>>().unwrap();
+    let file_service = client.rocket().state::<Arc<FileService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    let (_initial_user, initial_user_session) =
+        create_initial_user(auth_service, user_service).await;
+
+    let file_content = "file content";
+    let range_start = 5;
+    let range_end = 10;
+
+    let filled_staging_file = create_filled_staging_file(
+        &client,
+        staging_file_service,
+        &initial_user_session,
+        "file",
+        Some("video/mp4"),
+        file_content,
+    )
+    .await;
+
+    let file_content = &file_content[range_start..=range_end];
+
+    let response = client
+        .post(format!("/files/{}", filled_staging_file.id))
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    let file = response.into_json::<File>().await.unwrap();
+
+    let response = client
+        .get(format!("/files/{}/data", file.id))
+        .header(Header::new(
+            "Range",
+            format!("bytes={}-{}", range_start, range_end),
+        ))
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let content_type = response.content_type().unwrap();
+    let retrieved_file_data = response.into_string().await.unwrap();
+
+    assert_eq!(status, Status::PartialContent);
+    assert!(content_type.is_mp4());
+    assert_eq!(retrieved_file_data, file_content);
+
+    let mut raw_retrieved_file_data = file_service
+        .get_file_data_by_id(
+            file.id,
+            ReadRange::Range(range_start as u64, range_end as u64),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+    let raw_retrieved_file_data = {
+        let mut buffer = String::with_capacity(file_content.len());
+        raw_retrieved_file_data
+            .read_to_string(&mut buffer)
+            .await
+            .unwrap();
+        buffer
+    };
+
+    assert_eq!(raw_retrieved_file_data, file_content);
+}
+
+#[rocket::async_test]
+async fn test_get_file_data_range_end_min() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let staging_file_service = client.rocket().state::<Arc<StagingFileService>>().unwrap();
+    let file_service = client.rocket().state::<Arc<FileService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    let (_initial_user, initial_user_session) =
+        create_initial_user(auth_service, user_service).await;
+
+    let file_content = "file content";
+    let range_start = 5;
+    let range_end = range_start;
+
+    let filled_staging_file = create_filled_staging_file(
+        &client,
+        staging_file_service,
+        &initial_user_session,
+        "file",
+        Some("video/mp4"),
+        file_content,
+    )
+    .await;
+
+    let file_content = &file_content[range_start..=range_end];
+
+    let response = client
+        .post(format!("/files/{}", filled_staging_file.id))
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    let file = response.into_json::<File>().await.unwrap();
+
+    let response = client
+        .get(format!("/files/{}/data", file.id))
+        .header(Header::new(
+            "Range",
+            format!("bytes={}-{}", range_start, range_end),
+        ))
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let content_type = response.content_type().unwrap();
+    let retrieved_file_data = response.into_string().await.unwrap();
+
+    assert_eq!(status, Status::PartialContent);
+    assert!(content_type.is_mp4());
+    assert_eq!(retrieved_file_data, file_content);
+
+    let mut raw_retrieved_file_data = file_service
+        .get_file_data_by_id(
+            file.id,
+            ReadRange::Range(range_start as u64, range_end as u64),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+    let raw_retrieved_file_data = {
+        let mut buffer = String::with_capacity(file_content.len());
+        raw_retrieved_file_data
+            .read_to_string(&mut buffer)
+            .await
+            .unwrap();
+        buffer
+    };
+
+    assert_eq!(raw_retrieved_file_data, file_content);
+}
+
+#[rocket::async_test]
+async fn test_get_file_data_range_end_max() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let staging_file_service = client.rocket().state::<Arc<StagingFileService>>().unwrap();
+    let file_service = client.rocket().state::<Arc<FileService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    let (_initial_user, initial_user_session) =
+        create_initial_user(auth_service, user_service).await;
+
+    let file_content = "file content";
+    let range_start = 5;
+    let range_end = file_content.len() - 1;
+
+    let filled_staging_file = create_filled_staging_file(
+        &client,
+        staging_file_service,
+        &initial_user_session,
+        "file",
+        Some("video/mp4"),
+        file_content,
+    )
+    .await;
+
+    let file_content = &file_content[range_start..=range_end];
+
+    let response = client
+        .post(format!("/files/{}", filled_staging_file.id))
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    let file = response.into_json::<File>().await.unwrap();
+
+    let response = client
+        .get(format!("/files/{}/data", file.id))
+        .header(Header::new(
+            "Range",
+            format!("bytes={}-{}", range_start, range_end),
+        ))
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let content_type = response.content_type().unwrap();
+    let retrieved_file_data = response.into_string().await.unwrap();
+
+    assert_eq!(status, Status::PartialContent);
+    assert!(content_type.is_mp4());
+    assert_eq!(retrieved_file_data, file_content);
+
+    let mut raw_retrieved_file_data = file_service
+        .get_file_data_by_id(
+            file.id,
+            ReadRange::Range(range_start as u64, range_end as u64),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+    let raw_retrieved_file_data = {
+        let mut buffer = String::with_capacity(file_content.len());
+        raw_retrieved_file_data
+            .read_to_string(&mut buffer)
+            .await
+            .unwrap();
+        buffer
+    };
+
+    assert_eq!(raw_retrieved_file_data, file_content);
+}
+
+#[rocket::async_test]
+async fn test_get_file_data_range_suffix() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let staging_file_service = client.rocket().state::<Arc<StagingFileService>>().unwrap();
+    let file_service = client.rocket().state::<Arc<FileService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    let (_initial_user, initial_user_session) =
+        create_initial_user(auth_service, user_service).await;
+
+    let file_content = "file content";
+    let range_suffix = 7;
+
+    let filled_staging_file = create_filled_staging_file(
+        &client,
+        staging_file_service,
+        &initial_user_session,
+        "file",
+        Some("video/mp4"),
+        file_content,
+    )
+    .await;
+
+    let file_content = &file_content[file_content.len() - range_suffix..];
+
+    let response = client
+        .post(format!("/files/{}", filled_staging_file.id))
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    let file = response.into_json::<File>().await.unwrap();
+
+    let response = client
+        .get(format!("/files/{}/data", file.id))
+        .header(Header::new("Range", format!("bytes=-{}", range_suffix)))
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let content_type = response.content_type().unwrap();
+    let retrieved_file_data = response.into_string().await.unwrap();
+
+    assert_eq!(status, Status::PartialContent);
+    assert!(content_type.is_mp4());
+    assert_eq!(retrieved_file_data, file_content);
+
+    let mut raw_retrieved_file_data = file_service
+        .get_file_data_by_id(file.id, ReadRange::Suffix(range_suffix as u32))
+        .await
+        .unwrap()
+        .unwrap();
+    let raw_retrieved_file_data = {
+        let mut buffer = String::with_capacity(file_content.len());
+        raw_retrieved_file_data
+            .read_to_string(&mut buffer)
+            .await
+            .unwrap();
+        buffer
+    };
+
+    assert_eq!(raw_retrieved_file_data, file_content);
+}
+
+#[rocket::async_test]
+async fn test_get_file_data_range_suffix_overflow() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let staging_file_service = client.rocket().state::<Arc<StagingFileService>>().unwrap();
+    let file_service = client.rocket().state::<Arc<FileService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    let (_initial_user, initial_user_session) =
+        create_initial_user(auth_service, user_service).await;
+
+    let file_content = "file content";
+    let range_suffix = 9999;
+
+    let filled_staging_file = create_filled_staging_file(
+        &client,
+        staging_file_service,
+        &initial_user_session,
+        "file",
+        Some("video/mp4"),
+        file_content,
+    )
+    .await;
+
+    let response = client
+        .post(format!("/files/{}", filled_staging_file.id))
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    let file = response.into_json::<File>().await.unwrap();
+
+    let response = client
+        .get(format!("/files/{}/data", file.id))
+        .header(Header::new("Range", format!("bytes=-{}", range_suffix)))
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let content_type = response.content_type().unwrap();
+    let retrieved_file_data = response.into_string().await.unwrap();
+
+    assert_eq!(status, Status::PartialContent);
+    assert!(content_type.is_mp4());
+    assert_eq!(retrieved_file_data, file_content);
+
+    let mut raw_retrieved_file_data = file_service
+        .get_file_data_by_id(file.id, ReadRange::Suffix(range_suffix as u32))
+        .await
+        .unwrap()
+        .unwrap();
+    let raw_retrieved_file_data = {
+        let mut buffer = String::with_capacity(file_content.len());
+        raw_retrieved_file_data
+            .read_to_string(&mut buffer)
+            .await
+            .unwrap();
+        buffer
+    };
+
+    assert_eq!(raw_retrieved_file_data, file_content);
+}
+
+#[rocket::async_test]
+async fn test_get_file_data_content_disposition() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let staging_file_service = client.rocket().state::<Arc<StagingFileService>>().unwrap();
+    let file_service = client.rocket().state::<Arc<FileService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    let (_initial_user, initial_user_session) =
+        create_initial_user(auth_service, user_service).await;
+
+    let file = create_file(
+        &client,
+        staging_file_service,
+        file_service,
+        &initial_user_session,
+        "résumé \"final\".mp4",
+        Some("video/mp4"),
+        "file content",
+    )
+    .await;
+
+    let response = client
+        .get(format!("/files/{}/data", file.id))
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(
+        response.headers().get_one("Content-Disposition"),
+        Some("inline; filename=\"r_sum_ _final_.mp4\"; filename*=UTF-8''r%C3%A9sum%C3%A9%20%22final%22.mp4")
+    );
+
+    let response = client
+        .get(format!("/files/{}/data?download=true", file.id))
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(
+        response.headers().get_one("Content-Disposition"),
+        Some("attachment; filename=\"r_sum_ _final_.mp4\"; filename*=UTF-8''r%C3%A9sum%C3%A9%20%22final%22.mp4")
+    );
+}
+
+#[rocket::async_test]
+async fn test_head_file_data() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let staging_file_service = client.rocket().state::<Arc<StagingFileService>>().unwrap();
+    let file_service = client.rocket().state::<Arc<FileService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    let (_initial_user, initial_user_session) =
+        create_initial_user(auth_service, user_service).await;
+
+    let file_content = "file content";
+
+    let file = create_file(
+        &client,
+        staging_file_service,
+        file_service,
+        &initial_user_session,
+        "file",
+        Some("video/mp4"),
+        file_content,
+    )
+    .await;
+
+    let response = client
+        .head(format!("/files/{}/data", file.id))
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let content_type = response.content_type().unwrap();
+    let content_length = response
+        .headers()
+        .get_one("Content-Length")
+        .map(str::to_owned);
+    let accept_ranges = response
+        .headers()
+        .get_one("Accept-Ranges")
+        .map(str::to_owned);
+    let head_etag = response.headers().get_one("ETag").map(str::to_owned);
+    let retrieved_file_data = response.into_string().await.unwrap_or_default();
+
+    assert_eq!(status, Status::Ok);
+    assert!(content_type.is_mp4());
+    assert_eq!(content_length, Some(file_content.len().to_string()));
+    assert_eq!(accept_ranges.as_deref(), Some("bytes"));
+    assert!(head_etag.is_some());
+    assert!(retrieved_file_data.is_empty());
+
+    let response = client
+        .get(format!("/files/{}/data", file.id))
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    assert_eq!(
+        response.headers().get_one("ETag").map(str::to_owned),
+        head_etag
+    );
+
+    let response = client
+        .head(format!("/files/{}/data", Uuid::new_v4()))
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+#[rocket::async_test]
+async fn test_request_rendition() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let staging_file_service = client.rocket().state::<Arc<StagingFileService>>().unwrap();
+    let file_service = client.rocket().state::<Arc<FileService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    let (_initial_user, initial_user_session) =
+        create_initial_user(auth_service, user_service).await;
+
+    let file = create_file(
+        &client,
+        staging_file_service,
+        file_service,
+        &initial_user_session,
+        "file",
+        Some("video/mp4"),
+        "file content",
+    )
+    .await;
+
+    let response = client
+        .post(format!("/files/{}/renditions/mp4", file.id))
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let job = response.into_json::<TranscodeJob>().await.unwrap();
+
+    assert_eq!(status, Status::Accepted);
+    assert_eq!(job.file_id, file.id);
+    assert_eq!(job.profile, "mp4");
+    assert_eq!(job.status, "pending");
+
+    // the worker is disabled in tests, so the rendition stays pending
+    let response = client
+        .get(format!("/files/{}/renditions/mp4", file.id))
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::Conflict);
+
+    let response = client
+        .get(format!("/files/{}/renditions", file.id))
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let renditions = response.into_json::<RenditionList>().await.unwrap();
+
+    assert_eq!(status, Status::Ok);
+    assert_eq!(renditions.renditions, vec![job]);
+
+    let response = client
+        .post(format!("/files/{}/renditions/unknown", file.id))
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::BadRequest);
+}
+
+#[rocket::async_test]
+async fn test_get_hls_playlist() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let staging_file_service = client.rocket().state::<Arc<StagingFileService>>().unwrap();
+    let file_service = client.rocket().state::<Arc<FileService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    let (_initial_user, initial_user_session) =
+        create_initial_user(auth_service, user_service).await;
+
+    let file = create_file(
+        &client,
+        staging_file_service,
+        file_service,
+        &initial_user_session,
+        "file",
+        Some("video/mp4"),
+        "file content",
+    )
+    .await;
+
+    let response = client
+        .get(format!("/files/{}/hls/playlist.m3u8", file.id))
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::NotFound);
+
+    let response = client
+        .post(format!("/files/{}/renditions/hls", file.id))
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::Accepted);
+
+    // the worker is disabled in tests, so the playlist and segments are not ready
+    let response = client
+        .get(format!("/files/{}/hls/playlist.m3u8", file.id))
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::Conflict);
+
+    let response = client
+        .get(format!("/files/{}/hls/segment00000.ts", file.id))
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::Conflict);
+}
+
+#[rocket::async_test]
+async fn test_get_file_data_tiered() {
+    let (rocket, _database_dropper, _index_dropper) =
+        create_test_rocket_instance_with_file_driver(TestFileDriver::Tiered).await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let staging_file_service = client.rocket().state::<Arc<StagingFileService>>().unwrap();
+    let file_service = client.rocket().state::<Arc<FileService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    let (_initial_user, initial_user_session) =
+        create_initial_user(auth_service, user_service).await;
+
+    let file_content = "file content";
+    let file = create_file(
+        &client,
+        staging_file_service,
+        file_service,
+        &initial_user_session,
+        "file",
+        Some("text/plain"),
+        file_content,
+    )
+    .await;
+
+    let storage_info = file_service
+        .get_file_storage_info_by_id(file.id)
+        .await
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(storage_info.storage.tier.as_deref(), Some("cold"));
+
+    // the first read brings the file into the cache, and the rest are served from it
+    for _ in 0..2 {
+        let mut data = file_service
+            .get_file_data_by_id(file.id, ReadRange::Range(5, 8))
+            .await
+            .unwrap()
+            .unwrap();
+        let mut buffer = String::new();
+        data.read_to_string(&mut buffer).await.unwrap();
+
+        assert_eq!(buffer, &file_content[5..=8]);
+    }
+
+    let storage_info = file_service
+        .get_file_storage_info_by_id(file.id)
+        .await
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(storage_info.storage.tier.as_deref(), Some("hot"));
+
+    let response = client
+        .get(format!("/files/{}/data", file.id))
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.into_string().await.unwrap(), file_content);
+}
+
+#[rocket::async_test]
+async fn test_file_driver_exists_size_and_copy() {
+    for test_file_driver in [
+        TestFileDriver::Memory,
+        TestFileDriver::Local,
+        TestFileDriver::Tiered,
+    ] {
+        let (rocket, _database_dropper, _index_dropper) =
+            create_test_rocket_instance_with_file_driver(test_file_driver).await;
+        let client = Client::tracked(rocket).await.unwrap();
+        let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+        let staging_file_service = client.rocket().state::<Arc<StagingFileService>>().unwrap();
+        let file_service = client.rocket().state::<Arc<FileService>>().unwrap();
+        let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+        let file_driver = client
+            .rocket()
+            .state::<Arc<dyn FileDriver + Send + Sync>>()
+            .unwrap();
+
+        let (_initial_user, initial_user_session) =
+            create_initial_user(auth_service, user_service).await;
+
+        let file_content = "file content";
+        let file = create_file(
+            &client,
+            staging_file_service,
+            file_service,
+            &initial_user_session,
+            "file",
+            Some("text/plain"),
+            file_content,
+        )
+        .await;
+
+        assert!(file_driver.exists(file.id).await.unwrap());
+        assert_eq!(
+            file_driver.size(file.id).await.unwrap(),
+            Some(file_content.len() as u64)
+        );
+
+        let missing_id = Uuid::new_v4();
+
+        assert!(!file_driver.exists(missing_id).await.unwrap());
+        assert_eq!(file_driver.size(missing_id).await.unwrap(), None);
+        assert!(!file_driver.copy(missing_id, Uuid::new_v4()).await.unwrap());
+
+        let copy_id = Uuid::new_v4();
+
+        assert!(file_driver.copy(file.id, copy_id).await.unwrap());
+        assert!(file_driver.exists(copy_id).await.unwrap());
+
+        let mut copy_content = String::new();
+        file_driver
+            .read(copy_id, ReadRange::Full)
+            .await
+            .unwrap()
+            .unwrap()
+            .read_to_string(&mut copy_content)
+            .await
+            .unwrap();
+
+        assert_eq!(copy_content, file_content);
+
+        // the original is kept as is
+        assert_eq!(
+            file_driver.size(file.id).await.unwrap(),
+            Some(file_content.len() as u64)
+        );
+
+        // the copy is not known to the database, so it is removed here
+        file_driver.remove(copy_id).await.unwrap();
+        file_driver.remove(file.id).await.unwrap();
+    }
+}
+
+#[rocket::async_test]
+async fn test_search_files_postgres() {
+    let (rocket, _database_dropper, _index_dropper) =
+        create_test_rocket_instance_with_options(TestFileDriver::Memory, SearchBackend::Postgres)
+            .await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let staging_file_service = client.rocket().state::<Arc<StagingFileService>>().unwrap();
+    let file_service = client.rocket().state::<Arc<FileService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+    let tag_service = client.rocket().state::<Arc<TagService>>().unwrap();
+
+    let (_initial_user, initial_user_session) =
+        create_initial_user(auth_service, user_service).await;
+
+    let photo = create_file(
+        &client,
+        staging_file_service,
+        file_service,
+        &initial_user_session,
+        "holiday photo",
+        Some("image/png"),
+        "photo content",
+    )
+    .await;
+    let note = create_file(
+        &client,
+        staging_file_service,
+        file_service,
+        &initial_user_session,
+        "holiday note",
+        Some("text/plain"),
+        "note content",
+    )
+    .await;
+    create_file(
+        &client,
+        staging_file_service,
+        file_service,
+        &initial_user_session,
+        "report",
+        Some("text/plain"),
+        "report content",
+    )
+    .await;
+
+    tag_service
+        .add_tags_to_files(&[photo.id, note.id], &["year:1959"])
+        .await
+        .unwrap();
+    tag_service
+        .add_tags_to_files(&[photo.id], &["favorite"])
+        .await
+        .unwrap();
+
+    let response = client
+        .post("/files/search")
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .body(
+            serde_json::to_string(&SearchingFile {
+                query: "holiday",
+                filter_mime: None,
+                filter_size: None,
+                filter_hash: None,
+                filter_uploaded_at: None,
+                filter_metadata: Default::default(),
+                filter_tags: vec![TagFilter {
+                    ns: Some("year".to_owned()),
+                    value: "1959".to_owned(),
+                }],
+                filter_attributes: Default::default(),
+                cursor: None,
+                limit: None,
+            })
+            .unwrap(),
+        )
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let result = response.into_json::<FileSearchResult>().await.unwrap();
+
+    assert_eq!(status, Status::Ok);
+    assert_eq!(result.files.len(), 2);
+    assert!(result.files.contains(&photo));
+    assert!(result.files.contains(&note));
+    assert_eq!(result.facets.mime_type_part.get("image"), Some(&1));
+    assert_eq!(result.facets.mime_type_part.get("text"), Some(&1));
+    assert_eq!(result.facets.tags.get("year:1959"), Some(&2));
+    assert_eq!(result.facets.tags.get("favorite"), Some(&1));
+    assert_eq!(result.facets.size_bucket.get("<1MiB"), Some(&2));
+
+    let mut paged_files = vec![];
+    let mut cursor = None;
+
+    loop {
+        let response = client
+            .post("/files/search")
+            .header(Accept::JSON)
+            .header(ContentType::JSON)
+            .header(Header::new(
+                "Authorization",
+                format!("Bearer {}", initial_user_session.token),
+            ))
+            .body(
+                serde_json::to_string(&SearchingFile {
+                    query: "holiday",
+                    filter_mime: None,
+                    filter_size: None,
+                    filter_hash: None,
+                    filter_uploaded_at: None,
+                    filter_metadata: Default::default(),
+                    filter_tags: vec![],
+                    filter_attributes: Default::default(),
+                    cursor: cursor.as_deref(),
+                    limit: Some(1),
+                })
+                .unwrap(),
+            )
+            .dispatch()
+            .await;
+
+        let status = response.status();
+        let result = response.into_json::<FileSearchResult>().await.unwrap();
+
+        assert_eq!(status, Status::Ok);
+        assert!(result.files.len() <= 1);
+
+        paged_files.extend(result.files);
+        cursor = result.next_cursor;
+
+        if cursor.is_none() {
+            break;
+        }
+    }
+
+    assert_eq!(paged_files.len(), 2);
+    assert!(paged_files.contains(&photo));
+    assert!(paged_files.contains(&note));
+
+    let response = client
+        .post("/files/search")
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .body(
+            serde_json::to_string(&SearchingFile {
+                query: "holiday",
+                filter_mime: Some("image"),
+                filter_size: None,
+                filter_hash: None,
+                filter_uploaded_at: None,
+                filter_metadata: Default::default(),
+                filter_tags: vec![],
+                filter_attributes: Default::default(),
+                cursor: None,
+                limit: None,
+            })
+            .unwrap(),
+        )
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let result = response.into_json::<FileSearchResult>().await.unwrap();
+
+    assert_eq!(status, Status::Ok);
+    assert_eq!(result.files, vec![photo]);
+}
+
+#[rocket::async_test]
+async fn test_search_files_by_namespaced_tags() {
+    let (rocket, _database_dropper, _index_dropper) =
+        create_test_rocket_instance_with_options(TestFileDriver::Memory, SearchBackend::Postgres)
+            .await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let staging_file_service = client.rocket().state::<Arc<StagingFileService>>().unwrap();
+    let file_service = client.rocket().state::<Arc<FileService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+    let tag_service = client.rocket().state::<Arc<TagService>>().unwrap();
+
+    let (_initial_user, initial_user_session) =
+        create_initial_user(auth_service, user_service).await;
+
+    let album = create_file(
+        &client,
+        staging_file_service,
+        file_service,
+        &initial_user_session,
+        "album",
+        Some("audio/mpeg"),
+        "album content",
+    )
+    .await;
+    let poster = create_file(
+        &client,
+        staging_file_service,
+        file_service,
+        &initial_user_session,
+        "poster",
+        Some("image/png"),
+        "poster content",
+    )
+    .await;
+    let notes = create_file(
+        &client,
+        staging_file_service,
+        file_service,
+        &initial_user_session,
+        "notes",
+        Some("text/plain"),
+        "notes content",
+    )
+    .await;
+
+    tag_service
+        .add_tags_to_files(&[album.id], &["artist:miles-davis", "year:1959"])
+        .await
+        .unwrap();
+    tag_service
+        .add_tags_to_files(&[poster.id], &["1959"])
+        .await
+        .unwrap();
+    tag_service
+        .add_tags_to_files(&[notes.id], &["artist:1959"])
+        .await
+        .unwrap();
+
+    let search = |filter_tags: Vec<TagFilter>| {
+        let client = &client;
+        let initial_user_session = &initial_user_session;
+
+        async move {
+            let response = client
+                .post("/files/search")
+                .header(Accept::JSON)
+                .header(ContentType::JSON)
+                .header(Header::new(
+                    "Authorization",
+                    format!("Bearer {}", initial_user_session.token),
+                ))
+                .body(
+                    serde_json::to_string(&SearchingFile {
+                        query: "",
+                        filter_mime: None,
+                        filter_size: None,
+                        filter_hash: None,
+                        filter_uploaded_at: None,
+                        filter_metadata: Default::default(),
+                        filter_tags,
+                        filter_attributes: Default::default(),
+                        cursor: None,
+                        limit: None,
+                    })
+                    .unwrap(),
+                )
+                .dispatch()
+                .await;
+
+            assert_eq!(response.status(), Status::Ok);
+
+            let mut files = response
+                .into_json::<FileSearchResult>()
+                .await
+                .unwrap()
+                .files;
+            files.sort_by_key(|file| file.id);
+            files
+        }
+    };
+    let tag_filter = |ns: Option<&str>, value: &str| TagFilter {
+        ns: ns.map(|ns| ns.to_owned()),
+        value: value.to_owned(),
+    };
+    let sorted = |mut files: Vec<File>| {
+        files.sort_by_key(|file| file.id);
+        files
+    };
+
+    // a namespace matches only the tags in it
+    assert_eq!(
+        search(vec![tag_filter(Some("year"), "1959")]).await,
+        vec![album.clone()]
+    );
+    assert_eq!(
+        search(vec![tag_filter(Some("artist"), "1959")]).await,
+        vec![notes.clone()]
+    );
+
+    // no namespace matches the value in any namespace, including none
+    assert_eq!(
+        search(vec![tag_filter(None, "1959")]).await,
+        sorted(vec![album.clone(), poster.clone(), notes.clone()])
+    );
+
+    // every filter has to match
+    assert_eq!(
+        search(vec![
+            tag_filter(Some("artist"), "miles-davis"),
+            tag_filter(None, "1959"),
+        ])
+        .await,
+        vec![album.clone()]
+    );
+    assert_eq!(
+        search(vec![tag_filter(Some("year"), "miles-davis")]).await,
+        vec![]
+    );
+}
+
+#[rocket::async_test]
+async fn test_search_files_facets() {
+    let (rocket, _database_dropper, _index_dropper) =
+        create_test_rocket_instance_with_options(TestFileDriver::Memory, SearchBackend::Postgres)
+            .await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let staging_file_service = client.rocket().state::<Arc<StagingFileService>>().unwrap();
+    let file_service = client.rocket().state::<Arc<FileService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+    let tag_service = client.rocket().state::<Arc<TagService>>().unwrap();
+
+    let (_initial_user, initial_user_session) =
+        create_initial_user(auth_service, user_service).await;
+
+    let large_photo = create_file(
+        &client,
+        staging_file_service,
+        file_service,
+        &initial_user_session,
+        "large photo",
+        Some("image/png"),
+        &"a".repeat(2 << 20),
+    )
+    .await;
+    let small_photo = create_file(
+        &client,
+        staging_file_service,
+        file_service,
+        &initial_user_session,
+        "small photo",
+        Some("image/jpeg"),
+        "small photo content",
+    )
+    .await;
+    create_file(
+        &client,
+        staging_file_service,
+        file_service,
+        &initial_user_session,
+        "note",
+        Some("text/plain"),
+        "note content",
+    )
+    .await;
+
+    tag_service
+        .add_tags_to_files(&[large_photo.id, small_photo.id], &["year:1959"])
+        .await
+        .unwrap();
+    tag_service
+        .add_tags_to_files(&[large_photo.id], &["favorite"])
+        .await
+        .unwrap();
+
+    // the facets count every matching file, not only the ones in the page
+    let response = client
+        .post("/files/search")
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .body(
+            serde_json::to_string(&SearchingFile {
+                query: "",
+                filter_mime: None,
+                filter_size: None,
+                filter_hash: None,
+                filter_uploaded_at: None,
+                filter_metadata: Default::default(),
+                filter_tags: vec![],
+                filter_attributes: Default::default(),
+                cursor: None,
+                limit: Some(1),
+            })
+            .unwrap(),
+        )
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let result = response.into_json::<FileSearchResult>().await.unwrap();
+
+    assert_eq!(status, Status::Ok);
+    assert_eq!(result.files.len(), 1);
+    assert_eq!(
+        result.facets.mime_type_part,
+        BTreeMap::from([("image".to_owned(), 2), ("text".to_owned(), 1)])
+    );
+    assert_eq!(
+        result.facets.tags,
+        BTreeMap::from([("favorite".to_owned(), 1), ("year:1959".to_owned(), 2)])
+    );
+    assert_eq!(
+        result.facets.size_bucket,
+        BTreeMap::from([("<1MiB".to_owned(), 2), ("1MiB-10MiB".to_owned(), 1)])
+    );
+
+    // and only the files matching the filters
+    let response = client
+        .post("/files/search")
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .body(
+            serde_json::to_string(&SearchingFile {
+                query: "",
+                filter_mime: Some("text"),
+                filter_size: None,
+                filter_hash: None,
+                filter_uploaded_at: None,
+                filter_metadata: Default::default(),
+                filter_tags: vec![],
+                filter_attributes: Default::default(),
+                cursor: None,
+                limit: None,
+            })
+            .unwrap(),
+        )
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let result = response.into_json::<FileSearchResult>().await.unwrap();
+
+    assert_eq!(status, Status::Ok);
+    assert_eq!(
+        result.facets.mime_type_part,
+        BTreeMap::from([("text".to_owned(), 1)])
+    );
+    assert_eq!(result.facets.tags, BTreeMap::new());
+    assert_eq!(
+        result.facets.size_bucket,
+        BTreeMap::from([("<1MiB".to_owned(), 1)])
+    );
+}
+
+#[rocket::async_test]
+async fn test_file_attributes() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance_with_config(
+        TestFileDriver::Memory,
+        SearchBackend::Postgres,
+        |app_config| {
+            app_config.search.filterable_file_attributes = vec!["camera".to_owned()];
+        },
+    )
+    .await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let staging_file_service = client.rocket().state::<Arc<StagingFileService>>().unwrap();
+    let file_service = client.rocket().state::<Arc<FileService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    let (_initial_user, initial_user_session) =
+        create_initial_user(auth_service, user_service).await;
+
+    let photo = create_file(
+        &client,
+        staging_file_service,
+        file_service,
+        &initial_user_session,
+        "holiday photo",
+        Some("image/png"),
+        "photo content",
+    )
+    .await;
+    create_file(
+        &client,
+        staging_file_service,
+        file_service,
+        &initial_user_session,
+        "holiday note",
+        Some("text/plain"),
+        "note content",
+    )
+    .await;
+
+    let attributes = FileAttributes {
+        attributes: [
+            ("camera".to_owned(), "X100V".to_owned()),
+            ("project".to_owned(), "alpha".to_owned()),
+        ]
+        .into_iter()
+        .collect(),
+    };
+    let response = client
+        .put(format!("/files/{}/attributes", photo.id))
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .body(serde_json::to_string(&attributes).unwrap())
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::Ok);
+
+    let response = client
+        .get(format!("/files/{}/attributes", photo.id))
+        .header(Accept::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let result = response.into_json::<FileAttributes>().await.unwrap();
+
+    assert_eq!(status, Status::Ok);
+    assert_eq!(result.attributes, attributes.attributes);
+
+    let searching_file = SearchingFile {
+        query: "holiday",
+        filter_mime: None,
+        filter_size: None,
+        filter_hash: None,
+        filter_uploaded_at: None,
+        filter_metadata: Default::default(),
+        filter_tags: vec![],
+        filter_attributes: [("camera".to_owned(), "X100V".to_owned())]
+            .into_iter()
+            .collect(),
+        cursor: None,
+        limit: None,
+    };
+    let response = client
+        .post("/files/search")
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .body(serde_json::to_string(&searching_file).unwrap())
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let result = response.into_json::<FileSearchResult>().await.unwrap();
+
+    assert_eq!(status, Status::Ok);
+    assert_eq!(result.files, vec![photo.clone()]);
+
+    // only the whitelisted attributes can be filtered on
+    let response = client
+        .post("/files/search")
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .body(
+            serde_json::to_string(&SearchingFile {
+                filter_attributes: [("project".to_owned(), "alpha".to_owned())]
+                    .into_iter()
+                    .collect(),
+                ..searching_file
+            })
+            .unwrap(),
+        )
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+
+    let response = client
+        .put(format!("/files/{}/attributes", photo.id))
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .body(r#"{"attributes":{"not a key":"value"}}"#)
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+
+    let response = client
+        .put(format!("/files/{}/attributes", Uuid::new_v4()))
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .body(serde_json::to_string(&attributes).unwrap())
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::NotFound);
 }
 
 #[rocket::async_test]
-async fn test_get_file_data_range_start() {
+async fn test_favorite_file() {
     let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
     let client = Client::tracked(rocket).await.unwrap();
     let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
@@ -458,25 +3918,52 @@ async fn test_get_file_data_range_start() {
     let (_initial_user, initial_user_session) =
         create_initial_user(auth_service, user_service).await;
 
-    let file_content = "file content";
-    let range_start = 5;
-
-    let filled_staging_file = create_filled_staging_file(
+    let favorite_file = create_file(
         &client,
         staging_file_service,
+        file_service,
         &initial_user_session,
-        "file",
-        Some("video/mp4"),
-        file_content,
+        "a",
+        Some("text/plain"),
+        "favorite",
+    )
+    .await;
+    let other_file = create_file(
+        &client,
+        staging_file_service,
+        file_service,
+        &initial_user_session,
+        "b",
+        Some("text/plain"),
+        "other",
     )
     .await;
 
-    let file_content = &file_content[range_start..];
+    let response = client
+        .put(format!("/files/{}/favorite", favorite_file.id))
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::NoContent);
 
     let response = client
-        .post(format!("/files/{}", filled_staging_file.id))
+        .put(format!("/files/{}/favorite", Uuid::new_v4()))
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::NotFound);
+
+    let response = client
+        .get("/files")
         .header(Accept::JSON)
-        .header(ContentType::JSON)
         .header(Header::new(
             "Authorization",
             format!("Bearer {}", initial_user_session.token),
@@ -484,11 +3971,20 @@ async fn test_get_file_data_range_start() {
         .dispatch()
         .await;
 
-    let file = response.into_json::<File>().await.unwrap();
+    let status = response.status();
+    let retrieved_files = response.into_json::<FileList>().await.unwrap();
+
+    assert_eq!(status, Status::Ok);
+    assert_eq!(
+        unlist_files(&retrieved_files.files),
+        vec![favorite_file.clone(), other_file.clone()]
+    );
+    assert!(retrieved_files.files[0].is_favorite);
+    assert!(!retrieved_files.files[1].is_favorite);
 
     let response = client
-        .get(format!("/files/{}/data", file.id))
-        .header(Header::new("Range", format!("bytes={}", range_start)))
+        .get("/users/me/favorites")
+        .header(Accept::JSON)
         .header(Header::new(
             "Authorization",
             format!("Bearer {}", initial_user_session.token),
@@ -497,32 +3993,37 @@ async fn test_get_file_data_range_start() {
         .await;
 
     let status = response.status();
-    let content_type = response.content_type().unwrap();
-    let retrieved_file_data = response.into_string().await.unwrap();
+    let favorites = response.into_json::<FavoriteList>().await.unwrap();
 
-    assert_eq!(status, Status::PartialContent);
-    assert!(content_type.is_mp4());
-    assert_eq!(retrieved_file_data, file_content);
+    assert_eq!(status, Status::Ok);
+    assert_eq!(favorites.files, vec![favorite_file.clone()]);
+    assert!(favorites.collections.is_empty());
 
-    let mut raw_retrieved_file_data = file_service
-        .get_file_data_by_id(file.id, ReadRange::Start(range_start as u64))
-        .await
-        .unwrap()
-        .unwrap();
-    let raw_retrieved_file_data = {
-        let mut buffer = String::with_capacity(file_content.len());
-        raw_retrieved_file_data
-            .read_to_string(&mut buffer)
-            .await
-            .unwrap();
-        buffer
-    };
+    let response = client
+        .delete(format!("/files/{}/favorite", favorite_file.id))
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
 
-    assert_eq!(raw_retrieved_file_data, file_content);
+    assert_eq!(response.status(), Status::NoContent);
+
+    let response = client
+        .delete(format!("/files/{}/favorite", favorite_file.id))
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::NotFound);
 }
 
 #[rocket::async_test]
-async fn test_get_file_data_range_min() {
+async fn test_get_recent_files() {
     let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
     let client = Client::tracked(rocket).await.unwrap();
     let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
@@ -533,25 +4034,30 @@ async fn test_get_file_data_range_min() {
     let (_initial_user, initial_user_session) =
         create_initial_user(auth_service, user_service).await;
 
-    let file_content = "file content";
-    let range_start = 0;
-
-    let filled_staging_file = create_filled_staging_file(
+    let older_file = create_file(
         &client,
         staging_file_service,
+        file_service,
         &initial_user_session,
-        "file",
-        Some("video/mp4"),
-        file_content,
+        "older",
+        Some("text/plain"),
+        "older",
+    )
+    .await;
+    let newer_file = create_file(
+        &client,
+        staging_file_service,
+        file_service,
+        &initial_user_session,
+        "newer",
+        Some("text/plain"),
+        "newer",
     )
     .await;
-
-    let file_content = &file_content[range_start..];
 
     let response = client
-        .post(format!("/files/{}", filled_staging_file.id))
+        .get("/files/recent?kind=uploaded")
         .header(Accept::JSON)
-        .header(ContentType::JSON)
         .header(Header::new(
             "Authorization",
             format!("Bearer {}", initial_user_session.token),
@@ -559,11 +4065,18 @@ async fn test_get_file_data_range_min() {
         .dispatch()
         .await;
 
-    let file = response.into_json::<File>().await.unwrap();
+    let status = response.status();
+    let recent_files = response.into_json::<RecentFileList>().await.unwrap();
+
+    assert_eq!(status, Status::Ok);
+    assert_eq!(
+        unlist_files(&recent_files.files),
+        vec![newer_file.clone(), older_file.clone()]
+    );
 
     let response = client
-        .get(format!("/files/{}/data", file.id))
-        .header(Header::new("Range", format!("bytes={}", range_start)))
+        .get("/files/recent?kind=viewed")
+        .header(Accept::JSON)
         .header(Header::new(
             "Authorization",
             format!("Bearer {}", initial_user_session.token),
@@ -572,61 +4085,13 @@ async fn test_get_file_data_range_min() {
         .await;
 
     let status = response.status();
-    let content_type = response.content_type().unwrap();
-    let retrieved_file_data = response.into_string().await.unwrap();
-
-    assert_eq!(status, Status::PartialContent);
-    assert!(content_type.is_mp4());
-    assert_eq!(retrieved_file_data, file_content);
-
-    let mut raw_retrieved_file_data = file_service
-        .get_file_data_by_id(file.id, ReadRange::Start(range_start as u64))
-        .await
-        .unwrap()
-        .unwrap();
-    let raw_retrieved_file_data = {
-        let mut buffer = String::with_capacity(file_content.len());
-        raw_retrieved_file_data
-            .read_to_string(&mut buffer)
-            .await
-            .unwrap();
-        buffer
-    };
-
-    assert_eq!(raw_retrieved_file_data, file_content);
-}
-
-#[rocket::async_test]
-async fn test_get_file_data_range_max() {
-    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
-    let client = Client::tracked(rocket).await.unwrap();
-    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
-    let staging_file_service = client.rocket().state::<Arc<StagingFileService>>().unwrap();
-    let file_service = client.rocket().state::<Arc<FileService>>().unwrap();
-    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
-
-    let (_initial_user, initial_user_session) =
-        create_initial_user(auth_service, user_service).await;
-
-    let file_content = "file content";
-    let range_start = file_content.len() - 1;
-
-    let filled_staging_file = create_filled_staging_file(
-        &client,
-        staging_file_service,
-        &initial_user_session,
-        "file",
-        Some("video/mp4"),
-        file_content,
-    )
-    .await;
+    let recent_files = response.into_json::<RecentFileList>().await.unwrap();
 
-    let file_content = &file_content[range_start..];
+    assert_eq!(status, Status::Ok);
+    assert!(recent_files.files.is_empty());
 
     let response = client
-        .post(format!("/files/{}", filled_staging_file.id))
-        .header(Accept::JSON)
-        .header(ContentType::JSON)
+        .get(format!("/files/{}/data", older_file.id))
         .header(Header::new(
             "Authorization",
             format!("Bearer {}", initial_user_session.token),
@@ -634,11 +4099,11 @@ async fn test_get_file_data_range_max() {
         .dispatch()
         .await;
 
-    let file = response.into_json::<File>().await.unwrap();
+    assert_eq!(response.status(), Status::Ok);
 
     let response = client
-        .get(format!("/files/{}/data", file.id))
-        .header(Header::new("Range", format!("bytes={}", range_start)))
+        .get("/files/recent?kind=viewed")
+        .header(Accept::JSON)
         .header(Header::new(
             "Authorization",
             format!("Bearer {}", initial_user_session.token),
@@ -647,32 +4112,26 @@ async fn test_get_file_data_range_max() {
         .await;
 
     let status = response.status();
-    let content_type = response.content_type().unwrap();
-    let retrieved_file_data = response.into_string().await.unwrap();
+    let recent_files = response.into_json::<RecentFileList>().await.unwrap();
 
-    assert_eq!(status, Status::PartialContent);
-    assert!(content_type.is_mp4());
-    assert_eq!(retrieved_file_data, file_content);
+    assert_eq!(status, Status::Ok);
+    assert_eq!(unlist_files(&recent_files.files), vec![older_file]);
 
-    let mut raw_retrieved_file_data = file_service
-        .get_file_data_by_id(file.id, ReadRange::Start(range_start as u64))
-        .await
-        .unwrap()
-        .unwrap();
-    let raw_retrieved_file_data = {
-        let mut buffer = String::with_capacity(file_content.len());
-        raw_retrieved_file_data
-            .read_to_string(&mut buffer)
-            .await
-            .unwrap();
-        buffer
-    };
+    let response = client
+        .get("/files/recent?kind=unknown")
+        .header(Accept::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
 
-    assert_eq!(raw_retrieved_file_data, file_content);
+    assert_eq!(response.status(), Status::BadRequest);
 }
 
 #[rocket::async_test]
-async fn test_get_file_data_range_end() {
+async fn test_get_random_files() {
     let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
     let client = Client::tracked(rocket).await.unwrap();
     let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
@@ -683,26 +4142,36 @@ async fn test_get_file_data_range_end() {
     let (_initial_user, initial_user_session) =
         create_initial_user(auth_service, user_service).await;
 
-    let file_content = "file content";
-    let range_start = 5;
-    let range_end = 10;
+    let mut image_files = Vec::new();
 
-    let filled_staging_file = create_filled_staging_file(
+    for index in 0..3 {
+        let file = create_file(
+            &client,
+            staging_file_service,
+            file_service,
+            &initial_user_session,
+            &format!("image{}", index),
+            Some("image/png"),
+            &format!("image{}", index),
+        )
+        .await;
+        image_files.push(file);
+    }
+
+    create_file(
         &client,
         staging_file_service,
+        file_service,
         &initial_user_session,
-        "file",
+        "video",
         Some("video/mp4"),
-        file_content,
+        "video",
     )
     .await;
 
-    let file_content = &file_content[range_start..=range_end];
-
     let response = client
-        .post(format!("/files/{}", filled_staging_file.id))
+        .get("/files/random?count=10&filter_mime=image/*")
         .header(Accept::JSON)
-        .header(ContentType::JSON)
         .header(Header::new(
             "Authorization",
             format!("Bearer {}", initial_user_session.token),
@@ -710,54 +4179,44 @@ async fn test_get_file_data_range_end() {
         .dispatch()
         .await;
 
-    let file = response.into_json::<File>().await.unwrap();
+    let status = response.status();
+    let random_files = response.into_json::<RandomFileList>().await.unwrap();
+
+    assert_eq!(status, Status::Ok);
+    assert_eq!(random_files.count, 10);
+
+    let mut files = unlist_files(&random_files.files);
+    files.sort_by(|a, b| a.name.cmp(&b.name));
+
+    assert_eq!(files, image_files);
 
     let response = client
-        .get(format!("/files/{}/data", file.id))
-        .header(Header::new(
-            "Range",
-            format!("bytes={}-{}", range_start, range_end),
-        ))
+        .get("/files/random?count=2")
+        .header(Accept::JSON)
         .header(Header::new(
             "Authorization",
-            format!("Bearer {}", initial_user_session.token),
-        ))
-        .dispatch()
-        .await;
-
-    let status = response.status();
-    let content_type = response.content_type().unwrap();
-    let retrieved_file_data = response.into_string().await.unwrap();
-
-    assert_eq!(status, Status::PartialContent);
-    assert!(content_type.is_mp4());
-    assert_eq!(retrieved_file_data, file_content);
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
 
-    let mut raw_retrieved_file_data = file_service
-        .get_file_data_by_id(
-            file.id,
-            ReadRange::Range(range_start as u64, range_end as u64),
-        )
-        .await
-        .unwrap()
-        .unwrap();
-    let raw_retrieved_file_data = {
-        let mut buffer = String::with_capacity(file_content.len());
-        raw_retrieved_file_data
-            .read_to_string(&mut buffer)
-            .await
-            .unwrap();
-        buffer
-    };
+    let status = response.status();
+    let random_files = response.into_json::<RandomFileList>().await.unwrap();
 
-    assert_eq!(raw_retrieved_file_data, file_content);
+    assert_eq!(status, Status::Ok);
+    assert_eq!(random_files.files.len(), 2);
 }
 
 #[rocket::async_test]
-async fn test_get_file_data_range_end_min() {
+async fn test_get_uncollected_files() {
     let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
     let client = Client::tracked(rocket).await.unwrap();
     let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let collection_service = client.rocket().state::<Arc<CollectionService>>().unwrap();
+    let collection_file_pair_service = client
+        .rocket()
+        .state::<Arc<CollectionFilePairService>>()
+        .unwrap();
     let staging_file_service = client.rocket().state::<Arc<StagingFileService>>().unwrap();
     let file_service = client.rocket().state::<Arc<FileService>>().unwrap();
     let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
@@ -765,41 +4224,40 @@ async fn test_get_file_data_range_end_min() {
     let (_initial_user, initial_user_session) =
         create_initial_user(auth_service, user_service).await;
 
-    let file_content = "file content";
-    let range_start = 5;
-    let range_end = range_start;
+    let collection = collection_service
+        .create_collection("collection", None, false, None)
+        .await
+        .unwrap();
 
-    let filled_staging_file = create_filled_staging_file(
+    let collected_file = create_file(
         &client,
         staging_file_service,
+        file_service,
         &initial_user_session,
-        "file",
-        Some("video/mp4"),
-        file_content,
+        "collected",
+        Some("text/plain"),
+        "collected",
+    )
+    .await;
+    let uncollected_file = create_file(
+        &client,
+        staging_file_service,
+        file_service,
+        &initial_user_session,
+        "uncollected",
+        Some("text/plain"),
+        "uncollected",
     )
     .await;
 
-    let file_content = &file_content[range_start..=range_end];
+    collection_file_pair_service
+        .add_file_to_collection(collection.id, collected_file.id, None)
+        .await
+        .unwrap();
 
     let response = client
-        .post(format!("/files/{}", filled_staging_file.id))
+        .get("/files/uncollected?with_total=true")
         .header(Accept::JSON)
-        .header(ContentType::JSON)
-        .header(Header::new(
-            "Authorization",
-            format!("Bearer {}", initial_user_session.token),
-        ))
-        .dispatch()
-        .await;
-
-    let file = response.into_json::<File>().await.unwrap();
-
-    let response = client
-        .get(format!("/files/{}/data", file.id))
-        .header(Header::new(
-            "Range",
-            format!("bytes={}-{}", range_start, range_end),
-        ))
         .header(Header::new(
             "Authorization",
             format!("Bearer {}", initial_user_session.token),
@@ -808,65 +4266,66 @@ async fn test_get_file_data_range_end_min() {
         .await;
 
     let status = response.status();
-    let content_type = response.content_type().unwrap();
-    let retrieved_file_data = response.into_string().await.unwrap();
-
-    assert_eq!(status, Status::PartialContent);
-    assert!(content_type.is_mp4());
-    assert_eq!(retrieved_file_data, file_content);
-
-    let mut raw_retrieved_file_data = file_service
-        .get_file_data_by_id(
-            file.id,
-            ReadRange::Range(range_start as u64, range_end as u64),
-        )
-        .await
-        .unwrap()
-        .unwrap();
-    let raw_retrieved_file_data = {
-        let mut buffer = String::with_capacity(file_content.len());
-        raw_retrieved_file_data
-            .read_to_string(&mut buffer)
-            .await
-            .unwrap();
-        buffer
-    };
+    let file_list = response.into_json::<FileList>().await.unwrap();
 
-    assert_eq!(raw_retrieved_file_data, file_content);
+    assert_eq!(status, Status::Ok);
+    assert_eq!(unlist_files(&file_list.files), vec![uncollected_file]);
+    assert_eq!(file_list.next_cursor, None);
+    assert_eq!(file_list.total, Some(1));
 }
 
 #[rocket::async_test]
-async fn test_get_file_data_range_end_max() {
+async fn test_get_untagged_files() {
     let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
     let client = Client::tracked(rocket).await.unwrap();
     let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
     let staging_file_service = client.rocket().state::<Arc<StagingFileService>>().unwrap();
     let file_service = client.rocket().state::<Arc<FileService>>().unwrap();
+    let tag_service = client.rocket().state::<Arc<TagService>>().unwrap();
     let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
 
     let (_initial_user, initial_user_session) =
         create_initial_user(auth_service, user_service).await;
 
-    let file_content = "file content";
-    let range_start = 5;
-    let range_end = file_content.len() - 1;
-
-    let filled_staging_file = create_filled_staging_file(
+    let tagged_photo = create_file(
         &client,
         staging_file_service,
+        file_service,
         &initial_user_session,
-        "file",
-        Some("video/mp4"),
-        file_content,
+        "tagged photo",
+        Some("image/jpeg"),
+        "tagged photo content",
+    )
+    .await;
+    let untagged_photo = create_file(
+        &client,
+        staging_file_service,
+        file_service,
+        &initial_user_session,
+        "untagged photo",
+        Some("image/jpeg"),
+        "untagged photo content",
+    )
+    .await;
+    create_file(
+        &client,
+        staging_file_service,
+        file_service,
+        &initial_user_session,
+        "untagged note",
+        Some("text/plain"),
+        "untagged note content",
     )
     .await;
 
-    let file_content = &file_content[range_start..=range_end];
+    tag_service
+        .add_tags_to_files(&[tagged_photo.id], &["favorite"])
+        .await
+        .unwrap();
 
     let response = client
-        .post(format!("/files/{}", filled_staging_file.id))
+        .get("/files/untagged?mime=image/*")
         .header(Accept::JSON)
-        .header(ContentType::JSON)
         .header(Header::new(
             "Authorization",
             format!("Bearer {}", initial_user_session.token),
@@ -874,14 +4333,16 @@ async fn test_get_file_data_range_end_max() {
         .dispatch()
         .await;
 
-    let file = response.into_json::<File>().await.unwrap();
+    let status = response.status();
+    let file_list = response.into_json::<FileList>().await.unwrap();
+
+    assert_eq!(status, Status::Ok);
+    assert_eq!(unlist_files(&file_list.files), vec![untagged_photo]);
+    assert_eq!(file_list.total, Some(1));
 
     let response = client
-        .get(format!("/files/{}/data", file.id))
-        .header(Header::new(
-            "Range",
-            format!("bytes={}-{}", range_start, range_end),
-        ))
+        .get("/files/untagged")
+        .header(Accept::JSON)
         .header(Header::new(
             "Authorization",
             format!("Bearer {}", initial_user_session.token),
@@ -890,35 +4351,15 @@ async fn test_get_file_data_range_end_max() {
         .await;
 
     let status = response.status();
-    let content_type = response.content_type().unwrap();
-    let retrieved_file_data = response.into_string().await.unwrap();
-
-    assert_eq!(status, Status::PartialContent);
-    assert!(content_type.is_mp4());
-    assert_eq!(retrieved_file_data, file_content);
-
-    let mut raw_retrieved_file_data = file_service
-        .get_file_data_by_id(
-            file.id,
-            ReadRange::Range(range_start as u64, range_end as u64),
-        )
-        .await
-        .unwrap()
-        .unwrap();
-    let raw_retrieved_file_data = {
-        let mut buffer = String::with_capacity(file_content.len());
-        raw_retrieved_file_data
-            .read_to_string(&mut buffer)
-            .await
-            .unwrap();
-        buffer
-    };
+    let file_list = response.into_json::<FileList>().await.unwrap();
 
-    assert_eq!(raw_retrieved_file_data, file_content);
+    assert_eq!(status, Status::Ok);
+    assert_eq!(file_list.files.len(), 2);
+    assert_eq!(file_list.total, Some(2));
 }
 
 #[rocket::async_test]
-async fn test_get_file_data_range_suffix() {
+async fn test_get_similar_files_without_hash() {
     let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
     let client = Client::tracked(rocket).await.unwrap();
     let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
@@ -929,25 +4370,21 @@ async fn test_get_file_data_range_suffix() {
     let (_initial_user, initial_user_session) =
         create_initial_user(auth_service, user_service).await;
 
-    let file_content = "file content";
-    let range_suffix = 7;
-
-    let filled_staging_file = create_filled_staging_file(
+    let file = create_file(
         &client,
         staging_file_service,
+        file_service,
         &initial_user_session,
-        "file",
-        Some("video/mp4"),
-        file_content,
+        "photo",
+        Some("image/png"),
+        "photo content",
     )
     .await;
 
-    let file_content = &file_content[file_content.len() - range_suffix..];
-
+    // perceptual hashing is disabled by default, so the file has no hash to compare
     let response = client
-        .post(format!("/files/{}", filled_staging_file.id))
+        .get(format!("/files/{}/similar", file.id))
         .header(Accept::JSON)
-        .header(ContentType::JSON)
         .header(Header::new(
             "Authorization",
             format!("Bearer {}", initial_user_session.token),
@@ -955,11 +4392,15 @@ async fn test_get_file_data_range_suffix() {
         .dispatch()
         .await;
 
-    let file = response.into_json::<File>().await.unwrap();
+    let status = response.status();
+    let similar_files = response.into_json::<SimilarFileList>().await.unwrap();
+
+    assert_eq!(status, Status::Ok);
+    assert!(similar_files.files.is_empty());
 
     let response = client
-        .get(format!("/files/{}/data", file.id))
-        .header(Header::new("Range", format!("bytes=-{}", range_suffix)))
+        .get(format!("/files/{}/similar", Uuid::new_v4()))
+        .header(Accept::JSON)
         .header(Header::new(
             "Authorization",
             format!("Bearer {}", initial_user_session.token),
@@ -967,33 +4408,11 @@ async fn test_get_file_data_range_suffix() {
         .dispatch()
         .await;
 
-    let status = response.status();
-    let content_type = response.content_type().unwrap();
-    let retrieved_file_data = response.into_string().await.unwrap();
-
-    assert_eq!(status, Status::PartialContent);
-    assert!(content_type.is_mp4());
-    assert_eq!(retrieved_file_data, file_content);
-
-    let mut raw_retrieved_file_data = file_service
-        .get_file_data_by_id(file.id, ReadRange::Suffix(range_suffix as u32))
-        .await
-        .unwrap()
-        .unwrap();
-    let raw_retrieved_file_data = {
-        let mut buffer = String::with_capacity(file_content.len());
-        raw_retrieved_file_data
-            .read_to_string(&mut buffer)
-            .await
-            .unwrap();
-        buffer
-    };
-
-    assert_eq!(raw_retrieved_file_data, file_content);
+    assert_eq!(response.status(), Status::NotFound);
 }
 
 #[rocket::async_test]
-async fn test_get_file_data_range_suffix_overflow() {
+async fn test_get_file_stats() {
     let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
     let client = Client::tracked(rocket).await.unwrap();
     let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
@@ -1004,23 +4423,56 @@ async fn test_get_file_data_range_suffix_overflow() {
     let (_initial_user, initial_user_session) =
         create_initial_user(auth_service, user_service).await;
 
-    let file_content = "file content";
-    let range_suffix = 9999;
-
-    let filled_staging_file = create_filled_staging_file(
+    let popular_file = create_file(
         &client,
         staging_file_service,
+        file_service,
         &initial_user_session,
-        "file",
-        Some("video/mp4"),
-        file_content,
+        "popular",
+        Some("text/plain"),
+        "popular content",
+    )
+    .await;
+    let unpopular_file = create_file(
+        &client,
+        staging_file_service,
+        file_service,
+        &initial_user_session,
+        "unpopular",
+        Some("text/plain"),
+        "unpopular content",
     )
     .await;
 
+    for _ in 0..2 {
+        let response = client
+            .get(format!("/files/{}/data", popular_file.id))
+            .header(Header::new(
+                "Authorization",
+                format!("Bearer {}", initial_user_session.token),
+            ))
+            .dispatch()
+            .await;
+
+        assert_eq!(response.status(), Status::Ok);
+    }
+
+    // partial reads that do not start from the beginning only count the bytes served
     let response = client
-        .post(format!("/files/{}", filled_staging_file.id))
+        .get(format!("/files/{}/data", popular_file.id))
+        .header(Header::new("Range", "bytes=2-3"))
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::PartialContent);
+
+    let response = client
+        .get(format!("/files/{}/stats", popular_file.id))
         .header(Accept::JSON)
-        .header(ContentType::JSON)
         .header(Header::new(
             "Authorization",
             format!("Bearer {}", initial_user_session.token),
@@ -1028,11 +4480,18 @@ async fn test_get_file_data_range_suffix_overflow() {
         .dispatch()
         .await;
 
-    let file = response.into_json::<File>().await.unwrap();
+    let status = response.status();
+    let stats = response.into_json::<FileStats>().await.unwrap();
+
+    assert_eq!(status, Status::Ok);
+    assert_eq!(stats.file_id, popular_file.id);
+    assert_eq!(stats.downloads, 2);
+    assert_eq!(stats.served_bytes, popular_file.size * 2 + 2);
+    assert!(stats.last_downloaded_at.is_some());
 
     let response = client
-        .get(format!("/files/{}/data", file.id))
-        .header(Header::new("Range", format!("bytes=-{}", range_suffix)))
+        .get("/files?sort=downloads&direction=desc")
+        .header(Accept::JSON)
         .header(Header::new(
             "Authorization",
             format!("Bearer {}", initial_user_session.token),
@@ -1041,26 +4500,23 @@ async fn test_get_file_data_range_suffix_overflow() {
         .await;
 
     let status = response.status();
-    let content_type = response.content_type().unwrap();
-    let retrieved_file_data = response.into_string().await.unwrap();
+    let retrieved_files = response.into_json::<FileList>().await.unwrap();
 
-    assert_eq!(status, Status::PartialContent);
-    assert!(content_type.is_mp4());
-    assert_eq!(retrieved_file_data, file_content);
+    assert_eq!(status, Status::Ok);
+    assert_eq!(
+        unlist_files(&retrieved_files.files),
+        vec![popular_file, unpopular_file]
+    );
 
-    let mut raw_retrieved_file_data = file_service
-        .get_file_data_by_id(file.id, ReadRange::Suffix(range_suffix as u32))
-        .await
-        .unwrap()
-        .unwrap();
-    let raw_retrieved_file_data = {
-        let mut buffer = String::with_capacity(file_content.len());
-        raw_retrieved_file_data
-            .read_to_string(&mut buffer)
-            .await
-            .unwrap();
-        buffer
-    };
+    let response = client
+        .get(format!("/files/{}/stats", Uuid::new_v4()))
+        .header(Accept::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
 
-    assert_eq!(raw_retrieved_file_data, file_content);
+    assert_eq!(response.status(), Status::NotFound);
 }
@@ -0,0 +1,5 @@
+pub mod controllers;
+pub mod schema;
+
+#[cfg(test)]
+mod tests;
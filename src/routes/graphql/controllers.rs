@@ -0,0 +1,37 @@
+use super::schema::{build_schema, GraphQLSchema};
+use crate::{
+    dto::{ApiResponse, JsonRes},
+    guards::AuthUserSession,
+    services::{CollectionFilePairService, CollectionService, FileService, TagService},
+};
+use rocket::{http::Status, post, routes, serde::json::Json, Build, Rocket, State};
+use std::sync::Arc;
+
+pub fn register_routes(rocket: Rocket<Build>) -> Rocket<Build> {
+    rocket
+        .manage(build_schema())
+        .mount("/graphql", routes![execute_graphql])
+}
+
+/// Executes a GraphQL query over files, collections, tags and their relations.
+/// Errors in the query are reported in the `errors` field of the response, with the status `200 OK`.
+#[post("/", data = "<body>")]
+async fn execute_graphql(
+    #[allow(unused_variables)] sess: AuthUserSession<'_>,
+    schema: &State<GraphQLSchema>,
+    file_service: &State<Arc<FileService>>,
+    collection_service: &State<Arc<CollectionService>>,
+    collection_file_pair_service: &State<Arc<CollectionFilePairService>>,
+    tag_service: &State<Arc<TagService>>,
+    body: Json<async_graphql::Request>,
+) -> JsonRes<async_graphql::Response> {
+    let request = body
+        .into_inner()
+        .data(file_service.inner().clone())
+        .data(collection_service.inner().clone())
+        .data(collection_file_pair_service.inner().clone())
+        .data(tag_service.inner().clone());
+    let response = schema.execute(request).await;
+
+    Ok(ApiResponse::new(Status::Ok, response))
+}
@@ -0,0 +1,248 @@
+use crate::{
+    db::models::{Collection, File, Tag},
+    services::{
+        CollectionFilePairService, CollectionService, FileFilter, FileService, FileSort, ListOrder,
+        Page, SortDirection, TagService,
+    },
+};
+use async_graphql::{
+    Context, EmptyMutation, EmptySubscription, Object, OutputType, Schema, SimpleObject,
+};
+use chrono::NaiveDateTime;
+use std::sync::Arc;
+use uuid::Uuid;
+
+pub type GraphQLSchema = Schema<QueryRoot, EmptyMutation, EmptySubscription>;
+
+/// The deepest selection allowed, which is enough for collection → files → tags and back.
+const MAX_DEPTH: usize = 8;
+
+pub fn build_schema() -> GraphQLSchema {
+    Schema::build(QueryRoot, EmptyMutation, EmptySubscription)
+        .limit_depth(MAX_DEPTH)
+        .finish()
+}
+
+/// Clamps the `first` argument the same way the `limit` query parameter is clamped.
+fn page_limit(first: Option<u32>) -> u32 {
+    let limit = first.unwrap_or(25);
+    let limit = u32::max(1, limit);
+    u32::min(limit, 100)
+}
+
+fn service_error(
+    resolver: &'static str,
+    service: &'static str,
+    err: impl std::error::Error + 'static,
+) -> async_graphql::Error {
+    log::error!(target: "routes::graphql::schema", resolver, service, err:err; "Error returned from service.");
+    async_graphql::Error::new("internal server error")
+}
+
+/// A page of a list; pass `nextCursor` as `after` to fetch the next page.
+#[derive(SimpleObject)]
+#[graphql(concrete(name = "FilePage", params(FileNode)))]
+#[graphql(concrete(name = "CollectionPage", params(CollectionNode)))]
+pub struct NodePage<T: OutputType> {
+    pub items: Vec<T>,
+    /// The cursor to fetch the next page with, or `null` if this is the last page.
+    pub next_cursor: Option<Uuid>,
+    /// The number of items in the whole list, if it has been requested.
+    pub total: Option<i64>,
+}
+
+impl<T: OutputType> NodePage<T> {
+    fn from_page<U>(page: Page<U, Uuid>, node: impl Fn(U) -> T) -> Self {
+        Self {
+            items: page.items.into_iter().map(node).collect(),
+            next_cursor: page.next_cursor,
+            total: page.total,
+        }
+    }
+}
+
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    /// Retrieves a file by its ID.
+    async fn file(&self, ctx: &Context<'_>, id: Uuid) -> async_graphql::Result<Option<FileNode>> {
+        let file_service = ctx.data::<Arc<FileService>>()?;
+        let file = file_service
+            .get_file_by_id(id)
+            .await
+            .map_err(|err| service_error("file", "FileService", err))?;
+
+        Ok(file.map(FileNode))
+    }
+
+    /// Lists files sorted by name.
+    async fn files(
+        &self,
+        ctx: &Context<'_>,
+        after: Option<Uuid>,
+        first: Option<u32>,
+        #[graphql(default)] with_total: bool,
+    ) -> async_graphql::Result<NodePage<FileNode>> {
+        let file_service = ctx.data::<Arc<FileService>>()?;
+        let files = file_service
+            .get_files(
+                after,
+                &FileFilter::default(),
+                FileSort::Name,
+                SortDirection::Asc,
+                page_limit(first),
+                with_total,
+            )
+            .await
+            .map_err(|err| service_error("files", "FileService", err))?;
+
+        Ok(NodePage::from_page(files, FileNode))
+    }
+
+    /// Retrieves a collection by its ID.
+    async fn collection(
+        &self,
+        ctx: &Context<'_>,
+        id: Uuid,
+    ) -> async_graphql::Result<Option<CollectionNode>> {
+        let collection_service = ctx.data::<Arc<CollectionService>>()?;
+        let collection = collection_service
+            .get_collection_by_id(id)
+            .await
+            .map_err(|err| service_error("collection", "CollectionService", err))?;
+
+        Ok(collection.map(CollectionNode))
+    }
+
+    /// Lists collections sorted by name.
+    async fn collections(
+        &self,
+        ctx: &Context<'_>,
+        after: Option<Uuid>,
+        first: Option<u32>,
+        #[graphql(default)] with_total: bool,
+    ) -> async_graphql::Result<NodePage<CollectionNode>> {
+        let collection_service = ctx.data::<Arc<CollectionService>>()?;
+        let collections = collection_service
+            .get_collections(after, ListOrder::Name, page_limit(first), with_total)
+            .await
+            .map_err(|err| service_error("collections", "CollectionService", err))?;
+
+        Ok(NodePage::from_page(collections, CollectionNode))
+    }
+}
+
+pub struct FileNode(File);
+
+#[Object(name = "File")]
+impl FileNode {
+    async fn id(&self) -> Uuid {
+        self.0.id
+    }
+
+    async fn name(&self) -> &str {
+        &self.0.name
+    }
+
+    async fn mime(&self) -> &str {
+        &self.0.mime
+    }
+
+    async fn size(&self) -> i64 {
+        self.0.size
+    }
+
+    async fn hash(&self) -> i64 {
+        self.0.hash
+    }
+
+    async fn uploaded_at(&self) -> NaiveDateTime {
+        self.0.uploaded_at
+    }
+
+    /// The tags of the file, sorted by namespace and name.
+    async fn tags(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<TagNode>> {
+        let tag_service = ctx.data::<Arc<TagService>>()?;
+        let tags = tag_service
+            .get_tags_by_file_id(self.0.id)
+            .await
+            .map_err(|err| service_error("File.tags", "TagService", err))?;
+
+        Ok(tags.into_iter().map(TagNode).collect())
+    }
+
+    /// The collections the file belongs to, sorted by name.
+    async fn collections(
+        &self,
+        ctx: &Context<'_>,
+        after: Option<Uuid>,
+        first: Option<u32>,
+    ) -> async_graphql::Result<NodePage<CollectionNode>> {
+        let collection_file_pair_service = ctx.data::<Arc<CollectionFilePairService>>()?;
+        let collections = collection_file_pair_service
+            .get_collections_for_file(self.0.id, after, page_limit(first))
+            .await
+            .map_err(|err| service_error("File.collections", "CollectionFilePairService", err))?;
+
+        Ok(NodePage::from_page(collections, CollectionNode))
+    }
+}
+
+pub struct CollectionNode(Collection);
+
+#[Object(name = "Collection")]
+impl CollectionNode {
+    async fn id(&self) -> Uuid {
+        self.0.id
+    }
+
+    async fn name(&self) -> &str {
+        &self.0.name
+    }
+
+    async fn description(&self) -> Option<&str> {
+        self.0.description.as_deref()
+    }
+
+    async fn created_at(&self) -> NaiveDateTime {
+        self.0.created_at
+    }
+
+    async fn version(&self) -> i32 {
+        self.0.version
+    }
+
+    /// The files in the collection, in the same order as `GET /collections/<id>/files`.
+    async fn files(
+        &self,
+        ctx: &Context<'_>,
+        after: Option<Uuid>,
+        first: Option<u32>,
+    ) -> async_graphql::Result<NodePage<FileNode>> {
+        let collection_file_pair_service = ctx.data::<Arc<CollectionFilePairService>>()?;
+        let limit = page_limit(first);
+        // fetch one more file to tell whether there is a next page
+        let files = collection_file_pair_service
+            .get_files_in_collection(self.0.id, after, limit + 1)
+            .await
+            .map_err(|err| service_error("Collection.files", "CollectionFilePairService", err))?;
+        let files = Page::new(files, limit, None, |file| file.id);
+
+        Ok(NodePage::from_page(files, FileNode))
+    }
+}
+
+pub struct TagNode(Tag);
+
+#[Object(name = "Tag")]
+impl TagNode {
+    async fn name(&self) -> &str {
+        &self.0.name
+    }
+
+    /// The namespace of the tag; empty if the tag has no namespace.
+    async fn namespace(&self) -> &str {
+        &self.0.namespace
+    }
+}
@@ -0,0 +1,142 @@
+use crate::{
+    services::{
+        AuthService, CollectionFilePairService, CollectionService, FileService, StagingFileService,
+        TagService, UserService,
+    },
+    test::{
+        create_test_rocket_instance,
+        helpers::{create_file, create_initial_user},
+    },
+};
+use rocket::{
+    http::{Accept, ContentType, Header, Status},
+    local::asynchronous::Client,
+};
+use serde_json::json;
+use std::sync::Arc;
+
+#[rocket::async_test]
+async fn test_query_collection_files_and_tags() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let staging_file_service = client.rocket().state::<Arc<StagingFileService>>().unwrap();
+    let file_service = client.rocket().state::<Arc<FileService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+    let collection_service = client.rocket().state::<Arc<CollectionService>>().unwrap();
+    let collection_file_pair_service = client
+        .rocket()
+        .state::<Arc<CollectionFilePairService>>()
+        .unwrap();
+    let tag_service = client.rocket().state::<Arc<TagService>>().unwrap();
+
+    let (_initial_user, initial_user_session) =
+        create_initial_user(auth_service, user_service).await;
+
+    let collection = collection_service
+        .create_collection("collection", None, false, None)
+        .await
+        .unwrap();
+    let file = create_file(
+        &client,
+        staging_file_service,
+        file_service,
+        &initial_user_session,
+        "file",
+        Some("text/plain"),
+        "file content",
+    )
+    .await;
+
+    collection_file_pair_service
+        .add_file_to_collection(collection.id, file.id, None)
+        .await
+        .unwrap();
+    tag_service
+        .add_tags_to_files(&[file.id], &["year:1959"])
+        .await
+        .unwrap();
+
+    let query = r#"{
+        collections(withTotal: true) {
+            total
+            items {
+                name
+                files(first: 10) {
+                    nextCursor
+                    items { id name tags { name namespace } }
+                }
+            }
+        }
+    }"#;
+    let response = client
+        .post("/graphql")
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .body(json!({ "query": query }).to_string())
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let body = response.into_json::<serde_json::Value>().await.unwrap();
+
+    assert_eq!(status, Status::Ok);
+    assert_eq!(body.get("errors"), None);
+    assert_eq!(
+        body["data"],
+        json!({
+            "collections": {
+                "total": 1,
+                "items": [{
+                    "name": "collection",
+                    "files": {
+                        "nextCursor": null,
+                        "items": [{
+                            "id": file.id.to_string(),
+                            "name": "file",
+                            "tags": [{ "name": "1959", "namespace": "year" }],
+                        }],
+                    },
+                }],
+            },
+        })
+    );
+}
+
+#[rocket::async_test]
+async fn test_query_too_deep() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    let (_initial_user, initial_user_session) =
+        create_initial_user(auth_service, user_service).await;
+
+    let query = r#"{
+        files {
+            items { collections { items { files { items { collections { items { name } } } } } } }
+        }
+    }"#;
+    let response = client
+        .post("/graphql")
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .body(json!({ "query": query }).to_string())
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let body = response.into_json::<serde_json::Value>().await.unwrap();
+
+    assert_eq!(status, Status::Ok);
+    assert!(body.get("errors").is_some());
+}
@@ -0,0 +1,5 @@
+pub mod controllers;
+pub mod dto;
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,65 @@
+use super::dto::{RequestingPasswordReset, ResettingPassword};
+use crate::{
+    db::models::User,
+    dto::{ApiResponse, Error, ErrorCode, JsonRes},
+    services::PasswordResetService,
+    validation::Validate,
+};
+use rocket::{http::Status, post, routes, serde::json::Json, Build, Rocket, State};
+use std::sync::Arc;
+
+pub fn register_routes(rocket: Rocket<Build>) -> Rocket<Build> {
+    rocket.mount(
+        "/password-resets",
+        routes![request_password_reset, reset_password],
+    )
+}
+
+/// Mails a password reset link to the given email.
+/// It is accepted whether or not a user has the email, so that it cannot be used to probe for registered emails.
+#[post("/", data = "<body>")]
+async fn request_password_reset(
+    password_reset_service: &State<Arc<PasswordResetService>>,
+    body: Json<RequestingPasswordReset<'_>>,
+) -> Result<Status, Error> {
+    body.validate()?;
+
+    let result = password_reset_service
+        .request_password_reset(body.email)
+        .await;
+
+    if let Err(err) = result {
+        log::error!(target: "routes::password_reset::controllers", controller = "request_password_reset", service = "PasswordResetService", err:err; "Error returned from service.");
+        return Err(Status::InternalServerError.into());
+    }
+
+    Ok(Status::Accepted)
+}
+
+#[post("/<token>", data = "<body>")]
+async fn reset_password(
+    password_reset_service: &State<Arc<PasswordResetService>>,
+    token: &str,
+    body: Json<ResettingPassword<'_>>,
+) -> JsonRes<User> {
+    body.validate()?;
+
+    let user = password_reset_service
+        .reset_password(token, body.password)
+        .await;
+
+    let user = match user {
+        Ok(Some(user)) => user,
+        Ok(None) => {
+            return Err(Error::new_not_found(ErrorCode::TokenNotFound));
+        }
+        Err(err) => {
+            log::error!(target: "routes::password_reset::controllers", controller = "reset_password", service = "PasswordResetService", err:err; "Error returned from service.");
+            return Err(Status::InternalServerError.into());
+        }
+    };
+
+    log::info!(target: "routes::password_reset::controllers", controller = "reset_password", user_id = user.id; "Password reset.");
+
+    Ok(ApiResponse::new(Status::Ok, user))
+}
@@ -0,0 +1,24 @@
+use crate::validation::{FieldErrors, Validate, MAX_PASSWORD_LENGTH};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize)]
+pub struct RequestingPasswordReset<'a> {
+    pub email: &'a str,
+}
+
+impl Validate for RequestingPasswordReset<'_> {
+    fn validate_fields(&self, errors: &mut FieldErrors) {
+        errors.check_email("email", self.email);
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ResettingPassword<'a> {
+    pub password: &'a str,
+}
+
+impl Validate for ResettingPassword<'_> {
+    fn validate_fields(&self, errors: &mut FieldErrors) {
+        errors.check_text("password", self.password, MAX_PASSWORD_LENGTH);
+    }
+}
@@ -0,0 +1,108 @@
+use super::dto::{RequestingPasswordReset, ResettingPassword};
+use crate::{
+    db::models::User,
+    services::{AuthService, PasswordResetService, UserService},
+    test::{create_test_rocket_instance, helpers::create_initial_user},
+};
+use rocket::{
+    http::{Accept, ContentType, Header, Status},
+    local::asynchronous::Client,
+};
+use std::sync::Arc;
+
+#[rocket::async_test]
+async fn test_request_password_reset() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    let (initial_user, _) = create_initial_user(auth_service, user_service).await;
+
+    for email in [initial_user.email.as_str(), "unknown@example.com"] {
+        let response = client
+            .post("/password-resets")
+            .header(Accept::JSON)
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&RequestingPasswordReset { email }).unwrap())
+            .dispatch()
+            .await;
+
+        assert_eq!(response.status(), Status::Accepted);
+    }
+}
+
+#[rocket::async_test]
+async fn test_reset_password() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+    let password_reset_service = client
+        .rocket()
+        .state::<Arc<PasswordResetService>>()
+        .unwrap();
+
+    let (initial_user, initial_user_session) =
+        create_initial_user(auth_service, user_service).await;
+    let reset_token = password_reset_service
+        .create_password_reset_token(&initial_user.email)
+        .await
+        .unwrap()
+        .unwrap();
+
+    let response = client
+        .post(format!("/password-resets/{}", reset_token.token))
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .body(
+            serde_json::to_string(&ResettingPassword {
+                password: "new_password",
+            })
+            .unwrap(),
+        )
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let user = response.into_json::<User>().await.unwrap();
+
+    assert_eq!(status, Status::Ok);
+    assert_eq!(user, initial_user);
+
+    let authenticated_user_id = auth_service
+        .authenticate_user(&initial_user.email, "new_password")
+        .await
+        .unwrap();
+
+    assert_eq!(authenticated_user_id, Some(initial_user.id));
+
+    // the sessions made with the old password are removed
+    let response = client
+        .get(format!("/users/{}", initial_user.id))
+        .header(Accept::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::Unauthorized);
+
+    // the token cannot be used twice
+    let response = client
+        .post(format!("/password-resets/{}", reset_token.token))
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .body(
+            serde_json::to_string(&ResettingPassword {
+                password: "another_password",
+            })
+            .unwrap(),
+        )
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::NotFound);
+}
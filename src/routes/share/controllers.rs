@@ -0,0 +1,316 @@
+use super::dto::{CreatingShare, SharedContent, SharedFileList};
+use crate::{
+    db::models::Share,
+    dto::{ApiResponse, Error, ErrorCode, JsonRes},
+    guards::{AuthUserSession, RangeHeader},
+    routes::file::{controllers::read_file_data, dto::FileData},
+    services::{
+        CollectionFilePairService, CollectionService, FileService, ReadAheadService, ShareService,
+        ShareTarget, TransferLimitService,
+    },
+};
+use chrono::{Duration, Utc};
+use rocket::{delete, get, http::Status, post, routes, serde::json::Json, Build, Rocket, State};
+use std::sync::Arc;
+use uuid::Uuid;
+
+pub fn register_routes(rocket: Rocket<Build>) -> Rocket<Build> {
+    rocket.mount(
+        "/shares",
+        routes![
+            create_share,
+            remove_share,
+            get_shared_content,
+            get_shared_file_data,
+            get_shared_files,
+            get_shared_collection_file_data,
+        ],
+    )
+}
+
+#[post("/", data = "<body>")]
+async fn create_share(
+    sess: AuthUserSession<'_>,
+    share_service: &State<Arc<ShareService>>,
+    body: Json<CreatingShare>,
+) -> JsonRes<Share> {
+    let target = match (body.file_id, body.collection_id) {
+        (Some(file_id), None) => ShareTarget::File(file_id),
+        (None, Some(collection_id)) => ShareTarget::Collection(collection_id),
+        _ => {
+            return Err(Error::new_static(
+                Status::BadRequest,
+                "exactly one of `file_id` and `collection_id` should be given",
+            )
+            .with_code(ErrorCode::InvalidParameter));
+        }
+    };
+    let expires_at = match body.expires_in {
+        Some(expires_in) => match i64::try_from(expires_in)
+            .ok()
+            .and_then(Duration::try_seconds)
+            .and_then(|expires_in| Utc::now().naive_utc().checked_add_signed(expires_in))
+        {
+            Some(expires_at) => Some(expires_at),
+            None => {
+                return Err(
+                    Error::new_static(Status::BadRequest, "`expires_in` is too large")
+                        .with_code(ErrorCode::InvalidParameter),
+                );
+            }
+        },
+        None => None,
+    };
+
+    let share = share_service
+        .create_share(sess.user.id, target, expires_at)
+        .await;
+
+    let share = match share {
+        Ok(Some(share)) => share,
+        Ok(None) => {
+            return Err(Error::new_not_found(if body.file_id.is_some() {
+                ErrorCode::FileNotFound
+            } else {
+                ErrorCode::CollectionNotFound
+            }));
+        }
+        Err(err) => {
+            let body = body.into_inner();
+            log::error!(target: "routes::share::controllers", controller = "create_share", service = "ShareService", body:serde, err:err; "Error returned from service.");
+            return Err(Status::InternalServerError.into());
+        }
+    };
+
+    log::info!(target: "routes::share::controllers", controller = "create_share", user_id = sess.user.id, share_id:serde = share.id; "Share created.");
+
+    Ok(ApiResponse::new(Status::Created, share))
+}
+
+#[delete("/<share_id>")]
+async fn remove_share(
+    sess: AuthUserSession<'_>,
+    share_service: &State<Arc<ShareService>>,
+    share_id: Uuid,
+) -> JsonRes<Share> {
+    let share = share_service.remove_share(share_id, sess.user.id).await;
+
+    let share = match share {
+        Ok(Some(share)) => share,
+        Ok(None) => {
+            return Err(Error::new_not_found(ErrorCode::ShareNotFound));
+        }
+        Err(err) => {
+            log::error!(target: "routes::share::controllers", controller = "remove_share", service = "ShareService", share_id:serde, err:err; "Error returned from service.");
+            return Err(Status::InternalServerError.into());
+        }
+    };
+
+    Ok(ApiResponse::new(Status::Ok, share))
+}
+
+/// Retrieves the file or collection a share grants access to. No authentication is required.
+#[get("/<token>")]
+async fn get_shared_content(
+    share_service: &State<Arc<ShareService>>,
+    file_service: &State<Arc<FileService>>,
+    collection_service: &State<Arc<CollectionService>>,
+    token: &str,
+) -> JsonRes<SharedContent> {
+    let share = find_share(share_service, token, "get_shared_content").await?;
+
+    let (file, collection) = match share_target(&share) {
+        ShareTarget::File(file_id) => match file_service.get_file_by_id(file_id).await {
+            Ok(file) => (file, None),
+            Err(err) => {
+                log::error!(target: "routes::share::controllers", controller = "get_shared_content", service = "FileService", file_id:serde, err:err; "Error returned from service.");
+                return Err(Status::InternalServerError.into());
+            }
+        },
+        ShareTarget::Collection(collection_id) => {
+            match collection_service.get_collection_by_id(collection_id).await {
+                Ok(collection) => (None, collection),
+                Err(err) => {
+                    log::error!(target: "routes::share::controllers", controller = "get_shared_content", service = "CollectionService", collection_id:serde, err:err; "Error returned from service.");
+                    return Err(Status::InternalServerError.into());
+                }
+            }
+        }
+    };
+
+    if file.is_none() && collection.is_none() {
+        return Err(Error::new_not_found(ErrorCode::ShareNotFound));
+    }
+
+    Ok(ApiResponse::new(
+        Status::Ok,
+        SharedContent {
+            file,
+            collection,
+            expires_at: share.expires_at,
+        },
+    ))
+}
+
+/// Retrieves the data of a shared file. No authentication is required.
+#[get("/<token>/data?<download>")]
+async fn get_shared_file_data(
+    share_service: &State<Arc<ShareService>>,
+    file_service: &State<Arc<FileService>>,
+    read_ahead_service: &State<Arc<ReadAheadService>>,
+    transfer_limit_service: &State<Arc<TransferLimitService>>,
+    range_header: RangeHeader,
+    token: &str,
+    download: Option<bool>,
+) -> Result<FileData, Error> {
+    let share = find_share(share_service, token, "get_shared_file_data").await?;
+
+    let file_id = match share_target(&share) {
+        ShareTarget::File(file_id) => file_id,
+        ShareTarget::Collection(_) => {
+            return Err(Error::new_not_found(ErrorCode::ShareNotFound));
+        }
+    };
+
+    let file = file_service.get_file_by_id(file_id).await;
+    let file = match file {
+        Ok(Some(file)) => file,
+        Ok(None) => {
+            return Err(Error::new_not_found(ErrorCode::FileNotFound));
+        }
+        Err(err) => {
+            log::error!(target: "routes::share::controllers", controller = "get_shared_file_data", service = "FileService", file_id:serde, err:err; "Error returned from service.");
+            return Err(Status::InternalServerError.into());
+        }
+    };
+
+    read_file_data(
+        file_service,
+        transfer_limit_service,
+        read_ahead_service,
+        token,
+        None,
+        file,
+        range_header,
+        download.unwrap_or(false),
+    )
+    .await
+}
+
+/// Retrieves a list of files in a shared collection. No authentication is required.
+#[get("/<token>/files?<last_file_id>&<limit>")]
+async fn get_shared_files(
+    share_service: &State<Arc<ShareService>>,
+    collection_file_pair_service: &State<Arc<CollectionFilePairService>>,
+    token: &str,
+    last_file_id: Option<Uuid>,
+    limit: Option<u32>,
+) -> JsonRes<SharedFileList> {
+    let share = find_share(share_service, token, "get_shared_files").await?;
+
+    let collection_id = match share_target(&share) {
+        ShareTarget::Collection(collection_id) => collection_id,
+        ShareTarget::File(_) => {
+            return Err(Error::new_not_found(ErrorCode::ShareNotFound));
+        }
+    };
+
+    let limit = limit.unwrap_or(25);
+    let limit = u32::max(1, limit);
+    let limit = u32::min(limit, 100);
+    let files = collection_file_pair_service
+        .get_files_in_collection(collection_id, last_file_id, limit)
+        .await;
+
+    let files = match files {
+        Ok(files) => files,
+        Err(err) => {
+            log::error!(target: "routes::share::controllers", controller = "get_shared_files", service = "CollectionFilePairService", collection_id:serde, last_file_id:serde, limit, err:err; "Error returned from service.");
+            return Err(Status::InternalServerError.into());
+        }
+    };
+
+    Ok(ApiResponse::new(
+        Status::Ok,
+        SharedFileList {
+            files,
+            last_file_id,
+            limit,
+        },
+    ))
+}
+
+/// Retrieves the data of a file in a shared collection. No authentication is required.
+#[allow(clippy::too_many_arguments)]
+#[get("/<token>/files/<file_id>/data?<download>")]
+async fn get_shared_collection_file_data(
+    share_service: &State<Arc<ShareService>>,
+    collection_file_pair_service: &State<Arc<CollectionFilePairService>>,
+    file_service: &State<Arc<FileService>>,
+    read_ahead_service: &State<Arc<ReadAheadService>>,
+    transfer_limit_service: &State<Arc<TransferLimitService>>,
+    range_header: RangeHeader,
+    token: &str,
+    file_id: Uuid,
+    download: Option<bool>,
+) -> Result<FileData, Error> {
+    let share = find_share(share_service, token, "get_shared_collection_file_data").await?;
+
+    let collection_id = match share_target(&share) {
+        ShareTarget::Collection(collection_id) => collection_id,
+        ShareTarget::File(_) => {
+            return Err(Error::new_not_found(ErrorCode::ShareNotFound));
+        }
+    };
+
+    let file = collection_file_pair_service
+        .get_file_in_collection_by_id(collection_id, file_id)
+        .await;
+    let file = match file {
+        Ok(Some(file)) => file,
+        Ok(None) => {
+            return Err(Error::new_not_found(ErrorCode::FileNotInCollection));
+        }
+        Err(err) => {
+            log::error!(target: "routes::share::controllers", controller = "get_shared_collection_file_data", service = "CollectionFilePairService", collection_id:serde, file_id:serde, err:err; "Error returned from service.");
+            return Err(Status::InternalServerError.into());
+        }
+    };
+
+    read_file_data(
+        file_service,
+        transfer_limit_service,
+        read_ahead_service,
+        token,
+        None,
+        file,
+        range_header,
+        download.unwrap_or(false),
+    )
+    .await
+}
+
+async fn find_share(
+    share_service: &ShareService,
+    token: &str,
+    controller: &str,
+) -> Result<Share, Error> {
+    let share = share_service.get_share_by_token(token).await;
+
+    match share {
+        Ok(Some(share)) => Ok(share),
+        Ok(None) => Err(Error::new_not_found(ErrorCode::ShareNotFound)),
+        Err(err) => {
+            log::error!(target: "routes::share::controllers", controller, service = "ShareService", err:err; "Error returned from service.");
+            Err(Status::InternalServerError.into())
+        }
+    }
+}
+
+fn share_target(share: &Share) -> ShareTarget {
+    // the table guarantees that exactly one of them is set
+    match share.file_id {
+        Some(file_id) => ShareTarget::File(file_id),
+        None => ShareTarget::Collection(share.collection_id.unwrap_or_default()),
+    }
+}
@@ -0,0 +1,26 @@
+use crate::db::models::{Collection, File};
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+#[derive(Serialize, Deserialize)]
+pub struct CreatingShare {
+    pub file_id: Option<Uuid>,
+    pub collection_id: Option<Uuid>,
+    /// The number of seconds the share stays valid for. The share never expires if omitted.
+    pub expires_in: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SharedContent {
+    pub file: Option<File>,
+    pub collection: Option<Collection>,
+    pub expires_at: Option<NaiveDateTime>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SharedFileList {
+    pub files: Vec<File>,
+    pub last_file_id: Option<Uuid>,
+    pub limit: u32,
+}
@@ -0,0 +1,277 @@
+use super::dto::{CreatingShare, SharedContent, SharedFileList};
+use crate::{
+    db::models::Share,
+    services::{
+        AuthService, CollectionFilePairService, CollectionService, FileService, StagingFileService,
+        UserService,
+    },
+    test::{
+        create_test_rocket_instance,
+        helpers::{create_file, create_initial_user},
+    },
+};
+use rocket::{
+    http::{Accept, ContentType, Header, Status},
+    local::asynchronous::Client,
+};
+use std::sync::Arc;
+
+#[rocket::async_test]
+async fn test_share_file() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let staging_file_service = client.rocket().state::<Arc<StagingFileService>>().unwrap();
+    let file_service = client.rocket().state::<Arc<FileService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    let (initial_user, initial_user_session) =
+        create_initial_user(auth_service, user_service).await;
+
+    let file = create_file(
+        &client,
+        staging_file_service,
+        file_service,
+        &initial_user_session,
+        "file",
+        Some("video/mp4"),
+        "file content",
+    )
+    .await;
+
+    let response = client
+        .post("/shares")
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .body(
+            serde_json::to_string(&CreatingShare {
+                file_id: Some(file.id),
+                collection_id: None,
+                expires_in: Some(60),
+            })
+            .unwrap(),
+        )
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let share = response.into_json::<Share>().await.unwrap();
+
+    assert_eq!(status, Status::Created);
+    assert_eq!(share.user_id, initial_user.id);
+    assert_eq!(share.file_id, Some(file.id));
+    assert!(share.expires_at.is_some());
+
+    // shared content is accessible without authentication
+    let response = client
+        .get(format!("/shares/{}", share.token))
+        .header(Accept::JSON)
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let content = response.into_json::<SharedContent>().await.unwrap();
+
+    assert_eq!(status, Status::Ok);
+    assert_eq!(content.file, Some(file.clone()));
+    assert_eq!(content.collection, None);
+
+    let response = client
+        .get(format!("/shares/{}/data", share.token))
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.into_string().await.unwrap(), "file content");
+
+    let response = client
+        .delete(format!("/shares/{}", share.id))
+        .header(Accept::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::Ok);
+
+    let response = client
+        .get(format!("/shares/{}/data", share.token))
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+#[rocket::async_test]
+async fn test_share_collection() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let staging_file_service = client.rocket().state::<Arc<StagingFileService>>().unwrap();
+    let file_service = client.rocket().state::<Arc<FileService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+    let collection_service = client.rocket().state::<Arc<CollectionService>>().unwrap();
+    let collection_file_pair_service = client
+        .rocket()
+        .state::<Arc<CollectionFilePairService>>()
+        .unwrap();
+
+    let (_initial_user, initial_user_session) =
+        create_initial_user(auth_service, user_service).await;
+
+    let collection = collection_service
+        .create_collection("collection", None, false, None)
+        .await
+        .unwrap();
+    let shared_file = create_file(
+        &client,
+        staging_file_service,
+        file_service,
+        &initial_user_session,
+        "shared",
+        Some("text/plain"),
+        "shared content",
+    )
+    .await;
+    let private_file = create_file(
+        &client,
+        staging_file_service,
+        file_service,
+        &initial_user_session,
+        "private",
+        Some("text/plain"),
+        "private content",
+    )
+    .await;
+
+    collection_file_pair_service
+        .add_file_to_collection(collection.id, shared_file.id, None)
+        .await
+        .unwrap();
+
+    let response = client
+        .post("/shares")
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .body(
+            serde_json::to_string(&CreatingShare {
+                file_id: None,
+                collection_id: Some(collection.id),
+                expires_in: None,
+            })
+            .unwrap(),
+        )
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let share = response.into_json::<Share>().await.unwrap();
+
+    assert_eq!(status, Status::Created);
+    assert_eq!(share.collection_id, Some(collection.id));
+    assert_eq!(share.expires_at, None);
+
+    let response = client
+        .get(format!("/shares/{}/files", share.token))
+        .header(Accept::JSON)
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let file_list = response.into_json::<SharedFileList>().await.unwrap();
+
+    assert_eq!(status, Status::Ok);
+    assert_eq!(file_list.files, vec![shared_file.clone()]);
+
+    let response = client
+        .get(format!(
+            "/shares/{}/files/{}/data",
+            share.token, shared_file.id
+        ))
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.into_string().await.unwrap(), "shared content");
+
+    // files outside of the collection are not exposed
+    let response = client
+        .get(format!(
+            "/shares/{}/files/{}/data",
+            share.token, private_file.id
+        ))
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::NotFound);
+
+    let response = client
+        .get(format!("/shares/{}/data", share.token))
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+#[rocket::async_test]
+async fn test_create_share_invalid_target() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    let (_initial_user, initial_user_session) =
+        create_initial_user(auth_service, user_service).await;
+
+    let response = client
+        .post("/shares")
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .body(
+            serde_json::to_string(&CreatingShare {
+                file_id: None,
+                collection_id: None,
+                expires_in: None,
+            })
+            .unwrap(),
+        )
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::BadRequest);
+
+    let response = client
+        .post("/shares")
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .body(
+            serde_json::to_string(&CreatingShare {
+                file_id: Some(uuid::Uuid::new_v4()),
+                collection_id: None,
+                expires_in: None,
+            })
+            .unwrap(),
+        )
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::NotFound);
+}
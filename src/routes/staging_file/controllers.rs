@@ -2,14 +2,19 @@ use super::dto::{CreatingStagingFile, UpdatingStagingFile};
 use crate::{
     config::AppConfig,
     db::models::StagingFile,
-    dto::{Error, JsonRes},
-    guards::{AuthUserSession, OffsetHeader},
-    services::{StagingFileService, WriteError},
+    dto::{ApiResponse, Error, ErrorCode, JsonRes},
+    guards::{AuthUserSession, ContentLengthHeader, FinalizeHeader, OffsetHeader},
+    routes::file::controllers::{map_file_service_err, record_file_upload, too_many_transfers},
+    services::{
+        AuditLogService, ChunkWriteError, DirectUploadError, FileService, FreeSpaceService,
+        LiveConfigService, PresignedUpload, StagingFileService, TransferLimitService, WriteError,
+    },
+    validation::Validate,
 };
 use rocket::{
     delete, get, http::Status, post, put, routes, serde::json::Json, Build, Data, Rocket, State,
 };
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 use uuid::Uuid;
 
 pub fn register_routes(rocket: Rocket<Build>) -> Rocket<Build> {
@@ -20,19 +25,61 @@ pub fn register_routes(rocket: Rocket<Build>) -> Rocket<Build> {
             remove_staging_file,
             get_staging_file,
             update_staging_file,
-            fill_staging_file_data
+            fill_staging_file_data,
+            fill_staging_file_chunk,
+            create_direct_upload,
+            complete_direct_upload
         ],
     )
 }
 
+/// Picks the error code for a staging file write that has been rejected because of the request.
+pub(crate) fn write_error_code(err: &WriteError) -> ErrorCode {
+    match err {
+        WriteError::OffsetExceedsFileSize { .. } => ErrorCode::OffsetExceedsSize,
+        WriteError::FileTooLarge { .. } | WriteError::OffsetTooLarge { .. } => {
+            ErrorCode::FileTooLarge
+        }
+        WriteError::ExceedsExpectedSize { .. } => ErrorCode::SizeExceedsExpected,
+        WriteError::Write { .. } => ErrorCode::InternalError,
+    }
+}
+
+/// Rejects an upload of `size` bytes if the storage does not have room for it.
+/// An upload of unknown size is only rejected if the storage has already eaten into its reserve.
+pub(crate) async fn ensure_free_space(
+    free_space_service: &FreeSpaceService,
+    size: Option<u64>,
+    controller: &'static str,
+) -> Result<(), Error> {
+    let result = free_space_service.check(size.unwrap_or(0)).await;
+
+    match result {
+        Ok(Ok(())) => Ok(()),
+        Ok(Err(err)) => {
+            log::warn!(target: "routes::staging_file::controllers", controller, service = "FreeSpaceService", err:err; "Upload rejected for insufficient storage.");
+            Err(
+                Error::new_dynamic(Status::InsufficientStorage, err.to_string())
+                    .with_code(ErrorCode::InsufficientStorage),
+            )
+        }
+        Err(err) => {
+            log::error!(target: "routes::staging_file::controllers", controller, service = "FreeSpaceService", err:err; "Error returned from service.");
+            Err(Status::InternalServerError.into())
+        }
+    }
+}
+
 #[post("/", data = "<body>")]
 async fn create_staging_file(
     #[allow(unused_variables)] sess: AuthUserSession<'_>,
     staging_file_service: &State<Arc<StagingFileService>>,
     body: Json<CreatingStagingFile<'_>>,
 ) -> JsonRes<StagingFile> {
+    body.validate()?;
+
     let staging_file = staging_file_service
-        .create_staging_file(body.name, body.mime)
+        .create_staging_file(body.name, body.mime, body.expected_size, body.expected_hash)
         .await;
 
     let staging_file = match staging_file {
@@ -44,7 +91,7 @@ async fn create_staging_file(
         }
     };
 
-    Ok((Status::Created, Json(staging_file)))
+    Ok(ApiResponse::new(Status::Created, staging_file))
 }
 
 #[delete("/<staging_file_id>")]
@@ -60,7 +107,7 @@ async fn remove_staging_file(
     let staging_file = match staging_file {
         Ok(Some(staging_file)) => staging_file,
         Ok(None) => {
-            return Err(Status::NotFound.into());
+            return Err(Error::new_not_found(ErrorCode::StagingFileNotFound));
         }
         Err(err) => {
             log::error!(target: "routes::staging_file::controllers", controller = "remove_staging_file", service = "StagingFileService", staging_file_id:serde, err:err; "Error returned from service.");
@@ -68,7 +115,7 @@ async fn remove_staging_file(
         }
     };
 
-    Ok((Status::Ok, Json(staging_file)))
+    Ok(ApiResponse::new(Status::Ok, staging_file))
 }
 
 #[get("/<staging_file_id>")]
@@ -84,7 +131,7 @@ async fn get_staging_file(
     let staging_file = match staging_file {
         Ok(Some(staging_file)) => staging_file,
         Ok(None) => {
-            return Err(Status::NotFound.into());
+            return Err(Error::new_not_found(ErrorCode::StagingFileNotFound));
         }
         Err(err) => {
             log::error!(target: "routes::staging_file::controllers", controller = "get_staging_file", service = "StagingFileService", staging_file_id:serde, err:err; "Error returned from service.");
@@ -92,7 +139,7 @@ async fn get_staging_file(
         }
     };
 
-    Ok((Status::Ok, Json(staging_file)))
+    Ok(ApiResponse::new(Status::Ok, staging_file))
 }
 
 #[put("/<staging_file_id>", data = "<body>")]
@@ -102,6 +149,8 @@ async fn update_staging_file(
     staging_file_id: Uuid,
     body: Json<UpdatingStagingFile<'_>>,
 ) -> JsonRes<StagingFile> {
+    body.validate()?;
+
     let staging_file = staging_file_service
         .update_staging_file_by_id(staging_file_id, body.name, body.mime)
         .await;
@@ -109,7 +158,7 @@ async fn update_staging_file(
     let staging_file = match staging_file {
         Ok(Some(staging_file)) => staging_file,
         Ok(None) => {
-            return Err(Status::NotFound.into());
+            return Err(Error::new_not_found(ErrorCode::StagingFileNotFound));
         }
         Err(err) => {
             log::error!(target: "routes::staging_file::controllers", controller = "update_staging_file", service = "StagingFileService", staging_file_id:serde, err:err; "Error returned from service.");
@@ -117,19 +166,45 @@ async fn update_staging_file(
         }
     };
 
-    Ok((Status::Ok, Json(staging_file)))
+    Ok(ApiResponse::new(Status::Ok, staging_file))
 }
 
-#[put("/<staging_file_id>/data", data = "<body>")]
+/// Writes data into a staging file.
+/// If the staging file has been filled up to its expected size and automatic promotion is enabled,
+/// it is promoted into a file with the same ID, and `201 Created` is returned instead of `200 OK`.
+/// With `finalize=true` or the `X-Finalize: true` header, it is promoted once the data is written regardless,
+/// so that simple uploaders do not need another request.
+#[allow(clippy::too_many_arguments)]
+#[put("/<staging_file_id>/data?<finalize>", data = "<body>")]
 async fn fill_staging_file_data(
-    #[allow(unused_variables)] sess: AuthUserSession<'_>,
+    sess: AuthUserSession<'_>,
     app_config: &State<AppConfig>,
+    live_config_service: &State<Arc<LiveConfigService>>,
     staging_file_service: &State<Arc<StagingFileService>>,
+    transfer_limit_service: &State<Arc<TransferLimitService>>,
+    free_space_service: &State<Arc<FreeSpaceService>>,
+    file_service: &State<Arc<FileService>>,
+    audit_log_service: &State<Arc<AuditLogService>>,
     staging_file_id: Uuid,
+    finalize: Option<bool>,
     offset_header: OffsetHeader,
+    finalize_header: FinalizeHeader,
+    content_length_header: Option<ContentLengthHeader>,
     body: Data<'_>,
 ) -> JsonRes<StagingFile> {
-    let stream = body.open(app_config.limits.file);
+    let finalize = finalize.unwrap_or(finalize_header.finalize);
+    let _permit = transfer_limit_service
+        .try_acquire_upload()
+        .ok_or_else(|| too_many_transfers(transfer_limit_service))?;
+    ensure_free_space(
+        free_space_service,
+        content_length_header.map(|header| header.content_length),
+        "fill_staging_file_data",
+    )
+    .await?;
+
+    let stream = body.open(live_config_service.get().file_limit);
+    let stream = transfer_limit_service.throttle_upload(Some(&sess.user.role), Box::pin(stream));
     let staging_file = staging_file_service
         .fill_staging_file_by_id(staging_file_id, offset_header.offset, stream)
         .await;
@@ -137,7 +212,7 @@ async fn fill_staging_file_data(
     let staging_file = match staging_file {
         Ok(Ok(Some(staging_file))) => staging_file,
         Ok(Ok(None)) => {
-            return Err(Status::NotFound.into());
+            return Err(Error::new_not_found(ErrorCode::StagingFileNotFound));
         }
         Ok(Err(err)) => match err {
             WriteError::OffsetExceedsFileSize { offset, file_size } => {
@@ -147,7 +222,8 @@ async fn fill_staging_file_data(
                         "the offset `{}` exceeds the file size `{}`",
                         offset, file_size
                     ),
-                ));
+                )
+                .with_code(ErrorCode::OffsetExceedsSize));
             }
             WriteError::FileTooLarge {
                 max_size,
@@ -159,7 +235,8 @@ async fn fill_staging_file_data(
                         "the file size `{}` exceeds the maximum file size `{}`",
                         file_size, max_size
                     ),
-                ));
+                )
+                .with_code(ErrorCode::FileTooLarge));
             }
             WriteError::OffsetTooLarge { max_offset, offset } => {
                 return Err(Error::new_dynamic(
@@ -168,7 +245,18 @@ async fn fill_staging_file_data(
                         "the offset `{}` exceeds the maximum offset `{}`",
                         offset, max_offset
                     ),
-                ));
+                )
+                .with_code(ErrorCode::FileTooLarge));
+            }
+            WriteError::ExceedsExpectedSize { expected_size } => {
+                return Err(Error::new_dynamic(
+                    Status::PayloadTooLarge,
+                    format!(
+                        "the data exceeds the expected file size `{}`",
+                        expected_size
+                    ),
+                )
+                .with_code(ErrorCode::SizeExceedsExpected));
             }
             WriteError::Write {
                 io_error,
@@ -184,5 +272,256 @@ async fn fill_staging_file_data(
         }
     };
 
-    Ok((Status::Ok, Json(staging_file)))
+    let is_complete = staging_file.expected_size == Some(staging_file.size);
+
+    if !finalize && !(app_config.auto_promote_staging_files && is_complete) {
+        return Ok(ApiResponse::new(Status::Ok, staging_file));
+    }
+
+    let file = file_service
+        .create_file_from_staging_file_id(staging_file_id)
+        .await;
+
+    let file = match file {
+        Ok(Some(file)) => file,
+        Ok(None) => {
+            return Err(Error::new_not_found(ErrorCode::StagingFileNotFound));
+        }
+        Err(err) => {
+            log::error!(target: "routes::staging_file::controllers", controller = "fill_staging_file", service = "FileService", staging_file_id:serde, err:err; "Error returned from service.");
+            return Err(map_file_service_err(&err));
+        }
+    };
+
+    record_file_upload(audit_log_service, sess.audit_actor(), &file).await;
+
+    Ok(ApiResponse::new(Status::Created, staging_file))
+}
+
+/// Writes a chunk of data into a staging file at `offset`, without locking the staging file.
+/// Non-overlapping chunks can be written concurrently, but the staging file must have an expected size,
+/// and the size of the chunk must be given in the `Content-Length` header.
+/// Once all chunks up to the expected size are written and automatic promotion is enabled,
+/// the staging file is promoted into a file with the same ID, and `201 Created` is returned instead of `200 OK`.
+#[allow(clippy::too_many_arguments)]
+#[put("/<staging_file_id>/chunks/<offset>", data = "<body>")]
+async fn fill_staging_file_chunk(
+    sess: AuthUserSession<'_>,
+    app_config: &State<AppConfig>,
+    live_config_service: &State<Arc<LiveConfigService>>,
+    staging_file_service: &State<Arc<StagingFileService>>,
+    transfer_limit_service: &State<Arc<TransferLimitService>>,
+    free_space_service: &State<Arc<FreeSpaceService>>,
+    file_service: &State<Arc<FileService>>,
+    audit_log_service: &State<Arc<AuditLogService>>,
+    staging_file_id: Uuid,
+    offset: u64,
+    content_length_header: ContentLengthHeader,
+    body: Data<'_>,
+) -> JsonRes<StagingFile> {
+    let size = content_length_header.content_length;
+    let _permit = transfer_limit_service
+        .try_acquire_upload()
+        .ok_or_else(|| too_many_transfers(transfer_limit_service))?;
+    ensure_free_space(free_space_service, Some(size), "fill_staging_file_chunk").await?;
+
+    let stream = body.open(live_config_service.get().file_limit);
+    let stream = transfer_limit_service.throttle_upload(Some(&sess.user.role), Box::pin(stream));
+    let chunk = staging_file_service
+        .fill_staging_file_chunk_by_id(staging_file_id, offset, size, stream)
+        .await;
+
+    let chunk = match chunk {
+        Ok(Ok(Some(chunk))) => chunk,
+        Ok(Ok(None)) => {
+            return Err(Error::new_not_found(ErrorCode::StagingFileNotFound));
+        }
+        Ok(Err(err)) => match err {
+            ChunkWriteError::NoExpectedSize => {
+                return Err(Error::new_static(
+                    Status::UnprocessableEntity,
+                    "chunks can only be written to staging files with an expected size",
+                ));
+            }
+            ChunkWriteError::OutOfBounds {
+                offset,
+                size,
+                expected_size,
+            } => {
+                return Err(Error::new_dynamic(
+                    Status::UnprocessableEntity,
+                    format!(
+                        "the chunk at `{}` of size `{}` does not fit in the expected file size `{}`",
+                        offset, size, expected_size
+                    ),
+                ).with_code(ErrorCode::SizeExceedsExpected));
+            }
+            ChunkWriteError::Overlap => {
+                return Err(Error::new_static(
+                    Status::Conflict,
+                    "the chunk overlaps with another chunk",
+                )
+                .with_code(ErrorCode::ChunkOverlaps));
+            }
+            ChunkWriteError::Incomplete { size, written } => {
+                return Err(Error::new_dynamic(
+                    Status::BadRequest,
+                    format!(
+                        "only `{}` of `{}` bytes of the chunk were received",
+                        written, size
+                    ),
+                ));
+            }
+            ChunkWriteError::Write(WriteError::Write {
+                io_error,
+                file_size,
+            }) => {
+                log::error!(target: "routes::staging_file::controllers", controller = "fill_staging_file_chunk", service = "StagingFileService", staging_file_id:serde, offset, io_error:err, file_size; "Error returned from service.");
+                return Err(Status::InternalServerError.into());
+            }
+            ChunkWriteError::Write(err) => {
+                return Err(
+                    Error::new_dynamic(Status::UnprocessableEntity, err.to_string())
+                        .with_code(write_error_code(&err)),
+                );
+            }
+        },
+        Err(err) => {
+            log::error!(target: "routes::staging_file::controllers", controller = "fill_staging_file_chunk", service = "StagingFileService", staging_file_id:serde, offset, err:err; "Error returned from service.");
+            return Err(Status::InternalServerError.into());
+        }
+    };
+
+    if !app_config.auto_promote_staging_files || !chunk.is_complete {
+        return Ok(ApiResponse::new(Status::Ok, chunk.staging_file));
+    }
+
+    let file = file_service
+        .create_file_from_staging_file_id(staging_file_id)
+        .await;
+
+    match file {
+        Ok(Some(file)) => {
+            record_file_upload(audit_log_service, sess.audit_actor(), &file).await;
+        }
+        // `None` means that the last chunks were finished at the same time, and another request has promoted it
+        Ok(None) => {}
+        Err(err) => {
+            log::error!(target: "routes::staging_file::controllers", controller = "fill_staging_file_chunk", service = "FileService", staging_file_id:serde, err:err; "Error returned from service.");
+            return Err(map_file_service_err(&err));
+        }
+    }
+
+    Ok(ApiResponse::new(Status::Created, chunk.staging_file))
+}
+
+/// Maps a direct upload that has been rejected to its response.
+fn direct_upload_error(err: &DirectUploadError) -> Error {
+    match err {
+        DirectUploadError::Unsupported => {
+            Error::new_dynamic(Status::NotImplemented, err.to_string())
+                .with_code(ErrorCode::DirectUploadUnsupported)
+        }
+        DirectUploadError::NotUploaded => Error::new_dynamic(Status::Conflict, err.to_string())
+            .with_code(ErrorCode::DirectUploadMissing),
+        DirectUploadError::SizeMismatch {
+            expected_size,
+            size,
+        } => Error::new_dynamic(Status::UnprocessableEntity, err.to_string()).with_code(
+            if expected_size < size {
+                ErrorCode::SizeExceedsExpected
+            } else {
+                ErrorCode::StagingFileNotFilled
+            },
+        ),
+    }
+}
+
+/// Presigns a request to upload the data of a staging file directly to the storage, so that large uploads bypass the server.
+/// The data has to be taken in by `POST /staging-files/<staging_file_id>/direct-upload/complete` once it is uploaded.
+/// It is only available if the storage supports it; otherwise `501 Not Implemented` is returned.
+#[post("/<staging_file_id>/direct-upload")]
+async fn create_direct_upload(
+    #[allow(unused_variables)] sess: AuthUserSession<'_>,
+    app_config: &State<AppConfig>,
+    staging_file_service: &State<Arc<StagingFileService>>,
+    staging_file_id: Uuid,
+) -> JsonRes<PresignedUpload> {
+    let presigned_upload = staging_file_service
+        .presign_direct_upload_by_id(
+            staging_file_id,
+            Duration::from_secs(app_config.direct_upload_expiration),
+        )
+        .await;
+
+    let presigned_upload = match presigned_upload {
+        Ok(Ok(Some(presigned_upload))) => presigned_upload,
+        Ok(Ok(None)) => {
+            return Err(Error::new_not_found(ErrorCode::StagingFileNotFound));
+        }
+        Ok(Err(err)) => {
+            return Err(direct_upload_error(&err));
+        }
+        Err(err) => {
+            log::error!(target: "routes::staging_file::controllers", controller = "create_direct_upload", service = "StagingFileService", staging_file_id:serde, err:err; "Error returned from service.");
+            return Err(Status::InternalServerError.into());
+        }
+    };
+
+    Ok(ApiResponse::new(Status::Ok, presigned_upload))
+}
+
+/// Verifies the data uploaded directly to the storage, and takes it in as the data of the staging file.
+/// If automatic promotion is enabled,
+/// it is promoted into a file with the same ID, and `201 Created` is returned instead of `200 OK`.
+#[post("/<staging_file_id>/direct-upload/complete")]
+async fn complete_direct_upload(
+    sess: AuthUserSession<'_>,
+    app_config: &State<AppConfig>,
+    staging_file_service: &State<Arc<StagingFileService>>,
+    file_service: &State<Arc<FileService>>,
+    audit_log_service: &State<Arc<AuditLogService>>,
+    staging_file_id: Uuid,
+) -> JsonRes<StagingFile> {
+    let staging_file = staging_file_service
+        .complete_direct_upload_by_id(staging_file_id)
+        .await;
+
+    let staging_file = match staging_file {
+        Ok(Ok(Some(staging_file))) => staging_file,
+        Ok(Ok(None)) => {
+            return Err(Error::new_not_found(ErrorCode::StagingFileNotFound));
+        }
+        Ok(Err(err)) => {
+            return Err(direct_upload_error(&err));
+        }
+        Err(err) => {
+            log::error!(target: "routes::staging_file::controllers", controller = "complete_direct_upload", service = "StagingFileService", staging_file_id:serde, err:err; "Error returned from service.");
+            return Err(Status::InternalServerError.into());
+        }
+    };
+
+    // the data is uploaded as a whole, so it is complete even without an expected size
+    if !app_config.auto_promote_staging_files {
+        return Ok(ApiResponse::new(Status::Ok, staging_file));
+    }
+
+    let file = file_service
+        .create_file_from_staging_file_id(staging_file_id)
+        .await;
+
+    let file = match file {
+        Ok(Some(file)) => file,
+        Ok(None) => {
+            return Err(Error::new_not_found(ErrorCode::StagingFileNotFound));
+        }
+        Err(err) => {
+            log::error!(target: "routes::staging_file::controllers", controller = "complete_direct_upload", service = "FileService", staging_file_id:serde, err:err; "Error returned from service.");
+            return Err(map_file_service_err(&err));
+        }
+    };
+
+    record_file_upload(audit_log_service, sess.audit_actor(), &file).await;
+
+    Ok(ApiResponse::new(Status::Created, staging_file))
 }
@@ -1,9 +1,26 @@
+use crate::validation::{FieldErrors, Validate, MAX_NAME_LENGTH};
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize)]
 pub struct CreatingStagingFile<'a> {
     pub name: &'a str,
     pub mime: Option<&'a str>,
+    /// The size of the whole file. Writes beyond it are rejected.
+    #[serde(default)]
+    pub expected_size: Option<u64>,
+    /// The CRC32 hash of the whole file. The file cannot be promoted unless its content matches it.
+    #[serde(default)]
+    pub expected_hash: Option<u32>,
+}
+
+impl Validate for CreatingStagingFile<'_> {
+    fn validate_fields(&self, errors: &mut FieldErrors) {
+        errors.check_text("name", self.name, MAX_NAME_LENGTH);
+
+        if let Some(mime) = self.mime {
+            errors.check_mime("mime", mime);
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize)]
@@ -11,3 +28,13 @@ pub struct UpdatingStagingFile<'a> {
     pub name: &'a str,
     pub mime: Option<&'a str>,
 }
+
+impl Validate for UpdatingStagingFile<'_> {
+    fn validate_fields(&self, errors: &mut FieldErrors) {
+        errors.check_text("name", self.name, MAX_NAME_LENGTH);
+
+        if let Some(mime) = self.mime {
+            errors.check_mime("mime", mime);
+        }
+    }
+}
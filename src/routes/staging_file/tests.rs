@@ -1,14 +1,22 @@
 use super::dto::{CreatingStagingFile, UpdatingStagingFile};
 use crate::{
+    config::SearchBackend,
     db::models::StagingFile,
-    services::{AuthService, StagingFileService, UserService},
-    test::{create_test_rocket_instance, helpers::create_initial_user},
+    fairings::{remove_expired_staging_files, remove_orphaned_staging_files},
+    services::{AuthService, FileDriver, FileService, StagingFileService, UserService},
+    test::{
+        create_test_rocket_instance, create_test_rocket_instance_with_config,
+        create_test_rocket_instance_with_file_driver, helpers::create_initial_user, TestFileDriver,
+    },
 };
+use chrono::Duration;
 use rocket::{
+    data::ByteUnit,
     http::{Accept, ContentType, Header, Status},
     local::asynchronous::Client,
 };
 use std::sync::Arc;
+use uuid::Uuid;
 
 #[rocket::async_test]
 async fn test_create_staging_file() {
@@ -32,7 +40,15 @@ async fn test_create_staging_file() {
             "Authorization",
             format!("Bearer {}", initial_user_session.token),
         ))
-        .body(serde_json::to_string(&CreatingStagingFile { name, mime }).unwrap())
+        .body(
+            serde_json::to_string(&CreatingStagingFile {
+                name,
+                mime,
+                expected_size: None,
+                expected_hash: None,
+            })
+            .unwrap(),
+        )
         .dispatch()
         .await;
 
@@ -41,10 +57,7 @@ async fn test_create_staging_file() {
 
     assert_eq!(status, Status::Created);
     assert_eq!(created_staging_file.name, name);
-    assert_eq!(
-        created_staging_file.mime.as_ref().map(|mime| mime.as_str()),
-        mime
-    );
+    assert_eq!(created_staging_file.mime.as_deref(), mime);
 
     let raw_staging_file = staging_file_service
         .get_staging_file_by_id(created_staging_file.id)
@@ -67,7 +80,7 @@ async fn test_remove_staging_file() {
         create_initial_user(auth_service, user_service).await;
 
     let staging_file = staging_file_service
-        .create_staging_file("staging_file", Some("video/mp4"))
+        .create_staging_file("staging_file", Some("video/mp4"), None, None)
         .await
         .unwrap();
 
@@ -108,7 +121,7 @@ async fn test_get_staging_file() {
         create_initial_user(auth_service, user_service).await;
 
     let staging_file = staging_file_service
-        .create_staging_file("staging_file", Some("video/mp4"))
+        .create_staging_file("staging_file", Some("video/mp4"), None, None)
         .await
         .unwrap();
 
@@ -150,7 +163,7 @@ async fn test_update_staging_file() {
         create_initial_user(auth_service, user_service).await;
 
     let staging_file = staging_file_service
-        .create_staging_file("staging_file", Some("video/mp4"))
+        .create_staging_file("staging_file", Some("video/mp4"), None, None)
         .await
         .unwrap();
 
@@ -203,7 +216,7 @@ async fn test_fill_staging_file() {
         create_initial_user(auth_service, user_service).await;
 
     let staging_file = staging_file_service
-        .create_staging_file("staging_file", Some("video/mp4"))
+        .create_staging_file("staging_file", Some("video/mp4"), None, None)
         .await
         .unwrap();
 
@@ -238,6 +251,54 @@ async fn test_fill_staging_file() {
     assert_eq!(raw_filled_staging_file, filled_staging_file);
 }
 
+#[rocket::async_test]
+async fn test_fill_staging_file_insufficient_storage() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance_with_config(
+        TestFileDriver::Local,
+        SearchBackend::Meilisearch,
+        |app_config| app_config.transfers.free_space_reserve = ByteUnit::max_value(),
+    )
+    .await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let staging_file_service = client.rocket().state::<Arc<StagingFileService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    let (_initial_user, initial_user_session) =
+        create_initial_user(auth_service, user_service).await;
+
+    let staging_file = staging_file_service
+        .create_staging_file("staging_file", Some("video/mp4"), None, None)
+        .await
+        .unwrap();
+
+    let response = client
+        .put(format!("/staging-files/{}/data", staging_file.id))
+        .header(Accept::JSON)
+        .header(ContentType::Binary)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .body("file content")
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let body = response.into_json::<serde_json::Value>().await.unwrap();
+
+    assert_eq!(status, Status::InsufficientStorage);
+    assert_eq!(body["error_code"], "INSUFFICIENT_STORAGE");
+
+    let raw_staging_file = staging_file_service
+        .get_staging_file_by_id(staging_file.id)
+        .await
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(raw_staging_file.size, 0);
+}
+
 #[rocket::async_test]
 async fn test_fill_staging_file_with_offset() {
     let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
@@ -250,7 +311,7 @@ async fn test_fill_staging_file_with_offset() {
         create_initial_user(auth_service, user_service).await;
 
     let staging_file = staging_file_service
-        .create_staging_file("staging_file", Some("video/mp4"))
+        .create_staging_file("staging_file", Some("video/mp4"), None, None)
         .await
         .unwrap();
 
@@ -330,7 +391,7 @@ async fn test_fill_staging_file_with_offset_min() {
         create_initial_user(auth_service, user_service).await;
 
     let staging_file = staging_file_service
-        .create_staging_file("staging_file", Some("video/mp4"))
+        .create_staging_file("staging_file", Some("video/mp4"), None, None)
         .await
         .unwrap();
 
@@ -410,7 +471,7 @@ async fn test_fill_staging_file_with_offset_max() {
         create_initial_user(auth_service, user_service).await;
 
     let staging_file = staging_file_service
-        .create_staging_file("staging_file", Some("video/mp4"))
+        .create_staging_file("staging_file", Some("video/mp4"), None, None)
         .await
         .unwrap();
 
@@ -477,3 +538,493 @@ async fn test_fill_staging_file_with_offset_max() {
 
     assert_eq!(raw_filled_staging_file, filled_staging_file);
 }
+
+#[rocket::async_test]
+async fn test_fill_staging_file_with_expected_size() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let staging_file_service = client.rocket().state::<Arc<StagingFileService>>().unwrap();
+    let file_service = client.rocket().state::<Arc<FileService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    let (_initial_user, initial_user_session) =
+        create_initial_user(auth_service, user_service).await;
+
+    let file_content = "file content";
+    let staging_file = staging_file_service
+        .create_staging_file(
+            "staging_file",
+            Some("video/mp4"),
+            Some(file_content.len() as u64),
+            None,
+        )
+        .await
+        .unwrap();
+
+    let response = client
+        .put(format!("/staging-files/{}/data", staging_file.id))
+        .header(Accept::JSON)
+        .header(ContentType::Binary)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .body(&file_content[..4])
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let filled_staging_file = response.into_json::<StagingFile>().await.unwrap();
+
+    assert_eq!(status, Status::Ok);
+    assert_eq!(filled_staging_file.size, 4);
+
+    // the rest of the data goes past the expected size
+    let response = client
+        .put(format!("/staging-files/{}/data", staging_file.id))
+        .header(Accept::JSON)
+        .header(Header::new("Offset", "4"))
+        .header(ContentType::Binary)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .body(format!("{}!", &file_content[4..]))
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::PayloadTooLarge);
+
+    let response = client
+        .put(format!("/staging-files/{}/data", staging_file.id))
+        .header(Accept::JSON)
+        .header(Header::new("Offset", "4"))
+        .header(ContentType::Binary)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .body(&file_content[4..])
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let filled_staging_file = response.into_json::<StagingFile>().await.unwrap();
+
+    // filled up to the expected size, so it is promoted automatically
+    assert_eq!(status, Status::Created);
+    assert_eq!(filled_staging_file.size, file_content.len() as i64);
+
+    let file = file_service
+        .get_file_by_id(staging_file.id)
+        .await
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(file.size, file_content.len() as i64);
+
+    let staging_file = staging_file_service
+        .get_staging_file_by_id(staging_file.id)
+        .await
+        .unwrap();
+
+    assert_eq!(staging_file, None);
+}
+
+#[rocket::async_test]
+async fn test_fill_staging_file_finalize() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let staging_file_service = client.rocket().state::<Arc<StagingFileService>>().unwrap();
+    let file_service = client.rocket().state::<Arc<FileService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    let (_initial_user, initial_user_session) =
+        create_initial_user(auth_service, user_service).await;
+
+    let file_content = "file content";
+    let first_staging_file = staging_file_service
+        .create_staging_file("first", Some("video/mp4"), None, None)
+        .await
+        .unwrap();
+    let second_staging_file = staging_file_service
+        .create_staging_file("second", Some("video/mp4"), None, None)
+        .await
+        .unwrap();
+
+    let response = client
+        .put(format!(
+            "/staging-files/{}/data?finalize=true",
+            first_staging_file.id
+        ))
+        .header(Accept::JSON)
+        .header(ContentType::Binary)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .body(file_content)
+        .dispatch()
+        .await;
+
+    // no expected size, but finalized explicitly
+    assert_eq!(response.status(), Status::Created);
+
+    let response = client
+        .put(format!("/staging-files/{}/data", second_staging_file.id))
+        .header(Accept::JSON)
+        .header(ContentType::Binary)
+        .header(Header::new("X-Finalize", "true"))
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .body(file_content)
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::Created);
+
+    for staging_file in [first_staging_file, second_staging_file] {
+        let file = file_service
+            .get_file_by_id(staging_file.id)
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(file.name, staging_file.name);
+        assert_eq!(file.size, file_content.len() as i64);
+
+        let staging_file = staging_file_service
+            .get_staging_file_by_id(staging_file.id)
+            .await
+            .unwrap();
+
+        assert_eq!(staging_file, None);
+    }
+
+    let staging_file = staging_file_service
+        .create_staging_file("third", Some("video/mp4"), None, None)
+        .await
+        .unwrap();
+
+    let response = client
+        .put(format!("/staging-files/{}/data", staging_file.id))
+        .header(Accept::JSON)
+        .header(ContentType::Binary)
+        .header(Header::new("X-Finalize", "yes"))
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .body(file_content)
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::BadRequest);
+}
+
+#[rocket::async_test]
+async fn test_fill_staging_file_with_expected_hash_mismatch() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let staging_file_service = client.rocket().state::<Arc<StagingFileService>>().unwrap();
+    let file_service = client.rocket().state::<Arc<FileService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    let (_initial_user, initial_user_session) =
+        create_initial_user(auth_service, user_service).await;
+
+    let file_content = "file content";
+    // the CRC32 hash of `file content` is 3503491758
+    let staging_file = staging_file_service
+        .create_staging_file(
+            "staging_file",
+            Some("video/mp4"),
+            Some(file_content.len() as u64),
+            Some(3503491759),
+        )
+        .await
+        .unwrap();
+
+    let response = client
+        .put(format!("/staging-files/{}/data", staging_file.id))
+        .header(Accept::JSON)
+        .header(ContentType::Binary)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .body(file_content)
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+
+    let file = file_service.get_file_by_id(staging_file.id).await.unwrap();
+
+    assert_eq!(file, None);
+
+    // the staging file is kept, so that the upload can be inspected or retried
+    let staging_file = staging_file_service
+        .get_staging_file_by_id(staging_file.id)
+        .await
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(staging_file.size, file_content.len() as i64);
+}
+
+#[rocket::async_test]
+async fn test_fill_staging_file_chunks() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let staging_file_service = client.rocket().state::<Arc<StagingFileService>>().unwrap();
+    let file_service = client.rocket().state::<Arc<FileService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    let (_initial_user, initial_user_session) =
+        create_initial_user(auth_service, user_service).await;
+
+    let file_content = "file content";
+    let staging_file = staging_file_service
+        .create_staging_file(
+            "staging_file",
+            Some("text/plain"),
+            Some(file_content.len() as u64),
+            None,
+        )
+        .await
+        .unwrap();
+
+    // chunks can be written out of order
+    let response = client
+        .put(format!("/staging-files/{}/chunks/4", staging_file.id))
+        .header(Accept::JSON)
+        .header(ContentType::Binary)
+        .header(Header::new("Content-Length", "8"))
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .body(&file_content[4..])
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let filled_staging_file = response.into_json::<StagingFile>().await.unwrap();
+
+    assert_eq!(status, Status::Ok);
+    assert_eq!(filled_staging_file.size, file_content.len() as i64);
+
+    let response = client
+        .put(format!("/staging-files/{}/chunks/2", staging_file.id))
+        .header(Accept::JSON)
+        .header(ContentType::Binary)
+        .header(Header::new("Content-Length", "4"))
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .body(&file_content[2..6])
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::Conflict);
+
+    // the gap is still there, so it cannot be promoted yet
+    let file = file_service
+        .create_file_from_staging_file_id(staging_file.id)
+        .await;
+
+    assert!(file.is_err());
+
+    let response = client
+        .put(format!("/staging-files/{}/chunks/0", staging_file.id))
+        .header(Accept::JSON)
+        .header(ContentType::Binary)
+        .header(Header::new("Content-Length", "4"))
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .body(&file_content[..4])
+        .dispatch()
+        .await;
+
+    // all chunks are written, so it is promoted automatically
+    assert_eq!(response.status(), Status::Created);
+
+    let response = client
+        .get(format!("/files/{}/data", staging_file.id))
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.into_string().await.unwrap(), file_content);
+}
+
+#[rocket::async_test]
+async fn test_fill_staging_file_chunk_without_expected_size() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let staging_file_service = client.rocket().state::<Arc<StagingFileService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    let (_initial_user, initial_user_session) =
+        create_initial_user(auth_service, user_service).await;
+
+    let staging_file = staging_file_service
+        .create_staging_file("staging_file", Some("text/plain"), None, None)
+        .await
+        .unwrap();
+
+    let response = client
+        .put(format!("/staging-files/{}/chunks/0", staging_file.id))
+        .header(Accept::JSON)
+        .header(ContentType::Binary)
+        .header(Header::new("Content-Length", "4"))
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .body("file")
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+}
+
+#[rocket::async_test]
+async fn test_direct_upload_unsupported() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let staging_file_service = client.rocket().state::<Arc<StagingFileService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    let (_initial_user, initial_user_session) =
+        create_initial_user(auth_service, user_service).await;
+
+    let staging_file = staging_file_service
+        .create_staging_file("staging_file", Some("video/mp4"), Some(4), None)
+        .await
+        .unwrap();
+
+    // files in memory cannot be uploaded to directly
+    for uri in [
+        format!("/staging-files/{}/direct-upload", staging_file.id),
+        format!("/staging-files/{}/direct-upload/complete", staging_file.id),
+    ] {
+        let response = client
+            .post(uri)
+            .header(Accept::JSON)
+            .header(Header::new(
+                "Authorization",
+                format!("Bearer {}", initial_user_session.token),
+            ))
+            .dispatch()
+            .await;
+
+        let status = response.status();
+        let body = response.into_json::<serde_json::Value>().await.unwrap();
+
+        assert_eq!(status, Status::NotImplemented);
+        assert_eq!(body["error_code"], "DIRECT_UPLOAD_UNSUPPORTED");
+    }
+}
+
+#[rocket::async_test]
+async fn test_remove_expired_staging_files_in_batches() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let staging_file_service = client.rocket().state::<Arc<StagingFileService>>().unwrap();
+
+    let mut staging_file_ids = vec![];
+
+    for index in 0..10 {
+        let staging_file = staging_file_service
+            .create_staging_file(&format!("staging_file{}", index), None, None, None)
+            .await
+            .unwrap();
+        staging_file_ids.push(staging_file.id);
+    }
+
+    let count_staging_files = || async {
+        let mut count = 0;
+
+        for &staging_file_id in &staging_file_ids {
+            if staging_file_service
+                .get_staging_file_by_id(staging_file_id)
+                .await
+                .unwrap()
+                .is_some()
+            {
+                count += 1;
+            }
+        }
+
+        count
+    };
+
+    // the batches stop at the maximum, leaving the rest for the next period
+    remove_expired_staging_files(Duration::zero(), 3, 2, staging_file_service).await;
+
+    assert_eq!(count_staging_files().await, 4);
+
+    // and keep going until a short batch shows that the expired staging files are drained
+    remove_expired_staging_files(Duration::zero(), 3, 10, staging_file_service).await;
+
+    assert_eq!(count_staging_files().await, 0);
+}
+
+#[rocket::async_test]
+async fn test_remove_orphaned_staging_files() {
+    let (rocket, _database_dropper, _index_dropper) =
+        create_test_rocket_instance_with_file_driver(TestFileDriver::Memory).await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let staging_file_service = client.rocket().state::<Arc<StagingFileService>>().unwrap();
+    let file_driver = client
+        .rocket()
+        .state::<Arc<dyn FileDriver + Send + Sync>>()
+        .unwrap();
+
+    let staging_file = staging_file_service
+        .create_staging_file("staging_file", None, None, None)
+        .await
+        .unwrap();
+    file_driver
+        .write_staging(staging_file.id, 0, Box::pin(&b"staging content"[..]))
+        .await
+        .unwrap();
+
+    // e.g. written right before a crash, without the staging file being created
+    let orphan_id = Uuid::new_v4();
+    file_driver
+        .write_staging(orphan_id, 0, Box::pin(&b"orphan content"[..]))
+        .await
+        .unwrap();
+
+    // staging data written recently may still be waiting for its staging file
+    remove_orphaned_staging_files(Duration::hours(1), staging_file_service).await;
+
+    assert!(file_driver.read_staging(orphan_id).await.unwrap().is_some());
+
+    remove_orphaned_staging_files(Duration::zero(), staging_file_service).await;
+
+    assert!(file_driver.read_staging(orphan_id).await.unwrap().is_none());
+    assert!(file_driver
+        .read_staging(staging_file.id)
+        .await
+        .unwrap()
+        .is_some());
+}
@@ -1,5 +1,141 @@
-use rocket::{routes, Build, Rocket};
+use super::dto::{TagBatch, TagBatchResult, TagList};
+use crate::{
+    dto::{ApiResponse, Error, ErrorCode, JsonRes},
+    guards::AuthUserSession,
+    services::{AuditAction, AuditActor, AuditLogService, TagOrder, TagService},
+    validation::Validate,
+};
+use rocket::{get, http::Status, post, routes, serde::json::Json, Build, Rocket, State};
+use std::sync::Arc;
+use uuid::Uuid;
 
 pub fn register_routes(rocket: Rocket<Build>) -> Rocket<Build> {
-    rocket.mount("/tags", routes![])
+    rocket.mount("/tags", routes![get_tags, apply_tag_batch])
+}
+
+/// Lists the distinct tags with the number of files having each of them, e.g. to render a tag cloud.
+/// The tags are sorted by `sort`, which is either `count` (the default) or `name`.
+#[get("/?<prefix>&<sort>&<limit>&<last_tag>")]
+async fn get_tags(
+    #[allow(unused_variables)] sess: AuthUserSession<'_>,
+    tag_service: &State<Arc<TagService>>,
+    prefix: Option<&str>,
+    sort: Option<&str>,
+    limit: Option<u32>,
+    last_tag: Option<String>,
+) -> JsonRes<TagList> {
+    let order = match sort {
+        Some(sort) => TagOrder::from_name(sort).ok_or_else(|| {
+            Error::new_dynamic(
+                Status::BadRequest,
+                format!("unknown sort `{}`; expected one of count, name", sort),
+            )
+            .with_code(ErrorCode::InvalidParameter)
+        })?,
+        None => TagOrder::Count,
+    };
+    let limit = limit.unwrap_or(25);
+    let limit = u32::max(1, limit);
+    let limit = u32::min(limit, 100);
+    let tags = tag_service
+        .get_tags(prefix, order, last_tag.as_deref(), limit)
+        .await;
+
+    let page = match tags {
+        Ok(page) => page,
+        Err(err) => {
+            log::error!(target: "routes::tag::controllers", controller = "get_tags", service = "TagService", prefix:serde, last_tag:serde, limit, err:err; "Error returned from service.");
+            return Err(Status::InternalServerError.into());
+        }
+    };
+
+    Ok(ApiResponse::new(
+        Status::Ok,
+        TagList {
+            tags: page.items,
+            last_tag,
+            limit,
+            next_cursor: page.next_cursor,
+        },
+    ))
+}
+
+/// Adds and removes tags across a set of files at once.
+/// Removals are applied before additions, and all of them are applied in a single transaction.
+/// Files that do not exist are skipped and reported with `found: false`.
+#[post("/batch", data = "<body>")]
+async fn apply_tag_batch(
+    sess: AuthUserSession<'_>,
+    tag_service: &State<Arc<TagService>>,
+    audit_log_service: &State<Arc<AuditLogService>>,
+    body: Json<TagBatch>,
+) -> JsonRes<TagBatchResult> {
+    body.validate()?;
+
+    let changes = tag_service
+        .apply_tag_batch(&body.file_ids, &body.add, &body.remove)
+        .await;
+
+    let changes = match changes {
+        Ok(changes) => changes,
+        Err(err) => {
+            let body = body.into_inner();
+            log::error!(target: "routes::tag::controllers", controller = "apply_tag_batch", service = "TagService", body:serde, err:err; "Error returned from service.");
+            return Err(Status::InternalServerError.into());
+        }
+    };
+
+    for change in &changes {
+        if 0 < change.removed {
+            record_tag_action(
+                audit_log_service,
+                sess.audit_actor(),
+                AuditAction::TagsRemoved,
+                change.file_id,
+                &body.remove,
+            )
+            .await;
+        }
+
+        if 0 < change.added {
+            record_tag_action(
+                audit_log_service,
+                sess.audit_actor(),
+                AuditAction::TagsAdded,
+                change.file_id,
+                &body.add,
+            )
+            .await;
+        }
+    }
+
+    Ok(ApiResponse::new(
+        Status::Ok,
+        TagBatchResult { files: changes },
+    ))
+}
+
+/// Records a change of the tags of a file in the audit log.
+/// Failures are only logged, as the change itself has succeeded.
+async fn record_tag_action(
+    audit_log_service: &AuditLogService,
+    actor: AuditActor,
+    action: AuditAction,
+    file_id: Uuid,
+    tags: &[String],
+) {
+    let result = audit_log_service
+        .record(
+            actor,
+            action,
+            Some(file_id),
+            None,
+            serde_json::json!({ "tags": tags }),
+        )
+        .await;
+
+    if let Err(err) = result {
+        let user_id = actor.user_id;
+        log::warn!(target: "routes::tag::controllers", service = "AuditLogService", user_id, file_id:serde, err:err; "Failed to record the audit log.");
+    }
 }
@@ -0,0 +1,57 @@
+use crate::{
+    services::{FileTagChange, TagCount},
+    validation::{FieldErrors, Validate, MAX_BATCH_SIZE, MAX_NAME_LENGTH},
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Tags to add to and remove from a set of files, e.g. the files selected in a client.
+#[derive(Serialize, Deserialize)]
+pub struct TagBatch {
+    pub file_ids: Vec<Uuid>,
+    #[serde(default)]
+    pub add: Vec<String>,
+    #[serde(default)]
+    pub remove: Vec<String>,
+}
+
+impl Validate for TagBatch {
+    fn validate_fields(&self, errors: &mut FieldErrors) {
+        if MAX_BATCH_SIZE < self.file_ids.len() {
+            errors.add(
+                "file_ids",
+                format!("should have at most {} IDs", MAX_BATCH_SIZE),
+            );
+        }
+
+        if MAX_BATCH_SIZE < self.add.len() + self.remove.len() {
+            errors.add(
+                "add",
+                format!("should have at most {} tags in total", MAX_BATCH_SIZE),
+            );
+        }
+
+        for tag in &self.add {
+            errors.check_text("add", tag, MAX_NAME_LENGTH);
+        }
+
+        for tag in &self.remove {
+            errors.check_text("remove", tag, MAX_NAME_LENGTH);
+        }
+    }
+}
+
+/// What a tag batch changed, one entry per distinct file ID in the request order.
+#[derive(Serialize, Deserialize)]
+pub struct TagBatchResult {
+    pub files: Vec<FileTagChange>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct TagList {
+    pub tags: Vec<TagCount>,
+    pub last_tag: Option<String>,
+    pub limit: u32,
+    /// The `last_tag` to fetch the next page with, or `None` if this is the last page.
+    pub next_cursor: Option<String>,
+}
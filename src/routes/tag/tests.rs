@@ -0,0 +1,258 @@
+use super::dto::{TagBatch, TagBatchResult, TagList};
+use crate::{
+    services::{
+        AuthService, FileService, FileTagChange, StagingFileService, TagCount, TagService,
+        UserService,
+    },
+    test::{
+        create_test_rocket_instance,
+        helpers::{create_file, create_initial_user},
+    },
+};
+use rocket::{
+    http::{Accept, ContentType, Header, Status},
+    local::asynchronous::Client,
+};
+use std::sync::Arc;
+use uuid::Uuid;
+
+#[rocket::async_test]
+async fn test_apply_tag_batch() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let staging_file_service = client.rocket().state::<Arc<StagingFileService>>().unwrap();
+    let file_service = client.rocket().state::<Arc<FileService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+    let tag_service = client.rocket().state::<Arc<TagService>>().unwrap();
+
+    let (_initial_user, initial_user_session) =
+        create_initial_user(auth_service, user_service).await;
+
+    let photo = create_file(
+        &client,
+        staging_file_service,
+        file_service,
+        &initial_user_session,
+        "photo",
+        Some("image/png"),
+        "photo content",
+    )
+    .await;
+    let note = create_file(
+        &client,
+        staging_file_service,
+        file_service,
+        &initial_user_session,
+        "note",
+        Some("text/plain"),
+        "note content",
+    )
+    .await;
+
+    tag_service
+        .add_tags_to_files(&[photo.id], &["draft", "year:1959"])
+        .await
+        .unwrap();
+
+    let missing_file_id = Uuid::new_v4();
+    let response = client
+        .post("/tags/batch")
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .body(
+            serde_json::to_string(&TagBatch {
+                file_ids: vec![photo.id, note.id, missing_file_id],
+                add: vec!["year:1959".to_owned(), "favorite".to_owned()],
+                remove: vec!["draft".to_owned()],
+            })
+            .unwrap(),
+        )
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let result = response.into_json::<TagBatchResult>().await.unwrap();
+
+    assert_eq!(status, Status::Ok);
+    assert_eq!(
+        result.files,
+        vec![
+            FileTagChange {
+                file_id: photo.id,
+                found: true,
+                added: 1,
+                removed: 1,
+            },
+            FileTagChange {
+                file_id: note.id,
+                found: true,
+                added: 2,
+                removed: 0,
+            },
+            FileTagChange {
+                file_id: missing_file_id,
+                found: false,
+                added: 0,
+                removed: 0,
+            },
+        ]
+    );
+
+    let tags = tag_service.get_tags_by_file_id(photo.id).await.unwrap();
+    let tags = tags
+        .iter()
+        .map(|tag| (tag.namespace.as_str(), tag.name.as_str()))
+        .collect::<Vec<_>>();
+
+    assert_eq!(tags, vec![("", "favorite"), ("year", "1959")]);
+
+    let response = client
+        .post("/tags/batch")
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .body(
+            serde_json::to_string(&TagBatch {
+                file_ids: vec![photo.id],
+                add: vec![" ".to_owned()],
+                remove: vec![],
+            })
+            .unwrap(),
+        )
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+}
+
+#[rocket::async_test]
+async fn test_get_tags() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let staging_file_service = client.rocket().state::<Arc<StagingFileService>>().unwrap();
+    let file_service = client.rocket().state::<Arc<FileService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+    let tag_service = client.rocket().state::<Arc<TagService>>().unwrap();
+
+    let (_initial_user, initial_user_session) =
+        create_initial_user(auth_service, user_service).await;
+
+    let mut file_ids = vec![];
+
+    for index in 0..3 {
+        let file = create_file(
+            &client,
+            staging_file_service,
+            file_service,
+            &initial_user_session,
+            &format!("file {}", index),
+            Some("text/plain"),
+            "file content",
+        )
+        .await;
+        file_ids.push(file.id);
+    }
+
+    tag_service
+        .add_tags_to_files(&file_ids, &["year:1959"])
+        .await
+        .unwrap();
+    tag_service
+        .add_tags_to_files(&file_ids[..2], &["favorite"])
+        .await
+        .unwrap();
+    tag_service
+        .add_tags_to_files(&file_ids[..1], &["year:1960"])
+        .await
+        .unwrap();
+
+    let tag_count = |namespace: &str, name: &str, count: i64| TagCount {
+        namespace: namespace.to_owned(),
+        name: name.to_owned(),
+        count,
+    };
+
+    let mut paged_tags = vec![];
+    let mut cursor: Option<String> = None;
+
+    loop {
+        let mut uri = "/tags?sort=count&limit=2".to_owned();
+
+        if let Some(cursor) = &cursor {
+            uri.push_str(&format!("&last_tag={}", cursor));
+        }
+
+        let response = client
+            .get(uri)
+            .header(Accept::JSON)
+            .header(Header::new(
+                "Authorization",
+                format!("Bearer {}", initial_user_session.token),
+            ))
+            .dispatch()
+            .await;
+
+        let status = response.status();
+        let result = response.into_json::<TagList>().await.unwrap();
+
+        assert_eq!(status, Status::Ok);
+        assert!(result.tags.len() <= 2);
+
+        paged_tags.extend(result.tags);
+        cursor = result.next_cursor;
+
+        if cursor.is_none() {
+            break;
+        }
+    }
+
+    assert_eq!(
+        paged_tags,
+        vec![
+            tag_count("year", "1959", 3),
+            tag_count("", "favorite", 2),
+            tag_count("year", "1960", 1),
+        ]
+    );
+
+    let response = client
+        .get("/tags?prefix=year:19&sort=name")
+        .header(Accept::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let result = response.into_json::<TagList>().await.unwrap();
+
+    assert_eq!(status, Status::Ok);
+    assert_eq!(
+        result.tags,
+        vec![tag_count("year", "1959", 3), tag_count("year", "1960", 1)]
+    );
+    assert_eq!(result.next_cursor, None);
+
+    let response = client
+        .get("/tags?sort=size")
+        .header(Accept::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::BadRequest);
+}
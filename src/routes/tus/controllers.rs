@@ -0,0 +1,324 @@
+use super::dto::TusResponse;
+use crate::{
+    config::AppConfig,
+    dto::{Error, ErrorCode},
+    guards::{AuthUserSession, ContentLengthHeader, TusHeaders},
+    routes::{
+        file::controllers::{map_file_service_err, record_file_upload, too_many_transfers},
+        staging_file::controllers::{ensure_free_space, write_error_code},
+    },
+    services::{
+        parse_tus_metadata, AuditLogService, FileService, FreeSpaceService, LiveConfigService,
+        TransferLimitService, TusChecksum, TusChecksumAlgorithm, TusService, TusWriteError,
+        WriteError, TUS_EXTENSIONS, TUS_VERSION,
+    },
+};
+use rocket::{
+    delete, head,
+    http::{ContentType, Status},
+    options, patch, post, routes, Build, Data, Rocket, State,
+};
+use std::sync::Arc;
+use uuid::Uuid;
+
+pub fn register_routes(rocket: Rocket<Build>) -> Rocket<Build> {
+    rocket.mount(
+        "/tus",
+        routes![
+            get_tus_capabilities,
+            create_tus_upload,
+            get_tus_upload_offset,
+            write_tus_upload,
+            remove_tus_upload
+        ],
+    )
+}
+
+#[options("/")]
+async fn get_tus_capabilities(live_config_service: &State<Arc<LiveConfigService>>) -> TusResponse {
+    let checksum_algorithms = TusChecksumAlgorithm::ALL
+        .iter()
+        .map(|algorithm| algorithm.name())
+        .collect::<Vec<_>>()
+        .join(",");
+
+    TusResponse::new(Status::NoContent)
+        .header("Tus-Version", TUS_VERSION)
+        .header("Tus-Extension", TUS_EXTENSIONS)
+        .header(
+            "Tus-Max-Size",
+            live_config_service.get().file_limit.as_u64().to_string(),
+        )
+        .header("Tus-Checksum-Algorithm", checksum_algorithms)
+}
+
+#[post("/")]
+async fn create_tus_upload(
+    #[allow(unused_variables)] sess: AuthUserSession<'_>,
+    live_config_service: &State<Arc<LiveConfigService>>,
+    tus_service: &State<Arc<TusService>>,
+    tus_headers: TusHeaders<'_>,
+) -> Result<TusResponse, Error> {
+    let length = match tus_headers.upload_length {
+        Some(length) => length,
+        None => {
+            return Err(Error::new_static(
+                Status::BadRequest,
+                "`Upload-Length` header is required; deferring the length is not supported",
+            )
+            .with_code(ErrorCode::InvalidParameter));
+        }
+    };
+
+    let max_size = live_config_service.get().file_limit.as_u64();
+
+    if max_size < length {
+        return Err(Error::new_dynamic(
+            Status::PayloadTooLarge,
+            format!(
+                "the upload length `{}` exceeds the maximum size `{}`",
+                length, max_size
+            ),
+        )
+        .with_code(ErrorCode::FileTooLarge));
+    }
+
+    let metadata = match tus_headers.upload_metadata {
+        Some(metadata) => match parse_tus_metadata(metadata) {
+            Some(metadata) => metadata,
+            None => {
+                return Err(Error::new_static(
+                    Status::BadRequest,
+                    "`Upload-Metadata` header is invalid",
+                )
+                .with_code(ErrorCode::InvalidParameter));
+            }
+        },
+        None => Default::default(),
+    };
+    // tus clients commonly send the original filename and MIME type as `filename` and `filetype`
+    let name = metadata
+        .get("filename")
+        .or_else(|| metadata.get("name"))
+        .cloned()
+        .flatten()
+        .unwrap_or_else(|| "upload".to_owned());
+    let mime = metadata
+        .get("filetype")
+        .or_else(|| metadata.get("type"))
+        .cloned()
+        .flatten();
+
+    let upload = tus_service
+        .create_upload(&name, mime.as_deref(), length)
+        .await;
+
+    let upload = match upload {
+        Ok(upload) => upload,
+        Err(err) => {
+            log::error!(target: "routes::tus::controllers", controller = "create_tus_upload", service = "TusService", length, err:err; "Error returned from service.");
+            return Err(Status::InternalServerError.into());
+        }
+    };
+
+    Ok(TusResponse::new(Status::Created)
+        .header("Location", format!("/tus/{}", upload.id))
+        .header("Upload-Offset", "0"))
+}
+
+#[head("/<upload_id>")]
+async fn get_tus_upload_offset(
+    #[allow(unused_variables)] sess: AuthUserSession<'_>,
+    tus_service: &State<Arc<TusService>>,
+    file_service: &State<Arc<FileService>>,
+    #[allow(unused_variables)] tus_headers: TusHeaders<'_>,
+    upload_id: Uuid,
+) -> Result<TusResponse, Error> {
+    let upload = tus_service.get_upload(upload_id).await;
+
+    let (offset, length) = match upload {
+        Ok(Some(upload)) => (upload.size, upload.expected_size.unwrap_or(upload.size)),
+        Ok(None) => {
+            // the upload may have been promoted into a file already
+            match file_service.get_file_by_id(upload_id).await {
+                Ok(Some(file)) => (file.size, file.size),
+                Ok(None) => {
+                    return Err(Error::new_not_found(ErrorCode::UploadNotFound));
+                }
+                Err(err) => {
+                    log::error!(target: "routes::tus::controllers", controller = "get_tus_upload_offset", service = "FileService", upload_id:serde, err:err; "Error returned from service.");
+                    return Err(Status::InternalServerError.into());
+                }
+            }
+        }
+        Err(err) => {
+            log::error!(target: "routes::tus::controllers", controller = "get_tus_upload_offset", service = "TusService", upload_id:serde, err:err; "Error returned from service.");
+            return Err(Status::InternalServerError.into());
+        }
+    };
+
+    Ok(TusResponse::new(Status::Ok)
+        .header("Upload-Offset", offset.to_string())
+        .header("Upload-Length", length.to_string())
+        .header("Cache-Control", "no-store"))
+}
+
+/// Appends a chunk to an upload.
+/// The upload is promoted into a file with the same ID once it is complete, if automatic promotion is enabled.
+#[allow(clippy::too_many_arguments)]
+#[patch("/<upload_id>", data = "<body>")]
+async fn write_tus_upload(
+    sess: AuthUserSession<'_>,
+    app_config: &State<AppConfig>,
+    live_config_service: &State<Arc<LiveConfigService>>,
+    tus_service: &State<Arc<TusService>>,
+    transfer_limit_service: &State<Arc<TransferLimitService>>,
+    free_space_service: &State<Arc<FreeSpaceService>>,
+    file_service: &State<Arc<FileService>>,
+    audit_log_service: &State<Arc<AuditLogService>>,
+    tus_headers: TusHeaders<'_>,
+    content_length_header: Option<ContentLengthHeader>,
+    content_type: Option<&ContentType>,
+    upload_id: Uuid,
+    body: Data<'_>,
+) -> Result<TusResponse, Error> {
+    let is_offset_stream = content_type.is_some_and(|content_type| {
+        content_type.top() == "application" && content_type.sub() == "offset+octet-stream"
+    });
+
+    if !is_offset_stream {
+        return Err(Error::new_static(
+            Status::UnsupportedMediaType,
+            "`Content-Type` header should be `application/offset+octet-stream`",
+        ));
+    }
+
+    let offset = match tus_headers.upload_offset {
+        Some(offset) => offset,
+        None => {
+            return Err(Error::new_static(
+                Status::BadRequest,
+                "`Upload-Offset` header is required",
+            )
+            .with_code(ErrorCode::InvalidParameter));
+        }
+    };
+    let checksum = match tus_headers.upload_checksum {
+        Some(checksum) => match TusChecksum::parse(checksum) {
+            Some(checksum) => Some(checksum),
+            None => {
+                return Err(Error::new_static(
+                    Status::BadRequest,
+                    "`Upload-Checksum` header is invalid or its algorithm is not supported",
+                )
+                .with_code(ErrorCode::InvalidParameter));
+            }
+        },
+        None => None,
+    };
+
+    let _permit = transfer_limit_service
+        .try_acquire_upload()
+        .ok_or_else(|| too_many_transfers(transfer_limit_service))?;
+    ensure_free_space(
+        free_space_service,
+        content_length_header.map(|header| header.content_length),
+        "write_tus_upload",
+    )
+    .await?;
+
+    let stream = body.open(live_config_service.get().file_limit);
+    let stream = transfer_limit_service.throttle_upload(Some(&sess.user.role), Box::pin(stream));
+    let upload = tus_service
+        .write_chunk(upload_id, offset, checksum.as_ref(), stream)
+        .await;
+
+    let upload = match upload {
+        Ok(Ok(Some(upload))) => upload,
+        Ok(Ok(None)) => {
+            return Err(Error::new_not_found(ErrorCode::UploadNotFound));
+        }
+        Ok(Err(err)) => match err {
+            TusWriteError::OffsetMismatch { expected, actual } => {
+                return Err(Error::new_dynamic(
+                    Status::Conflict,
+                    format!(
+                        "the offset `{}` does not match the upload offset `{}`",
+                        actual, expected
+                    ),
+                )
+                .with_code(ErrorCode::OffsetMismatch));
+            }
+            TusWriteError::ChecksumMismatch => {
+                return Err(Error::new_static(Status::new(460), "checksum mismatch")
+                    .with_code(ErrorCode::ChecksumMismatch));
+            }
+            TusWriteError::Write(WriteError::Write {
+                io_error,
+                file_size,
+            }) => {
+                log::error!(target: "routes::tus::controllers", controller = "write_tus_upload", service = "TusService", upload_id:serde, io_error:err, file_size; "Error returned from service.");
+                return Err(Status::InternalServerError.into());
+            }
+            TusWriteError::Write(WriteError::ExceedsExpectedSize { expected_size }) => {
+                return Err(Error::new_dynamic(
+                    Status::PayloadTooLarge,
+                    format!("the data exceeds the upload length `{}`", expected_size),
+                )
+                .with_code(ErrorCode::SizeExceedsExpected));
+            }
+            TusWriteError::Write(err) => {
+                return Err(
+                    Error::new_dynamic(Status::UnprocessableEntity, err.to_string())
+                        .with_code(write_error_code(&err)),
+                );
+            }
+        },
+        Err(err) => {
+            log::error!(target: "routes::tus::controllers", controller = "write_tus_upload", service = "TusService", upload_id:serde, err:err; "Error returned from service.");
+            return Err(Status::InternalServerError.into());
+        }
+    };
+
+    if app_config.auto_promote_staging_files && upload.expected_size == Some(upload.size) {
+        let file = file_service
+            .create_file_from_staging_file_id(upload_id)
+            .await;
+
+        match file {
+            Ok(Some(file)) => {
+                record_file_upload(audit_log_service, sess.audit_actor(), &file).await;
+            }
+            Ok(None) => {}
+            Err(err) => {
+                log::error!(target: "routes::tus::controllers", controller = "write_tus_upload", service = "FileService", upload_id:serde, err:err; "Error returned from service.");
+                return Err(map_file_service_err(&err));
+            }
+        }
+    }
+
+    Ok(TusResponse::new(Status::NoContent).header("Upload-Offset", upload.size.to_string()))
+}
+
+#[delete("/<upload_id>")]
+async fn remove_tus_upload(
+    #[allow(unused_variables)] sess: AuthUserSession<'_>,
+    tus_service: &State<Arc<TusService>>,
+    #[allow(unused_variables)] tus_headers: TusHeaders<'_>,
+    upload_id: Uuid,
+) -> Result<TusResponse, Error> {
+    let upload = tus_service.remove_upload(upload_id).await;
+
+    match upload {
+        Ok(Some(_)) => {}
+        Ok(None) => {
+            return Err(Error::new_not_found(ErrorCode::UploadNotFound));
+        }
+        Err(err) => {
+            log::error!(target: "routes::tus::controllers", controller = "remove_tus_upload", service = "TusService", upload_id:serde, err:err; "Error returned from service.");
+            return Err(Status::InternalServerError.into());
+        }
+    }
+
+    Ok(TusResponse::new(Status::NoContent))
+}
@@ -0,0 +1,43 @@
+use crate::services::TUS_VERSION;
+use rocket::{
+    http::{Header, Status},
+    response::{Responder, Result},
+    Request, Response,
+};
+
+/// A bodiless response of the tus protocol.
+/// The `Tus-Resumable` header is always included.
+pub struct TusResponse {
+    pub status: Status,
+    pub headers: Vec<Header<'static>>,
+}
+
+impl TusResponse {
+    pub fn new(status: Status) -> Self {
+        Self {
+            status,
+            headers: Vec::new(),
+        }
+    }
+
+    pub fn header(mut self, name: &'static str, value: impl Into<String>) -> Self {
+        self.headers.push(Header::new(name, value.into()));
+        self
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> Responder<'r, 'static> for TusResponse {
+    fn respond_to(self, _: &'r Request<'_>) -> Result<'static> {
+        let mut response = Response::build();
+        response
+            .status(self.status)
+            .header(Header::new("Tus-Resumable", TUS_VERSION));
+
+        for header in self.headers {
+            response.header(header);
+        }
+
+        response.ok()
+    }
+}
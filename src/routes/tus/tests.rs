@@ -0,0 +1,188 @@
+use crate::{
+    services::{AuthService, FileService, UserService},
+    test::{create_test_rocket_instance, helpers::create_initial_user},
+};
+use rocket::{
+    http::{Header, Status},
+    local::asynchronous::Client,
+};
+use std::sync::Arc;
+use uuid::Uuid;
+
+#[rocket::async_test]
+async fn test_get_tus_capabilities() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+    let client = Client::tracked(rocket).await.unwrap();
+
+    let response = client.options("/tus").dispatch().await;
+
+    assert_eq!(response.status(), Status::NoContent);
+    assert_eq!(response.headers().get_one("Tus-Version"), Some("1.0.0"));
+    assert_eq!(
+        response.headers().get_one("Tus-Extension"),
+        Some("creation,checksum,termination")
+    );
+}
+
+#[rocket::async_test]
+async fn test_tus_upload() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let file_service = client.rocket().state::<Arc<FileService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    let (_initial_user, initial_user_session) =
+        create_initial_user(auth_service, user_service).await;
+    let authorization = Header::new(
+        "Authorization",
+        format!("Bearer {}", initial_user_session.token),
+    );
+
+    // `file.txt` and `text/plain` in Base64
+    let response = client
+        .post("/tus")
+        .header(authorization.clone())
+        .header(Header::new("Tus-Resumable", "1.0.0"))
+        .header(Header::new("Upload-Length", "12"))
+        .header(Header::new(
+            "Upload-Metadata",
+            "filename ZmlsZS50eHQ=,filetype dGV4dC9wbGFpbg==",
+        ))
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::Created);
+
+    let location = response.headers().get_one("Location").unwrap().to_owned();
+    let upload_id = location
+        .strip_prefix("/tus/")
+        .unwrap()
+        .parse::<Uuid>()
+        .unwrap();
+
+    let response = client
+        .patch(location.clone())
+        .header(authorization.clone())
+        .header(Header::new("Tus-Resumable", "1.0.0"))
+        .header(Header::new("Upload-Offset", "0"))
+        .header(Header::new(
+            "Content-Type",
+            "application/offset+octet-stream",
+        ))
+        // the SHA1 digest of `file ` in Base64
+        .header(Header::new(
+            "Upload-Checksum",
+            "sha1 PauY8J/Qz6nUNOfYY44Sc5Wp+wM=",
+        ))
+        .body("file ")
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::NoContent);
+    assert_eq!(response.headers().get_one("Upload-Offset"), Some("5"));
+
+    let response = client
+        .head(location.clone())
+        .header(authorization.clone())
+        .header(Header::new("Tus-Resumable", "1.0.0"))
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.headers().get_one("Upload-Offset"), Some("5"));
+    assert_eq!(response.headers().get_one("Upload-Length"), Some("12"));
+
+    // the chunk must start at the current offset
+    let response = client
+        .patch(location.clone())
+        .header(authorization.clone())
+        .header(Header::new("Tus-Resumable", "1.0.0"))
+        .header(Header::new("Upload-Offset", "4"))
+        .header(Header::new(
+            "Content-Type",
+            "application/offset+octet-stream",
+        ))
+        .body("content")
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::Conflict);
+
+    let response = client
+        .patch(location.clone())
+        .header(authorization.clone())
+        .header(Header::new("Tus-Resumable", "1.0.0"))
+        .header(Header::new("Upload-Offset", "5"))
+        .header(Header::new(
+            "Content-Type",
+            "application/offset+octet-stream",
+        ))
+        .header(Header::new("Upload-Checksum", "crc32 AAAAAA=="))
+        .body("content")
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status().code, 460);
+
+    let response = client
+        .patch(location.clone())
+        .header(authorization.clone())
+        .header(Header::new("Tus-Resumable", "1.0.0"))
+        .header(Header::new("Upload-Offset", "5"))
+        .header(Header::new(
+            "Content-Type",
+            "application/offset+octet-stream",
+        ))
+        .body("content")
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::NoContent);
+    assert_eq!(response.headers().get_one("Upload-Offset"), Some("12"));
+
+    // the complete upload is promoted into a file
+    let file = file_service
+        .get_file_by_id(upload_id)
+        .await
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(file.name, "file.txt");
+    assert_eq!(file.mime, "text/plain");
+    assert_eq!(file.size, 12);
+
+    let response = client
+        .head(location)
+        .header(authorization)
+        .header(Header::new("Tus-Resumable", "1.0.0"))
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.headers().get_one("Upload-Offset"), Some("12"));
+}
+
+#[rocket::async_test]
+async fn test_tus_upload_unsupported_version() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    let (_initial_user, initial_user_session) =
+        create_initial_user(auth_service, user_service).await;
+
+    let response = client
+        .post("/tus")
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .header(Header::new("Tus-Resumable", "0.2.2"))
+        .header(Header::new("Upload-Length", "12"))
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::PreconditionFailed);
+}
@@ -0,0 +1,215 @@
+use super::dto::{CreatingUploadTickets, UploadTicketList};
+use crate::{
+    config::AppConfig,
+    db::models::File,
+    dto::{ApiResponse, Error, ErrorCode, JsonRes},
+    guards::{AuthUserSession, ClientInfo, ContentLengthHeader},
+    routes::{
+        file::controllers::{record_file_upload, too_many_transfers},
+        staging_file::controllers::{ensure_free_space, write_error_code},
+    },
+    services::{
+        AuditActor, AuditLogService, FileService, FreeSpaceService, LiveConfigService,
+        StagingFileService, TransferLimitService, UploadTicketService, WriteError,
+    },
+};
+use rocket::{
+    data::ByteUnit, http::Status, post, put, routes, serde::json::Json, Build, Data, Rocket, State,
+};
+use std::sync::Arc;
+
+pub fn register_routes(rocket: Rocket<Build>) -> Rocket<Build> {
+    rocket.mount(
+        "/uploads",
+        routes![create_upload_tickets, upload_with_ticket],
+    )
+}
+
+#[post("/tickets", data = "<body>")]
+async fn create_upload_tickets(
+    sess: AuthUserSession<'_>,
+    app_config: &State<AppConfig>,
+    live_config_service: &State<Arc<LiveConfigService>>,
+    upload_ticket_service: &State<Arc<UploadTicketService>>,
+    body: Json<CreatingUploadTickets>,
+) -> JsonRes<UploadTicketList> {
+    let max_count = app_config.upload_ticket.max_count;
+
+    if body.count < 1 || max_count < body.count {
+        return Err(Error::new_dynamic(
+            Status::BadRequest,
+            format!("count should be between 1 and {}", max_count),
+        )
+        .with_code(ErrorCode::InvalidParameter));
+    }
+
+    let max_size = live_config_service.get().file_limit.as_u64();
+
+    if body.max_size < 1 || max_size < body.max_size {
+        return Err(Error::new_dynamic(
+            Status::BadRequest,
+            format!("max size should be between 1 and {}", max_size),
+        )
+        .with_code(ErrorCode::InvalidParameter));
+    }
+
+    let tickets = upload_ticket_service
+        .create_upload_tickets(sess.user.id, sess.token, body.count, body.max_size)
+        .await;
+
+    let tickets = match tickets {
+        Ok(tickets) => tickets,
+        Err(err) => {
+            let body = body.into_inner();
+            log::error!(target: "routes::upload::controllers", controller = "create_upload_tickets", service = "UploadTicketService", body:serde, err:err; "Error returned from service.");
+            return Err(Status::InternalServerError.into());
+        }
+    };
+
+    log::info!(target: "routes::upload::controllers", controller = "create_upload_tickets", user_id = sess.user.id, count = body.count, max_size = body.max_size; "Upload tickets created.");
+
+    Ok(ApiResponse::new(
+        Status::Created,
+        UploadTicketList { tickets },
+    ))
+}
+
+/// Uploads a file with an upload ticket instead of a bearer token.
+/// The ticket is consumed by the request, regardless of whether the upload succeeds.
+#[allow(clippy::too_many_arguments)]
+#[put("/<token>?<name>&<mime>", data = "<body>")]
+async fn upload_with_ticket(
+    live_config_service: &State<Arc<LiveConfigService>>,
+    upload_ticket_service: &State<Arc<UploadTicketService>>,
+    staging_file_service: &State<Arc<StagingFileService>>,
+    transfer_limit_service: &State<Arc<TransferLimitService>>,
+    free_space_service: &State<Arc<FreeSpaceService>>,
+    file_service: &State<Arc<FileService>>,
+    audit_log_service: &State<Arc<AuditLogService>>,
+    client_info: ClientInfo,
+    content_length_header: Option<ContentLengthHeader>,
+    token: &str,
+    name: &str,
+    mime: Option<&str>,
+    body: Data<'_>,
+) -> JsonRes<File> {
+    let ticket = upload_ticket_service.claim_upload_ticket(token).await;
+
+    let ticket = match ticket {
+        Ok(Some(ticket)) => ticket,
+        Ok(None) => {
+            return Err(Error::new_not_found(ErrorCode::UploadTicketNotFound));
+        }
+        Err(err) => {
+            log::error!(target: "routes::upload::controllers", controller = "upload_with_ticket", service = "UploadTicketService", err:err; "Error returned from service.");
+            return Err(Status::InternalServerError.into());
+        }
+    };
+
+    let staging_file = staging_file_service
+        .create_staging_file(name, mime, None, None)
+        .await;
+
+    let staging_file = match staging_file {
+        Ok(staging_file) => staging_file,
+        Err(err) => {
+            log::error!(target: "routes::upload::controllers", controller = "upload_with_ticket", service = "StagingFileService", err:err; "Error returned from service.");
+            return Err(Status::InternalServerError.into());
+        }
+    };
+
+    // read one byte more than allowed, so that oversized uploads can be told apart from truncated ones
+    let max_size = u64::min(
+        ticket.max_size as u64,
+        live_config_service.get().file_limit.as_u64(),
+    );
+    let limit = max_size + 1;
+    let _permit = transfer_limit_service
+        .try_acquire_upload()
+        .ok_or_else(|| too_many_transfers(transfer_limit_service))?;
+    ensure_free_space(
+        free_space_service,
+        content_length_header.map(|header| header.content_length),
+        "upload_with_ticket",
+    )
+    .await?;
+
+    // the ticket does not tell who uploads, so the upload takes the global speed
+    let stream = body.open(ByteUnit::from(limit));
+    let stream = transfer_limit_service.throttle_upload(None, Box::pin(stream));
+    let filled_staging_file = staging_file_service
+        .fill_staging_file_by_id(staging_file.id, None, stream)
+        .await;
+
+    let error = match filled_staging_file {
+        Ok(Ok(Some(filled_staging_file))) if filled_staging_file.size as u64 <= max_size => None,
+        Ok(Ok(Some(_))) => Some(
+            Error::new_dynamic(
+                Status::PayloadTooLarge,
+                format!(
+                    "the file exceeds the maximum size `{}` of the ticket",
+                    max_size
+                ),
+            )
+            .with_code(ErrorCode::FileTooLarge),
+        ),
+        Ok(Ok(None)) => Some(Error::new_not_found(ErrorCode::StagingFileNotFound)),
+        Ok(Err(WriteError::Write {
+            io_error,
+            file_size,
+        })) => {
+            let staging_file_id = staging_file.id;
+            log::error!(target: "routes::upload::controllers", controller = "upload_with_ticket", service = "StagingFileService", staging_file_id:serde, io_error:err, file_size; "Error returned from service.");
+            Some(Status::InternalServerError.into())
+        }
+        Ok(Err(err)) => Some(
+            Error::new_dynamic(Status::UnprocessableEntity, err.to_string())
+                .with_code(write_error_code(&err)),
+        ),
+        Err(err) => {
+            let staging_file_id = staging_file.id;
+            log::error!(target: "routes::upload::controllers", controller = "upload_with_ticket", service = "StagingFileService", staging_file_id:serde, err:err; "Error returned from service.");
+            Some(Status::InternalServerError.into())
+        }
+    };
+
+    if let Some(error) = error {
+        // it is safe to ignore the result, as leftover staging files expire anyway
+        staging_file_service
+            .remove_staging_file_by_id(staging_file.id, None, true)
+            .await
+            .ok();
+        return Err(error);
+    }
+
+    let file = file_service
+        .create_file_from_staging_file_id(staging_file.id)
+        .await;
+
+    let file = match file {
+        Ok(Some(file)) => file,
+        Ok(None) => {
+            return Err(Error::new_not_found(ErrorCode::StagingFileNotFound));
+        }
+        Err(err) => {
+            let staging_file_id = staging_file.id;
+            log::error!(target: "routes::upload::controllers", controller = "upload_with_ticket", service = "FileService", staging_file_id:serde, err:err; "Error returned from service.");
+            return Err(Status::InternalServerError.into());
+        }
+    };
+
+    if let Err(err) = upload_ticket_service
+        .record_uploaded_file(token, file.id)
+        .await
+    {
+        let file_id = file.id;
+        log::warn!(target: "routes::upload::controllers", controller = "upload_with_ticket", service = "UploadTicketService", file_id:serde, err:err; "Failed to record the uploaded file.");
+    }
+
+    record_file_upload(audit_log_service, AuditActor::user(ticket.user_id), &file).await;
+
+    let file_id = file.id;
+    log::info!(target: "routes::upload::controllers", controller = "upload_with_ticket", user_id = ticket.user_id, file_id:serde, client_ip:? = client_info.ip, scheme = client_info.scheme; "File uploaded with upload ticket.");
+
+    Ok(ApiResponse::new(Status::Created, file))
+}
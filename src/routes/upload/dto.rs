@@ -0,0 +1,14 @@
+use crate::db::models::UploadTicket;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreatingUploadTickets {
+    pub count: u32,
+    pub max_size: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct UploadTicketList {
+    pub tickets: Vec<UploadTicket>,
+}
@@ -0,0 +1,210 @@
+use super::dto::{CreatingUploadTickets, UploadTicketList};
+use crate::{
+    config::SearchBackend,
+    db::models::File,
+    services::{AuthService, FileService, UserService},
+    test::{
+        create_test_rocket_instance, create_test_rocket_instance_with_config,
+        helpers::create_initial_user, TestFileDriver,
+    },
+};
+use rocket::{
+    data::ByteUnit,
+    http::{Accept, ContentType, Header, Status},
+    local::asynchronous::Client,
+};
+use std::sync::Arc;
+
+#[rocket::async_test]
+async fn test_create_upload_tickets() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    let (initial_user, initial_user_session) =
+        create_initial_user(auth_service, user_service).await;
+
+    let response = client
+        .post("/uploads/tickets")
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .body(
+            serde_json::to_string(&CreatingUploadTickets {
+                count: 2,
+                max_size: 1024,
+            })
+            .unwrap(),
+        )
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let ticket_list = response.into_json::<UploadTicketList>().await.unwrap();
+
+    assert_eq!(status, Status::Created);
+    assert_eq!(ticket_list.tickets.len(), 2);
+    assert_ne!(ticket_list.tickets[0].token, ticket_list.tickets[1].token);
+
+    for ticket in &ticket_list.tickets {
+        assert_eq!(ticket.user_id, initial_user.id);
+        assert_eq!(ticket.max_size, 1024);
+        assert_eq!(ticket.used_at, None);
+    }
+
+    let response = client
+        .post("/uploads/tickets")
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .body(
+            serde_json::to_string(&CreatingUploadTickets {
+                count: 0,
+                max_size: 1024,
+            })
+            .unwrap(),
+        )
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::BadRequest);
+}
+
+#[rocket::async_test]
+async fn test_upload_with_ticket() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let file_service = client.rocket().state::<Arc<FileService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    let (_initial_user, initial_user_session) =
+        create_initial_user(auth_service, user_service).await;
+
+    let response = client
+        .post("/uploads/tickets")
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .body(
+            serde_json::to_string(&CreatingUploadTickets {
+                count: 2,
+                max_size: 16,
+            })
+            .unwrap(),
+        )
+        .dispatch()
+        .await;
+
+    let ticket_list = response.into_json::<UploadTicketList>().await.unwrap();
+    let tickets = ticket_list.tickets;
+
+    // no bearer token is sent; the ticket is the credential
+    let response = client
+        .put(format!(
+            "/uploads/{}?name=file&mime=text/plain",
+            tickets[0].token
+        ))
+        .header(Accept::JSON)
+        .header(ContentType::Binary)
+        .body("file content")
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let uploaded_file = response.into_json::<File>().await.unwrap();
+
+    assert_eq!(status, Status::Created);
+    assert_eq!(uploaded_file.name, "file");
+    assert_eq!(uploaded_file.mime, "text/plain");
+    assert_eq!(uploaded_file.size, "file content".len() as i64);
+
+    let raw_file = file_service
+        .get_file_by_id(uploaded_file.id)
+        .await
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(raw_file, uploaded_file);
+
+    // tickets are single-use
+    let response = client
+        .put(format!("/uploads/{}?name=file", tickets[0].token))
+        .header(Accept::JSON)
+        .header(ContentType::Binary)
+        .body("file content")
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::NotFound);
+
+    let response = client
+        .put(format!("/uploads/{}?name=file", tickets[1].token))
+        .header(Accept::JSON)
+        .header(ContentType::Binary)
+        .body("file content exceeding the maximum size")
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::PayloadTooLarge);
+}
+
+#[rocket::async_test]
+async fn test_upload_with_ticket_at_file_limit() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance_with_config(
+        TestFileDriver::Memory,
+        SearchBackend::Meilisearch,
+        |app_config| app_config.limits.file = ByteUnit::Byte(16),
+    )
+    .await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    let (_initial_user, initial_user_session) =
+        create_initial_user(auth_service, user_service).await;
+
+    let response = client
+        .post("/uploads/tickets")
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .body(
+            serde_json::to_string(&CreatingUploadTickets {
+                count: 1,
+                max_size: 16,
+            })
+            .unwrap(),
+        )
+        .dispatch()
+        .await;
+
+    let ticket_list = response.into_json::<UploadTicketList>().await.unwrap();
+
+    // one byte more than both the ticket and the file limit allow must be rejected, not truncated
+    let response = client
+        .put(format!(
+            "/uploads/{}?name=file",
+            ticket_list.tickets[0].token
+        ))
+        .header(Accept::JSON)
+        .header(ContentType::Binary)
+        .body([0u8; 17])
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::PayloadTooLarge);
+}
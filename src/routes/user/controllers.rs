@@ -1,9 +1,33 @@
-use super::dto::{CreatingUser, SettingUserPassword, SettingUserUsername, UserList};
-use crate::{db::models::User, dto::JsonRes, guards::AuthUserSession, services::UserService};
+use super::dto::{
+    ActivityList, AvatarData, CreatingUser, FavoriteList, SettingUserDisplayName, SettingUserEmail,
+    SettingUserPassword, SettingUserUsername, UserList,
+};
+use crate::{
+    db::models::User,
+    dto::{ApiResponse, Error, ErrorCode, JsonRes},
+    guards::{AdminUserSession, AuthUserSession, ContentLengthHeader, RangeHeader},
+    routes::{
+        file::controllers::{map_file_service_err, read_file_data, too_many_transfers},
+        staging_file::controllers::{ensure_free_space, write_error_code},
+        user_session::dto::{UserSessionInfo, UserSessionInfoList},
+    },
+    services::{
+        AuditLogService, AuthService, EmailChangeService, EmailChangeServiceError, FavoriteService,
+        FileService, FreeSpaceService, LiveConfigService, ReadAheadService,
+        SetUserPreferencesError, StagingFileService, TransferLimitService, UserRole, UserService,
+        UserServiceError, WriteError,
+    },
+    validation::Validate,
+};
 use rocket::{
-    delete, get, http::Status, post, put, routes, serde::json::Json, Build, Rocket, State,
+    delete, get,
+    http::{ContentType, Status},
+    post, put, routes,
+    serde::json::Json,
+    Build, Data, Rocket, State,
 };
 use std::sync::Arc;
+use uuid::Uuid;
 
 pub fn register_routes(rocket: Rocket<Build>) -> Rocket<Build> {
     rocket.mount(
@@ -12,9 +36,21 @@ pub fn register_routes(rocket: Rocket<Build>) -> Rocket<Build> {
             create_user,
             remove_user,
             get_users,
+            get_user_favorites,
             get_user,
+            get_user_activity,
             set_user_username,
-            set_user_password
+            set_user_email,
+            set_user_display_name,
+            set_user_password,
+            get_user_preferences,
+            set_user_preferences,
+            set_user_avatar,
+            remove_user_avatar,
+            get_user_avatar,
+            remove_user_sessions,
+            disable_user,
+            enable_user
         ],
     )
 }
@@ -25,14 +61,21 @@ async fn create_user(
     user_service: &State<Arc<UserService>>,
     body: Json<CreatingUser<'_>>,
 ) -> JsonRes<User> {
+    body.validate()?;
+
     let user = user_service
         .create_user(body.username, body.email, body.password)
         .await;
 
     let user = match user {
-        Ok(Some(user)) => user,
-        Ok(None) => {
-            return Err(Status::Conflict.into());
+        Ok(user) => user,
+        Err(err @ UserServiceError::DuplicateEmail(_)) => {
+            return Err(Error::new_dynamic(Status::Conflict, err.to_string())
+                .with_code(ErrorCode::EmailTaken));
+        }
+        Err(err @ UserServiceError::DuplicateUsername(_)) => {
+            return Err(Error::new_dynamic(Status::Conflict, err.to_string())
+                .with_code(ErrorCode::UsernameTaken));
         }
         Err(err) => {
             let body = body.into_inner();
@@ -41,7 +84,7 @@ async fn create_user(
         }
     };
 
-    Ok((Status::Created, Json(user)))
+    Ok(ApiResponse::new(Status::Created, user))
 }
 
 #[delete("/<user_id>")]
@@ -55,7 +98,7 @@ async fn remove_user(
     let user = match user {
         Ok(Some(user)) => user,
         Ok(None) => {
-            return Err(Status::NotFound.into());
+            return Err(Error::new_not_found(ErrorCode::UserNotFound));
         }
         Err(err) => {
             log::error!(target: "routes::user::controllers", controller = "remove_user", service = "UserService", user_id:serde, err:err; "Error returned from service.");
@@ -63,37 +106,76 @@ async fn remove_user(
         }
     };
 
-    Ok((Status::Ok, Json(user)))
+    Ok(ApiResponse::new(Status::Ok, user))
 }
 
-#[get("/?<last_user_id>&<limit>")]
+#[get("/?<last_user_id>&<limit>&<with_total>")]
 async fn get_users(
     #[allow(unused_variables)] sess: AuthUserSession<'_>,
     user_service: &State<Arc<UserService>>,
     last_user_id: Option<i32>,
     limit: Option<u32>,
+    with_total: Option<bool>,
 ) -> JsonRes<UserList> {
     let limit = limit.unwrap_or(25);
     let limit = u32::max(1, limit);
     let limit = u32::min(limit, 100);
 
-    let users = user_service.get_users(last_user_id, limit).await;
+    let users = user_service
+        .get_users(last_user_id, limit, with_total.unwrap_or(false))
+        .await;
 
-    let users = match users {
-        Ok(users) => users,
+    let page = match users {
+        Ok(page) => page,
         Err(err) => {
             log::error!(target: "routes::user::controllers", controller = "get_users", service = "UserService", last_user_id:serde, limit, err:err; "Error returned from service.");
             return Err(Status::InternalServerError.into());
         }
     };
 
-    Ok((
+    Ok(ApiResponse::new(
         Status::Ok,
-        Json(UserList {
-            users,
+        UserList {
+            users: page.items,
             last_user_id,
             limit,
-        }),
+            next_cursor: page.next_cursor,
+            total: page.total,
+        },
+    ))
+}
+
+/// Lists the files and collections the requesting user has marked as favorites, from the most recently marked one.
+#[get("/me/favorites")]
+async fn get_user_favorites(
+    sess: AuthUserSession<'_>,
+    favorite_service: &State<Arc<FavoriteService>>,
+) -> JsonRes<FavoriteList> {
+    let files = favorite_service.get_favorite_files(sess.user.id).await;
+
+    let files = match files {
+        Ok(files) => files,
+        Err(err) => {
+            log::error!(target: "routes::user::controllers", controller = "get_user_favorites", service = "FavoriteService", err:err; "Error returned from service.");
+            return Err(Status::InternalServerError.into());
+        }
+    };
+
+    let collections = favorite_service
+        .get_favorite_collections(sess.user.id)
+        .await;
+
+    let collections = match collections {
+        Ok(collections) => collections,
+        Err(err) => {
+            log::error!(target: "routes::user::controllers", controller = "get_user_favorites", service = "FavoriteService", err:err; "Error returned from service.");
+            return Err(Status::InternalServerError.into());
+        }
+    };
+
+    Ok(ApiResponse::new(
+        Status::Ok,
+        FavoriteList { files, collections },
     ))
 }
 
@@ -108,7 +190,7 @@ async fn get_user(
     let user = match user {
         Ok(Some(user)) => user,
         Ok(None) => {
-            return Err(Status::NotFound.into());
+            return Err(Error::new_not_found(ErrorCode::UserNotFound));
         }
         Err(err) => {
             log::error!(target: "routes::user::controllers", controller = "get_user", service = "UserService", user_id:serde, err:err; "Error returned from service.");
@@ -116,7 +198,57 @@ async fn get_user(
         }
     };
 
-    Ok((Status::Ok, Json(user)))
+    Ok(ApiResponse::new(Status::Ok, user))
+}
+
+/// Lists the recent actions of the user, such as uploads and collection edits, from the most recent one.
+#[get("/<user_id>/activity?<last_activity_id>&<limit>")]
+async fn get_user_activity(
+    #[allow(unused_variables)] sess: AuthUserSession<'_>,
+    user_service: &State<Arc<UserService>>,
+    audit_log_service: &State<Arc<AuditLogService>>,
+    user_id: i32,
+    last_activity_id: Option<i64>,
+    limit: Option<u32>,
+) -> JsonRes<ActivityList> {
+    let limit = limit.unwrap_or(25);
+    let limit = u32::max(1, limit);
+    let limit = u32::min(limit, 100);
+
+    let user = user_service.get_user_by_id(user_id).await;
+
+    match user {
+        Ok(Some(_)) => {}
+        Ok(None) => {
+            return Err(Error::new_not_found(ErrorCode::UserNotFound));
+        }
+        Err(err) => {
+            log::error!(target: "routes::user::controllers", controller = "get_user_activity", service = "UserService", user_id:serde, err:err; "Error returned from service.");
+            return Err(Status::InternalServerError.into());
+        }
+    }
+
+    let audit_logs = audit_log_service
+        .get_audit_logs_of_user(user_id, last_activity_id, limit)
+        .await;
+
+    let page = match audit_logs {
+        Ok(page) => page,
+        Err(err) => {
+            log::error!(target: "routes::user::controllers", controller = "get_user_activity", service = "AuditLogService", user_id:serde, last_activity_id:serde, limit, err:err; "Error returned from service.");
+            return Err(Status::InternalServerError.into());
+        }
+    };
+
+    Ok(ApiResponse::new(
+        Status::Ok,
+        ActivityList {
+            activities: page.items,
+            last_activity_id,
+            limit,
+            next_cursor: page.next_cursor,
+        },
+    ))
 }
 
 #[put("/<user_id>/username", data = "<body>")]
@@ -126,6 +258,8 @@ async fn set_user_username(
     user_id: i32,
     body: Json<SettingUserUsername<'_>>,
 ) -> JsonRes<User> {
+    body.validate()?;
+
     let user = user_service
         .set_user_username_by_id(user_id, body.username)
         .await;
@@ -133,7 +267,11 @@ async fn set_user_username(
     let user = match user {
         Ok(Some(user)) => user,
         Ok(None) => {
-            return Err(Status::NotFound.into());
+            return Err(Error::new_not_found(ErrorCode::UserNotFound));
+        }
+        Err(err @ UserServiceError::DuplicateUsername(_)) => {
+            return Err(Error::new_dynamic(Status::Conflict, err.to_string())
+                .with_code(ErrorCode::UsernameTaken));
         }
         Err(err) => {
             let body = body.into_inner();
@@ -142,7 +280,71 @@ async fn set_user_username(
         }
     };
 
-    Ok((Status::Ok, Json(user)))
+    Ok(ApiResponse::new(Status::Ok, user))
+}
+
+/// Requests a change of the user's email.
+/// The email is changed only once the link mailed to the new email is opened, which calls `POST /email-changes/<token>`.
+#[put("/<user_id>/email", data = "<body>")]
+async fn set_user_email(
+    #[allow(unused_variables)] sess: AuthUserSession<'_>,
+    email_change_service: &State<Arc<EmailChangeService>>,
+    user_id: i32,
+    body: Json<SettingUserEmail<'_>>,
+) -> Result<Status, Error> {
+    body.validate()?;
+
+    let result = email_change_service
+        .request_email_change(user_id, body.email)
+        .await;
+
+    match result {
+        Ok(true) => {}
+        Ok(false) => {
+            return Err(Error::new_not_found(ErrorCode::UserNotFound));
+        }
+        Err(EmailChangeServiceError::EmailTaken(_)) => {
+            return Err(
+                Error::new_static(Status::Conflict, "the email is already in use")
+                    .with_code(ErrorCode::EmailTaken),
+            );
+        }
+        Err(err) => {
+            let body = body.into_inner();
+            log::error!(target: "routes::user::controllers", controller = "set_user_email", service = "EmailChangeService", user_id:serde, body:serde, err:err; "Error returned from service.");
+            return Err(Status::InternalServerError.into());
+        }
+    }
+
+    Ok(Status::Accepted)
+}
+
+#[put("/<user_id>/display-name", data = "<body>")]
+async fn set_user_display_name(
+    #[allow(unused_variables)] sess: AuthUserSession<'_>,
+    user_service: &State<Arc<UserService>>,
+    user_id: i32,
+    body: Json<SettingUserDisplayName<'_>>,
+) -> JsonRes<User> {
+    body.validate()?;
+
+    let user = user_service
+        .set_user_display_name_by_id(user_id, body.display_name)
+        .await;
+
+    let user = match user {
+        Ok(Some(user)) => user,
+        Ok(None) => {
+            return Err(Error::new_not_found(ErrorCode::UserNotFound));
+        }
+        Err(err) => {
+            let body = body.into_inner();
+            log::error!(target: "routes::user::controllers", controller = "set_user_display_name", service = "UserService", user_id:serde, body:serde, err:err; "Error returned from service.");
+            return Err(Status::InternalServerError.into());
+        }
+    };
+
+    Ok(ApiResponse::new(Status::Ok, user))
 }
 
 #[put("/<user_id>/password", data = "<body>")]
@@ -152,6 +354,8 @@ async fn set_user_password(
     user_id: i32,
     body: Json<SettingUserPassword<'_>>,
 ) -> JsonRes<User> {
+    body.validate()?;
+
     let user = user_service
         .set_user_password_by_id(user_id, body.password)
         .await;
@@ -159,7 +363,7 @@ async fn set_user_password(
     let user = match user {
         Ok(Some(user)) => user,
         Ok(None) => {
-            return Err(Status::NotFound.into());
+            return Err(Error::new_not_found(ErrorCode::UserNotFound));
         }
         Err(err) => {
             let body = body.into_inner();
@@ -168,5 +372,409 @@ async fn set_user_password(
         }
     };
 
-    Ok((Status::Ok, Json(user)))
+    Ok(ApiResponse::new(Status::Ok, user))
+}
+
+/// Logs the user out everywhere by removing all of their sessions.
+/// Fails with `403 Forbidden` if a user other than an admin asks to log out someone else.
+#[delete("/<user_id>/sessions")]
+async fn remove_user_sessions(
+    sess: AuthUserSession<'_>,
+    auth_service: &State<Arc<AuthService>>,
+    user_service: &State<Arc<UserService>>,
+    user_id: i32,
+) -> JsonRes<UserSessionInfoList> {
+    if user_id != sess.user.id && sess.user.role != UserRole::Admin.name() {
+        return Err(Error::new_static(
+            Status::Forbidden,
+            "only admins can remove the sessions of other users",
+        ));
+    }
+
+    let user = user_service.get_user_by_id(user_id).await;
+
+    match user {
+        Ok(Some(_)) => {}
+        Ok(None) => {
+            return Err(Error::new_not_found(ErrorCode::UserNotFound));
+        }
+        Err(err) => {
+            log::error!(target: "routes::user::controllers", controller = "remove_user_sessions", service = "UserService", user_id:serde, err:err; "Error returned from service.");
+            return Err(Status::InternalServerError.into());
+        }
+    }
+
+    let user_sessions = auth_service.remove_user_sessions(user_id).await;
+
+    let user_sessions = match user_sessions {
+        Ok(user_sessions) => user_sessions,
+        Err(err) => {
+            log::error!(target: "routes::user::controllers", controller = "remove_user_sessions", service = "AuthService", user_id:serde, err:err; "Error returned from service.");
+            return Err(Status::InternalServerError.into());
+        }
+    };
+
+    log::info!(target: "routes::user::controllers", controller = "remove_user_sessions", by_user_id = sess.user.id, user_id, count = user_sessions.len(); "User sessions removed.");
+
+    let user_sessions = user_sessions
+        .into_iter()
+        .map(|user_session| UserSessionInfo::new(user_session, Some(sess.token)))
+        .collect();
+
+    Ok(ApiResponse::new(
+        Status::Ok,
+        UserSessionInfoList { user_sessions },
+    ))
+}
+
+/// Disables a user instead of removing them, keeping their files and audit history.
+/// The user is logged out everywhere, and cannot log in until enabled again.
+#[post("/<user_id>/disable")]
+async fn disable_user(
+    sess: AdminUserSession,
+    auth_service: &State<Arc<AuthService>>,
+    user_service: &State<Arc<UserService>>,
+    user_id: i32,
+) -> JsonRes<User> {
+    if user_id == sess.user.id {
+        return Err(
+            Error::new_static(Status::Conflict, "admins cannot disable themselves")
+                .with_code(ErrorCode::CannotDisableSelf),
+        );
+    }
+
+    let user = user_service.set_user_disabled_by_id(user_id, true).await;
+
+    let user = match user {
+        Ok(Some(user)) => user,
+        Ok(None) => {
+            return Err(Error::new_not_found(ErrorCode::UserNotFound));
+        }
+        Err(err) => {
+            log::error!(target: "routes::user::controllers", controller = "disable_user", service = "UserService", user_id:serde, err:err; "Error returned from service.");
+            return Err(Status::InternalServerError.into());
+        }
+    };
+
+    if let Err(err) = auth_service.remove_user_sessions(user_id).await {
+        // the sessions of disabled users are rejected anyway, so they only take up space
+        log::warn!(target: "routes::user::controllers", controller = "disable_user", service = "AuthService", user_id:serde, err:err; "Error returned from service.");
+    }
+
+    log::warn!(target: "routes::user::controllers", controller = "disable_user", by_user_id = sess.user.id, user_id; "User disabled.");
+
+    Ok(ApiResponse::new(Status::Ok, user))
+}
+
+#[post("/<user_id>/enable")]
+async fn enable_user(
+    sess: AdminUserSession,
+    user_service: &State<Arc<UserService>>,
+    user_id: i32,
+) -> JsonRes<User> {
+    let user = user_service.set_user_disabled_by_id(user_id, false).await;
+
+    let user = match user {
+        Ok(Some(user)) => user,
+        Ok(None) => {
+            return Err(Error::new_not_found(ErrorCode::UserNotFound));
+        }
+        Err(err) => {
+            log::error!(target: "routes::user::controllers", controller = "enable_user", service = "UserService", user_id:serde, err:err; "Error returned from service.");
+            return Err(Status::InternalServerError.into());
+        }
+    };
+
+    log::warn!(target: "routes::user::controllers", controller = "enable_user", by_user_id = sess.user.id, user_id; "User enabled.");
+
+    Ok(ApiResponse::new(Status::Ok, user))
+}
+
+#[get("/<user_id>/preferences")]
+async fn get_user_preferences(
+    #[allow(unused_variables)] sess: AuthUserSession<'_>,
+    user_service: &State<Arc<UserService>>,
+    user_id: i32,
+) -> JsonRes<serde_json::Value> {
+    let preferences = user_service.get_user_preferences_by_id(user_id).await;
+
+    let preferences = match preferences {
+        Ok(Some(preferences)) => preferences,
+        Ok(None) => {
+            return Err(Error::new_not_found(ErrorCode::UserNotFound));
+        }
+        Err(err) => {
+            log::error!(target: "routes::user::controllers", controller = "get_user_preferences", service = "UserService", user_id:serde, err:err; "Error returned from service.");
+            return Err(Status::InternalServerError.into());
+        }
+    };
+
+    Ok(ApiResponse::new(Status::Ok, preferences))
+}
+
+/// Replaces the preferences of the user, such as the default sort order or the theme of a client.
+#[put("/<user_id>/preferences", data = "<body>")]
+async fn set_user_preferences(
+    #[allow(unused_variables)] sess: AuthUserSession<'_>,
+    user_service: &State<Arc<UserService>>,
+    user_id: i32,
+    body: Json<serde_json::Value>,
+) -> JsonRes<serde_json::Value> {
+    let preferences = user_service
+        .set_user_preferences_by_id(user_id, &body)
+        .await;
+
+    let preferences = match preferences {
+        Ok(Some(preferences)) => preferences,
+        Ok(None) => {
+            return Err(Error::new_not_found(ErrorCode::UserNotFound));
+        }
+        Err(err @ SetUserPreferencesError::NotAnObject) => {
+            return Err(Error::new_dynamic(
+                Status::UnprocessableEntity,
+                err.to_string(),
+            ));
+        }
+        Err(err @ SetUserPreferencesError::TooLarge { .. }) => {
+            return Err(Error::new_dynamic(Status::PayloadTooLarge, err.to_string())
+                .with_code(ErrorCode::PreferencesTooLarge));
+        }
+        Err(SetUserPreferencesError::Error(err)) => {
+            log::error!(target: "routes::user::controllers", controller = "set_user_preferences", service = "UserService", user_id:serde, err:err; "Error returned from service.");
+            return Err(Status::InternalServerError.into());
+        }
+    };
+
+    Ok(ApiResponse::new(Status::Ok, preferences))
+}
+
+/// Uploads an image as the avatar of the user, replacing the previous one.
+/// The image goes through a staging file and is kept as a regular file.
+#[allow(clippy::too_many_arguments)]
+#[put("/<user_id>/avatar", data = "<body>")]
+async fn set_user_avatar(
+    sess: AuthUserSession<'_>,
+    live_config_service: &State<Arc<LiveConfigService>>,
+    user_service: &State<Arc<UserService>>,
+    staging_file_service: &State<Arc<StagingFileService>>,
+    transfer_limit_service: &State<Arc<TransferLimitService>>,
+    free_space_service: &State<Arc<FreeSpaceService>>,
+    file_service: &State<Arc<FileService>>,
+    content_type: Option<&ContentType>,
+    content_length_header: Option<ContentLengthHeader>,
+    user_id: i32,
+    body: Data<'_>,
+) -> JsonRes<User> {
+    let mime = match content_type {
+        Some(content_type) if content_type.top() == "image" => content_type.to_string(),
+        _ => {
+            return Err(Error::new_static(
+                Status::UnsupportedMediaType,
+                "`Content-Type` header should be an image type",
+            )
+            .with_code(ErrorCode::NotAnImage));
+        }
+    };
+
+    let user = user_service.get_user_by_id(user_id).await;
+
+    match user {
+        Ok(Some(_)) => {}
+        Ok(None) => {
+            return Err(Error::new_not_found(ErrorCode::UserNotFound));
+        }
+        Err(err) => {
+            log::error!(target: "routes::user::controllers", controller = "set_user_avatar", service = "UserService", user_id:serde, err:err; "Error returned from service.");
+            return Err(Status::InternalServerError.into());
+        }
+    }
+
+    let staging_file = staging_file_service
+        .create_staging_file("avatar", Some(&mime), None, None)
+        .await;
+
+    let staging_file = match staging_file {
+        Ok(staging_file) => staging_file,
+        Err(err) => {
+            log::error!(target: "routes::user::controllers", controller = "set_user_avatar", service = "StagingFileService", user_id:serde, err:err; "Error returned from service.");
+            return Err(Status::InternalServerError.into());
+        }
+    };
+
+    let _permit = transfer_limit_service
+        .try_acquire_upload()
+        .ok_or_else(|| too_many_transfers(transfer_limit_service))?;
+    ensure_free_space(
+        free_space_service,
+        content_length_header.map(|header| header.content_length),
+        "set_user_avatar",
+    )
+    .await?;
+
+    let stream = body.open(live_config_service.get().file_limit);
+    let stream = transfer_limit_service.throttle_upload(Some(&sess.user.role), Box::pin(stream));
+    let filled_staging_file = staging_file_service
+        .fill_staging_file_by_id(staging_file.id, None, stream)
+        .await;
+
+    let error = match filled_staging_file {
+        Ok(Ok(Some(_))) => None,
+        Ok(Ok(None)) => Some(Error::new_not_found(ErrorCode::StagingFileNotFound)),
+        Ok(Err(WriteError::Write {
+            io_error,
+            file_size,
+        })) => {
+            let staging_file_id = staging_file.id;
+            log::error!(target: "routes::user::controllers", controller = "set_user_avatar", service = "StagingFileService", staging_file_id:serde, io_error:err, file_size; "Error returned from service.");
+            Some(Status::InternalServerError.into())
+        }
+        Ok(Err(err)) => Some(
+            Error::new_dynamic(Status::UnprocessableEntity, err.to_string())
+                .with_code(write_error_code(&err)),
+        ),
+        Err(err) => {
+            let staging_file_id = staging_file.id;
+            log::error!(target: "routes::user::controllers", controller = "set_user_avatar", service = "StagingFileService", staging_file_id:serde, err:err; "Error returned from service.");
+            Some(Status::InternalServerError.into())
+        }
+    };
+
+    if let Some(error) = error {
+        // it is safe to ignore the result, as leftover staging files expire anyway
+        staging_file_service
+            .remove_staging_file_by_id(staging_file.id, None, true)
+            .await
+            .ok();
+        return Err(error);
+    }
+
+    let file = file_service
+        .create_file_from_staging_file_id(staging_file.id)
+        .await;
+
+    let file = match file {
+        Ok(Some(file)) => file,
+        Ok(None) => {
+            return Err(Error::new_not_found(ErrorCode::StagingFileNotFound));
+        }
+        Err(err) => {
+            let staging_file_id = staging_file.id;
+            log::error!(target: "routes::user::controllers", controller = "set_user_avatar", service = "FileService", staging_file_id:serde, err:err; "Error returned from service.");
+            return Err(map_file_service_err(&err));
+        }
+    };
+
+    let result = user_service
+        .set_user_avatar_by_id(user_id, Some(file.id))
+        .await;
+
+    let (user, previous_avatar_file_id) = match result {
+        Ok(Some(result)) => result,
+        Ok(None) => {
+            // the user has been removed in the meantime
+            remove_avatar_file(file_service, file.id).await;
+            return Err(Error::new_not_found(ErrorCode::UserNotFound));
+        }
+        Err(err) => {
+            let file_id = file.id;
+            log::error!(target: "routes::user::controllers", controller = "set_user_avatar", service = "UserService", user_id:serde, file_id:serde, err:err; "Error returned from service.");
+            remove_avatar_file(file_service, file.id).await;
+            return Err(Status::InternalServerError.into());
+        }
+    };
+
+    if let Some(previous_avatar_file_id) = previous_avatar_file_id {
+        remove_avatar_file(file_service, previous_avatar_file_id).await;
+    }
+
+    Ok(ApiResponse::new(Status::Ok, user))
+}
+
+#[delete("/<user_id>/avatar")]
+async fn remove_user_avatar(
+    #[allow(unused_variables)] sess: AuthUserSession<'_>,
+    user_service: &State<Arc<UserService>>,
+    file_service: &State<Arc<FileService>>,
+    user_id: i32,
+) -> JsonRes<User> {
+    let result = user_service.set_user_avatar_by_id(user_id, None).await;
+
+    let (user, previous_avatar_file_id) = match result {
+        Ok(Some(result)) => result,
+        Ok(None) => {
+            return Err(Error::new_not_found(ErrorCode::UserNotFound));
+        }
+        Err(err) => {
+            log::error!(target: "routes::user::controllers", controller = "remove_user_avatar", service = "UserService", user_id:serde, err:err; "Error returned from service.");
+            return Err(Status::InternalServerError.into());
+        }
+    };
+
+    if let Some(previous_avatar_file_id) = previous_avatar_file_id {
+        remove_avatar_file(file_service, previous_avatar_file_id).await;
+    }
+
+    Ok(ApiResponse::new(Status::Ok, user))
+}
+
+#[get("/<user_id>/avatar")]
+async fn get_user_avatar(
+    sess: AuthUserSession<'_>,
+    user_service: &State<Arc<UserService>>,
+    file_service: &State<Arc<FileService>>,
+    read_ahead_service: &State<Arc<ReadAheadService>>,
+    transfer_limit_service: &State<Arc<TransferLimitService>>,
+    range_header: RangeHeader,
+    user_id: i32,
+) -> Result<AvatarData, Error> {
+    let user = user_service.get_user_by_id(user_id).await;
+
+    let avatar_file_id = match user {
+        Ok(Some(User {
+            avatar_file_id: Some(avatar_file_id),
+            ..
+        })) => avatar_file_id,
+        Ok(_) => {
+            return Err(Error::new_not_found(ErrorCode::AvatarNotFound));
+        }
+        Err(err) => {
+            log::error!(target: "routes::user::controllers", controller = "get_user_avatar", service = "UserService", user_id:serde, err:err; "Error returned from service.");
+            return Err(Status::InternalServerError.into());
+        }
+    };
+
+    let file = file_service.get_file_by_id(avatar_file_id).await;
+
+    let file = match file {
+        Ok(Some(file)) => file,
+        Ok(None) => {
+            return Err(Error::new_not_found(ErrorCode::AvatarNotFound));
+        }
+        Err(err) => {
+            log::error!(target: "routes::user::controllers", controller = "get_user_avatar", service = "FileService", avatar_file_id:serde, err:err; "Error returned from service.");
+            return Err(map_file_service_err(&err));
+        }
+    };
+
+    let data = read_file_data(
+        file_service,
+        transfer_limit_service,
+        read_ahead_service,
+        sess.token,
+        Some(&sess.user.role),
+        file,
+        range_header,
+        false,
+    )
+    .await?;
+
+    Ok(AvatarData(data))
+}
+
+/// Removes an avatar file that is no longer referenced.
+async fn remove_avatar_file(file_service: &FileService, file_id: Uuid) {
+    if let Err(err) = file_service.remove_file_by_id(file_id).await {
+        // a leftover avatar only wastes storage, so it is not critical
+        log::warn!(target: "routes::user::controllers", service = "FileService", file_id:serde, err:err; "Failed to remove the avatar file.");
+    }
 }
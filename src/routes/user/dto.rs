@@ -1,4 +1,13 @@
-use crate::db::models::User;
+use crate::{
+    db::models::{AuditLog, Collection, File, User},
+    routes::file::dto::FileData,
+    validation::{FieldErrors, Validate, MAX_PASSWORD_LENGTH, MAX_USER_NAME_LENGTH},
+};
+use rocket::{
+    http::Header,
+    response::{Responder, Result},
+    Request,
+};
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize)]
@@ -8,19 +17,95 @@ pub struct CreatingUser<'a> {
     pub password: &'a str,
 }
 
+impl Validate for CreatingUser<'_> {
+    fn validate_fields(&self, errors: &mut FieldErrors) {
+        errors.check_text("username", self.username, MAX_USER_NAME_LENGTH);
+        errors.check_email("email", self.email);
+        errors.check_text("password", self.password, MAX_PASSWORD_LENGTH);
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct SettingUserUsername<'a> {
     pub username: &'a str,
 }
 
+impl Validate for SettingUserUsername<'_> {
+    fn validate_fields(&self, errors: &mut FieldErrors) {
+        errors.check_text("username", self.username, MAX_USER_NAME_LENGTH);
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SettingUserEmail<'a> {
+    pub email: &'a str,
+}
+
+impl Validate for SettingUserEmail<'_> {
+    fn validate_fields(&self, errors: &mut FieldErrors) {
+        errors.check_email("email", self.email);
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SettingUserDisplayName<'a> {
+    pub display_name: Option<&'a str>,
+}
+
+impl Validate for SettingUserDisplayName<'_> {
+    fn validate_fields(&self, errors: &mut FieldErrors) {
+        if let Some(display_name) = self.display_name {
+            errors.check_text("display_name", display_name, MAX_USER_NAME_LENGTH);
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct SettingUserPassword<'a> {
     pub password: &'a str,
 }
 
+impl Validate for SettingUserPassword<'_> {
+    fn validate_fields(&self, errors: &mut FieldErrors) {
+        errors.check_text("password", self.password, MAX_PASSWORD_LENGTH);
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct UserList {
     pub users: Vec<User>,
     pub last_user_id: Option<i32>,
     pub limit: u32,
+    /// The cursor to fetch the next page with, or `None` if this is the last page.
+    pub next_cursor: Option<i32>,
+    /// The number of all items, if `with_total` has been requested.
+    pub total: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ActivityList {
+    pub activities: Vec<AuditLog>,
+    pub last_activity_id: Option<i64>,
+    pub limit: u32,
+    /// The cursor to fetch the next page with, or `None` if this is the last page.
+    pub next_cursor: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct FavoriteList {
+    pub files: Vec<File>,
+    pub collections: Vec<Collection>,
+}
+
+/// The avatar image of a user.
+/// Avatars are replaced by new files rather than overwritten, so the image behind an `ETag` never changes.
+pub struct AvatarData(pub FileData);
+
+#[rocket::async_trait]
+impl<'r> Responder<'r, 'static> for AvatarData {
+    fn respond_to(self, request: &'r Request<'_>) -> Result<'static> {
+        let mut response = self.0.respond_to(request)?;
+        response.set_header(Header::new("Cache-Control", "private, max-age=86400"));
+        Ok(response)
+    }
 }
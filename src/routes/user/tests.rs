@@ -1,10 +1,22 @@
-use super::dto::{CreatingUser, SettingUserPassword, SettingUserUsername, UserList};
+use super::dto::{
+    ActivityList, CreatingUser, SettingUserDisplayName, SettingUserEmail, SettingUserPassword,
+    SettingUserUsername, UserList,
+};
 use crate::{
-    db::models::User,
-    services::{AuthService, UserService},
+    db::models::{Collection, File, User},
+    routes::{
+        collection::dto::CreatingCollection,
+        user_session::dto::{CreatingUserSession, UserSessionInfoList},
+    },
+    services::{
+        AuditAction, AuthService, FileService, StagingFileService, UserService,
+        MAX_USER_PREFERENCES_SIZE,
+    },
     test::{
         create_test_rocket_instance,
-        helpers::{create_initial_user, create_user},
+        helpers::{
+            create_filled_staging_file, create_initial_admin_user, create_initial_user, create_user,
+        },
     },
 };
 use rocket::{
@@ -62,6 +74,112 @@ async fn test_create_user() {
     assert_eq!(raw_created_user, created_user);
 }
 
+#[rocket::async_test]
+async fn test_create_user_invalid_fields() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    let (_initial_user, initial_user_session) =
+        create_initial_user(auth_service, user_service).await;
+
+    let response = client
+        .post("/users")
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .body(
+            serde_json::to_string(&CreatingUser {
+                username: " ",
+                email: "not an email",
+                password: "password",
+            })
+            .unwrap(),
+        )
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let error = response.into_json::<serde_json::Value>().await.unwrap();
+
+    assert_eq!(status, Status::UnprocessableEntity);
+    assert_eq!(
+        error["fields"],
+        serde_json::json!([
+            { "field": "username", "message": "should not be blank" },
+            { "field": "email", "message": "should be a valid email address" },
+        ])
+    );
+
+    let users = user_service.get_users(None, 100, false).await.unwrap();
+
+    assert_eq!(users.items.len(), 1);
+}
+
+#[rocket::async_test]
+async fn test_create_user_duplicate() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    let (initial_user, initial_user_session) =
+        create_initial_user(auth_service, user_service).await;
+
+    let users = [
+        (initial_user.username.as_str(), "another@example.com"),
+        ("another", initial_user.email.as_str()),
+    ];
+
+    for (username, email) in users {
+        let response = client
+            .post("/users")
+            .header(Accept::JSON)
+            .header(ContentType::JSON)
+            .header(Header::new(
+                "Authorization",
+                format!("Bearer {}", initial_user_session.token),
+            ))
+            .body(
+                serde_json::to_string(&CreatingUser {
+                    username,
+                    email,
+                    password: "password",
+                })
+                .unwrap(),
+            )
+            .dispatch()
+            .await;
+
+        assert_eq!(response.status(), Status::Conflict);
+    }
+
+    let user = create_user("user", user_service).await;
+
+    let response = client
+        .put(format!("/users/{}/username", user.id))
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .body(
+            serde_json::to_string(&SettingUserUsername {
+                username: &initial_user.username,
+            })
+            .unwrap(),
+        )
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::Conflict);
+}
+
 #[rocket::async_test]
 async fn test_remove_user() {
     let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
@@ -137,9 +255,10 @@ async fn test_get_users() {
     assert_eq!(retrieved_users.users, users);
 
     let raw_retrieved_users = user_service
-        .get_users(retrieved_users.last_user_id, retrieved_users.limit)
+        .get_users(retrieved_users.last_user_id, retrieved_users.limit, false)
         .await
-        .unwrap();
+        .unwrap()
+        .items;
 
     assert_eq!(raw_retrieved_users, retrieved_users.users);
 }
@@ -182,6 +301,116 @@ async fn test_get_user() {
     assert_eq!(raw_retrieved_user, retrieved_user);
 }
 
+#[rocket::async_test]
+async fn test_get_user_activity() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+    let staging_file_service = client.rocket().state::<Arc<StagingFileService>>().unwrap();
+
+    let (initial_user, initial_user_session) =
+        create_initial_user(auth_service, user_service).await;
+
+    let staging_file = create_filled_staging_file(
+        &client,
+        staging_file_service,
+        &initial_user_session,
+        "file",
+        Some("text/plain"),
+        "file content",
+    )
+    .await;
+
+    let response = client
+        .post(format!("/files/{}", staging_file.id))
+        .header(Accept::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::Created);
+
+    let file = response.into_json::<File>().await.unwrap();
+
+    let response = client
+        .post("/collections")
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .body(
+            serde_json::to_string(&CreatingCollection {
+                name: "collection",
+                description: None,
+                file_ids: Vec::new(),
+            })
+            .unwrap(),
+        )
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::Created);
+
+    let collection = response.into_json::<Collection>().await.unwrap();
+
+    let response = client
+        .get(format!("/users/{}/activity?limit=1", initial_user.id))
+        .header(Accept::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let activity_list = response.into_json::<ActivityList>().await.unwrap();
+
+    assert_eq!(status, Status::Ok);
+    assert_eq!(activity_list.activities.len(), 1);
+    assert_eq!(
+        activity_list.activities[0].action,
+        AuditAction::CollectionCreated.name()
+    );
+    assert_eq!(
+        activity_list.activities[0].collection_id,
+        Some(collection.id)
+    );
+    assert!(activity_list.next_cursor.is_some());
+
+    let response = client
+        .get(format!(
+            "/users/{}/activity?last_activity_id={}&limit=1",
+            initial_user.id,
+            activity_list.next_cursor.unwrap()
+        ))
+        .header(Accept::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let activity_list = response.into_json::<ActivityList>().await.unwrap();
+
+    assert_eq!(status, Status::Ok);
+    assert_eq!(activity_list.activities.len(), 1);
+    assert_eq!(
+        activity_list.activities[0].action,
+        AuditAction::FileUploaded.name()
+    );
+    assert_eq!(activity_list.activities[0].file_id, Some(file.id));
+    assert_eq!(activity_list.next_cursor, None);
+}
+
 #[rocket::async_test]
 async fn test_set_user_username() {
     let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
@@ -230,6 +459,311 @@ async fn test_set_user_username() {
     assert_eq!(raw_updated_user, updated_user);
 }
 
+#[rocket::async_test]
+async fn test_set_user_email() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    let (initial_user, initial_user_session) =
+        create_initial_user(auth_service, user_service).await;
+
+    let user = create_user("user", user_service).await;
+
+    let response = client
+        .put(format!("/users/{}/email", user.id))
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .body(
+            serde_json::to_string(&SettingUserEmail {
+                email: "new@example.com",
+            })
+            .unwrap(),
+        )
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::Accepted);
+
+    // the email is only changed once confirmed
+    let raw_user = user_service.get_user_by_id(user.id).await.unwrap().unwrap();
+
+    assert_eq!(raw_user.email, user.email);
+
+    let response = client
+        .put(format!("/users/{}/email", user.id))
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .body(
+            serde_json::to_string(&SettingUserEmail {
+                email: &initial_user.email,
+            })
+            .unwrap(),
+        )
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::Conflict);
+}
+
+#[rocket::async_test]
+async fn test_set_user_display_name() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    let (_initial_user, initial_user_session) =
+        create_initial_user(auth_service, user_service).await;
+
+    let user = create_user("user", user_service).await;
+
+    assert_eq!(user.display_name, None);
+
+    let response = client
+        .put(format!("/users/{}/display-name", user.id))
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .body(
+            serde_json::to_string(&SettingUserDisplayName {
+                display_name: Some("Display Name"),
+            })
+            .unwrap(),
+        )
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let updated_user = response.into_json::<User>().await.unwrap();
+
+    assert_eq!(status, Status::Ok);
+    assert_eq!(updated_user.id, user.id);
+    assert_eq!(updated_user.display_name.as_deref(), Some("Display Name"));
+
+    let raw_updated_user = user_service
+        .get_user_by_id(updated_user.id)
+        .await
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(raw_updated_user, updated_user);
+}
+
+#[rocket::async_test]
+async fn test_set_user_avatar() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+    let file_service = client.rocket().state::<Arc<FileService>>().unwrap();
+
+    let (_initial_user, initial_user_session) =
+        create_initial_user(auth_service, user_service).await;
+
+    let user = create_user("user", user_service).await;
+
+    let response = client
+        .put(format!("/users/{}/avatar", user.id))
+        .header(Accept::JSON)
+        .header(ContentType::Plain)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .body("not an image")
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::UnsupportedMediaType);
+
+    let response = client
+        .put(format!("/users/{}/avatar", user.id))
+        .header(Accept::JSON)
+        .header(ContentType::PNG)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .body("first avatar")
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let updated_user = response.into_json::<User>().await.unwrap();
+    let first_avatar_file_id = updated_user.avatar_file_id.unwrap();
+
+    assert_eq!(status, Status::Ok);
+
+    let response = client
+        .put(format!("/users/{}/avatar", user.id))
+        .header(Accept::JSON)
+        .header(ContentType::PNG)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .body("second avatar")
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let updated_user = response.into_json::<User>().await.unwrap();
+    let second_avatar_file_id = updated_user.avatar_file_id.unwrap();
+
+    assert_eq!(status, Status::Ok);
+    assert_ne!(first_avatar_file_id, second_avatar_file_id);
+
+    // the replaced avatar is removed
+    let first_avatar_file = file_service
+        .get_file_by_id(first_avatar_file_id)
+        .await
+        .unwrap();
+
+    assert_eq!(first_avatar_file, None);
+
+    let response = client
+        .get(format!("/users/{}/avatar", user.id))
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.content_type(), Some(ContentType::PNG));
+    assert!(response.headers().get_one("Cache-Control").is_some());
+    assert!(response.headers().get_one("ETag").is_some());
+    assert_eq!(response.into_string().await.unwrap(), "second avatar");
+
+    let response = client
+        .delete(format!("/users/{}/avatar", user.id))
+        .header(Accept::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let updated_user = response.into_json::<User>().await.unwrap();
+
+    assert_eq!(status, Status::Ok);
+    assert_eq!(updated_user.avatar_file_id, None);
+
+    let response = client
+        .get(format!("/users/{}/avatar", user.id))
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+#[rocket::async_test]
+async fn test_set_user_preferences() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    let (initial_user, initial_user_session) =
+        create_initial_user(auth_service, user_service).await;
+
+    let response = client
+        .get(format!("/users/{}/preferences", initial_user.id))
+        .header(Accept::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let preferences = response.into_json::<serde_json::Value>().await.unwrap();
+
+    assert_eq!(status, Status::Ok);
+    assert_eq!(preferences, serde_json::json!({}));
+
+    let new_preferences = serde_json::json!({
+        "sort": "uploaded_at",
+        "itemsPerPage": 50,
+        "theme": "dark",
+    });
+
+    let response = client
+        .put(format!("/users/{}/preferences", initial_user.id))
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .body(new_preferences.to_string())
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let preferences = response.into_json::<serde_json::Value>().await.unwrap();
+
+    assert_eq!(status, Status::Ok);
+    assert_eq!(preferences, new_preferences);
+
+    let raw_preferences = user_service
+        .get_user_preferences_by_id(initial_user.id)
+        .await
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(raw_preferences, new_preferences);
+
+    let response = client
+        .put(format!("/users/{}/preferences", initial_user.id))
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .body("[1, 2, 3]")
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+
+    let response = client
+        .put(format!("/users/{}/preferences", initial_user.id))
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .body(serde_json::json!({ "theme": "x".repeat(MAX_USER_PREFERENCES_SIZE) }).to_string())
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::PayloadTooLarge);
+}
+
 #[rocket::async_test]
 async fn test_set_user_password() {
     let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
@@ -282,3 +816,193 @@ async fn test_set_user_password() {
 
     assert_eq!(authenticated_user_id, user.id);
 }
+
+#[rocket::async_test]
+async fn test_remove_user_sessions() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    let (initial_user, initial_user_session) =
+        create_initial_admin_user(auth_service, user_service).await;
+    let user = create_user("user", user_service).await;
+
+    for _ in 0..2 {
+        auth_service
+            .create_user_session(user.id, None, None)
+            .await
+            .unwrap();
+    }
+
+    // only admins can log out other users
+    let user_session = auth_service
+        .create_user_session(user.id, None, None)
+        .await
+        .unwrap();
+
+    let response = client
+        .delete(format!("/users/{}/sessions", initial_user.id))
+        .header(Accept::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::Forbidden);
+
+    let response = client
+        .delete(format!("/users/{}/sessions", user.id))
+        .header(Accept::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let user_session_list = response.into_json::<UserSessionInfoList>().await.unwrap();
+
+    assert_eq!(status, Status::Ok);
+    assert_eq!(user_session_list.user_sessions.len(), 3);
+
+    let user_sessions = auth_service.get_user_sessions(user.id).await.unwrap();
+
+    assert!(user_sessions.is_empty());
+}
+
+#[rocket::async_test]
+async fn test_disable_user() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    let (initial_user, initial_user_session) =
+        create_initial_admin_user(auth_service, user_service).await;
+    let other_user = create_user("other", user_service).await;
+    let other_user_session = auth_service
+        .create_user_session(other_user.id, None, None)
+        .await
+        .unwrap();
+
+    let response = client
+        .post(format!("/users/{}/disable", initial_user.id))
+        .header(Accept::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let body = response.into_json::<serde_json::Value>().await.unwrap();
+
+    assert_eq!(status, Status::Conflict);
+    assert_eq!(body["error_code"], "CANNOT_DISABLE_SELF");
+
+    let response = client
+        .post(format!("/users/{}/disable", initial_user.id))
+        .header(Accept::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", other_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::Forbidden);
+
+    let response = client
+        .post(format!("/users/{}/disable", other_user.id))
+        .header(Accept::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let disabled_user = response.into_json::<User>().await.unwrap();
+
+    assert_eq!(status, Status::Ok);
+    assert_eq!(disabled_user.id, other_user.id);
+    assert!(disabled_user.disabled_at.is_some());
+
+    let response = client
+        .get(format!("/users/{}", other_user.id))
+        .header(Accept::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", other_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::Unauthorized);
+
+    let response = client
+        .post("/user-sessions")
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .body(
+            serde_json::to_string(&CreatingUserSession {
+                email: &other_user.email,
+                password: "other_user_pw",
+            })
+            .unwrap(),
+        )
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let body = response.into_json::<serde_json::Value>().await.unwrap();
+
+    assert_eq!(status, Status::Forbidden);
+    assert_eq!(body["error_code"], "USER_DISABLED");
+
+    let raw_user = user_service
+        .get_user_by_id(other_user.id)
+        .await
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(raw_user, disabled_user);
+
+    let response = client
+        .post(format!("/users/{}/enable", other_user.id))
+        .header(Accept::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let enabled_user = response.into_json::<User>().await.unwrap();
+
+    assert_eq!(status, Status::Ok);
+    assert_eq!(enabled_user.disabled_at, None);
+
+    let response = client
+        .post("/user-sessions")
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .body(
+            serde_json::to_string(&CreatingUserSession {
+                email: &other_user.email,
+                password: "other_user_pw",
+            })
+            .unwrap(),
+        )
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::Created);
+}
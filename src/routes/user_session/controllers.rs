@@ -1,39 +1,75 @@
-use super::dto::CreatingUserSession;
+use super::dto::{CreatingUserSession, UserSessionInfo, UserSessionInfoList};
 use crate::{
-    db::models::UserSession, dto::JsonRes, guards::AuthUserSession, services::AuthService,
+    db::models::UserSession,
+    dto::{ApiResponse, Error, ErrorCode, JsonRes},
+    guards::{AuthUserSession, ClientInfo, UserAgentHeader},
+    services::{AuthService, AuthServiceError, LoginThrottleError, LoginThrottleService},
 };
-use rocket::{delete, http::Status, post, routes, serde::json::Json, Build, Rocket, State};
+use rocket::{delete, get, http::Status, post, routes, serde::json::Json, Build, Rocket, State};
 use std::sync::Arc;
 
 pub fn register_routes(rocket: Rocket<Build>) -> Rocket<Build> {
     rocket.mount(
         "/user-sessions",
-        routes![create_user_session, remove_user_session],
+        routes![
+            create_user_session,
+            remove_user_session,
+            get_user_sessions,
+            remove_user_session_by_token_prefix
+        ],
     )
 }
 
 #[post("/", data = "<body>")]
 async fn create_user_session(
     auth_service: &State<Arc<AuthService>>,
+    login_throttle_service: &State<Arc<LoginThrottleService>>,
+    user_agent: UserAgentHeader<'_>,
+    client_info: ClientInfo,
     body: Json<CreatingUserSession<'_>>,
 ) -> JsonRes<UserSession> {
+    let client_ip = client_info.ip;
+
+    if let Err(err) = login_throttle_service.check(body.email, client_ip) {
+        let status = match err {
+            LoginThrottleError::Locked(_) => Status::Locked,
+            LoginThrottleError::TooManyAttempts(_) => Status::TooManyRequests,
+        };
+        return Err(
+            Error::new_dynamic(status, err.to_string()).with_code(ErrorCode::TooManyLoginAttempts)
+        );
+    }
+
     let user_id = auth_service
         .authenticate_user(body.email, body.password)
         .await;
 
     let user_id = match user_id {
-        Ok(Some(user_id)) => user_id,
+        Ok(Some(user_id)) => {
+            login_throttle_service.record_success(body.email, client_ip);
+            user_id
+        }
+        Err(err @ AuthServiceError::UserDisabled) => {
+            login_throttle_service.record_success(body.email, client_ip);
+            return Err(Error::new_dynamic(Status::Forbidden, err.to_string())
+                .with_code(ErrorCode::UserDisabled));
+        }
         Ok(None) => {
-            return Err(Status::Unauthorized.into());
+            login_throttle_service.record_failure(body.email, client_ip);
+            log::warn!(target: "routes::user_session::controllers", controller = "create_user_session", email = body.email, client_ip:?, scheme = client_info.scheme; "Failed login attempt.");
+            return Err(Error::from(Status::Unauthorized).with_code(ErrorCode::InvalidCredentials));
         }
         Err(err) => {
+            login_throttle_service.release(body.email, client_ip);
             let body = body.into_inner();
             log::error!(target: "routes::user_session::controllers", controller = "create_user_session", service = "AuthService", body:serde, err:err; "Error returned from service.");
             return Err(Status::InternalServerError.into());
         }
     };
 
-    let user_session = auth_service.create_user_session(user_id).await;
+    let user_session = auth_service
+        .create_user_session(user_id, user_agent.user_agent, client_ip)
+        .await;
 
     let user_session = match user_session {
         Ok(user_session) => user_session,
@@ -44,7 +80,7 @@ async fn create_user_session(
         }
     };
 
-    Ok((Status::Created, Json(user_session)))
+    Ok(ApiResponse::new(Status::Created, user_session))
 }
 
 #[delete("/")]
@@ -59,7 +95,7 @@ async fn remove_user_session(
     let user_session = match user_session {
         Ok(Some(user_session)) => user_session,
         Ok(None) => {
-            return Err(Status::NotFound.into());
+            return Err(Error::new_not_found(ErrorCode::SessionNotFound));
         }
         Err(err) => {
             log::error!(target: "routes::user_session::controllers", controller = "remove_user_session", service = "AuthService", sess:serde, err:err; "Error returned from service.");
@@ -67,5 +103,63 @@ async fn remove_user_session(
         }
     };
 
-    Ok((Status::Ok, Json(user_session)))
+    Ok(ApiResponse::new(Status::Ok, user_session))
+}
+
+#[get("/")]
+async fn get_user_sessions(
+    sess: AuthUserSession<'_>,
+    auth_service: &State<Arc<AuthService>>,
+) -> JsonRes<UserSessionInfoList> {
+    let user_sessions = auth_service.get_user_sessions(sess.user.id).await;
+
+    let user_sessions = match user_sessions {
+        Ok(user_sessions) => user_sessions,
+        Err(err) => {
+            log::error!(target: "routes::user_session::controllers", controller = "get_user_sessions", service = "AuthService", sess:serde, err:err; "Error returned from service.");
+            return Err(Status::InternalServerError.into());
+        }
+    };
+
+    let user_sessions = user_sessions
+        .into_iter()
+        .map(|user_session| UserSessionInfo::new(user_session, Some(sess.token)))
+        .collect();
+
+    Ok(ApiResponse::new(
+        Status::Ok,
+        UserSessionInfoList { user_sessions },
+    ))
+}
+
+#[delete("/<token_prefix>")]
+async fn remove_user_session_by_token_prefix(
+    sess: AuthUserSession<'_>,
+    auth_service: &State<Arc<AuthService>>,
+    token_prefix: &str,
+) -> JsonRes<UserSessionInfoList> {
+    let user_sessions = auth_service
+        .remove_user_sessions_by_token_prefix(sess.user.id, token_prefix)
+        .await;
+
+    let user_sessions = match user_sessions {
+        Ok(user_sessions) if user_sessions.is_empty() => {
+            return Err(Error::new_not_found(ErrorCode::SessionNotFound));
+        }
+        Ok(user_sessions) => user_sessions,
+        Err(err) => {
+            log::error!(target: "routes::user_session::controllers", controller = "remove_user_session_by_token_prefix", service = "AuthService", sess:serde, token_prefix, err:err; "Error returned from service.");
+            return Err(Status::InternalServerError.into());
+        }
+    };
+
+    let user_sessions = user_sessions
+        .into_iter()
+        .map(|user_session| UserSessionInfo::new(user_session, Some(sess.token)))
+        .collect();
+
+    Ok(ApiResponse::new(
+        Status::Ok,
+        UserSessionInfoList { user_sessions },
+    ))
 }
@@ -1,3 +1,5 @@
+use crate::{db::models::UserSessionWithClientInfo, services::user_session_token_prefix};
+use chrono::NaiveDateTime;
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize)]
@@ -5,3 +7,32 @@ pub struct CreatingUserSession<'a> {
     pub email: &'a str,
     pub password: &'a str,
 }
+
+/// A user session without its token, which would grant access to it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct UserSessionInfo {
+    /// The leading part of the token, to revoke the session with.
+    pub token_prefix: String,
+    pub created_at: NaiveDateTime,
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+    /// Whether it is the session the request has been made with.
+    pub current: bool,
+}
+
+impl UserSessionInfo {
+    pub fn new(user_session: UserSessionWithClientInfo, current_token: Option<&str>) -> Self {
+        Self {
+            token_prefix: user_session_token_prefix(&user_session.token),
+            created_at: user_session.created_at,
+            user_agent: user_session.user_agent,
+            ip_address: user_session.ip_address,
+            current: current_token == Some(user_session.token.as_str()),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct UserSessionInfoList {
+    pub user_sessions: Vec<UserSessionInfo>,
+}
@@ -1,15 +1,22 @@
-use super::dto::CreatingUserSession;
+use super::dto::{CreatingUserSession, UserSessionInfoList};
 use crate::{
+    config::{InitialUser, SearchBackend, SeedUser},
     db::models::{User, UserSession},
     routes::user::dto::CreatingUser,
-    services::{AuthService, UserService},
-    test::{create_test_rocket_instance, helpers::create_initial_user},
+    services::{
+        user_session_token_prefix, AuthService, LoginThrottleError, LoginThrottleService, UserRole,
+        UserService,
+    },
+    test::{
+        create_test_rocket_instance, create_test_rocket_instance_with_config,
+        helpers::create_initial_user, TestFileDriver,
+    },
 };
 use rocket::{
     http::{Accept, ContentType, Header, Status},
     local::asynchronous::Client,
 };
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
 #[rocket::async_test]
 async fn test_create_user_session() {
@@ -112,3 +119,418 @@ async fn test_remove_user_session() {
 
     assert_eq!(raw_user, None);
 }
+
+#[rocket::async_test]
+async fn test_create_user_session_throttled() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    let (initial_user, _initial_user_session) =
+        create_initial_user(auth_service, user_service).await;
+    let email = initial_user.email.as_str();
+
+    let response = client
+        .post("/user-sessions")
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .body(
+            serde_json::to_string(&CreatingUserSession {
+                email,
+                password: "wrong_password",
+            })
+            .unwrap(),
+        )
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::Unauthorized);
+
+    // even the right password is rejected until the delay has passed
+    let response = client
+        .post("/user-sessions")
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .body(
+            serde_json::to_string(&CreatingUserSession {
+                email,
+                password: "initial_user_pw",
+            })
+            .unwrap(),
+        )
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::TooManyRequests);
+
+    tokio::time::sleep(Duration::from_secs(1)).await;
+
+    let response = client
+        .post("/user-sessions")
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .body(
+            serde_json::to_string(&CreatingUserSession {
+                email,
+                password: "initial_user_pw",
+            })
+            .unwrap(),
+        )
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::Created);
+}
+
+#[rocket::async_test]
+async fn test_login_throttle_reserves_attempts() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let login_throttle_service = client
+        .rocket()
+        .state::<Arc<LoginThrottleService>>()
+        .unwrap();
+
+    let email = "user@example.com";
+
+    login_throttle_service.check(email, None).unwrap();
+
+    // an attempt made while another one is in flight is throttled as if the other one had failed
+    assert!(matches!(
+        login_throttle_service.check(email, None),
+        Err(LoginThrottleError::TooManyAttempts(_))
+    ));
+
+    login_throttle_service.record_success(email, None);
+    login_throttle_service.check(email, None).unwrap();
+    login_throttle_service.release(email, None);
+    login_throttle_service.check(email, None).unwrap();
+}
+
+#[rocket::async_test]
+async fn test_get_user_sessions() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    let (initial_user, initial_user_session) =
+        create_initial_user(auth_service, user_service).await;
+
+    let response = client
+        .post("/user-sessions")
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new("User-Agent", "test-agent"))
+        .body(
+            serde_json::to_string(&CreatingUserSession {
+                email: &initial_user.email,
+                password: "initial_user_pw",
+            })
+            .unwrap(),
+        )
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let user_session = response.into_json::<UserSession>().await.unwrap();
+
+    assert_eq!(status, Status::Created);
+
+    let response = client
+        .get("/user-sessions")
+        .header(Accept::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let user_session_list = response.into_json::<UserSessionInfoList>().await.unwrap();
+
+    assert_eq!(status, Status::Ok);
+    assert_eq!(user_session_list.user_sessions.len(), 2);
+
+    let current = user_session_list
+        .user_sessions
+        .iter()
+        .find(|info| info.current)
+        .unwrap();
+    let other = user_session_list
+        .user_sessions
+        .iter()
+        .find(|info| !info.current)
+        .unwrap();
+
+    assert_eq!(
+        current.token_prefix,
+        user_session_token_prefix(&initial_user_session.token)
+    );
+    assert_eq!(
+        other.token_prefix,
+        user_session_token_prefix(&user_session.token)
+    );
+    assert_eq!(other.user_agent.as_deref(), Some("test-agent"));
+}
+
+#[rocket::async_test]
+async fn test_create_user_session_behind_proxy() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance_with_config(
+        TestFileDriver::Memory,
+        SearchBackend::Meilisearch,
+        |app_config| {
+            app_config.proxy.trusted_proxies = vec![
+                "10.0.0.0/8".to_owned().try_into().unwrap(),
+                "127.0.0.1".to_owned().try_into().unwrap(),
+            ]
+        },
+    )
+    .await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    let (initial_user, initial_user_session) =
+        create_initial_user(auth_service, user_service).await;
+
+    // the leftmost address is made up by the client, and the rightmost one is another trusted proxy
+    let response = client
+        .post("/user-sessions")
+        .remote("127.0.0.1:8000".parse().unwrap())
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "X-Forwarded-For",
+            "192.0.2.1, 203.0.113.7, 10.1.2.3",
+        ))
+        .body(
+            serde_json::to_string(&CreatingUserSession {
+                email: &initial_user.email,
+                password: "initial_user_pw",
+            })
+            .unwrap(),
+        )
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::Created);
+
+    // the header of an untrusted peer is ignored
+    let response = client
+        .post("/user-sessions")
+        .remote("198.51.100.5:8000".parse().unwrap())
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .header(Header::new("X-Forwarded-For", "192.0.2.1"))
+        .body(
+            serde_json::to_string(&CreatingUserSession {
+                email: &initial_user.email,
+                password: "initial_user_pw",
+            })
+            .unwrap(),
+        )
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::Created);
+
+    let response = client
+        .get("/user-sessions")
+        .header(Accept::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let user_session_list = response.into_json::<UserSessionInfoList>().await.unwrap();
+    let mut ip_addresses = user_session_list
+        .user_sessions
+        .iter()
+        .filter(|info| !info.current)
+        .map(|info| info.ip_address.clone())
+        .collect::<Vec<_>>();
+    ip_addresses.sort();
+
+    assert_eq!(status, Status::Ok);
+    assert_eq!(
+        ip_addresses,
+        vec![
+            Some("198.51.100.5".to_owned()),
+            Some("203.0.113.7".to_owned())
+        ]
+    );
+}
+
+#[rocket::async_test]
+async fn test_remove_user_session_by_token_prefix() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance().await;
+    let client = Client::tracked(rocket).await.unwrap();
+    let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+    let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+
+    let (initial_user, initial_user_session) =
+        create_initial_user(auth_service, user_service).await;
+    let user_session = auth_service
+        .create_user_session(initial_user.id, None, None)
+        .await
+        .unwrap();
+    let token_prefix = user_session_token_prefix(&user_session.token);
+
+    let response = client
+        .delete(format!("/user-sessions/{}", &token_prefix[..4]))
+        .header(Accept::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::NotFound);
+
+    let response = client
+        .delete(format!("/user-sessions/{}", token_prefix))
+        .header(Accept::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", initial_user_session.token),
+        ))
+        .dispatch()
+        .await;
+
+    let status = response.status();
+    let user_session_list = response.into_json::<UserSessionInfoList>().await.unwrap();
+
+    assert_eq!(status, Status::Ok);
+    assert_eq!(user_session_list.user_sessions.len(), 1);
+    assert_eq!(
+        user_session_list.user_sessions[0].token_prefix,
+        token_prefix
+    );
+
+    let raw_user = auth_service
+        .get_user_from_session(&user_session.token)
+        .await
+        .unwrap();
+
+    assert_eq!(raw_user, None);
+}
+
+#[rocket::async_test]
+async fn test_create_user_session_for_seed_users() {
+    let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance_with_config(
+        TestFileDriver::Memory,
+        SearchBackend::Meilisearch,
+        |app_config| {
+            app_config.initial_user = Some(InitialUser {
+                username: "initial".to_owned(),
+                email: "initial@example.com".to_owned(),
+                password: "initial password".to_owned(),
+            });
+            app_config.seed_users = vec![
+                SeedUser {
+                    username: "operator".to_owned(),
+                    email: "operator@example.com".to_owned(),
+                    password: "operator password".to_owned(),
+                    role: UserRole::Admin,
+                },
+                SeedUser {
+                    username: "viewer".to_owned(),
+                    email: "viewer@example.com".to_owned(),
+                    password: "viewer password".to_owned(),
+                    role: UserRole::User,
+                },
+            ];
+        },
+    )
+    .await;
+
+    // the seed user is already there, e.g. from an earlier start with a different config
+    let user_service = rocket.state::<Arc<UserService>>().unwrap().clone();
+    let existing_user = user_service
+        .create_user("former viewer", "viewer@example.com", "former password")
+        .await
+        .unwrap();
+    user_service
+        .set_user_role_by_id(existing_user.id, UserRole::Admin)
+        .await
+        .unwrap();
+
+    // the users are seeded on liftoff
+    let client = Client::tracked(rocket).await.unwrap();
+
+    for (username, email, password, role) in [
+        (
+            "initial",
+            "initial@example.com",
+            "initial password",
+            UserRole::Admin,
+        ),
+        (
+            "operator",
+            "operator@example.com",
+            "operator password",
+            UserRole::Admin,
+        ),
+        (
+            "viewer",
+            "viewer@example.com",
+            "viewer password",
+            UserRole::User,
+        ),
+    ] {
+        let response = client
+            .post("/user-sessions")
+            .header(Accept::JSON)
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&CreatingUserSession { email, password }).unwrap())
+            .dispatch()
+            .await;
+
+        let status = response.status();
+        let user_session = response.into_json::<UserSession>().await.unwrap();
+
+        assert_eq!(status, Status::Created);
+
+        let user = user_service
+            .get_user_by_email(email)
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(user_session.user_id, user.id);
+        assert_eq!(user.username, username);
+        assert_eq!(user.role, role.name());
+    }
+
+    // the existing user is updated in place
+    let user = user_service
+        .get_user_by_email("viewer@example.com")
+        .await
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(user.id, existing_user.id);
+
+    let response = client
+        .post("/user-sessions")
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .body(
+            serde_json::to_string(&CreatingUserSession {
+                email: "viewer@example.com",
+                password: "former password",
+            })
+            .unwrap(),
+        )
+        .dispatch()
+        .await;
+
+    assert_ne!(response.status(), Status::Created);
+}
@@ -1,76 +1,291 @@
+mod archive_service;
+mod audit_log_service;
 mod auth_service;
+mod backup_service;
 mod collection_file_pair_service;
+mod collection_naming_service;
 mod collection_service;
+mod consistency_service;
+mod content_extraction_service;
+mod database_pool_service;
+mod email_change_service;
+mod favorite_service;
+mod file_attribute_service;
+mod file_comment_service;
 mod file_driver;
 mod file_service;
+mod file_view_service;
+mod free_space_service;
+mod gc_service;
+mod id_service;
+mod image_transform_service;
+mod live_config_service;
+mod login_throttle_service;
+mod mailer_service;
+mod metadata_service;
 mod metric_service;
+mod page;
+mod password_reset_service;
 mod password_service;
+mod perceptual_hash_service;
+mod read_ahead_service;
+mod reindex_service;
+mod retention_service;
+mod scanner_service;
 mod search_service;
+mod share_service;
 mod staging_file_service;
+mod stats_service;
+mod storage_migration_service;
 mod tag_service;
+mod transcode_service;
+mod transfer_limit_service;
+mod tus_service;
+mod unit_of_work_service;
+mod upload_ticket_service;
 mod user_service;
 
+pub use archive_service::*;
+pub use audit_log_service::*;
 pub use auth_service::*;
+pub use backup_service::*;
 pub use collection_file_pair_service::*;
+pub use collection_naming_service::*;
 pub use collection_service::*;
+pub use consistency_service::*;
+pub use content_extraction_service::*;
+pub use database_pool_service::*;
+pub use email_change_service::*;
+pub use favorite_service::*;
+pub use file_attribute_service::*;
+pub use file_comment_service::*;
 pub use file_driver::*;
 pub use file_service::*;
+pub use file_view_service::*;
+pub use free_space_service::*;
+pub use gc_service::*;
+pub use id_service::*;
+pub use image_transform_service::*;
+pub use live_config_service::*;
+pub use login_throttle_service::*;
+pub use mailer_service::*;
+pub use metadata_service::*;
 pub use metric_service::*;
+pub use page::*;
+pub use password_reset_service::*;
 pub use password_service::*;
+pub use perceptual_hash_service::*;
+pub use read_ahead_service::*;
+pub use reindex_service::*;
+pub use retention_service::*;
+pub use scanner_service::*;
 pub use search_service::*;
+pub use share_service::*;
 pub use staging_file_service::*;
+pub use stats_service::*;
+pub use storage_migration_service::*;
 pub use tag_service::*;
+pub use transcode_service::*;
+pub use transfer_limit_service::*;
+pub use tus_service::*;
+pub use unit_of_work_service::*;
+pub use upload_ticket_service::*;
 pub use user_service::*;
 
-use crate::config::AppConfig;
+use crate::{
+    config::AppConfig,
+    db::{DatabasePoolMetrics, ReadPool},
+};
 use diesel_async::{pooled_connection::deadpool::Pool, AsyncPgConnection};
 use rocket::{Build, Rocket};
-use std::{path::PathBuf, sync::Arc};
+use std::sync::Arc;
 
 pub async fn register_search_service(
     rocket: Rocket<Build>,
     app_config: &AppConfig,
+    db_pool: Pool<AsyncPgConnection>,
+    read_pool: ReadPool,
 ) -> Result<Rocket<Build>, SearchServiceError> {
-    let search_service = SearchService::new(
-        &app_config.meilisearch_url,
-        app_config.meilisearch_master_key.as_deref(),
-        app_config.meilisearch_index_prefix.as_deref(),
-    )
-    .await?;
+    let search_service = create_search_service(app_config, db_pool, read_pool).await?;
 
     Ok(rocket.manage(search_service))
 }
 
+pub fn register_mailer_service(
+    rocket: Rocket<Build>,
+    app_config: &AppConfig,
+) -> Result<Rocket<Build>, MailerServiceError> {
+    let mailer_service = MailerService::new(&app_config.mailer)?;
+
+    Ok(rocket.manage(mailer_service))
+}
+
 pub fn register_services(
     rocket: Rocket<Build>,
+    app_config: &AppConfig,
     db_pool: Pool<AsyncPgConnection>,
-    file_base_path: impl Into<PathBuf>,
-    file_driver: Arc<impl 'static + FileDriver + Send + Sync>,
+    read_pool: ReadPool,
+    db_pool_metrics: Arc<DatabasePoolMetrics>,
+    file_driver: Arc<dyn FileDriver + Send + Sync>,
 ) -> Rocket<Build> {
-    let search_service = rocket.state::<Arc<SearchService>>().unwrap();
+    let search_service = rocket
+        .state::<Arc<dyn SearchService + Send + Sync>>()
+        .unwrap();
+    let mailer_service = rocket.state::<Arc<MailerService>>().unwrap();
 
+    let id_service = IdService::new(app_config.id_version);
     let password_service = PasswordService::new();
-    let auth_service = AuthService::new(db_pool.clone(), password_service.clone());
-    let collection_service = CollectionService::new(db_pool.clone(), search_service.clone());
-    let staging_file_service = StagingFileService::new(db_pool.clone(), file_driver.clone());
+    let user_service = UserService::new(db_pool.clone(), password_service.clone());
+    let login_throttle_service = LoginThrottleService::new(&app_config.login_throttle);
+    let auth_service = AuthService::new(
+        db_pool.clone(),
+        password_service.clone(),
+        user_service.clone(),
+        &app_config.oidc,
+    );
+    let collection_service = CollectionService::new(
+        db_pool.clone(),
+        read_pool.clone(),
+        id_service.clone(),
+        search_service.clone(),
+        app_config.unique_collection_names,
+        &app_config.collection_limits,
+    );
+    let staging_file_service =
+        StagingFileService::new(db_pool.clone(), id_service.clone(), file_driver.clone());
+    let metadata_service = MetadataService::new(app_config.ffprobe_path.clone());
+    let content_extraction_service =
+        ContentExtractionService::new(app_config.pdftotext_path.clone());
+    let scanner_service = ScannerService::new(&app_config.scanner);
+    let perceptual_hash_service = PerceptualHashService::new(&app_config.perceptual_hash);
     let file_service = FileService::new(
         db_pool.clone(),
+        read_pool,
         staging_file_service.clone(),
         search_service.clone(),
-        file_driver,
+        metadata_service.clone(),
+        content_extraction_service.clone(),
+        scanner_service.clone(),
+        perceptual_hash_service.clone(),
+        file_driver.clone(),
+        &app_config.file_cache,
+        app_config.file_versions.max_versions,
+        &app_config.mime_policy,
+    );
+    let read_ahead_service = ReadAheadService::new(&app_config.read_ahead, file_service.clone());
+    let archive_service = ArchiveService::new(file_service.clone());
+    let image_transform_service = ImageTransformService::new(
+        file_service.clone(),
+        &app_config.image_transform,
+        &app_config.temp_base_path,
+    );
+    let transcode_service = TranscodeService::new(
+        db_pool.clone(),
+        id_service.clone(),
+        file_service.clone(),
+        &app_config.transcode,
+        &app_config.temp_base_path,
+    );
+    let live_config_service = LiveConfigService::new(app_config);
+    let transfer_limit_service = TransferLimitService::new(&app_config.transfers);
+    let tus_service = TusService::new(staging_file_service.clone(), &app_config.temp_base_path);
+    let collection_file_pair_service = CollectionFilePairService::new(
+        db_pool.clone(),
+        search_service.clone(),
+        app_config.collection_limits.max_files,
+    );
+    let tag_service = TagService::new(db_pool.clone(), search_service.clone());
+    let collection_naming_service = CollectionNamingService::new(
+        db_pool.clone(),
+        app_config.import.collection_name_template.clone(),
+        app_config.import.collection_name_collision,
+    );
+    let reindex_service = ReindexService::new(db_pool.clone(), search_service.clone());
+    let free_space_service = FreeSpaceService::new(
+        file_driver.clone(),
+        app_config.transfers.free_space_reserve.as_u64(),
+    );
+    let gc_service = GcService::new(db_pool.clone(), file_driver.clone());
+    let consistency_service = ConsistencyService::new(
+        db_pool.clone(),
+        search_service.clone(),
+        file_service.clone(),
+        gc_service.clone(),
+        file_driver.clone(),
+    );
+    let stats_service = StatsService::new(db_pool.clone());
+    let upload_ticket_service = UploadTicketService::new(
+        db_pool.clone(),
+        password_service.clone(),
+        &app_config.upload_ticket,
+    );
+    let share_service = ShareService::new(
+        db_pool.clone(),
+        id_service.clone(),
+        password_service.clone(),
+    );
+    let password_reset_service = PasswordResetService::new(
+        db_pool.clone(),
+        password_service.clone(),
+        mailer_service.clone(),
+        &app_config.password_reset,
+    );
+    let email_change_service = EmailChangeService::new(
+        db_pool.clone(),
+        password_service.clone(),
+        mailer_service.clone(),
+        &app_config.email_change,
     );
-    let collection_file_pair_service =
-        CollectionFilePairService::new(db_pool.clone(), search_service.clone());
-    let user_service = UserService::new(db_pool, password_service.clone());
-    let metric_service = MetricService::new(file_base_path);
+    let audit_log_service = AuditLogService::new(db_pool.clone());
+    let favorite_service = FavoriteService::new(db_pool.clone());
+    let file_view_service = FileViewService::new(db_pool.clone());
+    let file_comment_service = FileCommentService::new(db_pool.clone(), id_service.clone());
+    let file_attribute_service = FileAttributeService::new(db_pool.clone(), search_service.clone());
+    let retention_service = RetentionService::new(db_pool.clone(), search_service.clone());
+    let unit_of_work_service = UnitOfWorkService::new(db_pool.clone());
+    let database_pool_service = DatabasePoolService::new(db_pool, db_pool_metrics);
+    let metric_service = MetricService::new();
 
     rocket
+        .manage(id_service)
         .manage(password_service)
+        .manage(login_throttle_service)
         .manage(auth_service)
         .manage(collection_service)
         .manage(staging_file_service)
+        .manage(metadata_service)
+        .manage(content_extraction_service)
+        .manage(scanner_service)
+        .manage(perceptual_hash_service)
         .manage(file_service)
+        .manage(read_ahead_service)
+        .manage(archive_service)
+        .manage(image_transform_service)
+        .manage(transcode_service)
+        .manage(live_config_service)
+        .manage(transfer_limit_service)
+        .manage(tus_service)
         .manage(collection_file_pair_service)
+        .manage(tag_service)
+        .manage(collection_naming_service)
+        .manage(consistency_service)
+        .manage(reindex_service)
+        .manage(free_space_service)
+        .manage(gc_service)
+        .manage(stats_service)
+        .manage(upload_ticket_service)
+        .manage(share_service)
+        .manage(password_reset_service)
+        .manage(email_change_service)
+        .manage(audit_log_service)
+        .manage(favorite_service)
+        .manage(file_view_service)
+        .manage(file_comment_service)
+        .manage(file_attribute_service)
+        .manage(retention_service)
+        .manage(unit_of_work_service)
+        .manage(database_pool_service)
         .manage(user_service)
         .manage(metric_service)
+        .manage(file_driver)
 }
@@ -0,0 +1,304 @@
+use super::{FileService, ReadError, ReadRange};
+use crate::db::models::File;
+use chrono::{Datelike, NaiveDateTime, Timelike};
+use std::{collections::HashSet, pin::Pin, sync::Arc};
+use thiserror::Error;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use uuid::Uuid;
+
+/// The size of the buffer between the task writing an archive and the response reading it.
+const BUFFER_SIZE: usize = 64 * 1024;
+
+/// Sizes and offsets from this value on do not fit in the fields of a ZIP archive, and are stored as ZIP64 instead.
+const ZIP64_THRESHOLD: u64 = u32::MAX as u64;
+
+/// The entries from this number on do not fit in the end of central directory record.
+const ZIP64_ENTRY_THRESHOLD: usize = u16::MAX as usize;
+
+const VERSION_DEFAULT: u16 = 20;
+const VERSION_ZIP64: u16 = 45;
+
+/// The names of the entries are encoded in UTF-8.
+const FLAG_UTF8: u16 = 1 << 11;
+
+#[derive(Error, Debug)]
+pub enum ArchiveServiceError {
+    #[error("io error: {0}")]
+    IO(#[from] std::io::Error),
+    #[error("read error: {0}")]
+    Read(#[from] ReadError),
+    #[error("the data of file {file_id} is missing")]
+    Missing { file_id: Uuid },
+    #[error("the data of file {file_id} is shorter than its size")]
+    Truncated { file_id: Uuid },
+}
+
+/// Streams ZIP archives of files.
+/// The files are stored without compression, and their CRC-32 hashes are taken from the database,
+/// so the archive is written as the files are read, without buffering them or reading them twice.
+pub struct ArchiveService {
+    file_service: Arc<FileService>,
+}
+
+impl ArchiveService {
+    pub fn new(file_service: Arc<FileService>) -> Arc<Self> {
+        Arc::new(Self { file_service })
+    }
+
+    /// Streams a ZIP archive of the files, in the given order.
+    /// Files with the same name are renamed with a number, e.g. `name (1).txt`.
+    /// The archive is written in the background; if it fails, e.g. because a file has been removed in the meantime,
+    /// the failure is logged and the stream ends early, leaving the archive truncated.
+    pub fn stream_zip(self: &Arc<Self>, files: Vec<File>) -> Pin<Box<dyn AsyncRead + Send>> {
+        let (reader, writer) = tokio::io::duplex(BUFFER_SIZE);
+        let archive_service = self.clone();
+
+        tokio::spawn(async move {
+            if let Err(err) = archive_service.write_zip(files, writer).await {
+                log::warn!(target: "archive_service", err:err; "Failed to write an archive.");
+            }
+        });
+
+        Box::pin(reader)
+    }
+
+    async fn write_zip(
+        &self,
+        files: Vec<File>,
+        mut writer: impl AsyncWrite + Unpin,
+    ) -> Result<(), ArchiveServiceError> {
+        let mut names = HashSet::with_capacity(files.len());
+        let mut entries = Vec::with_capacity(files.len());
+        let mut offset = 0u64;
+
+        for file in files {
+            let data = match self
+                .file_service
+                .get_file_data_by_id(file.id, ReadRange::Full)
+                .await?
+            {
+                Some(data) => data,
+                None => {
+                    return Err(ArchiveServiceError::Missing { file_id: file.id });
+                }
+            };
+
+            let entry = ZipEntry {
+                name: unique_name(&mut names, &file.name),
+                crc32: file.hash as u32,
+                size: file.size as u64,
+                offset,
+                modified_at: file.uploaded_at,
+            };
+            let header = entry.local_header();
+            writer.write_all(&header).await?;
+
+            let copied = tokio::io::copy(&mut data.take(entry.size), &mut writer).await?;
+
+            if copied != entry.size {
+                return Err(ArchiveServiceError::Truncated { file_id: file.id });
+            }
+
+            offset += header.len() as u64 + entry.size;
+            entries.push(entry);
+        }
+
+        let mut central_directory = Vec::new();
+
+        for entry in &entries {
+            entry.write_central_header(&mut central_directory);
+        }
+
+        let end_offset = offset + central_directory.len() as u64;
+        write_end_of_central_directory(&mut central_directory, entries.len(), offset, end_offset);
+
+        writer.write_all(&central_directory).await?;
+        writer.shutdown().await?;
+
+        Ok(())
+    }
+}
+
+/// Makes the name of a file unique in an archive, and keeps it from being taken as a path.
+fn unique_name(names: &mut HashSet<String>, name: &str) -> String {
+    let name = name.replace(['/', '\\'], "_");
+
+    if names.insert(name.clone()) {
+        return name;
+    }
+
+    let (stem, extension) = match name.rfind('.') {
+        Some(index) if 0 < index => name.split_at(index),
+        _ => (name.as_str(), ""),
+    };
+
+    (1..)
+        .map(|index| format!("{} ({}){}", stem, index, extension))
+        .find(|name| names.insert(name.clone()))
+        .unwrap()
+}
+
+struct ZipEntry {
+    name: String,
+    crc32: u32,
+    size: u64,
+    offset: u64,
+    modified_at: NaiveDateTime,
+}
+
+impl ZipEntry {
+    fn is_zip64(&self) -> bool {
+        ZIP64_THRESHOLD <= self.size || ZIP64_THRESHOLD <= self.offset
+    }
+
+    fn version(&self) -> u16 {
+        if self.is_zip64() {
+            VERSION_ZIP64
+        } else {
+            VERSION_DEFAULT
+        }
+    }
+
+    fn local_header(&self) -> Vec<u8> {
+        let mut extra = Vec::new();
+
+        if ZIP64_THRESHOLD <= self.size {
+            extra.extend_from_slice(&0x0001u16.to_le_bytes());
+            extra.extend_from_slice(&16u16.to_le_bytes());
+            extra.extend_from_slice(&self.size.to_le_bytes());
+            extra.extend_from_slice(&self.size.to_le_bytes());
+        }
+
+        let size = u32::try_from(self.size).unwrap_or(u32::MAX);
+        let (time, date) = dos_date_time(&self.modified_at);
+
+        let mut header = Vec::with_capacity(30 + self.name.len() + extra.len());
+        header.extend_from_slice(&0x04034b50u32.to_le_bytes());
+        header.extend_from_slice(&self.version().to_le_bytes());
+        header.extend_from_slice(&FLAG_UTF8.to_le_bytes());
+        // stored without compression
+        header.extend_from_slice(&0u16.to_le_bytes());
+        header.extend_from_slice(&time.to_le_bytes());
+        header.extend_from_slice(&date.to_le_bytes());
+        header.extend_from_slice(&self.crc32.to_le_bytes());
+        header.extend_from_slice(&size.to_le_bytes());
+        header.extend_from_slice(&size.to_le_bytes());
+        header.extend_from_slice(&(self.name.len() as u16).to_le_bytes());
+        header.extend_from_slice(&(extra.len() as u16).to_le_bytes());
+        header.extend_from_slice(self.name.as_bytes());
+        header.extend_from_slice(&extra);
+        header
+    }
+
+    fn write_central_header(&self, buf: &mut Vec<u8>) {
+        // only the fields that overflow are in the extra field, in this order
+        let mut zip64 = Vec::new();
+
+        if ZIP64_THRESHOLD <= self.size {
+            zip64.extend_from_slice(&self.size.to_le_bytes());
+            zip64.extend_from_slice(&self.size.to_le_bytes());
+        }
+
+        if ZIP64_THRESHOLD <= self.offset {
+            zip64.extend_from_slice(&self.offset.to_le_bytes());
+        }
+
+        let mut extra = Vec::new();
+
+        if !zip64.is_empty() {
+            extra.extend_from_slice(&0x0001u16.to_le_bytes());
+            extra.extend_from_slice(&(zip64.len() as u16).to_le_bytes());
+            extra.extend_from_slice(&zip64);
+        }
+
+        let size = u32::try_from(self.size).unwrap_or(u32::MAX);
+        let offset = u32::try_from(self.offset).unwrap_or(u32::MAX);
+        let (time, date) = dos_date_time(&self.modified_at);
+
+        buf.extend_from_slice(&0x02014b50u32.to_le_bytes());
+        buf.extend_from_slice(&VERSION_ZIP64.to_le_bytes());
+        buf.extend_from_slice(&self.version().to_le_bytes());
+        buf.extend_from_slice(&FLAG_UTF8.to_le_bytes());
+        buf.extend_from_slice(&0u16.to_le_bytes());
+        buf.extend_from_slice(&time.to_le_bytes());
+        buf.extend_from_slice(&date.to_le_bytes());
+        buf.extend_from_slice(&self.crc32.to_le_bytes());
+        buf.extend_from_slice(&size.to_le_bytes());
+        buf.extend_from_slice(&size.to_le_bytes());
+        buf.extend_from_slice(&(self.name.len() as u16).to_le_bytes());
+        buf.extend_from_slice(&(extra.len() as u16).to_le_bytes());
+        // no comment, on the first disk, with no attributes
+        buf.extend_from_slice(&0u16.to_le_bytes());
+        buf.extend_from_slice(&0u16.to_le_bytes());
+        buf.extend_from_slice(&0u16.to_le_bytes());
+        buf.extend_from_slice(&0u32.to_le_bytes());
+        buf.extend_from_slice(&offset.to_le_bytes());
+        buf.extend_from_slice(self.name.as_bytes());
+        buf.extend_from_slice(&extra);
+    }
+}
+
+/// Writes the end of central directory record after the central directory,
+/// preceded by the ZIP64 ones if the archive does not fit in it.
+fn write_end_of_central_directory(
+    buf: &mut Vec<u8>,
+    entry_count: usize,
+    central_directory_offset: u64,
+    end_offset: u64,
+) {
+    let central_directory_size = end_offset - central_directory_offset;
+
+    if ZIP64_ENTRY_THRESHOLD <= entry_count
+        || ZIP64_THRESHOLD <= central_directory_offset
+        || ZIP64_THRESHOLD <= central_directory_size
+    {
+        buf.extend_from_slice(&0x06064b50u32.to_le_bytes());
+        // the size of the rest of the record
+        buf.extend_from_slice(&44u64.to_le_bytes());
+        buf.extend_from_slice(&VERSION_ZIP64.to_le_bytes());
+        buf.extend_from_slice(&VERSION_ZIP64.to_le_bytes());
+        buf.extend_from_slice(&0u32.to_le_bytes());
+        buf.extend_from_slice(&0u32.to_le_bytes());
+        buf.extend_from_slice(&(entry_count as u64).to_le_bytes());
+        buf.extend_from_slice(&(entry_count as u64).to_le_bytes());
+        buf.extend_from_slice(&central_directory_size.to_le_bytes());
+        buf.extend_from_slice(&central_directory_offset.to_le_bytes());
+
+        buf.extend_from_slice(&0x07064b50u32.to_le_bytes());
+        buf.extend_from_slice(&0u32.to_le_bytes());
+        buf.extend_from_slice(&end_offset.to_le_bytes());
+        buf.extend_from_slice(&1u32.to_le_bytes());
+    }
+
+    let entry_count = u16::try_from(entry_count).unwrap_or(u16::MAX);
+
+    buf.extend_from_slice(&0x06054b50u32.to_le_bytes());
+    buf.extend_from_slice(&0u16.to_le_bytes());
+    buf.extend_from_slice(&0u16.to_le_bytes());
+    buf.extend_from_slice(&entry_count.to_le_bytes());
+    buf.extend_from_slice(&entry_count.to_le_bytes());
+    buf.extend_from_slice(
+        &u32::try_from(central_directory_size)
+            .unwrap_or(u32::MAX)
+            .to_le_bytes(),
+    );
+    buf.extend_from_slice(
+        &u32::try_from(central_directory_offset)
+            .unwrap_or(u32::MAX)
+            .to_le_bytes(),
+    );
+    // no comment
+    buf.extend_from_slice(&0u16.to_le_bytes());
+}
+
+/// Encodes a timestamp in the MS-DOS format of ZIP archives, which starts at 1980 and has a precision of two seconds.
+fn dos_date_time(at: &NaiveDateTime) -> (u16, u16) {
+    if at.year() < 1980 {
+        return (0, (1 << 5) | 1);
+    }
+
+    let time = (at.hour() << 11) | (at.minute() << 5) | (at.second() / 2);
+    let date = ((at.year() as u32 - 1980).min(127) << 9) | (at.month() << 5) | at.day();
+
+    (time as u16, date as u16)
+}
@@ -0,0 +1,203 @@
+use super::Page;
+use crate::db::models::{AuditLog, CreatingAuditLog};
+use diesel::{ExpressionMethods, QueryDsl};
+use diesel_async::{pooled_connection::deadpool::Pool, AsyncPgConnection, RunQueryDsl};
+use std::sync::Arc;
+use thiserror::Error;
+use uuid::Uuid;
+
+#[derive(Error, Debug)]
+pub enum AuditLogServiceError {
+    #[error("database pool error: {0}")]
+    Pool(#[from] diesel_async::pooled_connection::deadpool::PoolError),
+    #[error("diesel error: {0}")]
+    Diesel(#[from] diesel::result::Error),
+}
+
+/// An action of a user that is kept in the audit log.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditAction {
+    FileUploaded,
+    FileRemoved,
+    CollectionCreated,
+    CollectionUpdated,
+    CollectionRemoved,
+    CollectionFileAdded,
+    CollectionFileRemoved,
+    CollectionMerged,
+    TagsAdded,
+    TagsRemoved,
+    RetentionApplied,
+    UserImpersonated,
+}
+
+impl AuditAction {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::FileUploaded => "file_uploaded",
+            Self::FileRemoved => "file_removed",
+            Self::CollectionCreated => "collection_created",
+            Self::CollectionUpdated => "collection_updated",
+            Self::CollectionRemoved => "collection_removed",
+            Self::CollectionFileAdded => "collection_file_added",
+            Self::CollectionFileRemoved => "collection_file_removed",
+            Self::CollectionMerged => "collection_merged",
+            Self::TagsAdded => "tags_added",
+            Self::TagsRemoved => "tags_removed",
+            Self::RetentionApplied => "retention_applied",
+            Self::UserImpersonated => "user_impersonated",
+        }
+    }
+}
+
+/// The user who has taken an action, along with the admin impersonating them, if any.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AuditActor {
+    pub user_id: i32,
+    pub impersonated_by: Option<i32>,
+}
+
+impl AuditActor {
+    /// A user acting on their own.
+    pub fn user(user_id: i32) -> Self {
+        Self {
+            user_id,
+            impersonated_by: None,
+        }
+    }
+}
+
+pub struct AuditLogService {
+    db_pool: Pool<AsyncPgConnection>,
+}
+
+impl AuditLogService {
+    pub fn new(db_pool: Pool<AsyncPgConnection>) -> Arc<Self> {
+        Arc::new(Self { db_pool })
+    }
+
+    /// Records an action of the given user on a file, a collection, or a file in a collection.
+    /// `details` holds whatever else is worth showing about the action, such as the name of the file.
+    /// Actions taken while impersonating the user are flagged with the admin.
+    pub async fn record(
+        &self,
+        actor: AuditActor,
+        action: AuditAction,
+        file_id: Option<Uuid>,
+        collection_id: Option<Uuid>,
+        details: serde_json::Value,
+    ) -> Result<AuditLog, AuditLogServiceError> {
+        self.insert(
+            Some(actor.user_id),
+            actor.impersonated_by,
+            action,
+            file_id,
+            collection_id,
+            details,
+        )
+        .await
+    }
+
+    /// Records an action taken by the server itself, such as applying a retention policy.
+    pub async fn record_system(
+        &self,
+        action: AuditAction,
+        file_id: Option<Uuid>,
+        collection_id: Option<Uuid>,
+        details: serde_json::Value,
+    ) -> Result<AuditLog, AuditLogServiceError> {
+        self.insert(None, None, action, file_id, collection_id, details)
+            .await
+    }
+
+    async fn insert(
+        &self,
+        user_id: Option<i32>,
+        impersonated_by: Option<i32>,
+        action: AuditAction,
+        file_id: Option<Uuid>,
+        collection_id: Option<Uuid>,
+        details: serde_json::Value,
+    ) -> Result<AuditLog, AuditLogServiceError> {
+        use crate::db::schema;
+
+        let db = &mut self.db_pool.get().await?;
+        let audit_log = diesel::insert_into(schema::audit_logs::table)
+            .values(CreatingAuditLog {
+                user_id,
+                action: action.name(),
+                file_id,
+                collection_id,
+                details,
+                impersonated_by,
+            })
+            .returning((
+                schema::audit_logs::id,
+                schema::audit_logs::user_id,
+                schema::audit_logs::action,
+                schema::audit_logs::file_id,
+                schema::audit_logs::collection_id,
+                schema::audit_logs::details,
+                schema::audit_logs::created_at,
+                schema::audit_logs::impersonated_by,
+            ))
+            .get_result::<AuditLog>(db)
+            .await?;
+
+        Ok(audit_log)
+    }
+
+    /// Retrieves the actions of the given user.
+    /// The result will be sorted from the most recent action.
+    /// If `last_audit_log_id` is provided, the result will start after the entry with that ID.
+    pub async fn get_audit_logs_of_user(
+        &self,
+        user_id: i32,
+        last_audit_log_id: Option<i64>,
+        limit: u32,
+    ) -> Result<Page<AuditLog, i64>, AuditLogServiceError> {
+        self.get_audit_logs(Some(user_id), last_audit_log_id, limit)
+            .await
+    }
+
+    /// Retrieves the actions of all users, or of the given user only if `user_id` is provided.
+    /// The result will be sorted from the most recent action.
+    /// If `last_audit_log_id` is provided, the result will start after the entry with that ID.
+    pub async fn get_audit_logs(
+        &self,
+        user_id: Option<i32>,
+        last_audit_log_id: Option<i64>,
+        limit: u32,
+    ) -> Result<Page<AuditLog, i64>, AuditLogServiceError> {
+        use crate::db::schema;
+
+        let db = &mut self.db_pool.get().await?;
+        let mut query = schema::audit_logs::table
+            .select((
+                schema::audit_logs::id,
+                schema::audit_logs::user_id,
+                schema::audit_logs::action,
+                schema::audit_logs::file_id,
+                schema::audit_logs::collection_id,
+                schema::audit_logs::details,
+                schema::audit_logs::created_at,
+                schema::audit_logs::impersonated_by,
+            ))
+            .order(schema::audit_logs::id.desc())
+            // fetch one more entry to tell whether there is a next page
+            .limit(limit as i64 + 1)
+            .into_boxed();
+
+        if let Some(user_id) = user_id {
+            query = query.filter(schema::audit_logs::user_id.eq(user_id));
+        }
+
+        if let Some(last_audit_log_id) = last_audit_log_id {
+            query = query.filter(schema::audit_logs::id.lt(last_audit_log_id));
+        }
+
+        let audit_logs = query.load::<AuditLog>(db).await?;
+
+        Ok(Page::new(audit_logs, limit, None, |audit_log| audit_log.id))
+    }
+}
@@ -1,10 +1,43 @@
-use super::{password_service, PasswordService};
-use crate::db::models::{CreatingUserSession, User, UserIdWithPassword, UserSession};
-use diesel::{BoolExpressionMethods, ExpressionMethods, OptionalExtension, QueryDsl};
+mod oidc;
+
+#[cfg(test)]
+pub use oidc::test::StubOidcProvider;
+pub use oidc::OidcError;
+
+use super::{password_service, PasswordService, UserService, UserServiceError};
+use crate::{
+    config::AppOidc,
+    db::models::{
+        CreatingUserSession, ImpersonationSession, User, UserIdWithPassword, UserSession,
+        UserSessionWithClientInfo,
+    },
+};
+use chrono::{Duration, Utc};
+use diesel::{
+    BoolExpressionMethods, ExpressionMethods, NullableExpressionMethods, OptionalExtension,
+    QueryDsl, TextExpressionMethods,
+};
 use diesel_async::{pooled_connection::deadpool::Pool, AsyncPgConnection, RunQueryDsl};
-use std::sync::Arc;
+use std::{net::IpAddr, sync::Arc};
 use thiserror::Error;
 
+/// The number of leading characters of a session token that are shown to identify the session.
+pub const USER_SESSION_TOKEN_PREFIX_LEN: usize = 12;
+
+/// Returns the part of the token that identifies the session without granting access to it.
+/// The prefix is written in the URL-safe base64 alphabet, so that it can be used in paths.
+pub fn user_session_token_prefix(token: &str) -> String {
+    token
+        .chars()
+        .take(USER_SESSION_TOKEN_PREFIX_LEN)
+        .map(|c| match c {
+            '+' => '-',
+            '/' => '_',
+            c => c,
+        })
+        .collect()
+}
+
 #[derive(Error, Debug)]
 pub enum AuthServiceError {
     #[error("database pool error: {0}")]
@@ -13,26 +46,49 @@ pub enum AuthServiceError {
     Diesel(#[from] diesel::result::Error),
     #[error("{0}")]
     PasswordService(#[from] password_service::PasswordServiceError),
+    #[error("{0}")]
+    UserService(#[from] UserServiceError),
+    #[error("the user has been disabled")]
+    UserDisabled,
+}
+
+#[derive(Error, Debug)]
+pub enum OidcLoginError {
+    #[error("oidc login is not enabled")]
+    Disabled,
+    #[error("{0}")]
+    Oidc(#[from] OidcError),
+    #[error("{0}")]
+    Error(#[from] AuthServiceError),
 }
 
 pub struct AuthService {
     db_pool: Pool<AsyncPgConnection>,
     password_service: Arc<PasswordService>,
+    user_service: Arc<UserService>,
+    oidc_client: Option<oidc::OidcClient>,
 }
 
 impl AuthService {
     pub fn new(
         db_pool: Pool<AsyncPgConnection>,
         password_service: Arc<PasswordService>,
+        user_service: Arc<UserService>,
+        oidc_config: &AppOidc,
     ) -> Arc<Self> {
         Arc::new(Self {
             db_pool,
             password_service,
+            user_service,
+            oidc_client: oidc_config
+                .enabled
+                .then(|| oidc::OidcClient::new(oidc_config)),
         })
     }
 
     /// Authenticates a user by their email and password.
     /// Returns the user ID if the authentication is successful, otherwise None.
+    /// Fails with [`AuthServiceError::UserDisabled`] if the password is correct but the user has been disabled.
     pub async fn authenticate_user(
         &self,
         email: &str,
@@ -43,7 +99,11 @@ impl AuthService {
         let db = &mut self.db_pool.get().await?;
         let user = schema::users::dsl::users
             .filter(schema::users::email.eq(email))
-            .select((schema::users::id, schema::users::password))
+            .select((
+                schema::users::id,
+                schema::users::password,
+                schema::users::disabled_at,
+            ))
             .first::<UserIdWithPassword>(db)
             .await
             .optional()?;
@@ -64,11 +124,21 @@ impl AuthService {
             return Ok(None);
         }
 
+        if user.disabled_at.is_some() {
+            return Err(AuthServiceError::UserDisabled);
+        }
+
         Ok(Some(user.id))
     }
 
     /// Creates a new user session for the given user ID.
-    pub async fn create_user_session(&self, user_id: i32) -> Result<UserSession, AuthServiceError> {
+    /// The user agent and the IP address of the client are recorded to tell the sessions apart when listing them.
+    pub async fn create_user_session(
+        &self,
+        user_id: i32,
+        user_agent: Option<&str>,
+        ip_address: Option<IpAddr>,
+    ) -> Result<UserSession, AuthServiceError> {
         use crate::db::schema;
 
         let token = self.password_service.generate_secure_token_252();
@@ -78,6 +148,10 @@ impl AuthService {
             .values(CreatingUserSession {
                 user_id,
                 token: &token,
+                user_agent,
+                ip_address: ip_address.map(|ip_address| ip_address.to_string()),
+                impersonated_by: None,
+                expires_at: None,
             })
             .returning((
                 schema::user_sessions::user_id,
@@ -90,6 +164,104 @@ impl AuthService {
         Ok(user_session)
     }
 
+    /// Lists the sessions of the given user, the most recent first.
+    pub async fn get_user_sessions(
+        &self,
+        user_id: i32,
+    ) -> Result<Vec<UserSessionWithClientInfo>, AuthServiceError> {
+        use crate::db::schema;
+
+        let db = &mut self.db_pool.get().await?;
+        let user_sessions = schema::user_sessions::table
+            .filter(schema::user_sessions::user_id.eq(user_id))
+            .order(schema::user_sessions::created_at.desc())
+            .select((
+                schema::user_sessions::user_id,
+                schema::user_sessions::token,
+                schema::user_sessions::created_at,
+                schema::user_sessions::user_agent,
+                schema::user_sessions::ip_address,
+            ))
+            .load::<UserSessionWithClientInfo>(db)
+            .await?;
+
+        Ok(user_sessions)
+    }
+
+    /// Starts a login through the OpenID Connect provider.
+    /// Returns the URL of the provider to redirect the user to.
+    pub async fn start_oidc_login(&self) -> Result<String, OidcLoginError> {
+        let oidc_client = match &self.oidc_client {
+            Some(oidc_client) => oidc_client,
+            None => return Err(OidcLoginError::Disabled),
+        };
+
+        let state = self.password_service.generate_url_safe_token_252();
+        let nonce = self.password_service.generate_url_safe_token_252();
+
+        Ok(oidc_client.start_login(state, nonce).await?)
+    }
+
+    /// Completes a login through the OpenID Connect provider, and creates a user session.
+    /// Users are matched by their email, and are created with a random password if they do not exist.
+    /// Disabled users are refused with [`AuthServiceError::UserDisabled`].
+    pub async fn finish_oidc_login(
+        &self,
+        code: &str,
+        state: &str,
+        user_agent: Option<&str>,
+        ip_address: Option<IpAddr>,
+    ) -> Result<UserSession, OidcLoginError> {
+        let oidc_client = match &self.oidc_client {
+            Some(oidc_client) => oidc_client,
+            None => return Err(OidcLoginError::Disabled),
+        };
+
+        let identity = oidc_client.finish_login(code, state).await?;
+
+        let user = self
+            .user_service
+            .get_user_by_email(&identity.email)
+            .await
+            .map_err(AuthServiceError::from)?;
+        let user = match user {
+            Some(user) => user,
+            None => {
+                let password = self.password_service.generate_secure_token_252();
+                let user = self
+                    .user_service
+                    .create_user(&identity.username, &identity.email, &password)
+                    .await;
+
+                match user {
+                    Ok(user) => user,
+                    // the user has been created by a concurrent login in the meantime
+                    Err(UserServiceError::DuplicateEmail(_)) => self
+                        .user_service
+                        .get_user_by_email(&identity.email)
+                        .await
+                        .map_err(AuthServiceError::from)?
+                        .ok_or(AuthServiceError::Diesel(diesel::result::Error::NotFound))?,
+                    // another user has the username, so the email is used as the username instead
+                    Err(UserServiceError::DuplicateUsername(_)) => self
+                        .user_service
+                        .create_user(&identity.email, &identity.email, &password)
+                        .await
+                        .map_err(AuthServiceError::from)?,
+                    Err(err) => return Err(AuthServiceError::from(err).into()),
+                }
+            }
+        };
+
+        if user.disabled_at.is_some() {
+            return Err(AuthServiceError::UserDisabled.into());
+        }
+
+        Ok(self
+            .create_user_session(user.id, user_agent, ip_address)
+            .await?)
+    }
+
     /// Removes a user session from the database.
     /// Returns the user session that was removed, or `None` if the user session was not found.
     pub async fn remove_user_session(
@@ -119,25 +291,155 @@ impl AuthService {
         Ok(deleted_user_session)
     }
 
+    /// Removes the sessions of the given user whose token starts with the given prefix,
+    /// as returned by [`user_session_token_prefix`].
+    /// Prefixes shorter than [`USER_SESSION_TOKEN_PREFIX_LEN`] match nothing, so that sessions cannot be removed by guessing.
+    /// Returns the user sessions that were removed.
+    pub async fn remove_user_sessions_by_token_prefix(
+        &self,
+        user_id: i32,
+        token_prefix: &str,
+    ) -> Result<Vec<UserSessionWithClientInfo>, AuthServiceError> {
+        use crate::db::schema;
+
+        let is_valid_prefix = token_prefix.len() >= USER_SESSION_TOKEN_PREFIX_LEN
+            && token_prefix
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+
+        if !is_valid_prefix {
+            return Ok(vec![]);
+        }
+
+        let pattern = format!("{}%", token_prefix.replace('-', "+").replace('_', "/"));
+
+        let db = &mut self.db_pool.get().await?;
+        let deleted_user_sessions = diesel::delete(
+            schema::user_sessions::table.filter(
+                schema::user_sessions::user_id
+                    .eq(user_id)
+                    .and(schema::user_sessions::token.like(pattern)),
+            ),
+        )
+        .returning((
+            schema::user_sessions::user_id,
+            schema::user_sessions::token,
+            schema::user_sessions::created_at,
+            schema::user_sessions::user_agent,
+            schema::user_sessions::ip_address,
+        ))
+        .get_results::<UserSessionWithClientInfo>(db)
+        .await?;
+
+        Ok(deleted_user_sessions)
+    }
+
+    /// Removes all sessions of the given user, logging them out everywhere.
+    /// Returns the user sessions that were removed.
+    pub async fn remove_user_sessions(
+        &self,
+        user_id: i32,
+    ) -> Result<Vec<UserSessionWithClientInfo>, AuthServiceError> {
+        use crate::db::schema;
+
+        let db = &mut self.db_pool.get().await?;
+        let deleted_user_sessions = diesel::delete(
+            schema::user_sessions::table.filter(schema::user_sessions::user_id.eq(user_id)),
+        )
+        .returning((
+            schema::user_sessions::user_id,
+            schema::user_sessions::token,
+            schema::user_sessions::created_at,
+            schema::user_sessions::user_agent,
+            schema::user_sessions::ip_address,
+        ))
+        .get_results::<UserSessionWithClientInfo>(db)
+        .await?;
+
+        Ok(deleted_user_sessions)
+    }
+
+    /// Creates a session for an admin to act as the given user, which expires after `expiration`.
+    /// The actions taken through the session are recorded in the audit log as taken by the admin on behalf of the user.
+    pub async fn create_impersonation_session(
+        &self,
+        admin_id: i32,
+        user_id: i32,
+        expiration: Duration,
+        user_agent: Option<&str>,
+        ip_address: Option<IpAddr>,
+    ) -> Result<ImpersonationSession, AuthServiceError> {
+        use crate::db::schema;
+
+        let token = self.password_service.generate_secure_token_252();
+        let expires_at = Utc::now().naive_utc() + expiration;
+
+        let db = &mut self.db_pool.get().await?;
+        let impersonation_session = diesel::insert_into(schema::user_sessions::table)
+            .values(CreatingUserSession {
+                user_id,
+                token: &token,
+                user_agent,
+                ip_address: ip_address.map(|ip_address| ip_address.to_string()),
+                impersonated_by: Some(admin_id),
+                expires_at: Some(expires_at),
+            })
+            .returning((
+                schema::user_sessions::user_id,
+                schema::user_sessions::token,
+                schema::user_sessions::created_at,
+                schema::user_sessions::impersonated_by.assume_not_null(),
+                schema::user_sessions::expires_at.assume_not_null(),
+            ))
+            .get_result::<ImpersonationSession>(db)
+            .await?;
+
+        Ok(impersonation_session)
+    }
+
     /// Gets a user from by session token.
     /// Returns the user if the session is found, otherwise None.
+    #[cfg(test)]
     pub async fn get_user_from_session(
         &self,
         token: &str,
     ) -> Result<Option<User>, AuthServiceError> {
+        let user = self.get_user_and_impersonator_from_session(token).await?;
+        Ok(user.map(|(user, _)| user))
+    }
+
+    /// Gets a user by session token, along with the ID of the admin impersonating them if it is an impersonation session.
+    /// Expired sessions and the sessions of disabled users are not found.
+    pub async fn get_user_and_impersonator_from_session(
+        &self,
+        token: &str,
+    ) -> Result<Option<(User, Option<i32>)>, AuthServiceError> {
         use crate::db::schema;
 
         let db = &mut self.db_pool.get().await?;
         let user = schema::users::table
             .inner_join(schema::user_sessions::table)
             .filter(schema::user_sessions::token.eq(token))
+            .filter(schema::users::disabled_at.is_null())
+            .filter(
+                schema::user_sessions::expires_at
+                    .is_null()
+                    .or(schema::user_sessions::expires_at.gt(diesel::dsl::now)),
+            )
             .select((
-                schema::users::id,
-                schema::users::username,
-                schema::users::email,
-                schema::users::joined_at,
+                (
+                    schema::users::id,
+                    schema::users::username,
+                    schema::users::email,
+                    schema::users::joined_at,
+                    schema::users::display_name,
+                    schema::users::avatar_file_id,
+                    schema::users::role,
+                    schema::users::disabled_at,
+                ),
+                schema::user_sessions::impersonated_by,
             ))
-            .first::<User>(db)
+            .first::<(User, Option<i32>)>(db)
             .await
             .optional()?;
 
@@ -0,0 +1,541 @@
+use crate::config::AppOidc;
+use base64::{prelude::BASE64_URL_SAFE_NO_PAD, Engine};
+use chrono::Utc;
+use parking_lot::Mutex;
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+use thiserror::Error;
+use tokio::sync::OnceCell;
+use url::Url;
+
+#[derive(Error, Debug)]
+pub enum OidcError {
+    #[error("request to the identity provider failed: {0}")]
+    Request(#[from] reqwest::Error),
+    #[error("invalid url: {0}")]
+    Url(#[from] url::ParseError),
+    #[error("the discovered issuer `{0}` does not match the configured one")]
+    IssuerMismatch(String),
+    #[error("unknown or expired login state")]
+    InvalidState,
+    #[error("invalid id token: {0}")]
+    InvalidIdToken(&'static str),
+    #[error("the identity provider did not return a verified email")]
+    UnverifiedEmail,
+}
+
+#[derive(Deserialize, Debug)]
+struct ProviderMetadata {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct TokenResponse {
+    id_token: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum Audience {
+    Single(String),
+    Multiple(Vec<String>),
+}
+
+impl Audience {
+    fn contains(&self, client_id: &str) -> bool {
+        match self {
+            Audience::Single(audience) => audience == client_id,
+            Audience::Multiple(audiences) => audiences.iter().any(|audience| audience == client_id),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct IdTokenClaims {
+    iss: String,
+    aud: Audience,
+    exp: i64,
+    nonce: Option<String>,
+    email: Option<String>,
+    email_verified: Option<bool>,
+    preferred_username: Option<String>,
+    name: Option<String>,
+}
+
+/// A user identified by the provider.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OidcIdentity {
+    pub email: String,
+    /// The name to give the user if they have to be created.
+    pub username: String,
+}
+
+struct PendingLogin {
+    nonce: String,
+    started_at: Instant,
+}
+
+/// A minimal OpenID Connect relying party using the authorization code flow.
+/// The ID token is taken directly from the token endpoint over TLS, so its claims are checked
+/// without verifying its signature, as allowed by OpenID Connect Core 1.0 section 3.1.3.7.
+pub struct OidcClient {
+    config: AppOidc,
+    http_client: reqwest::Client,
+    provider_metadata: OnceCell<ProviderMetadata>,
+    pending_logins: Mutex<HashMap<String, PendingLogin>>,
+}
+
+impl OidcClient {
+    pub fn new(config: &AppOidc) -> Self {
+        Self {
+            config: config.clone(),
+            http_client: reqwest::Client::new(),
+            provider_metadata: OnceCell::new(),
+            pending_logins: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Discovers the provider metadata on first use. A failed discovery is retried on the next login.
+    async fn provider_metadata(&self) -> Result<&ProviderMetadata, OidcError> {
+        self.provider_metadata
+            .get_or_try_init(|| async {
+                let issuer_url = self.config.issuer_url.trim_end_matches('/');
+                let metadata = self
+                    .http_client
+                    .get(format!("{}/.well-known/openid-configuration", issuer_url))
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .json::<ProviderMetadata>()
+                    .await?;
+
+                if metadata.issuer.trim_end_matches('/') != issuer_url {
+                    return Err(OidcError::IssuerMismatch(metadata.issuer));
+                }
+
+                Ok::<_, OidcError>(metadata)
+            })
+            .await
+    }
+
+    /// Starts a login, returning the URL of the provider to redirect the user to.
+    /// `state` and `nonce` must be unguessable.
+    pub async fn start_login(&self, state: String, nonce: String) -> Result<String, OidcError> {
+        let metadata = self.provider_metadata().await?;
+
+        let mut url = Url::parse(&metadata.authorization_endpoint)?;
+        url.query_pairs_mut()
+            .append_pair("response_type", "code")
+            .append_pair("client_id", &self.config.client_id)
+            .append_pair("redirect_uri", &self.config.redirect_url)
+            .append_pair("scope", &self.config.scopes.join(" "))
+            .append_pair("state", &state)
+            .append_pair("nonce", &nonce);
+
+        let expiration = Duration::from_secs(self.config.login_expiration);
+        let mut pending_logins = self.pending_logins.lock();
+
+        // drop the logins that have been abandoned, so that they do not pile up
+        pending_logins.retain(|_, login| login.started_at.elapsed() < expiration);
+        pending_logins.insert(
+            state,
+            PendingLogin {
+                nonce,
+                started_at: Instant::now(),
+            },
+        );
+
+        Ok(url.into())
+    }
+
+    /// Completes a login by exchanging the authorization code, returning the identified user.
+    /// The login state is consumed, so that the callback cannot be replayed.
+    pub async fn finish_login(&self, code: &str, state: &str) -> Result<OidcIdentity, OidcError> {
+        let expiration = Duration::from_secs(self.config.login_expiration);
+        let login = self.pending_logins.lock().remove(state);
+        let login = match login {
+            Some(login) if login.started_at.elapsed() < expiration => login,
+            _ => return Err(OidcError::InvalidState),
+        };
+
+        let metadata = self.provider_metadata().await?;
+        let token = self
+            .http_client
+            .post(&metadata.token_endpoint)
+            .basic_auth(&self.config.client_id, Some(&self.config.client_secret))
+            .form(&[
+                ("grant_type", "authorization_code"),
+                ("code", code),
+                ("redirect_uri", self.config.redirect_url.as_str()),
+                ("client_id", self.config.client_id.as_str()),
+            ])
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<TokenResponse>()
+            .await?;
+
+        let payload = token
+            .id_token
+            .split('.')
+            .nth(1)
+            .ok_or(OidcError::InvalidIdToken("malformed token"))?;
+        let payload = BASE64_URL_SAFE_NO_PAD
+            .decode(payload.trim_end_matches('='))
+            .map_err(|_| OidcError::InvalidIdToken("malformed payload"))?;
+        let claims = serde_json::from_slice::<IdTokenClaims>(&payload)
+            .map_err(|_| OidcError::InvalidIdToken("malformed claims"))?;
+
+        if claims.iss != metadata.issuer {
+            return Err(OidcError::InvalidIdToken("issuer mismatch"));
+        }
+
+        if !claims.aud.contains(&self.config.client_id) {
+            return Err(OidcError::InvalidIdToken("audience mismatch"));
+        }
+
+        if claims.exp <= Utc::now().timestamp() {
+            return Err(OidcError::InvalidIdToken("expired"));
+        }
+
+        if claims.nonce.as_deref() != Some(login.nonce.as_str()) {
+            return Err(OidcError::InvalidIdToken("nonce mismatch"));
+        }
+
+        // an unverified email could be used to take over the account of someone else
+        let email = match (claims.email, claims.email_verified) {
+            (Some(email), Some(true)) => email,
+            _ => return Err(OidcError::UnverifiedEmail),
+        };
+        let username = claims
+            .preferred_username
+            .or(claims.name)
+            .unwrap_or_else(|| email.split('@').next().unwrap_or(&email).to_owned());
+
+        Ok(OidcIdentity { email, username })
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use base64::{prelude::BASE64_URL_SAFE_NO_PAD, Engine};
+    use parking_lot::Mutex;
+    use serde_json::json;
+    use std::sync::Arc;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+        task::JoinHandle,
+    };
+
+    /// An identity provider serving the discovery document and the token endpoint on a local port.
+    /// The token endpoint returns an unsigned ID token with the claims given to [`StubOidcProvider::set_claims`].
+    pub struct StubOidcProvider {
+        issuer_url: String,
+        claims: Arc<Mutex<serde_json::Value>>,
+        server: JoinHandle<()>,
+    }
+
+    impl StubOidcProvider {
+        pub async fn start() -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let issuer_url = format!("http://{}", listener.local_addr().unwrap());
+            let claims = Arc::new(Mutex::new(json!({})));
+
+            let server = tokio::spawn({
+                let issuer_url = issuer_url.clone();
+                let claims = claims.clone();
+
+                async move {
+                    while let Ok((stream, _)) = listener.accept().await {
+                        tokio::spawn(serve(stream, issuer_url.clone(), claims.clone()));
+                    }
+                }
+            });
+
+            Self {
+                issuer_url,
+                claims,
+                server,
+            }
+        }
+
+        pub fn issuer_url(&self) -> &str {
+            &self.issuer_url
+        }
+
+        pub fn set_claims(&self, claims: serde_json::Value) {
+            *self.claims.lock() = claims;
+        }
+    }
+
+    impl Drop for StubOidcProvider {
+        fn drop(&mut self) {
+            self.server.abort();
+        }
+    }
+
+    async fn serve(
+        mut stream: TcpStream,
+        issuer_url: String,
+        claims: Arc<Mutex<serde_json::Value>>,
+    ) {
+        let mut request = Vec::new();
+        let mut buffer = [0u8; 1024];
+
+        let head_len = loop {
+            match stream.read(&mut buffer).await {
+                Ok(0) | Err(_) => return,
+                Ok(read) => request.extend_from_slice(&buffer[..read]),
+            }
+
+            if let Some(position) = request.windows(4).position(|window| window == b"\r\n\r\n") {
+                break position + 4;
+            }
+        };
+
+        let head = String::from_utf8_lossy(&request[..head_len]).into_owned();
+        let content_length = head
+            .lines()
+            .filter_map(|line| line.split_once(':'))
+            .find(|(name, _)| name.eq_ignore_ascii_case("content-length"))
+            .and_then(|(_, value)| value.trim().parse::<usize>().ok())
+            .unwrap_or(0);
+
+        // the body is read as well, so that closing the connection does not reset it
+        while request.len() < head_len + content_length {
+            match stream.read(&mut buffer).await {
+                Ok(0) | Err(_) => return,
+                Ok(read) => request.extend_from_slice(&buffer[..read]),
+            }
+        }
+
+        let body = match head.split_whitespace().nth(1) {
+            Some("/.well-known/openid-configuration") => json!({
+                "issuer": issuer_url,
+                "authorization_endpoint": format!("{}/authorize", issuer_url),
+                "token_endpoint": format!("{}/token", issuer_url),
+            }),
+            Some("/token") => {
+                let header = BASE64_URL_SAFE_NO_PAD.encode(json!({ "alg": "none" }).to_string());
+                let payload = BASE64_URL_SAFE_NO_PAD.encode(claims.lock().to_string());
+                json!({ "id_token": format!("{}.{}.", header, payload) })
+            }
+            _ => {
+                let _ = stream
+                    .write_all(
+                        b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                    )
+                    .await;
+                return;
+            }
+        };
+        let body = body.to_string();
+        let response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            body.len(),
+            body
+        );
+
+        let _ = stream.write_all(response.as_bytes()).await;
+        let _ = stream.shutdown().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{test::StubOidcProvider, *};
+    use serde_json::json;
+
+    const CLIENT_ID: &str = "poly-tag";
+
+    fn create_client(provider: &StubOidcProvider, login_expiration: u64) -> OidcClient {
+        OidcClient::new(&AppOidc {
+            enabled: true,
+            issuer_url: provider.issuer_url().to_owned(),
+            client_id: CLIENT_ID.to_owned(),
+            client_secret: "secret".to_owned(),
+            redirect_url: "http://localhost:8000/auth/oidc/callback".to_owned(),
+            login_expiration,
+            ..Default::default()
+        })
+    }
+
+    fn valid_claims(provider: &StubOidcProvider, nonce: &str) -> serde_json::Value {
+        json!({
+            "iss": provider.issuer_url(),
+            "aud": CLIENT_ID,
+            "exp": Utc::now().timestamp() + 60,
+            "nonce": nonce,
+            "email": "oidc_user@example.com",
+            "email_verified": true,
+            "preferred_username": "oidc_user",
+        })
+    }
+
+    async fn finish_login_with_claims(
+        claims: impl FnOnce(&StubOidcProvider) -> serde_json::Value,
+    ) -> Result<OidcIdentity, OidcError> {
+        let provider = StubOidcProvider::start().await;
+        let client = create_client(&provider, 60);
+
+        client
+            .start_login("state".to_owned(), "nonce".to_owned())
+            .await
+            .unwrap();
+        provider.set_claims(claims(&provider));
+
+        client.finish_login("code", "state").await
+    }
+
+    #[rocket::async_test]
+    async fn test_finish_login() {
+        let identity = finish_login_with_claims(|provider| valid_claims(provider, "nonce")).await;
+        assert_eq!(
+            identity.unwrap(),
+            OidcIdentity {
+                email: "oidc_user@example.com".to_owned(),
+                username: "oidc_user".to_owned(),
+            }
+        );
+    }
+
+    #[rocket::async_test]
+    async fn test_finish_login_consumed_state() {
+        let provider = StubOidcProvider::start().await;
+        let client = create_client(&provider, 60);
+
+        client
+            .start_login("state".to_owned(), "nonce".to_owned())
+            .await
+            .unwrap();
+        provider.set_claims(valid_claims(&provider, "nonce"));
+
+        assert!(client.finish_login("code", "state").await.is_ok());
+        assert!(matches!(
+            client.finish_login("code", "state").await,
+            Err(OidcError::InvalidState)
+        ));
+        assert!(matches!(
+            client.finish_login("code", "unknown").await,
+            Err(OidcError::InvalidState)
+        ));
+    }
+
+    #[rocket::async_test]
+    async fn test_finish_login_expired_state() {
+        let provider = StubOidcProvider::start().await;
+        let client = create_client(&provider, 0);
+
+        client
+            .start_login("state".to_owned(), "nonce".to_owned())
+            .await
+            .unwrap();
+        provider.set_claims(valid_claims(&provider, "nonce"));
+
+        assert!(matches!(
+            client.finish_login("code", "state").await,
+            Err(OidcError::InvalidState)
+        ));
+    }
+
+    #[rocket::async_test]
+    async fn test_finish_login_nonce_mismatch() {
+        let identity = finish_login_with_claims(|provider| valid_claims(provider, "other")).await;
+        assert!(matches!(
+            identity,
+            Err(OidcError::InvalidIdToken("nonce mismatch"))
+        ));
+
+        let identity = finish_login_with_claims(|provider| {
+            let mut claims = valid_claims(provider, "nonce");
+            claims.as_object_mut().unwrap().remove("nonce");
+            claims
+        })
+        .await;
+        assert!(matches!(
+            identity,
+            Err(OidcError::InvalidIdToken("nonce mismatch"))
+        ));
+    }
+
+    #[rocket::async_test]
+    async fn test_finish_login_audience_mismatch() {
+        let identity = finish_login_with_claims(|provider| {
+            let mut claims = valid_claims(provider, "nonce");
+            claims["aud"] = json!(["other-client", "another-client"]);
+            claims
+        })
+        .await;
+        assert!(matches!(
+            identity,
+            Err(OidcError::InvalidIdToken("audience mismatch"))
+        ));
+
+        let identity = finish_login_with_claims(|provider| {
+            let mut claims = valid_claims(provider, "nonce");
+            claims["aud"] = json!(["other-client", CLIENT_ID]);
+            claims
+        })
+        .await;
+        assert!(identity.is_ok());
+    }
+
+    #[rocket::async_test]
+    async fn test_finish_login_expired_token() {
+        let identity = finish_login_with_claims(|provider| {
+            let mut claims = valid_claims(provider, "nonce");
+            claims["exp"] = json!(Utc::now().timestamp() - 60);
+            claims
+        })
+        .await;
+        assert!(matches!(
+            identity,
+            Err(OidcError::InvalidIdToken("expired"))
+        ));
+    }
+
+    #[rocket::async_test]
+    async fn test_finish_login_unverified_email() {
+        let identity = finish_login_with_claims(|provider| {
+            let mut claims = valid_claims(provider, "nonce");
+            claims["email_verified"] = json!(false);
+            claims
+        })
+        .await;
+        assert!(matches!(identity, Err(OidcError::UnverifiedEmail)));
+
+        let identity = finish_login_with_claims(|provider| {
+            let mut claims = valid_claims(provider, "nonce");
+            claims.as_object_mut().unwrap().remove("email_verified");
+            claims
+        })
+        .await;
+        assert!(matches!(identity, Err(OidcError::UnverifiedEmail)));
+    }
+
+    #[rocket::async_test]
+    async fn test_finish_login_username_fallback() {
+        let identity = finish_login_with_claims(|provider| {
+            let mut claims = valid_claims(provider, "nonce");
+            claims.as_object_mut().unwrap().remove("preferred_username");
+            claims["name"] = json!("OIDC User");
+            claims
+        })
+        .await;
+        assert_eq!(identity.unwrap().username, "OIDC User");
+
+        let identity = finish_login_with_claims(|provider| {
+            let mut claims = valid_claims(provider, "nonce");
+            claims.as_object_mut().unwrap().remove("preferred_username");
+            claims
+        })
+        .await;
+        assert_eq!(identity.unwrap().username, "oidc_user");
+    }
+}
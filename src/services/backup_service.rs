@@ -0,0 +1,590 @@
+use super::{
+    parse_tag, FileDriver, FileMetadata, PasswordService, PasswordServiceError, ReadError,
+    ReadRange, SearchService, WriteError,
+};
+use crate::db::models::{Collection, CollectionFilePair, File, FileAttribute, Tag, User};
+use chrono::{NaiveDateTime, Utc};
+use diesel::{ExpressionMethods, QueryDsl, Queryable};
+use diesel_async::{
+    pooled_connection::deadpool::Pool, scoped_futures::ScopedFutureExt, AsyncConnection,
+    AsyncPgConnection, RunQueryDsl,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use thiserror::Error;
+use uuid::Uuid;
+
+/// The version of the backup layout. Bumped whenever the layout changes incompatibly.
+pub const BACKUP_FORMAT_VERSION: u32 = 1;
+/// The name of the JSON file holding all the metadata in a backup directory.
+pub const BACKUP_METADATA_NAME: &str = "metadata.json";
+/// The name of the directory holding the file contents in a backup directory, named by file ID.
+pub const BACKUP_BLOB_DIRECTORY_NAME: &str = "blobs";
+
+/// The number of rows inserted per statement, to stay below the bind parameter limit of PostgreSQL.
+const RESTORE_CHUNK_SIZE: usize = 1000;
+
+#[derive(Error, Debug)]
+pub enum BackupServiceError {
+    #[error("database pool error: {0}")]
+    Pool(#[from] diesel_async::pooled_connection::deadpool::PoolError),
+    #[error("diesel error: {0}")]
+    Diesel(#[from] diesel::result::Error),
+    #[error("io error: {0}")]
+    IO(#[from] std::io::Error),
+    #[error("json error: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("read error: {0}")]
+    Read(#[from] ReadError),
+    #[error("write error: {0}")]
+    Write(#[from] WriteError),
+    #[error("{0}")]
+    PasswordService(#[from] PasswordServiceError),
+    #[error("the directory `{}` is not empty", .0.display())]
+    DirectoryNotEmpty(PathBuf),
+    #[error("the instance is not empty; backups can only be restored into an empty instance")]
+    InstanceNotEmpty,
+    #[error("unsupported backup format version `{0}`; expected `{BACKUP_FORMAT_VERSION}`")]
+    UnsupportedFormatVersion(u32),
+    #[error("the content of file with ID `{0}` is missing")]
+    MissingBlob(Uuid),
+}
+
+/// A file row as kept in a backup, including the columns the API does not expose.
+#[derive(Serialize, Deserialize, Queryable, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct BackupFile {
+    pub id: Uuid,
+    pub name: String,
+    pub mime: String,
+    pub size: i64,
+    pub hash: i64,
+    pub uploaded_at: NaiveDateTime,
+    pub file_metadata: Option<serde_json::Value>,
+    pub verified_at: Option<NaiveDateTime>,
+    #[serde(default)]
+    pub scan_status: Option<String>,
+    #[serde(default)]
+    pub scan_signature: Option<String>,
+    #[serde(default)]
+    pub scanned_at: Option<NaiveDateTime>,
+    #[serde(default)]
+    pub perceptual_hash: Option<i64>,
+    /// The text extracted from the file, so that it can be searched again once restored.
+    #[serde(default)]
+    pub search_content: Option<String>,
+}
+
+/// All the metadata of an instance. Passwords and sessions are deliberately left out.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Backup {
+    pub format_version: u32,
+    pub exported_at: NaiveDateTime,
+    pub users: Vec<User>,
+    pub collections: Vec<Collection>,
+    pub files: Vec<BackupFile>,
+    pub collection_file_pairs: Vec<CollectionFilePair>,
+    pub tags: Vec<Tag>,
+    /// Backups made before file attributes have none.
+    #[serde(default)]
+    pub file_attributes: Vec<FileAttribute>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BackupSummary {
+    pub user_count: usize,
+    pub collection_count: usize,
+    pub file_count: usize,
+    pub byte_count: u64,
+    pub tag_count: usize,
+    pub file_attribute_count: usize,
+}
+
+impl Backup {
+    fn summary(&self) -> BackupSummary {
+        BackupSummary {
+            user_count: self.users.len(),
+            collection_count: self.collections.len(),
+            file_count: self.files.len(),
+            byte_count: self.files.iter().map(|file| file.size as u64).sum(),
+            tag_count: self.tags.len(),
+            file_attribute_count: self.file_attributes.len(),
+        }
+    }
+}
+
+pub struct BackupService {
+    db_pool: Pool<AsyncPgConnection>,
+    password_service: Arc<PasswordService>,
+    search_service: Arc<dyn SearchService + Send + Sync>,
+    file_driver: Arc<dyn FileDriver + Send + Sync>,
+}
+
+impl BackupService {
+    pub fn new(
+        db_pool: Pool<AsyncPgConnection>,
+        password_service: Arc<PasswordService>,
+        search_service: Arc<dyn SearchService + Send + Sync>,
+        file_driver: Arc<dyn FileDriver + Send + Sync>,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            db_pool,
+            password_service,
+            search_service,
+            file_driver,
+        })
+    }
+
+    /// Exports all the metadata and file contents into the given directory.
+    /// The directory is created if it does not exist, and must be empty otherwise.
+    /// The metadata is read from a single snapshot, and is written last, so that an interrupted
+    /// export is never mistaken for a complete one.
+    pub async fn export(&self, directory: &Path) -> Result<BackupSummary, BackupServiceError> {
+        use crate::db::schema;
+
+        tokio::fs::create_dir_all(directory).await?;
+
+        if tokio::fs::read_dir(directory)
+            .await?
+            .next_entry()
+            .await?
+            .is_some()
+        {
+            return Err(BackupServiceError::DirectoryNotEmpty(
+                directory.to_path_buf(),
+            ));
+        }
+
+        let db = &mut self.db_pool.get().await?;
+        let backup = db
+            .build_transaction()
+            .read_only()
+            .repeatable_read()
+            .run(|db| {
+                async move {
+                    let users = schema::users::table
+                        .select((
+                            schema::users::id,
+                            schema::users::username,
+                            schema::users::email,
+                            schema::users::joined_at,
+                            schema::users::display_name,
+                            schema::users::avatar_file_id,
+                            schema::users::role,
+                            schema::users::disabled_at,
+                        ))
+                        .order(schema::users::id.asc())
+                        .load::<User>(db)
+                        .await?;
+                    let collections = schema::collections::table
+                        .select((
+                            schema::collections::id,
+                            schema::collections::name,
+                            schema::collections::description,
+                            schema::collections::created_at,
+                            schema::collections::version,
+                        ))
+                        .order(schema::collections::id.asc())
+                        .load::<Collection>(db)
+                        .await?;
+                    let files = schema::files::table
+                        .select((
+                            schema::files::id,
+                            schema::files::name,
+                            schema::files::mime,
+                            schema::files::size,
+                            schema::files::hash,
+                            schema::files::uploaded_at,
+                            schema::files::file_metadata,
+                            schema::files::verified_at,
+                            schema::files::scan_status,
+                            schema::files::scan_signature,
+                            schema::files::scanned_at,
+                            schema::files::perceptual_hash,
+                            schema::files::search_content,
+                        ))
+                        .order(schema::files::id.asc())
+                        .load::<BackupFile>(db)
+                        .await?;
+                    let collection_file_pairs = schema::collection_file_pairs::table
+                        .select((
+                            schema::collection_file_pairs::collection_id,
+                            schema::collection_file_pairs::file_id,
+                            schema::collection_file_pairs::position,
+                        ))
+                        .order((
+                            schema::collection_file_pairs::collection_id.asc(),
+                            schema::collection_file_pairs::file_id.asc(),
+                        ))
+                        .load::<CollectionFilePair>(db)
+                        .await?;
+                    let tags = schema::tags::table
+                        .select((
+                            schema::tags::name,
+                            schema::tags::file_id,
+                            schema::tags::namespace,
+                        ))
+                        .order((
+                            schema::tags::file_id.asc(),
+                            schema::tags::namespace.asc(),
+                            schema::tags::name.asc(),
+                        ))
+                        .load::<Tag>(db)
+                        .await?;
+                    let file_attributes = schema::file_attributes::table
+                        .select((
+                            schema::file_attributes::file_id,
+                            schema::file_attributes::key,
+                            schema::file_attributes::value,
+                        ))
+                        .order((
+                            schema::file_attributes::file_id.asc(),
+                            schema::file_attributes::key.asc(),
+                        ))
+                        .load::<FileAttribute>(db)
+                        .await?;
+
+                    Ok::<_, BackupServiceError>(Backup {
+                        format_version: BACKUP_FORMAT_VERSION,
+                        exported_at: Utc::now().naive_utc(),
+                        users,
+                        collections,
+                        files,
+                        collection_file_pairs,
+                        tags,
+                        file_attributes,
+                    })
+                }
+                .scope_boxed()
+            })
+            .await?;
+
+        let blob_directory = directory.join(BACKUP_BLOB_DIRECTORY_NAME);
+        tokio::fs::create_dir(&blob_directory).await?;
+
+        for file in &backup.files {
+            let reader = self.file_driver.read(file.id, ReadRange::Full).await?;
+            let mut reader = match reader {
+                Some(reader) => reader,
+                None => return Err(BackupServiceError::MissingBlob(file.id)),
+            };
+
+            let mut blob =
+                tokio::fs::File::create(blob_directory.join(file.id.to_string())).await?;
+            tokio::io::copy(&mut reader, &mut blob).await?;
+            blob.sync_all().await?;
+        }
+
+        let metadata = serde_json::to_vec(&backup)?;
+        tokio::fs::write(directory.join(BACKUP_METADATA_NAME), metadata).await?;
+
+        Ok(backup.summary())
+    }
+
+    /// Restores a backup exported by `export` into this instance, keeping all the IDs.
+    /// The instance must not have any user, collection or file.
+    /// Since passwords are not exported, restored users get random passwords and must reset them.
+    pub async fn restore(&self, directory: &Path) -> Result<BackupSummary, BackupServiceError> {
+        use crate::db::schema;
+
+        let metadata = tokio::fs::read(directory.join(BACKUP_METADATA_NAME)).await?;
+        let backup = serde_json::from_slice::<Backup>(&metadata)?;
+
+        if backup.format_version != BACKUP_FORMAT_VERSION {
+            return Err(BackupServiceError::UnsupportedFormatVersion(
+                backup.format_version,
+            ));
+        }
+
+        let blob_directory = directory.join(BACKUP_BLOB_DIRECTORY_NAME);
+
+        // check every blob up front, rather than failing halfway through the restoration
+        for file in &backup.files {
+            if !tokio::fs::try_exists(blob_directory.join(file.id.to_string())).await? {
+                return Err(BackupServiceError::MissingBlob(file.id));
+            }
+        }
+
+        let mut user_passwords = Vec::with_capacity(backup.users.len());
+
+        for _ in &backup.users {
+            let password = self.password_service.generate_secure_token_252();
+            user_passwords.push(self.password_service.hash_password(&password)?);
+        }
+
+        // backups made before tag namespaces keep the namespace in the name of tags
+        let tags = backup
+            .tags
+            .iter()
+            .map(|tag| {
+                let (namespace, name) = if tag.namespace.is_empty() {
+                    parse_tag(&tag.name)
+                } else {
+                    (tag.namespace.as_str(), tag.name.as_str())
+                };
+
+                Tag {
+                    name: name.to_owned(),
+                    file_id: tag.file_id,
+                    namespace: namespace.to_owned(),
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let backup = &backup;
+        let blob_directory = &blob_directory;
+        let user_passwords = &user_passwords;
+        let tags = &tags;
+
+        let db = &mut self.db_pool.get().await?;
+        db.transaction(|db| {
+            async move {
+                let user_count = schema::users::table.count().get_result::<i64>(db).await?;
+                let collection_count = schema::collections::table
+                    .count()
+                    .get_result::<i64>(db)
+                    .await?;
+                let file_count = schema::files::table.count().get_result::<i64>(db).await?;
+
+                if user_count != 0 || collection_count != 0 || file_count != 0 {
+                    return Err(BackupServiceError::InstanceNotEmpty);
+                }
+
+                let users = backup.users.iter().zip(user_passwords).collect::<Vec<_>>();
+
+                for chunk in users.chunks(RESTORE_CHUNK_SIZE) {
+                    let values = chunk
+                        .iter()
+                        .map(|(user, password)| {
+                            (
+                                schema::users::id.eq(user.id),
+                                schema::users::username.eq(&user.username),
+                                schema::users::email.eq(&user.email),
+                                schema::users::password.eq(password.as_str()),
+                                schema::users::joined_at.eq(user.joined_at),
+                                schema::users::display_name.eq(user.display_name.as_deref()),
+                                schema::users::role.eq(&user.role),
+                                schema::users::disabled_at.eq(user.disabled_at),
+                            )
+                        })
+                        .collect::<Vec<_>>();
+                    diesel::insert_into(schema::users::table)
+                        .values(values)
+                        .execute(db)
+                        .await?;
+                }
+
+                // the IDs have been inserted explicitly, so the sequence has to catch up with them
+                diesel::sql_query(
+                    "SELECT setval(pg_get_serial_sequence('users', 'id'), COALESCE(MAX(id), 1), MAX(id) IS NOT NULL) FROM users",
+                )
+                .execute(db)
+                .await?;
+
+                for chunk in backup.collections.chunks(RESTORE_CHUNK_SIZE) {
+                    let values = chunk
+                        .iter()
+                        .map(|collection| {
+                            (
+                                schema::collections::id.eq(collection.id),
+                                schema::collections::name.eq(&collection.name),
+                                schema::collections::description
+                                    .eq(collection.description.as_deref()),
+                                schema::collections::created_at.eq(collection.created_at),
+                            )
+                        })
+                        .collect::<Vec<_>>();
+                    diesel::insert_into(schema::collections::table)
+                        .values(values)
+                        .execute(db)
+                        .await?;
+                }
+
+                for chunk in backup.files.chunks(RESTORE_CHUNK_SIZE) {
+                    let values = chunk
+                        .iter()
+                        .map(|file| {
+                            (
+                                schema::files::id.eq(file.id),
+                                schema::files::name.eq(&file.name),
+                                schema::files::mime.eq(&file.mime),
+                                schema::files::size.eq(file.size),
+                                schema::files::hash.eq(file.hash),
+                                schema::files::uploaded_at.eq(file.uploaded_at),
+                                schema::files::file_metadata.eq(file.file_metadata.clone()),
+                                schema::files::verified_at.eq(file.verified_at),
+                                schema::files::scan_status.eq(file.scan_status.as_deref()),
+                                schema::files::scan_signature.eq(file.scan_signature.as_deref()),
+                                schema::files::scanned_at.eq(file.scanned_at),
+                                schema::files::perceptual_hash.eq(file.perceptual_hash),
+                                schema::files::search_content.eq(file.search_content.as_deref()),
+                            )
+                        })
+                        .collect::<Vec<_>>();
+                    diesel::insert_into(schema::files::table)
+                        .values(values)
+                        .execute(db)
+                        .await?;
+                }
+
+                // avatars refer to files, so they can only be set once the files are in place
+                for user in &backup.users {
+                    if let Some(avatar_file_id) = user.avatar_file_id {
+                        diesel::update(schema::users::table.filter(schema::users::id.eq(user.id)))
+                            .set(schema::users::avatar_file_id.eq(avatar_file_id))
+                            .execute(db)
+                            .await?;
+                    }
+                }
+
+                for chunk in backup.collection_file_pairs.chunks(RESTORE_CHUNK_SIZE) {
+                    let values = chunk
+                        .iter()
+                        .map(|pair| {
+                            (
+                                schema::collection_file_pairs::collection_id.eq(pair.collection_id),
+                                schema::collection_file_pairs::file_id.eq(pair.file_id),
+                                schema::collection_file_pairs::position.eq(pair.position),
+                            )
+                        })
+                        .collect::<Vec<_>>();
+                    diesel::insert_into(schema::collection_file_pairs::table)
+                        .values(values)
+                        .execute(db)
+                        .await?;
+                }
+
+                for chunk in tags.chunks(RESTORE_CHUNK_SIZE) {
+                    let values = chunk
+                        .iter()
+                        .map(|tag| {
+                            (
+                                schema::tags::name.eq(&tag.name),
+                                schema::tags::file_id.eq(tag.file_id),
+                                schema::tags::namespace.eq(&tag.namespace),
+                            )
+                        })
+                        .collect::<Vec<_>>();
+                    diesel::insert_into(schema::tags::table)
+                        .values(values)
+                        .execute(db)
+                        .await?;
+                }
+
+                for chunk in backup.file_attributes.chunks(RESTORE_CHUNK_SIZE) {
+                    let values = chunk
+                        .iter()
+                        .map(|attribute| {
+                            (
+                                schema::file_attributes::file_id.eq(attribute.file_id),
+                                schema::file_attributes::key.eq(&attribute.key),
+                                schema::file_attributes::value.eq(&attribute.value),
+                            )
+                        })
+                        .collect::<Vec<_>>();
+                    diesel::insert_into(schema::file_attributes::table)
+                        .values(values)
+                        .execute(db)
+                        .await?;
+                }
+
+                for file in &backup.files {
+                    let blob =
+                        tokio::fs::File::open(blob_directory.join(file.id.to_string())).await?;
+                    self.file_driver
+                        .write_staging(file.id, 0, Box::pin(blob))
+                        .await?;
+                    self.file_driver.commit_staging(file.id).await?;
+                }
+
+                Ok(())
+            }
+            .scope_boxed()
+        })
+        .await?;
+
+        // ignore the error if the indexing fails, as it is not critical
+        for collection in &backup.collections {
+            self.search_service.index_collection(collection).await.ok();
+        }
+
+        let mut files = HashMap::with_capacity(backup.files.len());
+
+        for backup_file in &backup.files {
+            let file = File {
+                id: backup_file.id,
+                name: backup_file.name.clone(),
+                mime: backup_file.mime.clone(),
+                size: backup_file.size,
+                hash: backup_file.hash,
+                uploaded_at: backup_file.uploaded_at,
+            };
+            let metadata = backup_file
+                .file_metadata
+                .clone()
+                .and_then(|metadata| serde_json::from_value::<FileMetadata>(metadata).ok());
+
+            // ignore the error if the indexing fails, as it is not critical
+            self.search_service
+                .index_file(
+                    &file,
+                    metadata.as_ref(),
+                    backup_file.search_content.as_deref(),
+                )
+                .await
+                .ok();
+            files.insert(file.id, file);
+        }
+
+        for pair in &backup.collection_file_pairs {
+            if let Some(file) = files.get(&pair.file_id) {
+                // ignore the error if the indexing fails, as it is not critical
+                self.search_service
+                    .index_collection_file(pair.collection_id, file)
+                    .await
+                    .ok();
+            }
+        }
+
+        let mut tags_by_file = HashMap::<Uuid, Vec<Tag>>::new();
+
+        for tag in tags {
+            tags_by_file
+                .entry(tag.file_id)
+                .or_default()
+                .push(tag.clone());
+        }
+
+        for (file_id, tags) in &tags_by_file {
+            // ignore the error if the indexing fails, as it is not critical
+            self.search_service
+                .index_file_tags(*file_id, tags)
+                .await
+                .ok();
+        }
+
+        let mut attributes_by_file = HashMap::<Uuid, BTreeMap<String, String>>::new();
+
+        for attribute in &backup.file_attributes {
+            attributes_by_file
+                .entry(attribute.file_id)
+                .or_default()
+                .insert(attribute.key.clone(), attribute.value.clone());
+        }
+
+        for (file_id, attributes) in &attributes_by_file {
+            // ignore the error if the indexing fails, as it is not critical
+            self.search_service
+                .index_file_attributes(*file_id, attributes)
+                .await
+                .ok();
+        }
+
+        Ok(backup.summary())
+    }
+}
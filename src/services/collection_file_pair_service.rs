@@ -1,8 +1,15 @@
-use super::SearchService;
-use crate::db::models::{CollectionFilePair, CreatingCollectionFilePair, File};
-use diesel::{BoolExpressionMethods, ExpressionMethods, OptionalExtension, QueryDsl};
-use diesel_async::{pooled_connection::deadpool::Pool, AsyncPgConnection, RunQueryDsl};
-use std::sync::Arc;
+use super::{Page, SearchService, UnitOfWork, UnitOfWorkConnection};
+use crate::db::models::{Collection, CollectionFilePair, CreatingCollectionFilePair, File};
+use diesel::{
+    BoolExpressionMethods, ExpressionMethods, IntoSql, OptionalExtension, PgSortExpressionMethods,
+    QueryDsl,
+};
+use diesel_async::{
+    pooled_connection::deadpool::Pool, scoped_futures::ScopedFutureExt, AsyncConnection,
+    AsyncPgConnection, RunQueryDsl,
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashSet, sync::Arc};
 use thiserror::Error;
 use uuid::Uuid;
 
@@ -22,6 +29,8 @@ pub enum AddFileToCollectionError {
     InvalidCollection { collection_id: Uuid },
     #[error("file with ID `{file_id}` does not exist")]
     InvalidFile { file_id: Uuid },
+    #[error("collection with ID `{collection_id}` already has the maximum of {max_files} files")]
+    TooManyFiles { collection_id: Uuid, max_files: u64 },
     #[error("{0}")]
     Error(#[from] CollectionFilePairServiceError),
 }
@@ -36,32 +45,89 @@ pub enum RemoveFileFromCollectionError {
     Error(#[from] CollectionFilePairServiceError),
 }
 
+#[derive(Error, Debug)]
+pub enum SetFileOrderInCollectionError {
+    #[error("collection with ID `{collection_id}` does not exist")]
+    InvalidCollection { collection_id: Uuid },
+    #[error("some of the files are not in the collection: `{file_ids:?}`")]
+    InvalidFiles { file_ids: Vec<Uuid> },
+    #[error("file with ID `{file_id}` is listed more than once")]
+    DuplicateFile { file_id: Uuid },
+    #[error("{0}")]
+    Error(#[from] CollectionFilePairServiceError),
+}
+
+impl From<diesel::result::Error> for SetFileOrderInCollectionError {
+    fn from(err: diesel::result::Error) -> Self {
+        CollectionFilePairServiceError::from(err).into()
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum MergeCollectionsError {
+    #[error("collection with ID `{collection_id}` cannot be merged into itself")]
+    SameCollection { collection_id: Uuid },
+    #[error("collection with ID `{collection_id}` does not exist")]
+    InvalidCollection { collection_id: Uuid },
+    #[error("collection with ID `{collection_id}` would exceed the maximum of {max_files} files")]
+    TooManyFiles { collection_id: Uuid, max_files: u64 },
+    #[error("{0}")]
+    Error(#[from] CollectionFilePairServiceError),
+}
+
+impl From<diesel::result::Error> for MergeCollectionsError {
+    fn from(err: diesel::result::Error) -> Self {
+        CollectionFilePairServiceError::from(err).into()
+    }
+}
+
+/// The outcome of merging a collection into another.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CollectionMerge {
+    /// The number of files that have been moved into the target collection.
+    pub moved_count: usize,
+    /// The number of files that were already in the target collection.
+    pub skipped_count: usize,
+    /// Whether the source collection has been removed.
+    pub source_removed: bool,
+}
+
 pub struct CollectionFilePairService {
     db_pool: Pool<AsyncPgConnection>,
-    search_service: Arc<SearchService>,
+    search_service: Arc<dyn SearchService + Send + Sync>,
+    /// The maximum number of files in a collection, or `0` for no limit.
+    max_files: u64,
 }
 
 impl CollectionFilePairService {
-    pub fn new(db_pool: Pool<AsyncPgConnection>, search_service: Arc<SearchService>) -> Arc<Self> {
+    pub fn new(
+        db_pool: Pool<AsyncPgConnection>,
+        search_service: Arc<dyn SearchService + Send + Sync>,
+        max_files: u64,
+    ) -> Arc<Self> {
         Arc::new(Self {
             db_pool,
             search_service,
+            max_files,
         })
     }
 
     /// Adds a file to a collection.
+    /// It fails with `TooManyFiles` if the collection already has the maximum number of files.
+    /// The count is checked before the insertion, so concurrent additions may overshoot the limit slightly.
+    /// If `uow` is given, the file is added in it and indexed once it commits.
     pub async fn add_file_to_collection(
         &self,
         collection_id: Uuid,
         file_id: Uuid,
+        mut uow: Option<&mut UnitOfWork>,
     ) -> Result<CollectionFilePair, AddFileToCollectionError> {
         use crate::db::schema;
 
-        let db = &mut self
-            .db_pool
-            .get()
+        let mut db = UnitOfWorkConnection::acquire(&self.db_pool, uow.as_mut().map(|uow| uow.db()))
             .await
             .map_err(CollectionFilePairServiceError::from)?;
+        let db = &mut *db;
 
         let file = schema::files::dsl::files
             .select((
@@ -83,6 +149,22 @@ impl CollectionFilePairService {
             None => return Err(AddFileToCollectionError::InvalidFile { file_id }),
         };
 
+        if self.max_files != 0 {
+            let count = schema::collection_file_pairs::dsl::collection_file_pairs
+                .filter(schema::collection_file_pairs::collection_id.eq(collection_id))
+                .count()
+                .get_result::<i64>(db)
+                .await
+                .map_err(CollectionFilePairServiceError::from)?;
+
+            if self.max_files <= count as u64 {
+                return Err(AddFileToCollectionError::TooManyFiles {
+                    collection_id,
+                    max_files: self.max_files,
+                });
+            }
+        }
+
         let pair = diesel::insert_into(schema::collection_file_pairs::table)
             .values(CreatingCollectionFilePair {
                 collection_id,
@@ -91,6 +173,7 @@ impl CollectionFilePairService {
             .returning((
                 schema::collection_file_pairs::collection_id,
                 schema::collection_file_pairs::file_id,
+                schema::collection_file_pairs::position,
             ))
             .get_result::<CollectionFilePair>(db)
             .await;
@@ -121,11 +204,19 @@ impl CollectionFilePairService {
             Err(err) => return Err(CollectionFilePairServiceError::from(err).into()),
         };
 
-        // ignore the error if the indexing fails, as it is not critical
-        self.search_service
-            .index_collection_file(collection_id, &file)
-            .await
-            .ok();
+        let search_service = self.search_service.clone();
+        let indexing = async move {
+            // ignore the error if the indexing fails, as it is not critical
+            search_service
+                .index_collection_file(collection_id, &file)
+                .await
+                .ok();
+        };
+
+        match uow {
+            Some(uow) => uow.after_commit(indexing),
+            None => indexing.await,
+        }
 
         Ok(pair)
     }
@@ -155,6 +246,7 @@ impl CollectionFilePairService {
         .returning((
             schema::collection_file_pairs::collection_id,
             schema::collection_file_pairs::file_id,
+            schema::collection_file_pairs::position,
         ))
         .get_result::<CollectionFilePair>(db)
         .await
@@ -188,8 +280,250 @@ impl CollectionFilePairService {
         Ok(pair)
     }
 
+    /// Sets the manual order of files in a collection.
+    /// `file_ids` lists the files of the collection in their new order, and every listed file must be in the collection.
+    /// Files that are not listed lose their position and come after the ordered ones, sorted by name.
+    /// Passing an empty list clears the manual order.
+    /// Returns the number of files that have been positioned.
+    pub async fn set_file_order_in_collection(
+        &self,
+        collection_id: Uuid,
+        file_ids: &[Uuid],
+    ) -> Result<usize, SetFileOrderInCollectionError> {
+        use crate::db::schema;
+
+        let mut seen_file_ids = HashSet::with_capacity(file_ids.len());
+
+        for &file_id in file_ids {
+            if !seen_file_ids.insert(file_id) {
+                return Err(SetFileOrderInCollectionError::DuplicateFile { file_id });
+            }
+        }
+
+        let db = &mut self
+            .db_pool
+            .get()
+            .await
+            .map_err(CollectionFilePairServiceError::from)?;
+
+        db.transaction(|db| {
+            async move {
+                // lock the collection, so that concurrent reorders are serialized
+                let collection = schema::collections::dsl::collections
+                    .filter(schema::collections::id.eq(collection_id))
+                    .select(schema::collections::id)
+                    .for_update()
+                    .get_result::<Uuid>(db)
+                    .await
+                    .optional()?;
+
+                if collection.is_none() {
+                    return Err(SetFileOrderInCollectionError::InvalidCollection { collection_id });
+                }
+
+                let contained_file_ids = schema::collection_file_pairs::dsl::collection_file_pairs
+                    .filter(
+                        schema::collection_file_pairs::collection_id
+                            .eq(collection_id)
+                            .and(schema::collection_file_pairs::file_id.eq_any(file_ids)),
+                    )
+                    .select(schema::collection_file_pairs::file_id)
+                    .load::<Uuid>(db)
+                    .await?;
+
+                if contained_file_ids.len() != file_ids.len() {
+                    let contained_file_ids = contained_file_ids.into_iter().collect::<HashSet<_>>();
+                    let file_ids = file_ids
+                        .iter()
+                        .filter(|file_id| !contained_file_ids.contains(file_id))
+                        .copied()
+                        .collect();
+                    return Err(SetFileOrderInCollectionError::InvalidFiles { file_ids });
+                }
+
+                diesel::update(
+                    schema::collection_file_pairs::dsl::collection_file_pairs
+                        .filter(schema::collection_file_pairs::collection_id.eq(collection_id)),
+                )
+                .set(schema::collection_file_pairs::position.eq(None::<i32>))
+                .execute(db)
+                .await?;
+
+                for (position, &file_id) in file_ids.iter().enumerate() {
+                    diesel::update(
+                        schema::collection_file_pairs::dsl::collection_file_pairs.filter(
+                            schema::collection_file_pairs::collection_id
+                                .eq(collection_id)
+                                .and(schema::collection_file_pairs::file_id.eq(file_id)),
+                        ),
+                    )
+                    .set(schema::collection_file_pairs::position.eq(position as i32))
+                    .execute(db)
+                    .await?;
+                }
+
+                Ok(file_ids.len())
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+
+    /// Moves every file of the source collection into the target collection, all in one transaction.
+    /// Files already in the target collection are skipped, and moved files lose their manual position.
+    /// If `remove_source` is set, the source collection is removed afterwards.
+    /// It fails with `TooManyFiles` if the target collection would exceed the maximum number of files.
+    pub async fn merge_collections(
+        &self,
+        target_collection_id: Uuid,
+        source_collection_id: Uuid,
+        remove_source: bool,
+    ) -> Result<CollectionMerge, MergeCollectionsError> {
+        use crate::db::schema;
+
+        if target_collection_id == source_collection_id {
+            return Err(MergeCollectionsError::SameCollection {
+                collection_id: target_collection_id,
+            });
+        }
+
+        let db = &mut self
+            .db_pool
+            .get()
+            .await
+            .map_err(CollectionFilePairServiceError::from)?;
+
+        let (merge, moved_files, source_file_ids) = db
+            .transaction(|db| {
+                async move {
+                    // lock both collections in the order of their IDs, so that opposite merges cannot deadlock
+                    let locked_collection_ids = schema::collections::dsl::collections
+                        .filter(
+                            schema::collections::id
+                                .eq_any([target_collection_id, source_collection_id]),
+                        )
+                        .select(schema::collections::id)
+                        .order_by(schema::collections::id)
+                        .for_update()
+                        .load::<Uuid>(db)
+                        .await?;
+
+                    for collection_id in [target_collection_id, source_collection_id] {
+                        if !locked_collection_ids.contains(&collection_id) {
+                            return Err(MergeCollectionsError::InvalidCollection { collection_id });
+                        }
+                    }
+
+                    let moved_file_ids = diesel::insert_into(schema::collection_file_pairs::table)
+                        .values(
+                            schema::collection_file_pairs::table
+                                .filter(
+                                    schema::collection_file_pairs::collection_id
+                                        .eq(source_collection_id),
+                                )
+                                .select((
+                                    target_collection_id.into_sql::<diesel::sql_types::Uuid>(),
+                                    schema::collection_file_pairs::file_id,
+                                )),
+                        )
+                        .into_columns((
+                            schema::collection_file_pairs::collection_id,
+                            schema::collection_file_pairs::file_id,
+                        ))
+                        .on_conflict_do_nothing()
+                        .returning(schema::collection_file_pairs::file_id)
+                        .get_results::<Uuid>(db)
+                        .await?;
+
+                    if self.max_files != 0 && !moved_file_ids.is_empty() {
+                        let count = schema::collection_file_pairs::dsl::collection_file_pairs
+                            .filter(
+                                schema::collection_file_pairs::collection_id
+                                    .eq(target_collection_id),
+                            )
+                            .count()
+                            .get_result::<i64>(db)
+                            .await?;
+
+                        if self.max_files < count as u64 {
+                            return Err(MergeCollectionsError::TooManyFiles {
+                                collection_id: target_collection_id,
+                                max_files: self.max_files,
+                            });
+                        }
+                    }
+
+                    let source_file_ids = diesel::delete(
+                        schema::collection_file_pairs::dsl::collection_file_pairs.filter(
+                            schema::collection_file_pairs::collection_id.eq(source_collection_id),
+                        ),
+                    )
+                    .returning(schema::collection_file_pairs::file_id)
+                    .get_results::<Uuid>(db)
+                    .await?;
+
+                    if remove_source {
+                        diesel::delete(
+                            schema::collections::dsl::collections
+                                .filter(schema::collections::id.eq(source_collection_id)),
+                        )
+                        .execute(db)
+                        .await?;
+                    }
+
+                    let moved_files = schema::files::dsl::files
+                        .select((
+                            schema::files::id,
+                            schema::files::name,
+                            schema::files::mime,
+                            schema::files::size,
+                            schema::files::hash,
+                            schema::files::uploaded_at,
+                        ))
+                        .filter(schema::files::id.eq_any(&moved_file_ids))
+                        .load::<File>(db)
+                        .await?;
+
+                    let merge = CollectionMerge {
+                        moved_count: moved_file_ids.len(),
+                        skipped_count: source_file_ids.len() - moved_file_ids.len(),
+                        source_removed: remove_source,
+                    };
+
+                    Ok((merge, moved_files, source_file_ids))
+                }
+                .scope_boxed()
+            })
+            .await?;
+
+        // ignore the errors if the indexing fails, as it is not critical
+        for file in &moved_files {
+            self.search_service
+                .index_collection_file(target_collection_id, file)
+                .await
+                .ok();
+        }
+
+        if remove_source {
+            self.search_service
+                .remove_collection_by_id(source_collection_id)
+                .await
+                .ok();
+        } else {
+            for &file_id in &source_file_ids {
+                self.search_service
+                    .remove_collection_file(source_collection_id, file_id)
+                    .await
+                    .ok();
+            }
+        }
+
+        Ok(merge)
+    }
+
     /// Retrieves a list of files in a collection.
-    /// The result will be sorted by name and ID (name first) in ascending order.
+    /// Files with a manual position come first, sorted by their position in ascending order.
+    /// The rest will be sorted by name and ID (name first) in ascending order.
     /// If `last_file_id` is provided, the result will start from the file that comes after it.
     pub async fn get_files_in_collection(
         &self,
@@ -212,25 +546,33 @@ impl CollectionFilePairService {
                 schema::files::hash,
                 schema::files::uploaded_at,
             ))
-            .order((schema::files::name.asc(), schema::files::id.asc()))
+            .order((
+                schema::collection_file_pairs::position.asc().nulls_last(),
+                schema::files::name.asc(),
+                schema::files::id.asc(),
+            ))
             .limit(limit as i64);
 
         let last_file = match last_file_id {
             Some(last_file_id) => {
                 let last_file = schema::collection_file_pairs::table
                     .inner_join(schema::files::table)
-                    .select((schema::files::name, schema::files::id))
+                    .select((
+                        schema::collection_file_pairs::position,
+                        schema::files::name,
+                        schema::files::id,
+                    ))
                     .filter(
                         schema::collection_file_pairs::collection_id
                             .eq(collection_id)
                             .and(schema::files::id.eq(last_file_id)),
                     )
-                    .get_result::<(String, Uuid)>(db)
+                    .get_result::<(Option<i32>, String, Uuid)>(db)
                     .await
                     .optional()?;
 
                 let last_file = match last_file {
-                    Some(pair) => pair,
+                    Some(triple) => triple,
                     None => return Ok(Vec::new()),
                 };
 
@@ -240,13 +582,31 @@ impl CollectionFilePairService {
         };
 
         let files = match &last_file {
-            Some((last_file_name, last_file_id)) => query
+            Some((Some(last_file_position), last_file_name, last_file_id)) => query
                 .filter(
-                    schema::files::name
-                        .gt(last_file_name)
-                        .or(schema::files::name
-                            .eq(last_file_name)
-                            .and(schema::files::id.gt(last_file_id))),
+                    schema::collection_file_pairs::position
+                        .gt(last_file_position)
+                        .or(schema::collection_file_pairs::position.is_null())
+                        .or(schema::collection_file_pairs::position
+                            .eq(last_file_position)
+                            .and(
+                                schema::files::name
+                                    .gt(last_file_name)
+                                    .or(schema::files::name
+                                        .eq(last_file_name)
+                                        .and(schema::files::id.gt(last_file_id))),
+                            )),
+                )
+                .load::<File>(db),
+            Some((None, last_file_name, last_file_id)) => query
+                .filter(
+                    schema::collection_file_pairs::position.is_null().and(
+                        schema::files::name
+                            .gt(last_file_name)
+                            .or(schema::files::name
+                                .eq(last_file_name)
+                                .and(schema::files::id.gt(last_file_id))),
+                    ),
                 )
                 .load::<File>(db),
             None => query.load::<File>(db),
@@ -256,6 +616,78 @@ impl CollectionFilePairService {
         Ok(files)
     }
 
+    /// Retrieves a list of collections the file belongs to.
+    /// The result will be sorted by name and ID (name first) in ascending order.
+    /// If `last_collection_id` is provided, the result will start from the collection that comes after it.
+    pub async fn get_collections_for_file(
+        &self,
+        file_id: Uuid,
+        last_collection_id: Option<Uuid>,
+        limit: u32,
+    ) -> Result<Page<Collection, Uuid>, CollectionFilePairServiceError> {
+        use crate::db::schema;
+
+        let db = &mut self.db_pool.get().await?;
+
+        let query = schema::collection_file_pairs::table
+            .inner_join(schema::collections::table)
+            .filter(schema::collection_file_pairs::file_id.eq(file_id))
+            .select((
+                schema::collections::id,
+                schema::collections::name,
+                schema::collections::description,
+                schema::collections::created_at,
+                schema::collections::version,
+            ))
+            .order((
+                schema::collections::name.asc(),
+                schema::collections::id.asc(),
+            ))
+            // fetch one more collection to tell whether there is a next page
+            .limit(limit as i64 + 1);
+
+        let last_collection = match last_collection_id {
+            Some(last_collection_id) => {
+                let last_collection = schema::collections::table
+                    .select((schema::collections::name, schema::collections::id))
+                    .filter(schema::collections::id.eq(last_collection_id))
+                    .get_result::<(String, Uuid)>(db)
+                    .await
+                    .optional()?;
+
+                let last_collection = match last_collection {
+                    Some(pair) => pair,
+                    None => {
+                        return Ok(Page::new(Vec::new(), limit, None, |collection| {
+                            collection.id
+                        }))
+                    }
+                };
+
+                Some(last_collection)
+            }
+            None => None,
+        };
+
+        let collections = match &last_collection {
+            Some((last_collection_name, last_collection_id)) => query
+                .filter(
+                    schema::collections::name.gt(last_collection_name).or(
+                        schema::collections::name
+                            .eq(last_collection_name)
+                            .and(schema::collections::id.gt(last_collection_id)),
+                    ),
+                )
+                .load::<Collection>(db),
+            None => query.load::<Collection>(db),
+        };
+        let collections = collections.await?;
+
+        Ok(Page::new(collections, limit, None, |collection| {
+            collection.id
+        }))
+    }
+
     /// Retrieves a file by its ID.
     pub async fn get_file_in_collection_by_id(
         &self,
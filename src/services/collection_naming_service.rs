@@ -0,0 +1,500 @@
+use chrono::{Datelike, NaiveDate};
+use diesel::{BoolExpressionMethods, ExpressionMethods, QueryDsl, TextExpressionMethods};
+use diesel_async::{pooled_connection::deadpool::Pool, AsyncPgConnection, RunQueryDsl};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fmt::Display, sync::Arc};
+use thiserror::Error;
+use uuid::Uuid;
+
+#[derive(Error, Debug)]
+pub enum CollectionNamingServiceError {
+    #[error("database pool error: {0}")]
+    Pool(#[from] diesel_async::pooled_connection::deadpool::PoolError),
+    #[error("diesel error: {0}")]
+    Diesel(#[from] diesel::result::Error),
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum CollectionNameTemplateError {
+    #[error("placeholder opened at {position} is not closed")]
+    UnclosedPlaceholder { position: usize },
+    #[error("unknown placeholder `{name}`; expected one of year, month, day, date, folder, path")]
+    UnknownPlaceholder { name: String },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TemplateVariable {
+    Year,
+    Month,
+    Day,
+    Date,
+    Folder,
+    Path,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum TemplateSegment {
+    Literal(String),
+    Variable(TemplateVariable),
+}
+
+/// A template for naming the collections created by imports, e.g. `{{year}}/{{month}} Imports`.
+///
+/// The following placeholders are available:
+/// - `{{year}}`: the four-digit year of the batch
+/// - `{{month}}`: the two-digit month of the batch
+/// - `{{day}}`: the two-digit day of the batch
+/// - `{{date}}`: the date of the batch in `YYYY-MM-DD` form
+/// - `{{folder}}`: the name of the folder the batch comes from
+/// - `{{path}}`: the relative path of the folder the batch comes from
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(try_from = "String", into = "String")]
+pub struct CollectionNameTemplate {
+    source: String,
+    segments: Vec<TemplateSegment>,
+}
+
+impl CollectionNameTemplate {
+    pub fn parse(source: impl Into<String>) -> Result<Self, CollectionNameTemplateError> {
+        let source = source.into();
+        let mut segments = Vec::new();
+        let mut rest = source.as_str();
+
+        while let Some(start) = rest.find("{{") {
+            if 0 < start {
+                segments.push(TemplateSegment::Literal(rest[..start].to_owned()));
+            }
+
+            let end = match rest[start..].find("}}") {
+                Some(end) => start + end,
+                None => {
+                    return Err(CollectionNameTemplateError::UnclosedPlaceholder {
+                        position: source.len() - rest.len() + start,
+                    });
+                }
+            };
+
+            let name = rest[start + 2..end].trim();
+            let variable = match name {
+                "year" => TemplateVariable::Year,
+                "month" => TemplateVariable::Month,
+                "day" => TemplateVariable::Day,
+                "date" => TemplateVariable::Date,
+                "folder" => TemplateVariable::Folder,
+                "path" => TemplateVariable::Path,
+                _ => {
+                    return Err(CollectionNameTemplateError::UnknownPlaceholder {
+                        name: name.to_owned(),
+                    });
+                }
+            };
+
+            segments.push(TemplateSegment::Variable(variable));
+            rest = &rest[end + 2..];
+        }
+
+        if !rest.is_empty() {
+            segments.push(TemplateSegment::Literal(rest.to_owned()));
+        }
+
+        Ok(Self { source, segments })
+    }
+
+    /// Renders the template for the given batch.
+    /// Falls back to the date of the batch if the rendered name is blank.
+    pub fn render(&self, batch: &ImportBatch) -> String {
+        let mut name = String::new();
+
+        for segment in &self.segments {
+            match segment {
+                TemplateSegment::Literal(literal) => name.push_str(literal),
+                TemplateSegment::Variable(variable) => match variable {
+                    TemplateVariable::Year => name.push_str(&format!("{:04}", batch.date.year())),
+                    TemplateVariable::Month => name.push_str(&format!("{:02}", batch.date.month())),
+                    TemplateVariable::Day => name.push_str(&format!("{:02}", batch.date.day())),
+                    TemplateVariable::Date => {
+                        name.push_str(&batch.date.format("%Y-%m-%d").to_string())
+                    }
+                    TemplateVariable::Folder => {
+                        if let Some(path) = &batch.path {
+                            name.push_str(path.rsplit('/').next().unwrap_or(path));
+                        }
+                    }
+                    TemplateVariable::Path => {
+                        if let Some(path) = &batch.path {
+                            name.push_str(path);
+                        }
+                    }
+                },
+            }
+        }
+
+        let name = name.split_whitespace().collect::<Vec<_>>().join(" ");
+
+        if name.is_empty() {
+            batch.date.format("%Y-%m-%d").to_string()
+        } else {
+            name
+        }
+    }
+}
+
+impl Display for CollectionNameTemplate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl TryFrom<String> for CollectionNameTemplate {
+    type Error = CollectionNameTemplateError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Self::parse(value)
+    }
+}
+
+impl From<CollectionNameTemplate> for String {
+    fn from(value: CollectionNameTemplate) -> Self {
+        value.source
+    }
+}
+
+/// How to name a collection whose rendered name is already taken by an existing collection.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum CollectionNameCollision {
+    /// Add the files to the existing collection.
+    Reuse,
+    /// Create a new collection with a ` (2)`, ` (3)`, ... suffix.
+    Suffix,
+}
+
+/// A group of files imported together, from which a collection is created.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportBatch {
+    /// The relative path of the source folder, separated by `/`, if the batch comes from a folder.
+    pub path: Option<String>,
+    /// The date the batch belongs to.
+    pub date: NaiveDate,
+}
+
+/// A collection an import would put files in.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct PlannedCollection {
+    pub name: String,
+    /// The existing collection to reuse, or `None` if the collection has to be created.
+    pub existing_collection_id: Option<Uuid>,
+    /// The indices of the batches that go into this collection.
+    pub batches: Vec<usize>,
+}
+
+pub struct CollectionNamingService {
+    db_pool: Pool<AsyncPgConnection>,
+    template: CollectionNameTemplate,
+    collision: CollectionNameCollision,
+}
+
+impl CollectionNamingService {
+    pub fn new(
+        db_pool: Pool<AsyncPgConnection>,
+        template: CollectionNameTemplate,
+        collision: CollectionNameCollision,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            db_pool,
+            template,
+            collision,
+        })
+    }
+
+    pub fn template(&self) -> &CollectionNameTemplate {
+        &self.template
+    }
+
+    /// Plans the collections for the given batches without creating anything.
+    /// Batches rendering the same name share a collection. Names taken by existing collections
+    /// are handled according to the configured collision policy.
+    /// The result is in the order the names first appear in the batches.
+    pub async fn plan_collections(
+        &self,
+        batches: &[ImportBatch],
+    ) -> Result<Vec<PlannedCollection>, CollectionNamingServiceError> {
+        use crate::db::schema;
+
+        let mut plans = Vec::<PlannedCollection>::new();
+        let mut plan_indices = HashMap::<String, usize>::new();
+
+        for (index, batch) in batches.iter().enumerate() {
+            let name = self.template.render(batch);
+
+            match plan_indices.get(&name) {
+                Some(&plan_index) => plans[plan_index].batches.push(index),
+                None => {
+                    plan_indices.insert(name.clone(), plans.len());
+                    plans.push(PlannedCollection {
+                        name,
+                        existing_collection_id: None,
+                        batches: vec![index],
+                    });
+                }
+            }
+        }
+
+        let db = &mut self.db_pool.get().await?;
+
+        for (plan_index, plan) in plans.iter_mut().enumerate() {
+            let name = plan.name.clone();
+            let pattern = format!("{} (%)", escape_like_pattern(&name));
+
+            let existing = schema::collections::table
+                .select((schema::collections::id, schema::collections::name))
+                .filter(
+                    schema::collections::name
+                        .eq(&name)
+                        .or(schema::collections::name.like(&pattern)),
+                )
+                .order((
+                    schema::collections::created_at.asc(),
+                    schema::collections::id.asc(),
+                ))
+                .load::<(Uuid, String)>(db)
+                .await?;
+
+            let exact = existing
+                .iter()
+                .find(|(_, existing_name)| existing_name == &name);
+            let exact = match exact {
+                Some((id, _)) => *id,
+                None => continue,
+            };
+
+            match self.collision {
+                CollectionNameCollision::Reuse => {
+                    plan.existing_collection_id = Some(exact);
+                }
+                CollectionNameCollision::Suffix => {
+                    let is_taken = |candidate: &str| {
+                        existing
+                            .iter()
+                            .any(|(_, existing_name)| existing_name == candidate)
+                            || plan_indices.contains_key(candidate)
+                    };
+
+                    let mut suffix = 2;
+                    let mut candidate = format!("{} ({})", name, suffix);
+
+                    while is_taken(&candidate) {
+                        suffix += 1;
+                        candidate = format!("{} ({})", name, suffix);
+                    }
+
+                    plan_indices.insert(candidate.clone(), plan_index);
+                    plan.name = candidate;
+                }
+            }
+        }
+
+        Ok(plans)
+    }
+}
+
+fn escape_like_pattern(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+
+    for c in value.chars() {
+        if matches!(c, '\\' | '%' | '_') {
+            escaped.push('\\');
+        }
+
+        escaped.push(c);
+    }
+
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::SearchBackend,
+        services::CollectionService,
+        test::{create_test_rocket_instance_with_config, TestFileDriver},
+    };
+
+    fn batch(path: Option<&str>) -> ImportBatch {
+        ImportBatch {
+            path: path.map(|path| path.to_owned()),
+            date: NaiveDate::from_ymd_opt(2024, 3, 9).unwrap(),
+        }
+    }
+
+    async fn plan_collections(
+        collision: CollectionNameCollision,
+        existing_names: &[&str],
+        batches: &[ImportBatch],
+    ) -> (Vec<PlannedCollection>, Vec<Uuid>) {
+        let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance_with_config(
+            TestFileDriver::Memory,
+            SearchBackend::Postgres,
+            |app_config| {
+                app_config.import.collection_name_template =
+                    CollectionNameTemplate::parse("{{folder}}").unwrap();
+                app_config.import.collection_name_collision = collision;
+            },
+        )
+        .await;
+        let collection_service = rocket.state::<Arc<CollectionService>>().unwrap();
+        let collection_naming_service = rocket.state::<Arc<CollectionNamingService>>().unwrap();
+
+        let mut existing_ids = Vec::new();
+
+        for name in existing_names {
+            let collection = collection_service
+                .create_collection(name, None, true, None)
+                .await
+                .unwrap();
+            existing_ids.push(collection.id);
+        }
+
+        let plans = collection_naming_service
+            .plan_collections(batches)
+            .await
+            .unwrap();
+
+        // close the connections before the database is dropped
+        drop(rocket);
+
+        (plans, existing_ids)
+    }
+
+    #[test]
+    fn test_parse_template() {
+        let template = CollectionNameTemplate::parse("{{year}}/{{ month }} {{folder}}").unwrap();
+        assert_eq!(template.render(&batch(Some("a/Photos"))), "2024/03 Photos");
+        assert_eq!(template.to_string(), "{{year}}/{{ month }} {{folder}}");
+
+        let template = CollectionNameTemplate::parse("{{date}} {{path}}").unwrap();
+        assert_eq!(
+            template.render(&batch(Some("a/Photos"))),
+            "2024-03-09 a/Photos"
+        );
+
+        // a blank name falls back to the date
+        let template = CollectionNameTemplate::parse("  {{folder}}  ").unwrap();
+        assert_eq!(template.render(&batch(None)), "2024-03-09");
+    }
+
+    #[test]
+    fn test_parse_bad_template() {
+        assert_eq!(
+            CollectionNameTemplate::parse("{{year}}/{{month"),
+            Err(CollectionNameTemplateError::UnclosedPlaceholder { position: 9 })
+        );
+        assert_eq!(
+            CollectionNameTemplate::parse("Imports {{"),
+            Err(CollectionNameTemplateError::UnclosedPlaceholder { position: 8 })
+        );
+        assert_eq!(
+            CollectionNameTemplate::parse("{{year}} {{week}}"),
+            Err(CollectionNameTemplateError::UnknownPlaceholder {
+                name: "week".to_owned()
+            })
+        );
+        assert_eq!(
+            CollectionNameTemplate::parse("{{}}"),
+            Err(CollectionNameTemplateError::UnknownPlaceholder {
+                name: String::new()
+            })
+        );
+        assert!(serde_json::from_str::<CollectionNameTemplate>("\"{{folder\"").is_err());
+    }
+
+    #[test]
+    fn test_escape_like_pattern() {
+        assert_eq!(escape_like_pattern("Photos"), "Photos");
+        assert_eq!(escape_like_pattern("100% _done"), "100\\% \\_done");
+        assert_eq!(escape_like_pattern("a\\b"), "a\\\\b");
+    }
+
+    #[rocket::async_test]
+    async fn test_plan_collections_reuse() {
+        let (plans, existing_ids) = plan_collections(
+            CollectionNameCollision::Reuse,
+            &["Photos"],
+            &[
+                batch(Some("a/Photos")),
+                batch(Some("New")),
+                batch(Some("b/Photos")),
+            ],
+        )
+        .await;
+
+        assert_eq!(
+            plans,
+            vec![
+                PlannedCollection {
+                    name: "Photos".to_owned(),
+                    existing_collection_id: Some(existing_ids[0]),
+                    batches: vec![0, 2],
+                },
+                PlannedCollection {
+                    name: "New".to_owned(),
+                    existing_collection_id: None,
+                    batches: vec![1],
+                },
+            ]
+        );
+    }
+
+    #[rocket::async_test]
+    async fn test_plan_collections_suffix() {
+        // `Photos (3)` is rendered by another batch, so it is skipped as well
+        let (plans, _existing_ids) = plan_collections(
+            CollectionNameCollision::Suffix,
+            &["Photos", "Photos (2)"],
+            &[batch(Some("a/Photos")), batch(Some("Photos (3)"))],
+        )
+        .await;
+
+        assert_eq!(
+            plans,
+            vec![
+                PlannedCollection {
+                    name: "Photos (4)".to_owned(),
+                    existing_collection_id: None,
+                    batches: vec![0],
+                },
+                PlannedCollection {
+                    name: "Photos (3)".to_owned(),
+                    existing_collection_id: None,
+                    batches: vec![1],
+                },
+            ]
+        );
+    }
+
+    #[rocket::async_test]
+    async fn test_plan_collections_like_wildcards() {
+        // `%` and `_` in the name must not match other names as wildcards
+        let (plans, existing_ids) = plan_collections(
+            CollectionNameCollision::Reuse,
+            &["100x Xdone", "100% _done"],
+            &[batch(Some("100% _done"))],
+        )
+        .await;
+
+        assert_eq!(plans.len(), 1);
+        assert_eq!(plans[0].existing_collection_id, Some(existing_ids[1]));
+
+        let (plans, _existing_ids) = plan_collections(
+            CollectionNameCollision::Suffix,
+            &["100% _done", "100% _done (2)", "100x Xdone (3)"],
+            &[batch(Some("100% _done"))],
+        )
+        .await;
+
+        assert_eq!(plans.len(), 1);
+        assert_eq!(plans[0].name, "100% _done (3)");
+    }
+}
@@ -1,7 +1,16 @@
-use super::SearchService;
-use crate::db::models::{Collection, CreatingCollection, UpdatingCollection};
+use super::{IdService, ListOrder, Page, SearchService, UnitOfWork, UnitOfWorkConnection};
+use crate::{
+    config::AppCollectionLimits,
+    db::{
+        models::{Collection, CreatingCollection, UpdatingCollection},
+        ReadPool,
+    },
+};
 use diesel::{BoolExpressionMethods, ExpressionMethods, OptionalExtension, QueryDsl};
-use diesel_async::{pooled_connection::deadpool::Pool, AsyncPgConnection, RunQueryDsl};
+use diesel_async::{
+    pooled_connection::deadpool::Pool, scoped_futures::ScopedFutureExt, AsyncConnection,
+    AsyncPgConnection, RunQueryDsl,
+};
 use std::sync::Arc;
 use thiserror::Error;
 use uuid::Uuid;
@@ -12,43 +21,168 @@ pub enum CollectionServiceError {
     Pool(#[from] diesel_async::pooled_connection::deadpool::PoolError),
     #[error("diesel error: {0}")]
     Diesel(#[from] diesel::result::Error),
+    #[error("a collection named `{0}` already exists")]
+    NameTaken(String),
+    #[error("the collection has been modified; expected version {expected}, but it is {current}")]
+    VersionMismatch { expected: i32, current: i32 },
+    #[error("the name should be at most {max_length} characters long")]
+    NameTooLong { max_length: usize },
+    #[error("the description should be at most {max_length} characters long")]
+    DescriptionTooLong { max_length: usize },
 }
 
 pub struct CollectionService {
     db_pool: Pool<AsyncPgConnection>,
-    search_service: Arc<SearchService>,
+    /// The pool for listing collections, which may be served by a read replica.
+    read_pool: ReadPool,
+    id_service: Arc<IdService>,
+    search_service: Arc<dyn SearchService + Send + Sync>,
+    unique_names: bool,
+    max_name_length: usize,
+    max_description_length: usize,
 }
 
 impl CollectionService {
-    pub fn new(db_pool: Pool<AsyncPgConnection>, search_service: Arc<SearchService>) -> Arc<Self> {
+    pub fn new(
+        db_pool: Pool<AsyncPgConnection>,
+        read_pool: ReadPool,
+        id_service: Arc<IdService>,
+        search_service: Arc<dyn SearchService + Send + Sync>,
+        unique_names: bool,
+        limits: &AppCollectionLimits,
+    ) -> Arc<Self> {
         Arc::new(Self {
             db_pool,
+            read_pool,
+            id_service,
             search_service,
+            unique_names,
+            max_name_length: limits.max_name_length,
+            max_description_length: limits.max_description_length,
         })
     }
 
+    /// Fails with `NameTooLong` or `DescriptionTooLong` if the name or the description exceeds the configured limits.
+    fn check_lengths(
+        &self,
+        name: &str,
+        description: Option<&str>,
+    ) -> Result<(), CollectionServiceError> {
+        if self.max_name_length < name.chars().count() {
+            return Err(CollectionServiceError::NameTooLong {
+                max_length: self.max_name_length,
+            });
+        }
+
+        if description
+            .is_some_and(|description| self.max_description_length < description.chars().count())
+        {
+            return Err(CollectionServiceError::DescriptionTooLong {
+                max_length: self.max_description_length,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Fails with `NameTaken` if names are unique and a collection other than `except_collection_id` has the name.
+    /// It must be called in a transaction, which holds a lock on the name until it ends,
+    /// so that concurrent requests cannot take the same name in the meantime.
+    async fn check_name_available(
+        &self,
+        db: &mut AsyncPgConnection,
+        name: &str,
+        except_collection_id: Option<Uuid>,
+    ) -> Result<(), CollectionServiceError> {
+        use crate::db::schema;
+
+        diesel::sql_query("SELECT pg_advisory_xact_lock(hashtext($1))")
+            .bind::<diesel::sql_types::Text, _>(name)
+            .execute(db)
+            .await?;
+
+        let mut query = schema::collections::table
+            .filter(schema::collections::name.eq(name))
+            .into_boxed();
+
+        if let Some(except_collection_id) = except_collection_id {
+            query = query.filter(schema::collections::id.ne(except_collection_id));
+        }
+
+        let taken = diesel::select(diesel::dsl::exists(query))
+            .get_result::<bool>(db)
+            .await?;
+
+        if taken {
+            return Err(CollectionServiceError::NameTaken(name.to_owned()));
+        }
+
+        Ok(())
+    }
+
     /// Creates a new collection.
+    /// If collection names are unique, it fails with `NameTaken` when another collection has the name,
+    /// unless `allow_duplicate` is set.
+    /// It fails with `NameTooLong` or `DescriptionTooLong` if the name or the description exceeds the configured limits.
+    /// If `uow` is given, the collection is created in it and indexed once it commits.
     pub async fn create_collection(
         &self,
         name: &str,
         description: Option<&str>,
+        allow_duplicate: bool,
+        mut uow: Option<&mut UnitOfWork>,
     ) -> Result<Collection, CollectionServiceError> {
         use crate::db::schema;
 
-        let db = &mut self.db_pool.get().await?;
-        let collection = diesel::insert_into(schema::collections::table)
-            .values(CreatingCollection { name, description })
-            .returning((
-                schema::collections::id,
-                schema::collections::name,
-                schema::collections::description,
-                schema::collections::created_at,
-            ))
-            .get_result::<Collection>(db)
+        self.check_lengths(name, description)?;
+
+        let check_name = self.unique_names && !allow_duplicate;
+        let mut db =
+            UnitOfWorkConnection::acquire(&self.db_pool, uow.as_mut().map(|uow| uow.db())).await?;
+        let db = &mut *db;
+        let collection = db
+            .transaction(|db| {
+                async move {
+                    if check_name {
+                        self.check_name_available(db, name, None).await?;
+                    }
+
+                    let collection = diesel::insert_into(schema::collections::table)
+                        .values(CreatingCollection {
+                            id: self.id_service.generate(),
+                            name,
+                            description,
+                        })
+                        .returning((
+                            schema::collections::id,
+                            schema::collections::name,
+                            schema::collections::description,
+                            schema::collections::created_at,
+                            schema::collections::version,
+                        ))
+                        .get_result::<Collection>(db)
+                        .await?;
+
+                    Ok::<_, CollectionServiceError>(collection)
+                }
+                .scope_boxed()
+            })
             .await?;
 
-        // ignore the error if the indexing fails, as it is not critical
-        self.search_service.index_collection(&collection).await.ok();
+        let search_service = self.search_service.clone();
+        let indexed_collection = collection.clone();
+        let indexing = async move {
+            // ignore the error if the indexing fails, as it is not critical
+            search_service
+                .index_collection(&indexed_collection)
+                .await
+                .ok();
+        };
+
+        match uow {
+            Some(uow) => uow.after_commit(indexing),
+            None => indexing.await,
+        }
 
         Ok(collection)
     }
@@ -70,6 +204,7 @@ impl CollectionService {
             schema::collections::name,
             schema::collections::description,
             schema::collections::created_at,
+            schema::collections::version,
         ))
         .get_result::<Collection>(db)
         .await
@@ -89,15 +224,31 @@ impl CollectionService {
     }
 
     /// Retrieves a list of collections.
-    /// The result will be sorted by name and ID (name first) in ascending order.
+    /// The result will be sorted in ascending order by `order`; see `ListOrder` for the available orders.
     /// If `last_collection_id` is provided, the result will start from the collection that comes after it.
+    /// The number of all collections is counted only if `with_total` is set, as counting is not cheap.
     pub async fn get_collections(
         &self,
         last_collection_id: Option<Uuid>,
+        order: ListOrder,
         limit: u32,
-    ) -> Result<Vec<Collection>, CollectionServiceError> {
+        with_total: bool,
+    ) -> Result<Page<Collection, Uuid>, CollectionServiceError> {
         use crate::db::schema;
-        let db = &mut self.db_pool.get().await?;
+        let db = &mut self.read_pool.get().await?;
+
+        let total = if with_total {
+            let query = schema::collections::table.count();
+            let total = self
+                .read_pool
+                .measure("count_collections", query, |query| {
+                    query.get_result::<i64>(db)
+                })
+                .await?;
+            Some(total)
+        } else {
+            None
+        };
 
         let query = schema::collections::dsl::collections
             .select((
@@ -105,12 +256,36 @@ impl CollectionService {
                 schema::collections::name,
                 schema::collections::description,
                 schema::collections::created_at,
+                schema::collections::version,
             ))
-            .order((
-                schema::collections::name.asc(),
-                schema::collections::id.asc(),
-            ))
-            .limit(limit as i64);
+            // fetch one more collection to tell whether there is a next page
+            .limit(limit as i64 + 1)
+            .into_boxed();
+
+        if order == ListOrder::Id {
+            // the ID alone is the key, so the last collection doesn't have to be looked up
+            let mut query = query.order(schema::collections::id.asc());
+
+            if let Some(last_collection_id) = last_collection_id {
+                query = query.filter(schema::collections::id.gt(last_collection_id));
+            }
+
+            let collections = self
+                .read_pool
+                .measure("get_collections", query, |query| {
+                    query.load::<Collection>(db)
+                })
+                .await?;
+
+            return Ok(Page::new(collections, limit, total, |collection| {
+                collection.id
+            }));
+        }
+
+        let mut query = query.order((
+            schema::collections::name.asc(),
+            schema::collections::id.asc(),
+        ));
 
         let last_collection = match last_collection_id {
             Some(last_collection_id) => {
@@ -123,7 +298,11 @@ impl CollectionService {
 
                 let last_collection = match last_collection {
                     Some(pair) => pair,
-                    None => return Ok(Vec::new()),
+                    None => {
+                        return Ok(Page::new(Vec::new(), limit, total, |collection| {
+                            collection.id
+                        }))
+                    }
                 };
 
                 Some(last_collection)
@@ -131,21 +310,26 @@ impl CollectionService {
             None => None,
         };
 
-        let collections = match &last_collection {
-            Some((last_collection_name, last_collection_id)) => query
-                .filter(
-                    schema::collections::name.gt(last_collection_name).or(
-                        schema::collections::name
-                            .eq(last_collection_name)
-                            .and(schema::collections::id.gt(last_collection_id)),
-                    ),
-                )
-                .load::<Collection>(db),
-            None => query.load::<Collection>(db),
-        };
-        let collections = collections.await?;
+        if let Some((last_collection_name, last_collection_id)) = last_collection {
+            query = query.filter(
+                schema::collections::name
+                    .gt(last_collection_name.clone())
+                    .or(schema::collections::name
+                        .eq(last_collection_name)
+                        .and(schema::collections::id.gt(last_collection_id))),
+            );
+        }
 
-        Ok(collections)
+        let collections = self
+            .read_pool
+            .measure("get_collections", query, |query| {
+                query.load::<Collection>(db)
+            })
+            .await?;
+
+        Ok(Page::new(collections, limit, total, |collection| {
+            collection.id
+        }))
     }
 
     /// Retrieves a collection by its ID.
@@ -163,6 +347,7 @@ impl CollectionService {
                 schema::collections::name,
                 schema::collections::description,
                 schema::collections::created_at,
+                schema::collections::version,
             ))
             .first::<Collection>(db)
             .await
@@ -173,31 +358,81 @@ impl CollectionService {
 
     /// Updates a collection by its ID.
     /// Returns the collection that was updated, or `None` if no collection was found.
+    /// If collection names are unique, it fails with `NameTaken` when another collection has the new name,
+    /// unless `allow_duplicate` is set.
+    /// It fails with `NameTooLong` or `DescriptionTooLong` if the new name or description exceeds the configured limits.
+    /// If `expected_version` is given, it fails with `VersionMismatch` when the collection has been updated since.
     pub async fn update_collection_by_id(
         &self,
         collection_id: Uuid,
         new_name: &str,
         new_description: Option<&str>,
+        allow_duplicate: bool,
+        expected_version: Option<i32>,
     ) -> Result<Option<Collection>, CollectionServiceError> {
         use crate::db::schema;
 
+        self.check_lengths(new_name, new_description)?;
+
+        let check_name = self.unique_names && !allow_duplicate;
         let db = &mut self.db_pool.get().await?;
-        let collection = diesel::update(
-            schema::collections::dsl::collections.filter(schema::collections::id.eq(collection_id)),
-        )
-        .set(UpdatingCollection {
-            name: new_name,
-            description: new_description,
-        })
-        .returning((
-            schema::collections::id,
-            schema::collections::name,
-            schema::collections::description,
-            schema::collections::created_at,
-        ))
-        .get_result::<Collection>(db)
-        .await
-        .optional()?;
+        let collection = db
+            .transaction(|db| {
+                async move {
+                    if let Some(expected) = expected_version {
+                        // lock the row so that no other update can slip in between the check and the update
+                        let current = schema::collections::dsl::collections
+                            .filter(schema::collections::id.eq(collection_id))
+                            .select(schema::collections::version)
+                            .for_update()
+                            .first::<i32>(db)
+                            .await
+                            .optional()?;
+
+                        match current {
+                            Some(current) if current != expected => {
+                                return Err(CollectionServiceError::VersionMismatch {
+                                    expected,
+                                    current,
+                                });
+                            }
+                            Some(_) => {}
+                            None => return Ok(None),
+                        }
+                    }
+
+                    if check_name {
+                        self.check_name_available(db, new_name, Some(collection_id))
+                            .await?;
+                    }
+
+                    let collection = diesel::update(
+                        schema::collections::dsl::collections
+                            .filter(schema::collections::id.eq(collection_id)),
+                    )
+                    .set((
+                        UpdatingCollection {
+                            name: new_name,
+                            description: new_description,
+                        },
+                        schema::collections::version.eq(schema::collections::version + 1),
+                    ))
+                    .returning((
+                        schema::collections::id,
+                        schema::collections::name,
+                        schema::collections::description,
+                        schema::collections::created_at,
+                        schema::collections::version,
+                    ))
+                    .get_result::<Collection>(db)
+                    .await
+                    .optional()?;
+
+                    Ok::<_, CollectionServiceError>(collection)
+                }
+                .scope_boxed()
+            })
+            .await?;
 
         if let Some(collection) = &collection {
             // ignore the error if the indexing fails, as it is not critical
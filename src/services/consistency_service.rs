@@ -0,0 +1,239 @@
+use super::{
+    FileDriver, FileService, FileServiceError, GcService, GcServiceError, OrphanedBlob,
+    SearchService,
+};
+use crate::db::models::{CollectionFilePair, Tag};
+use diesel::{dsl::not, sql_types::Text, BoolExpressionMethods, ExpressionMethods, QueryDsl};
+use diesel_async::{
+    pooled_connection::deadpool::Pool, scoped_futures::ScopedFutureExt, AsyncConnection,
+    AsyncPgConnection, RunQueryDsl,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use thiserror::Error;
+use uuid::Uuid;
+
+/// Foreign keys the schema relies on to keep the tables consistent.
+const EXPECTED_FOREIGN_KEYS: [&str; 3] = [
+    "collection_file_pairs_collection_fk",
+    "collection_file_pairs_file_fk",
+    "tags_file_fk",
+];
+
+#[derive(Error, Debug)]
+pub enum ConsistencyServiceError {
+    #[error("database pool error: {0}")]
+    Pool(#[from] diesel_async::pooled_connection::deadpool::PoolError),
+    #[error("diesel error: {0}")]
+    Diesel(#[from] diesel::result::Error),
+    #[error("io error: {0}")]
+    IO(#[from] std::io::Error),
+    #[error("{0}")]
+    GcService(#[from] GcServiceError),
+    #[error("{0}")]
+    FileService(#[from] FileServiceError),
+}
+
+/// Inconsistencies found in the database and between the database and the file driver.
+/// When returned from a repair, it lists the rows and blobs that have been removed.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ConsistencyReport {
+    /// Foreign key constraints that are expected but missing in the database.
+    /// Missing constraints are not repaired automatically; they have to be restored by migrations.
+    pub missing_foreign_keys: Vec<String>,
+    /// Collection-file pairs that point at a collection or a file that does not exist.
+    pub dangling_collection_file_pairs: Vec<CollectionFilePair>,
+    /// Tags that point at a file that does not exist.
+    pub dangling_tags: Vec<Tag>,
+    /// Blobs in the file driver that no file refers to.
+    pub orphaned_blobs: Vec<OrphanedBlob>,
+    /// Files whose blob is missing in the file driver.
+    /// A repair removes these files, so their blobs should be restored before repairing if they can be recovered.
+    pub missing_blob_file_ids: Vec<Uuid>,
+}
+
+impl ConsistencyReport {
+    /// Returns `true` if no inconsistency has been found.
+    pub fn is_healthy(&self) -> bool {
+        self.missing_foreign_keys.is_empty()
+            && self.dangling_collection_file_pairs.is_empty()
+            && self.dangling_tags.is_empty()
+            && self.orphaned_blobs.is_empty()
+            && self.missing_blob_file_ids.is_empty()
+    }
+}
+
+#[derive(diesel::QueryableByName)]
+struct ForeignKeyName {
+    #[diesel(sql_type = Text)]
+    name: String,
+}
+
+pub struct ConsistencyService {
+    db_pool: Pool<AsyncPgConnection>,
+    search_service: Arc<dyn SearchService + Send + Sync>,
+    file_service: Arc<FileService>,
+    gc_service: Arc<GcService>,
+    file_driver: Arc<dyn FileDriver + Send + Sync>,
+}
+
+impl ConsistencyService {
+    pub fn new(
+        db_pool: Pool<AsyncPgConnection>,
+        search_service: Arc<dyn SearchService + Send + Sync>,
+        file_service: Arc<FileService>,
+        gc_service: Arc<GcService>,
+        file_driver: Arc<dyn FileDriver + Send + Sync>,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            db_pool,
+            search_service,
+            file_service,
+            gc_service,
+            file_driver,
+        })
+    }
+
+    /// Checks the database and the file driver for inconsistencies without modifying anything.
+    /// The result can be exported for manual review, or be repaired by `repair`.
+    pub async fn check(&self) -> Result<ConsistencyReport, ConsistencyServiceError> {
+        use crate::db::schema;
+
+        let db = &mut self.db_pool.get().await?;
+
+        let foreign_keys = diesel::sql_query(
+            "SELECT conname::TEXT AS name FROM pg_constraint WHERE contype = 'f'",
+        )
+        .load::<ForeignKeyName>(db)
+        .await?;
+        let missing_foreign_keys = EXPECTED_FOREIGN_KEYS
+            .iter()
+            .filter(|&&expected| !foreign_keys.iter().any(|fk| fk.name == expected))
+            .map(|&expected| expected.to_owned())
+            .collect();
+
+        let dangling_collection_file_pairs = schema::collection_file_pairs::table
+            .left_join(schema::collections::table)
+            .left_join(schema::files::table)
+            .filter(
+                schema::collections::id
+                    .is_null()
+                    .or(schema::files::id.is_null()),
+            )
+            .select((
+                schema::collection_file_pairs::collection_id,
+                schema::collection_file_pairs::file_id,
+                schema::collection_file_pairs::position,
+            ))
+            .load::<CollectionFilePair>(db)
+            .await?;
+
+        let dangling_tags = schema::tags::table
+            .left_join(schema::files::table)
+            .filter(schema::files::id.is_null())
+            .select((
+                schema::tags::name,
+                schema::tags::file_id,
+                schema::tags::namespace,
+            ))
+            .load::<Tag>(db)
+            .await?;
+
+        let gc_report = self.gc_service.check().await?;
+
+        Ok(ConsistencyReport {
+            missing_foreign_keys,
+            dangling_collection_file_pairs,
+            dangling_tags,
+            orphaned_blobs: gc_report.orphaned_blobs,
+            missing_blob_file_ids: gc_report.missing_blob_file_ids,
+        })
+    }
+
+    /// Removes all dangling rows in a single transaction, then removes the orphaned blobs
+    /// and the files whose blob is missing.
+    /// Returns the report of the rows and blobs that have been removed.
+    pub async fn repair(&self) -> Result<ConsistencyReport, ConsistencyServiceError> {
+        use crate::db::schema;
+
+        let missing_foreign_keys = self.check().await?.missing_foreign_keys;
+
+        let db = &mut self.db_pool.get().await?;
+        let (dangling_collection_file_pairs, dangling_tags) = db
+            .transaction(|db| {
+                async move {
+                    let dangling_collection_file_pairs = diesel::delete(
+                        schema::collection_file_pairs::table.filter(
+                            not(schema::collection_file_pairs::collection_id.eq_any(
+                                schema::collections::table.select(schema::collections::id),
+                            ))
+                            .or(not(schema::collection_file_pairs::file_id
+                                .eq_any(schema::files::table.select(schema::files::id)))),
+                        ),
+                    )
+                    .returning((
+                        schema::collection_file_pairs::collection_id,
+                        schema::collection_file_pairs::file_id,
+                        schema::collection_file_pairs::position,
+                    ))
+                    .get_results::<CollectionFilePair>(db)
+                    .await?;
+
+                    let dangling_tags = diesel::delete(
+                        schema::tags::table.filter(not(schema::tags::file_id
+                            .eq_any(schema::files::table.select(schema::files::id)))),
+                    )
+                    .returning((
+                        schema::tags::name,
+                        schema::tags::file_id,
+                        schema::tags::namespace,
+                    ))
+                    .get_results::<Tag>(db)
+                    .await?;
+
+                    Ok::<_, ConsistencyServiceError>((
+                        dangling_collection_file_pairs,
+                        dangling_tags,
+                    ))
+                }
+                .scope_boxed()
+            })
+            .await?;
+
+        for pair in &dangling_collection_file_pairs {
+            // ignore the error if the indexing fails, as it is not critical
+            self.search_service
+                .remove_collection_file(pair.collection_id, pair.file_id)
+                .await
+                .ok();
+        }
+
+        let gc_report = self.gc_service.sweep().await?;
+        let mut missing_blob_file_ids = Vec::new();
+
+        for file_id in gc_report.missing_blob_file_ids {
+            // the file may have been committed after the blobs were listed
+            if self.file_driver.exists(file_id).await? {
+                continue;
+            }
+
+            if self
+                .file_service
+                .remove_file_by_id(file_id)
+                .await?
+                .is_some()
+            {
+                missing_blob_file_ids.push(file_id);
+            }
+        }
+
+        Ok(ConsistencyReport {
+            missing_foreign_keys,
+            dangling_collection_file_pairs,
+            dangling_tags,
+            orphaned_blobs: gc_report.orphaned_blobs,
+            missing_blob_file_ids,
+        })
+    }
+}
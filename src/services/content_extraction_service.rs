@@ -0,0 +1,108 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use thiserror::Error;
+use tokio::{io::AsyncReadExt, process::Command};
+
+/// The maximum length of the extracted content in bytes.
+/// The rest of the content is not searchable.
+const MAX_CONTENT_LENGTH: usize = 1024 * 1024;
+
+#[derive(Error, Debug)]
+pub enum ContentExtractionServiceError {
+    #[error("io error: {0}")]
+    IO(#[from] std::io::Error),
+    #[error("pdftotext exited with {status}")]
+    PdfToText { status: std::process::ExitStatus },
+}
+
+pub struct ContentExtractionService {
+    pdftotext_path: Option<PathBuf>,
+}
+
+impl ContentExtractionService {
+    pub fn new(pdftotext_path: Option<PathBuf>) -> Arc<Self> {
+        Arc::new(Self { pdftotext_path })
+    }
+
+    /// Extracts the text content from the file at the given path, so that it can be searched.
+    /// Plain text and markdown are read as is, and PDFs are converted with `pdftotext` if it is configured.
+    /// Returns `None` if the file type is not supported or nothing could be extracted.
+    /// Extraction failures are logged and ignored, as the content is not critical.
+    pub async fn extract(&self, path: impl AsRef<Path>, mime: &str) -> Option<String> {
+        let path = path.as_ref();
+        let mime = mime.split_once(';').map_or(mime, |(mime, _)| mime).trim();
+
+        let result = match mime {
+            "text/plain" | "text/markdown" | "text/x-markdown" => self.extract_text(path).await,
+            "application/pdf" => self.extract_pdf(path).await,
+            _ => return None,
+        };
+
+        let content = match result {
+            Ok(content) => content,
+            Err(err) => {
+                log::warn!(target: "content_extraction_service", path:?, mime, err:err; "Failed to extract content.");
+                return None;
+            }
+        };
+
+        let content = content.trim();
+
+        if content.is_empty() {
+            None
+        } else {
+            Some(content.to_owned())
+        }
+    }
+
+    async fn extract_text(&self, path: &Path) -> Result<String, ContentExtractionServiceError> {
+        let file = tokio::fs::File::open(path).await?;
+        let mut data = Vec::new();
+        file.take(MAX_CONTENT_LENGTH as u64)
+            .read_to_end(&mut data)
+            .await?;
+
+        Ok(into_truncated_string(data))
+    }
+
+    async fn extract_pdf(&self, path: &Path) -> Result<String, ContentExtractionServiceError> {
+        let pdftotext_path = match &self.pdftotext_path {
+            Some(pdftotext_path) => pdftotext_path,
+            None => return Ok(String::new()),
+        };
+
+        // `-` writes the text to stdout
+        let output = Command::new(pdftotext_path)
+            .args(["-q", "-enc", "UTF-8"])
+            .arg(path)
+            .arg("-")
+            .kill_on_drop(true)
+            .output()
+            .await?;
+
+        if !output.status.success() {
+            return Err(ContentExtractionServiceError::PdfToText {
+                status: output.status,
+            });
+        }
+
+        let mut data = output.stdout;
+        data.truncate(MAX_CONTENT_LENGTH);
+
+        Ok(into_truncated_string(data))
+    }
+}
+
+/// Converts the data into a string, dropping a character cut off by the length limit.
+/// Other invalid sequences are replaced.
+fn into_truncated_string(data: Vec<u8>) -> String {
+    let end = match std::str::from_utf8(&data) {
+        Ok(_) => data.len(),
+        Err(err) if err.error_len().is_none() => err.valid_up_to(),
+        Err(_) => data.len(),
+    };
+
+    String::from_utf8_lossy(&data[..end]).into_owned()
+}
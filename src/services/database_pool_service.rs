@@ -0,0 +1,47 @@
+use crate::db::DatabasePoolMetrics;
+use diesel_async::{pooled_connection::deadpool::Pool, AsyncPgConnection};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// A snapshot of the database connection pool.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct DatabasePoolStats {
+    /// The maximum number of connections kept open at once.
+    pub max_size: usize,
+    /// Connections currently open, both in use and idle.
+    pub size: usize,
+    /// Idle connections ready to be used.
+    pub available: usize,
+    /// Requests waiting for a connection to become available.
+    pub waiting: usize,
+    /// Connections established since the application started.
+    pub created: u64,
+    /// Times an idle connection has been reused since the application started.
+    pub recycled: u64,
+}
+
+pub struct DatabasePoolService {
+    db_pool: Pool<AsyncPgConnection>,
+    metrics: Arc<DatabasePoolMetrics>,
+}
+
+impl DatabasePoolService {
+    pub fn new(db_pool: Pool<AsyncPgConnection>, metrics: Arc<DatabasePoolMetrics>) -> Arc<Self> {
+        Arc::new(Self { db_pool, metrics })
+    }
+
+    pub fn stats(&self) -> DatabasePoolStats {
+        let status = self.db_pool.status();
+
+        DatabasePoolStats {
+            max_size: status.max_size,
+            size: status.size,
+            // a negative number of available connections is the number of waiting requests
+            available: status.available.max(0) as usize,
+            waiting: (-status.available).max(0) as usize,
+            created: self.metrics.created(),
+            recycled: self.metrics.recycled(),
+        }
+    }
+}
@@ -0,0 +1,228 @@
+use super::{Mail, MailerService, PasswordService};
+use crate::{
+    config::AppEmailChange,
+    db::models::{CreatingEmailChangeRequest, EmailChangeRequest, User},
+};
+use chrono::{Duration, Utc};
+use diesel::{BoolExpressionMethods, ExpressionMethods, OptionalExtension, QueryDsl};
+use diesel_async::{
+    pooled_connection::deadpool::Pool, scoped_futures::ScopedFutureExt, AsyncConnection,
+    AsyncPgConnection, RunQueryDsl,
+};
+use std::sync::Arc;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum EmailChangeServiceError {
+    #[error("database pool error: {0}")]
+    Pool(#[from] diesel_async::pooled_connection::deadpool::PoolError),
+    #[error("diesel error: {0}")]
+    Diesel(#[from] diesel::result::Error),
+    /// The new email belongs to another user.
+    #[error("email `{0}` is already in use")]
+    EmailTaken(String),
+}
+
+/// Changes the emails of users once the new email has been confirmed.
+/// The email stays as it is until the link mailed to the new email is opened, so a mistyped email cannot lock a user out.
+pub struct EmailChangeService {
+    db_pool: Pool<AsyncPgConnection>,
+    password_service: Arc<PasswordService>,
+    mailer_service: Arc<MailerService>,
+    expiration: Duration,
+    url_template: String,
+}
+
+impl EmailChangeService {
+    pub fn new(
+        db_pool: Pool<AsyncPgConnection>,
+        password_service: Arc<PasswordService>,
+        mailer_service: Arc<MailerService>,
+        config: &AppEmailChange,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            db_pool,
+            password_service,
+            mailer_service,
+            expiration: Duration::seconds(config.expiration as i64),
+            url_template: config.url_template.clone(),
+        })
+    }
+
+    /// Creates an email change request for the given user, and mails the confirmation link to the new email.
+    /// The mail is sent in the background; failures to send it are only logged.
+    /// Returns `false` if the user was not found.
+    pub async fn request_email_change(
+        &self,
+        user_id: i32,
+        new_email: &str,
+    ) -> Result<bool, EmailChangeServiceError> {
+        let request = match self.create_email_change_request(user_id, new_email).await? {
+            Some(request) => request,
+            None => return Ok(false),
+        };
+
+        let mail = Mail {
+            to: request.new_email.clone(),
+            subject: "Confirm your new email".to_owned(),
+            body: format!(
+                "A change of the email of your account to this email has been requested.\n\nOpen the link below to confirm it. The link expires at {} UTC.\n\n{}\n\nIf you did not request it, you can ignore this mail.\n",
+                request.expires_at.format("%Y-%m-%d %H:%M"),
+                self.url_template.replace("{token}", &request.token),
+            ),
+        };
+        let mailer_service = self.mailer_service.clone();
+
+        tokio::spawn(async move {
+            if let Err(err) = mailer_service.send(&mail).await {
+                log::error!(target: "services::email_change_service", method = "request_email_change", user_id, err:err; "Failed to send the email change mail.");
+            }
+        });
+
+        Ok(true)
+    }
+
+    /// Creates an email change request for the given user, replacing any previous one.
+    /// Returns `None` if the user was not found.
+    pub async fn create_email_change_request(
+        &self,
+        user_id: i32,
+        new_email: &str,
+    ) -> Result<Option<EmailChangeRequest>, EmailChangeServiceError> {
+        use crate::db::schema;
+
+        let token = self.password_service.generate_url_safe_token_252();
+        let expires_at = Utc::now().naive_utc() + self.expiration;
+
+        let db = &mut self.db_pool.get().await?;
+        let request = db
+            .transaction(|db| {
+                async move {
+                    let user_id = schema::users::table
+                        .filter(schema::users::id.eq(user_id))
+                        .select(schema::users::id)
+                        .first::<i32>(db)
+                        .await
+                        .optional()?;
+
+                    let user_id = match user_id {
+                        Some(user_id) => user_id,
+                        None => return Ok(None),
+                    };
+
+                    let is_taken = schema::users::table
+                        .filter(schema::users::email.eq(new_email))
+                        .filter(schema::users::id.ne(user_id))
+                        .select(schema::users::id)
+                        .first::<i32>(db)
+                        .await
+                        .optional()?
+                        .is_some();
+
+                    if is_taken {
+                        return Err(EmailChangeServiceError::EmailTaken(new_email.to_owned()));
+                    }
+
+                    // only the latest request of a user can be confirmed, and expired ones are never used again
+                    diesel::delete(schema::email_change_requests::table.filter(
+                        schema::email_change_requests::user_id.eq(user_id).or(
+                            schema::email_change_requests::expires_at.le(Utc::now().naive_utc()),
+                        ),
+                    ))
+                    .execute(db)
+                    .await?;
+
+                    let request = diesel::insert_into(schema::email_change_requests::table)
+                        .values(CreatingEmailChangeRequest {
+                            token: &token,
+                            user_id,
+                            new_email,
+                            expires_at,
+                        })
+                        .returning((
+                            schema::email_change_requests::token,
+                            schema::email_change_requests::user_id,
+                            schema::email_change_requests::new_email,
+                            schema::email_change_requests::created_at,
+                            schema::email_change_requests::expires_at,
+                        ))
+                        .get_result::<EmailChangeRequest>(db)
+                        .await?;
+
+                    Ok::<_, EmailChangeServiceError>(Some(request))
+                }
+                .scope_boxed()
+            })
+            .await?;
+
+        Ok(request)
+    }
+
+    /// Sets the email of the user the given token was issued for to the requested one.
+    /// Returns `None` if the token does not exist or has expired.
+    pub async fn confirm_email_change(
+        &self,
+        token: &str,
+    ) -> Result<Option<User>, EmailChangeServiceError> {
+        use crate::db::schema;
+
+        let db = &mut self.db_pool.get().await?;
+        let user = db
+            .transaction(|db| {
+                async move {
+                    let request = diesel::delete(
+                        schema::email_change_requests::table
+                            .filter(schema::email_change_requests::token.eq(token))
+                            .filter(
+                                schema::email_change_requests::expires_at
+                                    .gt(Utc::now().naive_utc()),
+                            ),
+                    )
+                    .returning((
+                        schema::email_change_requests::user_id,
+                        schema::email_change_requests::new_email,
+                    ))
+                    .get_result::<(i32, String)>(db)
+                    .await
+                    .optional()?;
+
+                    let (user_id, new_email) = match request {
+                        Some(request) => request,
+                        None => return Ok(None),
+                    };
+
+                    let user =
+                        diesel::update(schema::users::table.filter(schema::users::id.eq(user_id)))
+                            .set(schema::users::email.eq(&new_email))
+                            .returning((
+                                schema::users::id,
+                                schema::users::username,
+                                schema::users::email,
+                                schema::users::joined_at,
+                                schema::users::display_name,
+                                schema::users::avatar_file_id,
+                                schema::users::role,
+                                schema::users::disabled_at,
+                            ))
+                            .get_result::<User>(db)
+                            .await;
+
+                    match user {
+                        Ok(user) => Ok(Some(user)),
+                        // the email may have been taken after the request was made
+                        Err(diesel::result::Error::DatabaseError(
+                            diesel::result::DatabaseErrorKind::UniqueViolation,
+                            err,
+                        )) if err.constraint_name() == Some("users_email_idx") => {
+                            Err(EmailChangeServiceError::EmailTaken(new_email))
+                        }
+                        Err(err) => Err(err.into()),
+                    }
+                }
+                .scope_boxed()
+            })
+            .await?;
+
+        Ok(user)
+    }
+}
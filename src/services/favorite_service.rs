@@ -0,0 +1,219 @@
+use crate::db::models::{Collection, CreatingFavoriteCollection, CreatingFavoriteFile, File};
+use diesel::{ExpressionMethods, QueryDsl};
+use diesel_async::{pooled_connection::deadpool::Pool, AsyncPgConnection, RunQueryDsl};
+use std::{collections::HashSet, sync::Arc};
+use thiserror::Error;
+use uuid::Uuid;
+
+#[derive(Error, Debug)]
+pub enum FavoriteServiceError {
+    #[error("database pool error: {0}")]
+    Pool(#[from] diesel_async::pooled_connection::deadpool::PoolError),
+    #[error("diesel error: {0}")]
+    Diesel(#[from] diesel::result::Error),
+}
+
+/// Keeps the files and collections each user has marked as favorites.
+pub struct FavoriteService {
+    db_pool: Pool<AsyncPgConnection>,
+}
+
+impl FavoriteService {
+    pub fn new(db_pool: Pool<AsyncPgConnection>) -> Arc<Self> {
+        Arc::new(Self { db_pool })
+    }
+
+    /// Marks a file as a favorite of the user. It does nothing if the file is already a favorite.
+    /// Returns `false` if the file does not exist.
+    pub async fn add_favorite_file(
+        &self,
+        user_id: i32,
+        file_id: Uuid,
+    ) -> Result<bool, FavoriteServiceError> {
+        use crate::db::schema;
+
+        let db = &mut self.db_pool.get().await?;
+        let result = diesel::insert_into(schema::favorite_files::table)
+            .values(CreatingFavoriteFile { user_id, file_id })
+            .on_conflict_do_nothing()
+            .execute(db)
+            .await;
+
+        match result {
+            Ok(_) => Ok(true),
+            Err(diesel::result::Error::DatabaseError(
+                diesel::result::DatabaseErrorKind::ForeignKeyViolation,
+                err,
+            )) if err.constraint_name() == Some("favorite_files_file_fk") => Ok(false),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Unmarks a file as a favorite of the user.
+    /// Returns `false` if the file was not a favorite.
+    pub async fn remove_favorite_file(
+        &self,
+        user_id: i32,
+        file_id: Uuid,
+    ) -> Result<bool, FavoriteServiceError> {
+        use crate::db::schema;
+
+        let db = &mut self.db_pool.get().await?;
+        let removed_count = diesel::delete(
+            schema::favorite_files::table
+                .filter(schema::favorite_files::user_id.eq(user_id))
+                .filter(schema::favorite_files::file_id.eq(file_id)),
+        )
+        .execute(db)
+        .await?;
+
+        Ok(removed_count != 0)
+    }
+
+    /// Marks a collection as a favorite of the user. It does nothing if the collection is already a favorite.
+    /// Returns `false` if the collection does not exist.
+    pub async fn add_favorite_collection(
+        &self,
+        user_id: i32,
+        collection_id: Uuid,
+    ) -> Result<bool, FavoriteServiceError> {
+        use crate::db::schema;
+
+        let db = &mut self.db_pool.get().await?;
+        let result = diesel::insert_into(schema::favorite_collections::table)
+            .values(CreatingFavoriteCollection {
+                user_id,
+                collection_id,
+            })
+            .on_conflict_do_nothing()
+            .execute(db)
+            .await;
+
+        match result {
+            Ok(_) => Ok(true),
+            Err(diesel::result::Error::DatabaseError(
+                diesel::result::DatabaseErrorKind::ForeignKeyViolation,
+                err,
+            )) if err.constraint_name() == Some("favorite_collections_collection_fk") => Ok(false),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Unmarks a collection as a favorite of the user.
+    /// Returns `false` if the collection was not a favorite.
+    pub async fn remove_favorite_collection(
+        &self,
+        user_id: i32,
+        collection_id: Uuid,
+    ) -> Result<bool, FavoriteServiceError> {
+        use crate::db::schema;
+
+        let db = &mut self.db_pool.get().await?;
+        let removed_count = diesel::delete(
+            schema::favorite_collections::table
+                .filter(schema::favorite_collections::user_id.eq(user_id))
+                .filter(schema::favorite_collections::collection_id.eq(collection_id)),
+        )
+        .execute(db)
+        .await?;
+
+        Ok(removed_count != 0)
+    }
+
+    /// Retrieves the favorite files of the user, from the most recently marked one.
+    pub async fn get_favorite_files(
+        &self,
+        user_id: i32,
+    ) -> Result<Vec<File>, FavoriteServiceError> {
+        use crate::db::schema;
+
+        let db = &mut self.db_pool.get().await?;
+        let files = schema::favorite_files::table
+            .inner_join(schema::files::table)
+            .filter(schema::favorite_files::user_id.eq(user_id))
+            .select((
+                schema::files::id,
+                schema::files::name,
+                schema::files::mime,
+                schema::files::size,
+                schema::files::hash,
+                schema::files::uploaded_at,
+            ))
+            .order(schema::favorite_files::created_at.desc())
+            .load::<File>(db)
+            .await?;
+
+        Ok(files)
+    }
+
+    /// Retrieves the favorite collections of the user, from the most recently marked one.
+    pub async fn get_favorite_collections(
+        &self,
+        user_id: i32,
+    ) -> Result<Vec<Collection>, FavoriteServiceError> {
+        use crate::db::schema;
+
+        let db = &mut self.db_pool.get().await?;
+        let collections = schema::favorite_collections::table
+            .inner_join(schema::collections::table)
+            .filter(schema::favorite_collections::user_id.eq(user_id))
+            .select((
+                schema::collections::id,
+                schema::collections::name,
+                schema::collections::description,
+                schema::collections::created_at,
+                schema::collections::version,
+            ))
+            .order(schema::favorite_collections::created_at.desc())
+            .load::<Collection>(db)
+            .await?;
+
+        Ok(collections)
+    }
+
+    /// Tells which of the given files are favorites of the user.
+    pub async fn get_favorite_file_ids(
+        &self,
+        user_id: i32,
+        file_ids: &[Uuid],
+    ) -> Result<HashSet<Uuid>, FavoriteServiceError> {
+        use crate::db::schema;
+
+        if file_ids.is_empty() {
+            return Ok(HashSet::new());
+        }
+
+        let db = &mut self.db_pool.get().await?;
+        let favorite_file_ids = schema::favorite_files::table
+            .filter(schema::favorite_files::user_id.eq(user_id))
+            .filter(schema::favorite_files::file_id.eq_any(file_ids))
+            .select(schema::favorite_files::file_id)
+            .load::<Uuid>(db)
+            .await?;
+
+        Ok(favorite_file_ids.into_iter().collect())
+    }
+
+    /// Tells which of the given collections are favorites of the user.
+    pub async fn get_favorite_collection_ids(
+        &self,
+        user_id: i32,
+        collection_ids: &[Uuid],
+    ) -> Result<HashSet<Uuid>, FavoriteServiceError> {
+        use crate::db::schema;
+
+        if collection_ids.is_empty() {
+            return Ok(HashSet::new());
+        }
+
+        let db = &mut self.db_pool.get().await?;
+        let favorite_collection_ids = schema::favorite_collections::table
+            .filter(schema::favorite_collections::user_id.eq(user_id))
+            .filter(schema::favorite_collections::collection_id.eq_any(collection_ids))
+            .select(schema::favorite_collections::collection_id)
+            .load::<Uuid>(db)
+            .await?;
+
+        Ok(favorite_collection_ids.into_iter().collect())
+    }
+}
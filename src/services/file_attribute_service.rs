@@ -0,0 +1,130 @@
+use super::{SearchService, SearchServiceError};
+use crate::db::models::CreatingFileAttribute;
+use diesel::{ExpressionMethods, OptionalExtension, QueryDsl};
+use diesel_async::{
+    pooled_connection::deadpool::Pool, scoped_futures::ScopedFutureExt, AsyncConnection,
+    AsyncPgConnection, RunQueryDsl,
+};
+use std::{collections::BTreeMap, sync::Arc};
+use thiserror::Error;
+use uuid::Uuid;
+
+#[derive(Error, Debug)]
+pub enum FileAttributeServiceError {
+    #[error("database pool error: {0}")]
+    Pool(#[from] diesel_async::pooled_connection::deadpool::PoolError),
+    #[error("diesel error: {0}")]
+    Diesel(#[from] diesel::result::Error),
+    #[error("search service error: {0}")]
+    SearchService(#[from] SearchServiceError),
+}
+
+/// Keeps the user-defined attributes of files, e.g. `camera=X100V` or `project=alpha`.
+pub struct FileAttributeService {
+    db_pool: Pool<AsyncPgConnection>,
+    search_service: Arc<dyn SearchService + Send + Sync>,
+}
+
+impl FileAttributeService {
+    pub fn new(
+        db_pool: Pool<AsyncPgConnection>,
+        search_service: Arc<dyn SearchService + Send + Sync>,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            db_pool,
+            search_service,
+        })
+    }
+
+    /// Retrieves the attributes of a file, sorted by key.
+    /// Returns `None` if the file does not exist.
+    pub async fn get_attributes(
+        &self,
+        file_id: Uuid,
+    ) -> Result<Option<BTreeMap<String, String>>, FileAttributeServiceError> {
+        use crate::db::schema;
+
+        let db = &mut self.db_pool.get().await?;
+        let exists = schema::files::table
+            .filter(schema::files::id.eq(file_id))
+            .select(schema::files::id)
+            .get_result::<Uuid>(db)
+            .await
+            .optional()?;
+
+        if exists.is_none() {
+            return Ok(None);
+        }
+
+        let attributes = schema::file_attributes::table
+            .filter(schema::file_attributes::file_id.eq(file_id))
+            .select((schema::file_attributes::key, schema::file_attributes::value))
+            .load::<(String, String)>(db)
+            .await?;
+
+        Ok(Some(attributes.into_iter().collect()))
+    }
+
+    /// Replaces all the attributes of a file with `attributes`, and indexes the filterable ones.
+    /// Returns `None` if the file does not exist.
+    pub async fn set_attributes(
+        &self,
+        file_id: Uuid,
+        attributes: &BTreeMap<String, String>,
+    ) -> Result<Option<BTreeMap<String, String>>, FileAttributeServiceError> {
+        use crate::db::schema;
+
+        let db = &mut self.db_pool.get().await?;
+        let exists = db
+            .transaction(|db| {
+                async move {
+                    // lock the file, so that concurrent replacements are applied one after another
+                    let exists = schema::files::table
+                        .filter(schema::files::id.eq(file_id))
+                        .select(schema::files::id)
+                        .for_update()
+                        .get_result::<Uuid>(db)
+                        .await
+                        .optional()?;
+
+                    if exists.is_none() {
+                        return Ok(false);
+                    }
+
+                    diesel::delete(
+                        schema::file_attributes::table
+                            .filter(schema::file_attributes::file_id.eq(file_id)),
+                    )
+                    .execute(db)
+                    .await?;
+
+                    let values = attributes
+                        .iter()
+                        .map(|(key, value)| CreatingFileAttribute {
+                            file_id,
+                            key,
+                            value,
+                        })
+                        .collect::<Vec<_>>();
+                    diesel::insert_into(schema::file_attributes::table)
+                        .values(values)
+                        .execute(db)
+                        .await?;
+
+                    Ok::<_, FileAttributeServiceError>(true)
+                }
+                .scope_boxed()
+            })
+            .await?;
+
+        if !exists {
+            return Ok(None);
+        }
+
+        self.search_service
+            .index_file_attributes(file_id, attributes)
+            .await?;
+
+        Ok(Some(attributes.clone()))
+    }
+}
@@ -0,0 +1,214 @@
+use super::{IdService, Page};
+use crate::db::models::{CreatingFileComment, FileComment};
+use chrono::NaiveDateTime;
+use diesel::{
+    BoolExpressionMethods, ExpressionMethods, NullableExpressionMethods, OptionalExtension,
+    QueryDsl,
+};
+use diesel_async::{pooled_connection::deadpool::Pool, AsyncPgConnection, RunQueryDsl};
+use std::sync::Arc;
+use thiserror::Error;
+use uuid::Uuid;
+
+#[derive(Error, Debug)]
+pub enum FileCommentServiceError {
+    #[error("database pool error: {0}")]
+    Pool(#[from] diesel_async::pooled_connection::deadpool::PoolError),
+    #[error("diesel error: {0}")]
+    Diesel(#[from] diesel::result::Error),
+}
+
+/// Keeps the comments users leave on files.
+pub struct FileCommentService {
+    db_pool: Pool<AsyncPgConnection>,
+    id_service: Arc<IdService>,
+}
+
+impl FileCommentService {
+    pub fn new(db_pool: Pool<AsyncPgConnection>, id_service: Arc<IdService>) -> Arc<Self> {
+        Arc::new(Self {
+            db_pool,
+            id_service,
+        })
+    }
+
+    /// Creates a comment on a file on behalf of the given user.
+    /// Returns `None` if the file does not exist.
+    pub async fn create_comment(
+        &self,
+        file_id: Uuid,
+        user_id: i32,
+        content: &str,
+    ) -> Result<Option<FileComment>, FileCommentServiceError> {
+        use crate::db::schema;
+
+        let db = &mut self.db_pool.get().await?;
+        let comment = diesel::insert_into(schema::file_comments::table)
+            .values(CreatingFileComment {
+                id: self.id_service.generate(),
+                file_id,
+                user_id,
+                content,
+            })
+            .returning((
+                schema::file_comments::id,
+                schema::file_comments::file_id,
+                schema::file_comments::user_id,
+                schema::file_comments::content,
+                schema::file_comments::created_at,
+                schema::file_comments::edited_at,
+            ))
+            .get_result::<FileComment>(db)
+            .await;
+
+        match comment {
+            Ok(comment) => Ok(Some(comment)),
+            Err(diesel::result::Error::DatabaseError(
+                diesel::result::DatabaseErrorKind::ForeignKeyViolation,
+                err,
+            )) if err.constraint_name() == Some("file_comments_file_fk") => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Retrieves the comments on a file, oldest first.
+    /// If `last_comment_id` is provided, the result will start from the comment that comes after it.
+    pub async fn get_comments(
+        &self,
+        file_id: Uuid,
+        last_comment_id: Option<Uuid>,
+        limit: u32,
+    ) -> Result<Page<FileComment, Uuid>, FileCommentServiceError> {
+        use crate::db::schema;
+
+        let db = &mut self.db_pool.get().await?;
+        let mut query = schema::file_comments::table
+            .filter(schema::file_comments::file_id.eq(file_id))
+            .into_boxed();
+
+        if let Some(last_comment_id) = last_comment_id {
+            let last_created_at = schema::file_comments::table
+                .filter(schema::file_comments::id.eq(last_comment_id))
+                .select(schema::file_comments::created_at)
+                .get_result::<NaiveDateTime>(db)
+                .await
+                .optional()?;
+
+            let last_created_at = match last_created_at {
+                Some(last_created_at) => last_created_at,
+                None => return Ok(Page::new(Vec::new(), limit, None, |comment| comment.id)),
+            };
+
+            query = query.filter(
+                schema::file_comments::created_at.gt(last_created_at).or(
+                    schema::file_comments::created_at
+                        .eq(last_created_at)
+                        .and(schema::file_comments::id.gt(last_comment_id)),
+                ),
+            );
+        }
+
+        let comments = query
+            .select((
+                schema::file_comments::id,
+                schema::file_comments::file_id,
+                schema::file_comments::user_id,
+                schema::file_comments::content,
+                schema::file_comments::created_at,
+                schema::file_comments::edited_at,
+            ))
+            .order((
+                schema::file_comments::created_at.asc(),
+                schema::file_comments::id.asc(),
+            ))
+            // fetch one more comment to tell whether there is a next page
+            .limit(limit as i64 + 1)
+            .load::<FileComment>(db)
+            .await?;
+
+        Ok(Page::new(comments, limit, None, |comment| comment.id))
+    }
+
+    /// Edits a comment written by the given user.
+    /// Returns `None` if the user has no comment with the ID on the file.
+    pub async fn update_comment(
+        &self,
+        file_id: Uuid,
+        comment_id: Uuid,
+        user_id: i32,
+        content: &str,
+    ) -> Result<Option<FileComment>, FileCommentServiceError> {
+        use crate::db::schema;
+
+        let db = &mut self.db_pool.get().await?;
+        let comment = diesel::update(
+            schema::file_comments::table
+                .filter(schema::file_comments::id.eq(comment_id))
+                .filter(schema::file_comments::file_id.eq(file_id))
+                .filter(schema::file_comments::user_id.eq(user_id)),
+        )
+        .set((
+            schema::file_comments::content.eq(content),
+            schema::file_comments::edited_at.eq(diesel::dsl::now.nullable()),
+        ))
+        .returning((
+            schema::file_comments::id,
+            schema::file_comments::file_id,
+            schema::file_comments::user_id,
+            schema::file_comments::content,
+            schema::file_comments::created_at,
+            schema::file_comments::edited_at,
+        ))
+        .get_result::<FileComment>(db)
+        .await
+        .optional()?;
+
+        Ok(comment)
+    }
+
+    /// Removes a comment written by the given user.
+    /// Returns the comment that was removed, or `None` if the user has no comment with the ID on the file.
+    pub async fn remove_comment(
+        &self,
+        file_id: Uuid,
+        comment_id: Uuid,
+        user_id: i32,
+    ) -> Result<Option<FileComment>, FileCommentServiceError> {
+        use crate::db::schema;
+
+        let db = &mut self.db_pool.get().await?;
+        let comment = diesel::delete(
+            schema::file_comments::table
+                .filter(schema::file_comments::id.eq(comment_id))
+                .filter(schema::file_comments::file_id.eq(file_id))
+                .filter(schema::file_comments::user_id.eq(user_id)),
+        )
+        .returning((
+            schema::file_comments::id,
+            schema::file_comments::file_id,
+            schema::file_comments::user_id,
+            schema::file_comments::content,
+            schema::file_comments::created_at,
+            schema::file_comments::edited_at,
+        ))
+        .get_result::<FileComment>(db)
+        .await
+        .optional()?;
+
+        Ok(comment)
+    }
+
+    /// Counts the comments on a file.
+    pub async fn count_comments(&self, file_id: Uuid) -> Result<i64, FileCommentServiceError> {
+        use crate::db::schema;
+
+        let db = &mut self.db_pool.get().await?;
+        let count = schema::file_comments::table
+            .filter(schema::file_comments::file_id.eq(file_id))
+            .count()
+            .get_result::<i64>(db)
+            .await?;
+
+        Ok(count)
+    }
+}
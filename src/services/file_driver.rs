@@ -1,12 +1,32 @@
+#[cfg(test)]
+pub mod faulty_file_driver;
 pub mod local_file_system;
+pub mod memory_file_system;
+pub mod tiered_file_driver;
 
+use crate::config::{AppConfig, StorageConfig};
 use async_trait::async_trait;
-use rocket::data::DataStream;
-use std::{path::PathBuf, pin::Pin};
+use chrono::NaiveDateTime;
+use local_file_system::LocalFileSystem;
+use memory_file_system::MemoryFileSystem;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    future::Future,
+    path::PathBuf,
+    pin::Pin,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
 use thiserror::Error;
+use tiered_file_driver::TieredFileDriver;
 use tokio::io::AsyncRead;
 use uuid::Uuid;
 
+/// A stream of data to be written into a staging file.
+/// Both request bodies and local files can be written through it.
+pub type WriteStream<'a> = Pin<Box<dyn AsyncRead + Send + 'a>>;
+
 #[derive(Error, Debug)]
 pub enum WriteError {
     /// The offset exceeds the file size.
@@ -24,6 +44,9 @@ pub enum WriteError {
     /// `i64::MAX` < `offset` + `file_size`
     #[error("offset is larger than the maximum allowed value: {max_offset} < {offset}")]
     OffsetTooLarge { max_offset: u64, offset: u64 },
+    /// The data goes past the size declared for the staging file.
+    #[error("data exceeds the expected file size: {expected_size}")]
+    ExceedsExpectedSize { expected_size: u64 },
     /// An I/O error occurred while writing the file.
     #[error("io error: {io_error}")]
     Write {
@@ -56,6 +79,89 @@ pub enum ReadRange {
     Suffix(u32),
 }
 
+impl ReadRange {
+    /// Computes the number of bytes the range covers in a file of `file_size` bytes.
+    /// The range is assumed to be satisfiable.
+    pub fn byte_count(&self, file_size: u64) -> u64 {
+        match *self {
+            Self::Full => file_size,
+            Self::Start(start) => file_size.saturating_sub(start),
+            Self::Range(start, end) => (end + 1).saturating_sub(start),
+            Self::Suffix(length) => u64::min(length as u64, file_size),
+        }
+    }
+
+    /// Computes the offset of the first byte the range covers in a file of `file_size` bytes.
+    /// The range is assumed to be satisfiable.
+    pub fn first_byte(&self, file_size: u64) -> u64 {
+        match *self {
+            Self::Full => 0,
+            Self::Start(start) | Self::Range(start, _) => start,
+            Self::Suffix(length) => file_size.saturating_sub(length as u64),
+        }
+    }
+
+    /// Builds the `Content-Range` header value of the range in a file of `file_size` bytes, e.g. `bytes 0-3/12`.
+    /// The range is assumed to be satisfiable.
+    pub fn content_range(&self, file_size: u64) -> String {
+        let first_byte = self.first_byte(file_size);
+        let last_byte = (first_byte + self.byte_count(file_size)).saturating_sub(1);
+
+        format!("bytes {}-{}/{}", first_byte, last_byte, file_size)
+    }
+
+    /// Tells whether the range starts from the beginning of the file.
+    pub fn starts_at_beginning(&self) -> bool {
+        matches!(self, Self::Full | Self::Start(0) | Self::Range(0, _))
+    }
+}
+
+/// Describes where a file is physically kept by a file driver.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct StorageLocation {
+    /// The name of the driver that holds the file.
+    pub driver: String,
+    /// The storage tier within the driver, if the driver has tiers.
+    pub tier: Option<String>,
+    /// The physical path or object key of the file.
+    pub location: String,
+    /// The space the file occupies in the storage, or `None` if the file is missing.
+    pub size_on_disk: Option<u64>,
+}
+
+/// The space left in the volumes of a storage system, in bytes.
+/// A volume reports `None` if its space is unknown or not limited by the driver, e.g. in memory.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct FreeSpace {
+    /// The space left in the volume staging files are written to.
+    pub staging: Option<u64>,
+    /// The space left in the volume committed files are kept in.
+    pub resident: Option<u64>,
+}
+
+/// A presigned request to upload the data of a staging file directly to the storage system, bypassing the server.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct PresignedUpload {
+    /// The HTTP method of the request, e.g. `PUT`.
+    pub method: String,
+    pub url: String,
+    /// The headers the request must carry as they are, since they are covered by the signature.
+    pub headers: HashMap<String, String>,
+    /// When the URL stops being accepted by the storage system.
+    pub expires_at: NaiveDateTime,
+}
+
+/// A staging file found in the storage system, whether or not it is still tracked.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StagingEntry {
+    pub id: Uuid,
+    /// When the data of the staging file was last written.
+    pub modified_at: SystemTime,
+}
+
 #[async_trait]
 pub trait FileDriver {
     /// Writes data to a staging file in the storage system.
@@ -70,9 +176,20 @@ pub trait FileDriver {
         &self,
         id: Uuid,
         offset: u64,
-        stream: DataStream<'_>,
+        stream: WriteStream<'_>,
     ) -> Result<i64, WriteError>;
 
+    /// Writes data to a staging file at `offset`, which may lie beyond the current end of the file.
+    /// Unlike [`FileDriver::write_staging`], it must be safe to call concurrently for non-overlapping ranges of the same file,
+    /// so that chunks of a file can be uploaded in parallel. Any gap before `offset` is left unwritten.
+    /// Returns the number of bytes written.
+    async fn write_staging_at(
+        &self,
+        id: Uuid,
+        offset: u64,
+        stream: WriteStream<'_>,
+    ) -> Result<u64, WriteError>;
+
     /// Removes a staging file from the storage system.
     async fn remove_staging(&self, id: Uuid) -> Result<(), std::io::Error>;
 
@@ -80,6 +197,30 @@ pub trait FileDriver {
     /// Returns the file if it exists, otherwise `None`.
     async fn read_staging(&self, id: Uuid) -> Result<Option<PathBuf>, std::io::Error>;
 
+    /// Tells whether the data of staging files can be uploaded directly to the storage system, e.g. to an object storage.
+    /// If so, [`FileDriver::presign_staging_upload`] and [`FileDriver::fetch_staging_upload`] must be implemented.
+    fn supports_direct_upload(&self) -> bool {
+        false
+    }
+
+    /// Presigns a request to upload the data of a staging file directly to the storage system.
+    /// If `size` is given, the request should only accept data of that size.
+    async fn presign_staging_upload(
+        &self,
+        _id: Uuid,
+        _size: Option<u64>,
+        _expires_in: Duration,
+    ) -> Result<PresignedUpload, std::io::Error> {
+        Err(std::io::ErrorKind::Unsupported.into())
+    }
+
+    /// Makes the data uploaded directly to the storage system by a presigned request the data of the staging file,
+    /// so that it can be read by [`FileDriver::read_staging`] and promoted like the data written by the server.
+    /// Returns the size of the data, or `None` if nothing has been uploaded.
+    async fn fetch_staging_upload(&self, _id: Uuid) -> Result<Option<u64>, std::io::Error> {
+        Err(std::io::ErrorKind::Unsupported.into())
+    }
+
     /// Commits a staging file to the storage system.
     /// The file must be uniquely identified by the given `id`.
     /// In case of a remote storage system, the file must be uploaded by this method.
@@ -95,4 +236,94 @@ pub trait FileDriver {
         id: Uuid,
         range: ReadRange,
     ) -> Result<Option<Pin<Box<dyn AsyncRead + Send>>>, ReadError>;
+
+    /// Tells whether a committed file exists in the storage system.
+    async fn exists(&self, id: Uuid) -> Result<bool, std::io::Error> {
+        Ok(self.size(id).await?.is_some())
+    }
+
+    /// Retrieves the size of a committed file in bytes.
+    /// Returns `None` if the file does not exist.
+    async fn size(&self, id: Uuid) -> Result<Option<u64>, std::io::Error>;
+
+    /// Copies a committed file into another committed file `new_id`, replacing the data `new_id` had.
+    /// Readers of `new_id` must see either the former data or the whole copy, never a partial one.
+    /// Returns `false` if there is no file to copy.
+    #[allow(dead_code)]
+    async fn copy(&self, id: Uuid, new_id: Uuid) -> Result<bool, std::io::Error>;
+
+    /// Lists all staging files in the storage system, including the ones no longer tracked, e.g. written before a crash.
+    /// Entries that are not named by an ID are not managed by the driver, and are skipped.
+    async fn list_staging(&self) -> Result<Vec<StagingEntry>, std::io::Error>;
+
+    /// Lists the IDs of all committed files in the storage system.
+    /// Entries that are not named by an ID are not managed by the driver, and are skipped.
+    async fn list(&self) -> Result<Vec<Uuid>, std::io::Error>;
+
+    /// Locates a file in the storage system.
+    /// The location must be returned even if the file does not exist, so that it can be recovered manually.
+    async fn locate(&self, id: Uuid) -> Result<StorageLocation, std::io::Error>;
+
+    /// Retrieves the space left for new data in the volumes of the storage system.
+    async fn free_space(&self) -> Result<FreeSpace, std::io::Error>;
+}
+
+/// Creates the file driver selected by the storage config.
+pub async fn create_file_driver(
+    app_config: &AppConfig,
+) -> Result<Arc<dyn FileDriver + Send + Sync>, std::io::Error> {
+    let file_driver = create_file_driver_for(app_config, &app_config.storage).await?;
+
+    // tests can make the storage fail on demand
+    #[cfg(test)]
+    let file_driver = Arc::new(faulty_file_driver::FaultyFileDriver::new(
+        file_driver,
+        app_config.test_faults.fail_commits.clone(),
+    ));
+
+    Ok(file_driver)
+}
+
+type CreatingFileDriver<'a> = Pin<
+    Box<dyn Future<Output = Result<Arc<dyn FileDriver + Send + Sync>, std::io::Error>> + Send + 'a>,
+>;
+
+/// Creates the file driver for `storage`. It is boxed, since tiered storages contain another storage.
+fn create_file_driver_for<'a>(
+    app_config: &'a AppConfig,
+    storage: &'a StorageConfig,
+) -> CreatingFileDriver<'a> {
+    Box::pin(async move {
+        let file_driver: Arc<dyn FileDriver + Send + Sync> = match storage {
+            StorageConfig::Local => {
+                let file_driver =
+                    LocalFileSystem::new(&app_config.temp_base_path, &app_config.file_base_path)
+                        .await?;
+                let file_driver = if app_config.file_read.positional {
+                    file_driver
+                        .with_positional_reads(app_config.file_read.buffer_size.as_u64() as usize)
+                } else {
+                    file_driver
+                };
+
+                Arc::new(file_driver)
+            }
+            StorageConfig::Memory => Arc::new(MemoryFileSystem::new(&app_config.temp_base_path)),
+            StorageConfig::Tiered {
+                cold,
+                cache_path,
+                cache_size,
+                eviction_policy,
+            } => {
+                let cold = create_file_driver_for(app_config, cold).await?;
+
+                Arc::new(
+                    TieredFileDriver::new(cold, cache_path, cache_size.as_u64(), *eviction_policy)
+                        .await?,
+                )
+            }
+        };
+
+        Ok(file_driver)
+    })
 }
@@ -0,0 +1,128 @@
+use super::{
+    FileDriver, FreeSpace, PresignedUpload, ReadError, ReadRange, StagingEntry, StorageLocation,
+    WriteError, WriteStream,
+};
+use async_trait::async_trait;
+use std::{
+    path::PathBuf,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::io::AsyncRead;
+use uuid::Uuid;
+
+/// A file driver that passes everything through to another driver, but fails commits while `fail_commits` is set.
+/// It is meant for tests of how the other services recover from a storage that is unavailable.
+pub struct FaultyFileDriver {
+    inner: Arc<dyn FileDriver + Send + Sync>,
+    fail_commits: Arc<AtomicBool>,
+}
+
+impl FaultyFileDriver {
+    pub fn new(inner: Arc<dyn FileDriver + Send + Sync>, fail_commits: Arc<AtomicBool>) -> Self {
+        Self {
+            inner,
+            fail_commits,
+        }
+    }
+}
+
+#[async_trait]
+impl FileDriver for FaultyFileDriver {
+    async fn write_staging(
+        &self,
+        id: Uuid,
+        offset: u64,
+        stream: WriteStream<'_>,
+    ) -> Result<i64, WriteError> {
+        self.inner.write_staging(id, offset, stream).await
+    }
+
+    async fn write_staging_at(
+        &self,
+        id: Uuid,
+        offset: u64,
+        stream: WriteStream<'_>,
+    ) -> Result<u64, WriteError> {
+        self.inner.write_staging_at(id, offset, stream).await
+    }
+
+    async fn remove_staging(&self, id: Uuid) -> Result<(), std::io::Error> {
+        self.inner.remove_staging(id).await
+    }
+
+    async fn read_staging(&self, id: Uuid) -> Result<Option<PathBuf>, std::io::Error> {
+        self.inner.read_staging(id).await
+    }
+
+    fn supports_direct_upload(&self) -> bool {
+        self.inner.supports_direct_upload()
+    }
+
+    async fn presign_staging_upload(
+        &self,
+        id: Uuid,
+        size: Option<u64>,
+        expires_in: Duration,
+    ) -> Result<PresignedUpload, std::io::Error> {
+        self.inner
+            .presign_staging_upload(id, size, expires_in)
+            .await
+    }
+
+    async fn fetch_staging_upload(&self, id: Uuid) -> Result<Option<u64>, std::io::Error> {
+        self.inner.fetch_staging_upload(id).await
+    }
+
+    async fn commit_staging(&self, id: Uuid) -> Result<(), std::io::Error> {
+        if self.fail_commits.load(Ordering::SeqCst) {
+            return Err(std::io::Error::other("the storage is unavailable"));
+        }
+
+        self.inner.commit_staging(id).await
+    }
+
+    async fn remove(&self, id: Uuid) -> Result<(), std::io::Error> {
+        self.inner.remove(id).await
+    }
+
+    async fn read(
+        &self,
+        id: Uuid,
+        range: ReadRange,
+    ) -> Result<Option<Pin<Box<dyn AsyncRead + Send>>>, ReadError> {
+        self.inner.read(id, range).await
+    }
+
+    async fn exists(&self, id: Uuid) -> Result<bool, std::io::Error> {
+        self.inner.exists(id).await
+    }
+
+    async fn size(&self, id: Uuid) -> Result<Option<u64>, std::io::Error> {
+        self.inner.size(id).await
+    }
+
+    async fn copy(&self, id: Uuid, new_id: Uuid) -> Result<bool, std::io::Error> {
+        self.inner.copy(id, new_id).await
+    }
+
+    async fn list_staging(&self) -> Result<Vec<StagingEntry>, std::io::Error> {
+        self.inner.list_staging().await
+    }
+
+    async fn list(&self) -> Result<Vec<Uuid>, std::io::Error> {
+        self.inner.list().await
+    }
+
+    async fn locate(&self, id: Uuid) -> Result<StorageLocation, std::io::Error> {
+        self.inner.locate(id).await
+    }
+
+    async fn free_space(&self) -> Result<FreeSpace, std::io::Error> {
+        self.inner.free_space().await
+    }
+}
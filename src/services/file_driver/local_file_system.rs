@@ -1,5 +1,12 @@
-use super::{FileDriver, ReadError, ReadRange, WriteError};
-use rocket::{async_trait, data::DataStream, tokio::fs::File};
+mod positional_reader;
+
+pub use positional_reader::*;
+
+use super::{
+    FileDriver, FreeSpace, ReadError, ReadRange, StagingEntry, StorageLocation, WriteError,
+    WriteStream,
+};
+use rocket::{async_trait, tokio::fs::File};
 use std::{fs::Metadata, path::PathBuf, pin::Pin};
 use tokio::{
     fs::OpenOptions,
@@ -11,6 +18,7 @@ pub struct LocalFileSystem {
     staging_path: PathBuf,
     resident_path: PathBuf,
     should_copy_files: bool,
+    positional_read_buffer_size: Option<usize>,
 }
 
 impl LocalFileSystem {
@@ -124,9 +132,17 @@ impl LocalFileSystem {
             staging_path,
             resident_path,
             should_copy_files,
+            positional_read_buffer_size: None,
         })
     }
 
+    /// Makes reads of resident files use [`PositionalReader`] with the given buffer size,
+    /// instead of a buffered reader over a seeked file.
+    pub fn with_positional_reads(mut self, buffer_size: usize) -> Self {
+        self.positional_read_buffer_size = Some(buffer_size);
+        self
+    }
+
     fn generate_staging_file_path(&self, id: Uuid) -> PathBuf {
         self.staging_path.join(id.to_string())
     }
@@ -142,7 +158,7 @@ impl FileDriver for LocalFileSystem {
         &self,
         id: Uuid,
         offset: u64,
-        mut stream: DataStream<'_>,
+        mut stream: WriteStream<'_>,
     ) -> Result<i64, WriteError> {
         fn make_write_error(io_error: std::io::Error, file_size: u64) -> WriteError {
             WriteError::Write {
@@ -232,6 +248,66 @@ impl FileDriver for LocalFileSystem {
         }
     }
 
+    async fn write_staging_at(
+        &self,
+        id: Uuid,
+        offset: u64,
+        mut stream: WriteStream<'_>,
+    ) -> Result<u64, WriteError> {
+        if (i64::MAX as u64) < offset {
+            return Err(WriteError::OffsetTooLarge {
+                max_offset: i64::MAX as u64,
+                offset,
+            });
+        }
+
+        let path = self.generate_staging_file_path(id);
+
+        // each writer has its own handle, so that seeking does not affect the others
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)
+            .await;
+        let mut file = match file {
+            Ok(file) => file,
+            Err(err) => {
+                log::error!(target: "file_driver", method="write_staging_at", id:serde, path:?, err:err; "Failed to open file.");
+                return Err(WriteError::Write {
+                    io_error: err,
+                    file_size: 0,
+                });
+            }
+        };
+
+        if let Err(err) = file.seek(SeekFrom::Start(offset)).await {
+            log::error!(target: "file_driver", method="write_staging_at", id:serde, path:?, err:err; "Failed to seek file.");
+            return Err(WriteError::Write {
+                io_error: err,
+                file_size: 0,
+            });
+        }
+
+        let copy_result = tokio::io::copy(&mut stream, &mut file).await;
+
+        if let Err(err) = file.flush().await {
+            log::error!(target: "file_driver", method="write_staging_at", id:serde, path:?, err:err; "Failed to flush file.");
+        }
+
+        match copy_result {
+            Ok(written) => Ok(written),
+            Err(err) => {
+                log::error!(target: "file_driver", method="write_staging_at", id:serde, path:?, err:err; "Failed to write to file.");
+                let file_size = file.metadata().await.map(|meta| meta.len()).unwrap_or(0);
+                Err(WriteError::Write {
+                    io_error: err,
+                    file_size,
+                })
+            }
+        }
+    }
+
     async fn remove_staging(&self, id: Uuid) -> Result<(), std::io::Error> {
         let path = self.generate_staging_file_path(id);
 
@@ -315,46 +391,268 @@ impl FileDriver for LocalFileSystem {
             }
         };
 
-        let reader: Pin<Box<dyn AsyncRead + Send>> = match read_range {
-            ReadRange::Full => Box::pin(BufReader::new(file)),
+        let (start, length) = match read_range {
+            ReadRange::Full => (0, file_size),
             ReadRange::Start(start) => {
                 if file_size <= start {
                     return Err(ReadError::RangeStartExceedsFileSize { start, file_size });
                 }
 
-                if let Err(err) = file.seek(SeekFrom::Start(start)).await {
-                    log::error!(target: "file_driver", method="read", id:serde, path:?, file_size, start, err:err; "Failed to seek file.");
-                    return Err(ReadError::Read { io_error: err });
-                }
-
-                Box::pin(BufReader::new(file))
+                (start, file_size - start)
             }
             ReadRange::Range(start, end) => {
                 if file_size <= end {
                     return Err(ReadError::RangeEndExceedsFileSize { end, file_size });
                 }
 
-                if let Err(err) = file.seek(SeekFrom::Start(start)).await {
-                    log::error!(target: "file_driver", method="read", id:serde, path:?, file_size, start, end, err:err; "Failed to seek file.");
-                    return Err(ReadError::Read { io_error: err });
-                }
-
-                Box::pin(BufReader::new(file.take(end - start + 1)))
+                (start, end - start + 1)
             }
             ReadRange::Suffix(suffix) => {
                 // it is allowed to specify a suffix that is larger than the file size.
                 // in that case, we just read the entire file instead.
                 let suffix = (suffix as u64).min(file_size);
 
-                if let Err(err) = file.seek(SeekFrom::End(-(suffix as i64))).await {
-                    log::error!(target: "file_driver", method="read", id:serde, path:?, file_size, suffix, err:err; "Failed to seek file.");
-                    return Err(ReadError::Read { io_error: err });
-                }
+                (file_size - suffix, suffix)
+            }
+        };
 
-                Box::pin(BufReader::new(file))
+        if let Some(buffer_size) = self.positional_read_buffer_size {
+            let file = file.into_std().await;
+            return Ok(Some(Box::pin(PositionalReader::new(
+                file,
+                start,
+                length,
+                buffer_size,
+            ))));
+        }
+
+        if start != 0 {
+            if let Err(err) = file.seek(SeekFrom::Start(start)).await {
+                log::error!(target: "file_driver", method="read", id:serde, path:?, file_size, start, length, err:err; "Failed to seek file.");
+                return Err(ReadError::Read { io_error: err });
             }
+        }
+
+        let reader: Pin<Box<dyn AsyncRead + Send>> = if start + length == file_size {
+            Box::pin(BufReader::new(file))
+        } else {
+            Box::pin(BufReader::new(file.take(length)))
         };
 
         Ok(Some(reader))
     }
+
+    async fn size(&self, id: Uuid) -> Result<Option<u64>, std::io::Error> {
+        let path = self.generate_resident_file_path(id);
+
+        match tokio::fs::metadata(&path).await {
+            Ok(meta) => Ok(Some(meta.len())),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => {
+                log::error!(target: "file_driver", method="size", id:serde, path:?, err:err; "Failed to get metadata of file.");
+                Err(err)
+            }
+        }
+    }
+
+    async fn copy(&self, id: Uuid, new_id: Uuid) -> Result<bool, std::io::Error> {
+        let path = self.generate_resident_file_path(id);
+        let new_path = self.generate_resident_file_path(new_id);
+        // the copy is written aside and renamed into place, so that readers never see a partial copy;
+        // the name is not an ID, so it is never listed as a file
+        let temp_path = self
+            .resident_path
+            .join(format!("{}.copy-{}", new_id, Uuid::new_v4()));
+
+        match tokio::fs::copy(&path, &temp_path).await {
+            Ok(_) => {}
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                tokio::fs::remove_file(&temp_path).await.ok();
+                return Ok(false);
+            }
+            Err(err) => {
+                log::error!(target: "file_driver", method="copy", id:serde, new_id:serde, path:?, temp_path:?, err:err; "Failed to copy file.");
+                tokio::fs::remove_file(&temp_path).await.ok();
+                return Err(err);
+            }
+        }
+
+        if let Err(err) = tokio::fs::rename(&temp_path, &new_path).await {
+            log::error!(target: "file_driver", method="copy", id:serde, new_id:serde, temp_path:?, new_path:?, err:err; "Failed to rename file.");
+            tokio::fs::remove_file(&temp_path).await.ok();
+            return Err(err);
+        }
+
+        Ok(true)
+    }
+
+    async fn list_staging(&self) -> Result<Vec<StagingEntry>, std::io::Error> {
+        let path = &self.staging_path;
+
+        let mut read_dir = match tokio::fs::read_dir(path).await {
+            Ok(read_dir) => read_dir,
+            Err(err) => {
+                log::error!(target: "file_driver", method="list_staging", path:?, err:err; "Failed to read directory.");
+                return Err(err);
+            }
+        };
+
+        let mut entries = Vec::new();
+
+        loop {
+            let entry = match read_dir.next_entry().await {
+                Ok(Some(entry)) => entry,
+                Ok(None) => break,
+                Err(err) => {
+                    log::error!(target: "file_driver", method="list_staging", path:?, err:err; "Failed to read directory entry.");
+                    return Err(err);
+                }
+            };
+
+            let id = entry
+                .file_name()
+                .to_str()
+                .and_then(|name| Uuid::try_parse(name).ok());
+            let id = match id {
+                Some(id) => id,
+                None => continue,
+            };
+
+            let modified_at = match entry.metadata().await.and_then(|meta| meta.modified()) {
+                Ok(modified_at) => modified_at,
+                // the staging file may have been committed or removed in the meantime
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(err) => {
+                    log::error!(target: "file_driver", method="list_staging", path:?, id:serde, err:err; "Failed to get metadata of file.");
+                    return Err(err);
+                }
+            };
+
+            entries.push(StagingEntry { id, modified_at });
+        }
+
+        Ok(entries)
+    }
+
+    async fn list(&self) -> Result<Vec<Uuid>, std::io::Error> {
+        let path = &self.resident_path;
+
+        let mut read_dir = match tokio::fs::read_dir(path).await {
+            Ok(read_dir) => read_dir,
+            Err(err) => {
+                log::error!(target: "file_driver", method="list", path:?, err:err; "Failed to read directory.");
+                return Err(err);
+            }
+        };
+
+        let mut ids = Vec::new();
+
+        loop {
+            let entry = match read_dir.next_entry().await {
+                Ok(Some(entry)) => entry,
+                Ok(None) => break,
+                Err(err) => {
+                    log::error!(target: "file_driver", method="list", path:?, err:err; "Failed to read directory entry.");
+                    return Err(err);
+                }
+            };
+
+            let id = entry
+                .file_name()
+                .to_str()
+                .and_then(|name| Uuid::try_parse(name).ok());
+
+            if let Some(id) = id {
+                ids.push(id);
+            }
+        }
+
+        Ok(ids)
+    }
+
+    async fn locate(&self, id: Uuid) -> Result<StorageLocation, std::io::Error> {
+        fn get_size_on_disk(meta: &Metadata) -> u64 {
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::MetadataExt;
+                meta.blocks() * 512
+            }
+            #[cfg(not(unix))]
+            {
+                meta.len()
+            }
+        }
+
+        let path = self.generate_resident_file_path(id);
+
+        let size_on_disk = match tokio::fs::metadata(&path).await {
+            Ok(meta) => Some(get_size_on_disk(&meta)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => None,
+            Err(err) => {
+                log::error!(target: "file_driver", method="locate", id:serde, path:?, err:err; "Failed to get metadata of file.");
+                return Err(err);
+            }
+        };
+
+        Ok(StorageLocation {
+            driver: "local".to_owned(),
+            tier: None,
+            location: path.display().to_string(),
+            size_on_disk,
+        })
+    }
+
+    async fn free_space(&self) -> Result<FreeSpace, std::io::Error> {
+        let staging_path = &self.staging_path;
+        let staging = match available_space(staging_path.clone()).await {
+            Ok(staging) => staging,
+            Err(err) => {
+                log::error!(target: "file_driver", method="free_space", staging_path:?, err:err; "Failed to get free space of staging path.");
+                return Err(err);
+            }
+        };
+
+        let resident_path = &self.resident_path;
+        let resident = match available_space(resident_path.clone()).await {
+            Ok(resident) => resident,
+            Err(err) => {
+                log::error!(target: "file_driver", method="free_space", resident_path:?, err:err; "Failed to get free space of resident path.");
+                return Err(err);
+            }
+        };
+
+        Ok(FreeSpace { staging, resident })
+    }
+}
+
+/// Retrieves the space available to unprivileged users in the volume of `path`.
+/// Returns `None` on platforms where it cannot be queried.
+async fn available_space(path: PathBuf) -> Result<Option<u64>, std::io::Error> {
+    #[cfg(unix)]
+    {
+        use std::{ffi::CString, mem::MaybeUninit, os::unix::ffi::OsStrExt};
+
+        tokio::task::spawn_blocking(move || {
+            let path = CString::new(path.as_os_str().as_bytes())
+                .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidInput, err))?;
+            let mut stat = MaybeUninit::<libc::statvfs>::uninit();
+
+            // SAFETY: `path` is a valid C string, and `stat` is only read if `statvfs` has filled it
+            if unsafe { libc::statvfs(path.as_ptr(), stat.as_mut_ptr()) } != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+
+            let stat = unsafe { stat.assume_init() };
+
+            // the widths of the fields differ between platforms
+            #[allow(clippy::unnecessary_cast)]
+            Ok(Some(stat.f_bavail as u64 * stat.f_frsize as u64))
+        })
+        .await?
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = path;
+        Ok(None)
+    }
 }
@@ -0,0 +1,222 @@
+use std::{
+    fs::File,
+    future::Future,
+    io::Error,
+    pin::Pin,
+    sync::Arc,
+    task::{ready, Context, Poll},
+};
+use tokio::{
+    io::{AsyncRead, ReadBuf},
+    task::JoinHandle,
+};
+
+/// A blocking read in progress. The buffer is handed back along with the result, so that it can be reused.
+type PendingRead = JoinHandle<(Vec<u8>, Result<usize, Error>)>;
+
+/// Reads a range of a file with positional reads (`pread` on Unix) of `buffer_size` bytes each.
+/// Unlike wrapping a [`tokio::fs::File`] in a [`tokio::io::BufReader`], the data is copied only once
+/// from the buffer filled by the blocking read, and the buffer is allocated once and reused across reads.
+/// As the reads carry their own offsets, the file is never seeked.
+pub struct PositionalReader {
+    file: Arc<File>,
+    position: u64,
+    remaining: u64,
+    buffer_size: usize,
+    buffer: Vec<u8>,
+    /// The number of bytes of `buffer` filled by the last read.
+    buffer_len: usize,
+    buffer_offset: usize,
+    pending: Option<PendingRead>,
+}
+
+impl PositionalReader {
+    /// Creates a reader of `length` bytes from `start`.
+    pub fn new(file: File, start: u64, length: u64, buffer_size: usize) -> Self {
+        let buffer_size = buffer_size.max(1);
+
+        Self {
+            file: Arc::new(file),
+            position: start,
+            remaining: length,
+            buffer_size,
+            // no read is larger than the range
+            buffer: vec![0; u64::min(buffer_size as u64, length) as usize],
+            buffer_len: 0,
+            buffer_offset: 0,
+            pending: None,
+        }
+    }
+}
+
+impl AsyncRead for PositionalReader {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<Result<(), Error>> {
+        let this = &mut *self;
+
+        loop {
+            if this.buffer_offset < this.buffer_len {
+                let len = usize::min(buf.remaining(), this.buffer_len - this.buffer_offset);
+                buf.put_slice(&this.buffer[this.buffer_offset..this.buffer_offset + len]);
+                this.buffer_offset += len;
+                return Poll::Ready(Ok(()));
+            }
+
+            if this.remaining == 0 {
+                return Poll::Ready(Ok(()));
+            }
+
+            let pending = this.pending.get_or_insert_with(|| {
+                let file = this.file.clone();
+                let position = this.position;
+                let len = u64::min(this.buffer_size as u64, this.remaining) as usize;
+                let mut buffer = std::mem::take(&mut this.buffer);
+
+                // the buffer is only lost if a previous read panicked
+                if buffer.len() < len {
+                    buffer.resize(this.buffer_size, 0);
+                }
+
+                tokio::task::spawn_blocking(move || {
+                    let result = read_at(&file, &mut buffer[..len], position);
+                    (buffer, result)
+                })
+            });
+
+            let result = ready!(Pin::new(pending).poll(cx));
+            this.pending = None;
+
+            let read = match result {
+                Ok((buffer, result)) => {
+                    this.buffer = buffer;
+                    result?
+                }
+                Err(err) => return Poll::Ready(Err(Error::other(err))),
+            };
+
+            // the file has been truncated after the range was computed
+            if read == 0 {
+                this.remaining = 0;
+                return Poll::Ready(Ok(()));
+            }
+
+            this.position += read as u64;
+            this.remaining = this.remaining.saturating_sub(read as u64);
+            this.buffer_len = read;
+            this.buffer_offset = 0;
+        }
+    }
+}
+
+fn read_at(file: &File, buffer: &mut [u8], offset: u64) -> Result<usize, Error> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::FileExt;
+        file.read_at(buffer, offset)
+    }
+    #[cfg(windows)]
+    {
+        use std::os::windows::fs::FileExt;
+        file.seek_read(buffer, offset)
+    }
+    #[cfg(not(any(unix, windows)))]
+    {
+        let _ = (file, buffer, offset);
+        Err(Error::new(
+            std::io::ErrorKind::Unsupported,
+            "positional reads are not supported on this platform",
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncReadExt;
+    use uuid::Uuid;
+
+    const CONTENT: &[u8] = b"0123456789";
+
+    /// Reads `length` bytes from `start` of a file holding `CONTENT`, `chunk_size` bytes at a time.
+    async fn read_range(start: u64, length: u64, buffer_size: usize, chunk_size: usize) -> Vec<u8> {
+        let path =
+            std::env::temp_dir().join(format!("__test_positional_reader_{}", Uuid::new_v4()));
+        std::fs::write(&path, CONTENT).unwrap();
+
+        let file = File::open(&path).unwrap();
+        let mut reader = PositionalReader::new(file, start, length, buffer_size);
+        let mut data = Vec::new();
+        let mut chunk = vec![0; chunk_size];
+
+        loop {
+            let read = reader.read(&mut chunk).await.unwrap();
+
+            if read == 0 {
+                break;
+            }
+
+            assert!(read <= chunk_size);
+            data.extend_from_slice(&chunk[..read]);
+        }
+
+        std::fs::remove_file(&path).unwrap();
+        data
+    }
+
+    #[rocket::async_test]
+    async fn test_read_whole_range() {
+        assert_eq!(read_range(0, 10, 4, 64).await, CONTENT);
+        assert_eq!(read_range(0, 10, 64, 64).await, CONTENT);
+    }
+
+    #[rocket::async_test]
+    async fn test_read_into_short_buffers() {
+        // each read returns less than the buffer holds, so the rest is served from the buffer
+        assert_eq!(read_range(0, 10, 4, 3).await, CONTENT);
+        assert_eq!(read_range(1, 8, 64, 1).await, &CONTENT[1..9]);
+    }
+
+    #[rocket::async_test]
+    async fn test_read_range_ending_mid_buffer() {
+        assert_eq!(read_range(2, 7, 4, 64).await, &CONTENT[2..9]);
+        assert_eq!(read_range(3, 2, 4, 64).await, &CONTENT[3..5]);
+    }
+
+    #[rocket::async_test]
+    async fn test_read_empty_range() {
+        assert_eq!(read_range(0, 0, 4, 64).await, b"");
+        assert_eq!(read_range(10, 0, 4, 64).await, b"");
+    }
+
+    #[rocket::async_test]
+    async fn test_read_range_past_end() {
+        // the file ends before the range does
+        assert_eq!(read_range(6, 100, 4, 64).await, &CONTENT[6..]);
+        assert_eq!(read_range(10, 5, 4, 64).await, b"");
+        assert_eq!(read_range(20, 5, 4, 64).await, b"");
+    }
+
+    #[test]
+    fn test_read_at() {
+        let path =
+            std::env::temp_dir().join(format!("__test_positional_reader_{}", Uuid::new_v4()));
+        std::fs::write(&path, CONTENT).unwrap();
+        let file = File::open(&path).unwrap();
+
+        let mut buffer = [0; 4];
+        assert_eq!(read_at(&file, &mut buffer, 3).unwrap(), 4);
+        assert_eq!(&buffer, b"3456");
+
+        // a read crossing the end of the file is short
+        assert_eq!(read_at(&file, &mut buffer, 8).unwrap(), 2);
+        assert_eq!(&buffer[..2], b"89");
+
+        assert_eq!(read_at(&file, &mut buffer, 10).unwrap(), 0);
+        assert_eq!(read_at(&file, &mut buffer[..0], 0).unwrap(), 0);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,273 @@
+use super::{
+    FileDriver, FreeSpace, ReadError, ReadRange, StagingEntry, StorageLocation, WriteError,
+    WriteStream,
+};
+use async_trait::async_trait;
+use bytes::{Bytes, BytesMut};
+use dashmap::DashMap;
+use std::{io::Cursor, path::PathBuf, pin::Pin, time::SystemTime};
+use tokio::io::{AsyncRead, AsyncReadExt};
+use uuid::Uuid;
+
+/// A file driver that keeps every file in memory. It is meant for tests, where it keeps them fast and hermetic.
+///
+/// Staging files are handed out by path through [`FileDriver::read_staging`], since the metadata of a file is extracted from a path.
+/// For that, the data of a staging file is spilled into `spill_path` on demand, and removed once the staging file is committed or removed.
+pub struct MemoryFileSystem {
+    spill_path: PathBuf,
+    staging_files: DashMap<Uuid, BytesMut>,
+    staging_modified_at: DashMap<Uuid, SystemTime>,
+    resident_files: DashMap<Uuid, Bytes>,
+}
+
+impl MemoryFileSystem {
+    pub fn new(spill_path: impl Into<PathBuf>) -> Self {
+        Self {
+            spill_path: spill_path.into(),
+            staging_files: DashMap::new(),
+            staging_modified_at: DashMap::new(),
+            resident_files: DashMap::new(),
+        }
+    }
+
+    fn generate_spill_file_path(&self, id: Uuid) -> PathBuf {
+        self.spill_path.join(format!("memory-{}", id))
+    }
+
+    /// Reads the whole stream into memory, so that no lock is held while awaiting it.
+    /// The data read before an error is returned along with the error.
+    async fn read_stream(mut stream: WriteStream<'_>) -> (Vec<u8>, Option<std::io::Error>) {
+        let mut data = Vec::new();
+
+        match stream.read_to_end(&mut data).await {
+            Ok(_) => (data, None),
+            Err(err) => (data, Some(err)),
+        }
+    }
+}
+
+#[async_trait]
+impl FileDriver for MemoryFileSystem {
+    async fn write_staging(
+        &self,
+        id: Uuid,
+        offset: u64,
+        stream: WriteStream<'_>,
+    ) -> Result<i64, WriteError> {
+        let initial_file_size = self
+            .staging_files
+            .get(&id)
+            .map(|file| file.len() as u64)
+            .unwrap_or(0);
+
+        if initial_file_size < offset {
+            return Err(WriteError::OffsetExceedsFileSize {
+                offset,
+                file_size: initial_file_size,
+            });
+        }
+
+        if (i64::MAX as u128) < offset as u128 + initial_file_size as u128 {
+            return Err(WriteError::OffsetTooLarge {
+                max_offset: i64::MAX as u64,
+                offset,
+            });
+        }
+
+        let (data, err) = Self::read_stream(stream).await;
+
+        let mut file = self.staging_files.entry(id).or_default();
+        let offset = offset as usize;
+        let end = offset + data.len();
+
+        if file.len() < end {
+            file.resize(end, 0);
+        }
+
+        file[offset..end].copy_from_slice(&data);
+        let file_size = file.len() as u64;
+        self.staging_modified_at.insert(id, SystemTime::now());
+
+        match err {
+            Some(io_error) => Err(WriteError::Write {
+                io_error,
+                file_size,
+            }),
+            None => Ok(file_size as i64),
+        }
+    }
+
+    async fn write_staging_at(
+        &self,
+        id: Uuid,
+        offset: u64,
+        stream: WriteStream<'_>,
+    ) -> Result<u64, WriteError> {
+        if (i64::MAX as u64) < offset {
+            return Err(WriteError::OffsetTooLarge {
+                max_offset: i64::MAX as u64,
+                offset,
+            });
+        }
+
+        let (data, err) = Self::read_stream(stream).await;
+
+        let mut file = self.staging_files.entry(id).or_default();
+        let offset = offset as usize;
+        let end = offset + data.len();
+
+        // the gap before the offset is filled with zeros, like a sparse file
+        if file.len() < end {
+            file.resize(end, 0);
+        }
+
+        file[offset..end].copy_from_slice(&data);
+        self.staging_modified_at.insert(id, SystemTime::now());
+
+        match err {
+            Some(io_error) => Err(WriteError::Write {
+                io_error,
+                file_size: file.len() as u64,
+            }),
+            None => Ok(data.len() as u64),
+        }
+    }
+
+    async fn remove_staging(&self, id: Uuid) -> Result<(), std::io::Error> {
+        tokio::fs::remove_file(self.generate_spill_file_path(id))
+            .await
+            .ok();
+        self.staging_modified_at.remove(&id);
+
+        match self.staging_files.remove(&id) {
+            Some(_) => Ok(()),
+            None => Err(std::io::ErrorKind::NotFound.into()),
+        }
+    }
+
+    async fn read_staging(&self, id: Uuid) -> Result<Option<PathBuf>, std::io::Error> {
+        let data = match self.staging_files.get(&id) {
+            Some(file) => file.clone().freeze(),
+            None => return Ok(None),
+        };
+
+        let path = self.generate_spill_file_path(id);
+
+        if let Err(err) = tokio::fs::write(&path, &data).await {
+            log::error!(target: "file_driver", method="read_staging", id:serde, path:?, err:err; "Failed to spill file.");
+            return Err(err);
+        }
+
+        Ok(Some(path))
+    }
+
+    async fn commit_staging(&self, id: Uuid) -> Result<(), std::io::Error> {
+        let (_, file) = match self.staging_files.remove(&id) {
+            Some(file) => file,
+            None => return Err(std::io::ErrorKind::NotFound.into()),
+        };
+
+        self.staging_modified_at.remove(&id);
+        self.resident_files.insert(id, file.freeze());
+        tokio::fs::remove_file(self.generate_spill_file_path(id))
+            .await
+            .ok();
+
+        Ok(())
+    }
+
+    async fn remove(&self, id: Uuid) -> Result<(), std::io::Error> {
+        match self.resident_files.remove(&id) {
+            Some(_) => Ok(()),
+            None => Err(std::io::ErrorKind::NotFound.into()),
+        }
+    }
+
+    async fn read(
+        &self,
+        id: Uuid,
+        read_range: ReadRange,
+    ) -> Result<Option<Pin<Box<dyn AsyncRead + Send>>>, ReadError> {
+        // cloning `Bytes` only bumps a reference count
+        let data = match self.resident_files.get(&id) {
+            Some(file) => file.clone(),
+            None => return Ok(None),
+        };
+        let file_size = data.len() as u64;
+
+        let (start, length) = match read_range {
+            ReadRange::Full => (0, file_size),
+            ReadRange::Start(start) => {
+                if file_size <= start {
+                    return Err(ReadError::RangeStartExceedsFileSize { start, file_size });
+                }
+
+                (start, file_size - start)
+            }
+            ReadRange::Range(start, end) => {
+                if file_size <= end {
+                    return Err(ReadError::RangeEndExceedsFileSize { end, file_size });
+                }
+
+                (start, end - start + 1)
+            }
+            ReadRange::Suffix(suffix) => {
+                let suffix = (suffix as u64).min(file_size);
+
+                (file_size - suffix, suffix)
+            }
+        };
+
+        let data = data.slice(start as usize..(start + length) as usize);
+
+        Ok(Some(Box::pin(Cursor::new(data))))
+    }
+
+    async fn size(&self, id: Uuid) -> Result<Option<u64>, std::io::Error> {
+        Ok(self.resident_files.get(&id).map(|file| file.len() as u64))
+    }
+
+    async fn copy(&self, id: Uuid, new_id: Uuid) -> Result<bool, std::io::Error> {
+        let data = match self.resident_files.get(&id) {
+            Some(file) => file.clone(),
+            None => return Ok(false),
+        };
+
+        self.resident_files.insert(new_id, data);
+
+        Ok(true)
+    }
+
+    async fn list_staging(&self) -> Result<Vec<StagingEntry>, std::io::Error> {
+        Ok(self
+            .staging_modified_at
+            .iter()
+            .map(|entry| StagingEntry {
+                id: *entry.key(),
+                modified_at: *entry.value(),
+            })
+            .collect())
+    }
+
+    async fn list(&self) -> Result<Vec<Uuid>, std::io::Error> {
+        Ok(self.resident_files.iter().map(|file| *file.key()).collect())
+    }
+
+    async fn locate(&self, id: Uuid) -> Result<StorageLocation, std::io::Error> {
+        let size_on_disk = self.resident_files.get(&id).map(|file| file.len() as u64);
+
+        Ok(StorageLocation {
+            driver: "memory".to_owned(),
+            tier: None,
+            location: format!("memory://{}", id),
+            size_on_disk,
+        })
+    }
+
+    async fn free_space(&self) -> Result<FreeSpace, std::io::Error> {
+        Ok(FreeSpace {
+            staging: None,
+            resident: None,
+        })
+    }
+}
@@ -0,0 +1,322 @@
+use super::{
+    local_file_system::LocalFileSystem, FileDriver, FreeSpace, PresignedUpload, ReadError,
+    ReadRange, StagingEntry, StorageLocation, WriteError, WriteStream,
+};
+use async_trait::async_trait;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+    pin::Pin,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::io::AsyncRead;
+use uuid::Uuid;
+
+/// The policy to pick the cached file to evict when the cache is full.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum CacheEvictionPolicy {
+    /// Evicts the file that has not been read for the longest time.
+    #[default]
+    Lru,
+    /// Evicts the file that has been cached for the longest time.
+    Fifo,
+}
+
+struct CachedFile {
+    size_on_disk: u64,
+    cached_at: Instant,
+    last_access: Instant,
+}
+
+/// A file driver that writes files to a cold driver, usually a remote one,
+/// and keeps the recently read files in a bounded cache on the local disk.
+/// Reads of cached files, including range reads, never reach the cold driver.
+pub struct TieredFileDriver {
+    cold: Arc<dyn FileDriver + Send + Sync>,
+    hot: LocalFileSystem,
+    max_cache_size: u64,
+    eviction_policy: CacheEvictionPolicy,
+    cached_files: Mutex<HashMap<Uuid, CachedFile>>,
+    /// The files being copied into the cache, so that concurrent misses copy them only once.
+    filling_files: Mutex<HashSet<Uuid>>,
+}
+
+impl TieredFileDriver {
+    /// Creates a tiered driver over `cold`, caching up to `max_cache_size` bytes under `cache_path`.
+    /// The files already in the cache are kept, and evicted first if the cache is over the limit.
+    pub async fn new(
+        cold: Arc<dyn FileDriver + Send + Sync>,
+        cache_path: impl Into<PathBuf>,
+        max_cache_size: u64,
+        eviction_policy: CacheEvictionPolicy,
+    ) -> Result<Self, std::io::Error> {
+        let cache_path = cache_path.into();
+        let hot = LocalFileSystem::new(cache_path.join(".staging"), &cache_path).await?;
+
+        let now = Instant::now();
+        let mut cached_files = HashMap::new();
+
+        for id in hot.list().await? {
+            let size_on_disk = hot.locate(id).await?.size_on_disk.unwrap_or(0);
+            cached_files.insert(
+                id,
+                CachedFile {
+                    size_on_disk,
+                    cached_at: now,
+                    last_access: now,
+                },
+            );
+        }
+
+        log::info!(target: "file_driver", method = "new", cache_path:?, cached_files = cached_files.len(), max_cache_size; "Hot cache is ready.");
+
+        let driver = Self {
+            cold,
+            hot,
+            max_cache_size,
+            eviction_policy,
+            cached_files: Mutex::new(cached_files),
+            filling_files: Mutex::new(HashSet::new()),
+        };
+        driver.evict(None).await;
+
+        Ok(driver)
+    }
+
+    /// Evicts cached files until the cache fits in its limit. `keep` is never evicted.
+    async fn evict(&self, keep: Option<Uuid>) {
+        let evicted = {
+            let mut cached_files = self.cached_files.lock();
+            let mut cache_size = cached_files
+                .values()
+                .map(|file| file.size_on_disk)
+                .sum::<u64>();
+            let mut evicted = Vec::new();
+
+            while self.max_cache_size < cache_size {
+                let victim = cached_files
+                    .iter()
+                    .filter(|(id, _)| Some(**id) != keep)
+                    .min_by_key(|(_, file)| match self.eviction_policy {
+                        CacheEvictionPolicy::Lru => file.last_access,
+                        CacheEvictionPolicy::Fifo => file.cached_at,
+                    })
+                    .map(|(id, _)| *id);
+                let victim = match victim {
+                    Some(victim) => victim,
+                    None => break,
+                };
+
+                if let Some(file) = cached_files.remove(&victim) {
+                    cache_size -= file.size_on_disk;
+                }
+
+                evicted.push(victim);
+            }
+
+            evicted
+        };
+
+        for id in evicted {
+            if let Err(err) = self.hot.remove(id).await {
+                log::warn!(target: "file_driver", method = "evict", id:serde, err:err; "Failed to evict file from hot cache.");
+            }
+        }
+    }
+
+    /// Copies a file from the cold driver into the cache.
+    /// Returns `false` if the file was not cached, because it is missing, too large, or being cached by another read.
+    async fn fill(&self, id: Uuid) -> Result<bool, ReadError> {
+        if !self.filling_files.lock().insert(id) {
+            return Ok(false);
+        }
+
+        let result = self.fill_exclusively(id).await;
+        self.filling_files.lock().remove(&id);
+
+        result
+    }
+
+    async fn fill_exclusively(&self, id: Uuid) -> Result<bool, ReadError> {
+        let reader = match self.cold.read(id, ReadRange::Full).await? {
+            Some(reader) => reader,
+            None => return Ok(false),
+        };
+
+        // a partial copy left by a previous failure must not be written over
+        self.hot.remove_staging(id).await.ok();
+
+        if let Err(err) = self.hot.write_staging(id, 0, reader).await {
+            self.hot.remove_staging(id).await.ok();
+
+            return Err(match err {
+                WriteError::Write { io_error, .. } => ReadError::Read { io_error },
+                err => ReadError::Read {
+                    io_error: std::io::Error::other(err),
+                },
+            });
+        }
+
+        self.hot.commit_staging(id).await?;
+
+        let size_on_disk = self.hot.locate(id).await?.size_on_disk.unwrap_or(0);
+
+        if self.max_cache_size < size_on_disk {
+            self.hot.remove(id).await.ok();
+            return Ok(false);
+        }
+
+        let now = Instant::now();
+        self.cached_files.lock().insert(
+            id,
+            CachedFile {
+                size_on_disk,
+                cached_at: now,
+                last_access: now,
+            },
+        );
+        self.evict(Some(id)).await;
+
+        Ok(true)
+    }
+
+    /// Reads a file from the cache, marking it as accessed.
+    async fn read_hot(
+        &self,
+        id: Uuid,
+        range: &ReadRange,
+    ) -> Result<Option<Pin<Box<dyn AsyncRead + Send>>>, ReadError> {
+        match self.cached_files.lock().get_mut(&id) {
+            Some(file) => file.last_access = Instant::now(),
+            None => return Ok(None),
+        }
+
+        // the file may have been evicted in the meantime, which is reported as `None`
+        self.hot.read(id, range.clone()).await
+    }
+}
+
+#[async_trait]
+impl FileDriver for TieredFileDriver {
+    async fn write_staging(
+        &self,
+        id: Uuid,
+        offset: u64,
+        stream: WriteStream<'_>,
+    ) -> Result<i64, WriteError> {
+        self.cold.write_staging(id, offset, stream).await
+    }
+
+    async fn write_staging_at(
+        &self,
+        id: Uuid,
+        offset: u64,
+        stream: WriteStream<'_>,
+    ) -> Result<u64, WriteError> {
+        self.cold.write_staging_at(id, offset, stream).await
+    }
+
+    async fn remove_staging(&self, id: Uuid) -> Result<(), std::io::Error> {
+        self.cold.remove_staging(id).await
+    }
+
+    async fn read_staging(&self, id: Uuid) -> Result<Option<PathBuf>, std::io::Error> {
+        self.cold.read_staging(id).await
+    }
+
+    fn supports_direct_upload(&self) -> bool {
+        self.cold.supports_direct_upload()
+    }
+
+    async fn presign_staging_upload(
+        &self,
+        id: Uuid,
+        size: Option<u64>,
+        expires_in: Duration,
+    ) -> Result<PresignedUpload, std::io::Error> {
+        self.cold.presign_staging_upload(id, size, expires_in).await
+    }
+
+    async fn fetch_staging_upload(&self, id: Uuid) -> Result<Option<u64>, std::io::Error> {
+        self.cold.fetch_staging_upload(id).await
+    }
+
+    async fn commit_staging(&self, id: Uuid) -> Result<(), std::io::Error> {
+        self.cold.commit_staging(id).await?;
+
+        // the committed data may replace a file that is still in the cache
+        if self.cached_files.lock().remove(&id).is_some() {
+            self.hot.remove(id).await.ok();
+        }
+
+        Ok(())
+    }
+
+    async fn remove(&self, id: Uuid) -> Result<(), std::io::Error> {
+        if self.cached_files.lock().remove(&id).is_some() {
+            self.hot.remove(id).await.ok();
+        }
+
+        self.cold.remove(id).await
+    }
+
+    async fn read(
+        &self,
+        id: Uuid,
+        range: ReadRange,
+    ) -> Result<Option<Pin<Box<dyn AsyncRead + Send>>>, ReadError> {
+        if let Some(reader) = self.read_hot(id, &range).await? {
+            return Ok(Some(reader));
+        }
+
+        if self.fill(id).await? {
+            if let Some(reader) = self.read_hot(id, &range).await? {
+                return Ok(Some(reader));
+            }
+        }
+
+        self.cold.read(id, range).await
+    }
+
+    async fn size(&self, id: Uuid) -> Result<Option<u64>, std::io::Error> {
+        self.cold.size(id).await
+    }
+
+    async fn copy(&self, id: Uuid, new_id: Uuid) -> Result<bool, std::io::Error> {
+        let copied = self.cold.copy(id, new_id).await?;
+
+        // the copied data may replace a file that is still in the cache
+        if copied && self.cached_files.lock().remove(&new_id).is_some() {
+            self.hot.remove(new_id).await.ok();
+        }
+
+        Ok(copied)
+    }
+
+    async fn list_staging(&self) -> Result<Vec<StagingEntry>, std::io::Error> {
+        self.cold.list_staging().await
+    }
+
+    async fn list(&self) -> Result<Vec<Uuid>, std::io::Error> {
+        self.cold.list().await
+    }
+
+    async fn locate(&self, id: Uuid) -> Result<StorageLocation, std::io::Error> {
+        let is_cached = self.cached_files.lock().contains_key(&id);
+        let location = self.cold.locate(id).await?;
+
+        Ok(StorageLocation {
+            tier: Some(if is_cached { "hot" } else { "cold" }.to_owned()),
+            ..location
+        })
+    }
+
+    async fn free_space(&self) -> Result<FreeSpace, std::io::Error> {
+        // the cache evicts files to stay within its size, so only the cold storage can run out of space
+        self.cold.free_space().await
+    }
+}
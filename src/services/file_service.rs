@@ -1,15 +1,36 @@
 mod compute_file_hash;
 mod compute_file_mime;
+mod file_cache;
+
+pub use file_cache::FileCacheStats;
 
 use super::{
-    FileDriver, ReadError, ReadRange, SearchService, StagingFileService, StagingFileServiceError,
+    ContentExtractionService, FileDriver, FileMetadata, MetadataService, Page,
+    PerceptualHashService, ReadError, ReadRange, ScanResult, ScanStatus, ScannerService,
+    ScannerServiceError, SearchService, SearchServiceError, SortDirection, StagingFileService,
+    StagingFileServiceError, StorageLocation, UnitOfWork, UnitOfWorkServiceError, WriteError,
+};
+use crate::{
+    config::{AppFileCache, AppMimePolicy, InfectedFileAction},
+    db::{
+        models::{
+            CreatingFile, CreatingPendingCommit, File, FileVersion, PendingCommit, StagingFile,
+            StagingFileChunk,
+        },
+        ReadPool,
+    },
+};
+use chrono::{NaiveDateTime, Utc};
+use diesel::{
+    pg::Pg, BoolExpressionMethods, ExpressionMethods, NullableExpressionMethods, OptionalExtension,
+    QueryDsl, Queryable, TextExpressionMethods,
 };
-use crate::db::models::{CreatingFile, File};
-use diesel::{BoolExpressionMethods, ExpressionMethods, OptionalExtension, QueryDsl};
 use diesel_async::{
     pooled_connection::deadpool::Pool, scoped_futures::ScopedFutureExt, AsyncConnection,
     AsyncPgConnection, RunQueryDsl,
 };
+use file_cache::FileCache;
+use serde::{Deserialize, Serialize};
 use std::{pin::Pin, sync::Arc};
 use thiserror::Error;
 use tokio::io::AsyncRead;
@@ -23,115 +44,718 @@ pub enum FileServiceError {
     Diesel(#[from] diesel::result::Error),
     #[error("staging file service error: {0}")]
     StagingFileService(#[from] StagingFileServiceError),
+    #[error("unit of work error: {0}")]
+    UnitOfWork(#[from] UnitOfWorkServiceError),
     #[error("file is not yet filled; upload it first")]
     FileNotYetFilled,
+    #[error("file hash `{actual}` does not match the expected hash `{expected}`")]
+    HashMismatch { expected: u32, actual: u32 },
     #[error("io error: {0}")]
     IO(#[from] std::io::Error),
     #[error("compute file mime error: {0}")]
     ComputeMime(#[from] compute_file_mime::ComputeFileMimeError),
     #[error("compute file hash error: {0}")]
     ComputeHash(#[from] compute_file_hash::ComputeFileHashError),
+    #[error("search service error: {0}")]
+    SearchService(#[from] SearchServiceError),
+    #[error("read error: {0}")]
+    Read(#[from] ReadError),
+    #[error("write error: {0}")]
+    Write(#[from] WriteError),
+    #[error("data of `{0}` is missing from the storage")]
+    DataMissing(Uuid),
+    #[error("scanner service error: {0}")]
+    Scanner(#[from] ScannerServiceError),
+    #[error("malware `{signature}` has been found in the file")]
+    Infected { signature: String },
+    #[error("MIME type `{mime}` is not allowed by the policy")]
+    MimeNotAllowed { mime: String },
+}
+
+/// The step of a file promotion that is left to run after the file is inserted into the database.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PendingCommitStage {
+    /// The staging data has to be committed to the file driver.
+    Data,
+    /// The file has to be indexed.
+    Index,
+}
+
+impl PendingCommitStage {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "data" => Some(Self::Data),
+            "index" => Some(Self::Index),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Data => "data",
+            Self::Index => "index",
+        }
+    }
+}
+
+/// The key to sort files by. Files with the same key are sorted by ID.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum FileSort {
+    Name,
+    Id,
+    Size,
+    UploadedAt,
+    Downloads,
+}
+
+impl FileSort {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "name" => Some(Self::Name),
+            "id" => Some(Self::Id),
+            "size" => Some(Self::Size),
+            "uploaded_at" => Some(Self::UploadedAt),
+            "downloads" => Some(Self::Downloads),
+            _ => None,
+        }
+    }
+}
+
+/// The sort key of the file a page starts after.
+enum FileSortKey {
+    Id,
+    Name(String),
+    Size(i64),
+    UploadedAt(NaiveDateTime),
+    Downloads(i64),
+}
+
+/// Conditions files must meet to be listed. Unset conditions match every file.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct FileFilter {
+    /// The exact MIME type, or a type followed by `/*` to match all of its subtypes, e.g. `image/*`.
+    pub mime: Option<String>,
+    pub min_size: Option<i64>,
+    pub max_size: Option<i64>,
+    pub uploaded_after: Option<NaiveDateTime>,
+    pub uploaded_before: Option<NaiveDateTime>,
+}
+
+impl FileFilter {
+    fn apply<'a>(
+        &self,
+        mut query: crate::db::schema::files::BoxedQuery<'a, Pg>,
+    ) -> crate::db::schema::files::BoxedQuery<'a, Pg> {
+        use crate::db::schema;
+
+        if let Some(mime) = &self.mime {
+            query = match mime.strip_suffix("/*") {
+                Some(mime_type) => {
+                    let escaped = mime_type
+                        .replace('\\', "\\\\")
+                        .replace('%', "\\%")
+                        .replace('_', "\\_");
+                    query.filter(schema::files::mime.like(format!("{}/%", escaped)))
+                }
+                None => query.filter(schema::files::mime.eq(mime.clone())),
+            };
+        }
+
+        if let Some(min_size) = self.min_size {
+            query = query.filter(schema::files::size.ge(min_size));
+        }
+
+        if let Some(max_size) = self.max_size {
+            query = query.filter(schema::files::size.le(max_size));
+        }
+
+        if let Some(uploaded_after) = self.uploaded_after {
+            query = query.filter(schema::files::uploaded_at.gt(uploaded_after));
+        }
+
+        if let Some(uploaded_before) = self.uploaded_before {
+            query = query.filter(schema::files::uploaded_at.lt(uploaded_before));
+        }
+
+        query
+    }
+}
+
+/// A group of files sharing the same size and hash, which are likely to be duplicates.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct DuplicateFileGroup {
+    pub size: i64,
+    pub hash: i64,
+    pub files: Vec<File>,
+}
+
+/// Where and how a file is stored, for recovering and debugging storage issues.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct FileStorageInfo {
+    pub file_id: Uuid,
+    /// The size of the file as recorded in the database.
+    pub size: i64,
+    /// The last time the stored content was confirmed to match the recorded hash.
+    pub verified_at: Option<NaiveDateTime>,
+    #[serde(flatten)]
+    pub storage: StorageLocation,
+}
+
+/// The result of the last malware scan of a file.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct FileScan {
+    pub file_id: Uuid,
+    /// The verdict, or `None` if the file has not been scanned, e.g. because scanning was disabled when it was promoted.
+    pub status: Option<ScanStatus>,
+    /// The name of the malware found, if any.
+    pub signature: Option<String>,
+    pub scanned_at: Option<NaiveDateTime>,
+}
+
+/// A file whose image looks similar to another.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SimilarFile {
+    #[serde(flatten)]
+    pub file: File,
+    /// The Hamming distance between the perceptual hashes of the images; `0` means they look the same.
+    pub distance: i32,
+}
+
+impl FileScan {
+    /// Whether the file has been promoted despite being infected, and must not be served.
+    pub fn is_quarantined(&self) -> bool {
+        self.status == Some(ScanStatus::Infected)
+    }
+}
+
+/// How often a file has been downloaded.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct FileStats {
+    pub file_id: Uuid,
+    /// The number of reads that started from the beginning of the file.
+    pub downloads: i64,
+    /// The number of bytes served by all reads, including partial ones.
+    pub served_bytes: i64,
+    pub last_downloaded_at: Option<NaiveDateTime>,
+}
+
+/// The columns describing the data of a file, which move along with the data between a file and its versions.
+#[derive(Queryable)]
+struct FileRevision {
+    name: String,
+    mime: String,
+    size: i64,
+    hash: i64,
+    uploaded_at: NaiveDateTime,
+    file_metadata: Option<serde_json::Value>,
+    search_content: Option<String>,
+    scan_status: Option<String>,
+    scan_signature: Option<String>,
+    scanned_at: Option<NaiveDateTime>,
+    perceptual_hash: Option<i64>,
 }
 
 pub struct FileService {
     db_pool: Pool<AsyncPgConnection>,
+    /// The pool for listing files, which may be served by a read replica.
+    read_pool: ReadPool,
     staging_file_service: Arc<StagingFileService>,
-    search_service: Arc<SearchService>,
+    search_service: Arc<dyn SearchService + Send + Sync>,
+    metadata_service: Arc<MetadataService>,
+    content_extraction_service: Arc<ContentExtractionService>,
+    scanner_service: Arc<ScannerService>,
+    perceptual_hash_service: Arc<PerceptualHashService>,
     file_driver: Arc<dyn FileDriver + Send + Sync>,
+    /// The cache of file records looked up by their IDs.
+    file_cache: FileCache,
+    /// The number of former versions kept for each file; `0` keeps all of them.
+    max_file_versions: u32,
+    /// Which MIME types may be promoted.
+    mime_policy: AppMimePolicy,
 }
 
 impl FileService {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         db_pool: Pool<AsyncPgConnection>,
+        read_pool: ReadPool,
         staging_file_service: Arc<StagingFileService>,
-        search_service: Arc<SearchService>,
-        file_driver: Arc<impl 'static + FileDriver + Send + Sync>,
+        search_service: Arc<dyn SearchService + Send + Sync>,
+        metadata_service: Arc<MetadataService>,
+        content_extraction_service: Arc<ContentExtractionService>,
+        scanner_service: Arc<ScannerService>,
+        perceptual_hash_service: Arc<PerceptualHashService>,
+        file_driver: Arc<dyn FileDriver + Send + Sync>,
+        file_cache_config: &AppFileCache,
+        max_file_versions: u32,
+        mime_policy: &AppMimePolicy,
     ) -> Arc<Self> {
         Arc::new(Self {
             db_pool,
+            read_pool,
             staging_file_service,
             search_service,
+            metadata_service,
+            content_extraction_service,
+            scanner_service,
+            perceptual_hash_service,
             file_driver,
+            file_cache: FileCache::new(file_cache_config),
+            max_file_versions,
+            mime_policy: mime_policy.clone(),
         })
     }
 
+    /// Returns the counters of the file record cache.
+    pub fn file_cache_stats(&self) -> FileCacheStats {
+        self.file_cache.stats()
+    }
+
     /// Creates a new file from a staging file.
-    /// It computes the file's MIME type and hash, and stores the file in the file driver.
+    /// It computes the file's MIME type and hash, extracts its media metadata and text content,
+    /// and stores the file in the file driver.
+    /// If scanning is enabled, the file is scanned for malware, and an infected file is either rejected
+    /// with [`FileServiceError::Infected`] or promoted as quarantined, depending on the scanner config.
+    ///
+    /// The promotion runs as a saga: the file is inserted into the database along with a pending commit,
+    /// then its data is committed to the file driver, and finally it is indexed.
+    /// If the data cannot be committed, the file is turned back into the staging file.
+    /// If that fails too, or the indexing fails, the pending commit is left for [`FileService::retry_pending_commits`].
     pub async fn create_file_from_staging_file_id(
         &self,
         staging_file_id: Uuid,
+    ) -> Result<Option<File>, FileServiceError> {
+        self.create_file_from_staging_file_id_with_policy(staging_file_id, true)
+            .await
+    }
+
+    /// Creates a new file from a staging file, like [`FileService::create_file_from_staging_file_id`].
+    /// If `enforce_mime_policy` is `false`, the file is promoted even if its MIME type is not allowed, e.g. by an admin.
+    pub async fn create_file_from_staging_file_id_with_policy(
+        &self,
+        staging_file_id: Uuid,
+        enforce_mime_policy: bool,
     ) -> Result<Option<File>, FileServiceError> {
         use crate::db::schema;
 
-        let db = &mut self.db_pool.get().await?;
-        db.transaction(|db| {
-            async move {
-                let staging_file = self
-                    .staging_file_service
-                    .remove_staging_file_by_id(staging_file_id, Some(db), false)
-                    .await?;
+        // the slow work is done before the transaction, which is kept to the row changes
+        let chunks = {
+            let db = &mut self.db_pool.get().await?;
+            schema::staging_file_chunks::table
+                .filter(schema::staging_file_chunks::staging_file_id.eq(staging_file_id))
+                .select((
+                    schema::staging_file_chunks::staging_file_id,
+                    schema::staging_file_chunks::chunk_offset,
+                    schema::staging_file_chunks::size,
+                    schema::staging_file_chunks::created_at,
+                    schema::staging_file_chunks::completed_at,
+                ))
+                .load::<StagingFileChunk>(db)
+                .await?
+        };
 
-                let staging_file = match staging_file {
-                    Some(staging_file) => staging_file,
-                    None => {
-                        return Ok(None);
-                    }
-                };
+        if chunks.iter().any(|chunk| chunk.completed_at.is_none()) {
+            return Err(FileServiceError::FileNotYetFilled);
+        }
 
-                let file = self.file_driver.read_staging(staging_file.id).await?;
-                let file_path = match file {
-                    Some(file) => file,
-                    None => {
-                        return Err(FileServiceError::FileNotYetFilled);
-                    }
-                };
+        let chunked_size = if chunks.is_empty() {
+            None
+        } else {
+            Some(chunks.iter().map(|chunk| chunk.size).sum::<i64>())
+        };
 
-                let compute_mime = || async {
-                    match &staging_file.mime {
-                        Some(mime) => Ok(mime.as_str()),
-                        None => compute_file_mime::compute_file_mime(&file_path)
-                            .await
-                            .map_err(FileServiceError::from),
-                    }
-                };
-                let compute_hash = || async {
-                    compute_file_hash::compute_file_hash(&file_path)
-                        .await
-                        .map_err(FileServiceError::from)
-                };
+        let staging_file = self
+            .staging_file_service
+            .get_staging_file_by_id(staging_file_id)
+            .await?;
 
-                let size = tokio::fs::metadata(&file_path).await?.len();
-                let (mime, hash) = tokio::try_join!(compute_mime(), compute_hash())?;
+        let staging_file = match staging_file {
+            Some(staging_file) => staging_file,
+            None => {
+                return Ok(None);
+            }
+        };
 
-                let file = diesel::insert_into(schema::files::table)
-                    .values(CreatingFile {
-                        id: staging_file.id,
-                        name: &staging_file.name,
-                        mime,
-                        size: size as i64,
-                        hash: hash as i64,
-                    })
-                    .returning((
-                        schema::files::id,
-                        schema::files::name,
-                        schema::files::mime,
-                        schema::files::size,
-                        schema::files::hash,
-                        schema::files::uploaded_at,
+        let file = self.file_driver.read_staging(staging_file.id).await?;
+        let file_path = match file {
+            Some(file) => file,
+            None => {
+                return Err(FileServiceError::FileNotYetFilled);
+            }
+        };
+
+        let compute_mime = || async {
+            match &staging_file.mime {
+                Some(mime) => Ok(mime.as_str()),
+                None => compute_file_mime::compute_file_mime(&file_path)
+                    .await
+                    .map_err(FileServiceError::from),
+            }
+        };
+        let compute_hash = || async {
+            compute_file_hash::compute_file_hash(&file_path)
+                .await
+                .map_err(FileServiceError::from)
+        };
+
+        let size = tokio::fs::metadata(&file_path).await?.len();
+
+        if let Some(expected_size) = staging_file.expected_size {
+            if size != expected_size as u64 {
+                return Err(FileServiceError::FileNotYetFilled);
+            }
+        }
+
+        // a file written in chunks may have gaps that are not yet written
+        if let Some(chunked_size) = chunked_size {
+            if chunked_size as u64 != size {
+                return Err(FileServiceError::FileNotYetFilled);
+            }
+        }
+
+        let (mime, hash) = tokio::try_join!(compute_mime(), compute_hash())?;
+
+        if enforce_mime_policy && !self.mime_policy.is_allowed(mime) {
+            return Err(FileServiceError::MimeNotAllowed {
+                mime: mime.to_owned(),
+            });
+        }
+
+        if let Some(expected_hash) = staging_file.expected_hash {
+            if hash != expected_hash as u32 {
+                return Err(FileServiceError::HashMismatch {
+                    expected: expected_hash as u32,
+                    actual: hash,
+                });
+            }
+        }
+
+        let scan = self.scanner_service.scan(&file_path).await?;
+
+        if let Some(ScanResult {
+            status: ScanStatus::Infected,
+            signature,
+        }) = &scan
+        {
+            if self.scanner_service.on_infected() == InfectedFileAction::Reject {
+                // the staging file is kept, and expires as usual
+                return Err(FileServiceError::Infected {
+                    signature: signature.clone().unwrap_or_default(),
+                });
+            }
+        }
+
+        let (metadata, content, perceptual_hash) = tokio::join!(
+            self.metadata_service.extract(&file_path, mime),
+            self.content_extraction_service.extract(&file_path, mime),
+            self.perceptual_hash_service.compute(&file_path, mime)
+        );
+
+        // the staging file is removed and the file is inserted together, or not at all
+        let mut uow = UnitOfWork::begin(&self.db_pool).await?;
+        let created = async {
+            let staging_file = self
+                .staging_file_service
+                .remove_staging_file_by_id(staging_file_id, Some(&mut uow), false)
+                .await?;
+
+            let staging_file = match staging_file {
+                Some(staging_file) => staging_file,
+                // the staging file has been removed or promoted in the meantime
+                None => {
+                    return Ok(None);
+                }
+            };
+
+            let file = diesel::insert_into(schema::files::table)
+                .values(CreatingFile {
+                    id: staging_file.id,
+                    name: &staging_file.name,
+                    mime,
+                    size: size as i64,
+                    hash: hash as i64,
+                    file_metadata: metadata
+                        .as_ref()
+                        .and_then(|metadata| serde_json::to_value(metadata).ok()),
+                    // the text is kept so that the file can be indexed again without extracting it
+                    search_content: content.as_deref(),
+                    scan_status: scan.as_ref().map(|scan| scan.status.name()),
+                    scan_signature: scan.as_ref().and_then(|scan| scan.signature.as_deref()),
+                    scanned_at: scan.as_ref().map(|_| Utc::now().naive_utc()),
+                    perceptual_hash,
+                })
+                .returning((
+                    schema::files::id,
+                    schema::files::name,
+                    schema::files::mime,
+                    schema::files::size,
+                    schema::files::hash,
+                    schema::files::uploaded_at,
+                ))
+                .get_result::<File>(uow.db())
+                .await?;
+
+            diesel::insert_into(schema::pending_commits::table)
+                .values(CreatingPendingCommit {
+                    file_id: file.id,
+                    stage: PendingCommitStage::Data.name(),
+                })
+                .execute(uow.db())
+                .await?;
+
+            Ok::<_, FileServiceError>(Some((staging_file, file)))
+        }
+        .await;
+
+        let created = match created {
+            Ok(created) => created,
+            Err(err) => {
+                uow.rollback().await.ok();
+                return Err(err);
+            }
+        };
+
+        uow.commit().await?;
+
+        let (staging_file, file) = match created {
+            Some(created) => created,
+            None => return Ok(None),
+        };
+
+        // the data is committed outside of the transaction, as the file driver cannot be rolled back
+        if let Err(err) = self.file_driver.commit_staging(file.id).await {
+            if let Err(compensation_err) =
+                self.compensate_file_creation(&staging_file, &chunks).await
+            {
+                // the file stays in the database, so the commit has to be retried instead
+                self.record_pending_commit_failure(file.id, &compensation_err.to_string())
+                    .await
+                    .ok();
+            }
+
+            return Err(err.into());
+        }
+
+        // the file is indexed once committed, so that the index never sees a file rolled back
+        if let Err(err) = self
+            .complete_pending_commit(&file, metadata.as_ref(), content.as_deref())
+            .await
+        {
+            // the file has been created regardless, and the indexing is retried later
+            self.record_pending_commit_failure(file.id, &err.to_string())
+                .await
+                .ok();
+        }
+
+        Ok(Some(file))
+    }
+
+    /// Turns a file whose data could not be committed back into the staging file it was promoted from,
+    /// along with the chunks it was written in, so that the promotion can be retried.
+    /// Its pending commit is removed along with it.
+    async fn compensate_file_creation(
+        &self,
+        staging_file: &StagingFile,
+        chunks: &[StagingFileChunk],
+    ) -> Result<(), FileServiceError> {
+        use crate::db::schema;
+
+        let db = &mut self.db_pool.get().await?;
+        db.transaction(|db| {
+            async move {
+                diesel::delete(schema::files::table.filter(schema::files::id.eq(staging_file.id)))
+                    .execute(db)
+                    .await?;
+
+                diesel::insert_into(schema::staging_files::table)
+                    .values((
+                        schema::staging_files::id.eq(staging_file.id),
+                        schema::staging_files::name.eq(&staging_file.name),
+                        schema::staging_files::mime.eq(staging_file.mime.as_deref()),
+                        schema::staging_files::size.eq(staging_file.size),
+                        schema::staging_files::staged_at.eq(staging_file.staged_at),
+                        schema::staging_files::expected_size.eq(staging_file.expected_size),
+                        schema::staging_files::expected_hash.eq(staging_file.expected_hash),
                     ))
-                    .get_result::<File>(db)
+                    .execute(db)
                     .await?;
 
-                self.file_driver.commit_staging(staging_file.id).await?;
+                let chunks = chunks
+                    .iter()
+                    .map(|chunk| {
+                        (
+                            schema::staging_file_chunks::staging_file_id.eq(chunk.staging_file_id),
+                            schema::staging_file_chunks::chunk_offset.eq(chunk.chunk_offset),
+                            schema::staging_file_chunks::size.eq(chunk.size),
+                            schema::staging_file_chunks::created_at.eq(chunk.created_at),
+                            schema::staging_file_chunks::completed_at.eq(chunk.completed_at),
+                        )
+                    })
+                    .collect::<Vec<_>>();
 
-                // ignore the error if the indexing fails, as it is not critical
-                self.search_service.index_file(&file).await.ok();
+                diesel::insert_into(schema::staging_file_chunks::table)
+                    .values(chunks)
+                    .execute(db)
+                    .await?;
 
-                Ok(Some(file))
+                Ok::<_, FileServiceError>(())
             }
             .scope_boxed()
         })
-        .await
+        .await?;
+
+        self.file_cache.invalidate(staging_file.id).await;
+
+        Ok(())
+    }
+
+    /// Runs the steps of a promotion that follow the data commit, and removes its pending commit.
+    async fn complete_pending_commit(
+        &self,
+        file: &File,
+        metadata: Option<&FileMetadata>,
+        content: Option<&str>,
+    ) -> Result<(), FileServiceError> {
+        use crate::db::schema;
+
+        let db = &mut self.db_pool.get().await?;
+        diesel::update(
+            schema::pending_commits::table.filter(schema::pending_commits::file_id.eq(file.id)),
+        )
+        .set(schema::pending_commits::stage.eq(PendingCommitStage::Index.name()))
+        .execute(db)
+        .await?;
+
+        self.search_service
+            .index_file(file, metadata, content)
+            .await?;
+
+        diesel::delete(
+            schema::pending_commits::table.filter(schema::pending_commits::file_id.eq(file.id)),
+        )
+        .execute(db)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Records a failed attempt of a pending commit, and schedules the next one with exponential backoff, up to an hour.
+    async fn record_pending_commit_failure(
+        &self,
+        file_id: Uuid,
+        error: &str,
+    ) -> Result<(), FileServiceError> {
+        let db = &mut self.db_pool.get().await?;
+        diesel::sql_query(
+            "UPDATE pending_commits SET attempts = attempts + 1, last_error = $2, next_attempt_at = NOW() + LEAST(POWER(2, attempts), 3600) * INTERVAL '1 second' WHERE file_id = $1",
+        )
+        .bind::<diesel::sql_types::Uuid, _>(file_id)
+        .bind::<diesel::sql_types::Text, _>(error)
+        .execute(db)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Retries up to `limit` pending commits that are due.
+    /// The data of a file is committed again unless the driver has already committed it, and the file is indexed again
+    /// with the text content stored when it was promoted.
+    /// Returns the number of pending commits that completed and that failed again.
+    pub async fn retry_pending_commits(
+        &self,
+        limit: u32,
+    ) -> Result<(usize, usize), FileServiceError> {
+        use crate::db::schema;
+
+        let pending_commits = {
+            let db = &mut self.db_pool.get().await?;
+            schema::pending_commits::table
+                .filter(schema::pending_commits::next_attempt_at.le(diesel::dsl::now))
+                .order(schema::pending_commits::next_attempt_at.asc())
+                .limit(limit as i64)
+                .select((
+                    schema::pending_commits::file_id,
+                    schema::pending_commits::stage,
+                    schema::pending_commits::attempts,
+                    schema::pending_commits::last_error,
+                    schema::pending_commits::created_at,
+                    schema::pending_commits::next_attempt_at,
+                ))
+                .load::<PendingCommit>(db)
+                .await?
+        };
+
+        let mut completed_count = 0;
+        let mut failed_count = 0;
+
+        for pending_commit in pending_commits {
+            match self.retry_pending_commit(&pending_commit).await {
+                Ok(()) => {
+                    completed_count += 1;
+                }
+                Err(err) => {
+                    failed_count += 1;
+                    self.record_pending_commit_failure(pending_commit.file_id, &err.to_string())
+                        .await?;
+                }
+            }
+        }
+
+        Ok((completed_count, failed_count))
+    }
+
+    async fn retry_pending_commit(
+        &self,
+        pending_commit: &PendingCommit,
+    ) -> Result<(), FileServiceError> {
+        let file = match self.get_file_by_id(pending_commit.file_id).await? {
+            Some(file) => file,
+            // the pending commit has been removed along with the file
+            None => return Ok(()),
+        };
+
+        if PendingCommitStage::from_name(&pending_commit.stage) == Some(PendingCommitStage::Data) {
+            // the data may have been committed before the stage could be advanced
+            if self.file_driver.read_staging(file.id).await?.is_some() {
+                self.file_driver.commit_staging(file.id).await?;
+            }
+        }
+
+        let metadata = self.get_file_metadata_by_id(file.id).await?;
+        let content = self.get_file_search_content_by_id(file.id).await?;
+        self.complete_pending_commit(&file, metadata.as_ref(), content.as_deref())
+            .await
+    }
+
+    /// Retrieves the pending commit of a file, or `None` if the promotion of the file has completed.
+    #[cfg(test)]
+    pub async fn get_pending_commit_by_file_id(
+        &self,
+        file_id: Uuid,
+    ) -> Result<Option<PendingCommit>, FileServiceError> {
+        use crate::db::schema;
+
+        let db = &mut self.db_pool.get().await?;
+        let pending_commit = schema::pending_commits::table
+            .filter(schema::pending_commits::file_id.eq(file_id))
+            .select((
+                schema::pending_commits::file_id,
+                schema::pending_commits::stage,
+                schema::pending_commits::attempts,
+                schema::pending_commits::last_error,
+                schema::pending_commits::created_at,
+                schema::pending_commits::next_attempt_at,
+            ))
+            .get_result::<PendingCommit>(db)
+            .await
+            .optional()?;
+
+        Ok(pending_commit)
     }
 
     /// Removes a file by its ID.
@@ -141,6 +765,12 @@ impl FileService {
         use crate::db::schema;
 
         let db = &mut self.db_pool.get().await?;
+        // the versions are removed along with the file, so their IDs are taken first to remove their data
+        let version_ids = schema::file_versions::table
+            .filter(schema::file_versions::file_id.eq(file_id))
+            .select(schema::file_versions::id)
+            .load::<Uuid>(db)
+            .await?;
         let file = diesel::delete(
             crate::db::schema::files::table.filter(crate::db::schema::files::id.eq(file_id)),
         )
@@ -157,9 +787,15 @@ impl FileService {
         .optional()?;
 
         if file.is_some() {
+            self.file_cache.invalidate(file_id).await;
+
             // it is safe to ignore the result of this operation
             self.file_driver.remove(file_id).await.ok();
 
+            for version_id in version_ids {
+                self.file_driver.remove(version_id).await.ok();
+            }
+
             // ignore the error if the indexing fails, as it is not critical
             self.search_service.remove_file_by_id(file_id).await.ok();
         }
@@ -167,95 +803,1397 @@ impl FileService {
         Ok(file)
     }
 
-    /// Retrieves a list of files.
-    /// The result will be sorted by name and ID (name first) in ascending order.
-    /// If `last_file_id` is provided, the result will start from the file that comes after it.
-    pub async fn get_files(
+    /// Replaces the data of a file with a staging file, keeping the current data as a former version.
+    /// The staging file is promoted as usual and then folded into the file,
+    /// so that the file keeps its ID along with its collections, tags and shares.
+    /// The former version takes the ID of the staging file. Versions beyond the limit are purged, oldest first.
+    /// The MIME policy is skipped if `enforce_mime_policy` is `false`.
+    /// Returns `None` if no file or no staging file was found.
+    pub async fn replace_file_from_staging_file_id(
         &self,
-        last_file_id: Option<Uuid>,
-        limit: u32,
-    ) -> Result<Vec<File>, FileServiceError> {
-        use crate::db::schema;
-        let db = &mut self.db_pool.get().await?;
-
-        let query = schema::files::dsl::files
-            .select((
-                schema::files::id,
-                schema::files::name,
-                schema::files::mime,
-                schema::files::size,
-                schema::files::hash,
-                schema::files::uploaded_at,
-            ))
-            .order((schema::files::name.asc(), schema::files::id.asc()))
-            .limit(limit as i64);
+        file_id: Uuid,
+        staging_file_id: Uuid,
+        enforce_mime_policy: bool,
+    ) -> Result<Option<File>, FileServiceError> {
+        // check the file first, so that the staging file is left untouched if there is nothing to replace
+        if self.get_file_by_id(file_id).await?.is_none() {
+            return Ok(None);
+        }
 
-        let last_file = match last_file_id {
-            Some(last_file_id) => {
-                let last_file = schema::files::dsl::files
-                    .select((schema::files::name, schema::files::id))
-                    .filter(schema::files::id.eq(last_file_id))
-                    .get_result::<(String, Uuid)>(db)
-                    .await
-                    .optional()?;
+        let new_file = match self
+            .create_file_from_staging_file_id_with_policy(staging_file_id, enforce_mime_policy)
+            .await?
+        {
+            Some(new_file) => new_file,
+            None => return Ok(None),
+        };
+        let version_id = new_file.id;
 
-                let last_file = match last_file {
-                    Some(pair) => pair,
-                    None => return Ok(Vec::new()),
-                };
+        // the data is swapped through the staging area: the file gets the new data, and the version the current one
+        let archived = async {
+            self.stage_copy(version_id, file_id).await?;
+            self.stage_copy(file_id, version_id).await?;
+            self.archive_file_version(file_id, version_id).await
+        }
+        .await;
 
-                Some(last_file)
+        let file = match archived {
+            Ok(Some(file)) => file,
+            Ok(None) => {
+                // the file has been removed meanwhile, so the promoted file is left as a file of its own
+                self.file_driver.remove_staging(file_id).await.ok();
+                self.file_driver.remove_staging(version_id).await.ok();
+                return Ok(Some(new_file));
+            }
+            Err(err) => {
+                self.file_driver.remove_staging(file_id).await.ok();
+                self.file_driver.remove_staging(version_id).await.ok();
+                return Err(err);
             }
-            None => None,
         };
 
-        let files = match &last_file {
-            Some((last_file_name, last_file_id)) => query
-                .filter(
-                    schema::files::name
-                        .gt(last_file_name)
-                        .or(schema::files::name
-                            .eq(last_file_name)
-                            .and(schema::files::id.gt(last_file_id))),
-                )
-                .load::<File>(db),
-            None => query.load::<File>(db),
-        };
-        let files = files.await?;
+        // the promoted file is now a version, which is never searched
+        self.search_service.remove_file_by_id(version_id).await.ok();
+        self.commit_swapped_data(&file, version_id).await;
+        self.purge_file_versions(file_id).await?;
 
-        Ok(files)
+        Ok(Some(file))
     }
 
-    /// Retrieves a file by its ID.
-    pub async fn get_file_by_id(&self, file_id: Uuid) -> Result<Option<File>, FileServiceError> {
-        use crate::db::schema;
+    /// Copies the committed data of `from` into the staging area of `to`.
+    async fn stage_copy(&self, from: Uuid, to: Uuid) -> Result<(), FileServiceError> {
+        let data = match self.file_driver.read(from, ReadRange::Full).await? {
+            Some(data) => data,
+            None => return Err(FileServiceError::DataMissing(from)),
+        };
 
-        let db = &mut self.db_pool.get().await?;
-        let file = schema::files::table
-            .filter(schema::files::id.eq(file_id))
-            .select((
-                schema::files::id,
-                schema::files::name,
-                schema::files::mime,
-                schema::files::size,
-                schema::files::hash,
-                schema::files::uploaded_at,
-            ))
-            .get_result::<File>(db)
-            .await
-            .optional()?;
+        self.file_driver.write_staging(to, 0, data).await?;
 
-        Ok(file)
+        Ok(())
     }
 
-    /// Retrieves the file data by its ID.
-    pub async fn get_file_data_by_id(
+    /// Turns the file promoted as `version_id` into the newest version of `file_id`, swapping their rows.
+    /// A pending commit is inserted for the file, as its new data is still in the staging area.
+    /// Returns `None` if the file has been removed.
+    async fn archive_file_version(
         &self,
         file_id: Uuid,
-        range: ReadRange,
-    ) -> Result<Option<Pin<Box<dyn AsyncRead + Send>>>, ReadError> {
+        version_id: Uuid,
+    ) -> Result<Option<File>, FileServiceError> {
+        use crate::db::schema;
+
+        let db = &mut self.db_pool.get().await?;
+        let file = db
+            .transaction(|db| {
+                async move {
+                    let current = schema::files::table
+                        .filter(schema::files::id.eq(file_id))
+                        .select((
+                            schema::files::name,
+                            schema::files::mime,
+                            schema::files::size,
+                            schema::files::hash,
+                            schema::files::uploaded_at,
+                            schema::files::file_metadata,
+                            schema::files::search_content,
+                            schema::files::scan_status,
+                            schema::files::scan_signature,
+                            schema::files::scanned_at,
+                            schema::files::perceptual_hash,
+                        ))
+                        .for_update()
+                        .get_result::<FileRevision>(db)
+                        .await
+                        .optional()?;
+
+                    let current = match current {
+                        Some(current) => current,
+                        None => return Ok(None),
+                    };
+
+                    let replacement = diesel::delete(
+                        schema::files::table.filter(schema::files::id.eq(version_id)),
+                    )
+                    .returning((
+                        schema::files::name,
+                        schema::files::mime,
+                        schema::files::size,
+                        schema::files::hash,
+                        schema::files::uploaded_at,
+                        schema::files::file_metadata,
+                        schema::files::search_content,
+                        schema::files::scan_status,
+                        schema::files::scan_signature,
+                        schema::files::scanned_at,
+                        schema::files::perceptual_hash,
+                    ))
+                    .get_result::<FileRevision>(db)
+                    .await?;
+
+                    let version = Self::next_file_version(db, file_id).await?;
+
+                    diesel::insert_into(schema::file_versions::table)
+                        .values((
+                            schema::file_versions::id.eq(version_id),
+                            schema::file_versions::file_id.eq(file_id),
+                            schema::file_versions::version.eq(version),
+                            schema::file_versions::name.eq(current.name),
+                            schema::file_versions::mime.eq(current.mime),
+                            schema::file_versions::size.eq(current.size),
+                            schema::file_versions::hash.eq(current.hash),
+                            schema::file_versions::uploaded_at.eq(current.uploaded_at),
+                            schema::file_versions::file_metadata.eq(current.file_metadata),
+                            schema::file_versions::search_content.eq(current.search_content),
+                            schema::file_versions::scan_status.eq(current.scan_status),
+                            schema::file_versions::scan_signature.eq(current.scan_signature),
+                            schema::file_versions::scanned_at.eq(current.scanned_at),
+                            schema::file_versions::perceptual_hash.eq(current.perceptual_hash),
+                        ))
+                        .execute(db)
+                        .await?;
+
+                    let file = Self::update_file_revision(db, file_id, replacement).await?;
+
+                    Ok::<_, FileServiceError>(Some(file))
+                }
+                .scope_boxed()
+            })
+            .await?;
+
+        Ok(file)
+    }
+
+    /// Restores a former version of a file, keeping the current data as the newest version.
+    /// Returns `None` if no file or no such version was found.
+    pub async fn restore_file_version(
+        &self,
+        file_id: Uuid,
+        version: i32,
+    ) -> Result<Option<File>, FileServiceError> {
+        use crate::db::schema;
+
+        let version_id = {
+            let db = &mut self.db_pool.get().await?;
+            schema::file_versions::table
+                .filter(schema::file_versions::file_id.eq(file_id))
+                .filter(schema::file_versions::version.eq(version))
+                .select(schema::file_versions::id)
+                .get_result::<Uuid>(db)
+                .await
+                .optional()?
+        };
+
+        let version_id = match version_id {
+            Some(version_id) => version_id,
+            None => return Ok(None),
+        };
+
+        let swapped = async {
+            self.stage_copy(version_id, file_id).await?;
+            self.stage_copy(file_id, version_id).await?;
+            self.swap_file_version(file_id, version_id).await
+        }
+        .await;
+
+        let file = match swapped {
+            Ok(Some(file)) => file,
+            result => {
+                self.file_driver.remove_staging(file_id).await.ok();
+                self.file_driver.remove_staging(version_id).await.ok();
+                return result;
+            }
+        };
+
+        self.commit_swapped_data(&file, version_id).await;
+
+        Ok(Some(file))
+    }
+
+    /// Swaps the rows of a file and one of its versions, renumbering the version as the newest one.
+    /// A pending commit is inserted for the file, as its restored data is still in the staging area.
+    /// Returns `None` if the file or the version has been removed.
+    async fn swap_file_version(
+        &self,
+        file_id: Uuid,
+        version_id: Uuid,
+    ) -> Result<Option<File>, FileServiceError> {
+        use crate::db::schema;
+
+        let db = &mut self.db_pool.get().await?;
+        let file = db
+            .transaction(|db| {
+                async move {
+                    let current = schema::files::table
+                        .filter(schema::files::id.eq(file_id))
+                        .select((
+                            schema::files::name,
+                            schema::files::mime,
+                            schema::files::size,
+                            schema::files::hash,
+                            schema::files::uploaded_at,
+                            schema::files::file_metadata,
+                            schema::files::search_content,
+                            schema::files::scan_status,
+                            schema::files::scan_signature,
+                            schema::files::scanned_at,
+                            schema::files::perceptual_hash,
+                        ))
+                        .for_update()
+                        .get_result::<FileRevision>(db)
+                        .await
+                        .optional()?;
+                    let restored = schema::file_versions::table
+                        .filter(schema::file_versions::id.eq(version_id))
+                        .select((
+                            schema::file_versions::name,
+                            schema::file_versions::mime,
+                            schema::file_versions::size,
+                            schema::file_versions::hash,
+                            schema::file_versions::uploaded_at,
+                            schema::file_versions::file_metadata,
+                            schema::file_versions::search_content,
+                            schema::file_versions::scan_status,
+                            schema::file_versions::scan_signature,
+                            schema::file_versions::scanned_at,
+                            schema::file_versions::perceptual_hash,
+                        ))
+                        .for_update()
+                        .get_result::<FileRevision>(db)
+                        .await
+                        .optional()?;
+
+                    let (current, restored) = match (current, restored) {
+                        (Some(current), Some(restored)) => (current, restored),
+                        _ => return Ok(None),
+                    };
+
+                    let version = Self::next_file_version(db, file_id).await?;
+
+                    diesel::update(
+                        schema::file_versions::table
+                            .filter(schema::file_versions::id.eq(version_id)),
+                    )
+                    .set((
+                        schema::file_versions::version.eq(version),
+                        schema::file_versions::name.eq(current.name),
+                        schema::file_versions::mime.eq(current.mime),
+                        schema::file_versions::size.eq(current.size),
+                        schema::file_versions::hash.eq(current.hash),
+                        schema::file_versions::uploaded_at.eq(current.uploaded_at),
+                        schema::file_versions::file_metadata.eq(current.file_metadata),
+                        schema::file_versions::search_content.eq(current.search_content),
+                        schema::file_versions::scan_status.eq(current.scan_status),
+                        schema::file_versions::scan_signature.eq(current.scan_signature),
+                        schema::file_versions::scanned_at.eq(current.scanned_at),
+                        schema::file_versions::perceptual_hash.eq(current.perceptual_hash),
+                        schema::file_versions::replaced_at.eq(diesel::dsl::now),
+                    ))
+                    .execute(db)
+                    .await?;
+
+                    let file = Self::update_file_revision(db, file_id, restored).await?;
+
+                    Ok::<_, FileServiceError>(Some(file))
+                }
+                .scope_boxed()
+            })
+            .await?;
+
+        Ok(file)
+    }
+
+    async fn next_file_version(
+        db: &mut AsyncPgConnection,
+        file_id: Uuid,
+    ) -> Result<i32, FileServiceError> {
+        use crate::db::schema;
+
+        let last_version = schema::file_versions::table
+            .filter(schema::file_versions::file_id.eq(file_id))
+            .select(diesel::dsl::max(schema::file_versions::version))
+            .get_result::<Option<i32>>(db)
+            .await?;
+
+        Ok(last_version.unwrap_or(0) + 1)
+    }
+
+    /// Sets the data columns of a file to `revision`, and inserts a pending commit for its staged data.
+    async fn update_file_revision(
+        db: &mut AsyncPgConnection,
+        file_id: Uuid,
+        revision: FileRevision,
+    ) -> Result<File, FileServiceError> {
+        use crate::db::schema;
+
+        let file = diesel::update(schema::files::table.filter(schema::files::id.eq(file_id)))
+            .set((
+                schema::files::name.eq(revision.name),
+                schema::files::mime.eq(revision.mime),
+                schema::files::size.eq(revision.size),
+                schema::files::hash.eq(revision.hash),
+                schema::files::uploaded_at.eq(revision.uploaded_at),
+                schema::files::file_metadata.eq(revision.file_metadata),
+                schema::files::search_content.eq(revision.search_content),
+                schema::files::scan_status.eq(revision.scan_status),
+                schema::files::scan_signature.eq(revision.scan_signature),
+                schema::files::scanned_at.eq(revision.scanned_at),
+                schema::files::perceptual_hash.eq(revision.perceptual_hash),
+                schema::files::verified_at.eq(None::<NaiveDateTime>),
+            ))
+            .returning((
+                schema::files::id,
+                schema::files::name,
+                schema::files::mime,
+                schema::files::size,
+                schema::files::hash,
+                schema::files::uploaded_at,
+            ))
+            .get_result::<File>(db)
+            .await?;
+
+        // a pending commit left by an earlier swap is restarted from the data stage
+        diesel::insert_into(schema::pending_commits::table)
+            .values(CreatingPendingCommit {
+                file_id,
+                stage: PendingCommitStage::Data.name(),
+            })
+            .on_conflict(schema::pending_commits::file_id)
+            .do_update()
+            .set(schema::pending_commits::stage.eq(PendingCommitStage::Data.name()))
+            .execute(db)
+            .await?;
+
+        Ok(file)
+    }
+
+    /// Commits the data swapped between a file and one of its versions.
+    /// The data of the file is committed through its pending commit, so that it is retried if it fails.
+    /// The version is dropped if its data cannot be committed, as the stored data would no longer match it.
+    async fn commit_swapped_data(&self, file: &File, version_id: Uuid) {
+        self.file_cache.invalidate(file.id).await;
+        self.file_cache.invalidate(version_id).await;
+
+        let committed = async {
+            self.file_driver.commit_staging(file.id).await?;
+            let metadata = self.get_file_metadata_by_id(file.id).await?;
+            // an empty content clears the text of the revision that has been swapped out
+            let content = self
+                .get_file_search_content_by_id(file.id)
+                .await?
+                .unwrap_or_default();
+            self.complete_pending_commit(file, metadata.as_ref(), Some(&content))
+                .await
+        }
+        .await;
+
+        if let Err(err) = committed {
+            self.record_pending_commit_failure(file.id, &err.to_string())
+                .await
+                .ok();
+        }
+
+        if let Err(err) = self.file_driver.commit_staging(version_id).await {
+            log::warn!(target: "services::file_service", method = "commit_swapped_data", file_id:serde = file.id, version_id:serde, err:err; "Failed to commit the data of a file version; dropping the version.");
+            self.remove_file_versions_by_ids(&[version_id]).await.ok();
+        }
+    }
+
+    /// Removes the oldest versions of a file beyond the configured limit, along with their data.
+    async fn purge_file_versions(&self, file_id: Uuid) -> Result<(), FileServiceError> {
+        use crate::db::schema;
+
+        if self.max_file_versions == 0 {
+            return Ok(());
+        }
+
+        let db = &mut self.db_pool.get().await?;
+        let expired_ids = schema::file_versions::table
+            .filter(schema::file_versions::file_id.eq(file_id))
+            .order(schema::file_versions::version.desc())
+            .offset(self.max_file_versions as i64)
+            .select(schema::file_versions::id)
+            .load::<Uuid>(db)
+            .await?;
+
+        self.remove_file_versions_by_ids(&expired_ids).await
+    }
+
+    async fn remove_file_versions_by_ids(
+        &self,
+        version_ids: &[Uuid],
+    ) -> Result<(), FileServiceError> {
+        use crate::db::schema;
+
+        if version_ids.is_empty() {
+            return Ok(());
+        }
+
+        let db = &mut self.db_pool.get().await?;
+        diesel::delete(
+            schema::file_versions::table.filter(schema::file_versions::id.eq_any(version_ids)),
+        )
+        .execute(db)
+        .await?;
+
+        for version_id in version_ids {
+            // it is safe to ignore the result of this operation, the data is swept as an orphan otherwise
+            self.file_driver.remove(*version_id).await.ok();
+        }
+
+        Ok(())
+    }
+
+    /// Retrieves the former versions of a file, newest first.
+    /// Returns `None` if no file was found.
+    pub async fn get_file_versions(
+        &self,
+        file_id: Uuid,
+    ) -> Result<Option<Vec<FileVersion>>, FileServiceError> {
+        use crate::db::schema;
+
+        if self.get_file_by_id(file_id).await?.is_none() {
+            return Ok(None);
+        }
+
+        let db = &mut self.db_pool.get().await?;
+        let versions = schema::file_versions::table
+            .filter(schema::file_versions::file_id.eq(file_id))
+            .select((
+                schema::file_versions::id,
+                schema::file_versions::file_id,
+                schema::file_versions::version,
+                schema::file_versions::name,
+                schema::file_versions::mime,
+                schema::file_versions::size,
+                schema::file_versions::hash,
+                schema::file_versions::uploaded_at,
+                schema::file_versions::replaced_at,
+            ))
+            .order(schema::file_versions::version.desc())
+            .load::<FileVersion>(db)
+            .await?;
+
+        Ok(Some(versions))
+    }
+
+    /// Finds the file a staging file would replace by name, which is the newest file with the same name.
+    /// Returns `None` if no staging file or no such file was found.
+    pub async fn find_file_by_staging_file_name(
+        &self,
+        staging_file_id: Uuid,
+    ) -> Result<Option<Uuid>, FileServiceError> {
+        use crate::db::schema;
+
+        let db = &mut self.db_pool.get().await?;
+        let file_id = schema::files::table
+            .filter(
+                schema::files::name.eq_any(
+                    schema::staging_files::table
+                        .filter(schema::staging_files::id.eq(staging_file_id))
+                        .select(schema::staging_files::name),
+                ),
+            )
+            .order((schema::files::uploaded_at.desc(), schema::files::id.desc()))
+            .select(schema::files::id)
+            .first::<Uuid>(db)
+            .await
+            .optional()?;
+
+        Ok(file_id)
+    }
+
+    /// Retrieves a list of files matching `filter`.
+    /// The result will be sorted by `sort` in `direction`, and by ID for the same keys.
+    /// If `last_file_id` is provided, the result will start from the file that comes after it.
+    /// The number of all matching files is counted only if `with_total` is set, as counting is not cheap.
+    pub async fn get_files(
+        &self,
+        last_file_id: Option<Uuid>,
+        filter: &FileFilter,
+        sort: FileSort,
+        direction: SortDirection,
+        limit: u32,
+        with_total: bool,
+    ) -> Result<Page<File, Uuid>, FileServiceError> {
+        use crate::db::schema;
+        let db = &mut self.read_pool.get().await?;
+
+        let total = if with_total {
+            let query = filter.apply(schema::files::table.into_boxed()).count();
+            let total = self
+                .read_pool
+                .measure("count_files", query, |query| query.get_result::<i64>(db))
+                .await?;
+            Some(total)
+        } else {
+            None
+        };
+
+        let last_file = match last_file_id {
+            // the ID alone is the key, so the last file doesn't have to be looked up
+            Some(last_file_id) if sort == FileSort::Id => Some((FileSortKey::Id, last_file_id)),
+            Some(last_file_id) => {
+                let last_file = schema::files::table
+                    .select((
+                        schema::files::name,
+                        schema::files::size,
+                        schema::files::uploaded_at,
+                        schema::files::download_count,
+                    ))
+                    .filter(schema::files::id.eq(last_file_id))
+                    .get_result::<(String, i64, NaiveDateTime, i64)>(db)
+                    .await
+                    .optional()?;
+
+                let (last_name, last_size, last_uploaded_at, last_download_count) = match last_file
+                {
+                    Some(last_file) => last_file,
+                    None => return Ok(Page::new(Vec::new(), limit, total, |file| file.id)),
+                };
+
+                let key = match sort {
+                    FileSort::Id => FileSortKey::Id,
+                    FileSort::Name => FileSortKey::Name(last_name),
+                    FileSort::Size => FileSortKey::Size(last_size),
+                    FileSort::UploadedAt => FileSortKey::UploadedAt(last_uploaded_at),
+                    FileSort::Downloads => FileSortKey::Downloads(last_download_count),
+                };
+
+                Some((key, last_file_id))
+            }
+            None => None,
+        };
+
+        let mut query = filter.apply(schema::files::table.into_boxed());
+
+        if let Some((key, last_id)) = last_file {
+            use schema::files::{download_count, id, name, size, uploaded_at};
+
+            query = match (key, direction) {
+                (FileSortKey::Id, SortDirection::Asc) => query.filter(id.gt(last_id)),
+                (FileSortKey::Id, SortDirection::Desc) => query.filter(id.lt(last_id)),
+                (FileSortKey::Name(last_name), SortDirection::Asc) => query.filter(
+                    name.gt(last_name.clone())
+                        .or(name.eq(last_name).and(id.gt(last_id))),
+                ),
+                (FileSortKey::Name(last_name), SortDirection::Desc) => query.filter(
+                    name.lt(last_name.clone())
+                        .or(name.eq(last_name).and(id.lt(last_id))),
+                ),
+                (FileSortKey::Size(last_size), SortDirection::Asc) => query.filter(
+                    size.gt(last_size)
+                        .or(size.eq(last_size).and(id.gt(last_id))),
+                ),
+                (FileSortKey::Size(last_size), SortDirection::Desc) => query.filter(
+                    size.lt(last_size)
+                        .or(size.eq(last_size).and(id.lt(last_id))),
+                ),
+                (FileSortKey::UploadedAt(last_uploaded_at), SortDirection::Asc) => query.filter(
+                    uploaded_at
+                        .gt(last_uploaded_at)
+                        .or(uploaded_at.eq(last_uploaded_at).and(id.gt(last_id))),
+                ),
+                (FileSortKey::UploadedAt(last_uploaded_at), SortDirection::Desc) => query.filter(
+                    uploaded_at
+                        .lt(last_uploaded_at)
+                        .or(uploaded_at.eq(last_uploaded_at).and(id.lt(last_id))),
+                ),
+                (FileSortKey::Downloads(last_download_count), SortDirection::Asc) => query.filter(
+                    download_count
+                        .gt(last_download_count)
+                        .or(download_count.eq(last_download_count).and(id.gt(last_id))),
+                ),
+                (FileSortKey::Downloads(last_download_count), SortDirection::Desc) => query.filter(
+                    download_count
+                        .lt(last_download_count)
+                        .or(download_count.eq(last_download_count).and(id.lt(last_id))),
+                ),
+            };
+        }
+
+        query = {
+            use schema::files::{download_count, id, name, size, uploaded_at};
+
+            match (sort, direction) {
+                (FileSort::Id, SortDirection::Asc) => query.order(id.asc()),
+                (FileSort::Id, SortDirection::Desc) => query.order(id.desc()),
+                (FileSort::Name, SortDirection::Asc) => query.order((name.asc(), id.asc())),
+                (FileSort::Name, SortDirection::Desc) => query.order((name.desc(), id.desc())),
+                (FileSort::Size, SortDirection::Asc) => query.order((size.asc(), id.asc())),
+                (FileSort::Size, SortDirection::Desc) => query.order((size.desc(), id.desc())),
+                (FileSort::UploadedAt, SortDirection::Asc) => {
+                    query.order((uploaded_at.asc(), id.asc()))
+                }
+                (FileSort::UploadedAt, SortDirection::Desc) => {
+                    query.order((uploaded_at.desc(), id.desc()))
+                }
+                (FileSort::Downloads, SortDirection::Asc) => {
+                    query.order((download_count.asc(), id.asc()))
+                }
+                (FileSort::Downloads, SortDirection::Desc) => {
+                    query.order((download_count.desc(), id.desc()))
+                }
+            }
+        };
+
+        let query = query
+            .select((
+                schema::files::id,
+                schema::files::name,
+                schema::files::mime,
+                schema::files::size,
+                schema::files::hash,
+                schema::files::uploaded_at,
+            ))
+            // fetch one more file to tell whether there is a next page
+            .limit(limit as i64 + 1);
+        let files = self
+            .read_pool
+            .measure("get_files", query, |query| query.load::<File>(db))
+            .await?;
+
+        Ok(Page::new(files, limit, total, |file| file.id))
+    }
+
+    /// Retrieves a list of files that belong to no collection, e.g. to triage unsorted uploads.
+    /// The result will be sorted by ID in ascending order.
+    /// If `last_file_id` is provided, the result will start from the file that comes after it.
+    /// The number of all such files is counted only if `with_total` is set.
+    pub async fn get_uncollected_files(
+        &self,
+        last_file_id: Option<Uuid>,
+        limit: u32,
+        with_total: bool,
+    ) -> Result<Page<File, Uuid>, FileServiceError> {
+        use crate::db::schema;
+        let db = &mut self.read_pool.get().await?;
+
+        // an anti-join; files without any pair come out with a null pair
+        let total = if with_total {
+            let query = schema::files::table
+                .left_join(schema::collection_file_pairs::table)
+                .filter(schema::collection_file_pairs::collection_id.is_null())
+                .count();
+            let total = self
+                .read_pool
+                .measure("count_uncollected_files", query, |query| {
+                    query.get_result::<i64>(db)
+                })
+                .await?;
+            Some(total)
+        } else {
+            None
+        };
+
+        let mut query = schema::files::table
+            .left_join(schema::collection_file_pairs::table)
+            .filter(schema::collection_file_pairs::collection_id.is_null())
+            .into_boxed();
+
+        if let Some(last_file_id) = last_file_id {
+            query = query.filter(schema::files::id.gt(last_file_id));
+        }
+
+        let query = query
+            .select((
+                schema::files::id,
+                schema::files::name,
+                schema::files::mime,
+                schema::files::size,
+                schema::files::hash,
+                schema::files::uploaded_at,
+            ))
+            .order(schema::files::id.asc())
+            // fetch one more file to tell whether there is a next page
+            .limit(limit as i64 + 1);
+        let files = self
+            .read_pool
+            .measure("get_uncollected_files", query, |query| {
+                query.load::<File>(db)
+            })
+            .await?;
+
+        Ok(Page::new(files, limit, total, |file| file.id))
+    }
+
+    /// Retrieves a list of files matching `filter` that have no tags, e.g. to find files that still need tagging.
+    /// The result will be sorted by ID in ascending order, and comes with the number of all such files.
+    /// If `last_file_id` is provided, the result will start from the file that comes after it.
+    pub async fn get_untagged_files(
+        &self,
+        last_file_id: Option<Uuid>,
+        filter: &FileFilter,
+        limit: u32,
+    ) -> Result<Page<File, Uuid>, FileServiceError> {
+        use crate::db::schema;
+        let db = &mut self.read_pool.get().await?;
+
+        let tagged_file_ids = || schema::tags::table.select(schema::tags::file_id);
+
+        let query = filter
+            .apply(schema::files::table.into_boxed())
+            .filter(schema::files::id.ne_all(tagged_file_ids()))
+            .count();
+        let total = self
+            .read_pool
+            .measure("count_untagged_files", query, |query| {
+                query.get_result::<i64>(db)
+            })
+            .await?;
+
+        let mut query = filter
+            .apply(schema::files::table.into_boxed())
+            .filter(schema::files::id.ne_all(tagged_file_ids()));
+
+        if let Some(last_file_id) = last_file_id {
+            query = query.filter(schema::files::id.gt(last_file_id));
+        }
+
+        let query = query
+            .select((
+                schema::files::id,
+                schema::files::name,
+                schema::files::mime,
+                schema::files::size,
+                schema::files::hash,
+                schema::files::uploaded_at,
+            ))
+            .order(schema::files::id.asc())
+            // fetch one more file to tell whether there is a next page
+            .limit(limit as i64 + 1);
+        let files = self
+            .read_pool
+            .measure("get_untagged_files", query, |query| query.load::<File>(db))
+            .await?;
+
+        Ok(Page::new(files, limit, Some(total), |file| file.id))
+    }
+
+    /// Retrieves a random sample of at most `count` files matching `filter`.
+    /// It shuffles every matching file, so `count` should be kept small, and filters help on large libraries.
+    pub async fn get_random_files(
+        &self,
+        filter: &FileFilter,
+        count: u32,
+    ) -> Result<Vec<File>, FileServiceError> {
+        use crate::db::schema;
+        let db = &mut self.read_pool.get().await?;
+
+        let query = filter
+            .apply(schema::files::table.into_boxed())
+            .select((
+                schema::files::id,
+                schema::files::name,
+                schema::files::mime,
+                schema::files::size,
+                schema::files::hash,
+                schema::files::uploaded_at,
+            ))
+            .order(diesel::dsl::sql::<diesel::sql_types::Double>("random()"))
+            .limit(count as i64);
+        let files = self
+            .read_pool
+            .measure("get_random_files", query, |query| query.load::<File>(db))
+            .await?;
+
+        Ok(files)
+    }
+
+    /// Retrieves groups of files that share the same size and hash.
+    /// Only groups with two or more files are returned.
+    /// The groups will be sorted by size in descending order and hash in ascending order,
+    /// so that the groups occupying the most storage come first.
+    /// If `last_group` is provided as `(size, hash)`, the result will start from the group that comes after it.
+    /// The files in each group are sorted by name and ID (name first) in ascending order.
+    pub async fn find_duplicate_groups(
+        &self,
+        last_group: Option<(i64, i64)>,
+        limit: u32,
+    ) -> Result<Vec<DuplicateFileGroup>, FileServiceError> {
+        use crate::db::schema;
+        let db = &mut self.db_pool.get().await?;
+
+        let query = schema::files::table
+            .group_by((schema::files::size, schema::files::hash))
+            .select((schema::files::size, schema::files::hash))
+            .having(diesel::dsl::count_star().gt(1))
+            .order((schema::files::size.desc(), schema::files::hash.asc()))
+            .limit(limit as i64);
+
+        let groups = match last_group {
+            Some((last_size, last_hash)) => query
+                .filter(
+                    schema::files::size.lt(last_size).or(schema::files::size
+                        .eq(last_size)
+                        .and(schema::files::hash.gt(last_hash))),
+                )
+                .load::<(i64, i64)>(db),
+            None => query.load::<(i64, i64)>(db),
+        };
+        let groups = groups.await?;
+
+        if groups.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let sizes = groups.iter().map(|(size, _)| *size).collect::<Vec<_>>();
+        let hashes = groups.iter().map(|(_, hash)| *hash).collect::<Vec<_>>();
+
+        // the filter may match files outside of the groups, they are dropped below
+        let files = schema::files::table
+            .filter(schema::files::size.eq_any(&sizes))
+            .filter(schema::files::hash.eq_any(&hashes))
+            .select((
+                schema::files::id,
+                schema::files::name,
+                schema::files::mime,
+                schema::files::size,
+                schema::files::hash,
+                schema::files::uploaded_at,
+            ))
+            .order((schema::files::name.asc(), schema::files::id.asc()))
+            .load::<File>(db)
+            .await?;
+
+        let mut groups = groups
+            .into_iter()
+            .map(|(size, hash)| DuplicateFileGroup {
+                size,
+                hash,
+                files: Vec::new(),
+            })
+            .collect::<Vec<_>>();
+
+        for file in files {
+            let group = groups
+                .iter_mut()
+                .find(|group| group.size == file.size && group.hash == file.hash);
+
+            if let Some(group) = group {
+                group.files.push(file);
+            }
+        }
+
+        Ok(groups)
+    }
+
+    /// Retrieves a file by its ID.
+    /// Files found are cached, so that hot files are not looked up in the database on every read.
+    pub async fn get_file_by_id(&self, file_id: Uuid) -> Result<Option<File>, FileServiceError> {
+        use crate::db::schema;
+
+        if let Some(file) = self.file_cache.get(file_id).await {
+            return Ok(Some(file));
+        }
+
+        let db = &mut self.db_pool.get().await?;
+        let file = schema::files::table
+            .filter(schema::files::id.eq(file_id))
+            .select((
+                schema::files::id,
+                schema::files::name,
+                schema::files::mime,
+                schema::files::size,
+                schema::files::hash,
+                schema::files::uploaded_at,
+            ))
+            .get_result::<File>(db)
+            .await
+            .optional()?;
+
+        if let Some(file) = &file {
+            self.file_cache.insert(file).await;
+        }
+
+        Ok(file)
+    }
+
+    /// Retrieves the files with the given IDs in a single query.
+    /// IDs of files that don't exist are skipped, and the files are returned in no particular order.
+    pub async fn get_files_by_ids(&self, file_ids: &[Uuid]) -> Result<Vec<File>, FileServiceError> {
+        use crate::db::schema;
+
+        let db = &mut self.db_pool.get().await?;
+        let files = schema::files::table
+            .filter(schema::files::id.eq_any(file_ids))
+            .select((
+                schema::files::id,
+                schema::files::name,
+                schema::files::mime,
+                schema::files::size,
+                schema::files::hash,
+                schema::files::uploaded_at,
+            ))
+            .load::<File>(db)
+            .await?;
+
+        Ok(files)
+    }
+
+    /// Retrieves the media metadata of a file by its ID.
+    /// Returns `None` if no file was found or no metadata was extracted from it.
+    pub async fn get_file_metadata_by_id(
+        &self,
+        file_id: Uuid,
+    ) -> Result<Option<FileMetadata>, FileServiceError> {
+        use crate::db::schema;
+
+        let db = &mut self.db_pool.get().await?;
+        let metadata = schema::files::table
+            .filter(schema::files::id.eq(file_id))
+            .select(schema::files::file_metadata)
+            .get_result::<Option<serde_json::Value>>(db)
+            .await
+            .optional()?;
+
+        // metadata that no longer matches the current shape is treated as missing
+        let metadata = metadata
+            .flatten()
+            .and_then(|metadata| serde_json::from_value(metadata).ok());
+
+        Ok(metadata)
+    }
+
+    /// Retrieves the text content extracted from a file by its ID.
+    /// Returns `None` if no file was found or no text was extracted from it.
+    async fn get_file_search_content_by_id(
+        &self,
+        file_id: Uuid,
+    ) -> Result<Option<String>, FileServiceError> {
+        use crate::db::schema;
+
+        let db = &mut self.db_pool.get().await?;
+        let content = schema::files::table
+            .filter(schema::files::id.eq(file_id))
+            .select(schema::files::search_content)
+            .get_result::<Option<String>>(db)
+            .await
+            .optional()?;
+
+        Ok(content.flatten())
+    }
+
+    /// Retrieves the storage information of a file by its ID.
+    /// Returns `None` if no file was found.
+    pub async fn get_file_storage_info_by_id(
+        &self,
+        file_id: Uuid,
+    ) -> Result<Option<FileStorageInfo>, FileServiceError> {
+        use crate::db::schema;
+
+        let db = &mut self.db_pool.get().await?;
+        let file = schema::files::table
+            .filter(schema::files::id.eq(file_id))
+            .select((schema::files::size, schema::files::verified_at))
+            .get_result::<(i64, Option<NaiveDateTime>)>(db)
+            .await
+            .optional()?;
+
+        let (size, verified_at) = match file {
+            Some(file) => file,
+            None => return Ok(None),
+        };
+
+        let storage = self.file_driver.locate(file_id).await?;
+
+        Ok(Some(FileStorageInfo {
+            file_id,
+            size,
+            verified_at,
+            storage,
+        }))
+    }
+
+    /// Records a read of `served_bytes` bytes of a file.
+    /// The read is counted as a download only if `is_download` is `true`, so that resumed or seeking reads are counted once.
+    pub async fn record_file_download(
+        &self,
+        file_id: Uuid,
+        served_bytes: u64,
+        is_download: bool,
+    ) -> Result<(), FileServiceError> {
+        use crate::db::schema;
+
+        let db = &mut self.db_pool.get().await?;
+        let query = diesel::update(schema::files::table).filter(schema::files::id.eq(file_id));
+        let served_bytes =
+            schema::files::served_bytes.eq(schema::files::served_bytes + served_bytes as i64);
+
+        if is_download {
+            query
+                .set((
+                    served_bytes,
+                    schema::files::download_count.eq(schema::files::download_count + 1),
+                    schema::files::last_downloaded_at.eq(diesel::dsl::now.nullable()),
+                ))
+                .execute(db)
+                .await?;
+        } else {
+            query.set(served_bytes).execute(db).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Retrieves the download statistics of a file by its ID.
+    /// Returns `None` if no file was found.
+    pub async fn get_file_stats_by_id(
+        &self,
+        file_id: Uuid,
+    ) -> Result<Option<FileStats>, FileServiceError> {
+        use crate::db::schema;
+
+        let db = &mut self.db_pool.get().await?;
+        let stats = schema::files::table
+            .filter(schema::files::id.eq(file_id))
+            .select((
+                schema::files::download_count,
+                schema::files::served_bytes,
+                schema::files::last_downloaded_at,
+            ))
+            .get_result::<(i64, i64, Option<NaiveDateTime>)>(db)
+            .await
+            .optional()?;
+
+        Ok(
+            stats.map(|(downloads, served_bytes, last_downloaded_at)| FileStats {
+                file_id,
+                downloads,
+                served_bytes,
+                last_downloaded_at,
+            }),
+        )
+    }
+
+    /// Retrieves the images that look similar to a file, by the Hamming distance of their perceptual hashes.
+    /// `max_distance` can only narrow the maximum distance of the config.
+    /// The result will be sorted by distance and ID in ascending order.
+    /// Returns `None` if no file was found, and no files if the file has no perceptual hash, e.g. if it is not an image.
+    pub async fn get_similar_files(
+        &self,
+        file_id: Uuid,
+        max_distance: Option<u32>,
+        limit: u32,
+    ) -> Result<Option<Vec<SimilarFile>>, FileServiceError> {
+        use crate::db::schema;
+        use diesel::{
+            dsl::sql,
+            sql_types::{BigInt, Integer},
+        };
+
+        let max_distance = match max_distance {
+            Some(max_distance) => max_distance.min(self.perceptual_hash_service.max_distance()),
+            None => self.perceptual_hash_service.max_distance(),
+        };
+
+        let db = &mut self.read_pool.get().await?;
+        let perceptual_hash = schema::files::table
+            .filter(schema::files::id.eq(file_id))
+            .select(schema::files::perceptual_hash)
+            .get_result::<Option<i64>>(db)
+            .await
+            .optional()?;
+
+        let perceptual_hash = match perceptual_hash {
+            Some(Some(perceptual_hash)) => perceptual_hash,
+            Some(None) => return Ok(Some(Vec::new())),
+            None => return Ok(None),
+        };
+
+        // counts the differing bits; `bit_count` is avoided, as it requires PostgreSQL 14
+        let distance = || {
+            sql::<Integer>("length(replace(((files.perceptual_hash # ")
+                .bind::<BigInt, _>(perceptual_hash)
+                .sql(")::BIT(64))::TEXT, '0', ''))")
+        };
+
+        let query = schema::files::table
+            .filter(schema::files::id.ne(file_id))
+            .filter(schema::files::perceptual_hash.is_not_null())
+            .filter(distance().le(max_distance as i32))
+            .select((
+                (
+                    schema::files::id,
+                    schema::files::name,
+                    schema::files::mime,
+                    schema::files::size,
+                    schema::files::hash,
+                    schema::files::uploaded_at,
+                ),
+                distance(),
+            ))
+            .order((distance().asc(), schema::files::id.asc()))
+            .limit(limit as i64);
+        let files = self
+            .read_pool
+            .measure("get_similar_files", query, |query| {
+                query.load::<(File, i32)>(db)
+            })
+            .await?;
+
+        Ok(Some(
+            files
+                .into_iter()
+                .map(|(file, distance)| SimilarFile { file, distance })
+                .collect(),
+        ))
+    }
+
+    /// Retrieves the result of the last malware scan of a file by its ID.
+    /// Returns `None` if no file was found.
+    pub async fn get_file_scan_by_id(
+        &self,
+        file_id: Uuid,
+    ) -> Result<Option<FileScan>, FileServiceError> {
+        use crate::db::schema;
+
+        let db = &mut self.db_pool.get().await?;
+        let scan = schema::files::table
+            .filter(schema::files::id.eq(file_id))
+            .select((
+                schema::files::scan_status,
+                schema::files::scan_signature,
+                schema::files::scanned_at,
+            ))
+            .get_result::<(Option<String>, Option<String>, Option<NaiveDateTime>)>(db)
+            .await
+            .optional()?;
+
+        Ok(scan.map(|(status, signature, scanned_at)| FileScan {
+            file_id,
+            status: status.as_deref().and_then(ScanStatus::from_name),
+            signature,
+            scanned_at,
+        }))
+    }
+
+    /// Retrieves the file data by its ID.
+    pub async fn get_file_data_by_id(
+        &self,
+        file_id: Uuid,
+        range: ReadRange,
+    ) -> Result<Option<Pin<Box<dyn AsyncRead + Send>>>, ReadError> {
         let data = self.file_driver.read(file_id, range).await?;
 
         Ok(data)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::SearchBackend,
+        test::{create_test_rocket_instance_with_config, TestFaults, TestFileDriver},
+    };
+    use chrono::Duration;
+    use rocket::local::asynchronous::Client;
+    use std::{io::Cursor, sync::atomic::Ordering};
+
+    /// Creates a staging file written in `chunks`, one after another.
+    async fn create_chunked_staging_file(
+        staging_file_service: &StagingFileService,
+        chunks: &[&str],
+    ) -> StagingFile {
+        let size = chunks.iter().map(|chunk| chunk.len() as u64).sum::<u64>();
+        let staging_file = staging_file_service
+            .create_staging_file("file", Some("text/plain"), Some(size), None)
+            .await
+            .unwrap();
+        let mut offset = 0;
+
+        for chunk in chunks {
+            staging_file_service
+                .fill_staging_file_chunk_by_id(
+                    staging_file.id,
+                    offset,
+                    chunk.len() as u64,
+                    Box::pin(Cursor::new(chunk.as_bytes().to_vec())),
+                )
+                .await
+                .unwrap()
+                .unwrap()
+                .unwrap();
+            offset += chunk.len() as u64;
+        }
+
+        staging_file_service
+            .get_staging_file_by_id(staging_file.id)
+            .await
+            .unwrap()
+            .unwrap()
+    }
+
+    async fn count_staging_file_chunks(file_service: &FileService, staging_file_id: Uuid) -> i64 {
+        use crate::db::schema;
+
+        let db = &mut file_service.db_pool.get().await.unwrap();
+        schema::staging_file_chunks::table
+            .filter(schema::staging_file_chunks::staging_file_id.eq(staging_file_id))
+            .count()
+            .get_result::<i64>(db)
+            .await
+            .unwrap()
+    }
+
+    /// Makes the pending commit of a file due now, instead of waiting for its backoff.
+    async fn make_pending_commit_due(file_service: &FileService, file_id: Uuid) {
+        use crate::db::schema;
+
+        let db = &mut file_service.db_pool.get().await.unwrap();
+        diesel::update(
+            schema::pending_commits::table.filter(schema::pending_commits::file_id.eq(file_id)),
+        )
+        .set(schema::pending_commits::next_attempt_at.eq(diesel::dsl::now))
+        .execute(db)
+        .await
+        .unwrap();
+    }
+
+    #[rocket::async_test]
+    async fn test_create_file_compensates_failed_commit() {
+        let faults = TestFaults::default();
+        let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance_with_config(
+            TestFileDriver::Memory,
+            SearchBackend::Meilisearch,
+            |app_config| app_config.test_faults = faults.clone(),
+        )
+        .await;
+        let client = Client::tracked(rocket).await.unwrap();
+        let staging_file_service = client.rocket().state::<Arc<StagingFileService>>().unwrap();
+        let file_service = client.rocket().state::<Arc<FileService>>().unwrap();
+
+        let staging_file =
+            create_chunked_staging_file(staging_file_service, &["file", " content"]).await;
+
+        faults.fail_commits.store(true, Ordering::SeqCst);
+
+        let result = file_service
+            .create_file_from_staging_file_id(staging_file.id)
+            .await;
+
+        assert!(matches!(result, Err(FileServiceError::IO(_))));
+
+        // the file is turned back into the staging file, along with its chunks
+        assert_eq!(
+            staging_file_service
+                .get_staging_file_by_id(staging_file.id)
+                .await
+                .unwrap(),
+            Some(staging_file.clone())
+        );
+        assert_eq!(
+            count_staging_file_chunks(file_service, staging_file.id).await,
+            2
+        );
+        assert_eq!(
+            file_service.get_file_by_id(staging_file.id).await.unwrap(),
+            None
+        );
+        assert_eq!(
+            file_service
+                .get_pending_commit_by_file_id(staging_file.id)
+                .await
+                .unwrap(),
+            None
+        );
+
+        // so the promotion can be retried once the storage is back
+        faults.fail_commits.store(false, Ordering::SeqCst);
+
+        let file = file_service
+            .create_file_from_staging_file_id(staging_file.id)
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(file.id, staging_file.id);
+        assert_eq!(file.size, 12);
+        assert_eq!(
+            count_staging_file_chunks(file_service, staging_file.id).await,
+            0
+        );
+        assert_eq!(
+            file_service
+                .get_pending_commit_by_file_id(file.id)
+                .await
+                .unwrap(),
+            None
+        );
+    }
+
+    #[rocket::async_test]
+    async fn test_create_file_retries_failed_indexing() {
+        let faults = TestFaults::default();
+        let (rocket, _database_dropper, _index_dropper) = create_test_rocket_instance_with_config(
+            TestFileDriver::Memory,
+            SearchBackend::Meilisearch,
+            |app_config| app_config.test_faults = faults.clone(),
+        )
+        .await;
+        let client = Client::tracked(rocket).await.unwrap();
+        let staging_file_service = client.rocket().state::<Arc<StagingFileService>>().unwrap();
+        let file_service = client.rocket().state::<Arc<FileService>>().unwrap();
+
+        let staging_file =
+            create_chunked_staging_file(staging_file_service, &["file content"]).await;
+
+        faults.fail_file_indexing.store(true, Ordering::SeqCst);
+
+        // the file is created regardless, and the indexing is left for later
+        let file = file_service
+            .create_file_from_staging_file_id(staging_file.id)
+            .await
+            .unwrap()
+            .unwrap();
+
+        let pending_commit = file_service
+            .get_pending_commit_by_file_id(file.id)
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(pending_commit.stage, PendingCommitStage::Index.name());
+        assert_eq!(pending_commit.attempts, 1);
+        assert!(pending_commit.last_error.is_some());
+        assert!(pending_commit.created_at + Duration::seconds(1) <= pending_commit.next_attempt_at);
+
+        // it is not retried until the backoff has passed
+        assert_eq!(
+            file_service.retry_pending_commits(100).await.unwrap(),
+            (0, 0)
+        );
+
+        make_pending_commit_due(file_service, file.id).await;
+
+        assert_eq!(
+            file_service.retry_pending_commits(100).await.unwrap(),
+            (0, 1)
+        );
+
+        let pending_commit = file_service
+            .get_pending_commit_by_file_id(file.id)
+            .await
+            .unwrap()
+            .unwrap();
+
+        // the backoff doubles with each attempt
+        assert_eq!(pending_commit.attempts, 2);
+        assert!(pending_commit.created_at + Duration::seconds(2) <= pending_commit.next_attempt_at);
+
+        faults.fail_file_indexing.store(false, Ordering::SeqCst);
+        make_pending_commit_due(file_service, file.id).await;
+
+        assert_eq!(
+            file_service.retry_pending_commits(100).await.unwrap(),
+            (1, 0)
+        );
+        assert_eq!(
+            file_service
+                .get_pending_commit_by_file_id(file.id)
+                .await
+                .unwrap(),
+            None
+        );
+    }
+}
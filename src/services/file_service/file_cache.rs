@@ -0,0 +1,84 @@
+use crate::{config::AppFileCache, db::models::File};
+use moka::future::Cache;
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+use uuid::Uuid;
+
+/// Counters describing how effective the file record cache is.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct FileCacheStats {
+    pub enabled: bool,
+    /// Lookups served from the cache.
+    pub hits: u64,
+    /// Lookups that had to query the database.
+    pub misses: u64,
+    /// Files currently kept in the cache.
+    pub entries: u64,
+}
+
+/// An in-process LRU cache of file records by their IDs.
+/// Only existing files are cached, so that new files are visible right away.
+pub struct FileCache {
+    cache: Option<Cache<Uuid, File>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl FileCache {
+    pub fn new(config: &AppFileCache) -> Self {
+        let cache = if config.capacity == 0 {
+            None
+        } else {
+            Some(
+                Cache::builder()
+                    .max_capacity(config.capacity)
+                    .time_to_live(Duration::from_secs(config.ttl))
+                    .build(),
+            )
+        };
+
+        Self {
+            cache,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    pub async fn get(&self, file_id: Uuid) -> Option<File> {
+        let cache = self.cache.as_ref()?;
+        let file = cache.get(&file_id).await;
+
+        if file.is_some() {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+        }
+
+        file
+    }
+
+    pub async fn insert(&self, file: &File) {
+        if let Some(cache) = &self.cache {
+            cache.insert(file.id, file.clone()).await;
+        }
+    }
+
+    pub async fn invalidate(&self, file_id: Uuid) {
+        if let Some(cache) = &self.cache {
+            cache.invalidate(&file_id).await;
+        }
+    }
+
+    pub fn stats(&self) -> FileCacheStats {
+        FileCacheStats {
+            enabled: self.cache.is_some(),
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            entries: self.cache.as_ref().map_or(0, |cache| cache.entry_count()),
+        }
+    }
+}
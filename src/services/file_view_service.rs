@@ -0,0 +1,82 @@
+use crate::db::models::{CreatingFileView, File};
+use diesel::{ExpressionMethods, QueryDsl};
+use diesel_async::{pooled_connection::deadpool::Pool, AsyncPgConnection, RunQueryDsl};
+use std::sync::Arc;
+use thiserror::Error;
+use uuid::Uuid;
+
+#[derive(Error, Debug)]
+pub enum FileViewServiceError {
+    #[error("database pool error: {0}")]
+    Pool(#[from] diesel_async::pooled_connection::deadpool::PoolError),
+    #[error("diesel error: {0}")]
+    Diesel(#[from] diesel::result::Error),
+}
+
+/// Keeps the last time each user viewed each file.
+/// Only the most recent view is kept, so the table stays small no matter how often files are read.
+pub struct FileViewService {
+    db_pool: Pool<AsyncPgConnection>,
+}
+
+impl FileViewService {
+    pub fn new(db_pool: Pool<AsyncPgConnection>) -> Arc<Self> {
+        Arc::new(Self { db_pool })
+    }
+
+    /// Records that the user viewed the file just now.
+    /// It does nothing if the file does not exist anymore.
+    pub async fn record_file_view(
+        &self,
+        user_id: i32,
+        file_id: Uuid,
+    ) -> Result<(), FileViewServiceError> {
+        use crate::db::schema;
+
+        let db = &mut self.db_pool.get().await?;
+        let result = diesel::insert_into(schema::file_views::table)
+            .values(CreatingFileView { user_id, file_id })
+            .on_conflict((schema::file_views::user_id, schema::file_views::file_id))
+            .do_update()
+            .set(schema::file_views::viewed_at.eq(diesel::dsl::now))
+            .execute(db)
+            .await;
+
+        match result {
+            Ok(_) => Ok(()),
+            Err(diesel::result::Error::DatabaseError(
+                diesel::result::DatabaseErrorKind::ForeignKeyViolation,
+                err,
+            )) if err.constraint_name() == Some("file_views_file_fk") => Ok(()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Retrieves the files the user viewed, from the most recently viewed one.
+    pub async fn get_recently_viewed_files(
+        &self,
+        user_id: i32,
+        limit: u32,
+    ) -> Result<Vec<File>, FileViewServiceError> {
+        use crate::db::schema;
+
+        let db = &mut self.db_pool.get().await?;
+        let files = schema::file_views::table
+            .inner_join(schema::files::table)
+            .filter(schema::file_views::user_id.eq(user_id))
+            .select((
+                schema::files::id,
+                schema::files::name,
+                schema::files::mime,
+                schema::files::size,
+                schema::files::hash,
+                schema::files::uploaded_at,
+            ))
+            .order(schema::file_views::viewed_at.desc())
+            .limit(limit as i64)
+            .load::<File>(db)
+            .await?;
+
+        Ok(files)
+    }
+}
@@ -0,0 +1,83 @@
+use super::FileDriver;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use thiserror::Error;
+
+/// An upload that does not fit in a volume of the storage without eating into the reserve.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("the {volume} volume has {available} bytes free, which cannot take {required} more bytes while keeping {reserve} bytes free")]
+pub struct InsufficientStorage {
+    pub volume: &'static str,
+    pub required: u64,
+    pub available: u64,
+    pub reserve: u64,
+}
+
+/// The space left in the volumes of the storage, in bytes.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct FreeSpaceStats {
+    /// The space left in the volume staging files are written to, or `None` if it is unknown or not limited.
+    pub staging: Option<u64>,
+    /// The space left in the volume committed files are kept in, or `None` if it is unknown or not limited.
+    pub resident: Option<u64>,
+    /// The space kept free in each volume; uploads that would eat into it are rejected.
+    pub reserve: u64,
+}
+
+/// Keeps uploads from filling up the volumes of the storage.
+pub struct FreeSpaceService {
+    file_driver: Arc<dyn FileDriver + Send + Sync>,
+    reserve: u64,
+}
+
+impl FreeSpaceService {
+    pub fn new(file_driver: Arc<dyn FileDriver + Send + Sync>, reserve: u64) -> Arc<Self> {
+        Arc::new(Self {
+            file_driver,
+            reserve,
+        })
+    }
+
+    /// Retrieves the space left in the volumes of the storage.
+    pub async fn stats(&self) -> Result<FreeSpaceStats, std::io::Error> {
+        let free_space = self.file_driver.free_space().await?;
+
+        Ok(FreeSpaceStats {
+            staging: free_space.staging,
+            resident: free_space.resident,
+            reserve: self.reserve,
+        })
+    }
+
+    /// Checks that an upload of `size` bytes fits in both the staging and the resident volume, keeping the reserve free.
+    /// The data is written to the staging volume first, and moved to the resident volume once it is committed.
+    pub async fn check(
+        &self,
+        size: u64,
+    ) -> Result<Result<(), InsufficientStorage>, std::io::Error> {
+        let free_space = self.file_driver.free_space().await?;
+        let volumes = [
+            ("staging", free_space.staging),
+            ("resident", free_space.resident),
+        ];
+
+        for (volume, available) in volumes {
+            let available = match available {
+                Some(available) => available,
+                None => continue,
+            };
+
+            if available < size.saturating_add(self.reserve) {
+                return Ok(Err(InsufficientStorage {
+                    volume,
+                    required: size,
+                    available,
+                    reserve: self.reserve,
+                }));
+            }
+        }
+
+        Ok(Ok(()))
+    }
+}
@@ -0,0 +1,157 @@
+use super::FileDriver;
+use diesel::QueryDsl;
+use diesel_async::{
+    pooled_connection::deadpool::Pool, scoped_futures::ScopedFutureExt, AsyncPgConnection,
+    RunQueryDsl,
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashSet, sync::Arc};
+use thiserror::Error;
+use uuid::Uuid;
+
+#[derive(Error, Debug)]
+pub enum GcServiceError {
+    #[error("database pool error: {0}")]
+    Pool(#[from] diesel_async::pooled_connection::deadpool::PoolError),
+    #[error("diesel error: {0}")]
+    Diesel(#[from] diesel::result::Error),
+    #[error("io error: {0}")]
+    IO(#[from] std::io::Error),
+}
+
+/// A blob in the file driver that no file refers to.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct OrphanedBlob {
+    pub id: Uuid,
+    /// The space the blob occupies in the storage, or `None` if it has vanished since the listing.
+    pub size_on_disk: Option<u64>,
+}
+
+/// Differences between the blobs in the file driver and the `files` table.
+/// When returned from a sweep, the orphaned blobs are the ones that have been removed.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct GcReport {
+    /// Blobs that no file refers to.
+    pub orphaned_blobs: Vec<OrphanedBlob>,
+    /// Files whose blob is missing in the file driver.
+    /// They are never removed automatically, as they may be recovered from elsewhere.
+    pub missing_blob_file_ids: Vec<Uuid>,
+}
+
+impl GcReport {
+    /// Returns `true` if the file driver and the `files` table agree with each other.
+    pub fn is_clean(&self) -> bool {
+        self.orphaned_blobs.is_empty() && self.missing_blob_file_ids.is_empty()
+    }
+
+    /// Returns the total space occupied by the orphaned blobs.
+    pub fn orphaned_size_on_disk(&self) -> u64 {
+        self.orphaned_blobs
+            .iter()
+            .filter_map(|blob| blob.size_on_disk)
+            .sum()
+    }
+}
+
+pub struct GcService {
+    db_pool: Pool<AsyncPgConnection>,
+    file_driver: Arc<dyn FileDriver + Send + Sync>,
+}
+
+impl GcService {
+    pub fn new(
+        db_pool: Pool<AsyncPgConnection>,
+        file_driver: Arc<dyn FileDriver + Send + Sync>,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            db_pool,
+            file_driver,
+        })
+    }
+
+    /// Reconciles the blobs in the file driver against the `files` table without modifying anything.
+    pub async fn check(&self) -> Result<GcReport, GcServiceError> {
+        self.reconcile(false).await
+    }
+
+    /// Reconciles the blobs in the file driver against the `files` table, and removes the orphaned blobs.
+    /// Returns the report of the blobs that have been removed.
+    pub async fn sweep(&self) -> Result<GcReport, GcServiceError> {
+        self.reconcile(true).await
+    }
+
+    async fn reconcile(&self, remove_orphans: bool) -> Result<GcReport, GcServiceError> {
+        use crate::db::schema;
+
+        // list the blobs before reading the rows: a file being promoted right now has its staging file row
+        // until its file row is committed, so its blob is never taken for an orphan
+        let blob_ids = self.file_driver.list().await?;
+
+        let db = &mut self.db_pool.get().await?;
+        let (file_ids, staging_file_ids, file_version_ids) = db
+            .build_transaction()
+            .read_only()
+            .repeatable_read()
+            .run(|db| {
+                async move {
+                    let file_ids = schema::files::table
+                        .select(schema::files::id)
+                        .load::<Uuid>(db)
+                        .await?;
+                    let staging_file_ids = schema::staging_files::table
+                        .select(schema::staging_files::id)
+                        .load::<Uuid>(db)
+                        .await?;
+                    // former versions of files keep their data under their own IDs
+                    let file_version_ids = schema::file_versions::table
+                        .select(schema::file_versions::id)
+                        .load::<Uuid>(db)
+                        .await?;
+
+                    Ok::<_, GcServiceError>((file_ids, staging_file_ids, file_version_ids))
+                }
+                .scope_boxed()
+            })
+            .await?;
+
+        let blob_id_set = blob_ids.iter().copied().collect::<HashSet<_>>();
+        let file_id_set = file_ids.iter().copied().collect::<HashSet<_>>();
+        let staging_file_id_set = staging_file_ids.into_iter().collect::<HashSet<_>>();
+        let file_version_id_set = file_version_ids.into_iter().collect::<HashSet<_>>();
+
+        let mut orphaned_blobs = Vec::new();
+
+        for id in blob_ids {
+            if file_id_set.contains(&id)
+                || staging_file_id_set.contains(&id)
+                || file_version_id_set.contains(&id)
+            {
+                continue;
+            }
+
+            let size_on_disk = self.file_driver.locate(id).await?.size_on_disk;
+
+            if remove_orphans {
+                if let Err(err) = self.file_driver.remove(id).await {
+                    log::warn!(target: "services::gc_service", method = "reconcile", id:serde, err:err; "Failed to remove orphaned blob.");
+                    continue;
+                }
+            }
+
+            orphaned_blobs.push(OrphanedBlob { id, size_on_disk });
+        }
+
+        // files committed after the listing are not missing their blobs, but they are rare and only reported
+        let missing_blob_file_ids = file_ids
+            .into_iter()
+            .filter(|id| !blob_id_set.contains(id))
+            .collect();
+
+        Ok(GcReport {
+            orphaned_blobs,
+            missing_blob_file_ids,
+        })
+    }
+}
@@ -0,0 +1,53 @@
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// The UUID version to generate IDs of new rows with.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum IdVersion {
+    /// Random UUIDs.
+    V4,
+    /// Time-ordered UUIDs. New rows are appended to the end of the indices,
+    /// which keeps the B-tree indices compact, and IDs follow the creation order.
+    V7,
+}
+
+/// The order to list rows in with keyset pagination.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ListOrder {
+    /// By name, and by ID for the same names.
+    Name,
+    /// By ID only. It follows the creation order for rows created with UUIDv7,
+    /// and it is the cheapest order as it is served by the primary key alone.
+    Id,
+}
+
+impl ListOrder {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "name" => Some(Self::Name),
+            "id" => Some(Self::Id),
+            _ => None,
+        }
+    }
+}
+
+pub struct IdService {
+    version: IdVersion,
+}
+
+impl IdService {
+    pub fn new(version: IdVersion) -> Arc<Self> {
+        Arc::new(Self { version })
+    }
+
+    /// Generates a new ID with the configured UUID version.
+    pub fn generate(&self) -> Uuid {
+        match self.version {
+            IdVersion::V4 => Uuid::new_v4(),
+            IdVersion::V7 => Uuid::now_v7(),
+        }
+    }
+}
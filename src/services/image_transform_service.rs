@@ -0,0 +1,363 @@
+use super::{FileService, ReadError, ReadRange};
+use crate::{config::AppImageTransform, db::models::File};
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::SystemTime,
+};
+use thiserror::Error;
+use tokio::{io::AsyncWriteExt, process::Command, sync::Semaphore};
+use uuid::Uuid;
+
+/// The widths and heights that asked dimensions are rounded up to, up to the maximum dimension.
+const DIMENSION_BUCKETS: [u32; 7] = [64, 128, 256, 512, 1024, 2048, 4096];
+
+#[derive(Error, Debug)]
+pub enum ImageTransformServiceError {
+    #[error("io error: {0}")]
+    IO(#[from] std::io::Error),
+    #[error("read error: {0}")]
+    Read(#[from] ReadError),
+    #[error("the data of the file is missing")]
+    SourceMissing,
+    #[error("ffmpeg exited with {status}: {stderr}")]
+    FFmpeg {
+        status: std::process::ExitStatus,
+        stderr: String,
+    },
+}
+
+/// The format to convert an image into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+    Webp,
+    Jpeg,
+    Png,
+}
+
+impl ImageFormat {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "webp" => Some(Self::Webp),
+            "jpeg" | "jpg" => Some(Self::Jpeg),
+            "png" => Some(Self::Png),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Webp => "webp",
+            Self::Jpeg => "jpeg",
+            Self::Png => "png",
+        }
+    }
+
+    pub fn mime(self) -> &'static str {
+        match self {
+            Self::Webp => "image/webp",
+            Self::Jpeg => "image/jpeg",
+            Self::Png => "image/png",
+        }
+    }
+
+    fn ffmpeg_args(self) -> &'static [&'static str] {
+        match self {
+            Self::Webp => &["-c:v", "libwebp", "-quality", "80", "-f", "webp"],
+            Self::Jpeg => &["-c:v", "mjpeg", "-q:v", "3", "-f", "image2"],
+            Self::Png => &["-c:v", "png", "-f", "image2"],
+        }
+    }
+}
+
+/// How to transform an image. The image is shrunk to fit in the given width and height, keeping its aspect ratio,
+/// but it is never enlarged.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageTransform {
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub format: ImageFormat,
+}
+
+impl ImageTransform {
+    /// A key that is unique to the transform, e.g. `320x0.webp` for a width of `320` in WebP.
+    pub fn key(&self) -> String {
+        format!(
+            "{}x{}.{}",
+            self.width.unwrap_or(0),
+            self.height.unwrap_or(0),
+            self.format.name()
+        )
+    }
+
+    fn scale_filter(&self) -> Option<String> {
+        match (self.width, self.height) {
+            (Some(width), Some(height)) => Some(format!(
+                "scale='min(iw,{})':'min(ih,{})':force_original_aspect_ratio=decrease",
+                width, height
+            )),
+            (Some(width), None) => Some(format!("scale='min(iw,{})':-1", width)),
+            (None, Some(height)) => Some(format!("scale=-1:'min(ih,{})'", height)),
+            (None, None) => None,
+        }
+    }
+}
+
+/// Resizes and converts images with `ffmpeg` as they are downloaded, so that clients need not download the originals.
+/// The results are cached on disk by the file, its hash and the transform, so a replaced file is transformed again.
+/// The cache is kept within its maximum size by evicting the least recently used results.
+pub struct ImageTransformService {
+    enabled: bool,
+    file_service: Arc<FileService>,
+    ffmpeg_path: PathBuf,
+    cache_base_path: PathBuf,
+    temp_base_path: PathBuf,
+    max_dimension: u32,
+    max_cache_size: u64,
+    /// The permits to run `ffmpeg`, one for each transform that may run at once.
+    transform_permits: Semaphore,
+}
+
+impl ImageTransformService {
+    pub fn new(
+        file_service: Arc<FileService>,
+        config: &AppImageTransform,
+        temp_base_path: impl Into<PathBuf>,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            enabled: config.enabled,
+            file_service,
+            ffmpeg_path: config.ffmpeg_path.clone(),
+            cache_base_path: config.cache_base_path.clone(),
+            temp_base_path: temp_base_path.into(),
+            max_dimension: config.max_dimension,
+            max_cache_size: config.max_cache_size.as_u64(),
+            transform_permits: Semaphore::new(usize::max(
+                1,
+                config.max_concurrent_transforms as usize,
+            )),
+        })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// The maximum width and height of transformed images.
+    pub fn max_dimension(&self) -> u32 {
+        self.max_dimension
+    }
+
+    /// Rounds a valid dimension up to the nearest of the fixed sizes, or to the maximum dimension beyond them,
+    /// so that only a few sizes of an image are ever transformed and cached.
+    pub fn snap_dimension(&self, dimension: u32) -> u32 {
+        DIMENSION_BUCKETS
+            .into_iter()
+            .find(|&bucket| dimension <= bucket)
+            .map_or(self.max_dimension, |bucket| {
+                u32::min(bucket, self.max_dimension)
+            })
+    }
+
+    /// Opens a transformed image, transforming it first unless it has been cached.
+    pub async fn open_transformed(
+        &self,
+        file: &File,
+        transform: &ImageTransform,
+    ) -> Result<tokio::fs::File, ImageTransformServiceError> {
+        let cache_path = self.cache_base_path.join(file.id.to_string()).join(format!(
+            "{:016x}-{}",
+            file.hash as u64,
+            transform.key()
+        ));
+
+        if let Some(cached) = open_cached(&cache_path).await? {
+            return Ok(cached);
+        }
+
+        let _permit = self
+            .transform_permits
+            .acquire()
+            .await
+            .expect("the semaphore is never closed");
+
+        // another request may have transformed the same image while this one was waiting
+        if let Some(cached) = open_cached(&cache_path).await? {
+            return Ok(cached);
+        }
+
+        let name = format!("image-transform-{}", Uuid::new_v4());
+        let input_path = self.temp_base_path.join(format!("{}-input", name));
+        let output_path = self.temp_base_path.join(format!("{}-output", name));
+
+        let result = self
+            .transform_into(file.id, &input_path, &output_path, transform)
+            .await;
+
+        // the temporary files may not exist, so the errors are ignored
+        tokio::fs::remove_file(&input_path).await.ok();
+
+        if let Err(err) = result {
+            tokio::fs::remove_file(&output_path).await.ok();
+            return Err(err);
+        }
+
+        if let Some(parent) = cache_path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+
+        // the temporary path may be on another device, so fall back to copying
+        if tokio::fs::rename(&output_path, &cache_path).await.is_err() {
+            let result = tokio::fs::copy(&output_path, &cache_path).await;
+            tokio::fs::remove_file(&output_path).await.ok();
+            result?;
+        }
+
+        let cached = tokio::fs::File::open(&cache_path).await?;
+
+        if let Err(err) = self.evict_cached(&cache_path).await {
+            log::warn!(target: "image_transform_service", err:err; "Failed to evict cached transformed images.");
+        }
+
+        Ok(cached)
+    }
+
+    /// Evicts the least recently used transformed images until the cache fits in its maximum size.
+    /// `keep` is never evicted.
+    async fn evict_cached(&self, keep: &Path) -> Result<(), ImageTransformServiceError> {
+        let mut entries = Vec::new();
+        let mut cache_size = 0;
+        let mut file_dirs = tokio::fs::read_dir(&self.cache_base_path).await?;
+
+        // images may be removed along the way by other requests, so the ones that are gone are skipped
+        while let Some(file_dir) = file_dirs.next_entry().await? {
+            let mut cached_images = match tokio::fs::read_dir(file_dir.path()).await {
+                Ok(cached_images) => cached_images,
+                Err(_) => continue,
+            };
+
+            while let Some(cached_image) = cached_images.next_entry().await? {
+                let metadata = match cached_image.metadata().await {
+                    Ok(metadata) => metadata,
+                    Err(_) => continue,
+                };
+                let used_at = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+
+                cache_size += metadata.len();
+                entries.push((cached_image.path(), metadata.len(), used_at));
+            }
+        }
+
+        if cache_size <= self.max_cache_size {
+            return Ok(());
+        }
+
+        entries.sort_by_key(|(_, _, used_at)| *used_at);
+
+        for (path, size, _) in entries {
+            if cache_size <= self.max_cache_size {
+                break;
+            }
+
+            if path == keep {
+                continue;
+            }
+
+            match tokio::fs::remove_file(&path).await {
+                Ok(()) => {}
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+                Err(err) => return Err(err.into()),
+            }
+
+            cache_size -= size;
+        }
+
+        Ok(())
+    }
+
+    /// Removes all cached transformed images of a file.
+    pub async fn remove_cached(&self, file_id: Uuid) -> Result<(), ImageTransformServiceError> {
+        let path = self.cache_base_path.join(file_id.to_string());
+
+        match tokio::fs::remove_dir_all(&path).await {
+            Ok(()) => Ok(()),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    async fn transform_into(
+        &self,
+        file_id: Uuid,
+        input_path: &Path,
+        output_path: &Path,
+        transform: &ImageTransform,
+    ) -> Result<(), ImageTransformServiceError> {
+        let data = self
+            .file_service
+            .get_file_data_by_id(file_id, ReadRange::Full)
+            .await?;
+        let mut data = match data {
+            Some(data) => data,
+            None => return Err(ImageTransformServiceError::SourceMissing),
+        };
+
+        let mut input = tokio::fs::File::create(input_path).await?;
+        tokio::io::copy(&mut data, &mut input).await?;
+        input.flush().await?;
+        drop(input);
+
+        let mut command = Command::new(&self.ffmpeg_path);
+        command
+            .args(["-nostdin", "-y", "-v", "error", "-i"])
+            .arg(input_path)
+            // animated images are reduced to their first frame
+            .args(["-frames:v", "1"]);
+
+        if let Some(scale_filter) = transform.scale_filter() {
+            command.args(["-vf", &scale_filter]);
+        }
+
+        let output = command
+            .args(transform.format.ffmpeg_args())
+            .arg(output_path)
+            .kill_on_drop(true)
+            .output()
+            .await?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            // keep the tail only, where ffmpeg reports the actual cause
+            let stderr = stderr
+                .char_indices()
+                .rev()
+                .nth(1024)
+                .map_or(&stderr[..], |(index, _)| &stderr[index..]);
+
+            return Err(ImageTransformServiceError::FFmpeg {
+                status: output.status,
+                stderr: stderr.trim().to_owned(),
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// Opens a cached transformed image, marking it as recently used.
+/// Returns `None` if the image has not been cached.
+async fn open_cached(
+    cache_path: &Path,
+) -> Result<Option<tokio::fs::File>, ImageTransformServiceError> {
+    let cached = match tokio::fs::File::open(cache_path).await {
+        Ok(cached) => cached,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+
+    // the modification time tells the least recently used images apart when the cache is full
+    let cached = cached.into_std().await;
+    cached.set_modified(SystemTime::now()).ok();
+
+    Ok(Some(tokio::fs::File::from_std(cached)))
+}
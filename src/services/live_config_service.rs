@@ -0,0 +1,65 @@
+use crate::{config::AppConfig, logger};
+use arc_swap::ArcSwap;
+use log::LevelFilter;
+use rocket::data::ByteUnit;
+use serde::{Deserialize, Serialize};
+use std::{path::PathBuf, sync::Arc};
+
+/// The config values that can be changed without restarting the server.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct LiveConfig {
+    /// The maximum size of the data of a file.
+    pub file_limit: ByteUnit,
+    /// The period after which staging files expire, in seconds.
+    pub expired_staging_file_expiration: u64,
+    /// The maximum level of the logs, or `None` if it is left to `LOG_LEVEL`.
+    pub log_level: Option<LevelFilter>,
+}
+
+impl LiveConfig {
+    fn new(app_config: &AppConfig) -> Self {
+        Self {
+            file_limit: app_config.limits.file,
+            expired_staging_file_expiration: app_config.expired_staging_file_expiration,
+            log_level: app_config.log_level,
+        }
+    }
+}
+
+/// Holds the config values that can be reloaded at runtime, e.g. on `SIGHUP`.
+/// Fairings and controllers read them through this service instead of the config managed by Rocket, which is fixed at launch.
+pub struct LiveConfigService {
+    config_path: Option<PathBuf>,
+    config: ArcSwap<LiveConfig>,
+}
+
+impl LiveConfigService {
+    pub fn new(app_config: &AppConfig) -> Arc<Self> {
+        Arc::new(Self {
+            config_path: app_config.config_path.clone(),
+            config: ArcSwap::from_pointee(LiveConfig::new(app_config)),
+        })
+    }
+
+    /// The current values.
+    pub fn get(&self) -> Arc<LiveConfig> {
+        self.config.load_full()
+    }
+
+    /// Loads the config again from the file the server has been started with, and swaps in its reloadable values.
+    /// The other values of the file are ignored until the server restarts.
+    /// If the config cannot be loaded, the current values are kept.
+    pub fn reload(&self) -> Result<Arc<LiveConfig>, figment::Error> {
+        let app_config = AppConfig::load(self.config_path.as_ref())?;
+        let config = Arc::new(LiveConfig::new(&app_config));
+
+        if let Some(log_level) = config.log_level {
+            logger::set_log_level(log_level);
+        }
+
+        self.config.store(config.clone());
+
+        Ok(config)
+    }
+}
@@ -0,0 +1,247 @@
+use crate::config::AppLoginThrottle;
+use parking_lot::Mutex;
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use thiserror::Error;
+
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoginThrottleError {
+    #[error("the account is locked, retry in {} seconds", .0.as_secs().max(1))]
+    Locked(Duration),
+    #[error("too many login attempts, retry in {} seconds", .0.as_secs().max(1))]
+    TooManyAttempts(Duration),
+}
+
+#[derive(Debug, Clone, Copy)]
+struct FailureRecord {
+    /// The failures since the last lockout, within the failure window.
+    failures: u32,
+    /// The attempts allowed by [`LoginThrottleService::check`] that have not been recorded yet.
+    /// They count as failures until they are, so that concurrent attempts cannot slip past the limits.
+    pending: u32,
+    /// The time of the last failure, or of the last pending attempt if it is later.
+    last_failure_at: Instant,
+    locked_until: Option<Instant>,
+}
+
+impl FailureRecord {
+    fn locked_for(&self, now: Instant) -> Option<Duration> {
+        self.locked_until
+            .filter(|locked_until| now < *locked_until)
+            .map(|locked_until| locked_until - now)
+    }
+}
+
+#[derive(Default)]
+struct FailureRecords {
+    emails: HashMap<String, FailureRecord>,
+    ips: HashMap<IpAddr, FailureRecord>,
+}
+
+/// Slows down password guessing by tracking failed logins per email and per client IP.
+/// Every failure on an email doubles the delay before the next attempt, and too many failures lock it temporarily.
+/// An allowed attempt counts as a failure until its outcome is recorded, so concurrent attempts are throttled as well.
+/// The records are kept in memory, so they are reset when the server restarts.
+pub struct LoginThrottleService {
+    enabled: bool,
+    max_failures: u32,
+    ip_max_failures: u32,
+    base_delay: Duration,
+    max_delay: Duration,
+    lockout_duration: Duration,
+    failure_window: Duration,
+    records: Mutex<FailureRecords>,
+}
+
+impl LoginThrottleService {
+    pub fn new(config: &AppLoginThrottle) -> Arc<Self> {
+        Arc::new(Self {
+            enabled: config.enabled,
+            max_failures: config.max_failures.max(1),
+            ip_max_failures: config.ip_max_failures.max(1),
+            base_delay: Duration::from_secs(config.base_delay),
+            max_delay: Duration::from_secs(config.max_delay),
+            lockout_duration: Duration::from_secs(config.lockout_duration),
+            failure_window: Duration::from_secs(config.failure_window),
+            records: Mutex::new(FailureRecords::default()),
+        })
+    }
+
+    /// Checks whether a login attempt for the given email from the given IP may be made now, and reserves it if so.
+    /// The check and the reservation are made at once, so that concurrent attempts see each other.
+    /// A reserved attempt has to be finished with [`Self::record_failure`], [`Self::record_success`] or [`Self::release`].
+    pub fn check(&self, email: &str, ip: Option<IpAddr>) -> Result<(), LoginThrottleError> {
+        if !self.enabled {
+            return Ok(());
+        }
+
+        let now = Instant::now();
+        let email = email.to_lowercase();
+        let mut records = self.records.lock();
+
+        if let Some(record) = records.emails.get(&email) {
+            if let Some(remaining) = record.locked_for(now) {
+                return Err(LoginThrottleError::Locked(remaining));
+            }
+
+            let failures = record.failures + record.pending;
+
+            if 0 < failures {
+                let allowed_at = record.last_failure_at + self.delay(failures);
+
+                if now < allowed_at {
+                    return Err(LoginThrottleError::TooManyAttempts(allowed_at - now));
+                }
+            }
+
+            // the pending attempts would lock the email if they all failed
+            if self.max_failures <= failures {
+                return Err(LoginThrottleError::TooManyAttempts(self.base_delay));
+            }
+        }
+
+        if let Some(record) = ip.and_then(|ip| records.ips.get(&ip)) {
+            if let Some(remaining) = record.locked_for(now) {
+                return Err(LoginThrottleError::TooManyAttempts(remaining));
+            }
+
+            if self.ip_max_failures <= record.failures + record.pending {
+                return Err(LoginThrottleError::TooManyAttempts(self.base_delay));
+            }
+        }
+
+        self.reserve(
+            records
+                .emails
+                .entry(email)
+                .or_insert_with(|| new_record(now)),
+            now,
+        );
+
+        if let Some(ip) = ip {
+            self.reserve(
+                records.ips.entry(ip).or_insert_with(|| new_record(now)),
+                now,
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Records a failed login attempt, locking the email or the IP if they have failed too many times.
+    pub fn record_failure(&self, email: &str, ip: Option<IpAddr>) {
+        if !self.enabled {
+            return;
+        }
+
+        let now = Instant::now();
+        let mut records = self.records.lock();
+
+        // forget the records that can no longer affect any login, so that they do not pile up
+        records
+            .emails
+            .retain(|_, record| !self.is_stale(record, now));
+        records.ips.retain(|_, record| !self.is_stale(record, now));
+
+        let email_record = records.emails.entry(email.to_lowercase());
+        self.bump(
+            email_record.or_insert_with(|| new_record(now)),
+            now,
+            self.max_failures,
+        );
+
+        if let Some(ip) = ip {
+            let ip_record = records.ips.entry(ip);
+            self.bump(
+                ip_record.or_insert_with(|| new_record(now)),
+                now,
+                self.ip_max_failures,
+            );
+        }
+    }
+
+    /// Forgets the failures of the given email after a successful login.
+    /// The failures of the IP are kept, as they may have been made against other emails.
+    pub fn record_success(&self, email: &str, ip: Option<IpAddr>) {
+        if !self.enabled {
+            return;
+        }
+
+        let mut records = self.records.lock();
+        records.emails.remove(&email.to_lowercase());
+
+        if let Some(record) = ip.and_then(|ip| records.ips.get_mut(&ip)) {
+            record.pending = record.pending.saturating_sub(1);
+        }
+    }
+
+    /// Releases an attempt that neither failed nor succeeded, e.g. because of an internal error.
+    pub fn release(&self, email: &str, ip: Option<IpAddr>) {
+        if !self.enabled {
+            return;
+        }
+
+        let mut records = self.records.lock();
+
+        if let Some(record) = records.emails.get_mut(&email.to_lowercase()) {
+            record.pending = record.pending.saturating_sub(1);
+        }
+
+        if let Some(record) = ip.and_then(|ip| records.ips.get_mut(&ip)) {
+            record.pending = record.pending.saturating_sub(1);
+        }
+    }
+
+    fn delay(&self, failures: u32) -> Duration {
+        let exponent = failures.saturating_sub(1).min(31);
+        self.base_delay
+            .saturating_mul(1 << exponent)
+            .min(self.max_delay)
+    }
+
+    fn is_stale(&self, record: &FailureRecord, now: Instant) -> bool {
+        record.pending == 0
+            && record.locked_for(now).is_none()
+            && self.failure_window <= now - record.last_failure_at
+    }
+
+    /// Counts an allowed attempt as pending, until its outcome is recorded.
+    fn reserve(&self, record: &mut FailureRecord, now: Instant) {
+        // the failures out of the window are forgotten here, as the pending attempt moves the last failure time
+        if record.pending == 0 && self.failure_window <= now - record.last_failure_at {
+            record.failures = 0;
+        }
+
+        record.pending += 1;
+        record.last_failure_at = now;
+    }
+
+    fn bump(&self, record: &mut FailureRecord, now: Instant, max_failures: u32) {
+        record.pending = record.pending.saturating_sub(1);
+
+        if self.failure_window <= now - record.last_failure_at {
+            record.failures = 0;
+        }
+
+        record.failures += 1;
+        record.last_failure_at = now;
+
+        if max_failures <= record.failures {
+            record.failures = 0;
+            record.locked_until = Some(now + self.lockout_duration);
+        }
+    }
+}
+
+fn new_record(now: Instant) -> FailureRecord {
+    FailureRecord {
+        failures: 0,
+        pending: 0,
+        last_failure_at: now,
+        locked_until: None,
+    }
+}
@@ -0,0 +1,137 @@
+use crate::config::AppMailer;
+use async_trait::async_trait;
+use lettre::{
+    message::Mailbox,
+    transport::smtp::{authentication::Credentials, AsyncSmtpTransport},
+    AsyncTransport, Message, Tokio1Executor,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum MailerServiceError {
+    #[error("invalid address: {0}")]
+    Address(#[from] lettre::address::AddressError),
+    #[error("failed to build the mail: {0}")]
+    Message(#[from] lettre::error::Error),
+    #[error("smtp error: {0}")]
+    Smtp(#[from] lettre::transport::smtp::Error),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MailTransportKind {
+    /// Writes the mails to the log instead of sending them. Meant for development.
+    Log,
+    /// Sends the mails through an SMTP server.
+    Smtp,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpTls {
+    /// Plain text connection. Only for servers on a trusted network.
+    None,
+    /// Upgrades a plain text connection with `STARTTLS`, usually on port 587.
+    StartTls,
+    /// Connects over TLS from the start, usually on port 465.
+    Tls,
+}
+
+/// A plain text mail to a single recipient.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mail {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+#[async_trait]
+pub trait MailTransport {
+    async fn send(&self, from: &Mailbox, mail: &Mail) -> Result<(), MailerServiceError>;
+}
+
+pub struct LogTransport;
+
+#[async_trait]
+impl MailTransport for LogTransport {
+    async fn send(&self, from: &Mailbox, mail: &Mail) -> Result<(), MailerServiceError> {
+        let from = from.to_string();
+        log::info!(target: "services::mailer_service", from, to = mail.to, subject = mail.subject, body = mail.body; "Mail not sent, as the log transport is in use.");
+        Ok(())
+    }
+}
+
+pub struct SmtpTransport {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+}
+
+impl SmtpTransport {
+    pub fn new(config: &AppMailer) -> Result<Self, MailerServiceError> {
+        let mut builder = match config.smtp_tls {
+            SmtpTls::None => {
+                AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&config.smtp_host)
+            }
+            SmtpTls::StartTls => {
+                AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&config.smtp_host)?
+            }
+            SmtpTls::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&config.smtp_host)?,
+        }
+        .port(config.smtp_port);
+
+        if let Some(username) = &config.smtp_username {
+            let password = config.smtp_password.clone().unwrap_or_default();
+            builder = builder.credentials(Credentials::new(username.clone(), password));
+        }
+
+        Ok(Self {
+            transport: builder.build(),
+        })
+    }
+}
+
+#[async_trait]
+impl MailTransport for SmtpTransport {
+    async fn send(&self, from: &Mailbox, mail: &Mail) -> Result<(), MailerServiceError> {
+        let message = Message::builder()
+            .from(from.clone())
+            .to(mail.to.parse()?)
+            .subject(&mail.subject)
+            .body(mail.body.clone())?;
+
+        self.transport.send(message).await?;
+        Ok(())
+    }
+}
+
+pub struct MailerService {
+    from: Mailbox,
+    transport: Box<dyn MailTransport + Send + Sync>,
+}
+
+impl MailerService {
+    pub fn new(config: &AppMailer) -> Result<Arc<Self>, MailerServiceError> {
+        let transport: Box<dyn MailTransport + Send + Sync> = match config.transport {
+            MailTransportKind::Log => Box::new(LogTransport),
+            MailTransportKind::Smtp => Box::new(SmtpTransport::new(config)?),
+        };
+
+        Self::with_transport(&config.from, transport)
+    }
+
+    /// Creates a mailer sending through the given transport, for transports other than the configurable ones.
+    pub fn with_transport(
+        from: &str,
+        transport: Box<dyn MailTransport + Send + Sync>,
+    ) -> Result<Arc<Self>, MailerServiceError> {
+        Ok(Arc::new(Self {
+            from: from.parse()?,
+            transport,
+        }))
+    }
+
+    pub async fn send(&self, mail: &Mail) -> Result<(), MailerServiceError> {
+        self.transport.send(&self.from, mail).await
+    }
+}
@@ -0,0 +1,254 @@
+use chrono::{NaiveDate, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use thiserror::Error;
+use tokio::process::Command;
+
+#[derive(Error, Debug)]
+pub enum MetadataServiceError {
+    #[error("io error: {0}")]
+    IO(#[from] std::io::Error),
+    #[error("exif error: {0}")]
+    Exif(#[from] exif::Error),
+    #[error("image size error: {0}")]
+    ImageSize(#[from] imagesize::ImageError),
+    #[error("ffprobe exited with {status}")]
+    FFProbe { status: std::process::ExitStatus },
+    #[error("json error: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("join error: {0}")]
+    Join(#[from] tokio::task::JoinError),
+}
+
+/// Media metadata extracted from the content of a file.
+/// Every field is optional, since the available metadata depends on the file type.
+#[derive(Serialize, Deserialize, Default, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct FileMetadata {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub width: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub height: Option<u32>,
+    /// The original date and time the image was taken, from EXIF.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub taken_at: Option<NaiveDateTime>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub camera_make: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub camera_model: Option<String>,
+    /// The duration in seconds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub video_codec: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audio_codec: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub artist: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub album: Option<String>,
+}
+
+impl FileMetadata {
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+}
+
+#[derive(Deserialize)]
+struct FFProbeOutput {
+    #[serde(default)]
+    streams: Vec<FFProbeStream>,
+    format: Option<FFProbeFormat>,
+}
+
+#[derive(Deserialize)]
+struct FFProbeStream {
+    codec_type: Option<String>,
+    codec_name: Option<String>,
+    width: Option<u32>,
+    height: Option<u32>,
+}
+
+#[derive(Deserialize)]
+struct FFProbeFormat {
+    duration: Option<String>,
+    #[serde(default)]
+    tags: HashMap<String, String>,
+}
+
+pub struct MetadataService {
+    ffprobe_path: Option<PathBuf>,
+}
+
+impl MetadataService {
+    pub fn new(ffprobe_path: Option<PathBuf>) -> Arc<Self> {
+        Arc::new(Self { ffprobe_path })
+    }
+
+    /// Extracts media metadata from the file at the given path.
+    /// Images are inspected for their dimensions and EXIF data, and videos and audios are
+    /// inspected with `ffprobe` if it is configured.
+    /// Returns `None` if nothing could be extracted. Extraction failures are logged and ignored,
+    /// as metadata is not critical.
+    pub async fn extract(&self, path: impl AsRef<Path>, mime: &str) -> Option<FileMetadata> {
+        let path = path.as_ref();
+        let mut metadata = FileMetadata::default();
+
+        match mime.split_once('/').map(|(type_part, _)| type_part) {
+            Some("image") => {
+                if let Err(err) = self.extract_image(path, &mut metadata).await {
+                    log::warn!(target: "metadata_service", path:?, mime, err:err; "Failed to extract image metadata.");
+                }
+            }
+            Some("video") | Some("audio") => {
+                if let Err(err) = self.extract_media(path, &mut metadata).await {
+                    log::warn!(target: "metadata_service", path:?, mime, err:err; "Failed to extract media metadata.");
+                }
+            }
+            _ => {}
+        }
+
+        if metadata.is_empty() {
+            None
+        } else {
+            Some(metadata)
+        }
+    }
+
+    async fn extract_image(
+        &self,
+        path: &Path,
+        metadata: &mut FileMetadata,
+    ) -> Result<(), MetadataServiceError> {
+        let path = path.to_owned();
+
+        let (size, exif) = tokio::task::spawn_blocking(move || {
+            let size = imagesize::size(&path);
+            let exif = std::fs::File::open(&path)
+                .map_err(MetadataServiceError::from)
+                .and_then(|file| {
+                    let mut reader = std::io::BufReader::new(file);
+                    exif::Reader::new()
+                        .read_from_container(&mut reader)
+                        .map_err(MetadataServiceError::from)
+                });
+            (size, exif)
+        })
+        .await?;
+
+        let size = size?;
+        metadata.width = Some(size.width as u32);
+        metadata.height = Some(size.height as u32);
+
+        // most images don't carry EXIF data, so it is not an error
+        let exif = match exif {
+            Ok(exif) => exif,
+            Err(_) => return Ok(()),
+        };
+
+        let ascii_field = |tag| {
+            let field = exif.get_field(tag, exif::In::PRIMARY)?;
+            match &field.value {
+                exif::Value::Ascii(values) => values.first().map(|value| {
+                    String::from_utf8_lossy(value)
+                        .trim_matches(|c: char| c == '\0' || c.is_whitespace())
+                        .to_owned()
+                }),
+                _ => None,
+            }
+        };
+
+        metadata.taken_at = ascii_field(exif::Tag::DateTimeOriginal).and_then(|value| {
+            let datetime = exif::DateTime::from_ascii(value.as_bytes()).ok()?;
+            NaiveDate::from_ymd_opt(
+                datetime.year as i32,
+                datetime.month as u32,
+                datetime.day as u32,
+            )?
+            .and_hms_opt(
+                datetime.hour as u32,
+                datetime.minute as u32,
+                datetime.second as u32,
+            )
+        });
+        metadata.camera_make = ascii_field(exif::Tag::Make).filter(|value| !value.is_empty());
+        metadata.camera_model = ascii_field(exif::Tag::Model).filter(|value| !value.is_empty());
+
+        Ok(())
+    }
+
+    async fn extract_media(
+        &self,
+        path: &Path,
+        metadata: &mut FileMetadata,
+    ) -> Result<(), MetadataServiceError> {
+        let ffprobe_path = match &self.ffprobe_path {
+            Some(ffprobe_path) => ffprobe_path,
+            None => return Ok(()),
+        };
+
+        let output = Command::new(ffprobe_path)
+            .args([
+                "-v",
+                "quiet",
+                "-print_format",
+                "json",
+                "-show_format",
+                "-show_streams",
+            ])
+            .arg(path)
+            .kill_on_drop(true)
+            .output()
+            .await?;
+
+        if !output.status.success() {
+            return Err(MetadataServiceError::FFProbe {
+                status: output.status,
+            });
+        }
+
+        let output = serde_json::from_slice::<FFProbeOutput>(&output.stdout)?;
+
+        for stream in &output.streams {
+            match stream.codec_type.as_deref() {
+                Some("video") if metadata.video_codec.is_none() => {
+                    metadata.video_codec = stream.codec_name.clone();
+                    metadata.width = stream.width;
+                    metadata.height = stream.height;
+                }
+                Some("audio") if metadata.audio_codec.is_none() => {
+                    metadata.audio_codec = stream.codec_name.clone();
+                }
+                _ => {}
+            }
+        }
+
+        if let Some(format) = output.format {
+            metadata.duration = format
+                .duration
+                .and_then(|duration| duration.parse::<f64>().ok());
+
+            // tag names are not normalized across containers
+            let tag = |name: &str| {
+                format
+                    .tags
+                    .iter()
+                    .find(|(key, _)| key.eq_ignore_ascii_case(name))
+                    .map(|(_, value)| value.clone())
+            };
+
+            metadata.title = tag("title");
+            metadata.artist = tag("artist");
+            metadata.album = tag("album");
+        }
+
+        Ok(())
+    }
+}
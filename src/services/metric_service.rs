@@ -1,13 +1,9 @@
-use std::{path::PathBuf, sync::Arc};
+use std::sync::Arc;
 
-pub struct MetricService {
-    file_base_path: PathBuf,
-}
+pub struct MetricService;
 
 impl MetricService {
-    pub fn new(file_base_path: impl Into<PathBuf>) -> Arc<Self> {
-        Arc::new(Self {
-            file_base_path: file_base_path.into(),
-        })
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self)
     }
 }
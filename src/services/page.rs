@@ -0,0 +1,50 @@
+use serde::{Deserialize, Serialize};
+
+/// The direction to sort a list in.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SortDirection {
+    Asc,
+    Desc,
+}
+
+impl SortDirection {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "asc" => Some(Self::Asc),
+            "desc" => Some(Self::Desc),
+            _ => None,
+        }
+    }
+}
+
+/// A page of a list fetched with keyset pagination.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Page<T, C> {
+    pub items: Vec<T>,
+    /// The cursor to fetch the next page with, or `None` if this is the last page.
+    pub next_cursor: Option<C>,
+    /// The number of items in the whole list, if it has been requested.
+    pub total: Option<i64>,
+}
+
+impl<T, C> Page<T, C> {
+    /// Makes a page from rows fetched with a limit of `limit + 1`.
+    /// The extra row is dropped; it only tells that there is a next page.
+    pub fn new(mut rows: Vec<T>, limit: u32, total: Option<i64>, cursor: impl Fn(&T) -> C) -> Self {
+        let has_next = (limit as usize) < rows.len();
+        rows.truncate(limit as usize);
+
+        let next_cursor = if has_next {
+            rows.last().map(cursor)
+        } else {
+            None
+        };
+
+        Self {
+            items: rows,
+            next_cursor,
+            total,
+        }
+    }
+}
@@ -0,0 +1,202 @@
+use super::{password_service, Mail, MailerService, PasswordService};
+use crate::{
+    config::AppPasswordReset,
+    db::models::{CreatingPasswordResetToken, PasswordResetToken, User},
+};
+use chrono::{Duration, Utc};
+use diesel::{ExpressionMethods, OptionalExtension, QueryDsl};
+use diesel_async::{
+    pooled_connection::deadpool::Pool, scoped_futures::ScopedFutureExt, AsyncConnection,
+    AsyncPgConnection, RunQueryDsl,
+};
+use std::sync::Arc;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum PasswordResetServiceError {
+    #[error("database pool error: {0}")]
+    Pool(#[from] diesel_async::pooled_connection::deadpool::PoolError),
+    #[error("diesel error: {0}")]
+    Diesel(#[from] diesel::result::Error),
+    #[error("{0}")]
+    PasswordService(#[from] password_service::PasswordServiceError),
+}
+
+pub struct PasswordResetService {
+    db_pool: Pool<AsyncPgConnection>,
+    password_service: Arc<PasswordService>,
+    mailer_service: Arc<MailerService>,
+    expiration: Duration,
+    url_template: String,
+}
+
+impl PasswordResetService {
+    pub fn new(
+        db_pool: Pool<AsyncPgConnection>,
+        password_service: Arc<PasswordService>,
+        mailer_service: Arc<MailerService>,
+        config: &AppPasswordReset,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            db_pool,
+            password_service,
+            mailer_service,
+            expiration: Duration::seconds(config.expiration as i64),
+            url_template: config.url_template.clone(),
+        })
+    }
+
+    /// Creates a password reset token for the user with the given email, and mails the reset link to them.
+    /// Nothing happens if no user has the email, so that callers cannot tell which emails are registered.
+    /// The mail is sent in the background for the same reason; failures to send it are only logged.
+    pub async fn request_password_reset(
+        &self,
+        email: &str,
+    ) -> Result<(), PasswordResetServiceError> {
+        let reset_token = match self.create_password_reset_token(email).await? {
+            Some(reset_token) => reset_token,
+            None => return Ok(()),
+        };
+
+        let mail = Mail {
+            to: email.to_owned(),
+            subject: "Reset your password".to_owned(),
+            body: format!(
+                "A password reset has been requested for your account.\n\nOpen the link below to set a new password. The link expires at {} UTC.\n\n{}\n\nIf you did not request it, you can ignore this mail.\n",
+                reset_token.expires_at.format("%Y-%m-%d %H:%M"),
+                self.url_template.replace("{token}", &reset_token.token),
+            ),
+        };
+        let user_id = reset_token.user_id;
+        let mailer_service = self.mailer_service.clone();
+
+        tokio::spawn(async move {
+            if let Err(err) = mailer_service.send(&mail).await {
+                log::error!(target: "services::password_reset_service", method = "request_password_reset", user_id, err:err; "Failed to send the password reset mail.");
+            }
+        });
+
+        Ok(())
+    }
+
+    /// Creates a password reset token for the user with the given email.
+    /// Returns `None` if no user has the email.
+    pub async fn create_password_reset_token(
+        &self,
+        email: &str,
+    ) -> Result<Option<PasswordResetToken>, PasswordResetServiceError> {
+        use crate::db::schema;
+
+        let db = &mut self.db_pool.get().await?;
+
+        let user_id = schema::users::table
+            .filter(schema::users::email.eq(email))
+            .select(schema::users::id)
+            .first::<i32>(db)
+            .await
+            .optional()?;
+
+        let user_id = match user_id {
+            Some(user_id) => user_id,
+            None => return Ok(None),
+        };
+
+        // expired tokens are never used again, so drop them while we are here
+        diesel::delete(
+            schema::password_reset_tokens::table
+                .filter(schema::password_reset_tokens::expires_at.le(Utc::now().naive_utc())),
+        )
+        .execute(db)
+        .await?;
+
+        let token = self.password_service.generate_url_safe_token_252();
+        let reset_token = diesel::insert_into(schema::password_reset_tokens::table)
+            .values(CreatingPasswordResetToken {
+                token: &token,
+                user_id,
+                expires_at: Utc::now().naive_utc() + self.expiration,
+            })
+            .returning((
+                schema::password_reset_tokens::token,
+                schema::password_reset_tokens::user_id,
+                schema::password_reset_tokens::created_at,
+                schema::password_reset_tokens::expires_at,
+            ))
+            .get_result::<PasswordResetToken>(db)
+            .await?;
+
+        Ok(Some(reset_token))
+    }
+
+    /// Sets the password of the user the given token was issued for.
+    /// All reset tokens and sessions of the user are removed, so that the old password cannot be used to stay logged in.
+    /// Returns `None` if the token does not exist or has expired.
+    pub async fn reset_password(
+        &self,
+        token: &str,
+        new_password: &str,
+    ) -> Result<Option<User>, PasswordResetServiceError> {
+        use crate::db::schema;
+
+        let password_hash = self.password_service.hash_password(new_password)?;
+
+        let db = &mut self.db_pool.get().await?;
+        let user = db
+            .transaction(|db| {
+                async move {
+                    let user_id = diesel::delete(
+                        schema::password_reset_tokens::table
+                            .filter(schema::password_reset_tokens::token.eq(token))
+                            .filter(
+                                schema::password_reset_tokens::expires_at
+                                    .gt(Utc::now().naive_utc()),
+                            ),
+                    )
+                    .returning(schema::password_reset_tokens::user_id)
+                    .get_result::<i32>(db)
+                    .await
+                    .optional()?;
+
+                    let user_id = match user_id {
+                        Some(user_id) => user_id,
+                        None => return Ok(None),
+                    };
+
+                    let user =
+                        diesel::update(schema::users::table.filter(schema::users::id.eq(user_id)))
+                            .set(schema::users::password.eq(&password_hash))
+                            .returning((
+                                schema::users::id,
+                                schema::users::username,
+                                schema::users::email,
+                                schema::users::joined_at,
+                                schema::users::display_name,
+                                schema::users::avatar_file_id,
+                                schema::users::role,
+                                schema::users::disabled_at,
+                            ))
+                            .get_result::<User>(db)
+                            .await?;
+
+                    diesel::delete(
+                        schema::password_reset_tokens::table
+                            .filter(schema::password_reset_tokens::user_id.eq(user_id)),
+                    )
+                    .execute(db)
+                    .await?;
+                    diesel::delete(
+                        schema::user_sessions::table
+                            .filter(schema::user_sessions::user_id.eq(user_id)),
+                    )
+                    .execute(db)
+                    .await?;
+
+                    Ok::<_, PasswordResetServiceError>(Some(user))
+                }
+                .scope_boxed()
+            })
+            .await?;
+
+        Ok(user)
+    }
+}
@@ -5,7 +5,10 @@ use argon2::{
     },
     Argon2, PasswordHash, PasswordHasher, PasswordVerifier,
 };
-use base64::{prelude::BASE64_STANDARD, Engine};
+use base64::{
+    prelude::{BASE64_STANDARD, BASE64_URL_SAFE_NO_PAD},
+    Engine,
+};
 use std::sync::Arc;
 use thiserror::Error;
 
@@ -22,7 +25,7 @@ impl PasswordService {
         Arc::new(Self)
     }
 
-    pub fn argon2(&self) -> Argon2 {
+    pub fn argon2(&self) -> Argon2<'_> {
         Argon2::default()
     }
 
@@ -36,6 +39,13 @@ impl PasswordService {
         BASE64_STANDARD.encode(buf)
     }
 
+    /// Generates a secure token that can be embedded in URLs as is.
+    pub fn generate_url_safe_token_252(&self) -> String {
+        let mut buf = [0u8; 189];
+        OsRng.fill_bytes(&mut buf);
+        BASE64_URL_SAFE_NO_PAD.encode(buf)
+    }
+
     pub fn hash_password(&self, password: &str) -> Result<String, PasswordServiceError> {
         let argon2 = self.argon2();
         let salt = self.salt_string();
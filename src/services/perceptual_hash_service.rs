@@ -0,0 +1,103 @@
+use crate::config::AppPerceptualHash;
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use thiserror::Error;
+use tokio::process::Command;
+
+/// The width of the grayscale thumbnail the hash is computed from.
+/// Each row has one more pixel than the bits it yields, as each bit compares two adjacent pixels.
+const THUMBNAIL_WIDTH: usize = 9;
+const THUMBNAIL_HEIGHT: usize = 8;
+
+#[derive(Error, Debug)]
+pub enum PerceptualHashServiceError {
+    #[error("io error: {0}")]
+    IO(#[from] std::io::Error),
+    #[error("ffmpeg exited with {status}")]
+    FFMpeg { status: std::process::ExitStatus },
+    #[error("ffmpeg returned {length} bytes instead of a thumbnail")]
+    InvalidThumbnail { length: usize },
+}
+
+/// Computes perceptual hashes of images, so that near-duplicates can be found by the Hamming distance of their hashes.
+/// It uses the difference hash (dHash): the image is shrunk into a 9x8 grayscale thumbnail with `ffmpeg`,
+/// and each bit tells whether a pixel is brighter than its right neighbor.
+pub struct PerceptualHashService {
+    enabled: bool,
+    ffmpeg_path: PathBuf,
+    max_distance: u32,
+}
+
+impl PerceptualHashService {
+    pub fn new(config: &AppPerceptualHash) -> Arc<Self> {
+        Arc::new(Self {
+            enabled: config.enabled,
+            ffmpeg_path: config.ffmpeg_path.clone(),
+            max_distance: config.max_distance,
+        })
+    }
+
+    /// The maximum Hamming distance between the hashes of similar images.
+    pub fn max_distance(&self) -> u32 {
+        self.max_distance
+    }
+
+    /// Computes the perceptual hash of the image at the given path.
+    /// Returns `None` if hashing is disabled, the file is not an image, or the hash could not be computed.
+    /// Failures are logged and ignored, as the hash is not critical.
+    pub async fn compute(&self, path: impl AsRef<Path>, mime: &str) -> Option<i64> {
+        if !self.enabled || !mime.starts_with("image/") {
+            return None;
+        }
+
+        let path = path.as_ref();
+
+        match self.compute_dhash(path).await {
+            Ok(hash) => Some(hash as i64),
+            Err(err) => {
+                log::warn!(target: "perceptual_hash_service", path:?, mime, err:err; "Failed to compute the perceptual hash.");
+                None
+            }
+        }
+    }
+
+    async fn compute_dhash(&self, path: &Path) -> Result<u64, PerceptualHashServiceError> {
+        let scale = format!(
+            "scale={}:{}:flags=area,format=gray",
+            THUMBNAIL_WIDTH, THUMBNAIL_HEIGHT
+        );
+        let output = Command::new(&self.ffmpeg_path)
+            .args(["-v", "error", "-i"])
+            .arg(path)
+            .args(["-frames:v", "1", "-vf", &scale, "-f", "rawvideo", "-"])
+            .kill_on_drop(true)
+            .output()
+            .await?;
+
+        if !output.status.success() {
+            return Err(PerceptualHashServiceError::FFMpeg {
+                status: output.status,
+            });
+        }
+
+        let pixels = output.stdout;
+
+        if pixels.len() != THUMBNAIL_WIDTH * THUMBNAIL_HEIGHT {
+            return Err(PerceptualHashServiceError::InvalidThumbnail {
+                length: pixels.len(),
+            });
+        }
+
+        let mut hash = 0u64;
+
+        for row in pixels.chunks_exact(THUMBNAIL_WIDTH) {
+            for pair in row.windows(2) {
+                hash = (hash << 1) | (pair[0] > pair[1]) as u64;
+            }
+        }
+
+        Ok(hash)
+    }
+}
@@ -0,0 +1,239 @@
+use super::{FileService, ReadError, ReadRange};
+use crate::config::AppReadAhead;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    io::Cursor,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use tokio::io::{AsyncRead, AsyncReadExt};
+use uuid::Uuid;
+
+struct ReadAheadBuffer {
+    /// The offset of the first byte in `data`.
+    start: u64,
+    data: Vec<u8>,
+    /// The inclusive end of the last range served for this session and file.
+    last_end: u64,
+    last_access: Instant,
+}
+
+impl ReadAheadBuffer {
+    fn covers(&self, start: u64, end: u64) -> bool {
+        self.start <= start && end < self.start + self.data.len() as u64
+    }
+
+    fn slice(&self, start: u64, end: u64) -> Vec<u8> {
+        let from = (start - self.start) as usize;
+        let to = (end - self.start) as usize;
+        self.data[from..=to].to_vec()
+    }
+}
+
+/// Counters describing how effective read-ahead coalescing is.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ReadAheadStats {
+    pub enabled: bool,
+    /// Range requests served from a read-ahead buffer.
+    pub hits: u64,
+    /// Eligible range requests that had to be read from the file driver.
+    pub misses: u64,
+    /// Read-ahead operations performed against the file driver.
+    pub read_aheads: u64,
+    /// Bytes served from read-ahead buffers.
+    pub bytes_served: u64,
+    /// Bytes read from the file driver to fill read-ahead buffers.
+    pub bytes_read_ahead: u64,
+    /// Read-ahead buffers currently kept in memory.
+    pub active_buffers: usize,
+}
+
+pub struct ReadAheadService {
+    enabled: bool,
+    max_range_size: u64,
+    buffer_size: u64,
+    max_buffers: usize,
+    idle_timeout: Duration,
+    file_service: Arc<FileService>,
+    buffers: Mutex<HashMap<(String, Uuid), ReadAheadBuffer>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    read_aheads: AtomicU64,
+    bytes_served: AtomicU64,
+    bytes_read_ahead: AtomicU64,
+}
+
+impl ReadAheadService {
+    pub fn new(config: &AppReadAhead, file_service: Arc<FileService>) -> Arc<Self> {
+        Arc::new(Self {
+            enabled: config.enabled,
+            max_range_size: config.max_range_size.as_u64(),
+            buffer_size: config.buffer_size.as_u64(),
+            max_buffers: config.max_buffers,
+            idle_timeout: Duration::from_secs(config.idle_timeout),
+            file_service,
+            buffers: Mutex::new(HashMap::new()),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            read_aheads: AtomicU64::new(0),
+            bytes_served: AtomicU64::new(0),
+            bytes_read_ahead: AtomicU64::new(0),
+        })
+    }
+
+    /// Reads the file data by its ID on behalf of the given session.
+    /// Small range requests that continue right after the previous range of the same session
+    /// are served from a read-ahead buffer, so that players issuing many tiny adjacent requests
+    /// don't cause a disk seek for each of them.
+    /// Any other request is passed to the file service as is.
+    pub async fn read(
+        &self,
+        session_key: &str,
+        file_id: Uuid,
+        file_size: u64,
+        range: ReadRange,
+    ) -> Result<Option<Pin<Box<dyn AsyncRead + Send>>>, ReadError> {
+        let (start, end) = match range {
+            ReadRange::Range(start, end)
+                if self.enabled && end < file_size && end - start < self.max_range_size =>
+            {
+                (start, end)
+            }
+            _ => return self.file_service.get_file_data_by_id(file_id, range).await,
+        };
+
+        let key = (session_key.to_owned(), file_id);
+        let now = Instant::now();
+
+        let is_sequential = {
+            let mut buffers = self.buffers.lock();
+
+            if let Some(buffer) = buffers.get_mut(&key) {
+                let is_sequential = buffer.last_end + 1 == start;
+                buffer.last_end = end;
+                buffer.last_access = now;
+
+                if buffer.covers(start, end) {
+                    let data = buffer.slice(start, end);
+                    drop(buffers);
+
+                    self.hits.fetch_add(1, Ordering::Relaxed);
+                    self.bytes_served
+                        .fetch_add(data.len() as u64, Ordering::Relaxed);
+                    return Ok(Some(Box::pin(Cursor::new(data))));
+                }
+
+                is_sequential
+            } else {
+                self.insert_buffer(
+                    &mut buffers,
+                    key.clone(),
+                    ReadAheadBuffer {
+                        start,
+                        data: Vec::new(),
+                        last_end: end,
+                        last_access: now,
+                    },
+                );
+                false
+            }
+        };
+
+        self.misses.fetch_add(1, Ordering::Relaxed);
+
+        if !is_sequential {
+            // the access pattern is not known yet, so don't pay for reading ahead
+            return self.file_service.get_file_data_by_id(file_id, range).await;
+        }
+
+        let read_ahead_end = u64::min(
+            start + u64::max(self.buffer_size, end - start + 1),
+            file_size,
+        ) - 1;
+        let reader = self
+            .file_service
+            .get_file_data_by_id(file_id, ReadRange::Range(start, read_ahead_end))
+            .await?;
+        let mut reader = match reader {
+            Some(reader) => reader,
+            None => return Ok(None),
+        };
+
+        let mut data = Vec::with_capacity((read_ahead_end - start + 1) as usize);
+        reader.read_to_end(&mut data).await?;
+
+        self.read_aheads.fetch_add(1, Ordering::Relaxed);
+        self.bytes_read_ahead
+            .fetch_add(data.len() as u64, Ordering::Relaxed);
+
+        let buffer = ReadAheadBuffer {
+            start,
+            data,
+            last_end: end,
+            last_access: now,
+        };
+
+        if !buffer.covers(start, end) {
+            // the file has been shrunk or replaced underneath; fall back to a direct read
+            return self.file_service.get_file_data_by_id(file_id, range).await;
+        }
+
+        let data = buffer.slice(start, end);
+        self.insert_buffer(&mut self.buffers.lock(), key, buffer);
+
+        Ok(Some(Box::pin(Cursor::new(data))))
+    }
+
+    /// Returns the current coalescing statistics.
+    pub fn stats(&self) -> ReadAheadStats {
+        ReadAheadStats {
+            enabled: self.enabled,
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            read_aheads: self.read_aheads.load(Ordering::Relaxed),
+            bytes_served: self.bytes_served.load(Ordering::Relaxed),
+            bytes_read_ahead: self.bytes_read_ahead.load(Ordering::Relaxed),
+            active_buffers: self.buffers.lock().len(),
+        }
+    }
+
+    /// Drops all read-ahead buffers of the given file.
+    /// It must be called when the file data is removed.
+    pub fn invalidate_file(&self, file_id: Uuid) {
+        self.buffers
+            .lock()
+            .retain(|(_, buffered_file_id), _| *buffered_file_id != file_id);
+    }
+
+    fn insert_buffer(
+        &self,
+        buffers: &mut HashMap<(String, Uuid), ReadAheadBuffer>,
+        key: (String, Uuid),
+        buffer: ReadAheadBuffer,
+    ) {
+        let idle_timeout = self.idle_timeout;
+        buffers.retain(|_, buffer| buffer.last_access.elapsed() < idle_timeout);
+
+        if !buffers.contains_key(&key) && self.max_buffers <= buffers.len() {
+            let least_recently_used = buffers
+                .iter()
+                .min_by_key(|(_, buffer)| buffer.last_access)
+                .map(|(key, _)| key.clone());
+
+            if let Some(least_recently_used) = least_recently_used {
+                buffers.remove(&least_recently_used);
+            }
+        }
+
+        if 0 < self.max_buffers {
+            buffers.insert(key, buffer);
+        }
+    }
+}
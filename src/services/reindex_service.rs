@@ -0,0 +1,256 @@
+use super::{FileMetadata, SearchService};
+use crate::db::models::{Collection, CollectionFilePair, File, Tag};
+use diesel::{ExpressionMethods, QueryDsl};
+use diesel_async::{pooled_connection::deadpool::Pool, AsyncPgConnection, RunQueryDsl};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Arc,
+};
+use thiserror::Error;
+use uuid::Uuid;
+
+/// The number of rows loaded from the database at once while reindexing.
+const REINDEX_CHUNK_SIZE: i64 = 500;
+
+#[derive(Error, Debug)]
+pub enum ReindexServiceError {
+    #[error("database pool error: {0}")]
+    Pool(#[from] diesel_async::pooled_connection::deadpool::PoolError),
+    #[error("diesel error: {0}")]
+    Diesel(#[from] diesel::result::Error),
+}
+
+/// The numbers of documents that have been indexed again.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ReindexReport {
+    pub collections: u64,
+    pub files: u64,
+    pub collection_files: u64,
+    /// The documents that could not be indexed; the failures are logged.
+    pub failures: u64,
+}
+
+/// Rebuilds the search indices from the database, e.g. after the index has been lost or has drifted.
+/// Documents of rows that no longer exist are not removed from the indices.
+pub struct ReindexService {
+    db_pool: Pool<AsyncPgConnection>,
+    search_service: Arc<dyn SearchService + Send + Sync>,
+}
+
+impl ReindexService {
+    pub fn new(
+        db_pool: Pool<AsyncPgConnection>,
+        search_service: Arc<dyn SearchService + Send + Sync>,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            db_pool,
+            search_service,
+        })
+    }
+
+    /// Indexes every collection and file again, along with the tags and attributes of the files
+    /// and the files in each collection.
+    /// The files are indexed with the text content stored when they were promoted, so nothing is extracted again.
+    /// A document that fails to be indexed is counted and skipped, so that a single failure does not stop the rest.
+    pub async fn reindex(&self) -> Result<ReindexReport, ReindexServiceError> {
+        let mut report = ReindexReport::default();
+
+        self.reindex_collections(&mut report).await?;
+        self.reindex_files(&mut report).await?;
+
+        Ok(report)
+    }
+
+    async fn reindex_collections(
+        &self,
+        report: &mut ReindexReport,
+    ) -> Result<(), ReindexServiceError> {
+        use crate::db::schema;
+
+        let db = &mut self.db_pool.get().await?;
+        let mut last_collection_id = None;
+
+        loop {
+            let mut query = schema::collections::table
+                .select((
+                    schema::collections::id,
+                    schema::collections::name,
+                    schema::collections::description,
+                    schema::collections::created_at,
+                    schema::collections::version,
+                ))
+                .order(schema::collections::id.asc())
+                .limit(REINDEX_CHUNK_SIZE)
+                .into_boxed();
+
+            if let Some(last_collection_id) = last_collection_id {
+                query = query.filter(schema::collections::id.gt(last_collection_id));
+            }
+
+            let collections = query.load::<Collection>(db).await?;
+
+            for collection in &collections {
+                match self.search_service.index_collection(collection).await {
+                    Ok(()) => report.collections += 1,
+                    Err(err) => {
+                        let collection_id = collection.id;
+                        log::warn!(target: "reindex_service", collection_id:serde, err:err; "Failed to index a collection.");
+                        report.failures += 1;
+                    }
+                }
+            }
+
+            match collections.last() {
+                Some(collection) if collections.len() as i64 == REINDEX_CHUNK_SIZE => {
+                    last_collection_id = Some(collection.id);
+                }
+                _ => break,
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn reindex_files(&self, report: &mut ReindexReport) -> Result<(), ReindexServiceError> {
+        use crate::db::schema;
+
+        let db = &mut self.db_pool.get().await?;
+        let mut last_file_id = None;
+
+        loop {
+            let mut query = schema::files::table
+                .select((
+                    (
+                        schema::files::id,
+                        schema::files::name,
+                        schema::files::mime,
+                        schema::files::size,
+                        schema::files::hash,
+                        schema::files::uploaded_at,
+                    ),
+                    schema::files::file_metadata,
+                    schema::files::search_content,
+                ))
+                .order(schema::files::id.asc())
+                .limit(REINDEX_CHUNK_SIZE)
+                .into_boxed();
+
+            if let Some(last_file_id) = last_file_id {
+                query = query.filter(schema::files::id.gt(last_file_id));
+            }
+
+            let rows = query
+                .load::<(File, Option<serde_json::Value>, Option<String>)>(db)
+                .await?;
+            let file_ids = rows.iter().map(|(file, _, _)| file.id).collect::<Vec<_>>();
+
+            let tags = schema::tags::table
+                .filter(schema::tags::file_id.eq_any(&file_ids))
+                .select((
+                    schema::tags::name,
+                    schema::tags::file_id,
+                    schema::tags::namespace,
+                ))
+                .load::<Tag>(db)
+                .await?;
+            let attributes = schema::file_attributes::table
+                .filter(schema::file_attributes::file_id.eq_any(&file_ids))
+                .select((
+                    schema::file_attributes::file_id,
+                    schema::file_attributes::key,
+                    schema::file_attributes::value,
+                ))
+                .load::<(Uuid, String, String)>(db)
+                .await?;
+            let pairs = schema::collection_file_pairs::table
+                .filter(schema::collection_file_pairs::file_id.eq_any(&file_ids))
+                .select((
+                    schema::collection_file_pairs::collection_id,
+                    schema::collection_file_pairs::file_id,
+                    schema::collection_file_pairs::position,
+                ))
+                .load::<CollectionFilePair>(db)
+                .await?;
+
+            let mut tags_by_file = HashMap::<Uuid, Vec<Tag>>::new();
+
+            for tag in tags {
+                tags_by_file.entry(tag.file_id).or_default().push(tag);
+            }
+
+            let mut attributes_by_file = HashMap::<Uuid, BTreeMap<String, String>>::new();
+
+            for (file_id, key, value) in attributes {
+                attributes_by_file
+                    .entry(file_id)
+                    .or_default()
+                    .insert(key, value);
+            }
+
+            let mut collections_by_file = HashMap::<Uuid, Vec<Uuid>>::new();
+
+            for pair in pairs {
+                collections_by_file
+                    .entry(pair.file_id)
+                    .or_default()
+                    .push(pair.collection_id);
+            }
+
+            for (file, metadata, content) in &rows {
+                let metadata = metadata
+                    .clone()
+                    .and_then(|metadata| serde_json::from_value::<FileMetadata>(metadata).ok());
+                // tags and attributes are indexed even if empty, to clear the ones that are no longer in the database
+                let tags = tags_by_file.remove(&file.id).unwrap_or_default();
+                let attributes = attributes_by_file.remove(&file.id).unwrap_or_default();
+
+                let result = async {
+                    self.search_service
+                        .index_file(file, metadata.as_ref(), content.as_deref())
+                        .await?;
+                    self.search_service.index_file_tags(file.id, &tags).await?;
+                    self.search_service
+                        .index_file_attributes(file.id, &attributes)
+                        .await
+                }
+                .await;
+
+                match result {
+                    Ok(()) => report.files += 1,
+                    Err(err) => {
+                        let file_id = file.id;
+                        log::warn!(target: "reindex_service", file_id:serde, err:err; "Failed to index a file.");
+                        report.failures += 1;
+                        continue;
+                    }
+                }
+
+                for collection_id in collections_by_file.remove(&file.id).unwrap_or_default() {
+                    match self
+                        .search_service
+                        .index_collection_file(collection_id, file)
+                        .await
+                    {
+                        Ok(()) => report.collection_files += 1,
+                        Err(err) => {
+                            let file_id = file.id;
+                            log::warn!(target: "reindex_service", collection_id:serde, file_id:serde, err:err; "Failed to index a file in a collection.");
+                            report.failures += 1;
+                        }
+                    }
+                }
+            }
+
+            match rows.last() {
+                Some((file, _, _)) if rows.len() as i64 == REINDEX_CHUNK_SIZE => {
+                    last_file_id = Some(file.id);
+                }
+                _ => break,
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,278 @@
+use super::SearchService;
+use crate::db::models::{CreatingRetentionPolicy, File, RetentionPolicy};
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel::{upsert::excluded, ExpressionMethods, OptionalExtension, QueryDsl};
+use diesel_async::{pooled_connection::deadpool::Pool, AsyncPgConnection, RunQueryDsl};
+use std::{collections::BTreeMap, sync::Arc};
+use thiserror::Error;
+use uuid::Uuid;
+
+#[derive(Error, Debug)]
+pub enum RetentionServiceError {
+    #[error("database pool error: {0}")]
+    Pool(#[from] diesel_async::pooled_connection::deadpool::PoolError),
+    #[error("diesel error: {0}")]
+    Diesel(#[from] diesel::result::Error),
+}
+
+/// The files a retention policy has let go of in one evaluation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AppliedRetention {
+    pub policy: RetentionPolicy,
+    pub file_ids: Vec<Uuid>,
+}
+
+/// Keeps the retention policies of collections and applies them.
+/// Applying a policy removes the matching files from the collection only; the files themselves are kept,
+/// so they can be added back until they are removed explicitly.
+pub struct RetentionService {
+    db_pool: Pool<AsyncPgConnection>,
+    search_service: Arc<dyn SearchService + Send + Sync>,
+}
+
+impl RetentionService {
+    pub fn new(
+        db_pool: Pool<AsyncPgConnection>,
+        search_service: Arc<dyn SearchService + Send + Sync>,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            db_pool,
+            search_service,
+        })
+    }
+
+    /// Attaches a retention policy to a collection, replacing the one it has.
+    /// At least one of `max_age_days` and `keep_latest` should be set.
+    /// Returns `None` if the collection does not exist.
+    pub async fn set_policy(
+        &self,
+        collection_id: Uuid,
+        max_age_days: Option<i32>,
+        keep_latest: Option<i32>,
+    ) -> Result<Option<RetentionPolicy>, RetentionServiceError> {
+        use crate::db::schema;
+
+        let db = &mut self.db_pool.get().await?;
+        let policy = diesel::insert_into(schema::retention_policies::table)
+            .values(CreatingRetentionPolicy {
+                collection_id,
+                max_age_days,
+                keep_latest,
+            })
+            .on_conflict(schema::retention_policies::collection_id)
+            .do_update()
+            .set((
+                schema::retention_policies::max_age_days
+                    .eq(excluded(schema::retention_policies::max_age_days)),
+                schema::retention_policies::keep_latest
+                    .eq(excluded(schema::retention_policies::keep_latest)),
+                schema::retention_policies::updated_at.eq(diesel::dsl::now),
+            ))
+            .returning((
+                schema::retention_policies::collection_id,
+                schema::retention_policies::max_age_days,
+                schema::retention_policies::keep_latest,
+                schema::retention_policies::created_at,
+                schema::retention_policies::updated_at,
+            ))
+            .get_result::<RetentionPolicy>(db)
+            .await;
+
+        match policy {
+            Ok(policy) => Ok(Some(policy)),
+            Err(diesel::result::Error::DatabaseError(
+                diesel::result::DatabaseErrorKind::ForeignKeyViolation,
+                err,
+            )) if err.constraint_name() == Some("retention_policies_collection_fk") => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Retrieves the retention policy of a collection, if it has one.
+    pub async fn get_policy(
+        &self,
+        collection_id: Uuid,
+    ) -> Result<Option<RetentionPolicy>, RetentionServiceError> {
+        use crate::db::schema;
+
+        let db = &mut self.db_pool.get().await?;
+        let policy = schema::retention_policies::table
+            .filter(schema::retention_policies::collection_id.eq(collection_id))
+            .select((
+                schema::retention_policies::collection_id,
+                schema::retention_policies::max_age_days,
+                schema::retention_policies::keep_latest,
+                schema::retention_policies::created_at,
+                schema::retention_policies::updated_at,
+            ))
+            .get_result::<RetentionPolicy>(db)
+            .await
+            .optional()?;
+
+        Ok(policy)
+    }
+
+    /// Detaches the retention policy from a collection.
+    /// Returns the policy that was removed, or `None` if the collection has no policy.
+    pub async fn remove_policy(
+        &self,
+        collection_id: Uuid,
+    ) -> Result<Option<RetentionPolicy>, RetentionServiceError> {
+        use crate::db::schema;
+
+        let db = &mut self.db_pool.get().await?;
+        let policy = diesel::delete(
+            schema::retention_policies::table
+                .filter(schema::retention_policies::collection_id.eq(collection_id)),
+        )
+        .returning((
+            schema::retention_policies::collection_id,
+            schema::retention_policies::max_age_days,
+            schema::retention_policies::keep_latest,
+            schema::retention_policies::created_at,
+            schema::retention_policies::updated_at,
+        ))
+        .get_result::<RetentionPolicy>(db)
+        .await
+        .optional()?;
+
+        Ok(policy)
+    }
+
+    /// Retrieves the files the retention policy of a collection would let go of if it were applied now.
+    /// The result will be sorted from the oldest file, and has at most `limit` files.
+    /// Returns `None` if the collection has no policy.
+    pub async fn preview_policy(
+        &self,
+        collection_id: Uuid,
+        limit: u32,
+    ) -> Result<Option<Vec<File>>, RetentionServiceError> {
+        let policy = match self.get_policy(collection_id).await? {
+            Some(policy) => policy,
+            None => return Ok(None),
+        };
+
+        let db = &mut self.db_pool.get().await?;
+        let files = find_expired_files(db, &policy, limit).await?;
+
+        Ok(Some(files))
+    }
+
+    /// Applies every retention policy, removing at most `batch_size` files from each collection.
+    /// Returns the policies that have let go of any file.
+    pub async fn apply_policies(
+        &self,
+        batch_size: u32,
+    ) -> Result<Vec<AppliedRetention>, RetentionServiceError> {
+        use crate::db::schema;
+
+        let db = &mut self.db_pool.get().await?;
+        let policies = schema::retention_policies::table
+            .select((
+                schema::retention_policies::collection_id,
+                schema::retention_policies::max_age_days,
+                schema::retention_policies::keep_latest,
+                schema::retention_policies::created_at,
+                schema::retention_policies::updated_at,
+            ))
+            .order(schema::retention_policies::collection_id.asc())
+            .load::<RetentionPolicy>(db)
+            .await?;
+
+        let mut applied = Vec::new();
+
+        for policy in policies {
+            let files = find_expired_files(db, &policy, batch_size).await?;
+
+            if files.is_empty() {
+                continue;
+            }
+
+            let file_ids = files.iter().map(|file| file.id).collect::<Vec<_>>();
+            let file_ids = diesel::delete(
+                schema::collection_file_pairs::table
+                    .filter(schema::collection_file_pairs::collection_id.eq(policy.collection_id))
+                    .filter(schema::collection_file_pairs::file_id.eq_any(&file_ids)),
+            )
+            .returning(schema::collection_file_pairs::file_id)
+            .load::<Uuid>(db)
+            .await?;
+
+            for &file_id in &file_ids {
+                // ignore the error if the indexing fails, as it is not critical
+                self.search_service
+                    .remove_collection_file(policy.collection_id, file_id)
+                    .await
+                    .ok();
+            }
+
+            if !file_ids.is_empty() {
+                applied.push(AppliedRetention { policy, file_ids });
+            }
+        }
+
+        Ok(applied)
+    }
+}
+
+/// Finds the files of the collection that the policy lets go of, from the oldest file.
+async fn find_expired_files(
+    db: &mut AsyncPgConnection,
+    policy: &RetentionPolicy,
+    limit: u32,
+) -> Result<Vec<File>, RetentionServiceError> {
+    use crate::db::schema;
+
+    let query = schema::collection_file_pairs::table
+        .inner_join(schema::files::table)
+        .filter(schema::collection_file_pairs::collection_id.eq(policy.collection_id))
+        .select((
+            schema::files::id,
+            schema::files::name,
+            schema::files::mime,
+            schema::files::size,
+            schema::files::hash,
+            schema::files::uploaded_at,
+        ))
+        .order((schema::files::uploaded_at.asc(), schema::files::id.asc()));
+
+    // both rules select a run of the oldest files, so each run is fetched separately and merged
+    let mut expired_files = BTreeMap::<(NaiveDateTime, Uuid), File>::new();
+
+    if let Some(keep_latest) = policy.keep_latest {
+        let count = schema::collection_file_pairs::table
+            .filter(schema::collection_file_pairs::collection_id.eq(policy.collection_id))
+            .count()
+            .get_result::<i64>(db)
+            .await?;
+        let excess = count - keep_latest as i64;
+
+        if 0 < excess {
+            let files = query
+                .limit(i64::min(excess, limit as i64))
+                .load::<File>(db)
+                .await?;
+            expired_files.extend(
+                files
+                    .into_iter()
+                    .map(|file| ((file.uploaded_at, file.id), file)),
+            );
+        }
+    }
+
+    if let Some(max_age_days) = policy.max_age_days {
+        let cutoff = Utc::now().naive_utc() - Duration::days(max_age_days as i64);
+        let files = query
+            .filter(schema::files::uploaded_at.lt(cutoff))
+            .limit(limit as i64)
+            .load::<File>(db)
+            .await?;
+        expired_files.extend(
+            files
+                .into_iter()
+                .map(|file| ((file.uploaded_at, file.id), file)),
+        );
+    }
+
+    Ok(expired_files.into_values().take(limit as usize).collect())
+}
@@ -0,0 +1,147 @@
+use crate::config::{AppScanner, InfectedFileAction};
+use serde::{Deserialize, Serialize};
+use std::{path::Path, sync::Arc, time::Duration};
+use thiserror::Error;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+
+/// The size of each chunk streamed to `clamd`.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+#[derive(Error, Debug)]
+pub enum ScannerServiceError {
+    #[error("io error: {0}")]
+    IO(#[from] std::io::Error),
+    #[error("scan timed out")]
+    Timeout,
+    #[error("clamd returned an error: {0}")]
+    Clamd(String),
+}
+
+/// The verdict of a scan.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ScanStatus {
+    Clean,
+    Infected,
+}
+
+impl ScanStatus {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Clean => "clean",
+            Self::Infected => "infected",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "clean" => Some(Self::Clean),
+            "infected" => Some(Self::Infected),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ScanResult {
+    pub status: ScanStatus,
+    /// The name of the malware found, if any.
+    pub signature: Option<String>,
+}
+
+/// Scans files for malware with ClamAV, through the `INSTREAM` command of a `clamd` TCP socket.
+/// The file is streamed to `clamd`, so that it does not have to share a file system with the server.
+pub struct ScannerService {
+    enabled: bool,
+    clamd_address: String,
+    timeout: Duration,
+    on_infected: InfectedFileAction,
+}
+
+impl ScannerService {
+    pub fn new(config: &AppScanner) -> Arc<Self> {
+        Arc::new(Self {
+            enabled: config.enabled,
+            clamd_address: config.clamd_address.clone(),
+            timeout: Duration::from_secs(config.timeout),
+            on_infected: config.on_infected,
+        })
+    }
+
+    /// What to do with files in which malware has been found.
+    pub fn on_infected(&self) -> InfectedFileAction {
+        self.on_infected
+    }
+
+    /// Scans a file.
+    /// Returns `None` if scanning is disabled.
+    pub async fn scan(&self, path: &Path) -> Result<Option<ScanResult>, ScannerServiceError> {
+        if !self.enabled {
+            return Ok(None);
+        }
+
+        let reply = tokio::time::timeout(self.timeout, self.scan_stream(path))
+            .await
+            .map_err(|_| ScannerServiceError::Timeout)??;
+
+        parse_reply(&reply).map(Some)
+    }
+
+    async fn scan_stream(&self, path: &Path) -> Result<String, ScannerServiceError> {
+        let mut file = tokio::fs::File::open(path).await?;
+        let mut stream = TcpStream::connect(&self.clamd_address).await?;
+
+        // the `z` prefix makes the command and its reply terminated by a null character
+        stream.write_all(b"zINSTREAM\0").await?;
+
+        let mut buf = vec![0u8; CHUNK_SIZE];
+
+        loop {
+            let read = file.read(&mut buf).await?;
+
+            if read == 0 {
+                break;
+            }
+
+            stream.write_all(&(read as u32).to_be_bytes()).await?;
+            stream.write_all(&buf[..read]).await?;
+        }
+
+        // a chunk of zero length ends the stream
+        stream.write_all(&0u32.to_be_bytes()).await?;
+        stream.flush().await?;
+
+        let mut reply = Vec::new();
+        stream.read_to_end(&mut reply).await?;
+
+        let reply = String::from_utf8_lossy(&reply);
+        Ok(reply.trim_end_matches('\0').trim().to_owned())
+    }
+}
+
+/// Parses a reply of `clamd`, e.g. `stream: OK` or `stream: Eicar-Signature FOUND`.
+fn parse_reply(reply: &str) -> Result<ScanResult, ScannerServiceError> {
+    let verdict = reply
+        .strip_prefix("stream:")
+        .map(|verdict| verdict.trim())
+        .ok_or_else(|| ScannerServiceError::Clamd(reply.to_owned()))?;
+
+    if verdict == "OK" {
+        return Ok(ScanResult {
+            status: ScanStatus::Clean,
+            signature: None,
+        });
+    }
+
+    match verdict.strip_suffix("FOUND") {
+        Some(signature) => Ok(ScanResult {
+            status: ScanStatus::Infected,
+            signature: Some(signature.trim().to_owned()),
+        }),
+        None => Err(ScannerServiceError::Clamd(reply.to_owned())),
+    }
+}
@@ -1,582 +1,229 @@
-use crate::db::models::{Collection, File};
-use chrono::{DateTime, NaiveDateTime};
-use meilisearch_sdk::{Client, DocumentDeletionQuery, Index, Selectors};
+#[cfg(test)]
+pub mod faulty_search_service;
+pub mod meilisearch_search_service;
+pub mod postgres_search_service;
+
+use super::{FileMetadata, Page};
+use crate::{
+    config::{AppConfig, AppSearch, SearchBackend},
+    db::{
+        models::{Collection, File, Tag},
+        ReadPool,
+    },
+};
+use async_trait::async_trait;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::NaiveDateTime;
+use diesel_async::{pooled_connection::deadpool::Pool, AsyncPgConnection};
+use meilisearch_sdk::errors::MeilisearchError;
+use meilisearch_search_service::MeilisearchSearchService;
+use postgres_search_service::PostgresSearchService;
 use serde::{Deserialize, Serialize};
-use std::sync::Arc;
+use std::{collections::BTreeMap, sync::Arc};
 use thiserror::Error;
 use uuid::Uuid;
 
 #[derive(Error, Debug)]
 pub enum SearchServiceError {
+    #[error("database pool error: {0}")]
+    Pool(#[from] diesel_async::pooled_connection::deadpool::PoolError),
+    #[error("diesel error: {0}")]
+    Diesel(#[from] diesel::result::Error),
     #[error("meilisearch error: {0}")]
     MeiliSearchError(#[from] meilisearch_sdk::errors::Error),
     #[error("index not found in task")]
     IndexInTaskNotFound,
+    #[error("settings rejected: {0}")]
+    SettingsRejected(MeilisearchError),
 }
 
-#[derive(Serialize)]
-struct IndexingFile<'a> {
-    pub id: Uuid,
-    pub name: &'a str,
-    pub mime_full: &'a str,
-    pub mime_type_part: &'a str,
-    pub mime_subtype_part: Option<&'a str>,
-    pub size: i64,
-    pub hash: i64,
-    pub uploaded_at: i64,
+/// Filters on the media metadata of files.
+/// Each filter is an inclusive range.
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+pub struct FileMetadataFilter {
+    #[serde(default)]
+    pub width: Option<(u32, u32)>,
+    #[serde(default)]
+    pub height: Option<(u32, u32)>,
+    #[serde(default)]
+    pub duration: Option<(f64, f64)>,
+    #[serde(default)]
+    pub taken_at: Option<(NaiveDateTime, NaiveDateTime)>,
 }
 
-impl<'a> IndexingFile<'a> {
-    pub fn from_file(file: &'a File) -> Self {
-        let (mime_type_part, mime_subtype_part) = match file.mime.trim().split_once('/') {
-            Some((type_part, subtype_part)) => (type_part, Some(subtype_part)),
-            None => (file.mime.as_str(), None),
-        };
-
-        let uploaded_at = file.uploaded_at.and_utc().timestamp_micros();
-
-        Self {
-            id: file.id,
-            name: &file.name,
-            mime_full: &file.mime,
-            mime_type_part,
-            mime_subtype_part,
-            size: file.size,
-            hash: file.hash,
-            uploaded_at,
-        }
-    }
+/// A filter on the tags of files.
+/// If `ns` is `None`, it matches the value in any namespace, including none.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TagFilter {
+    #[serde(default)]
+    pub ns: Option<String>,
+    pub value: String,
 }
 
-#[derive(Deserialize)]
-struct IndexedFile {
-    pub id: Uuid,
-    pub name: String,
-    pub mime_full: String,
-    pub size: i64,
-    pub hash: i64,
-    pub uploaded_at: i64,
+/// The size buckets files are counted in, as pairs of the exclusive upper bound and the name of each bucket.
+/// Files not fitting in any of them fall into [`LARGEST_SIZE_BUCKET`].
+const SIZE_BUCKETS: [(i64, &str); 4] = [
+    (1 << 20, "<1MiB"),
+    (10 << 20, "1MiB-10MiB"),
+    (100 << 20, "10MiB-100MiB"),
+    (1 << 30, "100MiB-1GiB"),
+];
+const LARGEST_SIZE_BUCKET: &str = ">=1GiB";
+
+fn size_bucket(size: i64) -> &'static str {
+    SIZE_BUCKETS
+        .iter()
+        .find(|(upper_bound, _)| size < *upper_bound)
+        .map(|(_, name)| *name)
+        .unwrap_or(LARGEST_SIZE_BUCKET)
 }
 
-impl IndexedFile {
-    pub fn into_file(self) -> File {
-        let uploaded_at = DateTime::from_timestamp_micros(self.uploaded_at).unwrap();
-        let uploaded_at = uploaded_at.naive_utc();
-
-        File {
-            id: self.id,
-            name: self.name,
-            mime: self.mime_full,
-            size: self.size,
-            hash: self.hash,
-            uploaded_at,
-        }
-    }
+/// The number of matching files per facet value.
+#[derive(Serialize, Deserialize, Default, Debug, Clone, PartialEq)]
+pub struct FileFacets {
+    pub mime_type_part: BTreeMap<String, usize>,
+    pub tags: BTreeMap<String, usize>,
+    pub size_bucket: BTreeMap<String, usize>,
 }
 
-#[derive(Serialize)]
-struct IndexingCollectionFile<'a> {
-    pub id: String,
-    pub collection_id: Uuid,
-    pub file_id: Uuid,
-    pub name: &'a str,
-    pub mime_full: &'a str,
-    pub mime_type_part: &'a str,
-    pub mime_subtype_part: Option<&'a str>,
-    pub size: i64,
-    pub hash: i64,
-    pub uploaded_at: i64,
+/// The position of the next page in the hits of a search.
+/// It is handed to clients as an opaque string, so that the way it is encoded can change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SearchCursor {
+    offset: usize,
 }
 
-impl<'a> IndexingCollectionFile<'a> {
-    pub fn make_id(collection_id: Uuid, file_id: Uuid) -> String {
-        format!("{}#{}", collection_id, file_id)
+impl SearchCursor {
+    pub fn new(offset: usize) -> Self {
+        Self { offset }
     }
 
-    pub fn from_file(collection_id: Uuid, file: &'a File) -> Self {
-        let id = Self::make_id(collection_id, file.id);
-
-        let (mime_type_part, mime_subtype_part) = match file.mime.trim().split_once('/') {
-            Some((type_part, subtype_part)) => (type_part, Some(subtype_part)),
-            None => (file.mime.as_str(), None),
-        };
-
-        let uploaded_at = file.uploaded_at.and_utc().timestamp_micros();
-
-        Self {
-            id,
-            collection_id,
-            file_id: file.id,
-            name: &file.name,
-            mime_full: &file.mime,
-            mime_type_part,
-            mime_subtype_part,
-            size: file.size,
-            hash: file.hash,
-            uploaded_at,
-        }
+    /// The number of hits before the page.
+    pub fn offset(self) -> usize {
+        self.offset
     }
-}
-
-#[derive(Deserialize)]
-struct IndexedCollectionFile {
-    pub file_id: Uuid,
-    pub name: String,
-    pub mime_full: String,
-    pub size: i64,
-    pub hash: i64,
-    pub uploaded_at: i64,
-}
 
-impl IndexedCollectionFile {
-    pub fn into_file(self) -> File {
-        let uploaded_at = DateTime::from_timestamp_micros(self.uploaded_at).unwrap();
-        let uploaded_at = uploaded_at.naive_utc();
-
-        File {
-            id: self.file_id,
-            name: self.name,
-            mime: self.mime_full,
-            size: self.size,
-            hash: self.hash,
-            uploaded_at,
-        }
+    pub fn encode(self) -> String {
+        URL_SAFE_NO_PAD.encode(format!("o:{}", self.offset))
     }
-}
 
-pub struct SearchService {
-    collections_index: Index,
-    files_index: Index,
-    collection_files_index: Index,
-}
-
-impl SearchService {
-    pub async fn new(
-        meilisearch_url: &str,
-        meilisearch_master_key: Option<&str>,
-        meilisearch_index_prefix: Option<&str>,
-    ) -> Result<Arc<Self>, SearchServiceError> {
-        let meilisearch_url: &str = meilisearch_url.trim_end_matches('/');
-        let meilisearch_index_prefix = match meilisearch_index_prefix {
-            Some(prefix) => format!("{}_", prefix.to_ascii_lowercase()),
-            None => String::new(),
-        };
-        let client = Client::new(meilisearch_url, meilisearch_master_key);
-
-        fn make_index_name(index_prefix: &str, name: &str) -> String {
-            format!("{}{}", index_prefix, name)
-        }
+    /// Decodes a cursor made by [`SearchCursor::encode`].
+    /// Returns `None` if the cursor is malformed.
+    pub fn decode(cursor: &str) -> Option<Self> {
+        let cursor = URL_SAFE_NO_PAD.decode(cursor).ok()?;
+        let cursor = String::from_utf8(cursor).ok()?;
+        let offset = cursor.strip_prefix("o:")?.parse().ok()?;
 
-        let collections_index_name = make_index_name(&meilisearch_index_prefix, "collections");
-        let files_index_name = make_index_name(&meilisearch_index_prefix, "files");
-        let collection_files_index_name =
-            make_index_name(&meilisearch_index_prefix, "collection_files");
-
-        log::info!(target: "search_service", collections_index_name, files_index_name, collection_files_index_name; "Creating indices. It may produce warnings if the indices are not found.");
+        Some(Self { offset })
+    }
 
-        let collections_index = client.get_index(&collections_index_name).await;
-        let files_index = client.get_index(&files_index_name).await;
-        let collection_files_index = client.get_index(&collection_files_index_name).await;
+    /// Makes a page from hits fetched from this position with a limit of `limit + 1`.
+    pub fn make_page<T>(cursor: Option<Self>, rows: Vec<T>, limit: u32) -> Page<T, Self> {
+        let offset = cursor.map_or(0, |cursor| cursor.offset);
 
-        let collections_index = match collections_index {
-            Ok(index) => {
-                log::info!(target: "search_service", collections_index_name; "Index already exists. Skipping creation.");
-                index
-            }
-            // ignore the error, assuming it's because the index doesn't exist
-            Err(_) => {
-                let task = client
-                    .create_index(&collections_index_name, Some("id"))
-                    .await;
-                let task = match task {
-                    Ok(task) => task,
-                    Err(err) => {
-                        log::error!(target: "search_service", collections_index_name, err:err; "Failed to create index. Aborting.");
-                        return Err(err.into());
-                    }
-                };
-
-                let task = task.wait_for_completion(&client, None, None).await;
-                let task = match task {
-                    Ok(task) => task,
-                    Err(err) => {
-                        log::error!(target: "search_service", collections_index_name, err:err; "Failed to wait for index creation. Aborting.");
-                        return Err(err.into());
-                    }
-                };
-
-                let index = match task.try_make_index(&client) {
-                    Ok(index) => index,
-                    Err(_) => {
-                        log::error!(target: "search_service", collections_index_name; "Failed to get index. Aborting.");
-                        return Err(SearchServiceError::IndexInTaskNotFound);
-                    }
-                };
-
-                if let Err(err) = index
-                    .set_searchable_attributes(["name", "description"])
-                    .await
-                {
-                    // failing to set searchable attributes is not a critical error
-                    log::warn!(target: "search_service", collections_index_name, err:err; "Failed to set searchable attributes.");
-                }
-
-                if let Err(err) = index.set_filterable_attributes(["created_at"]).await {
-                    // failing to set searchable attributes is not a critical error
-                    log::warn!(target: "search_service", collections_index_name, err:err; "Failed to set searchable attributes.");
-                }
-
-                index
-            }
-        };
+        Page::new(rows, limit, None, |_| Self::new(offset + limit as usize))
+    }
+}
 
-        let files_index = match files_index {
-            Ok(index) => {
-                log::info!(target: "search_service", files_index_name; "Index already exists. Skipping creation.");
-                index
-            }
-            // ignore the error, assuming it's because the index doesn't exist
-            Err(_) => {
-                let task = client.create_index(&files_index_name, Some("id")).await;
-                let task = match task {
-                    Ok(task) => task,
-                    Err(err) => {
-                        log::error!(target: "search_service", files_index_name, err:err; "Failed to create index. Aborting.");
-                        return Err(err.into());
-                    }
-                };
-
-                let task = task.wait_for_completion(&client, None, None).await;
-                let task = match task {
-                    Ok(task) => task,
-                    Err(err) => {
-                        log::error!(target: "search_service", files_index_name, err:err; "Failed to wait for index creation. Aborting.");
-                        return Err(err.into());
-                    }
-                };
-
-                let index = match task.try_make_index(&client) {
-                    Ok(index) => index,
-                    Err(_) => {
-                        log::error!(target: "search_service", files_index_name; "Failed to get index. Aborting.");
-                        return Err(SearchServiceError::IndexInTaskNotFound);
-                    }
-                };
-
-                if let Err(err) = index.set_searchable_attributes(["name"]).await {
-                    // failing to set searchable attributes is not a critical error
-                    log::warn!(target: "search_service", files_index_name, err:err; "Failed to set searchable attributes.");
-                }
-
-                if let Err(err) = index
-                    .set_filterable_attributes([
-                        "mime_full",
-                        "mime_type_part",
-                        "mime_subtype_part",
-                        "size",
-                        "hash",
-                        "uploaded_at",
-                    ])
-                    .await
-                {
-                    // failing to set filterable attributes is not a critical error
-                    log::warn!(target: "search_service", files_index_name, err:err; "Failed to set filterable attributes.");
-                }
-
-                index
-            }
-        };
+/// A page of files found by a search, along with the facets of all matching files.
+#[derive(Debug, Clone)]
+pub struct SearchedFiles {
+    pub files: Page<File, SearchCursor>,
+    pub facets: FileFacets,
+}
 
-        let collection_files_index = match collection_files_index {
-            Ok(index) => {
-                log::info!(target: "search_service", collection_files_index_name; "Index already exists. Skipping creation.");
-                index
-            }
-            // ignore the error, assuming it's because the index doesn't exist
-            Err(_) => {
-                let task = client
-                    .create_index(&collection_files_index_name, Some("id"))
-                    .await;
-                let task = match task {
-                    Ok(task) => task,
-                    Err(err) => {
-                        log::error!(target: "search_service", collection_files_index_name, err:err; "Failed to create index. Aborting.");
-                        return Err(err.into());
-                    }
-                };
-
-                let task = task.wait_for_completion(&client, None, None).await;
-                let task = match task {
-                    Ok(task) => task,
-                    Err(err) => {
-                        log::error!(target: "search_service", collection_files_index_name, err:err; "Failed to wait for index creation. Aborting.");
-                        return Err(err.into());
-                    }
-                };
-
-                let index = match task.try_make_index(&client) {
-                    Ok(index) => index,
-                    Err(_) => {
-                        log::error!(target: "search_service", collection_files_index_name; "Failed to get index. Aborting.");
-                        return Err(SearchServiceError::IndexInTaskNotFound);
-                    }
-                };
-
-                if let Err(err) = index.set_searchable_attributes(["name"]).await {
-                    // failing to set searchable attributes is not a critical error
-                    log::warn!(target: "search_service", collection_files_index_name, err:err; "Failed to set searchable attributes.");
-                }
-
-                if let Err(err) = index
-                    .set_filterable_attributes([
-                        "collection_id",
-                        "file_id",
-                        "mime_full",
-                        "mime_type_part",
-                        "mime_subtype_part",
-                        "size",
-                        "hash",
-                        "uploaded_at",
-                    ])
-                    .await
-                {
-                    // failing to set filterable attributes is not a critical error
-                    log::warn!(target: "search_service", collection_files_index_name, err:err; "Failed to set filterable attributes.");
-                }
-
-                index
-            }
-        };
+/// Indexes and searches collections and files.
+/// The other services keep the index in sync with the database through it.
+#[async_trait]
+pub trait SearchService {
+    /// Returns the current relevance settings of the indices.
+    fn settings(&self) -> AppSearch;
 
-        Ok(Arc::new(Self {
-            collections_index,
-            files_index,
-            collection_files_index,
-        }))
-    }
+    /// Updates the relevance settings of every index.
+    /// It waits for the settings to be applied, and fails with [`SearchServiceError::SettingsRejected`] if they are invalid.
+    /// The backend cannot be changed at runtime, so `settings.backend` is ignored.
+    async fn update_settings(&self, settings: &AppSearch) -> Result<(), SearchServiceError>;
 
     /// Indexes a collection.
     /// It will overwrite the previous with the same ID.
-    pub async fn index_collection(
-        &self,
-        collection: &Collection,
-    ) -> Result<(), SearchServiceError> {
-        let result = self
-            .collections_index
-            .add_or_replace(&[collection], Some("id"))
-            .await;
-
-        if let Err(err) = result {
-            let index_uid = &self.collections_index.uid;
-            log::error!(target: "search_service", index_uid, collection:serde, err:err; "Failed to add a collection to index.");
-            return Err(err.into());
-        }
-
-        Ok(())
-    }
+    async fn index_collection(&self, collection: &Collection) -> Result<(), SearchServiceError>;
 
     /// Removes a collection from the index.
     /// It will not fail if the collection is not found in the index.
-    pub async fn remove_collection_by_id(
-        &self,
-        collection_id: Uuid,
-    ) -> Result<(), SearchServiceError> {
-        if let Err(err) = self.collections_index.delete_document(collection_id).await {
-            let index_uid = &self.collections_index.uid;
-            log::error!(target: "search_service", index_uid, collection_id:serde, err:err; "Failed to remove collection.");
-        }
-
-        let filter = format!("collection_id = \"{}\"", collection_id);
-        let mut query = DocumentDeletionQuery::new(&self.collection_files_index);
-        query.with_filter(&filter);
-
-        if let Err(err) = self
-            .collection_files_index
-            .delete_documents_with(&query)
-            .await
-        {
-            let index_uid = &self.collection_files_index.uid;
-            log::error!(target: "search_service", index_uid, collection_id:serde, err:err; "Failed to remove collection files.");
-        }
-
-        Ok(())
-    }
+    async fn remove_collection_by_id(&self, collection_id: Uuid) -> Result<(), SearchServiceError>;
 
     /// Searches collections.
-    pub async fn search_collections(&self, q: &str) -> Result<Vec<Collection>, SearchServiceError> {
-        let query = self.collections_index.search().with_query(q).build();
-
-        let result = query.execute::<Collection>().await;
-        let result = match result {
-            Ok(result) => result,
-            Err(err) => {
-                let index_uid = &self.collections_index.uid;
-                log::error!(target: "search_service", index_uid, q, err:err; "Failed to search collections.");
-                return Err(err.into());
-            }
-        };
-
-        let hits = result.hits.into_iter().map(|hit| hit.result).collect();
-
-        Ok(hits)
-    }
+    /// It returns at most `limit` collections after `cursor`, or from the first one if `cursor` is `None`.
+    async fn search_collections(
+        &self,
+        q: &str,
+        cursor: Option<SearchCursor>,
+        limit: u32,
+    ) -> Result<Page<Collection, SearchCursor>, SearchServiceError>;
+
+    /// Indexes a file along with its media metadata and text content, if any.
+    /// It will overwrite the previous with the same ID, except for its tags and attributes.
+    /// A `None` content leaves the indexed content as is.
+    async fn index_file(
+        &self,
+        file: &File,
+        metadata: Option<&FileMetadata>,
+        content: Option<&str>,
+    ) -> Result<(), SearchServiceError>;
 
-    /// Indexes a file.
-    /// It will overwrite the previous with the same ID.
-    pub async fn index_file(&self, file: &File) -> Result<(), SearchServiceError> {
-        let indexing_file = IndexingFile::from_file(file);
-
-        let result = self
-            .files_index
-            .add_or_replace(&[indexing_file], Some("id"))
-            .await;
-
-        if let Err(err) = result {
-            let index_uid = &self.files_index.uid;
-            log::error!(target: "search_service", index_uid, file:serde, err:err; "Failed to add a file to index.");
-            return Err(err.into());
-        }
+    /// Replaces the tags of an indexed file with `tags`, leaving the rest of the document as is.
+    async fn index_file_tags(&self, file_id: Uuid, tags: &[Tag]) -> Result<(), SearchServiceError>;
 
-        Ok(())
-    }
+    /// Replaces the attributes of an indexed file with `attributes`, leaving the rest of the document as is.
+    /// Only the attributes listed in `filterable_file_attributes` of the settings are indexed.
+    async fn index_file_attributes(
+        &self,
+        file_id: Uuid,
+        attributes: &BTreeMap<String, String>,
+    ) -> Result<(), SearchServiceError>;
 
     /// Removes a file from the index.
     /// It will not fail if the file is not found in the index.
-    pub async fn remove_file_by_id(&self, file_id: Uuid) -> Result<(), SearchServiceError> {
-        if let Err(err) = self.files_index.delete_document(file_id).await {
-            let index_uid = &self.files_index.uid;
-            log::error!(target: "search_service", index_uid, file_id:serde, err:err; "Failed to remove file.");
-        }
-
-        let filter = format!("file_id = \"{}\"", file_id);
-        let mut query = DocumentDeletionQuery::new(&self.collection_files_index);
-        query.with_filter(&filter);
-
-        if let Err(err) = self
-            .collection_files_index
-            .delete_documents_with(&query)
-            .await
-        {
-            let index_uid = &self.collection_files_index.uid;
-            log::error!(target: "search_service", index_uid, file_id:serde, err:err; "Failed to remove collection files.");
-        }
-
-        Ok(())
-    }
+    async fn remove_file_by_id(&self, file_id: Uuid) -> Result<(), SearchServiceError>;
 
     /// Searches files.
-    pub async fn search_files(
+    /// It returns at most `limit` files after `cursor`, or from the first one if `cursor` is `None`.
+    /// The facets count all matching files per MIME type, tag and size bucket, regardless of the page.
+    /// `filter_attributes` matches files having all of the attributes, and should only have filterable keys.
+    #[allow(clippy::too_many_arguments)]
+    async fn search_files(
         &self,
         q: &str,
         filter_mime: Option<&str>,
         filter_size: Option<(u32, u32)>,
         filter_hash: Option<u32>,
         filter_uploaded_at: Option<(NaiveDateTime, NaiveDateTime)>,
-    ) -> Result<Vec<File>, SearchServiceError> {
-        let mut array_filter = Vec::with_capacity(4);
-
-        if let Some(filter_mime) = filter_mime {
-            array_filter.push(format!(
-                "mime_full = \"{}\" OR mime_type_part = \"{}\" OR mime_subtype_part = \"{}\"",
-                filter_mime, filter_mime, filter_mime
-            ));
-        }
-
-        if let Some(filter_size) = filter_size {
-            array_filter.push(format!("size {} TO {}", filter_size.0, filter_size.1));
-        }
-
-        if let Some(filter_hash) = filter_hash {
-            array_filter.push(format!("hash = {}", filter_hash));
-        }
-
-        if let Some(filter_uploaded_at) = filter_uploaded_at {
-            let start_timestamp = filter_uploaded_at.0.and_utc().timestamp();
-            let end_timestamp = filter_uploaded_at.1.and_utc().timestamp();
-
-            array_filter.push(format!(
-                "uploaded_at {} TO {}",
-                start_timestamp, end_timestamp
-            ));
-        }
-
-        let array_filter = array_filter.iter().map(|s| s.as_str()).collect();
-
-        let query = self
-            .files_index
-            .search()
-            .with_query(q)
-            .with_array_filter(array_filter)
-            .with_attributes_to_retrieve(Selectors::Some(&[
-                "id",
-                "name",
-                "mime_full",
-                "size",
-                "hash",
-                "uploaded_at",
-            ]))
-            .build();
-
-        let result = query.execute::<IndexedFile>().await;
-        let result = match result {
-            Ok(result) => result,
-            Err(err) => {
-                let index_uid = &self.files_index.uid;
-                log::error!(target: "search_service", index_uid, q, err:err; "Failed to search files.");
-                return Err(err.into());
-            }
-        };
-
-        let hits = result
-            .hits
-            .into_iter()
-            .map(|hit| hit.result.into_file())
-            .collect();
-
-        Ok(hits)
-    }
+        filter_metadata: &FileMetadataFilter,
+        filter_tags: &[TagFilter],
+        filter_attributes: &BTreeMap<String, String>,
+        cursor: Option<SearchCursor>,
+        limit: u32,
+    ) -> Result<SearchedFiles, SearchServiceError>;
 
     /// Indexes a file in a collection.
-    pub async fn index_collection_file(
+    async fn index_collection_file(
         &self,
         collection_id: Uuid,
         file: &File,
-    ) -> Result<(), SearchServiceError> {
-        let indexing_file = IndexingCollectionFile::from_file(collection_id, file);
-
-        let result = self
-            .collection_files_index
-            .add_or_replace(&[indexing_file], Some("id"))
-            .await;
-
-        if let Err(err) = result {
-            let index_uid = &self.collection_files_index.uid;
-            log::error!(target: "search_service", index_uid, collection_id:serde, file:serde, err:err; "Failed to add a collection file to index.");
-            return Err(err.into());
-        }
-
-        Ok(())
-    }
+    ) -> Result<(), SearchServiceError>;
 
     /// Removes a file from a collection in the index.
     /// It will not fail if the file is not found in the index.
-    pub async fn remove_collection_file(
+    async fn remove_collection_file(
         &self,
         collection_id: Uuid,
         file_id: Uuid,
-    ) -> Result<(), SearchServiceError> {
-        let id = IndexingCollectionFile::make_id(collection_id, file_id);
-
-        if let Err(err) = self.collection_files_index.delete_document(id).await {
-            let index_uid = &self.collection_files_index.uid;
-            log::error!(target: "search_service", index_uid, collection_id:serde, file_id:serde, err:err; "Failed to remove collection file.");
-        }
-
-        Ok(())
-    }
+    ) -> Result<(), SearchServiceError>;
 
     /// Searches files in a collection.
-    pub async fn search_collection_files(
+    /// It returns at most `limit` files after `cursor`, or from the first one if `cursor` is `None`.
+    #[allow(clippy::too_many_arguments)]
+    async fn search_collection_files(
         &self,
         collection_id: Uuid,
         q: &str,
@@ -584,76 +231,45 @@ impl SearchService {
         filter_size: Option<(u32, u32)>,
         filter_hash: Option<u32>,
         filter_uploaded_at: Option<(NaiveDateTime, NaiveDateTime)>,
-    ) -> Result<Vec<File>, SearchServiceError> {
-        let mut array_filter = Vec::with_capacity(5);
-
-        array_filter.push(format!("collection_id = \"{}\"", collection_id));
-
-        if let Some(filter_mime) = filter_mime {
-            array_filter.push(format!(
-                "mime_full = \"{}\" OR mime_type_part = \"{}\" OR mime_subtype_part = \"{}\"",
-                filter_mime, filter_mime, filter_mime
-            ));
-        }
-
-        if let Some(filter_size) = filter_size {
-            array_filter.push(format!("size {} TO {}", filter_size.0, filter_size.1));
-        }
+        cursor: Option<SearchCursor>,
+        limit: u32,
+    ) -> Result<Page<File, SearchCursor>, SearchServiceError>;
+}
 
-        if let Some(filter_hash) = filter_hash {
-            array_filter.push(format!("hash = {}", filter_hash));
+/// Creates the search service for the backend selected in the config.
+pub async fn create_search_service(
+    app_config: &AppConfig,
+    db_pool: Pool<AsyncPgConnection>,
+    read_pool: ReadPool,
+) -> Result<Arc<dyn SearchService + Send + Sync>, SearchServiceError> {
+    let search_service: Arc<dyn SearchService + Send + Sync> = match app_config.search.backend {
+        SearchBackend::Meilisearch => {
+            MeilisearchSearchService::new(
+                &app_config.meilisearch_url,
+                app_config.meilisearch_master_key.as_deref(),
+                app_config.meilisearch_index_prefix.as_deref(),
+                &app_config.search,
+            )
+            .await?
         }
-
-        if let Some(filter_uploaded_at) = filter_uploaded_at {
-            let start_timestamp = filter_uploaded_at.0.and_utc().timestamp();
-            let end_timestamp = filter_uploaded_at.1.and_utc().timestamp();
-
-            array_filter.push(format!(
-                "uploaded_at {} TO {}",
-                start_timestamp, end_timestamp
-            ));
+        SearchBackend::Postgres => {
+            PostgresSearchService::new(db_pool, read_pool, &app_config.search)
         }
+    };
 
-        let array_filter = array_filter.iter().map(|s| s.as_str()).collect();
-
-        let query = self
-            .collection_files_index
-            .search()
-            .with_query(q)
-            .with_array_filter(array_filter)
-            .with_attributes_to_retrieve(Selectors::Some(&[
-                "file_id",
-                "name",
-                "mime_full",
-                "size",
-                "hash",
-                "uploaded_at",
-            ]))
-            .build();
-
-        let result = query.execute::<IndexedCollectionFile>().await;
-        let result = match result {
-            Ok(result) => result,
-            Err(err) => {
-                let index_uid = &self.collection_files_index.uid;
-                log::error!(target: "search_service", index_uid, collection_id:serde, q, err:err; "Failed to search collection files.");
-                return Err(err.into());
-            }
-        };
+    // tests can make the indexing fail on demand
+    #[cfg(test)]
+    let search_service = Arc::new(faulty_search_service::FaultySearchService::new(
+        search_service,
+        app_config.test_faults.fail_file_indexing.clone(),
+    ));
 
-        let hits = result
-            .hits
-            .into_iter()
-            .map(|hit| hit.result.into_file())
-            .collect();
-
-        Ok(hits)
-    }
+    Ok(search_service)
 }
 
 #[cfg(test)]
 pub mod test {
-    use super::*;
+    use meilisearch_sdk::Client;
     use rocket::futures::executor::block_on;
 
     pub struct IndexDropper {
@@ -0,0 +1,167 @@
+use super::{
+    FileMetadataFilter, SearchCursor, SearchService, SearchServiceError, SearchedFiles, TagFilter,
+};
+use crate::{
+    config::AppSearch,
+    db::models::{Collection, File, Tag},
+    services::{FileMetadata, Page},
+};
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+use diesel_async::pooled_connection::deadpool::PoolError;
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+use uuid::Uuid;
+
+/// A search service that passes everything through to another one, but fails indexing files while `fail_file_indexing` is set.
+/// It is meant for tests of how the other services recover from a search backend that is unavailable.
+pub struct FaultySearchService {
+    inner: Arc<dyn SearchService + Send + Sync>,
+    fail_file_indexing: Arc<AtomicBool>,
+}
+
+impl FaultySearchService {
+    pub fn new(
+        inner: Arc<dyn SearchService + Send + Sync>,
+        fail_file_indexing: Arc<AtomicBool>,
+    ) -> Self {
+        Self {
+            inner,
+            fail_file_indexing,
+        }
+    }
+}
+
+#[async_trait]
+impl SearchService for FaultySearchService {
+    fn settings(&self) -> AppSearch {
+        self.inner.settings()
+    }
+
+    async fn update_settings(&self, settings: &AppSearch) -> Result<(), SearchServiceError> {
+        self.inner.update_settings(settings).await
+    }
+
+    async fn index_collection(&self, collection: &Collection) -> Result<(), SearchServiceError> {
+        self.inner.index_collection(collection).await
+    }
+
+    async fn remove_collection_by_id(&self, collection_id: Uuid) -> Result<(), SearchServiceError> {
+        self.inner.remove_collection_by_id(collection_id).await
+    }
+
+    async fn search_collections(
+        &self,
+        q: &str,
+        cursor: Option<SearchCursor>,
+        limit: u32,
+    ) -> Result<Page<Collection, SearchCursor>, SearchServiceError> {
+        self.inner.search_collections(q, cursor, limit).await
+    }
+
+    async fn index_file(
+        &self,
+        file: &File,
+        metadata: Option<&FileMetadata>,
+        content: Option<&str>,
+    ) -> Result<(), SearchServiceError> {
+        if self.fail_file_indexing.load(Ordering::SeqCst) {
+            return Err(PoolError::Closed.into());
+        }
+
+        self.inner.index_file(file, metadata, content).await
+    }
+
+    async fn index_file_tags(&self, file_id: Uuid, tags: &[Tag]) -> Result<(), SearchServiceError> {
+        self.inner.index_file_tags(file_id, tags).await
+    }
+
+    async fn index_file_attributes(
+        &self,
+        file_id: Uuid,
+        attributes: &BTreeMap<String, String>,
+    ) -> Result<(), SearchServiceError> {
+        self.inner.index_file_attributes(file_id, attributes).await
+    }
+
+    async fn remove_file_by_id(&self, file_id: Uuid) -> Result<(), SearchServiceError> {
+        self.inner.remove_file_by_id(file_id).await
+    }
+
+    async fn search_files(
+        &self,
+        q: &str,
+        filter_mime: Option<&str>,
+        filter_size: Option<(u32, u32)>,
+        filter_hash: Option<u32>,
+        filter_uploaded_at: Option<(NaiveDateTime, NaiveDateTime)>,
+        filter_metadata: &FileMetadataFilter,
+        filter_tags: &[TagFilter],
+        filter_attributes: &BTreeMap<String, String>,
+        cursor: Option<SearchCursor>,
+        limit: u32,
+    ) -> Result<SearchedFiles, SearchServiceError> {
+        self.inner
+            .search_files(
+                q,
+                filter_mime,
+                filter_size,
+                filter_hash,
+                filter_uploaded_at,
+                filter_metadata,
+                filter_tags,
+                filter_attributes,
+                cursor,
+                limit,
+            )
+            .await
+    }
+
+    async fn index_collection_file(
+        &self,
+        collection_id: Uuid,
+        file: &File,
+    ) -> Result<(), SearchServiceError> {
+        self.inner.index_collection_file(collection_id, file).await
+    }
+
+    async fn remove_collection_file(
+        &self,
+        collection_id: Uuid,
+        file_id: Uuid,
+    ) -> Result<(), SearchServiceError> {
+        self.inner
+            .remove_collection_file(collection_id, file_id)
+            .await
+    }
+
+    async fn search_collection_files(
+        &self,
+        collection_id: Uuid,
+        q: &str,
+        filter_mime: Option<&str>,
+        filter_size: Option<(u32, u32)>,
+        filter_hash: Option<u32>,
+        filter_uploaded_at: Option<(NaiveDateTime, NaiveDateTime)>,
+        cursor: Option<SearchCursor>,
+        limit: u32,
+    ) -> Result<Page<File, SearchCursor>, SearchServiceError> {
+        self.inner
+            .search_collection_files(
+                collection_id,
+                q,
+                filter_mime,
+                filter_size,
+                filter_hash,
+                filter_uploaded_at,
+                cursor,
+                limit,
+            )
+            .await
+    }
+}
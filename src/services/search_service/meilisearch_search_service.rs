@@ -0,0 +1,943 @@
+use super::{
+    size_bucket, FileFacets, FileMetadataFilter, SearchCursor, SearchService, SearchServiceError,
+    SearchedFiles, TagFilter,
+};
+use crate::{
+    config::AppSearch,
+    db::models::{Collection, File, Tag},
+    services::{FileMetadata, Page},
+};
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDateTime};
+use meilisearch_sdk::{
+    settings::{MinWordSizeForTypos, Settings, TypoToleranceSettings},
+    Client, DocumentDeletionQuery, Index, Selectors,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, RwLock},
+};
+use uuid::Uuid;
+
+#[derive(Serialize)]
+struct IndexingFile<'a> {
+    pub id: Uuid,
+    pub name: &'a str,
+    pub mime_full: &'a str,
+    pub mime_type_part: &'a str,
+    pub mime_subtype_part: Option<&'a str>,
+    pub size: i64,
+    pub size_bucket: &'static str,
+    pub hash: i64,
+    pub uploaded_at: i64,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub duration: Option<f64>,
+    pub taken_at: Option<i64>,
+    // a missing content is not sent, so that the indexed content is left as is
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content: Option<&'a str>,
+}
+
+impl<'a> IndexingFile<'a> {
+    pub fn from_file(
+        file: &'a File,
+        metadata: Option<&FileMetadata>,
+        content: Option<&'a str>,
+    ) -> Self {
+        let (mime_type_part, mime_subtype_part) = match file.mime.trim().split_once('/') {
+            Some((type_part, subtype_part)) => (type_part, Some(subtype_part)),
+            None => (file.mime.as_str(), None),
+        };
+
+        let uploaded_at = file.uploaded_at.and_utc().timestamp_micros();
+        let taken_at = metadata
+            .and_then(|metadata| metadata.taken_at)
+            .map(|taken_at| taken_at.and_utc().timestamp_micros());
+
+        Self {
+            id: file.id,
+            name: &file.name,
+            mime_full: &file.mime,
+            mime_type_part,
+            mime_subtype_part,
+            size: file.size,
+            size_bucket: size_bucket(file.size),
+            hash: file.hash,
+            uploaded_at,
+            width: metadata.and_then(|metadata| metadata.width),
+            height: metadata.and_then(|metadata| metadata.height),
+            duration: metadata.and_then(|metadata| metadata.duration),
+            taken_at,
+            content,
+        }
+    }
+}
+
+/// A partial document of the files index holding the filterable attributes of a file.
+/// They are nested under `attributes`, so that they never clash with the other fields.
+#[derive(Serialize)]
+struct IndexingFileAttributes<'a> {
+    pub id: Uuid,
+    pub attributes: BTreeMap<&'a str, &'a str>,
+}
+
+/// A partial document of the files index holding the tags of a file.
+/// `tags` holds the full tags, e.g. `year:1959`, while the namespaces and values are indexed separately.
+#[derive(Serialize)]
+struct IndexingFileTags<'a> {
+    pub id: Uuid,
+    pub tags: Vec<String>,
+    pub tag_namespaces: Vec<&'a str>,
+    pub tag_values: Vec<&'a str>,
+}
+
+impl<'a> IndexingFileTags<'a> {
+    pub fn from_tags(file_id: Uuid, tags: &'a [Tag]) -> Self {
+        let mut tag_namespaces = tags
+            .iter()
+            .map(|tag| tag.namespace.as_str())
+            .filter(|namespace| !namespace.is_empty())
+            .collect::<Vec<_>>();
+        tag_namespaces.sort_unstable();
+        tag_namespaces.dedup();
+
+        Self {
+            id: file_id,
+            tags: tags
+                .iter()
+                .map(|tag| make_tag_filter_value(&tag.namespace, &tag.name))
+                .collect(),
+            tag_namespaces,
+            tag_values: tags.iter().map(|tag| tag.name.as_str()).collect(),
+        }
+    }
+}
+
+fn make_tag_filter_value(namespace: &str, value: &str) -> String {
+    if namespace.is_empty() {
+        value.to_owned()
+    } else {
+        format!("{}:{}", namespace, value)
+    }
+}
+
+/// Escapes a string to be used in a quoted filter expression.
+fn escape_filter_value(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// The fields of the files index that are filterable regardless of the settings.
+const FILE_FILTERABLE_ATTRIBUTES: [&str; 14] = [
+    "mime_full",
+    "mime_type_part",
+    "mime_subtype_part",
+    "size",
+    "size_bucket",
+    "hash",
+    "uploaded_at",
+    "width",
+    "height",
+    "duration",
+    "taken_at",
+    "tags",
+    "tag_namespaces",
+    "tag_values",
+];
+
+#[derive(Deserialize)]
+struct IndexedFile {
+    pub id: Uuid,
+    pub name: String,
+    pub mime_full: String,
+    pub size: i64,
+    pub hash: i64,
+    pub uploaded_at: i64,
+}
+
+impl IndexedFile {
+    pub fn into_file(self) -> File {
+        let uploaded_at = DateTime::from_timestamp_micros(self.uploaded_at).unwrap();
+        let uploaded_at = uploaded_at.naive_utc();
+
+        File {
+            id: self.id,
+            name: self.name,
+            mime: self.mime_full,
+            size: self.size,
+            hash: self.hash,
+            uploaded_at,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct IndexingCollectionFile<'a> {
+    pub id: String,
+    pub collection_id: Uuid,
+    pub file_id: Uuid,
+    pub name: &'a str,
+    pub mime_full: &'a str,
+    pub mime_type_part: &'a str,
+    pub mime_subtype_part: Option<&'a str>,
+    pub size: i64,
+    pub hash: i64,
+    pub uploaded_at: i64,
+}
+
+impl<'a> IndexingCollectionFile<'a> {
+    pub fn make_id(collection_id: Uuid, file_id: Uuid) -> String {
+        format!("{}#{}", collection_id, file_id)
+    }
+
+    pub fn from_file(collection_id: Uuid, file: &'a File) -> Self {
+        let id = Self::make_id(collection_id, file.id);
+
+        let (mime_type_part, mime_subtype_part) = match file.mime.trim().split_once('/') {
+            Some((type_part, subtype_part)) => (type_part, Some(subtype_part)),
+            None => (file.mime.as_str(), None),
+        };
+
+        let uploaded_at = file.uploaded_at.and_utc().timestamp_micros();
+
+        Self {
+            id,
+            collection_id,
+            file_id: file.id,
+            name: &file.name,
+            mime_full: &file.mime,
+            mime_type_part,
+            mime_subtype_part,
+            size: file.size,
+            hash: file.hash,
+            uploaded_at,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct IndexedCollectionFile {
+    pub file_id: Uuid,
+    pub name: String,
+    pub mime_full: String,
+    pub size: i64,
+    pub hash: i64,
+    pub uploaded_at: i64,
+}
+
+impl IndexedCollectionFile {
+    pub fn into_file(self) -> File {
+        let uploaded_at = DateTime::from_timestamp_micros(self.uploaded_at).unwrap();
+        let uploaded_at = uploaded_at.naive_utc();
+
+        File {
+            id: self.file_id,
+            name: self.name,
+            mime: self.mime_full,
+            size: self.size,
+            hash: self.hash,
+            uploaded_at,
+        }
+    }
+}
+
+/// Searches through MeiliSearch indices, which are kept in sync with the database by the other services.
+pub struct MeilisearchSearchService {
+    client: Client,
+    collections_index: Index,
+    files_index: Index,
+    collection_files_index: Index,
+    settings: RwLock<AppSearch>,
+}
+
+impl MeilisearchSearchService {
+    pub async fn new(
+        meilisearch_url: &str,
+        meilisearch_master_key: Option<&str>,
+        meilisearch_index_prefix: Option<&str>,
+        settings: &AppSearch,
+    ) -> Result<Arc<Self>, SearchServiceError> {
+        let meilisearch_url: &str = meilisearch_url.trim_end_matches('/');
+        let meilisearch_index_prefix = match meilisearch_index_prefix {
+            Some(prefix) => format!("{}_", prefix.to_ascii_lowercase()),
+            None => String::new(),
+        };
+        let client = Client::new(meilisearch_url, meilisearch_master_key);
+
+        fn make_index_name(index_prefix: &str, name: &str) -> String {
+            format!("{}{}", index_prefix, name)
+        }
+
+        let collections_index_name = make_index_name(&meilisearch_index_prefix, "collections");
+        let files_index_name = make_index_name(&meilisearch_index_prefix, "files");
+        let collection_files_index_name =
+            make_index_name(&meilisearch_index_prefix, "collection_files");
+
+        log::info!(target: "search_service", collections_index_name, files_index_name, collection_files_index_name; "Creating indices. It may produce warnings if the indices are not found.");
+
+        let collections_index = client.get_index(&collections_index_name).await;
+        let files_index = client.get_index(&files_index_name).await;
+        let collection_files_index = client.get_index(&collection_files_index_name).await;
+
+        let collections_index = match collections_index {
+            Ok(index) => {
+                log::info!(target: "search_service", collections_index_name; "Index already exists. Skipping creation.");
+                index
+            }
+            // ignore the error, assuming it's because the index doesn't exist
+            Err(_) => {
+                let task = client
+                    .create_index(&collections_index_name, Some("id"))
+                    .await;
+                let task = match task {
+                    Ok(task) => task,
+                    Err(err) => {
+                        log::error!(target: "search_service", collections_index_name, err:err; "Failed to create index. Aborting.");
+                        return Err(err.into());
+                    }
+                };
+
+                let task = task.wait_for_completion(&client, None, None).await;
+                let task = match task {
+                    Ok(task) => task,
+                    Err(err) => {
+                        log::error!(target: "search_service", collections_index_name, err:err; "Failed to wait for index creation. Aborting.");
+                        return Err(err.into());
+                    }
+                };
+
+                let index = match task.try_make_index(&client) {
+                    Ok(index) => index,
+                    Err(_) => {
+                        log::error!(target: "search_service", collections_index_name; "Failed to get index. Aborting.");
+                        return Err(SearchServiceError::IndexInTaskNotFound);
+                    }
+                };
+
+                if let Err(err) = index
+                    .set_searchable_attributes(["name", "description"])
+                    .await
+                {
+                    // failing to set searchable attributes is not a critical error
+                    log::warn!(target: "search_service", collections_index_name, err:err; "Failed to set searchable attributes.");
+                }
+
+                if let Err(err) = index.set_filterable_attributes(["created_at"]).await {
+                    // failing to set searchable attributes is not a critical error
+                    log::warn!(target: "search_service", collections_index_name, err:err; "Failed to set searchable attributes.");
+                }
+
+                index
+            }
+        };
+
+        let files_index = match files_index {
+            Ok(index) => {
+                log::info!(target: "search_service", files_index_name; "Index already exists. Skipping creation.");
+                index
+            }
+            // ignore the error, assuming it's because the index doesn't exist
+            Err(_) => {
+                let task = client.create_index(&files_index_name, Some("id")).await;
+                let task = match task {
+                    Ok(task) => task,
+                    Err(err) => {
+                        log::error!(target: "search_service", files_index_name, err:err; "Failed to create index. Aborting.");
+                        return Err(err.into());
+                    }
+                };
+
+                let task = task.wait_for_completion(&client, None, None).await;
+                let task = match task {
+                    Ok(task) => task,
+                    Err(err) => {
+                        log::error!(target: "search_service", files_index_name, err:err; "Failed to wait for index creation. Aborting.");
+                        return Err(err.into());
+                    }
+                };
+
+                let index = match task.try_make_index(&client) {
+                    Ok(index) => index,
+                    Err(_) => {
+                        log::error!(target: "search_service", files_index_name; "Failed to get index. Aborting.");
+                        return Err(SearchServiceError::IndexInTaskNotFound);
+                    }
+                };
+
+                if let Err(err) = index.set_searchable_attributes(["name", "content"]).await {
+                    // failing to set searchable attributes is not a critical error
+                    log::warn!(target: "search_service", files_index_name, err:err; "Failed to set searchable attributes.");
+                }
+
+                if let Err(err) = index
+                    .set_filterable_attributes(FILE_FILTERABLE_ATTRIBUTES)
+                    .await
+                {
+                    // failing to set filterable attributes is not a critical error
+                    log::warn!(target: "search_service", files_index_name, err:err; "Failed to set filterable attributes.");
+                }
+
+                index
+            }
+        };
+
+        let collection_files_index = match collection_files_index {
+            Ok(index) => {
+                log::info!(target: "search_service", collection_files_index_name; "Index already exists. Skipping creation.");
+                index
+            }
+            // ignore the error, assuming it's because the index doesn't exist
+            Err(_) => {
+                let task = client
+                    .create_index(&collection_files_index_name, Some("id"))
+                    .await;
+                let task = match task {
+                    Ok(task) => task,
+                    Err(err) => {
+                        log::error!(target: "search_service", collection_files_index_name, err:err; "Failed to create index. Aborting.");
+                        return Err(err.into());
+                    }
+                };
+
+                let task = task.wait_for_completion(&client, None, None).await;
+                let task = match task {
+                    Ok(task) => task,
+                    Err(err) => {
+                        log::error!(target: "search_service", collection_files_index_name, err:err; "Failed to wait for index creation. Aborting.");
+                        return Err(err.into());
+                    }
+                };
+
+                let index = match task.try_make_index(&client) {
+                    Ok(index) => index,
+                    Err(_) => {
+                        log::error!(target: "search_service", collection_files_index_name; "Failed to get index. Aborting.");
+                        return Err(SearchServiceError::IndexInTaskNotFound);
+                    }
+                };
+
+                if let Err(err) = index.set_searchable_attributes(["name"]).await {
+                    // failing to set searchable attributes is not a critical error
+                    log::warn!(target: "search_service", collection_files_index_name, err:err; "Failed to set searchable attributes.");
+                }
+
+                if let Err(err) = index
+                    .set_filterable_attributes([
+                        "collection_id",
+                        "file_id",
+                        "mime_full",
+                        "mime_type_part",
+                        "mime_subtype_part",
+                        "size",
+                        "hash",
+                        "uploaded_at",
+                    ])
+                    .await
+                {
+                    // failing to set filterable attributes is not a critical error
+                    log::warn!(target: "search_service", collection_files_index_name, err:err; "Failed to set filterable attributes.");
+                }
+
+                index
+            }
+        };
+
+        let search_service = Self {
+            client,
+            collections_index,
+            files_index,
+            collection_files_index,
+            settings: RwLock::new(settings.clone()),
+        };
+
+        if let Err(err) = search_service.apply_settings(settings).await {
+            // the indices still work with their previous settings, so it is not critical
+            log::warn!(target: "search_service", err:err; "Failed to apply search settings.");
+        }
+
+        Ok(Arc::new(search_service))
+    }
+
+    async fn apply_settings(&self, settings: &AppSearch) -> Result<(), SearchServiceError> {
+        let meilisearch_settings = Settings::new()
+            .with_ranking_rules(&settings.ranking_rules)
+            .with_stop_words(&settings.stop_words)
+            .with_synonyms(settings.synonyms.clone())
+            .with_typo_tolerance(TypoToleranceSettings {
+                enabled: Some(settings.typo_tolerance),
+                disable_on_attributes: None,
+                disable_on_words: None,
+                min_word_size_for_typos: Some(MinWordSizeForTypos {
+                    one_typo: Some(settings.min_word_size_for_one_typo),
+                    two_typos: Some(settings.min_word_size_for_two_typos),
+                }),
+            });
+
+        // the filterable attributes of files are applied here too, as they follow the settings
+        let files_meilisearch_settings = meilisearch_settings.clone().with_filterable_attributes(
+            FILE_FILTERABLE_ATTRIBUTES
+                .iter()
+                .map(|attribute| attribute.to_string())
+                .chain(
+                    settings
+                        .filterable_file_attributes
+                        .iter()
+                        .map(|key| format!("attributes.{}", key)),
+                ),
+        );
+
+        for (index, meilisearch_settings) in [
+            (&self.collections_index, &meilisearch_settings),
+            (&self.files_index, &files_meilisearch_settings),
+            (&self.collection_files_index, &meilisearch_settings),
+        ] {
+            let task = index
+                .set_settings(meilisearch_settings)
+                .await?
+                .wait_for_completion(&self.client, None, None)
+                .await?;
+
+            if task.is_failure() {
+                let index_uid = &index.uid;
+                let err = task.unwrap_failure();
+                log::error!(target: "search_service", index_uid, err:err; "Failed to apply search settings.");
+                return Err(SearchServiceError::SettingsRejected(err));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl SearchService for MeilisearchSearchService {
+    fn settings(&self) -> AppSearch {
+        self.settings.read().unwrap().clone()
+    }
+
+    async fn update_settings(&self, settings: &AppSearch) -> Result<(), SearchServiceError> {
+        let mut settings = settings.clone();
+        settings.backend = self.settings.read().unwrap().backend;
+
+        self.apply_settings(&settings).await?;
+        *self.settings.write().unwrap() = settings;
+
+        Ok(())
+    }
+
+    async fn index_collection(&self, collection: &Collection) -> Result<(), SearchServiceError> {
+        let result = self
+            .collections_index
+            .add_or_replace(&[collection], Some("id"))
+            .await;
+
+        if let Err(err) = result {
+            let index_uid = &self.collections_index.uid;
+            log::error!(target: "search_service", index_uid, collection:serde, err:err; "Failed to add a collection to index.");
+            return Err(err.into());
+        }
+
+        Ok(())
+    }
+
+    async fn remove_collection_by_id(&self, collection_id: Uuid) -> Result<(), SearchServiceError> {
+        if let Err(err) = self.collections_index.delete_document(collection_id).await {
+            let index_uid = &self.collections_index.uid;
+            log::error!(target: "search_service", index_uid, collection_id:serde, err:err; "Failed to remove collection.");
+        }
+
+        let filter = format!("collection_id = \"{}\"", collection_id);
+        let mut query = DocumentDeletionQuery::new(&self.collection_files_index);
+        query.with_filter(&filter);
+
+        if let Err(err) = self
+            .collection_files_index
+            .delete_documents_with(&query)
+            .await
+        {
+            let index_uid = &self.collection_files_index.uid;
+            log::error!(target: "search_service", index_uid, collection_id:serde, err:err; "Failed to remove collection files.");
+        }
+
+        Ok(())
+    }
+
+    async fn search_collections(
+        &self,
+        q: &str,
+        cursor: Option<SearchCursor>,
+        limit: u32,
+    ) -> Result<Page<Collection, SearchCursor>, SearchServiceError> {
+        let query = self
+            .collections_index
+            .search()
+            .with_query(q)
+            .with_offset(cursor.map_or(0, |cursor| cursor.offset()))
+            .with_limit(limit as usize + 1)
+            .build();
+
+        let result = query.execute::<Collection>().await;
+        let result = match result {
+            Ok(result) => result,
+            Err(err) => {
+                let index_uid = &self.collections_index.uid;
+                log::error!(target: "search_service", index_uid, q, err:err; "Failed to search collections.");
+                return Err(err.into());
+            }
+        };
+
+        let hits = result.hits.into_iter().map(|hit| hit.result).collect();
+
+        Ok(SearchCursor::make_page(cursor, hits, limit))
+    }
+
+    async fn index_file(
+        &self,
+        file: &File,
+        metadata: Option<&FileMetadata>,
+        content: Option<&str>,
+    ) -> Result<(), SearchServiceError> {
+        let indexing_file = IndexingFile::from_file(file, metadata, content);
+
+        // the tags and attributes are indexed separately, so they are left as is
+        let result = self
+            .files_index
+            .add_or_update(&[indexing_file], Some("id"))
+            .await;
+
+        if let Err(err) = result {
+            let index_uid = &self.files_index.uid;
+            log::error!(target: "search_service", index_uid, file:serde, err:err; "Failed to add a file to index.");
+            return Err(err.into());
+        }
+
+        Ok(())
+    }
+
+    async fn index_file_tags(&self, file_id: Uuid, tags: &[Tag]) -> Result<(), SearchServiceError> {
+        let indexing_file_tags = IndexingFileTags::from_tags(file_id, tags);
+
+        let result = self
+            .files_index
+            .add_or_update(&[indexing_file_tags], Some("id"))
+            .await;
+
+        if let Err(err) = result {
+            let index_uid = &self.files_index.uid;
+            log::error!(target: "search_service", index_uid, file_id:serde, err:err; "Failed to update tags of a file in index.");
+            return Err(err.into());
+        }
+
+        Ok(())
+    }
+
+    async fn index_file_attributes(
+        &self,
+        file_id: Uuid,
+        attributes: &BTreeMap<String, String>,
+    ) -> Result<(), SearchServiceError> {
+        let filterable_file_attributes = self
+            .settings
+            .read()
+            .unwrap()
+            .filterable_file_attributes
+            .clone();
+        let indexing_file_attributes = IndexingFileAttributes {
+            id: file_id,
+            attributes: attributes
+                .iter()
+                .filter(|(key, _)| filterable_file_attributes.contains(key))
+                .map(|(key, value)| (key.as_str(), value.as_str()))
+                .collect(),
+        };
+
+        let result = self
+            .files_index
+            .add_or_update(&[indexing_file_attributes], Some("id"))
+            .await;
+
+        if let Err(err) = result {
+            let index_uid = &self.files_index.uid;
+            log::error!(target: "search_service", index_uid, file_id:serde, err:err; "Failed to update attributes of a file in index.");
+            return Err(err.into());
+        }
+
+        Ok(())
+    }
+
+    async fn remove_file_by_id(&self, file_id: Uuid) -> Result<(), SearchServiceError> {
+        if let Err(err) = self.files_index.delete_document(file_id).await {
+            let index_uid = &self.files_index.uid;
+            log::error!(target: "search_service", index_uid, file_id:serde, err:err; "Failed to remove file.");
+        }
+
+        let filter = format!("file_id = \"{}\"", file_id);
+        let mut query = DocumentDeletionQuery::new(&self.collection_files_index);
+        query.with_filter(&filter);
+
+        if let Err(err) = self
+            .collection_files_index
+            .delete_documents_with(&query)
+            .await
+        {
+            let index_uid = &self.collection_files_index.uid;
+            log::error!(target: "search_service", index_uid, file_id:serde, err:err; "Failed to remove collection files.");
+        }
+
+        Ok(())
+    }
+
+    async fn search_files(
+        &self,
+        q: &str,
+        filter_mime: Option<&str>,
+        filter_size: Option<(u32, u32)>,
+        filter_hash: Option<u32>,
+        filter_uploaded_at: Option<(NaiveDateTime, NaiveDateTime)>,
+        filter_metadata: &FileMetadataFilter,
+        filter_tags: &[TagFilter],
+        filter_attributes: &BTreeMap<String, String>,
+        cursor: Option<SearchCursor>,
+        limit: u32,
+    ) -> Result<SearchedFiles, SearchServiceError> {
+        let mut array_filter = Vec::with_capacity(8 + filter_tags.len() + filter_attributes.len());
+
+        if let Some(filter_mime) = filter_mime {
+            array_filter.push(format!(
+                "mime_full = \"{}\" OR mime_type_part = \"{}\" OR mime_subtype_part = \"{}\"",
+                filter_mime, filter_mime, filter_mime
+            ));
+        }
+
+        if let Some(filter_size) = filter_size {
+            array_filter.push(format!("size {} TO {}", filter_size.0, filter_size.1));
+        }
+
+        if let Some(filter_hash) = filter_hash {
+            array_filter.push(format!("hash = {}", filter_hash));
+        }
+
+        if let Some(filter_uploaded_at) = filter_uploaded_at {
+            let start_timestamp = filter_uploaded_at.0.and_utc().timestamp();
+            let end_timestamp = filter_uploaded_at.1.and_utc().timestamp();
+
+            array_filter.push(format!(
+                "uploaded_at {} TO {}",
+                start_timestamp, end_timestamp
+            ));
+        }
+
+        if let Some(filter_width) = filter_metadata.width {
+            array_filter.push(format!("width {} TO {}", filter_width.0, filter_width.1));
+        }
+
+        if let Some(filter_height) = filter_metadata.height {
+            array_filter.push(format!("height {} TO {}", filter_height.0, filter_height.1));
+        }
+
+        if let Some(filter_duration) = filter_metadata.duration {
+            array_filter.push(format!(
+                "duration {} TO {}",
+                filter_duration.0, filter_duration.1
+            ));
+        }
+
+        if let Some(filter_taken_at) = filter_metadata.taken_at {
+            let start_timestamp = filter_taken_at.0.and_utc().timestamp_micros();
+            let end_timestamp = filter_taken_at.1.and_utc().timestamp_micros();
+
+            array_filter.push(format!("taken_at {} TO {}", start_timestamp, end_timestamp));
+        }
+
+        for filter_tag in filter_tags {
+            match &filter_tag.ns {
+                Some(ns) => array_filter.push(format!(
+                    "tags = \"{}\"",
+                    escape_filter_value(&make_tag_filter_value(ns, &filter_tag.value))
+                )),
+                None => array_filter.push(format!(
+                    "tag_values = \"{}\"",
+                    escape_filter_value(&filter_tag.value)
+                )),
+            }
+        }
+
+        for (key, value) in filter_attributes {
+            array_filter.push(format!(
+                "attributes.{} = \"{}\"",
+                key,
+                escape_filter_value(value)
+            ));
+        }
+
+        let array_filter = array_filter.iter().map(|s| s.as_str()).collect();
+
+        let query = self
+            .files_index
+            .search()
+            .with_query(q)
+            .with_array_filter(array_filter)
+            .with_offset(cursor.map_or(0, |cursor| cursor.offset()))
+            .with_limit(limit as usize + 1)
+            .with_attributes_to_retrieve(Selectors::Some(&[
+                "id",
+                "name",
+                "mime_full",
+                "size",
+                "hash",
+                "uploaded_at",
+            ]))
+            .with_facets(Selectors::Some(&["mime_type_part", "tags", "size_bucket"]))
+            .build();
+
+        let result = query.execute::<IndexedFile>().await;
+        let result = match result {
+            Ok(result) => result,
+            Err(err) => {
+                let index_uid = &self.files_index.uid;
+                log::error!(target: "search_service", index_uid, q, err:err; "Failed to search files.");
+                return Err(err.into());
+            }
+        };
+
+        let mut facet_distribution = result.facet_distribution.unwrap_or_default();
+        let mut take_facet = |name: &str| {
+            facet_distribution
+                .remove(name)
+                .unwrap_or_default()
+                .into_iter()
+                .collect::<BTreeMap<_, _>>()
+        };
+        let facets = FileFacets {
+            mime_type_part: take_facet("mime_type_part"),
+            tags: take_facet("tags"),
+            size_bucket: take_facet("size_bucket"),
+        };
+
+        let files = result
+            .hits
+            .into_iter()
+            .map(|hit| hit.result.into_file())
+            .collect();
+
+        Ok(SearchedFiles {
+            files: SearchCursor::make_page(cursor, files, limit),
+            facets,
+        })
+    }
+
+    async fn index_collection_file(
+        &self,
+        collection_id: Uuid,
+        file: &File,
+    ) -> Result<(), SearchServiceError> {
+        let indexing_file = IndexingCollectionFile::from_file(collection_id, file);
+
+        let result = self
+            .collection_files_index
+            .add_or_replace(&[indexing_file], Some("id"))
+            .await;
+
+        if let Err(err) = result {
+            let index_uid = &self.collection_files_index.uid;
+            log::error!(target: "search_service", index_uid, collection_id:serde, file:serde, err:err; "Failed to add a collection file to index.");
+            return Err(err.into());
+        }
+
+        Ok(())
+    }
+
+    async fn remove_collection_file(
+        &self,
+        collection_id: Uuid,
+        file_id: Uuid,
+    ) -> Result<(), SearchServiceError> {
+        let id = IndexingCollectionFile::make_id(collection_id, file_id);
+
+        if let Err(err) = self.collection_files_index.delete_document(id).await {
+            let index_uid = &self.collection_files_index.uid;
+            log::error!(target: "search_service", index_uid, collection_id:serde, file_id:serde, err:err; "Failed to remove collection file.");
+        }
+
+        Ok(())
+    }
+
+    async fn search_collection_files(
+        &self,
+        collection_id: Uuid,
+        q: &str,
+        filter_mime: Option<&str>,
+        filter_size: Option<(u32, u32)>,
+        filter_hash: Option<u32>,
+        filter_uploaded_at: Option<(NaiveDateTime, NaiveDateTime)>,
+        cursor: Option<SearchCursor>,
+        limit: u32,
+    ) -> Result<Page<File, SearchCursor>, SearchServiceError> {
+        let mut array_filter = Vec::with_capacity(5);
+
+        array_filter.push(format!("collection_id = \"{}\"", collection_id));
+
+        if let Some(filter_mime) = filter_mime {
+            array_filter.push(format!(
+                "mime_full = \"{}\" OR mime_type_part = \"{}\" OR mime_subtype_part = \"{}\"",
+                filter_mime, filter_mime, filter_mime
+            ));
+        }
+
+        if let Some(filter_size) = filter_size {
+            array_filter.push(format!("size {} TO {}", filter_size.0, filter_size.1));
+        }
+
+        if let Some(filter_hash) = filter_hash {
+            array_filter.push(format!("hash = {}", filter_hash));
+        }
+
+        if let Some(filter_uploaded_at) = filter_uploaded_at {
+            let start_timestamp = filter_uploaded_at.0.and_utc().timestamp();
+            let end_timestamp = filter_uploaded_at.1.and_utc().timestamp();
+
+            array_filter.push(format!(
+                "uploaded_at {} TO {}",
+                start_timestamp, end_timestamp
+            ));
+        }
+
+        let array_filter = array_filter.iter().map(|s| s.as_str()).collect();
+
+        let query = self
+            .collection_files_index
+            .search()
+            .with_query(q)
+            .with_array_filter(array_filter)
+            .with_offset(cursor.map_or(0, |cursor| cursor.offset()))
+            .with_limit(limit as usize + 1)
+            .with_attributes_to_retrieve(Selectors::Some(&[
+                "file_id",
+                "name",
+                "mime_full",
+                "size",
+                "hash",
+                "uploaded_at",
+            ]))
+            .build();
+
+        let result = query.execute::<IndexedCollectionFile>().await;
+        let result = match result {
+            Ok(result) => result,
+            Err(err) => {
+                let index_uid = &self.collection_files_index.uid;
+                log::error!(target: "search_service", index_uid, collection_id:serde, q, err:err; "Failed to search collection files.");
+                return Err(err.into());
+            }
+        };
+
+        let hits = result
+            .hits
+            .into_iter()
+            .map(|hit| hit.result.into_file())
+            .collect();
+
+        Ok(SearchCursor::make_page(cursor, hits, limit))
+    }
+}
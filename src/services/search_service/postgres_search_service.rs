@@ -0,0 +1,536 @@
+use super::{
+    FileFacets, FileMetadataFilter, SearchCursor, SearchService, SearchServiceError, SearchedFiles,
+    TagFilter, LARGEST_SIZE_BUCKET, SIZE_BUCKETS,
+};
+use crate::{
+    config::AppSearch,
+    db::{
+        models::{Collection, File, Tag},
+        ReadPool,
+    },
+    services::{FileMetadata, Page},
+};
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+use diesel::{
+    dsl::sql,
+    pg::Pg,
+    sql_types::{BigInt, Bool, Double, Float, Text, Timestamp},
+    BoolExpressionMethods, ExpressionMethods, PgTextExpressionMethods, QueryDsl,
+    TextExpressionMethods,
+};
+use diesel_async::{pooled_connection::deadpool::Pool, AsyncPgConnection, RunQueryDsl};
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, RwLock},
+};
+use uuid::Uuid;
+
+/// Escapes a string to be matched literally in a `LIKE` pattern.
+fn escape_like(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+/// The conditions of a file search. Unset conditions match every file.
+#[derive(Default)]
+struct FileSearch<'a> {
+    collection_id: Option<Uuid>,
+    q: &'a str,
+    filter_mime: Option<&'a str>,
+    filter_size: Option<(u32, u32)>,
+    filter_hash: Option<u32>,
+    filter_uploaded_at: Option<(NaiveDateTime, NaiveDateTime)>,
+    filter_metadata: Option<&'a FileMetadataFilter>,
+    filter_tags: &'a [TagFilter],
+    filter_attributes: Option<&'a BTreeMap<String, String>>,
+}
+
+impl<'a> FileSearch<'a> {
+    fn apply(
+        &self,
+        mut query: crate::db::schema::files::BoxedQuery<'a, Pg>,
+    ) -> crate::db::schema::files::BoxedQuery<'a, Pg> {
+        use crate::db::schema;
+
+        if let Some(collection_id) = self.collection_id {
+            query = query.filter(
+                schema::files::id.eq_any(
+                    schema::collection_file_pairs::table
+                        .filter(schema::collection_file_pairs::collection_id.eq(collection_id))
+                        .select(schema::collection_file_pairs::file_id),
+                ),
+            );
+        }
+
+        let q = self.q.trim();
+
+        if !q.is_empty() {
+            query = query.filter(
+                schema::files::name
+                    .ilike(format!("%{}%", escape_like(q)))
+                    .or(sql::<Bool>("files.name % ").bind::<Text, _>(q.to_owned()))
+                    .or(
+                        sql::<Bool>("files.search_vector @@ plainto_tsquery('simple', ")
+                            .bind::<Text, _>(q.to_owned())
+                            .sql(")"),
+                    ),
+            );
+        }
+
+        if let Some(filter_mime) = self.filter_mime {
+            let escaped = escape_like(filter_mime);
+            query = query.filter(
+                schema::files::mime
+                    .eq(filter_mime.to_owned())
+                    .or(schema::files::mime.like(format!("{}/%", escaped)))
+                    .or(schema::files::mime.like(format!("%/{}", escaped))),
+            );
+        }
+
+        if let Some((min_size, max_size)) = self.filter_size {
+            query = query.filter(schema::files::size.between(min_size as i64, max_size as i64));
+        }
+
+        if let Some(filter_hash) = self.filter_hash {
+            query = query.filter(schema::files::hash.eq(filter_hash as i64));
+        }
+
+        if let Some((start, end)) = self.filter_uploaded_at {
+            query = query.filter(schema::files::uploaded_at.between(start, end));
+        }
+
+        if let Some(filter_metadata) = self.filter_metadata {
+            for (key, range) in [
+                ("width", filter_metadata.width),
+                ("height", filter_metadata.height),
+            ] {
+                if let Some((min, max)) = range {
+                    query = query.filter(
+                        sql::<Bool>(&format!(
+                            "(files.file_metadata->>'{}')::BIGINT BETWEEN ",
+                            key
+                        ))
+                        .bind::<BigInt, _>(min as i64)
+                        .sql(" AND ")
+                        .bind::<BigInt, _>(max as i64),
+                    );
+                }
+            }
+
+            if let Some((min, max)) = filter_metadata.duration {
+                query = query.filter(
+                    sql::<Bool>("(files.file_metadata->>'duration')::DOUBLE PRECISION BETWEEN ")
+                        .bind::<Double, _>(min)
+                        .sql(" AND ")
+                        .bind::<Double, _>(max),
+                );
+            }
+
+            if let Some((start, end)) = filter_metadata.taken_at {
+                query = query.filter(
+                    sql::<Bool>("(files.file_metadata->>'taken_at')::TIMESTAMP BETWEEN ")
+                        .bind::<Timestamp, _>(start)
+                        .sql(" AND ")
+                        .bind::<Timestamp, _>(end),
+                );
+            }
+        }
+
+        for filter_tag in self.filter_tags {
+            query = match &filter_tag.ns {
+                Some(ns) => query.filter(
+                    schema::files::id.eq_any(
+                        schema::tags::table
+                            .filter(schema::tags::namespace.eq(ns.clone()))
+                            .filter(schema::tags::name.eq(filter_tag.value.clone()))
+                            .select(schema::tags::file_id),
+                    ),
+                ),
+                None => query.filter(
+                    schema::files::id.eq_any(
+                        schema::tags::table
+                            .filter(schema::tags::name.eq(filter_tag.value.clone()))
+                            .select(schema::tags::file_id),
+                    ),
+                ),
+            };
+        }
+
+        for (key, value) in self.filter_attributes.into_iter().flatten() {
+            query = query.filter(
+                schema::files::id.eq_any(
+                    schema::file_attributes::table
+                        .filter(schema::file_attributes::key.eq(key.clone()))
+                        .filter(schema::file_attributes::value.eq(value.clone()))
+                        .select(schema::file_attributes::file_id),
+                ),
+            );
+        }
+
+        query
+    }
+
+    /// Loads the files from `cursor` with a limit of `limit + 1`.
+    /// The query is measured with `read_pool`, so that it is logged if it is slow.
+    async fn load_files(
+        &self,
+        read_pool: &ReadPool,
+        db: &mut AsyncPgConnection,
+        cursor: Option<SearchCursor>,
+        limit: u32,
+    ) -> Result<Vec<File>, SearchServiceError> {
+        use crate::db::schema;
+
+        let mut query = self
+            .apply(schema::files::table.into_boxed())
+            .select((
+                schema::files::id,
+                schema::files::name,
+                schema::files::mime,
+                schema::files::size,
+                schema::files::hash,
+                schema::files::uploaded_at,
+            ))
+            .offset(cursor.map_or(0, |cursor| cursor.offset() as i64))
+            .limit(limit as i64 + 1);
+
+        let q = self.q.trim();
+
+        query = if q.is_empty() {
+            query.order((schema::files::uploaded_at.desc(), schema::files::id.asc()))
+        } else {
+            query
+                .order(
+                    sql::<Float>("ts_rank(files.search_vector, plainto_tsquery('simple', ")
+                        .bind::<Text, _>(q.to_owned())
+                        .sql(")) + similarity(files.name, ")
+                        .bind::<Text, _>(q.to_owned())
+                        .sql(")")
+                        .desc(),
+                )
+                .then_order_by(schema::files::id.asc())
+        };
+
+        let files = read_pool
+            .measure("search_files", query, |query| query.load::<File>(db))
+            .await?;
+
+        Ok(files)
+    }
+
+    async fn count_facets(
+        &self,
+        db: &mut AsyncPgConnection,
+    ) -> Result<FileFacets, SearchServiceError> {
+        use crate::db::schema;
+
+        let mut facets = FileFacets::default();
+
+        let mimes = schema::files::table
+            .filter(
+                schema::files::id.eq_any(
+                    self.apply(schema::files::table.into_boxed())
+                        .select(schema::files::id),
+                ),
+            )
+            .group_by(schema::files::mime)
+            .select((schema::files::mime, diesel::dsl::count_star()))
+            .load::<(String, i64)>(db)
+            .await?;
+
+        for (mime, count) in mimes {
+            let mime_type_part = match mime.trim().split_once('/') {
+                Some((type_part, _)) => type_part.to_owned(),
+                None => mime,
+            };
+            *facets.mime_type_part.entry(mime_type_part).or_default() += count as usize;
+        }
+
+        let tags = schema::tags::table
+            .filter(
+                schema::tags::file_id.eq_any(
+                    self.apply(schema::files::table.into_boxed())
+                        .select(schema::files::id),
+                ),
+            )
+            .group_by((schema::tags::namespace, schema::tags::name))
+            .select((
+                schema::tags::namespace,
+                schema::tags::name,
+                diesel::dsl::count_star(),
+            ))
+            .load::<(String, String, i64)>(db)
+            .await?;
+
+        for (namespace, name, count) in tags {
+            let tag = if namespace.is_empty() {
+                name
+            } else {
+                format!("{}:{}", namespace, name)
+            };
+            facets.tags.insert(tag, count as usize);
+        }
+
+        let mut lower_bound = 0;
+
+        for (upper_bound, name) in SIZE_BUCKETS
+            .iter()
+            .copied()
+            .map(|(upper_bound, name)| (Some(upper_bound), name))
+            .chain([(None, LARGEST_SIZE_BUCKET)])
+        {
+            let mut query = self
+                .apply(schema::files::table.into_boxed())
+                .filter(schema::files::size.ge(lower_bound));
+
+            if let Some(upper_bound) = upper_bound {
+                query = query.filter(schema::files::size.lt(upper_bound));
+                lower_bound = upper_bound;
+            }
+
+            let count = query.count().get_result::<i64>(db).await?;
+
+            if count != 0 {
+                facets.size_bucket.insert(name.to_owned(), count as usize);
+            }
+        }
+
+        Ok(facets)
+    }
+}
+
+/// Searches the database itself, with full-text search on names and extracted text, and trigram matching on names.
+/// The database is the index, so only the extracted text has to be kept in sync.
+/// The relevance settings are kept, but have no effect.
+/// Searches run on the read replicas, if any.
+pub struct PostgresSearchService {
+    db_pool: Pool<AsyncPgConnection>,
+    read_pool: ReadPool,
+    settings: RwLock<AppSearch>,
+}
+
+impl PostgresSearchService {
+    pub fn new(
+        db_pool: Pool<AsyncPgConnection>,
+        read_pool: ReadPool,
+        settings: &AppSearch,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            db_pool,
+            read_pool,
+            settings: RwLock::new(settings.clone()),
+        })
+    }
+}
+
+#[async_trait]
+impl SearchService for PostgresSearchService {
+    fn settings(&self) -> AppSearch {
+        self.settings.read().unwrap().clone()
+    }
+
+    async fn update_settings(&self, settings: &AppSearch) -> Result<(), SearchServiceError> {
+        let mut settings = settings.clone();
+        settings.backend = self.settings.read().unwrap().backend;
+
+        *self.settings.write().unwrap() = settings;
+
+        Ok(())
+    }
+
+    async fn index_collection(&self, _collection: &Collection) -> Result<(), SearchServiceError> {
+        Ok(())
+    }
+
+    async fn remove_collection_by_id(
+        &self,
+        _collection_id: Uuid,
+    ) -> Result<(), SearchServiceError> {
+        Ok(())
+    }
+
+    async fn search_collections(
+        &self,
+        q: &str,
+        cursor: Option<SearchCursor>,
+        limit: u32,
+    ) -> Result<Page<Collection, SearchCursor>, SearchServiceError> {
+        use crate::db::schema;
+
+        let q = q.trim();
+
+        let db = &mut self.read_pool.get().await?;
+        let mut query = schema::collections::table
+            .select((
+                schema::collections::id,
+                schema::collections::name,
+                schema::collections::description,
+                schema::collections::created_at,
+                schema::collections::version,
+            ))
+            .offset(cursor.map_or(0, |cursor| cursor.offset() as i64))
+            .limit(limit as i64 + 1)
+            .into_boxed();
+
+        query = if q.is_empty() {
+            query.order(schema::collections::id.asc())
+        } else {
+            query
+                .filter(
+                    schema::collections::name
+                        .ilike(format!("%{}%", escape_like(q)))
+                        .or(sql::<Bool>("collections.name % ").bind::<Text, _>(q.to_owned()))
+                        .or(
+                            sql::<Bool>("collections.search_vector @@ plainto_tsquery('simple', ")
+                                .bind::<Text, _>(q.to_owned())
+                                .sql(")"),
+                        ),
+                )
+                .order(
+                    sql::<Float>("ts_rank(collections.search_vector, plainto_tsquery('simple', ")
+                        .bind::<Text, _>(q.to_owned())
+                        .sql(")) + similarity(collections.name, ")
+                        .bind::<Text, _>(q.to_owned())
+                        .sql(")")
+                        .desc(),
+                )
+                .then_order_by(schema::collections::id.asc())
+        };
+
+        let collections = self
+            .read_pool
+            .measure("search_collections", query, |query| {
+                query.load::<Collection>(db)
+            })
+            .await?;
+
+        Ok(SearchCursor::make_page(cursor, collections, limit))
+    }
+
+    async fn index_file(
+        &self,
+        file: &File,
+        _metadata: Option<&FileMetadata>,
+        content: Option<&str>,
+    ) -> Result<(), SearchServiceError> {
+        use crate::db::schema;
+
+        let content = match content {
+            Some(content) => content,
+            None => return Ok(()),
+        };
+
+        let db = &mut self.db_pool.get().await?;
+        diesel::update(schema::files::table.filter(schema::files::id.eq(file.id)))
+            .set(schema::files::search_content.eq(content))
+            .execute(db)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn index_file_tags(
+        &self,
+        _file_id: Uuid,
+        _tags: &[Tag],
+    ) -> Result<(), SearchServiceError> {
+        Ok(())
+    }
+
+    async fn index_file_attributes(
+        &self,
+        _file_id: Uuid,
+        _attributes: &BTreeMap<String, String>,
+    ) -> Result<(), SearchServiceError> {
+        Ok(())
+    }
+
+    async fn remove_file_by_id(&self, _file_id: Uuid) -> Result<(), SearchServiceError> {
+        Ok(())
+    }
+
+    async fn search_files(
+        &self,
+        q: &str,
+        filter_mime: Option<&str>,
+        filter_size: Option<(u32, u32)>,
+        filter_hash: Option<u32>,
+        filter_uploaded_at: Option<(NaiveDateTime, NaiveDateTime)>,
+        filter_metadata: &FileMetadataFilter,
+        filter_tags: &[TagFilter],
+        filter_attributes: &BTreeMap<String, String>,
+        cursor: Option<SearchCursor>,
+        limit: u32,
+    ) -> Result<SearchedFiles, SearchServiceError> {
+        let search = FileSearch {
+            collection_id: None,
+            q,
+            filter_mime,
+            filter_size,
+            filter_hash,
+            filter_uploaded_at,
+            filter_metadata: Some(filter_metadata),
+            filter_tags,
+            filter_attributes: Some(filter_attributes),
+        };
+
+        let db = &mut self.read_pool.get().await?;
+        let files = search
+            .load_files(&self.read_pool, db, cursor, limit)
+            .await?;
+        let facets = search.count_facets(db).await?;
+
+        Ok(SearchedFiles {
+            files: SearchCursor::make_page(cursor, files, limit),
+            facets,
+        })
+    }
+
+    async fn index_collection_file(
+        &self,
+        _collection_id: Uuid,
+        _file: &File,
+    ) -> Result<(), SearchServiceError> {
+        Ok(())
+    }
+
+    async fn remove_collection_file(
+        &self,
+        _collection_id: Uuid,
+        _file_id: Uuid,
+    ) -> Result<(), SearchServiceError> {
+        Ok(())
+    }
+
+    async fn search_collection_files(
+        &self,
+        collection_id: Uuid,
+        q: &str,
+        filter_mime: Option<&str>,
+        filter_size: Option<(u32, u32)>,
+        filter_hash: Option<u32>,
+        filter_uploaded_at: Option<(NaiveDateTime, NaiveDateTime)>,
+        cursor: Option<SearchCursor>,
+        limit: u32,
+    ) -> Result<Page<File, SearchCursor>, SearchServiceError> {
+        let search = FileSearch {
+            collection_id: Some(collection_id),
+            q,
+            filter_mime,
+            filter_size,
+            filter_hash,
+            filter_uploaded_at,
+            ..Default::default()
+        };
+
+        let db = &mut self.read_pool.get().await?;
+        let files = search
+            .load_files(&self.read_pool, db, cursor, limit)
+            .await?;
+
+        Ok(SearchCursor::make_page(cursor, files, limit))
+    }
+}
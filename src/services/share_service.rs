@@ -0,0 +1,159 @@
+use super::{IdService, PasswordService};
+use crate::db::models::{CreatingShare, Share};
+use chrono::{NaiveDateTime, Utc};
+use diesel::{BoolExpressionMethods, ExpressionMethods, OptionalExtension, QueryDsl};
+use diesel_async::{pooled_connection::deadpool::Pool, AsyncPgConnection, RunQueryDsl};
+use std::sync::Arc;
+use thiserror::Error;
+use uuid::Uuid;
+
+#[derive(Error, Debug)]
+pub enum ShareServiceError {
+    #[error("database pool error: {0}")]
+    Pool(#[from] diesel_async::pooled_connection::deadpool::PoolError),
+    #[error("diesel error: {0}")]
+    Diesel(#[from] diesel::result::Error),
+}
+
+/// The resource a share grants read access to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShareTarget {
+    File(Uuid),
+    Collection(Uuid),
+}
+
+pub struct ShareService {
+    db_pool: Pool<AsyncPgConnection>,
+    id_service: Arc<IdService>,
+    password_service: Arc<PasswordService>,
+}
+
+impl ShareService {
+    pub fn new(
+        db_pool: Pool<AsyncPgConnection>,
+        id_service: Arc<IdService>,
+        password_service: Arc<PasswordService>,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            db_pool,
+            id_service,
+            password_service,
+        })
+    }
+
+    /// Creates a share of the given file or collection on behalf of the given user.
+    /// The share never expires if `expires_at` is `None`.
+    /// Returns `None` if the file or collection does not exist.
+    pub async fn create_share(
+        &self,
+        user_id: i32,
+        target: ShareTarget,
+        expires_at: Option<NaiveDateTime>,
+    ) -> Result<Option<Share>, ShareServiceError> {
+        use crate::db::schema;
+
+        let (file_id, collection_id) = match target {
+            ShareTarget::File(file_id) => (Some(file_id), None),
+            ShareTarget::Collection(collection_id) => (None, Some(collection_id)),
+        };
+        let token = self.password_service.generate_url_safe_token_252();
+
+        let db = &mut self.db_pool.get().await?;
+        let share = diesel::insert_into(schema::shares::table)
+            .values(CreatingShare {
+                id: self.id_service.generate(),
+                token: &token,
+                user_id,
+                file_id,
+                collection_id,
+                expires_at,
+            })
+            .returning((
+                schema::shares::id,
+                schema::shares::token,
+                schema::shares::user_id,
+                schema::shares::file_id,
+                schema::shares::collection_id,
+                schema::shares::created_at,
+                schema::shares::expires_at,
+            ))
+            .get_result::<Share>(db)
+            .await;
+
+        match share {
+            Ok(share) => Ok(Some(share)),
+            Err(diesel::result::Error::DatabaseError(
+                diesel::result::DatabaseErrorKind::ForeignKeyViolation,
+                err,
+            )) if err.constraint_name() == Some("shares_file_fk")
+                || err.constraint_name() == Some("shares_collection_fk") =>
+            {
+                Ok(None)
+            }
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Removes a share created by the given user.
+    /// Returns the share that was removed, or `None` if the user has no share with the ID.
+    pub async fn remove_share(
+        &self,
+        share_id: Uuid,
+        user_id: i32,
+    ) -> Result<Option<Share>, ShareServiceError> {
+        use crate::db::schema;
+
+        let db = &mut self.db_pool.get().await?;
+        let share = diesel::delete(
+            schema::shares::table
+                .filter(schema::shares::id.eq(share_id))
+                .filter(schema::shares::user_id.eq(user_id)),
+        )
+        .returning((
+            schema::shares::id,
+            schema::shares::token,
+            schema::shares::user_id,
+            schema::shares::file_id,
+            schema::shares::collection_id,
+            schema::shares::created_at,
+            schema::shares::expires_at,
+        ))
+        .get_result::<Share>(db)
+        .await
+        .optional()?;
+
+        Ok(share)
+    }
+
+    /// Retrieves a share by its token.
+    /// Returns `None` if the share does not exist or has expired.
+    pub async fn get_share_by_token(
+        &self,
+        token: &str,
+    ) -> Result<Option<Share>, ShareServiceError> {
+        use crate::db::schema;
+
+        let db = &mut self.db_pool.get().await?;
+        let share = schema::shares::table
+            .filter(schema::shares::token.eq(token))
+            .filter(
+                schema::shares::expires_at
+                    .is_null()
+                    .or(schema::shares::expires_at.gt(Utc::now().naive_utc())),
+            )
+            .select((
+                schema::shares::id,
+                schema::shares::token,
+                schema::shares::user_id,
+                schema::shares::file_id,
+                schema::shares::collection_id,
+                schema::shares::created_at,
+                schema::shares::expires_at,
+            ))
+            .get_result::<Share>(db)
+            .await
+            .optional()?;
+
+        Ok(share)
+    }
+}
@@ -1,15 +1,19 @@
-use super::{FileDriver, WriteError};
-use crate::db::models::{CreatingStagingFile, StagingFile, UpdatingStagingFile};
+use super::{
+    FileDriver, IdService, PresignedUpload, UnitOfWork, UnitOfWorkConnection, WriteError,
+    WriteStream,
+};
+use crate::db::models::{
+    CreatingStagingFile, CreatingStagingFileChunk, StagingFile, UpdatingStagingFile,
+};
 use chrono::{Duration, Utc};
-use diesel::{ExpressionMethods, OptionalExtension, QueryDsl};
+use diesel::{BoolExpressionMethods, ExpressionMethods, OptionalExtension, QueryDsl};
 use diesel_async::{
     pooled_connection::deadpool::Pool, scoped_futures::ScopedFutureExt, AsyncConnection,
     AsyncPgConnection, RunQueryDsl,
 };
-use rocket::data::DataStream;
-use std::sync::Arc;
+use std::{collections::HashSet, sync::Arc};
 use thiserror::Error;
-use tokio::task::JoinSet;
+use tokio::{io::AsyncReadExt, task::JoinSet};
 use uuid::Uuid;
 
 #[derive(Debug)]
@@ -24,38 +28,94 @@ pub enum StagingFileServiceError {
     PoolError(#[from] diesel_async::pooled_connection::deadpool::PoolError),
     #[error("diesel error: {0}")]
     DieselError(#[from] diesel::result::Error),
+    #[error("io error: {0}")]
+    IO(#[from] std::io::Error),
+}
+
+#[derive(Error, Debug)]
+pub enum ChunkWriteError {
+    /// Chunks can only be written to staging files with an expected size, since it tells when the file is complete.
+    #[error("staging file has no expected size")]
+    NoExpectedSize,
+    /// The chunk does not fit in the expected size of the staging file.
+    #[error("chunk at {offset} of size {size} exceeds the expected file size {expected_size}")]
+    OutOfBounds {
+        offset: u64,
+        size: u64,
+        expected_size: u64,
+    },
+    /// The chunk overlaps with another chunk that has been or is being written.
+    #[error("chunk overlaps with another chunk")]
+    Overlap,
+    /// The stream ended before the whole chunk was written.
+    #[error("chunk is incomplete: {written} of {size} bytes written")]
+    Incomplete { size: u64, written: u64 },
+    #[error("{0}")]
+    Write(#[from] WriteError),
+}
+
+#[derive(Error, Debug)]
+pub enum DirectUploadError {
+    /// The storage system cannot be uploaded to directly, e.g. if it keeps files on the local disk.
+    #[error("storage does not support direct uploads")]
+    Unsupported,
+    /// Nothing has been uploaded by the presigned request yet.
+    #[error("no data has been uploaded")]
+    NotUploaded,
+    /// The uploaded data does not have the expected size of the staging file.
+    #[error("uploaded data of size {size} does not match the expected file size {expected_size}")]
+    SizeMismatch { expected_size: u64, size: u64 },
+}
+
+/// The result of writing a chunk of a staging file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StagingFileChunkWrite {
+    pub staging_file: StagingFile,
+    /// Whether the written chunks cover the whole expected size of the staging file.
+    pub is_complete: bool,
 }
 
 pub struct StagingFileService {
     db_pool: Pool<AsyncPgConnection>,
+    id_service: Arc<IdService>,
     file_driver: Arc<dyn FileDriver + Send + Sync>,
 }
 
 impl StagingFileService {
     pub fn new(
         db_pool: Pool<AsyncPgConnection>,
-        file_driver: Arc<impl 'static + FileDriver + Send + Sync>,
+        id_service: Arc<IdService>,
+        file_driver: Arc<dyn FileDriver + Send + Sync>,
     ) -> Arc<Self> {
         Arc::new(Self {
             db_pool,
+            id_service,
             file_driver,
         })
     }
 
     /// Creates a new staging file.
+    /// The file promoted from it inherits its ID.
+    /// If `expected_size` is given, writes beyond it are rejected.
+    /// If `expected_hash` is given, the staging file cannot be promoted unless its content matches it.
     pub async fn create_staging_file(
         &self,
         name: &str,
         mime: Option<&str>,
+        expected_size: Option<u64>,
+        expected_hash: Option<u32>,
     ) -> Result<StagingFile, StagingFileServiceError> {
         use crate::db::schema;
 
         let db = &mut self.db_pool.get().await?;
         let staging_file = diesel::insert_into(schema::staging_files::table)
             .values(CreatingStagingFile {
+                id: self.id_service.generate(),
                 name,
                 mime,
                 size: 0,
+                expected_size: expected_size.map(|size| size as i64),
+                expected_hash: expected_hash.map(|hash| hash as i64),
             })
             .returning((
                 schema::staging_files::id,
@@ -63,6 +123,8 @@ impl StagingFileService {
                 schema::staging_files::mime,
                 schema::staging_files::size,
                 schema::staging_files::staged_at,
+                schema::staging_files::expected_size,
+                schema::staging_files::expected_hash,
             ))
             .get_result::<StagingFile>(db)
             .await?;
@@ -72,24 +134,18 @@ impl StagingFileService {
 
     /// Removes a staging file by its ID.
     /// Returns the staging file that was removed, or `None` if no staging file was found.
-    /// The `db` parameter is a mutable reference to a database connection.
-    /// This allows the caller to pass in a transaction, if needed.
+    /// If `uow` is given, the staging file is removed in it, and its data is removed once it commits.
     pub async fn remove_staging_file_by_id(
         &self,
         staging_file_id: Uuid,
-        db: Option<&mut AsyncPgConnection>,
+        mut uow: Option<&mut UnitOfWork>,
         delete_data: bool,
     ) -> Result<Option<StagingFile>, StagingFileServiceError> {
         use crate::db::schema;
 
-        let mut fallback_db = match db {
-            Some(_) => None,
-            None => Some(self.db_pool.get().await?),
-        };
-        let db = match db {
-            Some(db) => db,
-            None => fallback_db.as_mut().unwrap(),
-        };
+        let mut db =
+            UnitOfWorkConnection::acquire(&self.db_pool, uow.as_mut().map(|uow| uow.db())).await?;
+        let db = &mut *db;
         let staging_file = diesel::delete(
             schema::staging_files::dsl::staging_files
                 .filter(schema::staging_files::id.eq(staging_file_id)),
@@ -100,14 +156,24 @@ impl StagingFileService {
             schema::staging_files::mime,
             schema::staging_files::size,
             schema::staging_files::staged_at,
+            schema::staging_files::expected_size,
+            schema::staging_files::expected_hash,
         ))
         .get_result::<StagingFile>(db)
         .await
         .optional()?;
 
         if staging_file.is_some() && delete_data {
-            // it is safe to ignore the result of this operation
-            self.file_driver.remove_staging(staging_file_id).await.ok();
+            let file_driver = self.file_driver.clone();
+            let removal = async move {
+                // it is safe to ignore the result of this operation
+                file_driver.remove_staging(staging_file_id).await.ok();
+            };
+
+            match uow {
+                Some(uow) => uow.after_commit(removal),
+                None => removal.await,
+            }
         }
 
         Ok(staging_file)
@@ -163,7 +229,7 @@ impl StagingFileService {
         for staging_file_id in &expired_staging_files {
             let file_driver = self.file_driver.clone();
             let task = remove_staging_file(file_driver, *staging_file_id);
-            removal_tasks.spawn_local(task);
+            removal_tasks.spawn(task);
         }
 
         let mut removal_errors = Vec::with_capacity(expired_staging_files.len());
@@ -186,6 +252,62 @@ impl StagingFileService {
         Ok((expired_staging_files.len(), removal_errors))
     }
 
+    /// Removes the staging files kept in the storage system without a staging file row or a pending commit, e.g. written before a crash.
+    /// Only the ones last written more than `duration` ago are removed, so that files being staged or committed are left alone.
+    /// Returns the number of staging files that were removed.
+    pub async fn remove_orphaned_staging_files(
+        &self,
+        duration: Duration,
+    ) -> Result<(usize, Vec<ExpiredStagingFileRemovalError>), StagingFileServiceError> {
+        use crate::db::schema;
+
+        let expiration_time = Utc::now() - duration;
+        let candidate_ids = self
+            .file_driver
+            .list_staging()
+            .await?
+            .into_iter()
+            .filter(|entry| chrono::DateTime::<Utc>::from(entry.modified_at) < expiration_time)
+            .map(|entry| entry.id)
+            .collect::<Vec<_>>();
+
+        let db = &mut self.db_pool.get().await?;
+        let mut known_ids = HashSet::with_capacity(candidate_ids.len());
+
+        // keep the number of bind parameters of a query bounded
+        for ids in candidate_ids.chunks(1000) {
+            let staging_file_ids = schema::staging_files::table
+                .filter(schema::staging_files::id.eq_any(ids))
+                .select(schema::staging_files::id)
+                .load::<Uuid>(db)
+                .await?;
+            // files whose commit has not completed yet still keep their data in the staging area
+            let pending_file_ids = schema::pending_commits::table
+                .filter(schema::pending_commits::file_id.eq_any(ids))
+                .select(schema::pending_commits::file_id)
+                .load::<Uuid>(db)
+                .await?;
+            known_ids.extend(staging_file_ids);
+            known_ids.extend(pending_file_ids);
+        }
+
+        let mut removed_count = 0;
+        let mut removal_errors = Vec::new();
+
+        for id in candidate_ids {
+            if known_ids.contains(&id) {
+                continue;
+            }
+
+            match self.file_driver.remove_staging(id).await {
+                Ok(()) => removed_count += 1,
+                Err(err) => removal_errors.push(ExpiredStagingFileRemovalError { id, error: err }),
+            }
+        }
+
+        Ok((removed_count, removal_errors))
+    }
+
     /// Retrieves a staging file by its ID.
     pub async fn get_staging_file_by_id(
         &self,
@@ -202,6 +324,8 @@ impl StagingFileService {
                 schema::staging_files::mime,
                 schema::staging_files::size,
                 schema::staging_files::staged_at,
+                schema::staging_files::expected_size,
+                schema::staging_files::expected_hash,
             ))
             .get_result::<StagingFile>(db)
             .await
@@ -235,6 +359,8 @@ impl StagingFileService {
             schema::staging_files::mime,
             schema::staging_files::size,
             schema::staging_files::staged_at,
+            schema::staging_files::expected_size,
+            schema::staging_files::expected_hash,
         ))
         .get_result::<StagingFile>(db)
         .await
@@ -246,35 +372,70 @@ impl StagingFileService {
     /// Fills a staging file by its ID.
     /// Returns the updated staging file, or `None` if no staging file was found.
     /// It will lock the staging file for writing, so that no other operation can write to it at the same time.
+    /// If the staging file has an expected size, only the data up to it is written,
+    /// and [`WriteError::ExceedsExpectedSize`] is returned if the stream has more.
     pub async fn fill_staging_file_by_id(
         &self,
         staging_file_id: Uuid,
         offset: Option<u64>,
-        stream: DataStream<'_>,
+        mut stream: WriteStream<'_>,
     ) -> Result<Result<Option<StagingFile>, WriteError>, StagingFileServiceError> {
         use crate::db::schema;
 
         let db = &mut self.db_pool.get().await?;
         db.transaction(|db| {
             async move {
-                let staging_file_id = schema::staging_files::dsl::staging_files
+                let staging_file = schema::staging_files::dsl::staging_files
                     .filter(schema::staging_files::id.eq(staging_file_id))
-                    .select(schema::staging_files::id)
+                    .select((
+                        schema::staging_files::id,
+                        schema::staging_files::expected_size,
+                    ))
                     .for_update()
-                    .get_result::<Uuid>(db)
+                    .get_result::<(Uuid, Option<i64>)>(db)
                     .await
                     .optional()?;
-                let staging_file_id = match staging_file_id {
-                    Some(staging_file_id) => staging_file_id,
+                let (staging_file_id, expected_size) = match staging_file {
+                    Some(staging_file) => staging_file,
                     None => {
                         return Ok(Ok(None));
                     }
                 };
 
-                let result = self
-                    .file_driver
-                    .write_staging(staging_file_id, offset.unwrap_or(0), stream)
-                    .await;
+                let offset = offset.unwrap_or(0);
+                let mut exceeded_size = None;
+                let result = match expected_size {
+                    Some(expected_size) => {
+                        let expected_size = expected_size as u64;
+
+                        if expected_size < offset {
+                            return Ok(Err(WriteError::ExceedsExpectedSize { expected_size }));
+                        }
+
+                        let result = self
+                            .file_driver
+                            .write_staging(
+                                staging_file_id,
+                                offset,
+                                Box::pin((&mut stream).take(expected_size - offset)),
+                            )
+                            .await;
+
+                        // anything left in the stream would go past the expected size
+                        let mut rest = [0u8; 1];
+
+                        if matches!(stream.read(&mut rest).await, Ok(read) if read != 0) {
+                            exceeded_size = Some(expected_size);
+                        }
+
+                        result
+                    }
+                    None => {
+                        self.file_driver
+                            .write_staging(staging_file_id, offset, stream)
+                            .await
+                    }
+                };
                 let size = match result {
                     Ok(size) => size,
                     Err(err) => {
@@ -293,14 +454,257 @@ impl StagingFileService {
                     schema::staging_files::mime,
                     schema::staging_files::size,
                     schema::staging_files::staged_at,
+                    schema::staging_files::expected_size,
+                    schema::staging_files::expected_hash,
                 ))
                 .get_result::<StagingFile>(db)
                 .await?;
 
+                // the data up to the expected size is kept, so that the upload can still be completed
+                if let Some(expected_size) = exceeded_size {
+                    return Ok(Err(WriteError::ExceedsExpectedSize { expected_size }));
+                }
+
                 Ok(Ok(Some(staging_file)))
             }
             .scope_boxed()
         })
         .await
     }
+
+    /// Presigns a request to upload the data of a staging file directly to the storage system, bypassing the server.
+    /// Once the data is uploaded, [`StagingFileService::complete_direct_upload_by_id`] has to be called to take it in.
+    /// Returns `None` if no staging file was found.
+    pub async fn presign_direct_upload_by_id(
+        &self,
+        staging_file_id: Uuid,
+        expires_in: std::time::Duration,
+    ) -> Result<Result<Option<PresignedUpload>, DirectUploadError>, StagingFileServiceError> {
+        use crate::db::schema;
+
+        if !self.file_driver.supports_direct_upload() {
+            return Ok(Err(DirectUploadError::Unsupported));
+        }
+
+        let db = &mut self.db_pool.get().await?;
+        let expected_size = schema::staging_files::dsl::staging_files
+            .filter(schema::staging_files::id.eq(staging_file_id))
+            .select(schema::staging_files::expected_size)
+            .get_result::<Option<i64>>(db)
+            .await
+            .optional()?;
+        let expected_size = match expected_size {
+            Some(expected_size) => expected_size,
+            None => {
+                return Ok(Ok(None));
+            }
+        };
+
+        let presigned_upload = self
+            .file_driver
+            .presign_staging_upload(
+                staging_file_id,
+                expected_size.map(|size| size as u64),
+                expires_in,
+            )
+            .await?;
+
+        Ok(Ok(Some(presigned_upload)))
+    }
+
+    /// Takes in the data uploaded directly to the storage system, and updates the size of the staging file to match it.
+    /// The staging file can then be promoted as if the data had been written through the server.
+    /// Returns the updated staging file, or `None` if no staging file was found.
+    /// It will lock the staging file, so that no other operation can write to it at the same time.
+    pub async fn complete_direct_upload_by_id(
+        &self,
+        staging_file_id: Uuid,
+    ) -> Result<Result<Option<StagingFile>, DirectUploadError>, StagingFileServiceError> {
+        use crate::db::schema;
+
+        if !self.file_driver.supports_direct_upload() {
+            return Ok(Err(DirectUploadError::Unsupported));
+        }
+
+        let db = &mut self.db_pool.get().await?;
+        db.transaction(|db| {
+            async move {
+                let expected_size = schema::staging_files::dsl::staging_files
+                    .filter(schema::staging_files::id.eq(staging_file_id))
+                    .select(schema::staging_files::expected_size)
+                    .for_update()
+                    .get_result::<Option<i64>>(db)
+                    .await
+                    .optional()?;
+                let expected_size = match expected_size {
+                    Some(expected_size) => expected_size,
+                    None => {
+                        return Ok(Ok(None));
+                    }
+                };
+
+                let size = match self
+                    .file_driver
+                    .fetch_staging_upload(staging_file_id)
+                    .await?
+                {
+                    Some(size) => size,
+                    None => {
+                        return Ok(Err(DirectUploadError::NotUploaded));
+                    }
+                };
+
+                if let Some(expected_size) = expected_size {
+                    if size != expected_size as u64 {
+                        return Ok(Err(DirectUploadError::SizeMismatch {
+                            expected_size: expected_size as u64,
+                            size,
+                        }));
+                    }
+                }
+
+                let staging_file = diesel::update(
+                    schema::staging_files::dsl::staging_files
+                        .filter(schema::staging_files::id.eq(staging_file_id)),
+                )
+                .set(schema::staging_files::size.eq(size as i64))
+                .returning((
+                    schema::staging_files::id,
+                    schema::staging_files::name,
+                    schema::staging_files::mime,
+                    schema::staging_files::size,
+                    schema::staging_files::staged_at,
+                    schema::staging_files::expected_size,
+                    schema::staging_files::expected_hash,
+                ))
+                .get_result::<StagingFile>(db)
+                .await?;
+
+                Ok(Ok(Some(staging_file)))
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+
+    /// Writes a chunk of `size` bytes into a staging file at `offset`.
+    /// Unlike [`StagingFileService::fill_staging_file_by_id`], it does not lock the staging file,
+    /// so that non-overlapping chunks can be written concurrently.
+    /// Each chunk is recorded before it is written, and overlapping chunks are rejected.
+    /// Returns `None` if no staging file was found.
+    pub async fn fill_staging_file_chunk_by_id(
+        &self,
+        staging_file_id: Uuid,
+        offset: u64,
+        size: u64,
+        stream: WriteStream<'_>,
+    ) -> Result<Result<Option<StagingFileChunkWrite>, ChunkWriteError>, StagingFileServiceError>
+    {
+        use crate::db::schema;
+
+        let db = &mut self.db_pool.get().await?;
+
+        let expected_size = schema::staging_files::dsl::staging_files
+            .filter(schema::staging_files::id.eq(staging_file_id))
+            .select(schema::staging_files::expected_size)
+            .get_result::<Option<i64>>(db)
+            .await
+            .optional()?;
+        let expected_size = match expected_size {
+            Some(Some(expected_size)) => expected_size as u64,
+            Some(None) => return Ok(Err(ChunkWriteError::NoExpectedSize)),
+            None => return Ok(Ok(None)),
+        };
+
+        if size == 0 || expected_size < offset.saturating_add(size) {
+            return Ok(Err(ChunkWriteError::OutOfBounds {
+                offset,
+                size,
+                expected_size,
+            }));
+        }
+
+        let result = diesel::insert_into(schema::staging_file_chunks::table)
+            .values(CreatingStagingFileChunk {
+                staging_file_id,
+                chunk_offset: offset as i64,
+                size: size as i64,
+            })
+            .execute(db)
+            .await;
+
+        match result {
+            Ok(_) => {}
+            Err(diesel::result::Error::DatabaseError(
+                diesel::result::DatabaseErrorKind::ForeignKeyViolation,
+                err,
+            )) if err.constraint_name() == Some("staging_file_chunks_staging_file_fk") => {
+                return Ok(Ok(None));
+            }
+            Err(diesel::result::Error::DatabaseError(_, err))
+                if err.constraint_name() == Some("staging_file_chunks_no_overlap")
+                    || err.constraint_name() == Some("staging_file_chunks_pkey") =>
+            {
+                return Ok(Err(ChunkWriteError::Overlap));
+            }
+            Err(err) => return Err(err.into()),
+        }
+
+        let chunk_filter = schema::staging_file_chunks::staging_file_id
+            .eq(staging_file_id)
+            .and(schema::staging_file_chunks::chunk_offset.eq(offset as i64));
+
+        let result = self
+            .file_driver
+            .write_staging_at(staging_file_id, offset, Box::pin(stream.take(size)))
+            .await;
+        let error = match result {
+            Ok(written) if written == size => None,
+            Ok(written) => Some(ChunkWriteError::Incomplete { size, written }),
+            Err(err) => Some(ChunkWriteError::Write(err)),
+        };
+
+        if let Some(error) = error {
+            // release the range, so that the chunk can be retried
+            diesel::delete(schema::staging_file_chunks::table.filter(chunk_filter))
+                .execute(db)
+                .await?;
+            return Ok(Err(error));
+        }
+
+        diesel::update(schema::staging_file_chunks::table.filter(chunk_filter))
+            .set(schema::staging_file_chunks::completed_at.eq(Utc::now().naive_utc()))
+            .execute(db)
+            .await?;
+
+        let end = (offset + size) as i64;
+        diesel::update(
+            schema::staging_files::dsl::staging_files
+                .filter(schema::staging_files::id.eq(staging_file_id))
+                .filter(schema::staging_files::size.lt(end)),
+        )
+        .set(schema::staging_files::size.eq(end))
+        .execute(db)
+        .await?;
+
+        let staging_file = self.get_staging_file_by_id(staging_file_id).await?;
+        let staging_file = match staging_file {
+            Some(staging_file) => staging_file,
+            None => return Ok(Ok(None)),
+        };
+
+        let written_size = schema::staging_file_chunks::table
+            .filter(schema::staging_file_chunks::staging_file_id.eq(staging_file_id))
+            .filter(schema::staging_file_chunks::completed_at.is_not_null())
+            .select(schema::staging_file_chunks::size)
+            .load::<i64>(db)
+            .await?
+            .into_iter()
+            .sum::<i64>();
+
+        Ok(Ok(Some(StagingFileChunkWrite {
+            staging_file,
+            is_complete: written_size as u64 == expected_size,
+        })))
+    }
 }
@@ -0,0 +1,221 @@
+use crate::db::models::{CreatingStatsSnapshot, StatsSnapshot};
+use chrono::{NaiveDate, Utc};
+use diesel::{sql_types::BigInt, upsert::excluded, ExpressionMethods, QueryDsl};
+use diesel_async::{pooled_connection::deadpool::Pool, AsyncPgConnection, RunQueryDsl};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum StatsServiceError {
+    #[error("database pool error: {0}")]
+    Pool(#[from] diesel_async::pooled_connection::deadpool::PoolError),
+    #[error("diesel error: {0}")]
+    Diesel(#[from] diesel::result::Error),
+}
+
+/// A library-wide metric recorded in the statistics history.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum StatsMetric {
+    /// The total number of files.
+    Files,
+    /// The total size of all files in bytes.
+    Bytes,
+    /// The total number of users.
+    Users,
+    /// The number of distinct tag names.
+    Tags,
+}
+
+impl StatsMetric {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "files" => Some(Self::Files),
+            "bytes" => Some(Self::Bytes),
+            "users" => Some(Self::Users),
+            "tags" => Some(Self::Tags),
+            _ => None,
+        }
+    }
+}
+
+/// The current size of the library.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct StatsSummary {
+    pub file_count: i64,
+    /// The total size of all files in bytes.
+    pub byte_count: i64,
+    pub user_count: i64,
+    /// The number of distinct tag names.
+    pub tag_count: i64,
+    pub collection_count: i64,
+    /// The number of uploads that have not been promoted into files yet.
+    pub staging_file_count: i64,
+}
+
+/// The value of a metric on a day.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct StatsPoint {
+    pub date: NaiveDate,
+    pub value: i64,
+}
+
+pub struct StatsService {
+    db_pool: Pool<AsyncPgConnection>,
+}
+
+impl StatsService {
+    pub fn new(db_pool: Pool<AsyncPgConnection>) -> Arc<Self> {
+        Arc::new(Self { db_pool })
+    }
+
+    /// Counts the current size of the library, without recording it.
+    pub async fn get_summary(&self) -> Result<StatsSummary, StatsServiceError> {
+        use crate::db::schema;
+
+        let db = &mut self.db_pool.get().await?;
+        let (file_count, byte_count, user_count, tag_count) = count_metrics(db).await?;
+        let collection_count = schema::collections::table
+            .count()
+            .get_result::<i64>(db)
+            .await?;
+        let staging_file_count = schema::staging_files::table
+            .count()
+            .get_result::<i64>(db)
+            .await?;
+
+        Ok(StatsSummary {
+            file_count,
+            byte_count,
+            user_count,
+            tag_count,
+            collection_count,
+            staging_file_count,
+        })
+    }
+
+    /// Records a snapshot of the library-wide metrics for today.
+    /// Recording again on the same day overwrites the snapshot of the day,
+    /// so that the history keeps the latest values of each day.
+    pub async fn record_snapshot(&self) -> Result<StatsSnapshot, StatsServiceError> {
+        use crate::db::schema;
+
+        let db = &mut self.db_pool.get().await?;
+        let (file_count, byte_count, user_count, tag_count) = count_metrics(db).await?;
+
+        let snapshot = diesel::insert_into(schema::stats_history::table)
+            .values(CreatingStatsSnapshot {
+                date: Utc::now().date_naive(),
+                file_count,
+                byte_count,
+                user_count,
+                tag_count,
+            })
+            .on_conflict(schema::stats_history::date)
+            .do_update()
+            .set((
+                schema::stats_history::file_count.eq(excluded(schema::stats_history::file_count)),
+                schema::stats_history::byte_count.eq(excluded(schema::stats_history::byte_count)),
+                schema::stats_history::user_count.eq(excluded(schema::stats_history::user_count)),
+                schema::stats_history::tag_count.eq(excluded(schema::stats_history::tag_count)),
+                schema::stats_history::recorded_at.eq(diesel::dsl::now),
+            ))
+            .returning((
+                schema::stats_history::date,
+                schema::stats_history::file_count,
+                schema::stats_history::byte_count,
+                schema::stats_history::user_count,
+                schema::stats_history::tag_count,
+                schema::stats_history::recorded_at,
+            ))
+            .get_result::<StatsSnapshot>(db)
+            .await?;
+
+        Ok(snapshot)
+    }
+
+    /// Retrieves the history of a metric since the given date, in chronological order.
+    /// Days without a snapshot are omitted.
+    pub async fn get_history(
+        &self,
+        metric: StatsMetric,
+        since: NaiveDate,
+    ) -> Result<Vec<StatsPoint>, StatsServiceError> {
+        use crate::db::schema;
+
+        let db = &mut self.db_pool.get().await?;
+        let query = schema::stats_history::table
+            .filter(schema::stats_history::date.ge(since))
+            .order(schema::stats_history::date.asc());
+
+        let points = match metric {
+            StatsMetric::Files => {
+                query
+                    .select((
+                        schema::stats_history::date,
+                        schema::stats_history::file_count,
+                    ))
+                    .load::<(NaiveDate, i64)>(db)
+                    .await?
+            }
+            StatsMetric::Bytes => {
+                query
+                    .select((
+                        schema::stats_history::date,
+                        schema::stats_history::byte_count,
+                    ))
+                    .load::<(NaiveDate, i64)>(db)
+                    .await?
+            }
+            StatsMetric::Users => {
+                query
+                    .select((
+                        schema::stats_history::date,
+                        schema::stats_history::user_count,
+                    ))
+                    .load::<(NaiveDate, i64)>(db)
+                    .await?
+            }
+            StatsMetric::Tags => {
+                query
+                    .select((
+                        schema::stats_history::date,
+                        schema::stats_history::tag_count,
+                    ))
+                    .load::<(NaiveDate, i64)>(db)
+                    .await?
+            }
+        };
+
+        Ok(points
+            .into_iter()
+            .map(|(date, value)| StatsPoint { date, value })
+            .collect())
+    }
+}
+
+/// Counts the files, their total size, the users and the distinct tag names.
+async fn count_metrics(
+    db: &mut AsyncPgConnection,
+) -> Result<(i64, i64, i64, i64), StatsServiceError> {
+    use crate::db::schema;
+
+    let (file_count, byte_count) = schema::files::table
+        .select((
+            diesel::dsl::count_star(),
+            diesel::dsl::sql::<BigInt>("COALESCE(SUM(size), 0)::BIGINT"),
+        ))
+        .get_result::<(i64, i64)>(db)
+        .await?;
+    let user_count = schema::users::table.count().get_result::<i64>(db).await?;
+    let tag_count = schema::tags::table
+        .select(diesel::dsl::sql::<BigInt>(
+            "COUNT(DISTINCT (namespace, name))",
+        ))
+        .get_result::<i64>(db)
+        .await?;
+
+    Ok((file_count, byte_count, user_count, tag_count))
+}
@@ -0,0 +1,328 @@
+use super::{FileDriver, ReadError, ReadRange, WriteError};
+use diesel::QueryDsl;
+use diesel_async::{pooled_connection::deadpool::Pool, AsyncPgConnection, RunQueryDsl};
+use std::sync::Arc;
+use thiserror::Error;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use uuid::Uuid;
+
+const HASH_BUFFER_SIZE: usize = 4 * 1024 * 1024;
+
+#[derive(Error, Debug)]
+pub enum StorageMigrationServiceError {
+    #[error("database pool error: {0}")]
+    Pool(#[from] diesel_async::pooled_connection::deadpool::PoolError),
+    #[error("diesel error: {0}")]
+    Diesel(#[from] diesel::result::Error),
+    #[error("io error: {0}")]
+    IO(#[from] std::io::Error),
+    #[error("read error: {0}")]
+    Read(#[from] ReadError),
+    #[error("write error: {0}")]
+    Write(#[from] WriteError),
+    #[error("the staging file of blob `{0}` has vanished before it was committed")]
+    StagingFileVanished(Uuid),
+    #[error(
+        "the data of blob `{id}` does not match its recorded hash: `{actual}` != `{expected}`"
+    )]
+    HashMismatch {
+        id: Uuid,
+        expected: u32,
+        actual: u32,
+    },
+    #[error("the target storage holds {actual:?} bytes of blob `{id}`; expected {expected} bytes")]
+    SizeMismatch {
+        id: Uuid,
+        expected: u64,
+        actual: Option<u64>,
+    },
+}
+
+/// A blob to be moved, as recorded in the `files` or `file_versions` table.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MigratingBlob {
+    pub id: Uuid,
+    pub size: i64,
+    pub hash: i64,
+}
+
+/// What has been done to a blob during a migration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlobMigration {
+    /// The blob has been copied into the target storage and verified.
+    Copied,
+    /// The target storage already holds an intact copy, e.g. from an interrupted run.
+    AlreadyMigrated,
+    /// The source storage does not hold the blob, so there is nothing to copy.
+    MissingInSource,
+}
+
+/// Moves the blobs of files from one file driver to another, e.g. from the local disk to a remote storage.
+/// The blobs in the source storage are never modified, so the source stays usable until the switch-over.
+pub struct StorageMigrationService {
+    db_pool: Pool<AsyncPgConnection>,
+    source: Arc<dyn FileDriver + Send + Sync>,
+    target: Arc<dyn FileDriver + Send + Sync>,
+}
+
+impl StorageMigrationService {
+    pub fn new(
+        db_pool: Pool<AsyncPgConnection>,
+        source: Arc<dyn FileDriver + Send + Sync>,
+        target: Arc<dyn FileDriver + Send + Sync>,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            db_pool,
+            source,
+            target,
+        })
+    }
+
+    /// Lists the blobs to move, sorted by ID.
+    /// Former versions of files keep their data under their own IDs, so they are included as well.
+    pub async fn list_blobs(&self) -> Result<Vec<MigratingBlob>, StorageMigrationServiceError> {
+        use crate::db::schema;
+
+        let db = &mut self.db_pool.get().await?;
+        let files = schema::files::table
+            .select((schema::files::id, schema::files::size, schema::files::hash))
+            .load::<(Uuid, i64, i64)>(db)
+            .await?;
+        let file_versions = schema::file_versions::table
+            .select((
+                schema::file_versions::id,
+                schema::file_versions::size,
+                schema::file_versions::hash,
+            ))
+            .load::<(Uuid, i64, i64)>(db)
+            .await?;
+
+        let mut blobs = files
+            .into_iter()
+            .chain(file_versions)
+            .map(|(id, size, hash)| MigratingBlob { id, size, hash })
+            .collect::<Vec<_>>();
+        blobs.sort_by_key(|blob| blob.id);
+
+        Ok(blobs)
+    }
+
+    /// Copies a blob into the target storage, and verifies the copy against the recorded hash and size.
+    /// A blob the target storage already holds intact is not copied again, so an interrupted migration can be resumed.
+    pub async fn migrate_blob(
+        &self,
+        blob: &MigratingBlob,
+    ) -> Result<BlobMigration, StorageMigrationServiceError> {
+        if self.target.size(blob.id).await? == Some(blob.size as u64) {
+            if let Some(reader) = self.target.read(blob.id, ReadRange::Full).await? {
+                if compute_stream_hash(reader).await? as i64 == blob.hash {
+                    return Ok(BlobMigration::AlreadyMigrated);
+                }
+            }
+        }
+
+        let reader = match self.source.read(blob.id, ReadRange::Full).await? {
+            Some(reader) => reader,
+            None => return Ok(BlobMigration::MissingInSource),
+        };
+
+        // writing a staging file does not truncate it, so the one left by an interrupted run is removed first
+        if self.target.read_staging(blob.id).await?.is_some() {
+            self.target.remove_staging(blob.id).await?;
+        }
+
+        self.target.write_staging(blob.id, 0, reader).await?;
+
+        let path = match self.target.read_staging(blob.id).await? {
+            Some(path) => path,
+            None => return Err(StorageMigrationServiceError::StagingFileVanished(blob.id)),
+        };
+        let hash = compute_stream_hash(tokio::fs::File::open(&path).await?).await?;
+
+        if hash as i64 != blob.hash {
+            self.target.remove_staging(blob.id).await.ok();
+            return Err(StorageMigrationServiceError::HashMismatch {
+                id: blob.id,
+                expected: blob.hash as u32,
+                actual: hash,
+            });
+        }
+
+        self.target.commit_staging(blob.id).await?;
+
+        let size = self.target.size(blob.id).await?;
+
+        if size != Some(blob.size as u64) {
+            return Err(StorageMigrationServiceError::SizeMismatch {
+                id: blob.id,
+                expected: blob.size as u64,
+                actual: size,
+            });
+        }
+
+        Ok(BlobMigration::Copied)
+    }
+}
+
+/// Computes the CRC32 hash of a stream, which is the hash recorded for files.
+async fn compute_stream_hash(mut reader: impl AsyncRead + Unpin) -> Result<u32, std::io::Error> {
+    let mut hasher = crc32fast::Hasher::new();
+    let mut buffer = vec![0; HASH_BUFFER_SIZE];
+
+    loop {
+        let read = reader.read(&mut buffer).await?;
+
+        if read == 0 {
+            break;
+        }
+
+        hasher.update(&buffer[..read]);
+    }
+
+    Ok(hasher.finalize())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::AppConfig,
+        db,
+        services::{
+            memory_file_system::MemoryFileSystem, AuthService, FileService, StagingFileService,
+            UserService,
+        },
+        test::{
+            create_test_rocket_instance_with_file_driver,
+            helpers::{create_file, create_initial_user},
+            TestFileDriver,
+        },
+    };
+    use rocket::local::asynchronous::Client;
+
+    async fn read_blob(file_driver: &(dyn FileDriver + Send + Sync), id: Uuid) -> Option<String> {
+        let mut content = String::new();
+        file_driver
+            .read(id, ReadRange::Full)
+            .await
+            .unwrap()?
+            .read_to_string(&mut content)
+            .await
+            .unwrap();
+        Some(content)
+    }
+
+    #[rocket::async_test]
+    async fn test_migrate_blobs() {
+        let (rocket, _database_dropper, _index_dropper) =
+            create_test_rocket_instance_with_file_driver(TestFileDriver::Memory).await;
+        let client = Client::tracked(rocket).await.unwrap();
+        let app_config = client.rocket().state::<AppConfig>().unwrap();
+        let auth_service = client.rocket().state::<Arc<AuthService>>().unwrap();
+        let staging_file_service = client.rocket().state::<Arc<StagingFileService>>().unwrap();
+        let file_service = client.rocket().state::<Arc<FileService>>().unwrap();
+        let user_service = client.rocket().state::<Arc<UserService>>().unwrap();
+        let source = client
+            .rocket()
+            .state::<Arc<dyn FileDriver + Send + Sync>>()
+            .unwrap()
+            .clone();
+        let target: Arc<dyn FileDriver + Send + Sync> =
+            Arc::new(MemoryFileSystem::new(&app_config.temp_base_path));
+
+        let (_initial_user, initial_user_session) =
+            create_initial_user(auth_service, user_service).await;
+
+        let mut files = vec![];
+
+        for index in 0..3 {
+            files.push(
+                create_file(
+                    &client,
+                    staging_file_service,
+                    file_service,
+                    &initial_user_session,
+                    format!("file{}", index),
+                    Some("text/plain"),
+                    format!("file{} content", index),
+                )
+                .await,
+            );
+        }
+
+        files.sort_by_key(|file| file.id);
+
+        let db_pool = db::create_database_connection_pool(
+            &app_config.database_url_base,
+            &app_config.database_name,
+            &app_config.database_pool,
+            None,
+        )
+        .unwrap();
+        let storage_migration_service =
+            StorageMigrationService::new(db_pool, source.clone(), target.clone());
+
+        let blobs = storage_migration_service.list_blobs().await.unwrap();
+
+        assert_eq!(
+            blobs,
+            files
+                .iter()
+                .map(|file| MigratingBlob {
+                    id: file.id,
+                    size: file.size,
+                    hash: file.hash,
+                })
+                .collect::<Vec<_>>()
+        );
+
+        // the first blob is moved by an earlier, interrupted run
+        assert_eq!(
+            storage_migration_service
+                .migrate_blob(&blobs[0])
+                .await
+                .unwrap(),
+            BlobMigration::Copied
+        );
+
+        // the second one is lost in the source storage
+        source.remove(blobs[1].id).await.unwrap();
+
+        // and the third one is corrupted in the source storage
+        source.remove(blobs[2].id).await.unwrap();
+        source
+            .write_staging(blobs[2].id, 0, Box::pin(&b"corrupted content"[..]))
+            .await
+            .unwrap();
+        source.commit_staging(blobs[2].id).await.unwrap();
+
+        assert_eq!(
+            storage_migration_service
+                .migrate_blob(&blobs[0])
+                .await
+                .unwrap(),
+            BlobMigration::AlreadyMigrated
+        );
+        assert_eq!(
+            storage_migration_service
+                .migrate_blob(&blobs[1])
+                .await
+                .unwrap(),
+            BlobMigration::MissingInSource
+        );
+        assert!(matches!(
+            storage_migration_service.migrate_blob(&blobs[2]).await,
+            Err(StorageMigrationServiceError::HashMismatch { id, .. }) if id == blobs[2].id
+        ));
+
+        assert_eq!(
+            read_blob(target.as_ref(), blobs[0].id).await,
+            Some(format!("{} content", files[0].name))
+        );
+        assert_eq!(read_blob(target.as_ref(), blobs[1].id).await, None);
+        assert_eq!(read_blob(target.as_ref(), blobs[2].id).await, None);
+
+        // the source storage is left as is
+        assert!(source.exists(blobs[0].id).await.unwrap());
+    }
+}
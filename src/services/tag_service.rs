@@ -1,11 +1,15 @@
-use super::{FileService, SearchService};
-use crate::db::models::CreatingTag;
-use diesel::{
-    expression::AsExpression, sql_types::Bool, BoolExpressionMethods, BoxableExpression,
-    ExpressionMethods, QueryDsl,
+use super::{Page, SearchService};
+use crate::db::models::{CreatingTag, Tag};
+use diesel::{BoolExpressionMethods, ExpressionMethods, QueryDsl, TextExpressionMethods};
+use diesel_async::{
+    pooled_connection::deadpool::Pool, scoped_futures::ScopedFutureExt, AsyncConnection,
+    AsyncPgConnection, RunQueryDsl,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
 };
-use diesel_async::{pooled_connection::deadpool::Pool, AsyncPgConnection, RunQueryDsl};
-use std::sync::Arc;
 use thiserror::Error;
 use uuid::Uuid;
 
@@ -25,29 +29,87 @@ pub enum AddTagToFileError<'a> {
     Error(#[from] TagServiceError),
 }
 
-#[derive(Error, Debug)]
-pub enum RemoveTagFromFileError<'a> {
-    #[error("some of the file does not exist: `{file_ids:?}`")]
-    InvalidFiles { file_ids: &'a [Uuid] },
-    #[error("{0}")]
-    Error(#[from] TagServiceError),
+/// Parses a tag into its namespace and value, split at the first colon.
+/// For example, `year:1959` is parsed into `("year", "1959")`.
+/// The namespace is empty if the tag has no namespace.
+pub fn parse_tag(tag: &str) -> (&str, &str) {
+    match tag.split_once(':') {
+        Some((namespace, value)) => (namespace, value),
+        None => ("", tag),
+    }
+}
+
+/// Formats a namespace and a value back into a tag; the inverse of `parse_tag`.
+pub fn format_tag(namespace: &str, name: &str) -> String {
+    if namespace.is_empty() {
+        name.to_owned()
+    } else {
+        format!("{}:{}", namespace, name)
+    }
+}
+
+/// Escapes a string to be matched literally in a `LIKE` pattern.
+fn escape_like(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+/// The order to list distinct tags in.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TagOrder {
+    /// By the number of files having the tag in descending order, and by the tag for the same counts.
+    Count,
+    /// By namespace and value (namespace first).
+    Name,
+}
+
+impl TagOrder {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "count" => Some(Self::Count),
+            "name" => Some(Self::Name),
+            _ => None,
+        }
+    }
+}
+
+/// A distinct tag with the number of files having it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TagCount {
+    pub namespace: String,
+    pub name: String,
+    pub count: i64,
+}
+
+/// What a tag batch changed on a file.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct FileTagChange {
+    pub file_id: Uuid,
+    /// Whether the file exists. Missing files are left out of the batch.
+    pub found: bool,
+    /// The number of tags the file did not have before.
+    pub added: usize,
+    /// The number of tags removed from the file.
+    pub removed: usize,
 }
 
 pub struct TagService {
     db_pool: Pool<AsyncPgConnection>,
-    file_service: Arc<FileService>,
-    search_service: Arc<SearchService>,
+    search_service: Arc<dyn SearchService + Send + Sync>,
 }
 
 impl TagService {
     pub fn new(
         db_pool: Pool<AsyncPgConnection>,
-        file_service: Arc<FileService>,
-        search_service: Arc<SearchService>,
+        search_service: Arc<dyn SearchService + Send + Sync>,
     ) -> Arc<Self> {
         Arc::new(Self {
             db_pool,
-            file_service,
             search_service,
         })
     }
@@ -63,9 +125,11 @@ impl TagService {
 
         for &file_id in file_ids {
             for tag in tags {
+                let (namespace, name) = parse_tag(tag.as_ref());
                 creating_tags.push(CreatingTag {
-                    name: tag.as_ref(),
+                    name,
                     file_id,
+                    namespace,
                 });
             }
         }
@@ -89,54 +153,290 @@ impl TagService {
             Err(err) => return Err(TagServiceError::from(err).into()),
         };
 
-        // TODO: index the tags
+        self.index_tags_of_files(db, file_ids).await?;
 
         Ok(count)
     }
 
-    pub async fn remove_tags_from_files<'a>(
+    /// Removes `removing_tags` from and then adds `adding_tags` to the given files, in a single transaction.
+    /// Files that do not exist are skipped and reported as not found.
+    /// The result has one entry for each distinct file ID, in the order they were given.
+    pub async fn apply_tag_batch(
         &self,
-        file_ids: &'a [Uuid],
-        tags: &'a [impl AsRef<str>],
-    ) -> Result<usize, RemoveTagFromFileError<'a>> {
+        file_ids: &[Uuid],
+        adding_tags: &[impl AsRef<str> + Sync],
+        removing_tags: &[impl AsRef<str> + Sync],
+    ) -> Result<Vec<FileTagChange>, TagServiceError> {
         use crate::db::schema;
 
-        if file_ids.is_empty() || tags.is_empty() {
-            return Ok(0);
+        let mut seen_file_ids = HashSet::with_capacity(file_ids.len());
+        let file_ids = file_ids
+            .iter()
+            .copied()
+            .filter(|file_id| seen_file_ids.insert(*file_id))
+            .collect::<Vec<_>>();
+
+        let db = &mut self.db_pool.get().await?;
+        let (found_file_ids, added_file_ids, removed_file_ids) = db
+            .transaction(|db| {
+                let file_ids = &file_ids;
+
+                async move {
+                    // lock the files, so that none of them is removed while the batch is applied
+                    let found_file_ids = schema::files::table
+                        .filter(schema::files::id.eq_any(file_ids))
+                        .select(schema::files::id)
+                        .for_share()
+                        .load::<Uuid>(db)
+                        .await?;
+
+                    let mut removed_file_ids = Vec::new();
+
+                    for tag in removing_tags {
+                        let (namespace, name) = parse_tag(tag.as_ref());
+                        let file_ids = diesel::delete(
+                            schema::tags::table
+                                .filter(schema::tags::file_id.eq_any(&found_file_ids))
+                                .filter(schema::tags::namespace.eq(namespace))
+                                .filter(schema::tags::name.eq(name)),
+                        )
+                        .returning(schema::tags::file_id)
+                        .load::<Uuid>(db)
+                        .await?;
+
+                        removed_file_ids.extend(file_ids);
+                    }
+
+                    let mut creating_tags =
+                        Vec::with_capacity(found_file_ids.len() * adding_tags.len());
+
+                    for &file_id in &found_file_ids {
+                        for tag in adding_tags {
+                            let (namespace, name) = parse_tag(tag.as_ref());
+                            creating_tags.push(CreatingTag {
+                                name,
+                                file_id,
+                                namespace,
+                            });
+                        }
+                    }
+
+                    let added_file_ids = if creating_tags.is_empty() {
+                        Vec::new()
+                    } else {
+                        diesel::insert_into(schema::tags::table)
+                            .values(creating_tags)
+                            .on_conflict_do_nothing()
+                            .returning(schema::tags::file_id)
+                            .load::<Uuid>(db)
+                            .await?
+                    };
+
+                    Ok::<_, TagServiceError>((found_file_ids, added_file_ids, removed_file_ids))
+                }
+                .scope_boxed()
+            })
+            .await?;
+
+        self.index_tags_of_files(db, &found_file_ids).await?;
+
+        let found_file_ids = found_file_ids.into_iter().collect::<HashSet<_>>();
+        let mut changes = file_ids
+            .iter()
+            .map(|&file_id| FileTagChange {
+                file_id,
+                found: found_file_ids.contains(&file_id),
+                added: 0,
+                removed: 0,
+            })
+            .collect::<Vec<_>>();
+        let indices = file_ids
+            .iter()
+            .enumerate()
+            .map(|(index, &file_id)| (file_id, index))
+            .collect::<HashMap<_, _>>();
+
+        for file_id in added_file_ids {
+            changes[indices[&file_id]].added += 1;
         }
 
-        let db = &mut self.db_pool.get().await.map_err(TagServiceError::from)?;
+        for file_id in removed_file_ids {
+            changes[indices[&file_id]].removed += 1;
+        }
 
-        let mut conditions: Box<dyn BoxableExpression<_, _, SqlType = _>> =
-            Box::new(<bool as AsExpression<Bool>>::as_expression(false));
+        Ok(changes)
+    }
 
-        for &file_id in file_ids {
-            for tag in tags {
-                conditions = Box::new(
-                    conditions.or(schema::tags::name
-                        .eq(tag.as_ref())
-                        .and(schema::tags::file_id.eq(file_id))),
-                );
-            }
+    /// Retrieves the distinct tags with the number of files having each of them.
+    /// If `prefix` is provided, only the tags starting with it are listed, e.g. `year:19` or `fav`.
+    /// If `last_tag` is provided, the result will start from the tag that comes after it in `order`.
+    /// The cursor of the page is the last tag, formatted with `format_tag`.
+    pub async fn get_tags(
+        &self,
+        prefix: Option<&str>,
+        order: TagOrder,
+        last_tag: Option<&str>,
+        limit: u32,
+    ) -> Result<Page<TagCount, String>, TagServiceError> {
+        use crate::db::schema;
+        use diesel::dsl::count_star;
+
+        let db = &mut self.db_pool.get().await?;
+        let mut query = schema::tags::table
+            .group_by((schema::tags::namespace, schema::tags::name))
+            .select((schema::tags::namespace, schema::tags::name, count_star()))
+            // fetch one more tag to tell whether there is a next page
+            .limit(limit as i64 + 1)
+            .into_boxed();
+
+        if let Some(prefix) = prefix {
+            query = match prefix.split_once(':') {
+                Some((namespace, name)) => query
+                    .filter(schema::tags::namespace.eq(namespace.to_owned()))
+                    .filter(schema::tags::name.like(format!("{}%", escape_like(name)))),
+                None => {
+                    let pattern = format!("{}%", escape_like(prefix));
+                    query.filter(
+                        schema::tags::namespace
+                            .like(pattern.clone())
+                            .or(schema::tags::namespace
+                                .eq("")
+                                .and(schema::tags::name.like(pattern))),
+                    )
+                }
+            };
         }
 
-        let result = diesel::delete(schema::tags::table.filter(conditions))
-            .execute(db)
-            .await;
+        let last_tag = last_tag.map(parse_tag);
 
-        let count = match result {
-            Ok(count) => count,
-            Err(diesel::result::Error::DatabaseError(
-                diesel::result::DatabaseErrorKind::ForeignKeyViolation,
-                err,
-            )) if err.constraint_name() == Some("tags_file_fk") => {
-                return Err(RemoveTagFromFileError::InvalidFiles { file_ids });
+        query = match order {
+            TagOrder::Count => {
+                query = query.order((
+                    count_star().desc(),
+                    schema::tags::namespace.asc(),
+                    schema::tags::name.asc(),
+                ));
+
+                match last_tag {
+                    Some((last_namespace, last_name)) => {
+                        let last_count = schema::tags::table
+                            .filter(schema::tags::namespace.eq(last_namespace))
+                            .filter(schema::tags::name.eq(last_name))
+                            .count()
+                            .get_result::<i64>(db)
+                            .await?;
+
+                        // the last tag is gone, so there is no position to continue from
+                        if last_count == 0 {
+                            return Ok(Page::new(Vec::new(), limit, None, |tag: &TagCount| {
+                                format_tag(&tag.namespace, &tag.name)
+                            }));
+                        }
+
+                        query.having(
+                            count_star()
+                                .lt(last_count)
+                                .or(count_star().eq(last_count).and(
+                                    schema::tags::namespace.gt(last_namespace.to_owned()).or(
+                                        schema::tags::namespace
+                                            .eq(last_namespace.to_owned())
+                                            .and(schema::tags::name.gt(last_name.to_owned())),
+                                    ),
+                                )),
+                        )
+                    }
+                    None => query,
+                }
+            }
+            TagOrder::Name => {
+                query = query.order((schema::tags::namespace.asc(), schema::tags::name.asc()));
+
+                match last_tag {
+                    Some((last_namespace, last_name)) => query.filter(
+                        schema::tags::namespace.gt(last_namespace.to_owned()).or(
+                            schema::tags::namespace
+                                .eq(last_namespace.to_owned())
+                                .and(schema::tags::name.gt(last_name.to_owned())),
+                        ),
+                    ),
+                    None => query,
+                }
             }
-            Err(err) => return Err(TagServiceError::from(err).into()),
         };
 
-        // TODO: index the tags
+        let tags = query
+            .load::<(String, String, i64)>(db)
+            .await?
+            .into_iter()
+            .map(|(namespace, name, count)| TagCount {
+                namespace,
+                name,
+                count,
+            })
+            .collect();
 
-        Ok(count)
+        Ok(Page::new(tags, limit, None, |tag| {
+            format_tag(&tag.namespace, &tag.name)
+        }))
+    }
+
+    /// Retrieves the tags of a file.
+    /// The result will be sorted by namespace and name (namespace first) in ascending order.
+    pub async fn get_tags_by_file_id(&self, file_id: Uuid) -> Result<Vec<Tag>, TagServiceError> {
+        use crate::db::schema;
+
+        let db = &mut self.db_pool.get().await?;
+        let tags = schema::tags::table
+            .filter(schema::tags::file_id.eq(file_id))
+            .select((
+                schema::tags::name,
+                schema::tags::file_id,
+                schema::tags::namespace,
+            ))
+            .order((schema::tags::namespace.asc(), schema::tags::name.asc()))
+            .load::<Tag>(db)
+            .await?;
+
+        Ok(tags)
+    }
+
+    /// Indexes the current tags of the given files.
+    async fn index_tags_of_files(
+        &self,
+        db: &mut AsyncPgConnection,
+        file_ids: &[Uuid],
+    ) -> Result<(), TagServiceError> {
+        use crate::db::schema;
+
+        let tags = schema::tags::table
+            .filter(schema::tags::file_id.eq_any(file_ids))
+            .select((
+                schema::tags::name,
+                schema::tags::file_id,
+                schema::tags::namespace,
+            ))
+            .order((schema::tags::namespace.asc(), schema::tags::name.asc()))
+            .load::<Tag>(db)
+            .await?;
+
+        let mut tags_by_file = HashMap::<Uuid, Vec<Tag>>::with_capacity(file_ids.len());
+
+        for &file_id in file_ids {
+            tags_by_file.entry(file_id).or_default();
+        }
+
+        for tag in tags {
+            tags_by_file.entry(tag.file_id).or_default().push(tag);
+        }
+
+        for (file_id, tags) in &tags_by_file {
+            // ignore the error if the indexing fails, as it is not critical
+            self.search_service
+                .index_file_tags(*file_id, tags)
+                .await
+                .ok();
+        }
+
+        Ok(())
     }
 }
@@ -0,0 +1,719 @@
+use super::{FileService, IdService, ReadError, ReadRange};
+use crate::{
+    config::AppTranscode,
+    db::models::{CreatingTranscodeJob, TranscodeJob},
+};
+use chrono::NaiveDateTime;
+use diesel::{ExpressionMethods, NullableExpressionMethods, OptionalExtension, QueryDsl};
+use diesel_async::{
+    pooled_connection::deadpool::Pool, scoped_futures::ScopedFutureExt, AsyncConnection,
+    AsyncPgConnection, RunQueryDsl,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use thiserror::Error;
+use tokio::{io::AsyncWriteExt, process::Command};
+use uuid::Uuid;
+
+#[derive(Error, Debug)]
+pub enum TranscodeServiceError {
+    #[error("database pool error: {0}")]
+    Pool(#[from] diesel_async::pooled_connection::deadpool::PoolError),
+    #[error("diesel error: {0}")]
+    Diesel(#[from] diesel::result::Error),
+    #[error("io error: {0}")]
+    IO(#[from] std::io::Error),
+    #[error("read error: {0}")]
+    Read(#[from] ReadError),
+    #[error("the source file of the job is missing")]
+    SourceMissing,
+    #[error("unknown rendition profile `{profile}`")]
+    UnknownProfile { profile: String },
+    #[error("ffmpeg exited with {status}: {stderr}")]
+    FFmpeg {
+        status: std::process::ExitStatus,
+        stderr: String,
+    },
+}
+
+/// The name of the playlist in an HLS rendition.
+pub const HLS_PLAYLIST_NAME: &str = "playlist.m3u8";
+
+/// The web-friendly renditions that can be produced from media files.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RenditionProfile {
+    /// H.264 video and AAC audio in MP4.
+    Mp4,
+    /// VP9 video and Opus audio in WebM.
+    Webm,
+    /// MP3 audio only.
+    Mp3,
+    /// H.264 video and AAC audio in HLS segments, for streaming large videos.
+    Hls,
+}
+
+impl RenditionProfile {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "mp4" => Some(Self::Mp4),
+            "webm" => Some(Self::Webm),
+            "mp3" => Some(Self::Mp3),
+            "hls" => Some(Self::Hls),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Mp4 => "mp4",
+            Self::Webm => "webm",
+            Self::Mp3 => "mp3",
+            Self::Hls => "hls",
+        }
+    }
+
+    pub fn mime(self) -> &'static str {
+        match self {
+            Self::Mp4 => "video/mp4",
+            Self::Webm => "video/webm",
+            Self::Mp3 => "audio/mpeg",
+            Self::Hls => "application/vnd.apple.mpegurl",
+        }
+    }
+
+    fn ffmpeg_args(self) -> &'static [&'static str] {
+        match self {
+            Self::Mp4 => &[
+                "-c:v",
+                "libx264",
+                "-preset",
+                "veryfast",
+                "-crf",
+                "23",
+                "-pix_fmt",
+                "yuv420p",
+                "-c:a",
+                "aac",
+                "-b:a",
+                "128k",
+                "-movflags",
+                "+faststart",
+                "-f",
+                "mp4",
+            ],
+            Self::Webm => &[
+                "-c:v",
+                "libvpx-vp9",
+                "-crf",
+                "32",
+                "-b:v",
+                "0",
+                "-c:a",
+                "libopus",
+                "-b:a",
+                "96k",
+                "-f",
+                "webm",
+            ],
+            Self::Mp3 => &["-vn", "-c:a", "libmp3lame", "-q:a", "2", "-f", "mp3"],
+            Self::Hls => &[
+                "-c:v",
+                "libx264",
+                "-preset",
+                "veryfast",
+                "-crf",
+                "23",
+                "-pix_fmt",
+                "yuv420p",
+                "-c:a",
+                "aac",
+                "-b:a",
+                "128k",
+                "-f",
+                "hls",
+                "-hls_time",
+                "6",
+                "-hls_playlist_type",
+                "vod",
+            ],
+        }
+    }
+}
+
+pub struct TranscodeService {
+    db_pool: Pool<AsyncPgConnection>,
+    id_service: Arc<IdService>,
+    file_service: Arc<FileService>,
+    ffmpeg_path: PathBuf,
+    temp_base_path: PathBuf,
+    rendition_base_path: PathBuf,
+    max_attempts: i32,
+}
+
+impl TranscodeService {
+    pub fn new(
+        db_pool: Pool<AsyncPgConnection>,
+        id_service: Arc<IdService>,
+        file_service: Arc<FileService>,
+        config: &AppTranscode,
+        temp_base_path: impl Into<PathBuf>,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            db_pool,
+            id_service,
+            file_service,
+            ffmpeg_path: config.ffmpeg_path.clone(),
+            temp_base_path: temp_base_path.into(),
+            rendition_base_path: config.rendition_base_path.clone(),
+            max_attempts: config.max_attempts,
+        })
+    }
+
+    /// Requests a rendition of a file.
+    /// A failed job is queued again, and any other existing job is returned as is.
+    /// Returns `None` if no file was found.
+    pub async fn request_rendition(
+        &self,
+        file_id: Uuid,
+        profile: RenditionProfile,
+    ) -> Result<Option<TranscodeJob>, TranscodeServiceError> {
+        use crate::db::schema;
+
+        let db = &mut self.db_pool.get().await?;
+        db.transaction(|db| {
+            async move {
+                let job = schema::transcode_jobs::table
+                    .filter(schema::transcode_jobs::file_id.eq(file_id))
+                    .filter(schema::transcode_jobs::profile.eq(profile.name()))
+                    .select((
+                        schema::transcode_jobs::id,
+                        schema::transcode_jobs::file_id,
+                        schema::transcode_jobs::profile,
+                        schema::transcode_jobs::status,
+                        schema::transcode_jobs::error,
+                        schema::transcode_jobs::attempts,
+                        schema::transcode_jobs::size,
+                        schema::transcode_jobs::created_at,
+                        schema::transcode_jobs::started_at,
+                        schema::transcode_jobs::finished_at,
+                    ))
+                    .for_update()
+                    .get_result::<TranscodeJob>(db)
+                    .await
+                    .optional()?;
+
+                match job {
+                    Some(job) if job.status == "failed" => {
+                        let job = diesel::update(schema::transcode_jobs::table)
+                            .filter(schema::transcode_jobs::id.eq(job.id))
+                            .set((
+                                schema::transcode_jobs::status.eq("pending"),
+                                schema::transcode_jobs::error.eq(None::<String>),
+                                schema::transcode_jobs::attempts.eq(0),
+                                schema::transcode_jobs::started_at.eq(None::<NaiveDateTime>),
+                                schema::transcode_jobs::finished_at.eq(None::<NaiveDateTime>),
+                            ))
+                            .returning((
+                                schema::transcode_jobs::id,
+                                schema::transcode_jobs::file_id,
+                                schema::transcode_jobs::profile,
+                                schema::transcode_jobs::status,
+                                schema::transcode_jobs::error,
+                                schema::transcode_jobs::attempts,
+                                schema::transcode_jobs::size,
+                                schema::transcode_jobs::created_at,
+                                schema::transcode_jobs::started_at,
+                                schema::transcode_jobs::finished_at,
+                            ))
+                            .get_result::<TranscodeJob>(db)
+                            .await?;
+
+                        Ok(Some(job))
+                    }
+                    Some(job) => Ok(Some(job)),
+                    None => {
+                        let job = diesel::insert_into(schema::transcode_jobs::table)
+                            .values(CreatingTranscodeJob {
+                                id: self.id_service.generate(),
+                                file_id,
+                                profile: profile.name(),
+                            })
+                            .returning((
+                                schema::transcode_jobs::id,
+                                schema::transcode_jobs::file_id,
+                                schema::transcode_jobs::profile,
+                                schema::transcode_jobs::status,
+                                schema::transcode_jobs::error,
+                                schema::transcode_jobs::attempts,
+                                schema::transcode_jobs::size,
+                                schema::transcode_jobs::created_at,
+                                schema::transcode_jobs::started_at,
+                                schema::transcode_jobs::finished_at,
+                            ))
+                            .get_result::<TranscodeJob>(db)
+                            .await;
+
+                        match job {
+                            Ok(job) => Ok(Some(job)),
+                            Err(diesel::result::Error::DatabaseError(
+                                diesel::result::DatabaseErrorKind::ForeignKeyViolation,
+                                err,
+                            )) if err.constraint_name() == Some("transcode_jobs_file_fk") => {
+                                Ok(None)
+                            }
+                            Err(err) => Err(err.into()),
+                        }
+                    }
+                }
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+
+    /// Retrieves all rendition jobs of a file, sorted by creation time.
+    pub async fn get_renditions(
+        &self,
+        file_id: Uuid,
+    ) -> Result<Vec<TranscodeJob>, TranscodeServiceError> {
+        use crate::db::schema;
+
+        let db = &mut self.db_pool.get().await?;
+        let jobs = schema::transcode_jobs::table
+            .filter(schema::transcode_jobs::file_id.eq(file_id))
+            .select((
+                schema::transcode_jobs::id,
+                schema::transcode_jobs::file_id,
+                schema::transcode_jobs::profile,
+                schema::transcode_jobs::status,
+                schema::transcode_jobs::error,
+                schema::transcode_jobs::attempts,
+                schema::transcode_jobs::size,
+                schema::transcode_jobs::created_at,
+                schema::transcode_jobs::started_at,
+                schema::transcode_jobs::finished_at,
+            ))
+            .order((
+                schema::transcode_jobs::created_at.asc(),
+                schema::transcode_jobs::id.asc(),
+            ))
+            .load::<TranscodeJob>(db)
+            .await?;
+
+        Ok(jobs)
+    }
+
+    /// Retrieves the rendition job of a file for the given profile.
+    pub async fn get_rendition(
+        &self,
+        file_id: Uuid,
+        profile: RenditionProfile,
+    ) -> Result<Option<TranscodeJob>, TranscodeServiceError> {
+        use crate::db::schema;
+
+        let db = &mut self.db_pool.get().await?;
+        let job = schema::transcode_jobs::table
+            .filter(schema::transcode_jobs::file_id.eq(file_id))
+            .filter(schema::transcode_jobs::profile.eq(profile.name()))
+            .select((
+                schema::transcode_jobs::id,
+                schema::transcode_jobs::file_id,
+                schema::transcode_jobs::profile,
+                schema::transcode_jobs::status,
+                schema::transcode_jobs::error,
+                schema::transcode_jobs::attempts,
+                schema::transcode_jobs::size,
+                schema::transcode_jobs::created_at,
+                schema::transcode_jobs::started_at,
+                schema::transcode_jobs::finished_at,
+            ))
+            .get_result::<TranscodeJob>(db)
+            .await
+            .optional()?;
+
+        Ok(job)
+    }
+
+    /// Opens the produced rendition of a file.
+    /// For HLS, the playlist is opened.
+    /// Returns `None` if the rendition does not exist.
+    pub async fn open_rendition(
+        &self,
+        file_id: Uuid,
+        profile: RenditionProfile,
+    ) -> Result<Option<tokio::fs::File>, TranscodeServiceError> {
+        let path = match profile {
+            RenditionProfile::Hls => self
+                .rendition_path(file_id, profile)
+                .join(HLS_PLAYLIST_NAME),
+            _ => self.rendition_path(file_id, profile),
+        };
+
+        match tokio::fs::File::open(&path).await {
+            Ok(file) => Ok(Some(file)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Opens a file of the produced HLS rendition of a file, which is either the playlist or a segment.
+    /// Returns `None` if the name is not a valid HLS file name or the file does not exist.
+    pub async fn open_hls_file(
+        &self,
+        file_id: Uuid,
+        name: &str,
+    ) -> Result<Option<tokio::fs::File>, TranscodeServiceError> {
+        let is_segment = name
+            .strip_prefix("segment")
+            .and_then(|name| name.strip_suffix(".ts"))
+            .is_some_and(|index| !index.is_empty() && index.chars().all(|c| c.is_ascii_digit()));
+
+        if name != HLS_PLAYLIST_NAME && !is_segment {
+            return Ok(None);
+        }
+
+        let path = self
+            .rendition_path(file_id, RenditionProfile::Hls)
+            .join(name);
+
+        match tokio::fs::File::open(&path).await {
+            Ok(file) => Ok(Some(file)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Removes all produced renditions of a file.
+    /// The jobs are removed along with the file by the database.
+    pub async fn remove_renditions(&self, file_id: Uuid) -> Result<(), TranscodeServiceError> {
+        let path = self.rendition_base_path.join(file_id.to_string());
+
+        match tokio::fs::remove_dir_all(&path).await {
+            Ok(()) => Ok(()),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Puts jobs left running by a previous process back into the queue.
+    /// It must be called before the worker starts.
+    pub async fn requeue_running_jobs(&self) -> Result<usize, TranscodeServiceError> {
+        use crate::db::schema;
+
+        let db = &mut self.db_pool.get().await?;
+        let count = diesel::update(schema::transcode_jobs::table)
+            .filter(schema::transcode_jobs::status.eq("running"))
+            .set((
+                schema::transcode_jobs::status.eq("pending"),
+                schema::transcode_jobs::started_at.eq(None::<NaiveDateTime>),
+            ))
+            .execute(db)
+            .await?;
+
+        Ok(count)
+    }
+
+    /// Claims the oldest pending job and runs it.
+    /// Returns the finished job, or `None` if the queue is empty.
+    /// A failed job is queued again until it reaches the maximum number of attempts.
+    pub async fn run_next_job(&self) -> Result<Option<TranscodeJob>, TranscodeServiceError> {
+        use crate::db::schema;
+
+        let job = self.claim_next_job().await?;
+        let job = match job {
+            Some(job) => job,
+            None => return Ok(None),
+        };
+
+        let result = match RenditionProfile::from_name(&job.profile) {
+            Some(profile) => self.transcode(job.file_id, job.id, profile).await,
+            None => Err(TranscodeServiceError::UnknownProfile {
+                profile: job.profile.clone(),
+            }),
+        };
+
+        let db = &mut self.db_pool.get().await?;
+        let query = diesel::update(schema::transcode_jobs::table)
+            .filter(schema::transcode_jobs::id.eq(job.id));
+
+        let job = match result {
+            Ok(size) => {
+                query
+                    .set((
+                        schema::transcode_jobs::status.eq("completed"),
+                        schema::transcode_jobs::error.eq(None::<String>),
+                        schema::transcode_jobs::size.eq(Some(size as i64)),
+                        schema::transcode_jobs::finished_at.eq(diesel::dsl::now.nullable()),
+                    ))
+                    .returning((
+                        schema::transcode_jobs::id,
+                        schema::transcode_jobs::file_id,
+                        schema::transcode_jobs::profile,
+                        schema::transcode_jobs::status,
+                        schema::transcode_jobs::error,
+                        schema::transcode_jobs::attempts,
+                        schema::transcode_jobs::size,
+                        schema::transcode_jobs::created_at,
+                        schema::transcode_jobs::started_at,
+                        schema::transcode_jobs::finished_at,
+                    ))
+                    .get_result::<TranscodeJob>(db)
+                    .await?
+            }
+            Err(err) => {
+                log::warn!(target: "transcode_service", job_id:serde = job.id, file_id:serde = job.file_id, profile = job.profile, attempts = job.attempts, err:err; "Failed to transcode file.");
+
+                let status = if self.max_attempts <= job.attempts {
+                    "failed"
+                } else {
+                    "pending"
+                };
+
+                query
+                    .set((
+                        schema::transcode_jobs::status.eq(status),
+                        schema::transcode_jobs::error.eq(Some(err.to_string())),
+                        schema::transcode_jobs::finished_at.eq(diesel::dsl::now.nullable()),
+                    ))
+                    .returning((
+                        schema::transcode_jobs::id,
+                        schema::transcode_jobs::file_id,
+                        schema::transcode_jobs::profile,
+                        schema::transcode_jobs::status,
+                        schema::transcode_jobs::error,
+                        schema::transcode_jobs::attempts,
+                        schema::transcode_jobs::size,
+                        schema::transcode_jobs::created_at,
+                        schema::transcode_jobs::started_at,
+                        schema::transcode_jobs::finished_at,
+                    ))
+                    .get_result::<TranscodeJob>(db)
+                    .await?
+            }
+        };
+
+        Ok(Some(job))
+    }
+
+    async fn claim_next_job(&self) -> Result<Option<TranscodeJob>, TranscodeServiceError> {
+        use crate::db::schema;
+
+        let db = &mut self.db_pool.get().await?;
+        db.transaction(|db| {
+            async move {
+                let job_id = schema::transcode_jobs::table
+                    .filter(schema::transcode_jobs::status.eq("pending"))
+                    .select(schema::transcode_jobs::id)
+                    .order((
+                        schema::transcode_jobs::created_at.asc(),
+                        schema::transcode_jobs::id.asc(),
+                    ))
+                    .limit(1)
+                    .for_update()
+                    .skip_locked()
+                    .get_result::<Uuid>(db)
+                    .await
+                    .optional()?;
+
+                let job_id = match job_id {
+                    Some(job_id) => job_id,
+                    None => return Ok(None),
+                };
+
+                let job = diesel::update(schema::transcode_jobs::table)
+                    .filter(schema::transcode_jobs::id.eq(job_id))
+                    .set((
+                        schema::transcode_jobs::status.eq("running"),
+                        schema::transcode_jobs::attempts.eq(schema::transcode_jobs::attempts + 1),
+                        schema::transcode_jobs::started_at.eq(diesel::dsl::now.nullable()),
+                        schema::transcode_jobs::finished_at.eq(None::<NaiveDateTime>),
+                    ))
+                    .returning((
+                        schema::transcode_jobs::id,
+                        schema::transcode_jobs::file_id,
+                        schema::transcode_jobs::profile,
+                        schema::transcode_jobs::status,
+                        schema::transcode_jobs::error,
+                        schema::transcode_jobs::attempts,
+                        schema::transcode_jobs::size,
+                        schema::transcode_jobs::created_at,
+                        schema::transcode_jobs::started_at,
+                        schema::transcode_jobs::finished_at,
+                    ))
+                    .get_result::<TranscodeJob>(db)
+                    .await?;
+
+                Ok(Some(job))
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+
+    /// Transcodes a file into the rendition of the given profile.
+    /// Returns the size of the produced rendition.
+    async fn transcode(
+        &self,
+        file_id: Uuid,
+        job_id: Uuid,
+        profile: RenditionProfile,
+    ) -> Result<u64, TranscodeServiceError> {
+        let input_path = self
+            .temp_base_path
+            .join(format!("transcode-{}-input", job_id));
+        let output_path =
+            self.temp_base_path
+                .join(format!("transcode-{}-output-{}", job_id, profile.name()));
+
+        let result = self
+            .transcode_into(file_id, &input_path, &output_path, profile)
+            .await;
+
+        // the temporary files may not exist, so the errors are ignored
+        tokio::fs::remove_file(&input_path).await.ok();
+
+        if let Err(err) = result {
+            remove_path(&output_path).await.ok();
+            return Err(err);
+        }
+
+        let rendition_path = self.rendition_path(file_id, profile);
+
+        if let Some(parent) = rendition_path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+
+        // a previous attempt may have left a rendition behind
+        remove_path(&rendition_path).await.ok();
+
+        // the temporary path may be on another device, so fall back to copying
+        if tokio::fs::rename(&output_path, &rendition_path)
+            .await
+            .is_err()
+        {
+            let result = copy_path(&output_path, &rendition_path).await;
+            remove_path(&output_path).await.ok();
+            result?;
+        }
+
+        let size = path_size(&rendition_path).await?;
+
+        Ok(size)
+    }
+
+    async fn transcode_into(
+        &self,
+        file_id: Uuid,
+        input_path: &Path,
+        output_path: &Path,
+        profile: RenditionProfile,
+    ) -> Result<(), TranscodeServiceError> {
+        let data = self
+            .file_service
+            .get_file_data_by_id(file_id, ReadRange::Full)
+            .await?;
+        let mut data = match data {
+            Some(data) => data,
+            None => return Err(TranscodeServiceError::SourceMissing),
+        };
+
+        let mut input = tokio::fs::File::create(input_path).await?;
+        tokio::io::copy(&mut data, &mut input).await?;
+        input.flush().await?;
+        drop(input);
+
+        let mut command = Command::new(&self.ffmpeg_path);
+        command
+            .args(["-nostdin", "-y", "-v", "error", "-i"])
+            .arg(input_path)
+            .args(profile.ffmpeg_args());
+
+        match profile {
+            RenditionProfile::Hls => {
+                // HLS produces a playlist and its segments, so the output is a directory
+                tokio::fs::create_dir_all(output_path).await?;
+                command
+                    .arg("-hls_segment_filename")
+                    .arg(output_path.join("segment%05d.ts"))
+                    .arg(output_path.join(HLS_PLAYLIST_NAME));
+            }
+            _ => {
+                command.arg(output_path);
+            }
+        }
+
+        let output = command.kill_on_drop(true).output().await?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            // keep the tail only, where ffmpeg reports the actual cause
+            let stderr = stderr
+                .char_indices()
+                .rev()
+                .nth(1024)
+                .map_or(&stderr[..], |(index, _)| &stderr[index..]);
+
+            return Err(TranscodeServiceError::FFmpeg {
+                status: output.status,
+                stderr: stderr.trim().to_owned(),
+            });
+        }
+
+        Ok(())
+    }
+
+    fn rendition_path(&self, file_id: Uuid, profile: RenditionProfile) -> PathBuf {
+        self.rendition_base_path
+            .join(file_id.to_string())
+            .join(profile.name())
+    }
+}
+
+/// Removes a file, or a directory with its contents.
+async fn remove_path(path: &Path) -> Result<(), std::io::Error> {
+    if tokio::fs::metadata(path).await?.is_dir() {
+        tokio::fs::remove_dir_all(path).await
+    } else {
+        tokio::fs::remove_file(path).await
+    }
+}
+
+/// Copies a file, or a flat directory with its files.
+async fn copy_path(from: &Path, to: &Path) -> Result<(), std::io::Error> {
+    if !tokio::fs::metadata(from).await?.is_dir() {
+        tokio::fs::copy(from, to).await?;
+        return Ok(());
+    }
+
+    tokio::fs::create_dir_all(to).await?;
+
+    let mut entries = tokio::fs::read_dir(from).await?;
+
+    while let Some(entry) = entries.next_entry().await? {
+        tokio::fs::copy(entry.path(), to.join(entry.file_name())).await?;
+    }
+
+    Ok(())
+}
+
+/// Computes the size of a file, or the total size of the files in a flat directory.
+async fn path_size(path: &Path) -> Result<u64, std::io::Error> {
+    let metadata = tokio::fs::metadata(path).await?;
+
+    if !metadata.is_dir() {
+        return Ok(metadata.len());
+    }
+
+    let mut size = 0;
+    let mut entries = tokio::fs::read_dir(path).await?;
+
+    while let Some(entry) = entries.next_entry().await? {
+        size += entry.metadata().await?.len();
+    }
+
+    Ok(size)
+}
@@ -0,0 +1,195 @@
+use crate::config::{AppTransferRates, AppTransfers};
+use std::{
+    collections::HashMap,
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{ready, Context, Poll},
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncRead, ReadBuf},
+    sync::{OwnedSemaphorePermit, Semaphore},
+    time::{Instant, Sleep},
+};
+
+/// A stream of data being transferred.
+pub type TransferStream<'a> = Pin<Box<dyn AsyncRead + Send + 'a>>;
+
+/// A slot of a transfer in progress. The slot is freed when the permit is dropped.
+pub struct TransferPermit {
+    _permit: Option<OwnedSemaphorePermit>,
+}
+
+impl TransferPermit {
+    /// Keeps the slot taken until the reader is dropped, e.g. once a streamed response has been sent.
+    pub fn hold_while_reading(
+        self,
+        reader: Pin<Box<dyn AsyncRead + Send>>,
+    ) -> Pin<Box<dyn AsyncRead + Send>> {
+        Box::pin(PermittedReader {
+            reader,
+            _permit: self,
+        })
+    }
+}
+
+struct PermittedReader {
+    reader: Pin<Box<dyn AsyncRead + Send>>,
+    _permit: TransferPermit,
+}
+
+impl AsyncRead for PermittedReader {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        self.reader.as_mut().poll_read(cx, buf)
+    }
+}
+
+/// Slows a stream down to a number of bytes per second.
+/// Each read is let through as it is, and the next one waits until the bytes read so far are due at the rate,
+/// so the rate holds on average rather than for each read.
+struct ThrottledReader<'a> {
+    reader: TransferStream<'a>,
+    rate: u64,
+    started_at: Instant,
+    transferred: u64,
+    delay: Option<Pin<Box<Sleep>>>,
+}
+
+impl AsyncRead for ThrottledReader<'_> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        if let Some(delay) = self.delay.as_mut() {
+            ready!(delay.as_mut().poll(cx));
+            self.delay = None;
+        }
+
+        let filled = buf.filled().len();
+        ready!(self.reader.as_mut().poll_read(cx, buf))?;
+        self.transferred += (buf.filled().len() - filled) as u64;
+
+        let due =
+            self.started_at + Duration::from_secs_f64(self.transferred as f64 / self.rate as f64);
+
+        if Instant::now() < due {
+            self.delay = Some(Box::pin(tokio::time::sleep_until(due)));
+        }
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Limits the number of uploads and downloads of file data in progress at once,
+/// so that parallel large transfers do not overwhelm the disk and the database.
+/// Transfers over the limit are rejected instead of queued, so clients can back off and retry.
+/// It also limits the speed of each transfer, globally or by the role of the user.
+pub struct TransferLimitService {
+    uploads: Option<Arc<Semaphore>>,
+    downloads: Option<Arc<Semaphore>>,
+    retry_after: u64,
+    max_upload_rate: u64,
+    max_download_rate: u64,
+    role_rates: HashMap<String, AppTransferRates>,
+}
+
+impl TransferLimitService {
+    pub fn new(config: &AppTransfers) -> Arc<Self> {
+        Arc::new(Self {
+            uploads: semaphore_of(config.max_concurrent_uploads),
+            downloads: semaphore_of(config.max_concurrent_downloads),
+            retry_after: config.retry_after,
+            max_upload_rate: config.max_upload_rate.as_u64(),
+            max_download_rate: config.max_download_rate.as_u64(),
+            role_rates: config.role_rates.clone(),
+        })
+    }
+
+    /// The period clients are asked to wait before retrying a rejected transfer, in seconds.
+    pub fn retry_after(&self) -> u64 {
+        self.retry_after
+    }
+
+    /// Takes a slot for an upload. Returns `None` if all the slots are taken.
+    pub fn try_acquire_upload(&self) -> Option<TransferPermit> {
+        try_acquire(&self.uploads)
+    }
+
+    /// Takes a slot for a download. Returns `None` if all the slots are taken.
+    pub fn try_acquire_download(&self) -> Option<TransferPermit> {
+        try_acquire(&self.downloads)
+    }
+
+    /// Slows an upload down to the upload speed of the role of the user, or the global one if `role` is `None`.
+    pub fn throttle_upload<'a>(
+        &self,
+        role: Option<&str>,
+        stream: TransferStream<'a>,
+    ) -> TransferStream<'a> {
+        let rate = role
+            .and_then(|role| self.role_rates.get(role))
+            .and_then(|rates| rates.max_upload_rate)
+            .map_or(self.max_upload_rate, |rate| rate.as_u64());
+
+        throttle(rate, stream)
+    }
+
+    /// Slows a download down to the download speed of the role of the user, or the global one if `role` is `None`.
+    pub fn throttle_download<'a>(
+        &self,
+        role: Option<&str>,
+        stream: TransferStream<'a>,
+    ) -> TransferStream<'a> {
+        let rate = role
+            .and_then(|role| self.role_rates.get(role))
+            .and_then(|rates| rates.max_download_rate)
+            .map_or(self.max_download_rate, |rate| rate.as_u64());
+
+        throttle(rate, stream)
+    }
+}
+
+fn throttle(rate: u64, stream: TransferStream<'_>) -> TransferStream<'_> {
+    // `0` lifts the limit
+    if rate == 0 {
+        return stream;
+    }
+
+    Box::pin(ThrottledReader {
+        reader: stream,
+        rate,
+        started_at: Instant::now(),
+        transferred: 0,
+        delay: None,
+    })
+}
+
+fn semaphore_of(max_concurrent: usize) -> Option<Arc<Semaphore>> {
+    // `0` lifts the limit
+    if max_concurrent == 0 {
+        None
+    } else {
+        Some(Arc::new(Semaphore::new(max_concurrent)))
+    }
+}
+
+fn try_acquire(semaphore: &Option<Arc<Semaphore>>) -> Option<TransferPermit> {
+    match semaphore {
+        Some(semaphore) => {
+            semaphore
+                .clone()
+                .try_acquire_owned()
+                .ok()
+                .map(|permit| TransferPermit {
+                    _permit: Some(permit),
+                })
+        }
+        None => Some(TransferPermit { _permit: None }),
+    }
+}
@@ -0,0 +1,260 @@
+use super::{StagingFileService, StagingFileServiceError, WriteError, WriteStream};
+use crate::db::models::StagingFile;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use sha1::{Digest, Sha1};
+use std::{collections::HashMap, path::PathBuf, sync::Arc};
+use thiserror::Error;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use uuid::Uuid;
+
+/// The version of the tus protocol the server speaks.
+pub const TUS_VERSION: &str = "1.0.0";
+
+/// The tus extensions the server supports.
+pub const TUS_EXTENSIONS: &str = "creation,checksum,termination";
+
+#[derive(Error, Debug)]
+pub enum TusServiceError {
+    #[error("staging file service error: {0}")]
+    StagingFileService(#[from] StagingFileServiceError),
+    #[error("io error: {0}")]
+    IO(#[from] std::io::Error),
+}
+
+#[derive(Error, Debug)]
+pub enum TusWriteError {
+    /// The offset of the request does not match the current offset of the upload.
+    #[error("upload offset mismatch: expected {expected}, got {actual}")]
+    OffsetMismatch { expected: u64, actual: u64 },
+    /// The checksum of the received chunk does not match the one in the request.
+    #[error("checksum mismatch")]
+    ChecksumMismatch,
+    #[error("{0}")]
+    Write(#[from] WriteError),
+}
+
+/// A checksum algorithm of the tus checksum extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TusChecksumAlgorithm {
+    Sha1,
+    Crc32,
+}
+
+impl TusChecksumAlgorithm {
+    pub const ALL: [Self; 2] = [Self::Sha1, Self::Crc32];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "sha1" => Some(Self::Sha1),
+            "crc32" => Some(Self::Crc32),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Sha1 => "sha1",
+            Self::Crc32 => "crc32",
+        }
+    }
+}
+
+/// A checksum sent in an `Upload-Checksum` header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TusChecksum {
+    pub algorithm: TusChecksumAlgorithm,
+    pub digest: Vec<u8>,
+}
+
+impl TusChecksum {
+    /// Parses an `Upload-Checksum` header value, which is an algorithm name and a Base64 digest separated by a space.
+    /// Returns `None` if the value is malformed or the algorithm is not supported.
+    pub fn parse(value: &str) -> Option<Self> {
+        let (algorithm, digest) = value.trim().split_once(' ')?;
+        let algorithm = TusChecksumAlgorithm::from_name(algorithm)?;
+        let digest = STANDARD.decode(digest.trim()).ok()?;
+
+        Some(Self { algorithm, digest })
+    }
+}
+
+enum ChecksumHasher {
+    Sha1(Sha1),
+    Crc32(crc32fast::Hasher),
+}
+
+impl ChecksumHasher {
+    fn new(algorithm: TusChecksumAlgorithm) -> Self {
+        match algorithm {
+            TusChecksumAlgorithm::Sha1 => Self::Sha1(Sha1::new()),
+            TusChecksumAlgorithm::Crc32 => Self::Crc32(crc32fast::Hasher::new()),
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Self::Sha1(hasher) => hasher.update(data),
+            Self::Crc32(hasher) => hasher.update(data),
+        }
+    }
+
+    fn finalize(self) -> Vec<u8> {
+        match self {
+            Self::Sha1(hasher) => hasher.finalize().to_vec(),
+            Self::Crc32(hasher) => hasher.finalize().to_be_bytes().to_vec(),
+        }
+    }
+}
+
+/// Parses an `Upload-Metadata` header value.
+/// It is a comma-separated list of keys, each optionally followed by a space and a Base64 value.
+/// Returns `None` if a value is not valid Base64 or UTF-8.
+pub fn parse_tus_metadata(value: &str) -> Option<HashMap<String, Option<String>>> {
+    let mut metadata = HashMap::new();
+
+    for pair in value.split(',') {
+        let pair = pair.trim();
+
+        if pair.is_empty() {
+            continue;
+        }
+
+        let (key, value) = match pair.split_once(' ') {
+            Some((key, value)) => {
+                let value = STANDARD.decode(value.trim()).ok()?;
+                (key, Some(String::from_utf8(value).ok()?))
+            }
+            None => (pair, None),
+        };
+
+        metadata.insert(key.to_owned(), value);
+    }
+
+    Some(metadata)
+}
+
+/// Implements the tus resumable upload protocol on top of staging files.
+/// Each upload is a staging file whose expected size is the declared upload length.
+pub struct TusService {
+    staging_file_service: Arc<StagingFileService>,
+    temp_base_path: PathBuf,
+}
+
+impl TusService {
+    pub fn new(
+        staging_file_service: Arc<StagingFileService>,
+        temp_base_path: impl Into<PathBuf>,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            staging_file_service,
+            temp_base_path: temp_base_path.into(),
+        })
+    }
+
+    /// Creates an upload of `length` bytes.
+    pub async fn create_upload(
+        &self,
+        name: &str,
+        mime: Option<&str>,
+        length: u64,
+    ) -> Result<StagingFile, TusServiceError> {
+        let staging_file = self
+            .staging_file_service
+            .create_staging_file(name, mime, Some(length), None)
+            .await?;
+
+        Ok(staging_file)
+    }
+
+    /// Retrieves an upload by its ID.
+    pub async fn get_upload(&self, id: Uuid) -> Result<Option<StagingFile>, TusServiceError> {
+        let staging_file = self.staging_file_service.get_staging_file_by_id(id).await?;
+
+        Ok(staging_file)
+    }
+
+    /// Appends a chunk to an upload at `offset`, which must be the current offset of the upload.
+    /// If `checksum` is given, the chunk is buffered in a temporary file and only appended if it matches.
+    /// Returns the updated upload, or `None` if no upload was found.
+    pub async fn write_chunk(
+        &self,
+        id: Uuid,
+        offset: u64,
+        checksum: Option<&TusChecksum>,
+        mut stream: WriteStream<'_>,
+    ) -> Result<Result<Option<StagingFile>, TusWriteError>, TusServiceError> {
+        let staging_file = match self.get_upload(id).await? {
+            Some(staging_file) => staging_file,
+            None => return Ok(Ok(None)),
+        };
+
+        if staging_file.size as u64 != offset {
+            return Ok(Err(TusWriteError::OffsetMismatch {
+                expected: staging_file.size as u64,
+                actual: offset,
+            }));
+        }
+
+        let checksum = match checksum {
+            Some(checksum) => checksum,
+            None => {
+                let result = self
+                    .staging_file_service
+                    .fill_staging_file_by_id(id, Some(offset), stream)
+                    .await?;
+                return Ok(result.map_err(TusWriteError::from));
+            }
+        };
+
+        let temp_path = self
+            .temp_base_path
+            .join(format!("tus-{}-{}", id, Uuid::new_v4()));
+        let result: Result<Result<Option<StagingFile>, TusWriteError>, TusServiceError> = async {
+            let mut temp_file = tokio::fs::File::create(&temp_path).await?;
+            let mut hasher = ChecksumHasher::new(checksum.algorithm);
+            let mut buffer = vec![0u8; 64 * 1024];
+
+            loop {
+                let read = stream.read(&mut buffer).await?;
+
+                if read == 0 {
+                    break;
+                }
+
+                hasher.update(&buffer[..read]);
+                temp_file.write_all(&buffer[..read]).await?;
+            }
+
+            temp_file.flush().await?;
+
+            if hasher.finalize() != checksum.digest {
+                return Ok(Err(TusWriteError::ChecksumMismatch));
+            }
+
+            let temp_file = tokio::fs::File::open(&temp_path).await?;
+            let result = self
+                .staging_file_service
+                .fill_staging_file_by_id(id, Some(offset), Box::pin(temp_file))
+                .await?;
+
+            Ok(result.map_err(TusWriteError::from))
+        }
+        .await;
+
+        // it is safe to ignore the result, as the temporary directory is cleaned up eventually
+        tokio::fs::remove_file(&temp_path).await.ok();
+
+        result
+    }
+
+    /// Terminates an upload, removing its data.
+    /// Returns the removed upload, or `None` if no upload was found.
+    pub async fn remove_upload(&self, id: Uuid) -> Result<Option<StagingFile>, TusServiceError> {
+        let staging_file = self
+            .staging_file_service
+            .remove_staging_file_by_id(id, None, true)
+            .await?;
+
+        Ok(staging_file)
+    }
+}
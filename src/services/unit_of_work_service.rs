@@ -0,0 +1,129 @@
+use diesel_async::{
+    pooled_connection::deadpool::{Object, Pool, PoolError},
+    AnsiTransactionManager, AsyncPgConnection, TransactionManager,
+};
+use std::{
+    future::Future,
+    ops::{Deref, DerefMut},
+    pin::Pin,
+    sync::Arc,
+};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum UnitOfWorkServiceError {
+    #[error("database pool error: {0}")]
+    Pool(#[from] PoolError),
+    #[error("diesel error: {0}")]
+    Diesel(#[from] diesel::result::Error),
+}
+
+type AfterCommitTask = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// A database transaction shared by several service calls, so that they take effect together or not at all.
+/// Service methods that can take part in one accept `Option<&mut UnitOfWork>`, and run on their own connection if `None` is given.
+///
+/// Work outside the database, e.g. indexing, is deferred by [`UnitOfWork::after_commit`] until the transaction commits.
+/// If the unit of work is dropped without being committed, the transaction is rolled back,
+/// since the pool never hands out a connection left in a transaction.
+pub struct UnitOfWork {
+    db: Object<AsyncPgConnection>,
+    after_commit_tasks: Vec<AfterCommitTask>,
+}
+
+impl UnitOfWork {
+    /// Begins a new unit of work on a connection from `db_pool`.
+    pub(crate) async fn begin(
+        db_pool: &Pool<AsyncPgConnection>,
+    ) -> Result<Self, UnitOfWorkServiceError> {
+        let mut db = db_pool.get().await?;
+        AnsiTransactionManager::begin_transaction(&mut *db).await?;
+
+        Ok(Self {
+            db,
+            after_commit_tasks: Vec::new(),
+        })
+    }
+
+    /// The connection to run the queries of the unit of work on.
+    pub fn db(&mut self) -> &mut AsyncPgConnection {
+        &mut self.db
+    }
+
+    /// Defers a task until the unit of work commits. The task is dropped if it rolls back.
+    pub fn after_commit(&mut self, task: impl Future<Output = ()> + Send + 'static) {
+        self.after_commit_tasks.push(Box::pin(task));
+    }
+
+    /// Commits the transaction, then runs the deferred tasks in the order they were added.
+    pub async fn commit(mut self) -> Result<(), UnitOfWorkServiceError> {
+        AnsiTransactionManager::commit_transaction(&mut *self.db).await?;
+
+        for task in self.after_commit_tasks.drain(..) {
+            task.await;
+        }
+
+        Ok(())
+    }
+
+    /// Rolls back the transaction, and drops the deferred tasks.
+    pub async fn rollback(mut self) -> Result<(), UnitOfWorkServiceError> {
+        AnsiTransactionManager::rollback_transaction(&mut *self.db).await?;
+        Ok(())
+    }
+}
+
+/// Opens units of work for controllers that call several services at once.
+pub struct UnitOfWorkService {
+    db_pool: Pool<AsyncPgConnection>,
+}
+
+impl UnitOfWorkService {
+    pub fn new(db_pool: Pool<AsyncPgConnection>) -> Arc<Self> {
+        Arc::new(Self { db_pool })
+    }
+
+    /// Begins a new unit of work on a connection from the pool.
+    pub async fn begin(&self) -> Result<UnitOfWork, UnitOfWorkServiceError> {
+        UnitOfWork::begin(&self.db_pool).await
+    }
+}
+
+/// The connection a service method runs its queries on:
+/// the one of the caller's unit of work if there is one, or its own one from the pool otherwise.
+pub(crate) enum UnitOfWorkConnection<'a> {
+    Borrowed(&'a mut AsyncPgConnection),
+    Pooled(Object<AsyncPgConnection>),
+}
+
+impl<'a> UnitOfWorkConnection<'a> {
+    pub(crate) async fn acquire(
+        db_pool: &Pool<AsyncPgConnection>,
+        db: Option<&'a mut AsyncPgConnection>,
+    ) -> Result<Self, PoolError> {
+        match db {
+            Some(db) => Ok(Self::Borrowed(db)),
+            None => Ok(Self::Pooled(db_pool.get().await?)),
+        }
+    }
+}
+
+impl Deref for UnitOfWorkConnection<'_> {
+    type Target = AsyncPgConnection;
+
+    fn deref(&self) -> &Self::Target {
+        match self {
+            Self::Borrowed(db) => db,
+            Self::Pooled(db) => db,
+        }
+    }
+}
+
+impl DerefMut for UnitOfWorkConnection<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        match self {
+            Self::Borrowed(db) => db,
+            Self::Pooled(db) => db,
+        }
+    }
+}
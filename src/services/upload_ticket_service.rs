@@ -0,0 +1,149 @@
+use super::PasswordService;
+use crate::{
+    config::AppUploadTicket,
+    db::models::{CreatingUploadTicket, UploadTicket},
+};
+use chrono::{Duration, Utc};
+use diesel::{ExpressionMethods, OptionalExtension, QueryDsl};
+use diesel_async::{pooled_connection::deadpool::Pool, AsyncPgConnection, RunQueryDsl};
+use std::sync::Arc;
+use thiserror::Error;
+use uuid::Uuid;
+
+#[derive(Error, Debug)]
+pub enum UploadTicketServiceError {
+    #[error("database pool error: {0}")]
+    Pool(#[from] diesel_async::pooled_connection::deadpool::PoolError),
+    #[error("diesel error: {0}")]
+    Diesel(#[from] diesel::result::Error),
+}
+
+pub struct UploadTicketService {
+    db_pool: Pool<AsyncPgConnection>,
+    password_service: Arc<PasswordService>,
+    expiration: Duration,
+}
+
+impl UploadTicketService {
+    pub fn new(
+        db_pool: Pool<AsyncPgConnection>,
+        password_service: Arc<PasswordService>,
+        config: &AppUploadTicket,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            db_pool,
+            password_service,
+            expiration: Duration::seconds(config.expiration as i64),
+        })
+    }
+
+    /// Creates `count` single-use upload tickets bound to the given user session.
+    /// Each ticket accepts one upload of at most `max_size` bytes before it expires.
+    /// The tickets are voided when the user session is removed.
+    pub async fn create_upload_tickets(
+        &self,
+        user_id: i32,
+        user_session_token: &str,
+        count: u32,
+        max_size: u64,
+    ) -> Result<Vec<UploadTicket>, UploadTicketServiceError> {
+        use crate::db::schema;
+
+        let expires_at = Utc::now().naive_utc() + self.expiration;
+        let tokens = (0..count)
+            .map(|_| self.password_service.generate_url_safe_token_252())
+            .collect::<Vec<_>>();
+        let creating_tickets = tokens
+            .iter()
+            .map(|token| CreatingUploadTicket {
+                token,
+                user_id,
+                user_session_token,
+                max_size: max_size as i64,
+                expires_at,
+            })
+            .collect::<Vec<_>>();
+
+        let db = &mut self.db_pool.get().await?;
+        let tickets = diesel::insert_into(schema::upload_tickets::table)
+            .values(&creating_tickets)
+            .returning((
+                schema::upload_tickets::token,
+                schema::upload_tickets::user_id,
+                schema::upload_tickets::max_size,
+                schema::upload_tickets::file_id,
+                schema::upload_tickets::created_at,
+                schema::upload_tickets::expires_at,
+                schema::upload_tickets::used_at,
+            ))
+            .get_results::<UploadTicket>(db)
+            .await?;
+
+        Ok(tickets)
+    }
+
+    /// Claims an upload ticket for an upload.
+    /// Returns `None` if the ticket does not exist, has been used, has expired or its session is gone.
+    /// A claimed ticket can never be claimed again, even if the upload fails afterwards.
+    pub async fn claim_upload_ticket(
+        &self,
+        token: &str,
+    ) -> Result<Option<UploadTicket>, UploadTicketServiceError> {
+        use crate::db::schema;
+
+        let now = Utc::now().naive_utc();
+
+        let db = &mut self.db_pool.get().await?;
+        let ticket = diesel::update(
+            schema::upload_tickets::table
+                .filter(schema::upload_tickets::token.eq(token))
+                .filter(schema::upload_tickets::used_at.is_null())
+                .filter(schema::upload_tickets::user_session_token.is_not_null())
+                .filter(schema::upload_tickets::expires_at.gt(now)),
+        )
+        .set(schema::upload_tickets::used_at.eq(now))
+        .returning((
+            schema::upload_tickets::token,
+            schema::upload_tickets::user_id,
+            schema::upload_tickets::max_size,
+            schema::upload_tickets::file_id,
+            schema::upload_tickets::created_at,
+            schema::upload_tickets::expires_at,
+            schema::upload_tickets::used_at,
+        ))
+        .get_result::<UploadTicket>(db)
+        .await
+        .optional()?;
+
+        Ok(ticket)
+    }
+
+    /// Records the file uploaded with a claimed upload ticket, so that the upload can be audited.
+    pub async fn record_uploaded_file(
+        &self,
+        token: &str,
+        file_id: Uuid,
+    ) -> Result<Option<UploadTicket>, UploadTicketServiceError> {
+        use crate::db::schema;
+
+        let db = &mut self.db_pool.get().await?;
+        let ticket = diesel::update(
+            schema::upload_tickets::table.filter(schema::upload_tickets::token.eq(token)),
+        )
+        .set(schema::upload_tickets::file_id.eq(file_id))
+        .returning((
+            schema::upload_tickets::token,
+            schema::upload_tickets::user_id,
+            schema::upload_tickets::max_size,
+            schema::upload_tickets::file_id,
+            schema::upload_tickets::created_at,
+            schema::upload_tickets::expires_at,
+            schema::upload_tickets::used_at,
+        ))
+        .get_result::<UploadTicket>(db)
+        .await
+        .optional()?;
+
+        Ok(ticket)
+    }
+}
@@ -1,9 +1,17 @@
-use super::{password_service, PasswordService};
+use super::{password_service, Page, PasswordService};
 use crate::db::models::{CreatingUser, User};
-use diesel::{ExpressionMethods, OptionalExtension, QueryDsl};
-use diesel_async::{pooled_connection::deadpool::Pool, AsyncPgConnection, RunQueryDsl};
+use diesel::{
+    sql_types::{Nullable, Timestamp},
+    ExpressionMethods, OptionalExtension, QueryDsl,
+};
+use diesel_async::{
+    pooled_connection::deadpool::Pool, scoped_futures::ScopedFutureExt, AsyncConnection,
+    AsyncPgConnection, RunQueryDsl,
+};
+use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use thiserror::Error;
+use uuid::Uuid;
 
 #[derive(Error, Debug)]
 pub enum UserServiceError {
@@ -13,6 +21,41 @@ pub enum UserServiceError {
     Diesel(#[from] diesel::result::Error),
     #[error("{0}")]
     PasswordService(#[from] password_service::PasswordServiceError),
+    #[error("a user with the email `{0}` already exists")]
+    DuplicateEmail(String),
+    #[error("a user with the username `{0}` already exists")]
+    DuplicateUsername(String),
+}
+
+/// The maximum size of the preferences of a user, serialized as JSON.
+pub const MAX_USER_PREFERENCES_SIZE: usize = 16 * 1024;
+
+#[derive(Error, Debug)]
+pub enum SetUserPreferencesError {
+    #[error("preferences should be a JSON object")]
+    NotAnObject,
+    #[error("preferences of {size} bytes exceed the maximum size of {max_size} bytes")]
+    TooLarge { size: usize, max_size: usize },
+    #[error("{0}")]
+    Error(#[from] UserServiceError),
+}
+
+/// The role of a user, which decides what they are allowed to do.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum UserRole {
+    #[default]
+    User,
+    Admin,
+}
+
+impl UserRole {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::User => "user",
+            Self::Admin => "admin",
+        }
+    }
 }
 
 pub struct UserService {
@@ -32,13 +75,13 @@ impl UserService {
     }
 
     /// Creates a new user. Their password will be hashed before being stored in the database.
-    /// Returns the created user, or `None` if the user with the same email already exists.
+    /// Fails with `DuplicateEmail` or `DuplicateUsername` if another user already has the email or the username.
     pub async fn create_user(
         &self,
         username: &str,
         email: &str,
         password: &str,
-    ) -> Result<Option<User>, UserServiceError> {
+    ) -> Result<User, UserServiceError> {
         use crate::db::schema;
 
         let password_hash = self.password_service.hash_password(password)?;
@@ -55,20 +98,30 @@ impl UserService {
                 schema::users::username,
                 schema::users::email,
                 schema::users::joined_at,
+                schema::users::display_name,
+                schema::users::avatar_file_id,
+                schema::users::role,
+                schema::users::disabled_at,
             ))
             .get_result::<User>(db)
             .await;
 
-        let user = match user {
-            Ok(user) => Some(user),
+        match user {
+            Ok(user) => Ok(user),
             Err(diesel::result::Error::DatabaseError(
                 diesel::result::DatabaseErrorKind::UniqueViolation,
                 err,
-            )) if err.constraint_name() == Some("users_email_idx") => None,
-            Err(err) => return Err(err.into()),
-        };
-
-        Ok(user)
+            )) if err.constraint_name() == Some("users_email_idx") => {
+                Err(UserServiceError::DuplicateEmail(email.to_owned()))
+            }
+            Err(diesel::result::Error::DatabaseError(
+                diesel::result::DatabaseErrorKind::UniqueViolation,
+                err,
+            )) if err.constraint_name() == Some("users_username_idx") => {
+                Err(UserServiceError::DuplicateUsername(username.to_owned()))
+            }
+            Err(err) => Err(err.into()),
+        }
     }
 
     /// Removes a user by their ID.
@@ -84,6 +137,10 @@ impl UserService {
                     schema::users::username,
                     schema::users::email,
                     schema::users::joined_at,
+                    schema::users::display_name,
+                    schema::users::avatar_file_id,
+                    schema::users::role,
+                    schema::users::disabled_at,
                 ))
                 .get_result::<User>(db)
                 .await
@@ -95,14 +152,23 @@ impl UserService {
     /// Retrieves a list of users.
     /// The result will be sorted by user ID in ascending order.
     /// If `last_user_id` is provided, the result will start after the user with that ID.
+    /// The number of all users is counted only if `with_total` is set.
     pub async fn get_users(
         &self,
         last_user_id: Option<i32>,
         limit: u32,
-    ) -> Result<Vec<User>, UserServiceError> {
+        with_total: bool,
+    ) -> Result<Page<User, i32>, UserServiceError> {
         use crate::db::schema;
 
         let db = &mut self.db_pool.get().await?;
+
+        let total = if with_total {
+            Some(schema::users::table.count().get_result::<i64>(db).await?)
+        } else {
+            None
+        };
+
         let users = schema::users::dsl::users
             .filter(schema::users::id.gt(last_user_id.unwrap_or(0)))
             .select((
@@ -110,13 +176,18 @@ impl UserService {
                 schema::users::username,
                 schema::users::email,
                 schema::users::joined_at,
+                schema::users::display_name,
+                schema::users::avatar_file_id,
+                schema::users::role,
+                schema::users::disabled_at,
             ))
             .order(schema::users::id.asc())
-            .limit(limit as i64)
+            // fetch one more user to tell whether there is a next page
+            .limit(limit as i64 + 1)
             .load::<User>(db)
             .await?;
 
-        Ok(users)
+        Ok(Page::new(users, limit, total, |user| user.id))
     }
 
     /// Retrieves a user by their ID.
@@ -131,6 +202,10 @@ impl UserService {
                 schema::users::username,
                 schema::users::email,
                 schema::users::joined_at,
+                schema::users::display_name,
+                schema::users::avatar_file_id,
+                schema::users::role,
+                schema::users::disabled_at,
             ))
             .first::<User>(db)
             .await
@@ -154,6 +229,10 @@ impl UserService {
                 schema::users::username,
                 schema::users::email,
                 schema::users::joined_at,
+                schema::users::display_name,
+                schema::users::avatar_file_id,
+                schema::users::role,
+                schema::users::disabled_at,
             ))
             .first::<User>(db)
             .await
@@ -162,8 +241,9 @@ impl UserService {
         Ok(user)
     }
 
-    /// Updates a user's email by their ID.
+    /// Updates a user's username by their ID.
     /// Returns the updated user, or `None` if the user was not found.
+    /// Fails with `DuplicateUsername` if another user already has the username.
     pub async fn set_user_username_by_id(
         &self,
         user_id: i32,
@@ -180,12 +260,25 @@ impl UserService {
                     schema::users::username,
                     schema::users::email,
                     schema::users::joined_at,
+                    schema::users::display_name,
+                    schema::users::avatar_file_id,
+                    schema::users::role,
+                    schema::users::disabled_at,
                 ))
                 .get_result::<User>(db)
                 .await
-                .optional()?;
+                .optional();
 
-        Ok(updated_user)
+        match updated_user {
+            Ok(updated_user) => Ok(updated_user),
+            Err(diesel::result::Error::DatabaseError(
+                diesel::result::DatabaseErrorKind::UniqueViolation,
+                err,
+            )) if err.constraint_name() == Some("users_username_idx") => {
+                Err(UserServiceError::DuplicateUsername(new_username.to_owned()))
+            }
+            Err(err) => Err(err.into()),
+        }
     }
 
     /// Updates a user's password by their ID.
@@ -209,6 +302,40 @@ impl UserService {
                     schema::users::username,
                     schema::users::email,
                     schema::users::joined_at,
+                    schema::users::display_name,
+                    schema::users::avatar_file_id,
+                    schema::users::role,
+                    schema::users::disabled_at,
+                ))
+                .get_result::<User>(db)
+                .await
+                .optional()?;
+
+        Ok(updated_user)
+    }
+
+    /// Updates a user's display name by their ID. `None` clears the display name.
+    /// Returns the updated user, or `None` if the user was not found.
+    pub async fn set_user_display_name_by_id(
+        &self,
+        user_id: i32,
+        new_display_name: Option<&str>,
+    ) -> Result<Option<User>, UserServiceError> {
+        use crate::db::schema;
+
+        let db = &mut self.db_pool.get().await?;
+        let updated_user =
+            diesel::update(schema::users::dsl::users.filter(schema::users::id.eq(user_id)))
+                .set(schema::users::display_name.eq(new_display_name))
+                .returning((
+                    schema::users::id,
+                    schema::users::username,
+                    schema::users::email,
+                    schema::users::joined_at,
+                    schema::users::display_name,
+                    schema::users::avatar_file_id,
+                    schema::users::role,
+                    schema::users::disabled_at,
                 ))
                 .get_result::<User>(db)
                 .await
@@ -216,4 +343,178 @@ impl UserService {
 
         Ok(updated_user)
     }
+
+    /// Updates a user's role by their ID.
+    /// Returns the updated user, or `None` if the user was not found.
+    pub async fn set_user_role_by_id(
+        &self,
+        user_id: i32,
+        new_role: UserRole,
+    ) -> Result<Option<User>, UserServiceError> {
+        use crate::db::schema;
+
+        let db = &mut self.db_pool.get().await?;
+        let updated_user =
+            diesel::update(schema::users::dsl::users.filter(schema::users::id.eq(user_id)))
+                .set(schema::users::role.eq(new_role.name()))
+                .returning((
+                    schema::users::id,
+                    schema::users::username,
+                    schema::users::email,
+                    schema::users::joined_at,
+                    schema::users::display_name,
+                    schema::users::avatar_file_id,
+                    schema::users::role,
+                    schema::users::disabled_at,
+                ))
+                .get_result::<User>(db)
+                .await
+                .optional()?;
+
+        Ok(updated_user)
+    }
+
+    /// Disables or enables a user by their ID. Disabled users cannot log in, but their files and audit history are kept.
+    /// Disabling a user who is already disabled keeps the time they have been disabled at.
+    /// Returns the updated user, or `None` if the user was not found.
+    pub async fn set_user_disabled_by_id(
+        &self,
+        user_id: i32,
+        disabled: bool,
+    ) -> Result<Option<User>, UserServiceError> {
+        use crate::db::schema;
+
+        let disabled_at = diesel::dsl::sql::<Nullable<Timestamp>>(if disabled {
+            "COALESCE(disabled_at, NOW())"
+        } else {
+            "NULL"
+        });
+
+        let db = &mut self.db_pool.get().await?;
+        let updated_user =
+            diesel::update(schema::users::dsl::users.filter(schema::users::id.eq(user_id)))
+                .set(schema::users::disabled_at.eq(disabled_at))
+                .returning((
+                    schema::users::id,
+                    schema::users::username,
+                    schema::users::email,
+                    schema::users::joined_at,
+                    schema::users::display_name,
+                    schema::users::avatar_file_id,
+                    schema::users::role,
+                    schema::users::disabled_at,
+                ))
+                .get_result::<User>(db)
+                .await
+                .optional()?;
+
+        Ok(updated_user)
+    }
+
+    /// Updates a user's avatar by their ID. `None` clears the avatar.
+    /// Returns the updated user along with the ID of the previous avatar file, or `None` if the user was not found.
+    pub async fn set_user_avatar_by_id(
+        &self,
+        user_id: i32,
+        avatar_file_id: Option<Uuid>,
+    ) -> Result<Option<(User, Option<Uuid>)>, UserServiceError> {
+        use crate::db::schema;
+
+        let db = &mut self.db_pool.get().await?;
+        let result = db
+            .transaction(|db| {
+                async move {
+                    let previous_avatar_file_id = schema::users::table
+                        .filter(schema::users::id.eq(user_id))
+                        .select(schema::users::avatar_file_id)
+                        .for_update()
+                        .first::<Option<Uuid>>(db)
+                        .await
+                        .optional()?;
+
+                    let previous_avatar_file_id = match previous_avatar_file_id {
+                        Some(previous_avatar_file_id) => previous_avatar_file_id,
+                        None => return Ok(None),
+                    };
+
+                    let user =
+                        diesel::update(schema::users::table.filter(schema::users::id.eq(user_id)))
+                            .set(schema::users::avatar_file_id.eq(avatar_file_id))
+                            .returning((
+                                schema::users::id,
+                                schema::users::username,
+                                schema::users::email,
+                                schema::users::joined_at,
+                                schema::users::display_name,
+                                schema::users::avatar_file_id,
+                                schema::users::role,
+                                schema::users::disabled_at,
+                            ))
+                            .get_result::<User>(db)
+                            .await?;
+
+                    Ok::<_, UserServiceError>(Some((user, previous_avatar_file_id)))
+                }
+                .scope_boxed()
+            })
+            .await?;
+
+        Ok(result)
+    }
+
+    /// Retrieves the preferences of a user by their ID.
+    /// Preferences are opaque to the server; clients store their settings in them.
+    /// Returns `None` if the user was not found.
+    pub async fn get_user_preferences_by_id(
+        &self,
+        user_id: i32,
+    ) -> Result<Option<serde_json::Value>, UserServiceError> {
+        use crate::db::schema;
+
+        let db = &mut self.db_pool.get().await?;
+        let preferences = schema::users::table
+            .filter(schema::users::id.eq(user_id))
+            .select(schema::users::user_preferences)
+            .first::<serde_json::Value>(db)
+            .await
+            .optional()?;
+
+        Ok(preferences)
+    }
+
+    /// Replaces the preferences of a user by their ID.
+    /// The preferences should be a JSON object of at most [`MAX_USER_PREFERENCES_SIZE`] bytes.
+    /// Returns the updated preferences, or `None` if the user was not found.
+    pub async fn set_user_preferences_by_id(
+        &self,
+        user_id: i32,
+        preferences: &serde_json::Value,
+    ) -> Result<Option<serde_json::Value>, SetUserPreferencesError> {
+        use crate::db::schema;
+
+        if !preferences.is_object() {
+            return Err(SetUserPreferencesError::NotAnObject);
+        }
+
+        let size = preferences.to_string().len();
+
+        if MAX_USER_PREFERENCES_SIZE < size {
+            return Err(SetUserPreferencesError::TooLarge {
+                size,
+                max_size: MAX_USER_PREFERENCES_SIZE,
+            });
+        }
+
+        let db = &mut self.db_pool.get().await.map_err(UserServiceError::from)?;
+        let preferences =
+            diesel::update(schema::users::table.filter(schema::users::id.eq(user_id)))
+                .set(schema::users::user_preferences.eq(preferences))
+                .returning(schema::users::user_preferences)
+                .get_result::<serde_json::Value>(db)
+                .await
+                .optional()
+                .map_err(UserServiceError::from)?;
+
+        Ok(preferences)
+    }
 }
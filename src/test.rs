@@ -1,17 +1,70 @@
 use crate::{
-    config::AppConfig,
+    config::{AppConfig, SearchBackend, StorageConfig},
     create_rocket_instance,
     db::{self, test::DatabaseDropper},
-    services::test::IndexDropper,
+    services::{test::IndexDropper, tiered_file_driver::CacheEvictionPolicy},
     setup_rocket_instance,
 };
-use rocket::{Build, Rocket};
-use std::path::PathBuf;
+use rocket::{data::ToByteUnit, Build, Rocket};
+use std::{
+    path::PathBuf,
+    sync::{atomic::AtomicBool, Arc},
+};
 use uuid::Uuid;
 
-/// Creates a new Rocket instance for testing.
+/// The file driver to create a Rocket instance for testing with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TestFileDriver {
+    /// Keeps the files in memory. Tests with it are faster, and do not leave files behind.
+    Memory,
+    /// Keeps the files under the paths in the config, like the server does.
+    /// Tests that inspect the stored files on disk should use it.
+    Local,
+    /// Keeps the files in memory, behind a hot cache in a directory of the test under the temporary path.
+    Tiered,
+}
+
+/// The faults to inject into a Rocket instance for testing, to test how the services recover from them.
+/// Each of them can be turned on and off while the instance is running.
+#[derive(Debug, Clone, Default)]
+pub struct TestFaults {
+    /// Fails the commits of staging files in the file driver.
+    pub fail_commits: Arc<AtomicBool>,
+    /// Fails the indexing of files in the search service.
+    pub fail_file_indexing: Arc<AtomicBool>,
+}
+
+/// Creates a new Rocket instance for testing, with the files kept in memory.
 /// It creates a new database for the test and runs the migrations.
 pub async fn create_test_rocket_instance() -> (Rocket<Build>, DatabaseDropper, IndexDropper) {
+    create_test_rocket_instance_with_file_driver(TestFileDriver::Memory).await
+}
+
+/// Creates a new Rocket instance for testing, with the given file driver.
+/// It creates a new database for the test and runs the migrations.
+pub async fn create_test_rocket_instance_with_file_driver(
+    file_driver: TestFileDriver,
+) -> (Rocket<Build>, DatabaseDropper, IndexDropper) {
+    create_test_rocket_instance_with_options(file_driver, SearchBackend::Meilisearch).await
+}
+
+/// Creates a new Rocket instance for testing, with the given file driver and search backend.
+/// It creates a new database for the test and runs the migrations.
+pub async fn create_test_rocket_instance_with_options(
+    file_driver: TestFileDriver,
+    search_backend: SearchBackend,
+) -> (Rocket<Build>, DatabaseDropper, IndexDropper) {
+    create_test_rocket_instance_with_config(file_driver, search_backend, |_| {}).await
+}
+
+/// Creates a new Rocket instance for testing, with the given file driver and search backend.
+/// The loaded config is passed to `configure` before the instance is created, so tests can turn on optional behaviors.
+/// It creates a new database for the test and runs the migrations.
+pub async fn create_test_rocket_instance_with_config(
+    file_driver: TestFileDriver,
+    search_backend: SearchBackend,
+    configure: impl FnOnce(&mut AppConfig),
+) -> (Rocket<Build>, DatabaseDropper, IndexDropper) {
     let mut app_config = AppConfig::load(None as Option<PathBuf>).unwrap();
 
     let database_url_base = app_config.database_url_base.clone();
@@ -25,6 +78,20 @@ pub async fn create_test_rocket_instance() -> (Rocket<Build>, DatabaseDropper, I
 
     app_config.database_name = database_name.clone();
     app_config.meilisearch_index_prefix = Some(index_prefix.clone());
+    app_config.search.backend = search_backend;
+    app_config.storage = match file_driver {
+        TestFileDriver::Memory => StorageConfig::Memory,
+        TestFileDriver::Local => StorageConfig::Local,
+        TestFileDriver::Tiered => StorageConfig::Tiered {
+            cold: Box::new(StorageConfig::Memory),
+            cache_path: app_config
+                .temp_base_path
+                .join(format!("__test_cache_{}", id)),
+            cache_size: 1.mebibytes(),
+            eviction_policy: CacheEvictionPolicy::Lru,
+        },
+    };
+    configure(&mut app_config);
 
     let index_dropper = IndexDropper::new(
         &app_config.meilisearch_url,
@@ -50,8 +117,9 @@ pub mod helpers {
     };
 
     use crate::{
-        db::models::{File, StagingFile, User, UserSession},
-        services::{AuthService, FileService, StagingFileService, UserService},
+        db::models::{Collection, File, StagingFile, User, UserSession},
+        routes::{collection::dto::ListedCollection, file::dto::ListedFile},
+        services::{AuthService, FileService, StagingFileService, UserRole, UserService},
     };
 
     pub async fn create_user(id: &str, user_service: &UserService) -> User {
@@ -62,7 +130,6 @@ pub mod helpers {
                 &format!("{}_user_pw", id),
             )
             .await
-            .unwrap()
             .unwrap();
         user
     }
@@ -72,7 +139,23 @@ pub mod helpers {
         user_service: &UserService,
     ) -> (User, UserSession) {
         let user = create_user("initial", user_service).await;
-        let user_session = auth_service.create_user_session(user.id).await.unwrap();
+        let user_session = auth_service
+            .create_user_session(user.id, None, None)
+            .await
+            .unwrap();
+        (user, user_session)
+    }
+
+    pub async fn create_initial_admin_user(
+        auth_service: &AuthService,
+        user_service: &UserService,
+    ) -> (User, UserSession) {
+        let (user, user_session) = create_initial_user(auth_service, user_service).await;
+        let user = user_service
+            .set_user_role_by_id(user.id, UserRole::Admin)
+            .await
+            .unwrap()
+            .unwrap();
         (user, user_session)
     }
 
@@ -88,7 +171,7 @@ pub mod helpers {
         let mime = mime.as_ref().map(|mime| mime.as_ref());
 
         let staging_file = staging_file_service
-            .create_staging_file(name, mime)
+            .create_staging_file(name, mime, None, None)
             .await
             .unwrap();
 
@@ -138,4 +221,17 @@ pub mod helpers {
 
         file
     }
+
+    /// Strips the favorite flags from listed files, for comparing them against files.
+    pub fn unlist_files(files: &[ListedFile]) -> Vec<File> {
+        files.iter().map(|listed| listed.file.clone()).collect()
+    }
+
+    /// Strips the favorite flags from listed collections, for comparing them against collections.
+    pub fn unlist_collections(collections: &[ListedCollection]) -> Vec<Collection> {
+        collections
+            .iter()
+            .map(|listed| listed.collection.clone())
+            .collect()
+    }
 }
@@ -0,0 +1,137 @@
+use crate::dto::{Error, FieldError};
+
+/// The longest email address that can be delivered to, as limited by SMTP.
+pub const MAX_EMAIL_LENGTH: usize = 254;
+/// The longest usernames and display names.
+pub const MAX_USER_NAME_LENGTH: usize = 64;
+pub const MAX_PASSWORD_LENGTH: usize = 1024;
+/// The longest names of files and collections.
+pub const MAX_NAME_LENGTH: usize = 256;
+pub const MAX_DESCRIPTION_LENGTH: usize = 4096;
+pub const MAX_COMMENT_LENGTH: usize = 4096;
+/// The largest page a search request can ask for.
+pub const MAX_SEARCH_LIMIT: u32 = 100;
+/// The most IDs a batch request can name at once.
+pub const MAX_BATCH_SIZE: usize = 100;
+/// The most attributes a file can have.
+pub const MAX_FILE_ATTRIBUTES: usize = 64;
+pub const MAX_ATTRIBUTE_KEY_LENGTH: usize = 64;
+pub const MAX_ATTRIBUTE_VALUE_LENGTH: usize = 256;
+
+/// Checks the fields of a request body before it reaches services.
+/// Implementors report every invalid field at once, so clients can show all problems together.
+pub trait Validate {
+    fn validate_fields(&self, errors: &mut FieldErrors);
+
+    /// Validates the body, returning a `422 Unprocessable Entity` error listing the invalid fields.
+    fn validate(&self) -> Result<(), Error> {
+        let mut errors = FieldErrors::default();
+        self.validate_fields(&mut errors);
+        errors.into_result()
+    }
+}
+
+/// Collects the errors of invalid fields.
+#[derive(Debug, Default)]
+pub struct FieldErrors {
+    errors: Vec<FieldError>,
+}
+
+impl FieldErrors {
+    pub fn add(&mut self, field: &'static str, message: impl Into<String>) {
+        self.errors.push(FieldError {
+            field,
+            message: message.into(),
+        });
+    }
+
+    pub fn into_result(self) -> Result<(), Error> {
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::new_validation(self.errors))
+        }
+    }
+
+    /// Checks that the value is not blank and at most `max_length` characters long.
+    pub fn check_text(&mut self, field: &'static str, value: &str, max_length: usize) {
+        if value.trim().is_empty() {
+            self.add(field, "should not be blank");
+        } else if max_length < value.chars().count() {
+            self.add(
+                field,
+                format!("should be at most {} characters long", max_length),
+            );
+        }
+    }
+
+    /// Checks that the value is at most `max_length` characters long.
+    pub fn check_max_length(&mut self, field: &'static str, value: &str, max_length: usize) {
+        if max_length < value.chars().count() {
+            self.add(
+                field,
+                format!("should be at most {} characters long", max_length),
+            );
+        }
+    }
+
+    /// Checks that the value looks like an email address.
+    /// Deliverability is not checked; the address is only expected to have a local part and a domain.
+    pub fn check_email(&mut self, field: &'static str, value: &str) {
+        let is_valid = match value.split_once('@') {
+            Some((local, domain)) => {
+                !local.is_empty()
+                    && !domain.is_empty()
+                    && !domain.contains('@')
+                    && domain.contains('.')
+                    && !domain.starts_with('.')
+                    && !domain.ends_with('.')
+                    && !value.chars().any(char::is_whitespace)
+            }
+            None => false,
+        };
+
+        if !is_valid {
+            self.add(field, "should be a valid email address");
+        } else if MAX_EMAIL_LENGTH < value.len() {
+            self.add(
+                field,
+                format!("should be at most {} characters long", MAX_EMAIL_LENGTH),
+            );
+        }
+    }
+
+    /// Checks that the value looks like a MIME type, such as `image/png`.
+    pub fn check_mime(&mut self, field: &'static str, value: &str) {
+        let is_valid = match value.split_once('/') {
+            Some((top, sub)) => {
+                !top.is_empty()
+                    && !sub.is_empty()
+                    && !value.chars().any(|c| c.is_whitespace() || c.is_control())
+            }
+            None => false,
+        };
+
+        if !is_valid {
+            self.add(field, "should be a MIME type such as `image/png`");
+        }
+    }
+
+    /// Checks that the limit, if given, is within `1..=max`.
+    pub fn check_limit(&mut self, field: &'static str, value: Option<u32>, max: u32) {
+        if let Some(value) = value {
+            if value < 1 || max < value {
+                self.add(field, format!("should be between 1 and {}", max));
+            }
+        }
+    }
+
+    /// Checks that the start of the range, if given, does not come after its end.
+    pub fn check_range<T: PartialOrd>(&mut self, field: &'static str, value: Option<&(T, T)>) {
+        if let Some((start, end)) = value {
+            if end < start {
+                self.add(field, "the start should not come after the end");
+            }
+        }
+    }
+}